@@ -1,9 +1,13 @@
+use crate::managers::orbit::BeltDistribution;
 use crate::managers::scene::Entity;
-use crate::utils::camera::Camera;
+use crate::utils::camera::{Camera, FovAxis, DEFAULT_FOV_V};
 use crate::utils::log::*;
-use crate::utils::web::{local_storage_get, local_storage_set};
+use crate::utils::web::{is_local_storage_enabled, local_storage_get, local_storage_set};
 use na::*;
 use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 
 #[derive(Serialize, Deserialize)]
 pub struct OpenWindows {
@@ -13,6 +17,15 @@ pub struct OpenWindows {
     pub settings: bool,
     pub controls: bool,
     pub about: bool,
+
+    #[serde(default)]
+    pub distance_plot: bool,
+
+    #[serde(default)]
+    pub shader_editor: bool,
+
+    #[serde(default)]
+    pub transit_finder: bool,
 }
 
 impl Default for OpenWindows {
@@ -24,6 +37,9 @@ impl Default for OpenWindows {
             settings: true,
             controls: true,
             about: false,
+            distance_plot: false,
+            shader_editor: false,
+            transit_finder: false,
         }
     }
 }
@@ -44,9 +60,305 @@ impl Default for ButtonState {
 pub enum ReferenceChange {
     FocusKeepLocation(Entity),
     FocusKeepCoords(Entity),
+    Follow(Entity), // Like FocusKeepLocation, but also carries the camera along with the body's motion
     Clear,
 }
 
+/// Tracks the body we're following with `ReferenceChange::Follow`, so that the transform update
+/// system can tell how its velocity turned since the last frame
+pub struct FollowState {
+    pub entity: Entity,
+    pub last_translation: Vector3<f64>,      // World space, Mm
+    pub last_velocity: Option<Vector3<f64>>, // World space, Mm/s
+}
+
+/// Operator used to map the exposed HDR scene color into displayable range when blitting to the canvas
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Tonemap {
+    None, // Just clamps
+    Reinhard,
+    ACESFilmic,
+}
+impl Default for Tonemap {
+    fn default() -> Self {
+        Tonemap::Reinhard
+    }
+}
+
+/// What the color of each point marker represents
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PointColorMode {
+    Default, // Fixed color for each body type, unless the body has its own base_color
+    BodyType,
+    Eccentricity,
+    SemiMajorAxis,
+}
+impl Default for PointColorMode {
+    fn default() -> Self {
+        PointColorMode::Default
+    }
+}
+
+/// How the UI shows very large and very small values, when formatting them with format_number
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NumberNotation {
+    Fixed,      // With an SI prefix, like 149.6k
+    Scientific, // With an exponent, like 1.496e5
+}
+impl Default for NumberNotation {
+    fn default() -> Self {
+        NumberNotation::Fixed
+    }
+}
+
+/// Unit that the UI shows distances in
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DistanceUnit {
+    Auto, // Whichever of km, Mm or AU reads best for each distance
+    Km,
+    Mm,
+    Au,
+}
+impl Default for DistanceUnit {
+    fn default() -> Self {
+        DistanceUnit::Auto
+    }
+}
+
+/// Frame that the grid and coordinate axes are drawn in. Everything else always uses the J2000 ecliptic frame
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CoordinateFrame {
+    Ecliptic,   // X towards the vernal equinox and Z towards the north ecliptic pole
+    Equatorial, // X towards the vernal equinox and Z towards the north celestial pole
+}
+impl Default for CoordinateFrame {
+    fn default() -> Self {
+        CoordinateFrame::Ecliptic
+    }
+}
+
+/// Unit that the UI shows velocities in
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VelocityUnit {
+    KmPerS,
+    MmPerS,
+    AuPerDay,
+}
+impl Default for VelocityUnit {
+    fn default() -> Self {
+        VelocityUnit::MmPerS
+    }
+}
+
+/// How the toolbar shows the current simulation time
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TimeFormat {
+    Utc, // Calendar date and time in UTC
+    Tt,  // Calendar date and time in Terrestrial Time, which doesn't have leap seconds
+    Jd,  // Julian Date
+    Mjd, // Modified Julian Date, JD - 2400000.5
+    #[serde(alias = "SecondsSinceJ2000")]
+    SecondsSinceEpoch, // Simulation seconds since the epoch of the scene
+}
+impl Default for TimeFormat {
+    fn default() -> Self {
+        TimeFormat::Utc
+    }
+}
+impl TimeFormat {
+    /// The format after this one, wrapping back around to the first. Clicking the toolbar's date goes through them
+    pub fn next(&self) -> Self {
+        return match self {
+            TimeFormat::Utc => TimeFormat::Tt,
+            TimeFormat::Tt => TimeFormat::Jd,
+            TimeFormat::Jd => TimeFormat::Mjd,
+            TimeFormat::Mjd => TimeFormat::SecondsSinceEpoch,
+            TimeFormat::SecondsSinceEpoch => TimeFormat::Utc,
+        };
+    }
+
+    /// Name to show for this format on the settings window
+    pub fn get_name(&self) -> &'static str {
+        return match self {
+            TimeFormat::Utc => "UTC",
+            TimeFormat::Tt => "TT",
+            TimeFormat::Jd => "Julian Date",
+            TimeFormat::Mjd => "Modified Julian Date",
+            TimeFormat::SecondsSinceEpoch => "Seconds since epoch",
+        };
+    }
+}
+
+/// How we decide which animation frame callbacks to draw on, given frames_per_second_limit
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FramePacing {
+    Unlimited,  // Draw on every callback
+    DropFrames, // Skip callbacks until a whole frame interval has elapsed since the last draw
+    Accumulate, // Keep a schedule of frame deadlines and draw on the callback closest to each one
+}
+impl Default for FramePacing {
+    fn default() -> Self {
+        FramePacing::Accumulate
+    }
+}
+
+/// How dragging the mouse orbits or rotates the camera
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CameraDragMode {
+    PointerLock, // Locks the pointer and uses the raw mouse movement, so drags can go on forever
+    Drag, // Follows the visible cursor over the canvas, which plays nicer with trackpads and touch screens
+}
+impl Default for CameraDragMode {
+    fn default() -> Self {
+        CameraDragMode::PointerLock
+    }
+}
+
+/// What scrolling the mouse wheel does, when not zooming to the focused object with Alt
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WheelMode {
+    AdjustSpeed, // Speeds up or slows down the WASD camera movement
+    Dolly,       // Moves the camera towards or away from whatever is under the cursor
+}
+impl Default for WheelMode {
+    fn default() -> Self {
+        WheelMode::AdjustSpeed
+    }
+}
+
+/// Whether the camera's up stays towards the ecliptic's +Z, or turns along with the camera
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CameraRollMode {
+    Locked, // Looking around turns about +Z and stops short of looking straight up or down
+    Free,   // Looking around turns about the camera's own axes, and Z/C roll it
+}
+impl Default for CameraRollMode {
+    fn default() -> Self {
+        CameraRollMode::Locked
+    }
+}
+
+/// Modifier key that boosts the WASD camera movement while held
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MoveBoostModifier {
+    Shift,
+    Alt,
+}
+impl Default for MoveBoostModifier {
+    fn default() -> Self {
+        MoveBoostModifier::Shift
+    }
+}
+
+/// Which order Tab and Shift-Tab cycle through the bodies in
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FocusCycleOrder {
+    BodyList, // Same order (and search filters) as the body list
+    Distance, // Nearest to the camera first
+}
+impl Default for FocusCycleOrder {
+    fn default() -> Self {
+        FocusCycleOrder::BodyList
+    }
+}
+
+/// Decides whether each animation frame callback should draw. The simulation isn't advanced on the skipped ones,
+/// so the next drawn frame gets the entire elapsed time as its delta
+#[derive(Default)]
+pub struct FrameLimiter {
+    last_callback_s: Option<f64>,
+    last_draw_s: f64,
+    next_frame_due_s: f64,
+}
+impl FrameLimiter {
+    /// Returns whether we should draw on a callback at `now_s`, and if so records it as drawn
+    pub fn should_draw(&mut self, pacing: FramePacing, fps_limit: f64, now_s: f64) -> bool {
+        let callback_interval_s = now_s - self.last_callback_s.unwrap_or(now_s);
+        self.last_callback_s = Some(now_s);
+
+        let frame_interval_s = 1.0 / fps_limit.max(0.001);
+        let draw = match pacing {
+            FramePacing::Unlimited => true,
+            FramePacing::DropFrames => now_s - self.last_draw_s >= frame_interval_s,
+            FramePacing::Accumulate => {
+                // Draw if this callback is closer to the deadline than the next one will likely be
+                if now_s + callback_interval_s / 2.0 >= self.next_frame_due_s {
+                    self.next_frame_due_s += frame_interval_s;
+
+                    // Don't try catching up on a backlog of frames after a hitch (or on the first frame)
+                    if self.next_frame_due_s < now_s {
+                        self.next_frame_due_s = now_s + frame_interval_s;
+                    }
+                    true
+                } else {
+                    false
+                }
+            }
+        };
+
+        if draw {
+            self.last_draw_s = now_s;
+        }
+        return draw;
+    }
+}
+
+/// Tracks the startup asset fetches so that we can display progress while they stream in
+#[derive(Default)]
+pub struct LoadingState {
+    pub total: u32,
+    pub loaded: u32,
+    pub failed: u32,
+    pub errors: Vec<String>, // Cleared once the user acknowledges them
+}
+impl LoadingState {
+    pub fn is_done(&self) -> bool {
+        return self.loaded + self.failed >= self.total;
+    }
+}
+
+/// Most toasts shown at once. Adding more drops the oldest ones
+pub const MAX_TOASTS: usize = 5;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ToastSeverity {
+    Info,
+    Warning,
+    Error,
+}
+impl ToastSeverity {
+    /// How long toasts stay on screen. Warnings and errors stay up for longer since they usually need reading
+    pub fn get_duration_s(&self) -> f64 {
+        return match self {
+            ToastSeverity::Info => 5.0,
+            ToastSeverity::Warning => 10.0,
+            ToastSeverity::Error => 15.0,
+        };
+    }
+}
+
+/// Short message shown in a corner of the screen until it expires or is dismissed
+pub struct Toast {
+    pub text: String,
+    pub severity: ToastSeverity,
+    pub expire_time_s: f64, // Real time
+}
+
+/// Counters filled in by the rendering system every frame, for the debug window
+#[derive(Default)]
+pub struct RenderStats {
+    pub drawn_primitives: u32,
+    pub culled_primitives: u32, // Skipped by frustum culling
+}
+
+/// Last ray that picking clicked along, for the debug overlay that draws it
+pub struct PickRay {
+    pub start: Point3<f64>,
+    pub direction: Vector3<f64>,
+    pub hit: Option<(Entity, Point3<f64>)>, // First entity whose collider the ray hit, and where
+    pub picked: Option<Entity>, // What the click selected, after falling back to point markers and going up to ancestors
+}
+
 #[derive(Serialize, Deserialize, Default)]
 pub struct Input {
     pub mouse_x: i32, // Canvas pixels, (0,0) on top left
@@ -56,6 +368,12 @@ pub struct Input {
     pub scroll_delta_x: i32,
     pub scroll_delta_y: i32,
     pub over_ui: bool, // Prevents interaction with the scene
+    #[serde(skip)]
+    pub dragging_scene: bool, // Whether the held mouse buttons are orbiting/rotating the camera instead of using the UI
+    #[serde(skip)]
+    pub pinching: bool, // Whether two fingers are pinching the canvas, which zooms like alt + scroll
+    #[serde(skip)]
+    pub tapped: bool, // A finger tapped the canvas at mouse_x/mouse_y this frame, which selects like a click
     pub m0: ButtonState,
     pub m1: ButtonState,
     pub forward: ButtonState,
@@ -68,6 +386,14 @@ pub struct Input {
     pub f: ButtonState,
     pub g: ButtonState,
     pub esc: ButtonState,
+    pub step_back: ButtonState,
+    pub step_forward: ButtonState,
+    pub command_palette: ButtonState,
+    pub cycle_focus: ButtonState, // Tab, or Shift-Tab to go backwards
+    pub look_at_star: ButtonState,
+    pub spectator: ButtonState,
+    pub roll_left: ButtonState,
+    pub roll_right: ButtonState,
 
     #[serde(skip)]
     pub modifiers: egui::Modifiers, // We can use this for the rest of the app too
@@ -76,8 +402,17 @@ pub struct Input {
     pub egui_events: Vec<egui::Event>,
 }
 
+/// Bump this whenever the persisted fields of AppState change in a way that needs a migration
+pub const APP_STATE_SCHEMA_VERSION: u32 = 1;
+
+/// Exposure and light intensity scale that scenes without their own lighting preset go back to
+pub const DEFAULT_EV100: f32 = 11.0;
+pub const DEFAULT_LIGHT_INTENSITY_SCALE: f32 = 1.0;
+
 #[derive(Serialize, Deserialize)]
 pub struct AppState {
+    pub schema_version: u32,
+
     #[serde(skip)]
     pub pending_reset: bool, // Whether we want the state to be reset to default the next possible time
 
@@ -109,28 +444,163 @@ pub struct AppState {
     #[serde(skip)]
     pub time_of_last_save: f64,
 
+    #[serde(skip)]
+    pub save_requested: bool, // Save on the next frame regardless of autosave_interval_s
+
+    #[serde(skip)]
+    pub saved_hashes: HashMap<String, u64>, // Of what we last saved to each local storage key
+
+    #[serde(skip)]
+    pub time_of_last_hash_update: f64,
+
+    #[serde(skip)]
+    pub loading: LoadingState,
+
+    #[serde(skip)]
+    pub render_stats: RenderStats,
+
+    #[serde(skip)]
+    pub toasts: Vec<Toast>,
+
+    #[serde(skip)]
+    pub gl_context_lost: bool, // We can't render anything until the browser restores the WebGL context
+
+    #[serde(skip)]
+    pub gl_context_restored: bool, // Whether we need to rebuild our GL resources before the next frame
+
     pub use_skyboxes: bool,
+    pub max_body_count: usize, // Most database bodies a scene spawns, keeping the largest or brightest. Zero is no limit
+    pub starfield_num_stars: u32, // Procedural stars drawn when there's no skybox. Zero disables them
+    pub starfield_seed: u64,
+    pub use_star_catalog: bool, // Draws the real stars of the star catalog instead of procedural ones, if we have it
+    pub star_catalog_mag_limit: f32, // Faintest catalog stars we draw. Bigger is fainter
     pub show_grid: bool,
     pub show_axes: bool,
+    pub billboard_axes: bool, // Turns the coordinate axes to face the camera, instead of along coordinate_frame
+    pub show_axis_gizmo: bool, // Small X/Y/Z axes in the bottom left corner, turning along with the camera
+    pub label_axis_gizmo: bool,
+    pub show_outlines: bool, // Outlines around the hovered and selected bodies
+    pub coordinate_frame: CoordinateFrame,
+    pub show_scale_bar: bool,
+    pub auto_depth_range: bool, // Fits camera.near and camera.far to what we draw every frame
     pub show_points: bool,
+    pub max_body_labels: u32, // Most body name labels shown at once, nearest bodies first. Zero hides them
+    pub point_color_mode: PointColorMode,
+    pub point_min_size_px: f32, // Smallest body point markers, so that tiny bodies remain clickable
+    pub point_max_size_px: f32, // Largest body point markers, used for the Sun
+    pub point_glow: bool, // Point markers fade out from their center like stars, instead of being solid discs
+    pub number_notation: NumberNotation,
+    pub distance_unit: DistanceUnit,
+    pub velocity_unit: VelocityUnit,
+    pub time_format: TimeFormat,
+    #[serde(skip)]
+    pub display_units_overridden: bool, // Whether the user picked the units/notation this session, so scenes shouldn't change them
+    pub show_orbits: bool,
+    pub fade_orbits: bool,
+    pub orbits_relative_to_reference: bool, // Draws the orbit of the reference body as its central body's around it
+    pub center_of_mass_frame: bool, // Draws everything relative to the center of mass of the rigid bodies
+    pub show_orbit_trails: bool,
+    pub orbit_trail_days: f64, // How far back in time orbit trails go
+    pub line_width: f32,       // Pixels, for orbits, grid and axes
+    pub trajectory_prediction_days: f64, // How far ahead predicted trajectories go
+    pub trajectory_prediction_steps: u32, // Physics steps (and points) along predicted trajectories
+
+    // Body whose trajectory the physics system should predict on its next run
+    #[serde(skip)]
+    pub trajectory_prediction_request: Option<Entity>,
+
+    pub ground_track_hours: f64,  // How far back in time ground tracks go
+    pub show_body_trails: bool,   // Trails behind the bodies that aren't on Keplerian orbits
+    pub body_trail_days: f64,     // How far back in time body trails go
+    pub body_trail_points: usize, // Most points a body trail keeps, dropping the oldest ones past it
+
+    pub belt_distribution: BeltDistribution, // Last used settings of the asteroid belt generator
+
+    // Asteroid belt that the engine should add to the current scene before the next frame
+    #[serde(skip)]
+    pub belt_request: Option<BeltDistribution>,
+
+    // Body whose ground track the ground track system should start (or stop, if it already had one) on its next run
+    #[serde(skip)]
+    pub ground_track_request: Option<Entity>,
 
     pub pixels_per_point: f32,
 
     pub frames_per_second_limit: f64,
+    pub frame_pacing: FramePacing,
+
+    #[serde(skip)]
+    pub frame_limiter: FrameLimiter,
+
+    pub autosave_interval_s: f64, // How often we save to local storage. 0 to only save when asked to
+
     pub simulation_speed: f64,
     pub simulation_paused: bool,
+    pub time_step_days: f64, // How far each step goes when stepping through time while paused
+
+    // Simulated time that the engine should advance by before the next frame, after stepping through time
+    #[serde(skip)]
+    pub pending_time_step_s: f64,
+
+    // Largest pending_time_step_s of the time step the engine is spreading over frames, to show how far along it is
+    #[serde(skip)]
+    pub time_step_total_s: f64,
     pub move_speed: f64,
+    pub move_boost_modifier: MoveBoostModifier,
+    pub move_boost_factor: f64, // Multiplies move_speed while move_boost_modifier is held
+    pub move_ramp_time_s: f64, // How long the movement keys need to be held to reach full speed. Zero to start at full speed
+
+    // How long the movement keys have been held for, to ramp up the speed
+    #[serde(skip)]
+    pub move_hold_time_s: f64,
     pub rotate_speed: f64,
+    pub camera_drag_mode: CameraDragMode,
+    pub camera_roll_mode: CameraRollMode,
+
+    // Turn the camera so that its up is +Z again on the next frame
+    #[serde(skip)]
+    pub level_horizon_request: bool,
+    pub wheel_mode: WheelMode,
     pub ev100: f32,
+    pub light_intensity_scale: f32, // Multiplies the intensity of every light, e.g. to brighten up bodies far from their star
+    #[serde(skip)]
+    pub lighting_overridden: bool, // Whether the user changed the exposure/light intensity this session, so scenes shouldn't change them
+    pub tonemap: Tonemap,
+    pub background_color: [u8; 3], // sRGB, as displayed after tonemapping
+    pub body_colors: HashMap<String, [f32; 4]>, // Linear RGBA that replaces the base color of each body, by body id
+    pub gravity_softening: f64, // Mm, Plummer softening length used by the physics system
+    pub max_physics_substep_s: f64, // Longest physics step, as frames at high simulation speeds get split into several. Zero disables it
+    pub command_palette_key: String, // Opens the command palette along with Ctrl (or Cmd)
+    pub look_at_star_key: String,   // Turns the camera to face the nearest star
+    pub look_at_star_smooth: bool, // Whether the camera turns towards the star over a moment, instead of snapping to it
+
+    // Turn the camera to face the nearest star on the next frame
+    #[serde(skip)]
+    pub look_at_star_request: bool,
+    pub spectator_key: String, // Toggles spectator mode
+
+    // Hides the interface and stops clicks and Tab from changing the selection, for clean viewing (and screenshots). Not
+    // saved, so that a reload always brings the interface back
+    #[serde(skip)]
+    pub spectator: bool,
+    pub focus_cycle_order: FocusCycleOrder,
+    pub focus_cycle_go_to: bool, // Whether cycling with Tab also focuses and goes to the body, instead of just selecting it
 
     #[serde(skip)]
     pub input: Input,
 
     #[serde(skip)]
     pub hovered: Option<Entity>,
+    #[serde(skip)]
+    pub last_pick_ray: Option<PickRay>,
+    pub show_pick_ray: bool, // Debug overlay of last_pick_ray
     pub selection: Option<Entity>,
     pub camera: Camera,
 
+    // Bodies shown on the body list, in the same order and with its filters applied. Empty while it's closed
+    #[serde(skip)]
+    pub body_list_order: Vec<Entity>,
+
     #[serde(skip)]
     pub reference_entity: Option<Entity>, // If this is Some, our pos/up/target are wrt. reference_translation
 
@@ -143,6 +613,11 @@ pub struct AppState {
     #[serde(skip)]
     pub next_reference_entity: Option<ReferenceChange>,
 
+    // Set when our reference entity was focused with ReferenceChange::Follow
+    #[serde(skip)]
+    pub follow: Option<FollowState>,
+    pub follow_keeps_velocity_horizontal: bool,
+
     // Entity we're currently lerping/pointing to
     #[serde(skip)]
     pub entity_going_to: Option<Entity>,
@@ -153,6 +628,7 @@ pub struct AppState {
 impl AppState {
     pub fn new() -> Self {
         Self {
+            schema_version: APP_STATE_SCHEMA_VERSION,
             pending_reset: false,
             canvas_height: 0,
             canvas_width: 0,
@@ -163,46 +639,176 @@ impl AppState {
             sim_delta_time_s: 0.,
             real_delta_time_s: 0.,
             time_of_last_save: 0.,
+            save_requested: false,
+            saved_hashes: HashMap::new(),
+            time_of_last_hash_update: 0.,
+            loading: LoadingState::default(),
+            render_stats: RenderStats::default(),
+            toasts: Vec::new(),
+            gl_context_lost: false,
+            gl_context_restored: false,
             use_skyboxes: false,
+            starfield_num_stars: 4000,
+            max_body_count: 0,
+            starfield_seed: 0,
+            use_star_catalog: true,
+            star_catalog_mag_limit: 6.5,
             show_grid: false,
             show_axes: false,
+            billboard_axes: false,
+            show_axis_gizmo: true,
+            label_axis_gizmo: true,
+            show_outlines: true,
+            coordinate_frame: CoordinateFrame::default(),
+            show_scale_bar: true,
+            auto_depth_range: true,
             show_points: true,
+            max_body_labels: 20,
+            point_color_mode: PointColorMode::Default,
+            point_min_size_px: 2.0,
+            point_max_size_px: 10.0,
+            point_glow: false,
+            number_notation: NumberNotation::default(),
+            distance_unit: DistanceUnit::default(),
+            velocity_unit: VelocityUnit::default(),
+            time_format: TimeFormat::default(),
+            display_units_overridden: false,
+            show_orbits: false,
+            fade_orbits: true,
+            orbits_relative_to_reference: false,
+            center_of_mass_frame: false,
+            show_orbit_trails: false,
+            orbit_trail_days: 30.0,
+            trajectory_prediction_days: 30.0,
+            trajectory_prediction_steps: 500,
+            trajectory_prediction_request: None,
+            ground_track_hours: 6.0,
+            show_body_trails: false,
+            body_trail_days: 30.0,
+            body_trail_points: 1000,
+            belt_distribution: BeltDistribution::default(),
+            belt_request: None,
+            ground_track_request: None,
+            line_width: 1.5,
             pixels_per_point: 1.0,
             simulation_speed: 1.0,
             simulation_paused: true,
+            time_step_days: 1.0,
+            pending_time_step_s: 0.0,
+            time_step_total_s: 0.0,
             move_speed: 5.0,
+            move_boost_modifier: MoveBoostModifier::default(),
+            move_boost_factor: 10.0,
+            move_ramp_time_s: 0.5,
+            move_hold_time_s: 0.0,
             rotate_speed: 2.0,
+            camera_drag_mode: CameraDragMode::default(),
+            camera_roll_mode: CameraRollMode::default(),
+            level_horizon_request: false,
+            wheel_mode: WheelMode::default(),
             frames_per_second_limit: 120.0,
-            ev100: 11.0,
+            frame_pacing: FramePacing::default(),
+            frame_limiter: FrameLimiter::default(),
+            autosave_interval_s: 3.0,
+            ev100: DEFAULT_EV100,
+            light_intensity_scale: DEFAULT_LIGHT_INTENSITY_SCALE,
+            lighting_overridden: false,
+            tonemap: Tonemap::default(),
+            background_color: [26, 26, 51],
+            body_colors: HashMap::new(),
+            gravity_softening: 0.001,
+            max_physics_substep_s: 600.0,
+            command_palette_key: String::from("k"),
+            look_at_star_key: String::from("l"),
+            look_at_star_smooth: true,
+            look_at_star_request: false,
+            spectator_key: String::from("h"),
+            spectator: false,
+            focus_cycle_order: FocusCycleOrder::default(),
+            focus_cycle_go_to: true,
             input: Input::default(),
             hovered: None,
+            last_pick_ray: None,
+            show_pick_ray: false,
             selection: None,
             camera: Camera {
                 pos: Point3::new(10.0, 10.0, 10.0),
                 up: Unit::new_unchecked(Vector3::z()),
                 target: Point3::new(0.0, 0.0, 0.0),
-                fov_v: 60.0,
+                fov_v: DEFAULT_FOV_V,
                 near: 0.01,
                 far: 100000000.0,
+                fov_axis: FovAxis::default(),
+                last_aspect_ratio: None,
                 v: Matrix4::identity(),
                 p: Matrix4::identity(),
                 v_inv: Matrix4::identity(),
                 p_inv: Matrix4::identity(),
+                v_rel: Matrix4::identity(),
+                world_pos: Vector3::zeros(),
             },
+            body_list_order: Vec::new(),
             reference_entity: None,
             reference_translation: None,
             next_reference_entity: None,
+            follow: None,
+            follow_keeps_velocity_horizontal: true,
             entity_going_to: None,
             open_windows: Default::default(),
             last_scene_identifier: String::new(),
         }
     }
 
-    /// Tries fetching our last state from local storage if we can find one.
-    /// Just creates a new state otherwise.
+    /// Shows `text` to the user for a while, depending on `severity`. Showing the same text again just keeps the
+    /// existing toast up for longer, so that repeated failures don't flood the screen
+    pub fn add_toast(&mut self, text: String, severity: ToastSeverity) {
+        let expire_time_s = self.real_time_s + severity.get_duration_s();
+
+        if let Some(index) = self
+            .toasts
+            .iter()
+            .position(|t| t.text == text && t.severity == severity)
+        {
+            let mut toast = self.toasts.remove(index);
+            toast.expire_time_s = expire_time_s;
+            self.toasts.push(toast);
+            return;
+        }
+
+        self.toasts.push(Toast {
+            text,
+            severity,
+            expire_time_s,
+        });
+
+        if self.toasts.len() > MAX_TOASTS {
+            let num_extra = self.toasts.len() - MAX_TOASTS;
+            self.toasts.drain(..num_extra);
+        }
+    }
+
+    pub fn add_info_toast(&mut self, text: String) {
+        self.add_toast(text, ToastSeverity::Info);
+    }
+
+    pub fn add_warning_toast(&mut self, text: String) {
+        self.add_toast(text, ToastSeverity::Warning);
+    }
+
+    pub fn add_error_toast(&mut self, text: String) {
+        self.add_toast(text, ToastSeverity::Error);
+    }
+
+    pub fn remove_expired_toasts(&mut self) {
+        let now = self.real_time_s;
+        self.toasts.retain(|t| t.expire_time_s > now);
+    }
+
+    /// Tries fetching our last state from local storage if we can find one, migrating
+    /// it to the current schema version if needed. Just creates a new state otherwise.
     pub fn load_or_new() -> Self {
         if let Some(serialized) = local_storage_get("app_state") {
-            match ron::de::from_str::<AppState>(&serialized) {
+            match AppState::deserialize_and_migrate(&serialized) {
                 Ok(mut state) => {
                     state.start_date = js_sys::Date::now() / 1000.0;
                     return state;
@@ -219,8 +825,471 @@ impl AppState {
         return Self::new();
     }
 
-    pub fn save(&self) {
-        let serialized = ron::ser::to_string_pretty(&self, ron::ser::PrettyConfig::new()).unwrap();
-        local_storage_set("app_state", &serialized);
+    /// Whether we were asked to save, or it's been autosave_interval_s since the last time we did
+    pub fn is_save_due(&self) -> bool {
+        return self.save_requested
+            || (self.autosave_interval_s > 0.0
+                && self.real_time_s - self.time_of_last_save > self.autosave_interval_s);
+    }
+
+    /// Remembers that we saved `serialized` to the local storage `key`. Returns false if that's what we saved there
+    /// last time too, so that we can skip writing it again
+    pub fn mark_saved(&mut self, key: &str, serialized: &str) -> bool {
+        let mut hasher = DefaultHasher::new();
+        serialized.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        return self.saved_hashes.insert(key.to_owned(), hash) != Some(hash);
+    }
+
+    /// Writes `serialized` to the local storage `key` if we're allowed to use local storage and it changed since the
+    /// last time we saved it. Returns whether we wrote anything
+    pub fn save_if_changed(&mut self, key: &str, serialized: &str) -> bool {
+        if !is_local_storage_enabled() || !self.mark_saved(key, serialized) {
+            return false;
+        }
+
+        local_storage_set(key, serialized);
+        return true;
+    }
+
+    pub fn save(&mut self) -> bool {
+        let serialized = serde_json::to_string(&self).unwrap();
+        return self.save_if_changed("app_state", &serialized);
+    }
+
+    /// Deserializes a persisted app state of any schema version into the current one.
+    /// Fields that can't be carried over are reset to their defaults instead of discarding
+    /// the whole thing
+    pub fn deserialize_and_migrate(serialized: &str) -> Result<AppState, String> {
+        let mut value = match serde_json::from_str::<serde_json::Value>(serialized) {
+            Ok(value) => value,
+            Err(json_error) => {
+                // Schema version 0 was saved as RON. It's read as a generic value too, so that it goes through the
+                // same migrations and field merging as the JSON payloads
+                let value =
+                    ron::de::from_str::<serde_json::Value>(serialized).map_err(|ron_error| {
+                        format!(
+                            "Not valid JSON ('{}') nor a legacy RON payload ('{}')",
+                            json_error, ron_error
+                        )
+                    })?;
+                info!(LogCat::Io, "Read app state from legacy RON payload");
+                value
+            }
+        };
+
+        let old_map = value
+            .as_object_mut()
+            .ok_or_else(|| String::from("App state payload is not an object"))?;
+
+        let mut version = old_map
+            .get("schema_version")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(0) as u32;
+
+        if version > APP_STATE_SCHEMA_VERSION {
+            return Err(format!(
+                "App state has schema version {}, newer than the supported {}",
+                version, APP_STATE_SCHEMA_VERSION
+            ));
+        }
+
+        while version < APP_STATE_SCHEMA_VERSION {
+            migrate_app_state(version, old_map);
+            info!(
+                LogCat::Io,
+                "Migrated app state from schema version {} to {}",
+                version,
+                version + 1
+            );
+            version += 1;
+        }
+
+        // Fast path: Everything matches already
+        if let Ok(state) = serde_json::from_value::<AppState>(value.clone()) {
+            return Ok(state);
+        }
+
+        // Start from a default state and carry over each old field that still deserializes
+        let mut merged = serde_json::to_value(AppState::new()).map_err(|e| e.to_string())?;
+        let merged_map = merged.as_object_mut().unwrap();
+        for (key, old_field) in value.as_object().unwrap() {
+            if !merged_map.contains_key(key) {
+                warning!(LogCat::Io, "Dropping unknown app state field '{}'", key);
+                continue;
+            }
+
+            let default_field = merged_map.insert(key.clone(), old_field.clone()).unwrap();
+            if serde_json::from_value::<AppState>(serde_json::Value::Object(merged_map.clone()))
+                .is_err()
+            {
+                warning!(
+                    LogCat::Io,
+                    "Resetting app state field '{}' as its value '{}' is no longer valid",
+                    key,
+                    old_field
+                );
+                merged_map.insert(key.clone(), default_field);
+            }
+        }
+
+        return serde_json::from_value::<AppState>(merged).map_err(|e| e.to_string());
+    }
+}
+
+/// Bump this whenever egui's memory serializes differently, e.g. when updating egui
+pub const EGUI_MEMORY_SCHEMA_VERSION: u32 = 1;
+
+/// Serializes egui's memory along with EGUI_MEMORY_SCHEMA_VERSION, as egui doesn't version it on its own
+pub fn serialize_egui_memory(memory: &egui::Memory) -> Result<String, String> {
+    let memory = serde_json::to_value(memory).map_err(|e| e.to_string())?;
+
+    let mut map = serde_json::Map::new();
+    map.insert(
+        String::from("schema_version"),
+        serde_json::Value::from(EGUI_MEMORY_SCHEMA_VERSION),
+    );
+    map.insert(String::from("memory"), memory);
+    return Ok(serde_json::Value::Object(map).to_string());
+}
+
+/// Deserializes egui's memory saved with serialize_egui_memory, or saved on its own before it was versioned
+pub fn deserialize_egui_memory(serialized: &str) -> Result<egui::Memory, String> {
+    let mut value =
+        serde_json::from_str::<serde_json::Value>(serialized).map_err(|e| e.to_string())?;
+
+    let version = value
+        .get("schema_version")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(0) as u32;
+
+    let memory = match version {
+        // Version 0 was just the memory
+        0 => value,
+        EGUI_MEMORY_SCHEMA_VERSION => value
+            .get_mut("memory")
+            .map(serde_json::Value::take)
+            .ok_or_else(|| String::from("egui state payload has no memory"))?,
+        _ => {
+            return Err(format!(
+                "egui state has schema version {}, which isn't the supported {}",
+                version, EGUI_MEMORY_SCHEMA_VERSION
+            ))
+        }
+    };
+
+    return serde_json::from_value::<egui::Memory>(memory).map_err(|e| e.to_string());
+}
+
+/// Upgrades a persisted app state payload from `version` to `version + 1` in place
+fn migrate_app_state(version: u32, state: &mut serde_json::Map<String, serde_json::Value>) {
+    match version {
+        // Version 0 had no schema_version field and may be missing fields added since then,
+        // which will be filled in with defaults
+        0 => {
+            state.insert(
+                String::from("schema_version"),
+                serde_json::Value::from(1u32),
+            );
+        }
+        _ => {}
+    }
+}
+
+/// Shows a toast from somewhere that doesn't have the app state at hand, like asset fetch callbacks. If the state is
+/// busy (e.g. we're in the middle of a frame) it's only logged, as whoever calls this should have logged it already
+pub fn add_global_toast(text: String, severity: ToastSeverity) {
+    crate::STATE.with(|s| match s.try_borrow_mut() {
+        Ok(mut ref_mut) => {
+            if let Some(state) = ref_mut.as_mut() {
+                state.add_toast(text, severity);
+            }
+        }
+        Err(_) => debug!(
+            LogCat::Engine,
+            "Skipping toast '{}' as the state is borrowed", text
+        ),
+    });
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    pub fn migrate_unversioned_snapshot() {
+        // Version 0 payload: No schema_version and missing show_points, ev100 and open_windows
+        let snapshot = r#"{
+            "use_skyboxes": true,
+            "show_grid": true,
+            "show_axes": false,
+            "pixels_per_point": 1.5,
+            "frames_per_second_limit": 60.0,
+            "simulation_speed": 100.0,
+            "simulation_paused": false,
+            "move_speed": 12.0,
+            "rotate_speed": 3.0,
+            "selection": null,
+            "camera": {
+                "pos": [1.0, 2.0, 3.0],
+                "up": [0.0, 0.0, 1.0],
+                "target": [0.0, 0.0, 0.0],
+                "fov_v": 45.0,
+                "near": 0.01,
+                "far": 1000.0
+            },
+            "last_scene_identifier": "earth_centric"
+        }"#;
+
+        let state = AppState::deserialize_and_migrate(snapshot).unwrap();
+        let default = AppState::new();
+
+        assert_eq!(state.schema_version, APP_STATE_SCHEMA_VERSION);
+        assert_eq!(state.use_skyboxes, true);
+        assert_eq!(state.pixels_per_point, 1.5);
+        assert_eq!(state.simulation_speed, 100.0);
+        assert_eq!(state.camera.fov_v, 45.0);
+        assert_eq!(state.last_scene_identifier, "earth_centric");
+        assert_eq!(state.show_points, default.show_points);
+        assert_eq!(state.ev100, default.ev100);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn migrate_snapshot_with_invalid_fields() {
+        // Version 0 payload where move_speed was a string and with a field that was since removed
+        let snapshot = r#"{
+            "use_skyboxes": false,
+            "show_grid": false,
+            "show_axes": true,
+            "show_points": false,
+            "pixels_per_point": 2.0,
+            "frames_per_second_limit": 30.0,
+            "simulation_speed": 1.0,
+            "simulation_paused": true,
+            "move_speed": "fast",
+            "rotate_speed": 2.0,
+            "ev100": 9.0,
+            "selection": null,
+            "light_intensity": 5.0,
+            "last_scene_identifier": "planet_line_up"
+        }"#;
+
+        let state = AppState::deserialize_and_migrate(snapshot).unwrap();
+        let default = AppState::new();
+
+        assert_eq!(state.schema_version, APP_STATE_SCHEMA_VERSION);
+        assert_eq!(state.show_axes, true);
+        assert_eq!(state.show_points, false);
+        assert_eq!(state.frames_per_second_limit, 30.0);
+        assert_eq!(state.ev100, 9.0);
+        assert_eq!(state.move_speed, default.move_speed);
+        assert_eq!(state.camera.fov_v, default.camera.fov_v);
+        assert_eq!(state.last_scene_identifier, "planet_line_up");
+    }
+
+    #[wasm_bindgen_test]
+    pub fn background_color_round_trips() {
+        let mut state = AppState::new();
+        state.background_color = [0, 0, 0];
+        let serialized = serde_json::to_string(&state).unwrap();
+
+        let state = AppState::deserialize_and_migrate(&serialized).unwrap();
+        assert_eq!(state.background_color, [0, 0, 0]);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn body_colors_round_trip() {
+        let mut state = AppState::new();
+        state
+            .body_colors
+            .insert(String::from("499"), [0.9, 0.2, 0.1, 1.0]);
+        state
+            .body_colors
+            .insert(String::from("2000001"), [0.0, 1.0, 0.5, 1.0]);
+        let serialized = serde_json::to_string(&state).unwrap();
+
+        let loaded = AppState::deserialize_and_migrate(&serialized).unwrap();
+        assert_eq!(loaded.body_colors, state.body_colors);
+
+        // Older snapshots just don't have any
+        let loaded = AppState::deserialize_and_migrate("{}").unwrap();
+        assert!(loaded.body_colors.is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    pub fn migrate_legacy_ron_snapshot() {
+        // Version 0 payload as ron::ser::to_string_pretty saved it, with Point3 tuples and fewer windows than we have now
+        let snapshot = r#"(
+    use_skyboxes: true,
+    show_grid: false,
+    show_axes: true,
+    show_points: false,
+    pixels_per_point: 1.25,
+    frames_per_second_limit: 30,
+    simulation_speed: 86400,
+    simulation_paused: true,
+    move_speed: 7.5,
+    rotate_speed: 2,
+    selection: Some((12)),
+    camera: (
+        pos: (1, -2, 3.5),
+        up: (0, 0, 1),
+        target: (0, 0, 0),
+        fov_v: 45,
+        near: 0.0001,
+        far: 10000,
+    ),
+    open_windows: (
+        debug: false,
+        body_list: true,
+        scene_browser: true,
+        settings: false,
+        controls: false,
+        about: false,
+    ),
+    last_scene_identifier: "planets_and_satellites",
+)"#;
+
+        let state = AppState::deserialize_and_migrate(snapshot).unwrap();
+        let default = AppState::new();
+
+        assert_eq!(state.schema_version, APP_STATE_SCHEMA_VERSION);
+        assert_eq!(state.use_skyboxes, true);
+        assert_eq!(state.show_points, false);
+        assert_eq!(state.pixels_per_point, 1.25);
+        assert_eq!(state.frames_per_second_limit, 30.0);
+        assert_eq!(state.simulation_speed, 86400.0);
+        assert_eq!(state.simulation_paused, true);
+        assert_eq!(state.move_speed, 7.5);
+        assert_eq!(state.camera.pos, Point3::new(1.0, -2.0, 3.5));
+        assert_eq!(state.camera.fov_v, 45.0);
+        assert_eq!(state.open_windows.settings, false);
+        assert_eq!(state.last_scene_identifier, "planets_and_satellites");
+
+        // Fields added since then get their defaults
+        assert_eq!(state.ev100, default.ev100);
+        assert_eq!(state.tonemap, default.tonemap);
+        assert_eq!(state.camera.fov_axis, default.camera.fov_axis);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn egui_memory_round_trips_with_its_version() {
+        let mut memory = egui::Memory::default();
+        memory.options.tessellation_options.feathering = false;
+
+        let serialized = serialize_egui_memory(&memory).unwrap();
+        let loaded = deserialize_egui_memory(&serialized).unwrap();
+        assert_eq!(loaded.options.tessellation_options.feathering, false);
+
+        // Unversioned memory from before we versioned it
+        let unversioned = serde_json::to_string(&memory).unwrap();
+        let loaded = deserialize_egui_memory(&unversioned).unwrap();
+        assert_eq!(loaded.options.tessellation_options.feathering, false);
+
+        let newer = format!(
+            r#"{{ "schema_version": {}, "memory": {} }}"#,
+            EGUI_MEMORY_SCHEMA_VERSION + 1,
+            unversioned
+        );
+        assert!(deserialize_egui_memory(&newer).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    pub fn reject_newer_snapshot() {
+        let snapshot = format!(
+            r#"{{ "schema_version": {} }}"#,
+            APP_STATE_SCHEMA_VERSION + 1
+        );
+
+        assert!(AppState::deserialize_and_migrate(&snapshot).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    pub fn frame_pacing_strategies() {
+        // Counts how many of one second's worth of 60Hz callbacks draw when limited to 50fps
+        let count_draws = |pacing: FramePacing| -> u32 {
+            let mut limiter = FrameLimiter::default();
+            return (1..=60)
+                .filter(|i| limiter.should_draw(pacing, 50.0, *i as f64 / 60.0))
+                .count() as u32;
+        };
+
+        assert_eq!(count_draws(FramePacing::Unlimited), 60);
+        assert_eq!(count_draws(FramePacing::DropFrames), 30);
+
+        let accumulated = count_draws(FramePacing::Accumulate);
+        assert!(accumulated >= 49 && accumulated <= 51);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn saves_on_interval_or_request_and_skips_unchanged() {
+        let mut state = AppState::new();
+        state.autosave_interval_s = 10.0;
+        state.time_of_last_save = 100.0;
+
+        state.real_time_s = 105.0;
+        assert!(!state.is_save_due());
+        state.real_time_s = 111.0;
+        assert!(state.is_save_due());
+
+        // Manual only
+        state.autosave_interval_s = 0.0;
+        state.real_time_s = 1E6;
+        assert!(!state.is_save_due());
+        state.save_requested = true;
+        assert!(state.is_save_due());
+
+        // Keys are tracked separately
+        assert!(state.mark_saved("app_state", "{\"a\": 1}"));
+        assert!(!state.mark_saved("app_state", "{\"a\": 1}"));
+        assert!(state.mark_saved("egui_memory_json", "{\"a\": 1}"));
+        assert!(state.mark_saved("app_state", "{\"a\": 2}"));
+
+        // Saved hashes aren't part of what we save
+        let serialized = serde_json::to_string(&state).unwrap();
+        assert!(!serialized.contains("saved_hashes"));
+        assert!(serialized.contains("autosave_interval_s"));
+    }
+
+    #[wasm_bindgen_test]
+    pub fn toasts_stack_and_expire() {
+        let mut state = AppState::new();
+        state.real_time_s = 100.0;
+
+        state.add_info_toast(String::from("Saved"));
+        state.add_error_toast(String::from("Failed to fetch 'a.png'"));
+        state.add_warning_toast(String::from("Couldn't find 'Mars'"));
+        assert_eq!(state.toasts.len(), 3);
+        assert_eq!(state.toasts[1].severity, ToastSeverity::Error);
+
+        // Repeats just keep the toast up for longer, as the newest one
+        state.real_time_s = 103.0;
+        state.add_info_toast(String::from("Saved"));
+        assert_eq!(state.toasts.len(), 3);
+        assert_eq!(state.toasts[2].text, "Saved");
+        assert_eq!(
+            state.toasts[2].expire_time_s,
+            103.0 + ToastSeverity::Info.get_duration_s()
+        );
+
+        // Info goes away first, errors stay up the longest
+        state.real_time_s = 107.0;
+        state.remove_expired_toasts();
+        assert_eq!(state.toasts.len(), 3);
+        state.real_time_s = 112.0;
+        state.remove_expired_toasts();
+        assert_eq!(state.toasts.len(), 1);
+        assert_eq!(state.toasts[0].text, "Failed to fetch 'a.png'");
+
+        // Too many drops the oldest ones
+        for i in 0..MAX_TOASTS + 2 {
+            state.add_info_toast(format!("Toast {}", i));
+        }
+        assert_eq!(state.toasts.len(), MAX_TOASTS);
+        assert_eq!(state.toasts[0].text, "Toast 2");
     }
 }
@@ -1,5 +1,6 @@
 use crate::components::Component;
-use crate::managers::details_ui::DetailsUI;
+use crate::managers::details_ui::{get_number_notation, get_velocity_unit, DetailsUI};
+use crate::utils::units::format_velocity;
 use na::Vector3;
 use serde::{Deserialize, Serialize};
 
@@ -33,8 +34,17 @@ impl Component for KinematicComponent {
 
 impl DetailsUI for KinematicComponent {
     fn draw_details_ui(&mut self, ui: &mut egui::Ui) {
+        let notation = get_number_notation(ui);
+        let velocity_unit = get_velocity_unit(ui);
+        let speed = self.lin_vel.magnitude();
+
         ui.columns(2, |cols| {
-            cols[0].label("Linear velocity [Mm/s]:");
+            cols[0]
+                .label("Linear velocity [Mm/s]:")
+                .on_hover_text(format!(
+                    "Speed: {}",
+                    format_velocity(speed, velocity_unit, notation)
+                ));
             cols[1].horizontal(|ui| {
                 ui.add(egui::DragValue::new(&mut self.lin_vel.x).prefix("x: "));
                 ui.add(egui::DragValue::new(&mut self.lin_vel.y).prefix("y: "));
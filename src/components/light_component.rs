@@ -38,7 +38,33 @@ impl Component for LightComponent {
     }
 }
 impl DetailsUI for LightComponent {
-    fn draw_details_ui(&mut self, _ui: &mut egui::Ui) {
-        todo!();
+    fn draw_details_ui(&mut self, ui: &mut egui::Ui) {
+        ui.columns(2, |cols| {
+            cols[0].label("Type:");
+            cols[1].label(format!("{:?}", self.light_type));
+        });
+
+        ui.columns(2, |cols| {
+            cols[0].label("Color:");
+
+            let mut color: [f32; 3] = self.color.into();
+            if cols[1].color_edit_button_rgb(&mut color).changed() {
+                self.color = Vector3::from(color);
+            }
+        });
+
+        // Intensities go from candles to stars, so drag by a fraction of the current value instead
+        let speed = self.intensity.abs().max(1.0) * 0.01;
+        ui.columns(2, |cols| {
+            cols[0].label(match self.light_type {
+                LightType::Point => "Intensity [cd]:",
+                LightType::Directional => "Intensity [lux]:",
+            });
+            cols[1].add(
+                egui::DragValue::new(&mut self.intensity)
+                    .speed(speed)
+                    .clamp_range(0.0..=f32::MAX),
+            );
+        });
     }
 }
@@ -13,8 +13,11 @@ pub struct MeshComponent {
     pub last_ndc_position: Vector4<f32>,
 
     pub raycasting_visible: bool,
+    pub precise_raycasting: bool, // Raycast against the mesh's triangles instead of its collider
     pub visible: bool,
+    pub color_override: Option<[f32; 4]>, // Replaces the base color factor of every material we're drawn with
     mesh: Option<Rc<RefCell<Mesh>>>,
+    lod_meshes: Vec<Rc<RefCell<Mesh>>>, // Coarser versions of `mesh`, with the first one being LOD 1
     material_overrides: Vec<Option<Rc<RefCell<Material>>>>,
 }
 
@@ -28,8 +31,34 @@ impl MeshComponent {
         return self.mesh.clone();
     }
 
+    /// Also drops any LOD meshes, as they were made for the previous mesh
     pub fn set_mesh(&mut self, mesh: Option<Rc<RefCell<Mesh>>>) {
         self.mesh = mesh;
+        self.lod_meshes.clear();
+    }
+
+    /// Sets the coarser versions of our mesh to draw when we're small on screen, from most to least detailed.
+    /// They should have the same primitives as the mesh, so that the material overrides still apply
+    pub fn set_lod_meshes(&mut self, lod_meshes: Vec<Rc<RefCell<Mesh>>>) {
+        self.lod_meshes = lod_meshes;
+    }
+
+    /// Number of levels of detail we can draw, including the mesh itself as LOD 0
+    pub fn get_num_lods(&self) -> usize {
+        return self.mesh.is_some() as usize + self.lod_meshes.len();
+    }
+
+    /// Mesh to draw for level of detail `lod`, or the coarsest one we have if we don't have that many.
+    /// LOD 0 is the mesh itself
+    pub fn get_lod_mesh(&self, lod: usize) -> Option<Rc<RefCell<Mesh>>> {
+        if lod == 0 || self.lod_meshes.is_empty() {
+            return self.mesh.clone();
+        }
+
+        return self
+            .lod_meshes
+            .get((lod - 1).min(self.lod_meshes.len() - 1))
+            .cloned();
     }
 
     pub fn get_material_override(&self, index: usize) -> Option<Rc<RefCell<Material>>> {
@@ -79,8 +108,11 @@ impl Default for MeshComponent {
         return Self {
             last_ndc_position: Vector4::new(0.0, 0.0, 0.0, 1.0),
             raycasting_visible: true,
+            precise_raycasting: false,
             visible: true,
+            color_override: None,
             mesh: None,
+            lod_meshes: Vec::new(),
             material_overrides: Vec::new(),
         };
     }
@@ -104,6 +136,23 @@ impl DetailsUI for MeshComponent {
             );
         });
 
+        ui.columns(2, |cols| {
+            cols[0].label("Levels of detail:");
+            cols[1].label(self.get_num_lods().to_string());
+        });
+
+        if let Some(color) = &self.color_override {
+            ui.columns(2, |cols| {
+                cols[0].label("Color override:");
+                cols[1].label(format!("{:.2} {:.2} {:.2}", color[0], color[1], color[2]));
+            });
+        }
+
+        ui.columns(2, |cols| {
+            cols[0].label("Precise raycasting:");
+            cols[1].checkbox(&mut self.precise_raycasting, "");
+        });
+
         for i in 0..self.get_num_materials() {
             if let Some(mat) = self.get_resolved_material(i) {
                 mat.borrow_mut().draw_details_ui(ui);
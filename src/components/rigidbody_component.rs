@@ -1,6 +1,9 @@
 use crate::components::Component;
-use crate::managers::{details_ui::DetailsUI, scene::Scene};
+use crate::managers::details_ui::{get_number_notation, get_velocity_unit, DetailsUI};
+use crate::managers::scene::Scene;
+use crate::utils::string::format_number;
 use crate::utils::transform::Transform;
+use crate::utils::units::format_velocity;
 use na::{Matrix3, Vector3};
 use nalgebra::UnitQuaternion;
 use serde::{Deserialize, Serialize};
@@ -58,6 +61,9 @@ impl Component for RigidBodyComponent {
 
 impl DetailsUI for RigidBodyComponent {
     fn draw_details_ui(&mut self, ui: &mut egui::Ui) {
+        let notation = get_number_notation(ui);
+        let velocity_unit = get_velocity_unit(ui);
+
         ui.columns(2, |cols| {
             cols[0].label("Collision enabled:");
             cols[1].checkbox(&mut self.collision_enabled, "");
@@ -65,7 +71,9 @@ impl DetailsUI for RigidBodyComponent {
 
         ui.columns(2, |cols| {
             cols[0].label("Mass [kg]:");
-            cols[1].add(egui::DragValue::new(&mut self.mass));
+            cols[1]
+                .add(egui::DragValue::new(&mut self.mass))
+                .on_hover_text(format_number(self.mass, notation));
         });
 
         ui.collapsing("Last transform:", |ui| {
@@ -124,8 +132,15 @@ impl DetailsUI for RigidBodyComponent {
         let mut vel_y = self.lin_mom.y / self.mass;
         let mut vel_z = self.lin_mom.z / self.mass;
 
+        let speed = self.lin_mom.magnitude() / self.mass;
+
         ui.columns(2, |cols| {
-            cols[0].label("Linear velocity [Mm/s]:");
+            cols[0]
+                .label("Linear velocity [Mm/s]:")
+                .on_hover_text(format!(
+                    "Speed: {}",
+                    format_velocity(speed, velocity_unit, notation)
+                ));
             cols[1].horizontal(|ui| {
                 ui.add(egui::DragValue::new(&mut vel_x).prefix("x: "));
                 ui.add(egui::DragValue::new(&mut vel_y).prefix("y: "));
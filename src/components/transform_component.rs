@@ -11,6 +11,14 @@ pub struct TransformComponent {
 
     #[serde(skip)]
     world_transform: Transform<f64>,
+
+    // Whether the transform update system turns the world transform to face the camera every frame
+    #[serde(default)]
+    billboard: bool,
+
+    // Whether the world transform is drawn scene.render_scale times larger than it actually is
+    #[serde(default)]
+    render_scaled: bool,
 }
 impl TransformComponent {
     pub fn new() -> Self {
@@ -32,6 +40,35 @@ impl TransformComponent {
     pub fn get_world_transform_mut(&mut self) -> &mut Transform<f64> {
         return &mut self.world_transform;
     }
+
+    pub fn is_billboard(&self) -> bool {
+        return self.billboard;
+    }
+
+    /// Billboards have their world rotation replaced so that their +Z points at the camera and their +Y is as close
+    /// to the camera's up as it can be, which keeps text on them upright on screen. Their children don't turn along
+    pub fn set_billboard(&mut self, billboard: bool) {
+        self.billboard = billboard;
+    }
+
+    pub fn is_render_scaled(&self) -> bool {
+        return self.render_scaled;
+    }
+
+    /// Render scaled transforms have their world scale multiplied by scene.render_scale. Meant for the entities holding
+    /// the meshes of bodies, whose world transform is then no longer their actual size: See get_physical_world_transform
+    pub fn set_render_scaled(&mut self, render_scaled: bool) {
+        self.render_scaled = render_scaled;
+    }
+
+    /// World transform without the exaggeration of scene.render_scale, if we were render scaled by `render_scale`
+    pub fn get_physical_world_transform(&self, render_scale: f64) -> Transform<f64> {
+        let mut result = self.world_transform.clone();
+        if self.render_scaled && render_scale > 0.0 {
+            result.scale /= render_scale;
+        }
+        return result;
+    }
 }
 
 impl Component for TransformComponent {
@@ -1,19 +1,35 @@
-use crate::app_state::AppState;
-use crate::managers::scene::SceneManager;
+use crate::app_state::{add_global_toast, AppState, ReferenceChange, ToastSeverity};
+use crate::components::TransformComponent;
+use crate::managers::resource::gltf::{decompress_gltf_bytes, parse_emissive_strengths};
+use crate::managers::scene::{Entity, SceneBuilder, SceneManager};
 use crate::managers::{
     EventManager, InputManager, InterfaceManager, OrbitManager, ResourceManager, SystemManager,
 };
 use crate::utils::log::*;
-use crate::STATE;
+use crate::utils::star_catalog::parse_star_catalog;
+use crate::utils::view_link::{ViewLink, ViewLinkCamera};
+use crate::utils::web::{location_hash, replace_location_hash};
+use crate::{GLCTX, STATE};
+use na::{Point3, Unit};
+
+// Time steps taken while paused are split into physics steps at most this long
+const MAX_TIME_STEP_SUBSTEP_S: f64 = 3600.0;
+
+// Longer time steps (e.g. jumping to the current date) are spread over frames, taking at most this many physics
+// steps each frame so that we don't freeze
+const MAX_TIME_STEP_SUBSTEPS_PER_FRAME: u32 = 100;
 
 pub struct Engine {
     pub res_man: ResourceManager,
     pub sys_man: SystemManager,
     pub event_man: EventManager,
     pub input_man: InputManager,
-    pub int_man: InterfaceManager,
+    pub int_man: Option<InterfaceManager>, // None when running headless
     pub scene_man: SceneManager,
     pub orbit_man: OrbitManager,
+
+    // Url, content type and data of files that arrived while the WebGL context was lost
+    deferred_bytes: Vec<(String, String, Vec<u8>)>,
 }
 impl Engine {
     pub fn new() -> Self {
@@ -26,19 +42,42 @@ impl Engine {
             sys_man,
             event_man: EventManager::new(),
             input_man: InputManager::new(),
-            int_man: InterfaceManager::new(),
+            int_man: Some(InterfaceManager::new()),
             orbit_man: OrbitManager::new(),
+            deferred_bytes: Vec::new(),
         };
 
         return new_engine;
     }
 
+    /// Creates an Engine without rendering or UI, that can be advanced with `step`.
+    /// Useful for tests and automation, as it never touches GL or the DOM
+    pub fn new_headless() -> Self {
+        return Self {
+            res_man: ResourceManager::new(),
+            sys_man: SystemManager::new_headless(),
+            event_man: EventManager::new(),
+            input_man: InputManager::new(),
+            int_man: None,
+            scene_man: SceneManager::new(),
+            orbit_man: OrbitManager::new(),
+            deferred_bytes: Vec::new(),
+        };
+    }
+
     pub fn update(&mut self, state: &mut AppState) {
         // Startup the UI frame, collecting UI elements
-        self.int_man.begin_frame(state);
+        if let Some(int_man) = &mut self.int_man {
+            int_man.begin_frame(state);
+        }
 
         // Run the input manager after begin frame to allow the UI a chance to intercept input
-        self.input_man.run(state);
+        self.input_man
+            .run(state, self.scene_man.get_current_scene());
+
+        self.apply_pending_time_step(state);
+
+        self.apply_belt_request(state);
 
         if let Some(scene) = self.scene_man.get_main_scene_mut() {
             // Run all systems
@@ -46,32 +85,172 @@ impl Engine {
         }
 
         // Draw the UI elements
-        self.int_man.end_frame(
-            state,
-            &mut self.scene_man,
-            &mut self.res_man,
-            &self.orbit_man,
-        );
+        if let Some(int_man) = &mut self.int_man {
+            int_man.end_frame(
+                state,
+                &mut self.scene_man,
+                &mut self.res_man,
+                &self.orbit_man,
+            );
+        }
+    }
+
+    /// Deterministically advances the current scene by `dt_s` simulated seconds, without input, UI or rendering.
+    /// Ignores simulation_paused and simulation_speed, as `dt_s` is already in simulated time
+    pub fn step(&mut self, state: &mut AppState, dt_s: f64) {
+        state.sim_delta_time_s = dt_s;
+        state.sim_time_s += dt_s;
+
+        if let Some(scene) = self.scene_man.get_current_scene_mut() {
+            self.sys_man.step(state, scene);
+        }
+    }
+
+    /// Advances the current scene by state.pending_time_step_s (backwards if negative), which the input manager
+    /// accumulates when stepping through time while paused. Whatever doesn't fit in MAX_TIME_STEP_SUBSTEPS_PER_FRAME
+    /// is left pending for the next frames
+    fn apply_pending_time_step(&mut self, state: &mut AppState) {
+        if state.pending_time_step_s == 0.0 {
+            state.time_step_total_s = 0.0;
+            return;
+        }
+
+        let delta_s = state.pending_time_step_s;
+        state.time_step_total_s = state.time_step_total_s.max(delta_s.abs());
+
+        let num_steps = (delta_s.abs() / MAX_TIME_STEP_SUBSTEP_S).ceil().max(1.0) as u32;
+        let substep_s = delta_s / num_steps as f64;
+        let frame_steps = num_steps.min(MAX_TIME_STEP_SUBSTEPS_PER_FRAME);
+        for _ in 0..frame_steps {
+            self.step(state, substep_s);
+        }
+
+        if frame_steps == num_steps {
+            state.pending_time_step_s = 0.0;
+            state.time_step_total_s = 0.0;
+        } else {
+            state.pending_time_step_s = delta_s - substep_s * frame_steps as f64;
+        }
+
+        // Don't move things again when the systems run for this frame
+        state.sim_delta_time_s = 0.0;
+    }
+
+    /// Adds the asteroid belt of state.belt_request to the current scene, if the interface asked for one
+    fn apply_belt_request(&mut self, state: &mut AppState) {
+        let dist = match state.belt_request.take() {
+            Some(dist) => dist,
+            None => return,
+        };
+        let scene = match self.scene_man.get_current_scene_mut() {
+            Some(scene) => scene,
+            None => return,
+        };
+
+        let mut builder = SceneBuilder::from_scene(scene, &mut self.res_man);
+        let result = builder.add_belt(&dist);
+        *scene = builder.build();
+
+        match result {
+            Ok(asteroids) => {
+                info!(
+                    LogCat::Scene,
+                    "Generated {} belt asteroids with seed {}",
+                    asteroids.len(),
+                    dist.seed
+                );
+                state.add_info_toast(format!("Added {} asteroids", asteroids.len()));
+            }
+            Err(message) => {
+                warning!(LogCat::Scene, "Failed to generate belt: {}", message);
+                state.add_warning_toast(format!("Failed to generate belt: {}", message));
+            }
+        }
+    }
+
+    /// World-space position of `entity` in the current scene as physics sees it, in Mm
+    pub fn body_position(&self, entity: Entity) -> Option<Point3<f64>> {
+        let scene = self.scene_man.get_current_scene()?;
+
+        return scene.get_physical_position(entity).map(Point3::from);
     }
 
     pub fn resize(&mut self, width: u32, height: u32) {
         self.sys_man.resize(width, height);
     }
 
-    pub fn receive_text(&mut self, url: &str, content_type: &str, text: &str) {
-        match content_type {
+    /// Rebuilds all of our GL resources once the WebGL context is restored after being lost
+    pub fn restore_gl_resources(&mut self, state: &AppState) {
+        self.res_man.restore_gl_resources();
+        self.sys_man.restore_gl_resources(state);
+
+        if let Some(int_man) = &mut self.int_man {
+            int_man.restore_gl_resources();
+        }
+
+        if let Some(scene) = self.scene_man.get_current_scene_mut() {
+            GLCTX.with(|ctx| {
+                for orbit in &scene.orbits {
+                    if let Some(trail) = &orbit.trail {
+                        trail.borrow_mut().restore_gl_objects(ctx);
+                    }
+                }
+
+                if let Some(trail) = scene
+                    .trajectory_prediction
+                    .as_ref()
+                    .and_then(|prediction| prediction.trail.as_ref())
+                {
+                    trail.borrow_mut().restore_gl_objects(ctx);
+                }
+
+                if let Some(trail) = scene
+                    .ground_track
+                    .as_ref()
+                    .and_then(|track| track.trail.as_ref())
+                {
+                    trail.borrow_mut().restore_gl_objects(ctx);
+                }
+
+                for body_trail in scene.body_trails.values() {
+                    if let Some(trail) = &body_trail.trail {
+                        trail.borrow_mut().restore_gl_objects(ctx);
+                    }
+                }
+            });
+        }
+
+        // Now we can also handle whatever arrived while we couldn't upload it
+        for (url, content_type, mut data) in std::mem::take(&mut self.deferred_bytes) {
+            self.receive_bytes(&url, &content_type, &mut data);
+        }
+    }
+
+    pub fn receive_text(
+        &mut self,
+        url: &str,
+        content_type: &str,
+        text: &str,
+    ) -> Result<(), String> {
+        let result = match content_type {
             "scene" => self.receive_scene_text(url, text),
-            "body_database" | "vectors_database" | "elements_database" => {
+            "star_catalog" => self.receive_star_catalog_text(url, text),
+            "body_database" | "vectors_database" | "elements_database" | "mpc_database" => {
                 self.receive_database_text(url, content_type, text)
             }
-            _ => error!(
-                LogCat::Io,
-                "Can't handle text content_type '{}' from url: '{}'", content_type, url
-            ),
+            _ => Err(format!(
+                "Can't handle text content_type '{}' from url: '{}'",
+                content_type, url
+            )),
+        };
+
+        if let Err(err) = &result {
+            error!(LogCat::Io, "Failed to receive text from '{}': {}", url, err);
         }
+        return result;
     }
 
-    fn receive_scene_text(&mut self, url: &str, text: &str) {
+    fn receive_scene_text(&mut self, url: &str, text: &str) -> Result<(), String> {
         info!(
             LogCat::Io,
             "Loading scene from '{}' (length {})",
@@ -79,7 +258,55 @@ impl Engine {
             text.len()
         );
 
-        self.scene_man.receive_serialized_scene(text);
+        return self.scene_man.receive_serialized_scene(text);
+    }
+
+    fn receive_star_catalog_text(&mut self, url: &str, text: &str) -> Result<(), String> {
+        info!(
+            LogCat::Io,
+            "Loading star catalog from '{}' (length {})",
+            url,
+            text.len()
+        );
+
+        let catalog = parse_star_catalog(text)?;
+        info!(LogCat::Io, "Loaded {} catalog stars", catalog.len());
+
+        self.scene_man.set_star_catalog(catalog);
+        return Ok(());
+    }
+
+    /// Loads a body database the user picked from their own files, on top of the default ones we fetch on startup.
+    /// `content_type` is either "body_database" or "mpc_database", which are both named after the file.
+    /// Reports how it went with a toast, and reloads the current scene if it uses bodies from that database
+    pub fn receive_user_database_text(
+        &mut self,
+        file_name: &str,
+        content_type: &str,
+        text: Result<String, String>,
+        state: &mut AppState,
+    ) {
+        let result = text.and_then(|text| self.receive_text(file_name, content_type, &text));
+        if let Err(err) = result {
+            state.add_error_toast(format!("Failed to load '{}':\n{}", file_name, err));
+            return;
+        }
+
+        let db_name = std::path::Path::new(file_name)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default();
+        state.add_info_toast(format!(
+            "Loaded body database '{}' from '{}'",
+            db_name, file_name
+        ));
+
+        self.scene_man.reload_current_scene_if_using_database(
+            db_name,
+            &mut self.res_man,
+            &self.orbit_man,
+            state,
+        );
     }
 
     pub fn try_loading_last_scene(&mut self) {
@@ -93,7 +320,116 @@ impl Engine {
         });
     }
 
-    fn receive_database_text(&mut self, url: &str, content_type: &str, text: &str) {
+    /// Loads the scene and view described by the URL fragment, if there is one.
+    /// Returns whether it loaded a scene
+    pub fn try_applying_location_hash(&mut self) -> bool {
+        let link = location_hash().and_then(|hash| ViewLink::from_hash(&hash));
+        if link.is_none() {
+            return false;
+        }
+        let link = link.unwrap();
+
+        let mut applied = false;
+        STATE.with(|s| {
+            if let Ok(mut ref_mut_s) = s.try_borrow_mut() {
+                let s = ref_mut_s.as_mut().unwrap();
+
+                applied = self.apply_view_link(s, &link);
+            }
+        });
+        return applied;
+    }
+
+    /// Loads the scene from `link` and restores its time, reference and camera pose
+    pub fn apply_view_link(&mut self, state: &mut AppState, link: &ViewLink) -> bool {
+        if !self.scene_man.descriptions.contains_key(&link.scene) {
+            warning!(
+                LogCat::Scene,
+                "Failed to find a description for linked scene '{}'",
+                link.scene
+            );
+            return false;
+        }
+
+        info!(LogCat::Scene, "Applying view link '{}'", link.to_hash());
+        self.scene_man
+            .load_scene(&link.scene, &mut self.res_man, &self.orbit_man, state);
+
+        // Scenes always start at their epoch, so we have to simulate our way to the linked time. This goes through
+        // the same substeps as stepping through time, spread over the next frames
+        if let Some(target_s) = link.sim_time_s {
+            state.pending_time_step_s = target_s - state.sim_time_s;
+        }
+
+        if let Some(camera) = &link.camera {
+            state.camera.pos = camera.pos;
+            state.camera.target = camera.target;
+            state.camera.up = Unit::new_normalize(camera.up);
+            state.entity_going_to = None;
+        }
+
+        // Our camera pose is already wrt. the reference, so just swap to it without moving the camera
+        let reference = link.reference.as_ref().and_then(|r| {
+            let scene = self.scene_man.get_current_scene()?;
+            return scene
+                .get_entity_from_body_id(r)
+                .or_else(|| scene.get_entity_from_name(r));
+        });
+        state.reference_entity = None;
+        state.reference_translation = None;
+        state.next_reference_entity =
+            reference.and_then(|r| Some(ReferenceChange::FocusKeepCoords(r)));
+
+        return true;
+    }
+
+    /// Describes the current view, if we have a loaded scene
+    pub fn get_view_link(&self, state: &AppState) -> Option<ViewLink> {
+        let scene = self.scene_man.get_current_scene()?;
+        if !self.scene_man.descriptions.contains_key(&scene.identifier) {
+            return None;
+        }
+
+        let reference = state.reference_entity.and_then(|r| {
+            return scene
+                .get_body_id_from_entity(r)
+                .or_else(|| scene.get_entity_name(r))
+                .map(|n| n.to_owned());
+        });
+
+        return Some(ViewLink {
+            scene: scene.identifier.clone(),
+            sim_time_s: Some(state.sim_time_s),
+            reference,
+            camera: Some(ViewLinkCamera {
+                pos: state.camera.pos,
+                target: state.camera.target,
+                up: state.camera.up.into_inner(),
+            }),
+        });
+    }
+
+    /// Keeps the URL fragment pointing at the current view, so that it can be copied and shared
+    pub fn update_location_hash(&self, state: &AppState) {
+        // Don't clobber the link we were opened with before we got a chance to apply it
+        if !state.loading.is_done() {
+            return;
+        }
+
+        if let Some(link) = self.get_view_link(state) {
+            let hash = link.to_hash();
+            if location_hash().as_ref() != Some(&hash) {
+                replace_location_hash(&hash);
+            }
+        }
+    }
+
+    fn receive_database_text(
+        &mut self,
+        url: &str,
+        content_type: &str,
+        text: &str,
+    ) -> Result<(), String> {
         info!(
             LogCat::Io,
             "Loading database file from '{}' (length {})",
@@ -101,10 +437,24 @@ impl Engine {
             text.len()
         );
 
-        self.orbit_man.load_database_file(url, content_type, text);
+        return self
+            .orbit_man
+            .load_database_file(url, content_type, text)
+            .map_err(|e| e.to_string());
     }
 
     pub fn receive_bytes(&mut self, url: &str, content_type: &str, data: &mut [u8]) {
+        // We can't create GL resources while the context is lost, so hold on to these until it's restored
+        if is_gl_context_lost() {
+            debug!(
+                LogCat::Io,
+                "Deferring bytes from url '{}' until the WebGL context is restored", url
+            );
+            self.deferred_bytes
+                .push((url.to_owned(), content_type.to_owned(), data.to_vec()));
+            return;
+        }
+
         match content_type {
             "cubemap_face" => self.res_man.receive_cubemap_face_file_bytes(url, data),
             "texture" => self.res_man.receive_texture_file_bytes(url, data),
@@ -124,9 +474,261 @@ impl Engine {
             data.len()
         );
 
-        if let Ok((gltf_doc, gltf_buffers, gltf_images)) = gltf::import_slice(data) {
-            self.res_man
-                .load_gltf_data(file_identifier, &gltf_doc, &gltf_buffers, &gltf_images);
+        let decompressed = match decompress_gltf_bytes(data) {
+            Ok(decompressed) => decompressed,
+            Err(err) => {
+                error!(
+                    LogCat::Gltf,
+                    "Failed to load GLTF from file '{}': {}", file_identifier, err
+                );
+                add_global_toast(
+                    format!("Failed to load '{}':\n{}", file_identifier, err),
+                    ToastSeverity::Error,
+                );
+                return;
+            }
+        };
+        let data: &[u8] = decompressed.as_deref().unwrap_or(data);
+
+        let emissive_strengths = parse_emissive_strengths(data);
+        match gltf::import_slice(data) {
+            Ok((gltf_doc, gltf_buffers, gltf_images)) => {
+                self.res_man.load_gltf_data(
+                    file_identifier,
+                    &gltf_doc,
+                    &gltf_buffers,
+                    &gltf_images,
+                    &emissive_strengths,
+                );
+                self.res_man.build_atlas();
+            }
+            Err(err) => {
+                error!(
+                    LogCat::Gltf,
+                    "Failed to parse GLTF from file '{}': {}", file_identifier, err
+                );
+                add_global_toast(
+                    format!("Failed to parse '{}':\n{}", file_identifier, err),
+                    ToastSeverity::Error,
+                );
+            }
+        }
+    }
+}
+
+/// Assumes the context is fine if the app state is busy, as then we're in the middle of a frame
+fn is_gl_context_lost() -> bool {
+    return STATE.with(|s| {
+        return s
+            .try_borrow()
+            .ok()
+            .and_then(|s| s.as_ref().map(|s| s.gl_context_lost))
+            .unwrap_or(false);
+    });
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+    use crate::components::RigidBodyComponent;
+    use crate::managers::orbit::{BodyDescription, OrbitalElements};
+    use crate::managers::scene::{Scene, SceneBuilder};
+    use crate::utils::orbits::GRAVITATION_CONSTANT;
+    use crate::utils::units::{
+        get_time_step_to_jdn, unix_time_ms_to_julian_date_number, Jdn, Mm, J2000_JDN,
+    };
+    use na::Vector3;
+
+    const SUN_MASS: f64 = 1.989E30; // kg
+    const EARTH_MASS: f64 = 5.972E24; // kg
+    const EARTH_ORBIT_RADIUS: f64 = 149597.870; // Mm
+
+    /// Speed of a circular orbit of EARTH_ORBIT_RADIUS around SUN_MASS, in Mm/s
+    fn get_earth_speed() -> f64 {
+        return (GRAVITATION_CONSTANT * SUN_MASS / EARTH_ORBIT_RADIUS).sqrt();
+    }
+
+    /// Adds a Sun at the origin and an Earth on a circular orbit around it, starting on +X and moving towards +Y
+    fn add_sun_and_earth(scene: &mut Scene) -> (Entity, Entity) {
+        let sun = scene.add_test_body("Sun", Vector3::zeros(), SUN_MASS);
+
+        let earth = scene.add_test_body(
+            "Earth",
+            Vector3::new(EARTH_ORBIT_RADIUS, 0.0, 0.0),
+            EARTH_MASS,
+        );
+        let earth_body = scene
+            .get_component_mut::<RigidBodyComponent>(earth)
+            .unwrap();
+        earth_body.lin_mom = Vector3::new(0.0, get_earth_speed() * EARTH_MASS, 0.0);
+
+        return (sun, earth);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn headless_circular_orbit_returns_to_start() {
+        let mut engine = Engine::new_headless();
+        let mut state = AppState::new();
+
+        let radius = EARTH_ORBIT_RADIUS;
+        let period_s = 2.0 * std::f64::consts::PI * (radius / get_earth_speed());
+
+        let (_, earth) = add_sun_and_earth(engine.scene_man.get_current_scene_mut().unwrap());
+
+        let num_steps = 10000;
+        let dt_s = period_s / num_steps as f64;
+        for _ in 0..num_steps {
+            engine.step(&mut state, dt_s);
+        }
+
+        let end_pos = engine.body_position(earth).unwrap();
+        let dist = (end_pos - Point3::new(radius, 0.0, 0.0)).magnitude();
+        assert!(
+            dist < radius * 0.01,
+            "Ended {} Mm away from the start",
+            dist
+        );
+        assert!((state.sim_time_s - period_s).abs() < 1.0);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn time_steps_go_back_and_forth() {
+        let mut engine = Engine::new_headless();
+        let mut state = AppState::new();
+        state.simulation_paused = true;
+
+        let radius = EARTH_ORBIT_RADIUS;
+        let speed = get_earth_speed();
+
+        let (_, earth) = add_sun_and_earth(engine.scene_man.get_current_scene_mut().unwrap());
+
+        let start_pos = engine.body_position(earth).unwrap();
+
+        for _ in 0..10 {
+            state.pending_time_step_s += state.time_step_days * 86400.0;
+            engine.apply_pending_time_step(&mut state);
         }
+        assert!((state.sim_time_s - 10.0 * 86400.0).abs() < 1E-6);
+        assert_eq!(state.pending_time_step_s, 0.0);
+        assert_eq!(state.sim_delta_time_s, 0.0);
+
+        // Moved about 10 days worth along the orbit
+        let moved_pos = engine.body_position(earth).unwrap();
+        let expected_angle = 10.0 * 86400.0 * speed / radius;
+        let angle = moved_pos.y.atan2(moved_pos.x);
+        assert!((angle - expected_angle).abs() < expected_angle * 0.01);
+
+        for _ in 0..10 {
+            state.pending_time_step_s -= state.time_step_days * 86400.0;
+            engine.apply_pending_time_step(&mut state);
+        }
+        assert!(state.sim_time_s.abs() < 1E-6);
+
+        let end_pos = engine.body_position(earth).unwrap();
+        assert!((end_pos - start_pos).magnitude() < radius * 0.001);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn snapping_to_now_matches_the_clock() {
+        let mut engine = Engine::new_headless();
+        let mut state = AppState::new();
+
+        // A month behind, so that it takes a few frames to catch up
+        let now = unix_time_ms_to_julian_date_number(js_sys::Date::now());
+        let epoch = Jdn(now.0 - 30.0);
+        engine.scene_man.get_current_scene_mut().unwrap().epoch = epoch;
+
+        state.pending_time_step_s = get_time_step_to_jdn(state.sim_time_s, epoch, now);
+        let total_s = state.pending_time_step_s;
+        engine.apply_pending_time_step(&mut state);
+        assert_eq!(state.time_step_total_s, total_s);
+        assert!(state.pending_time_step_s > 0.0 && state.pending_time_step_s < total_s);
+        assert!((state.sim_time_s + state.pending_time_step_s - total_s).abs() < 1E-3);
+
+        let mut frames = 1;
+        while state.pending_time_step_s != 0.0 {
+            engine.apply_pending_time_step(&mut state);
+            frames += 1;
+        }
+        assert_eq!(frames, 8);
+        assert_eq!(state.time_step_total_s, 0.0);
+
+        // Where the clock was when we snapped, to the minute the toolbar shows
+        let sim_jdn = state.sim_time_s / 86400.0 + epoch.0;
+        assert!((sim_jdn - now.0).abs() * 86400.0 < 60.0);
+
+        // J2000 is noon of January 1st 2000, UTC
+        assert_eq!(
+            unix_time_ms_to_julian_date_number(946728000000.0),
+            J2000_JDN
+        );
+    }
+
+    #[wasm_bindgen_test]
+    pub fn visual_orbit_scale_only_moves_drawn_moons() {
+        let jupiter_mass = 1.898E27; // kg
+        let radius = 421.7; // Mm, like Io
+        let period_s = 2.0
+            * std::f64::consts::PI
+            * (f64::powi(radius, 3) / (GRAVITATION_CONSTANT * jupiter_mass)).sqrt();
+
+        let run = |visual_orbit_scale: f64| {
+            let mut engine = Engine::new_headless();
+            let mut state = AppState::new();
+
+            let new_body = |id: &str, mass: f32| BodyDescription {
+                mass: Some(mass),
+                ..BodyDescription::new_test_body(id, id, 1.0)
+            };
+
+            let mut scene = {
+                let mut builder = SceneBuilder::new("moons", &mut engine.res_man);
+                let jupiter = builder
+                    .add_body(new_body("599", jupiter_mass as f32))
+                    .unwrap();
+                let io = builder.add_body(new_body("501", 8.9E22)).unwrap();
+                let elements = OrbitalElements {
+                    ref_id: String::from("599"),
+                    epoch: J2000_JDN,
+                    semi_major_axis: Mm(radius),
+                    sidereal_orbit_period_days: period_s / 86400.0,
+                    ..OrbitalElements::default()
+                };
+                builder.set_orbit(io, jupiter, &elements);
+                builder.build()
+            };
+            scene.visual_orbit_scale = visual_orbit_scale;
+            engine.scene_man.set_current_scene(scene, &mut state);
+
+            for _ in 0..100 {
+                engine.step(&mut state, period_s / 1000.0);
+            }
+
+            let scene = engine.scene_man.get_current_scene().unwrap();
+            let jupiter = scene.get_entity_from_body_id("599").unwrap();
+            let io = scene.get_entity_from_body_id("501").unwrap();
+            let drawn_pos = |entity: Entity| {
+                scene
+                    .get_component::<TransformComponent>(entity)
+                    .unwrap()
+                    .get_world_transform()
+                    .trans
+            };
+
+            return (
+                engine.body_position(io).unwrap() - engine.body_position(jupiter).unwrap(),
+                drawn_pos(io) - drawn_pos(jupiter),
+            );
+        };
+
+        let (physical, drawn) = run(1.0);
+        let (spread_physical, spread_drawn) = run(10.0);
+
+        assert_eq!(physical, drawn);
+        assert!((spread_physical - physical).magnitude() < 1E-9);
+        assert!((spread_drawn - physical * 10.0).magnitude() < 1E-6);
     }
 }
@@ -9,17 +9,16 @@ extern crate ron;
 extern crate serde;
 extern crate wasm_bindgen;
 
-use crate::app_state::AppState;
+use crate::app_state::{serialize_egui_memory, AppState};
 use crate::engine::Engine;
 use crate::utils::log::*;
 use crate::utils::web::{
-    get_canvas, get_gl_context, local_storage_remove, request_animation_frame, request_text,
-    setup_event_handlers,
+    get_canvas, get_gl_context, is_local_storage_enabled, local_storage_remove,
+    request_animation_frame, request_text, setup_event_handlers,
 };
-use futures::future::join_all;
+use futures::stream::{FuturesUnordered, StreamExt};
 use std::cell::RefCell;
 use std::rc::Rc;
-use utils::web::local_storage_set;
 use wasm_bindgen::prelude::*;
 use web_sys::HtmlCanvasElement;
 
@@ -65,8 +64,11 @@ pub async fn start() -> Result<(), JsValue> {
         Scene,
         StateVectors,
         OscElements,
+        StarCatalog,
     }
     struct AssetRequest(&'static str, AssetType);
+
+    // These are just the defaults: Users can load their own body databases from the settings window later
     let requests = vec![
         AssetRequest("public/database/artificial.json", AssetType::BodyDatabase),
         AssetRequest("public/database/asteroids.json", AssetType::BodyDatabase),
@@ -89,10 +91,13 @@ pub async fn start() -> Result<(), JsValue> {
             AssetType::StateVectors,
         ),
         AssetRequest("public/database/osc_elements.json", AssetType::OscElements),
+        AssetRequest("public/database/star_catalog.csv", AssetType::StarCatalog),
         AssetRequest("public/scenes/earth_centric.ron", AssetType::Scene),
+        AssetRequest("public/scenes/far_from_origin.ron", AssetType::Scene),
         AssetRequest("public/scenes/full_solar_system.ron", AssetType::Scene),
         AssetRequest("public/scenes/gltf_test.ron", AssetType::Scene),
         AssetRequest("public/scenes/light_test.ron", AssetType::Scene),
+        AssetRequest("public/scenes/lunar_eclipse.ron", AssetType::Scene),
         AssetRequest("public/scenes/planet_line_up.ron", AssetType::Scene),
         AssetRequest("public/scenes/planets_and_satellites.ron", AssetType::Scene),
     ];
@@ -102,45 +107,10 @@ pub async fn start() -> Result<(), JsValue> {
         asset_type: AssetType,
     }
 
-    let promises = join_all(requests.iter().map(|ar| async move {
-        match request_text(ar.0).await {
-            Ok(res) => Ok(AssetResponse {
-                url: ar.0,
-                data: res,
-                asset_type: ar.1,
-            }),
-            Err(err) => Err(err),
-        }
-    }));
-
-    let results: Vec<AssetResponse> = promises
-        .await
-        .into_iter()
-        .collect::<Result<Vec<AssetResponse>, JsValue>>()
-        .unwrap();
-
-    ENGINE.with(|e| {
-        let mut ref_mut = e.borrow_mut();
-        let e = ref_mut.as_mut().unwrap();
-
-        for resp in results.iter() {
-            match resp.asset_type {
-                AssetType::BodyDatabase => {
-                    e.receive_text(resp.url, "body_database", resp.data.as_str());
-                }
-                AssetType::Scene => {
-                    e.receive_text(resp.url, "scene", resp.data.as_str());
-                }
-                AssetType::StateVectors => {
-                    e.receive_text(resp.url, "vectors_database", resp.data.as_str());
-                }
-                AssetType::OscElements => {
-                    e.receive_text(resp.url, "elements_database", resp.data.as_str());
-                }
-            }
-        }
-
-        e.try_loading_last_scene();
+    STATE.with(|s| {
+        let mut ref_mut = s.borrow_mut();
+        let s = ref_mut.as_mut().unwrap();
+        s.loading.total = requests.len() as u32;
     });
 
     // Summoning ritual courtesy of https://rustwasm.github.io/docs/wasm-bindgen/examples/request-animation-frame.html
@@ -154,6 +124,74 @@ pub async fn start() -> Result<(), JsValue> {
     }) as Box<dyn FnMut()>));
     request_animation_frame(g.borrow().as_ref().unwrap());
 
+    // Feed each asset to the engine as soon as it arrives instead of waiting for all of them,
+    // so that the loading overlay can show progress and a failed fetch doesn't stop the others
+    let mut pending: FuturesUnordered<_> = requests
+        .iter()
+        .map(|ar| async move {
+            match request_text(ar.0).await {
+                Ok(res) => Ok(AssetResponse {
+                    url: ar.0,
+                    data: res,
+                    asset_type: ar.1,
+                }),
+                Err(err) => Err((ar.0, err)),
+            }
+        })
+        .collect();
+
+    while let Some(result) = pending.next().await {
+        match result {
+            Ok(resp) => {
+                let result = ENGINE.with(|e| {
+                    let mut ref_mut = e.borrow_mut();
+                    let e = ref_mut.as_mut().unwrap();
+
+                    let content_type = match resp.asset_type {
+                        AssetType::BodyDatabase => "body_database",
+                        AssetType::Scene => "scene",
+                        AssetType::StateVectors => "vectors_database",
+                        AssetType::OscElements => "elements_database",
+                        AssetType::StarCatalog => "star_catalog",
+                    };
+                    return e.receive_text(resp.url, content_type, resp.data.as_str());
+                });
+
+                STATE.with(|s| {
+                    let mut ref_mut = s.borrow_mut();
+                    let s = ref_mut.as_mut().unwrap();
+                    match result {
+                        Ok(_) => s.loading.loaded += 1,
+                        Err(err) => {
+                            s.loading.failed += 1;
+                            s.loading.errors.push(format!("{}: {}", resp.url, err));
+                        }
+                    }
+                });
+            }
+            Err((url, err)) => {
+                error!(LogCat::Io, "Failed to fetch asset '{}': {:?}", url, err);
+
+                STATE.with(|s| {
+                    let mut ref_mut = s.borrow_mut();
+                    let s = ref_mut.as_mut().unwrap();
+                    s.loading.failed += 1;
+                    s.loading.errors.push(format!("{}: {:?}", url, err));
+                });
+            }
+        }
+    }
+
+    ENGINE.with(|e| {
+        let mut ref_mut = e.borrow_mut();
+        let e = ref_mut.as_mut().unwrap();
+
+        // A shared link takes priority over whatever we were looking at last time
+        if !e.try_applying_location_hash() {
+            e.try_loading_last_scene();
+        }
+    });
+
     Ok(())
 }
 
@@ -164,13 +202,19 @@ fn redraw_requested() {
 
             let canvas = get_canvas();
 
+            // Our GL resources are gone until the context is restored, so keep time frozen meanwhile
+            if s.gl_context_lost {
+                s.last_frame_s = js_sys::Date::now() / 1000.0 - s.start_date;
+                return;
+            }
+
             let state_result = update_state(s, &canvas);
             if state_result == UpdateStateResult::NoDraw {
                 return;
             }
 
-            // Save state to local storage once in a while
-            if s.real_time_s - s.time_of_last_save > 3.0 {
+            // Save state to local storage once in a while, or when asked to
+            if s.is_save_due() {
                 serialize_state(s);
             }
 
@@ -178,12 +222,23 @@ fn redraw_requested() {
                 if let Ok(mut ref_mut_e) = e.try_borrow_mut() {
                     let e = ref_mut_e.as_mut().unwrap();
 
+                    if s.gl_context_restored {
+                        s.gl_context_restored = false;
+                        e.restore_gl_resources(s);
+                    }
+
                     // Update our main framebuffer
                     if let UpdateStateResult::ResizeDraw(width, height) = state_result {
                         e.resize(width, height);
                     };
 
                     e.update(s);
+
+                    // Update the URL fragment once in a while, so that the current view can be shared
+                    if s.real_time_s - s.time_of_last_hash_update > 1.0 {
+                        s.time_of_last_hash_update = s.real_time_s;
+                        e.update_location_hash(s);
+                    }
                 } else {
                     warning!(LogCat::Engine, "Failed to borrow engine for engine update!");
                 }
@@ -197,13 +252,15 @@ fn redraw_requested() {
     });
 }
 
+/// Saves the app state and the egui state to local storage, skipping whichever didn't change since the last time
 fn serialize_state(state: &mut AppState) {
     state.time_of_last_save = state.real_time_s;
-    state.save();
+    let requested = std::mem::take(&mut state.save_requested);
 
+    let mut saved = state.save();
     UICTX.with(|ui| {
-        if let Ok(memory_string) = serde_json::to_string(&*ui.memory()) {
-            local_storage_set("egui_memory_json", &memory_string)
+        if let Ok(memory_string) = serialize_egui_memory(&*ui.memory()) {
+            saved |= state.save_if_changed("egui_memory_json", &memory_string);
         } else {
             error!(
                 LogCat::Io,
@@ -211,6 +268,16 @@ fn serialize_state(state: &mut AppState) {
             );
         }
     });
+
+    if requested {
+        if !is_local_storage_enabled() {
+            state.add_error_toast(String::from("Local storage is disabled in the settings"));
+        } else if saved {
+            state.add_info_toast(String::from("Saved"));
+        } else {
+            state.add_info_toast(String::from("Nothing changed since the last save"));
+        }
+    }
 }
 
 #[derive(PartialEq)]
@@ -222,10 +289,23 @@ enum UpdateStateResult {
 
 fn update_state(state: &mut AppState, canvas: &HtmlCanvasElement) -> UpdateStateResult {
     if state.pending_reset {
+        // Keep tracking any startup fetches that may still be in flight
+        let loading = std::mem::take(&mut state.loading);
         *state = AppState::new();
+        state.loading = loading;
         local_storage_remove("app_state");
     }
 
+    // Framerate limiter. We haven't touched last_frame_s if we skip this frame, so the next one we draw
+    // will advance the simulation by the time we skipped too
+    let now_s = js_sys::Date::now() / 1000.0 - state.start_date;
+    if !state
+        .frame_limiter
+        .should_draw(state.frame_pacing, state.frames_per_second_limit, now_s)
+    {
+        return UpdateStateResult::NoDraw;
+    }
+
     let canvas_width_on_screen = canvas.client_width() as u32;
     let canvas_height_on_screen = canvas.client_height() as u32;
 
@@ -248,14 +328,7 @@ fn update_state(state: &mut AppState, canvas: &HtmlCanvasElement) -> UpdateState
     state.canvas_height = canvas_height_on_screen;
     state.canvas_width = canvas_width_on_screen;
 
-    let now_s = js_sys::Date::now() / 1000.0 - state.start_date;
     let real_delta_s = now_s - state.last_frame_s;
-
-    // Framerate limiter
-    if real_delta_s < 1.0 / state.frames_per_second_limit {
-        return UpdateStateResult::NoDraw;
-    }
-
     let sim_delta_s =
         real_delta_s * state.simulation_speed * (!state.simulation_paused as i32 as f64);
     state.last_frame_s = now_s;
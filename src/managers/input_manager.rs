@@ -1,20 +1,92 @@
-use crate::app_state::{AppState, ButtonState, ReferenceChange};
+use crate::app_state::{
+    AppState, ButtonState, CameraRollMode, FocusCycleOrder, MoveBoostModifier, ReferenceChange,
+    WheelMode,
+};
+use crate::components::{MetadataComponent, RigidBodyComponent};
+use crate::managers::scene::{Entity, Scene};
+use crate::utils::web::set_canvas_cursor;
 use na::*;
 
+/// Fraction of the distance to the focused object that each mouse wheel step dollies the camera by
+const DOLLY_STEP_FRACTION: f64 = 0.1;
+
+/// Roll speed in free roll mode, times state.rotate_speed
+const ROLL_SPEED_RAD_S: f64 = 0.5;
+
+/// Fraction of the full movement speed that we start at when the movement keys are first pressed
+const MIN_MOVE_RAMP_FRACTION: f64 = 0.1;
+
+/// How long the camera takes to turn and face the star, when state.look_at_star_smooth is set
+const LOOK_AT_STAR_TURN_S: f64 = 0.6;
+
+/// Turns the camera in place to face `star`. Keeps aiming at where the star is every frame, so that it still ends up
+/// facing it if the star or the camera moves during the turn
+struct StarTurn {
+    star: Entity,
+    start_forward: Unit<Vector3<f64>>,
+    elapsed_s: f64,
+}
+
 pub struct InputManager {
     last_mouse_x: i32,
     last_mouse_y: i32,
+    dragging_camera: bool, // Whether we're orbiting or looking around with the mouse
+    star_turn: Option<StarTurn>,
 }
 impl InputManager {
     pub fn new() -> Self {
         return Self {
             last_mouse_x: 0,
             last_mouse_y: 0,
+            dragging_camera: false,
+            star_turn: None,
         };
     }
 
-    pub fn run(&mut self, state: &mut AppState) {
+    pub fn run(&mut self, state: &mut AppState, scene: Option<&Scene>) {
         process_input(state, self.last_mouse_x, self.last_mouse_y);
+
+        if state.input.spectator == ButtonState::Pressed {
+            state.spectator = !state.spectator;
+            state.input.spectator = ButtonState::Handled;
+        }
+
+        if state.input.cycle_focus == ButtonState::Pressed {
+            if let Some(scene) = scene {
+                if !state.spectator {
+                    cycle_focus(state, scene, state.input.modifiers.shift);
+                }
+            }
+            state.input.cycle_focus = ButtonState::Handled;
+        }
+
+        if state.input.look_at_star == ButtonState::Pressed {
+            state.look_at_star_request = true;
+            state.input.look_at_star = ButtonState::Handled;
+        }
+        if state.look_at_star_request {
+            state.look_at_star_request = false;
+            self.star_turn = scene
+                .and_then(|scene| get_nearest_star(state, scene))
+                .map(|star| StarTurn {
+                    star,
+                    start_forward: Unit::new_normalize(state.camera.target - state.camera.pos),
+                    elapsed_s: if state.look_at_star_smooth {
+                        0.0
+                    } else {
+                        LOOK_AT_STAR_TURN_S
+                    },
+                });
+        }
+        self.update_star_turn(state, scene);
+
+        // While the pointer is locked the browser hides the cursor anyway, but we still need to set
+        // it so that it shows up right if the lock fails, and to restore it when the drag ends
+        let dragging_camera = is_dragging_camera(state);
+        if dragging_camera != self.dragging_camera {
+            self.dragging_camera = dragging_camera;
+            set_canvas_cursor(if dragging_camera { "grabbing" } else { "" });
+        }
         state.camera.update_transforms(
             state.canvas_width as f64 / state.canvas_height as f64,
             state.reference_translation,
@@ -23,6 +95,203 @@ impl InputManager {
         self.last_mouse_x = state.input.mouse_x;
         self.last_mouse_y = state.input.mouse_y;
     }
+
+    /// Advances the current star turn, if any. Looking around or orbiting with the mouse cancels it
+    fn update_star_turn(&mut self, state: &mut AppState, scene: Option<&Scene>) {
+        if is_dragging_camera(state) {
+            self.star_turn = None;
+        }
+
+        let star_pos = match (&self.star_turn, scene) {
+            (Some(turn), Some(scene)) => scene.get_physical_position(turn.star),
+            _ => None,
+        };
+        if star_pos.is_none() {
+            self.star_turn = None;
+            return;
+        }
+        let star_pos = star_pos.unwrap() - state.reference_translation.unwrap_or_default();
+
+        let turn = self.star_turn.as_mut().unwrap();
+        let to_star = star_pos - state.camera.pos.coords;
+        if to_star.magnitude_squared() == 0.0 {
+            self.star_turn = None;
+            return;
+        }
+
+        turn.elapsed_s += state.real_delta_time_s;
+        let t = (turn.elapsed_s / LOOK_AT_STAR_TURN_S).clamp(0.0, 1.0);
+        let forward = get_turned_forward(
+            &turn.start_forward,
+            &Unit::new_normalize(to_star),
+            &state.camera.up,
+            t * t * (3.0 - 2.0 * t),
+        );
+
+        let target_dist = (state.camera.target - state.camera.pos).magnitude();
+        state.camera.target = state.camera.pos + forward.into_inner() * target_dist;
+
+        if t >= 1.0 {
+            self.star_turn = None;
+        }
+    }
+}
+
+/// Nearest live, visible entity with a "Star" body type to the camera
+pub fn get_nearest_star(state: &AppState, scene: &Scene) -> Option<Entity> {
+    let camera_pos = state.camera.pos.coords + state.reference_translation.unwrap_or_default();
+
+    return scene
+        .get_entity_entries()
+        .iter()
+        .filter(|entry| entry.live && scene.is_entity_visible(entry.current))
+        .filter(|entry| {
+            scene
+                .get_component::<MetadataComponent>(entry.current)
+                .and_then(|meta| meta.get_metadata("body_type"))
+                .map(|body_type| body_type == "Star")
+                .unwrap_or(false)
+        })
+        .filter_map(|entry| {
+            scene
+                .get_physical_position(entry.current)
+                .map(|pos| ((pos - camera_pos).magnitude(), entry.current))
+        })
+        .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+        .map(|(_, entity)| entity);
+}
+
+/// Camera forward direction a fraction `t` of the way through turning from `from` to `to`. Turns around `up` if they
+/// point in opposite directions, and keeps clear of `up` itself so that the view matrix stays valid
+pub fn get_turned_forward(
+    from: &Unit<Vector3<f64>>,
+    to: &Unit<Vector3<f64>>,
+    up: &Unit<Vector3<f64>>,
+    t: f64,
+) -> Unit<Vector3<f64>> {
+    let turned = match from.try_slerp(to, t, 1E-9) {
+        Some(turned) => turned,
+        None => UnitQuaternion::from_axis_angle(up, std::f64::consts::PI * t) * *from,
+    };
+
+    if turned.cross(up).magnitude() < 1E-4 {
+        if let Some(back) = up.cross(&from.cross(up)).try_normalize(1E-9) {
+            return Unit::new_normalize(turned.into_inner() + back * 1E-3);
+        }
+    }
+
+    return turned;
+}
+
+/// Bodies that Tab and Shift-Tab cycle through, in order: The ones on the body list if it's open (so that its search
+/// filters apply), or otherwise every named body with a rigid body, alphabetically. Skips hidden and dead entities
+pub fn get_focus_cycle_candidates(state: &AppState, scene: &Scene) -> Vec<Entity> {
+    let mut result: Vec<Entity> = if state.body_list_order.is_empty() {
+        let mut named: Vec<(&str, Entity)> = scene
+            .get_entity_entries()
+            .iter()
+            .filter(|entry| {
+                entry.live
+                    && scene
+                        .get_component::<RigidBodyComponent>(entry.current)
+                        .is_some()
+            })
+            .filter_map(|entry| {
+                entry
+                    .name
+                    .as_ref()
+                    .map(|name| (name.as_str(), entry.current))
+            })
+            .collect();
+        named.sort_by(|a, b| a.0.cmp(b.0));
+        named.iter().map(|(_, entity)| *entity).collect()
+    } else {
+        state.body_list_order.clone()
+    };
+    result.retain(|entity| scene.is_live(*entity) && scene.is_entity_visible(*entity));
+
+    if state.focus_cycle_order == FocusCycleOrder::Distance {
+        let camera_pos = state.camera.pos.coords + state.reference_translation.unwrap_or_default();
+        let get_dist = |entity: &Entity| {
+            return scene
+                .get_physical_position(*entity)
+                .map(|pos| (pos - camera_pos).magnitude())
+                .unwrap_or(f64::INFINITY);
+        };
+        result.sort_by(|a, b| get_dist(a).partial_cmp(&get_dist(b)).unwrap());
+    }
+
+    return result;
+}
+
+/// The entry of `candidates` after `current`, or before it if `backwards`, wrapping around at the ends.
+/// Starts from the first (or last) one if `current` isn't one of them
+pub fn get_next_cycled_entity(
+    candidates: &[Entity],
+    current: Option<Entity>,
+    backwards: bool,
+) -> Option<Entity> {
+    if candidates.is_empty() {
+        return None;
+    }
+
+    let num = candidates.len();
+    let index = match current.and_then(|cur| candidates.iter().position(|e| *e == cur)) {
+        Some(index) if backwards => (index + num - 1) % num,
+        Some(index) => (index + 1) % num,
+        None if backwards => num - 1,
+        None => 0,
+    };
+
+    return Some(candidates[index]);
+}
+
+/// Selects the next (or previous) body for Tab/Shift-Tab, also focusing and going to it if the user wants that
+fn cycle_focus(state: &mut AppState, scene: &Scene, backwards: bool) {
+    let candidates = get_focus_cycle_candidates(state, scene);
+    let next = match get_next_cycled_entity(&candidates, state.selection, backwards) {
+        Some(next) => next,
+        None => return,
+    };
+
+    state.selection = Some(next);
+    if state.focus_cycle_go_to {
+        state.next_reference_entity = Some(ReferenceChange::FocusKeepLocation(next));
+        state.entity_going_to = Some(next);
+    }
+}
+
+/// Fraction of the full movement speed after holding the movement keys for `hold_time_s`, easing in from
+/// MIN_MOVE_RAMP_FRACTION so that quick taps make small adjustments. Always 1 if `ramp_time_s` is zero
+pub fn get_move_ramp_fraction(hold_time_s: f64, ramp_time_s: f64) -> f64 {
+    if ramp_time_s <= 0.0 {
+        return 1.0;
+    }
+
+    let t = (hold_time_s / ramp_time_s).clamp(0.0, 1.0);
+    let eased = t * t * (3.0 - 2.0 * t);
+    return MIN_MOVE_RAMP_FRACTION + (1.0 - MIN_MOVE_RAMP_FRACTION) * eased;
+}
+
+fn is_move_boost_held(state: &AppState) -> bool {
+    return match state.move_boost_modifier {
+        MoveBoostModifier::Shift => state.input.modifiers.shift,
+        MoveBoostModifier::Alt => state.input.modifiers.alt,
+    };
+}
+
+/// Whether the mouse buttons held down are orbiting or rotating the camera, same as in process_input
+fn is_dragging_camera(state: &AppState) -> bool {
+    if !state.input.dragging_scene {
+        return false;
+    }
+
+    let orbiting = state.input.m0 == ButtonState::Pressed
+        && state.input.modifiers.alt
+        && state.reference_translation.is_some();
+    let looking_around = state.input.m1 == ButtonState::Pressed;
+
+    return orbiting || looking_around;
 }
 
 fn process_input(state: &mut AppState, last_mouse_x: i32, last_mouse_y: i32) {
@@ -31,6 +300,21 @@ fn process_input(state: &mut AppState, last_mouse_x: i32, last_mouse_y: i32) {
         state.input.spacebar = ButtonState::Handled;
     }
 
+    // Step through time while paused. The engine actually advances the scene before running the systems
+    if state.simulation_paused {
+        let step_s = state.time_step_days * 86400.0;
+
+        if state.input.step_forward == ButtonState::Pressed {
+            state.pending_time_step_s += step_s;
+            state.input.step_forward = ButtonState::Handled;
+        }
+
+        if state.input.step_back == ButtonState::Pressed {
+            state.pending_time_step_s -= step_s;
+            state.input.step_back = ButtonState::Handled;
+        }
+    }
+
     state.input.delta_x = state.input.mouse_x - last_mouse_x;
     state.input.delta_y = state.input.mouse_y - last_mouse_y;
 
@@ -40,7 +324,10 @@ fn process_input(state: &mut AppState, last_mouse_x: i32, last_mouse_y: i32) {
     let cam_right = cam_forward.cross(&state.camera.up).normalize();
     let cam_up = cam_right.cross(&cam_forward).normalize();
 
-    let lock_pitch = true;
+    // In free roll mode `up` is the camera's own up instead of the ecliptic's, so along with the direction to the
+    // target it's the camera's full orientation. We turn it along with the camera, and keep it perpendicular to it
+    let lock_pitch = state.camera_roll_mode == CameraRollMode::Locked;
+    let mut new_up = cam_up;
 
     if let Some(selected) = state.selection {
         if state.input.f == ButtonState::Pressed {
@@ -57,7 +344,8 @@ fn process_input(state: &mut AppState, last_mouse_x: i32, last_mouse_y: i32) {
     }
 
     // Zoom in/out
-    if state.input.modifiers.alt && state.reference_translation.is_some() {
+    if (state.input.modifiers.alt || state.input.pinching) && state.reference_translation.is_some()
+    {
         if state.input.scroll_delta_y < 0 {
             state.camera.pos *= 0.9;
             state.camera.target *= 0.9;
@@ -66,6 +354,30 @@ fn process_input(state: &mut AppState, last_mouse_x: i32, last_mouse_y: i32) {
             state.camera.target *= 1.1;
         }
     }
+    // Dolly towards whatever is under the cursor. Egui already zeroes the scroll deltas while it wants the pointer
+    else if state.wheel_mode == WheelMode::Dolly {
+        if state.input.scroll_delta_y != 0 {
+            // Slow down as we get close to the focused object, so that we never go through it, and keep looking at
+            // it. Without one we just go as far as a second of WASD movement would take us
+            let step = match state.reference_translation {
+                Some(_) => state.camera.pos.coords.magnitude() * DOLLY_STEP_FRACTION,
+                None => state.move_speed,
+            };
+
+            state.camera.dolly_towards_canvas_point(
+                state.input.mouse_x,
+                state.input.mouse_y,
+                state.canvas_width,
+                state.canvas_height,
+                if state.input.scroll_delta_y < 0 {
+                    step
+                } else {
+                    -step
+                },
+                state.reference_translation.is_some(),
+            );
+        }
+    }
     // Change speed
     else {
         if state.input.scroll_delta_y < 0 {
@@ -97,12 +409,24 @@ fn process_input(state: &mut AppState, last_mouse_x: i32, last_mouse_y: i32) {
     if state.input.down == ButtonState::Pressed {
         incr -= cam_up;
     }
+    // The interface manager marks the movement keys as handled while egui has keyboard focus, so we don't move (or
+    // boost) when the modifier is held for typing
     if incr.magnitude_squared() > 0.0 {
-        incr = incr.normalize() * state.real_delta_time_s * state.move_speed;
+        let mut speed = state.move_speed
+            * get_move_ramp_fraction(state.move_hold_time_s, state.move_ramp_time_s);
+        if is_move_boost_held(state) {
+            speed *= state.move_boost_factor;
+        }
+
+        incr = incr.normalize() * state.real_delta_time_s * speed;
+        state.move_hold_time_s += state.real_delta_time_s;
+    } else {
+        state.move_hold_time_s = 0.0;
     }
 
     // Orbit
-    if state.input.m0 == ButtonState::Pressed
+    if state.input.dragging_scene
+        && state.input.m0 == ButtonState::Pressed
         && (state.input.delta_y.abs() > 0 || state.input.delta_x.abs() > 0)
         && state.input.modifiers.alt
         && state.reference_translation.is_some()
@@ -140,12 +464,14 @@ fn process_input(state: &mut AppState, last_mouse_x: i32, last_mouse_y: i32) {
 
         // Always orbit about (0, 0, 0), which is where the focused object is
         let new_cam_to_center = rot_z.transform_vector(&rot_x.transform_vector(&cam_to_center));
+        new_up = rot_z.transform_vector(&rot_x.transform_vector(&cam_up));
         let cam_dist_from_center = state.camera.pos.coords.magnitude();
         state.camera.pos = Point3::from(-new_cam_to_center * cam_dist_from_center);
         state.camera.target = Point3::new(0.0, 0.0, 0.0);
     }
     // Look around
-    else if state.input.m1 == ButtonState::Pressed
+    else if state.input.dragging_scene
+        && state.input.m1 == ButtonState::Pressed
         && (state.input.delta_y.abs() > 0 || state.input.delta_x.abs() > 0)
     {
         let half_canvas_height_world =
@@ -177,6 +503,7 @@ fn process_input(state: &mut AppState, last_mouse_x: i32, last_mouse_y: i32) {
         let rot_x = Rotation3::from_axis_angle(&Unit::new_unchecked(cam_right), y_angle);
 
         let new_cam_forward = rot_z.transform_vector(&rot_x.transform_vector(&cam_forward));
+        new_up = rot_z.transform_vector(&rot_x.transform_vector(&cam_up));
         let prev_targ_dist = (state.camera.target - state.camera.pos).magnitude();
         let new_targ = state.camera.pos + new_cam_forward * prev_targ_dist;
         state.camera.target = new_targ;
@@ -186,6 +513,314 @@ fn process_input(state: &mut AppState, last_mouse_x: i32, last_mouse_y: i32) {
     state.camera.target += incr;
 
     if !lock_pitch {
-        state.camera.up = Unit::new_unchecked(cam_up);
+        let forward = (state.camera.target - state.camera.pos).normalize();
+
+        let mut roll = 0.0;
+        if state.input.roll_left == ButtonState::Pressed {
+            roll -= 1.0;
+        }
+        if state.input.roll_right == ButtonState::Pressed {
+            roll += 1.0;
+        }
+        if roll != 0.0 {
+            let angle = roll * ROLL_SPEED_RAD_S * state.rotate_speed * state.real_delta_time_s;
+            new_up = Rotation3::from_axis_angle(&Unit::new_unchecked(forward), angle) * new_up;
+        }
+
+        let perpendicular_up = new_up - forward * new_up.dot(&forward);
+        if let Some(up) = perpendicular_up.try_normalize(1E-9) {
+            state.camera.up = Unit::new_unchecked(up);
+        }
+    }
+
+    if state.level_horizon_request {
+        state.level_horizon_request = false;
+        level_horizon(state);
+    }
+}
+
+/// Turns the camera about its view direction so that its up is the ecliptic's +Z again
+pub fn level_horizon(state: &mut AppState) {
+    let old_up = state.camera.up;
+    state.camera.up = Vector3::z_axis();
+
+    // Looking straight up or down we need to tilt a bit towards where our head was, or there's no horizon to find
+    let forward = state.camera.target - state.camera.pos;
+    let dist = forward.magnitude();
+    if dist > 0.0 && (forward / dist).cross(&Vector3::z()).magnitude() < 1E-4 {
+        let tilted = (forward / dist + old_up.into_inner() * 1E-3).normalize();
+        state.camera.target = state.camera.pos + tilted * dist;
+    }
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+    use crate::components::TransformComponent;
+
+    #[wasm_bindgen_test]
+    pub fn tab_cycles_through_visible_bodies() {
+        let mut state = AppState::new();
+        state.focus_cycle_go_to = false;
+        state.camera.pos = Point3::new(0.0, 0.0, 0.0);
+
+        let mut scene = Scene::new("cycling");
+        let venus = scene.add_test_body("Venus", Vector3::new(30.0, 0.0, 0.0), 1.0);
+        let earth = scene.add_test_body("Earth", Vector3::new(10.0, 0.0, 0.0), 1.0);
+        let mars = scene.add_test_body("Mars", Vector3::new(20.0, 0.0, 0.0), 1.0);
+        let hidden = scene.add_test_body("Hidden", Vector3::new(5.0, 0.0, 0.0), 1.0);
+        scene.set_entity_visible(hidden, false, false);
+
+        // Not a body
+        let builder = scene.start_new_entity("Marker");
+        scene.finish_new_entity(builder);
+
+        // Alphabetically, wrapping around at both ends
+        assert_eq!(
+            get_focus_cycle_candidates(&state, &scene),
+            vec![earth, mars, venus]
+        );
+        cycle_focus(&mut state, &scene, false);
+        assert_eq!(state.selection, Some(earth));
+        cycle_focus(&mut state, &scene, true);
+        assert_eq!(state.selection, Some(venus));
+        cycle_focus(&mut state, &scene, false);
+        assert_eq!(state.selection, Some(earth));
+        assert!(state.entity_going_to.is_none());
+
+        // Nearest first
+        state.focus_cycle_order = FocusCycleOrder::Distance;
+        assert_eq!(
+            get_focus_cycle_candidates(&state, &scene),
+            vec![earth, mars, venus]
+        );
+        state.camera.pos = Point3::new(40.0, 0.0, 0.0);
+        assert_eq!(
+            get_focus_cycle_candidates(&state, &scene),
+            vec![venus, mars, earth]
+        );
+
+        // Only the ones left on the open body list
+        state.focus_cycle_order = FocusCycleOrder::BodyList;
+        state.body_list_order = vec![venus, hidden, mars];
+        assert_eq!(
+            get_focus_cycle_candidates(&state, &scene),
+            vec![venus, mars]
+        );
+
+        // Starts over from the first one if the selection isn't in the list, and goes to it if we want that
+        state.focus_cycle_go_to = true;
+        cycle_focus(&mut state, &scene, false);
+        assert_eq!(state.selection, Some(venus));
+        assert_eq!(state.entity_going_to, Some(venus));
+
+        assert_eq!(get_next_cycled_entity(&[], Some(venus), false), None);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn movement_ramps_up_and_boosts() {
+        let mut state = AppState::new();
+        state.canvas_width = 800;
+        state.canvas_height = 600;
+        state.camera.pos = Point3::new(0.0, 0.0, 0.0);
+        state.camera.target = Point3::new(1.0, 0.0, 0.0);
+        state.camera.up = Vector3::z_axis();
+        state.move_speed = 1.0;
+        state.move_boost_factor = 10.0;
+        state.move_ramp_time_s = 1.0;
+        state.real_delta_time_s = 0.1;
+
+        let step = |state: &mut AppState| -> f64 {
+            let start = state.camera.pos;
+            process_input(state, 0, 0);
+            return (state.camera.pos - start).magnitude();
+        };
+
+        // Starts slow and speeds up while held
+        state.input.forward = ButtonState::Pressed;
+        let first = step(&mut state);
+        assert!((first - 0.1 * MIN_MOVE_RAMP_FRACTION).abs() < 1E-9);
+        for _ in 0..20 {
+            step(&mut state);
+        }
+        assert!((step(&mut state) - 0.1).abs() < 1E-9);
+
+        // Boosted while the modifier is held
+        state.input.modifiers.shift = true;
+        assert!((step(&mut state) - 1.0).abs() < 1E-9);
+        state.move_boost_modifier = MoveBoostModifier::Alt;
+        assert!((step(&mut state) - 0.1).abs() < 1E-9);
+        state.move_boost_modifier = MoveBoostModifier::Shift;
+
+        // Letting go starts the ramp over
+        state.input.forward = ButtonState::Depressed;
+        assert_eq!(step(&mut state), 0.0);
+        assert_eq!(state.move_hold_time_s, 0.0);
+        state.input.forward = ButtonState::Pressed;
+        assert!((step(&mut state) - 0.1 * MIN_MOVE_RAMP_FRACTION * 10.0).abs() < 1E-9);
+
+        // Nothing moves while egui has the keyboard, even with the modifier held
+        state.input.forward = ButtonState::Handled;
+        assert_eq!(step(&mut state), 0.0);
+        assert_eq!(state.move_hold_time_s, 0.0);
+
+        // No ramp at all
+        state.move_ramp_time_s = 0.0;
+        state.input.modifiers.shift = false;
+        state.input.forward = ButtonState::Pressed;
+        assert!((step(&mut state) - 0.1).abs() < 1E-9);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn look_at_nearest_star() {
+        let mut state = AppState::new();
+        state.canvas_width = 800;
+        state.canvas_height = 600;
+        state.camera.pos = Point3::new(0.0, 0.0, 0.0);
+        state.camera.target = Point3::new(0.0, 2.0, 0.0);
+        state.camera.up = Vector3::z_axis();
+        state.real_delta_time_s = 0.1;
+
+        let mut scene = Scene::new("stars");
+        let mut add_body = |name: &str, body_type: &str, pos: Vector3<f64>| -> Entity {
+            let builder = scene.start_new_entity(name);
+            let entity = scene.finish_new_entity(builder);
+            scene
+                .add_component::<TransformComponent>(entity)
+                .get_world_transform_mut()
+                .trans = pos;
+            scene
+                .add_component::<MetadataComponent>(entity)
+                .set_metadata("body_type", body_type);
+            return entity;
+        };
+        let near = add_body("Near", "Star", Vector3::new(0.0, -50.0, 0.0));
+        let far = add_body("Far", "Star", Vector3::new(1000.0, 0.0, 0.0));
+        add_body("Planet", "Planet", Vector3::new(0.0, 10.0, 0.0));
+        assert_eq!(get_nearest_star(&state, &scene), Some(near));
+
+        // Turns around smoothly to face the star right behind us, without moving
+        let mut input_man = InputManager::new();
+        state.look_at_star_request = true;
+        input_man.run(&mut state, Some(&scene));
+        let forward = (state.camera.target - state.camera.pos).normalize();
+        assert!(forward.y > 0.9 && forward.y < 1.0, "{:?}", forward);
+        for _ in 0..10 {
+            input_man.run(&mut state, Some(&scene));
+            let forward = (state.camera.target - state.camera.pos).normalize();
+            assert!(forward.iter().all(|c| c.is_finite()));
+        }
+        assert_eq!(state.camera.pos, Point3::new(0.0, 0.0, 0.0));
+        assert!((state.camera.target - Point3::new(0.0, -2.0, 0.0)).magnitude() < 1E-9);
+        assert!(input_man.star_turn.is_none());
+
+        // Snaps to it when not smooth, and picks whichever is nearest
+        state.camera.pos = Point3::new(900.0, 0.0, 0.0);
+        state.camera.target = Point3::new(900.0, 1.0, 0.0);
+        state.look_at_star_smooth = false;
+        state.input.look_at_star = ButtonState::Pressed;
+        input_man.run(&mut state, Some(&scene));
+        assert_eq!(state.input.look_at_star, ButtonState::Handled);
+        assert!((state.camera.target - Point3::new(901.0, 0.0, 0.0)).magnitude() < 1E-9);
+        assert_eq!(get_nearest_star(&state, &scene), Some(far));
+
+        // Never faces straight up, even if that's where the star is
+        let forward = get_turned_forward(
+            &Vector3::y_axis(),
+            &Vector3::z_axis(),
+            &Vector3::z_axis(),
+            1.0,
+        );
+        assert!(forward.cross(&Vector3::z()).magnitude() > 0.0);
+        assert!(forward.z > 0.999);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn free_roll_and_level_horizon() {
+        let mut state = AppState::new();
+        state.canvas_width = 800;
+        state.canvas_height = 600;
+        state.camera.pos = Point3::new(0.0, 0.0, 0.0);
+        state.camera.target = Point3::new(1.0, 0.0, 0.0);
+        state.camera.up = Vector3::z_axis();
+        state.rotate_speed = 1.0;
+        state.real_delta_time_s = 1.0;
+        state.input.roll_right = ButtonState::Pressed;
+
+        // Locked to the ecliptic, rolling does nothing
+        process_input(&mut state, 0, 0);
+        assert!((state.camera.up.into_inner() - Vector3::z()).magnitude() < 1E-9);
+
+        // Free roll turns up about the view direction, towards the right
+        state.camera_roll_mode = CameraRollMode::Free;
+        process_input(&mut state, 0, 0);
+        let up = state.camera.up.into_inner();
+        let forward = (state.camera.target - state.camera.pos).normalize();
+        assert!(up.dot(&forward).abs() < 1E-9);
+        assert!(
+            up.y < 0.0 && (up.y + ROLL_SPEED_RAD_S.sin()).abs() < 1E-9,
+            "{:?}",
+            up
+        );
+
+        // Picking still goes through the camera's own up
+        state
+            .camera
+            .update_transforms(state.canvas_width as f64 / state.canvas_height as f64, None);
+        let top = state.camera.canvas_to_world(400, 0, 800, 600);
+        let top_dir = (top - state.camera.pos).normalize();
+        assert!(top_dir.dot(&up) > 0.1, "{:?}", top_dir);
+
+        // Back to +Z, without having moved
+        state.input.roll_right = ButtonState::Depressed;
+        state.level_horizon_request = true;
+        process_input(&mut state, 0, 0);
+        assert!(!state.level_horizon_request);
+        assert!((state.camera.up.into_inner() - Vector3::z()).magnitude() < 1E-9);
+        assert!((state.camera.target - Point3::new(1.0, 0.0, 0.0)).magnitude() < 1E-9);
+
+        // Looking straight down there's no horizon, so tilt up a bit towards where our head was
+        state.camera.target = Point3::new(0.0, 0.0, -1.0);
+        state.camera.up = Vector3::x_axis();
+        level_horizon(&mut state);
+        let forward = state.camera.target - state.camera.pos;
+        assert!(forward.cross(&Vector3::z()).magnitude() > 1E-4);
+        assert!(forward.x > 0.0);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn spectator_mode_keeps_the_selection() {
+        let mut state = AppState::new();
+        state.canvas_width = 800;
+        state.canvas_height = 600;
+        state.focus_cycle_go_to = false;
+
+        let mut scene = Scene::new("spectator");
+        let earth = scene.add_test_body("Earth", Vector3::zeros(), 1.0);
+
+        let mut input_man = InputManager::new();
+        state.input.spectator = ButtonState::Pressed;
+        input_man.run(&mut state, Some(&scene));
+        assert!(state.spectator);
+
+        // Tab doesn't select anything while spectating
+        state.input.cycle_focus = ButtonState::Pressed;
+        input_man.run(&mut state, Some(&scene));
+        assert_eq!(state.selection, None);
+        assert_eq!(state.input.cycle_focus, ButtonState::Handled);
+
+        // Holding the key down doesn't toggle it back
+        input_man.run(&mut state, Some(&scene));
+        assert!(state.spectator);
+
+        state.input.spectator = ButtonState::Pressed;
+        input_man.run(&mut state, Some(&scene));
+        assert!(!state.spectator);
+        state.input.cycle_focus = ButtonState::Pressed;
+        input_man.run(&mut state, Some(&scene));
+        assert_eq!(state.selection, Some(earth));
     }
 }
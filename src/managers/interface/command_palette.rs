@@ -0,0 +1,185 @@
+use crate::app_state::{AppState, ReferenceChange};
+use crate::components::RigidBodyComponent;
+use crate::managers::scene::{Scene, SceneManager};
+use crate::managers::{OrbitManager, ResourceManager};
+use crate::utils::string::fuzzy_match_score;
+
+/// Most commands the command palette lists at once, best matches first
+pub const MAX_PALETTE_RESULTS: usize = 12;
+
+/// Everything a palette command is allowed to change when it runs
+pub struct CommandContext<'a> {
+    pub state: &'a mut AppState,
+    pub scene_man: &'a mut SceneManager,
+    pub res_man: &'a mut ResourceManager,
+    pub orbit_man: &'a OrbitManager,
+    pub scene_desc_name: &'a str, // Scene description that "Reset scene" reloads
+}
+
+pub struct PaletteCommand {
+    pub name: String,
+    pub run: Box<dyn Fn(&mut CommandContext)>,
+}
+
+fn command(name: &str, run: impl Fn(&mut CommandContext) + 'static) -> PaletteCommand {
+    return PaletteCommand {
+        name: name.to_owned(),
+        run: Box::new(run),
+    };
+}
+
+/// Every command that the command palette offers, including one to focus each named body of `scene`.
+/// `include_debug` also adds the commands for the debug-only windows
+pub fn get_palette_commands(scene: Option<&Scene>, include_debug: bool) -> Vec<PaletteCommand> {
+    let mut commands = vec![
+        command("Open scene browser", |ctx| {
+            ctx.state.open_windows.scene_browser = true
+        }),
+        command("Open body list", |ctx| {
+            ctx.state.open_windows.body_list = true
+        }),
+        command("Open settings", |ctx| {
+            ctx.state.open_windows.settings = true
+        }),
+        command("Open controls", |ctx| {
+            ctx.state.open_windows.controls = true
+        }),
+        command("Open about", |ctx| ctx.state.open_windows.about = true),
+        command("Open distance plot", |ctx| {
+            ctx.state.open_windows.distance_plot = true
+        }),
+        command("Open transit finder", |ctx| {
+            ctx.state.open_windows.transit_finder = true
+        }),
+        command("Reset scene", |ctx| {
+            ctx.scene_man
+                .set_scene("empty", ctx.res_man, ctx.orbit_man, ctx.state);
+            ctx.scene_man
+                .set_scene(ctx.scene_desc_name, ctx.res_man, ctx.orbit_man, ctx.state);
+        }),
+        command("Reset view", |ctx| {
+            ctx.scene_man.reset_view_to_scene_default(ctx.state)
+        }),
+        command("Close scene", |ctx| {
+            ctx.scene_man
+                .set_scene("empty", ctx.res_man, ctx.orbit_man, ctx.state);
+        }),
+        command("Play/pause simulation", |ctx| {
+            ctx.state.simulation_paused = !ctx.state.simulation_paused
+        }),
+        command("Toggle ecliptic grid", |ctx| {
+            ctx.state.show_grid = !ctx.state.show_grid
+        }),
+        command("Toggle coordinate axes", |ctx| {
+            ctx.state.show_axes = !ctx.state.show_axes
+        }),
+        command("Toggle points", |ctx| {
+            ctx.state.show_points = !ctx.state.show_points
+        }),
+        command("Toggle orbits", |ctx| {
+            ctx.state.show_orbits = !ctx.state.show_orbits
+        }),
+        command("Look at nearest star", |ctx| {
+            ctx.state.look_at_star_request = true
+        }),
+        command("Toggle spectator mode", |ctx| {
+            ctx.state.spectator = !ctx.state.spectator
+        }),
+        command("Level horizon", |ctx| {
+            ctx.state.level_horizon_request = true
+        }),
+        command("Stop focusing", |ctx| {
+            ctx.state.next_reference_entity = Some(ReferenceChange::Clear)
+        }),
+    ];
+
+    if include_debug {
+        commands.push(command("Open debug", |ctx| {
+            ctx.state.open_windows.debug = true
+        }));
+        commands.push(command("Open shader editor", |ctx| {
+            ctx.state.open_windows.shader_editor = true
+        }));
+    }
+
+    if let Some(scene) = scene {
+        for entity in scene.get_entity_entries() {
+            if !entity.live
+                || scene
+                    .get_component::<RigidBodyComponent>(entity.current)
+                    .is_none()
+            {
+                continue;
+            }
+
+            if let Some(name) = &entity.name {
+                let body = entity.current;
+                commands.push(command(&format!("Focus {}", name), move |ctx| {
+                    ctx.state.selection = Some(body);
+                    ctx.state.next_reference_entity =
+                        Some(ReferenceChange::FocusKeepLocation(body));
+                    ctx.state.entity_going_to = Some(body);
+                }));
+            }
+        }
+    }
+
+    return commands;
+}
+
+/// Indices into `commands` of the ones that fuzzy match `query`, best matches first and then alphabetically.
+/// Returns at most MAX_PALETTE_RESULTS of them
+pub fn rank_palette_commands(commands: &[PaletteCommand], query: &str) -> Vec<usize> {
+    let mut results: Vec<(i32, usize)> = commands
+        .iter()
+        .enumerate()
+        .filter_map(|(index, command)| {
+            fuzzy_match_score(query, &command.name).map(|score| (score, index))
+        })
+        .collect();
+    results.sort_by(|a, b| {
+        b.0.cmp(&a.0)
+            .then(commands[a.1].name.cmp(&commands[b.1].name))
+    });
+
+    return results
+        .iter()
+        .take(MAX_PALETTE_RESULTS)
+        .map(|(_, index)| *index)
+        .collect();
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    pub fn palette_ranks_best_matches_first() {
+        let commands = get_palette_commands(None, false);
+        let names = |query: &str| -> Vec<String> {
+            return rank_palette_commands(&commands, query)
+                .iter()
+                .map(|index| commands[*index].name.clone())
+                .collect();
+        };
+
+        assert_eq!(names("reset")[..2], ["Reset scene", "Reset view"]);
+        assert_eq!(names("tog orb")[0], "Toggle orbits");
+        assert_eq!(names("opsett")[0], "Open settings");
+        assert!(names("xyzzy").is_empty());
+
+        // Everything matches an empty query, alphabetically
+        let all = names("");
+        assert_eq!(all.len(), MAX_PALETTE_RESULTS.min(commands.len()));
+        assert_eq!(all[0], "Close scene");
+
+        // Debug windows only when asked for
+        assert!(!commands.iter().any(|c| c.name == "Open debug"));
+        assert!(get_palette_commands(None, true)
+            .iter()
+            .any(|c| c.name == "Open debug"));
+    }
+}
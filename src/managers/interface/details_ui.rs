@@ -1,5 +1,171 @@
-use egui::Ui;
+use crate::app_state::{NumberNotation, VelocityUnit};
+use crate::components::{
+    Component, LightComponent, MeshComponent, RigidBodyComponent, TransformComponent,
+};
+use crate::managers::scene::{Entity, Scene};
+use egui::{Context, Id, Ui};
 
 pub trait DetailsUI {
     fn draw_details_ui(&mut self, _ui: &mut Ui) {}
 }
+
+fn get_number_notation_id() -> Id {
+    return Id::new("number_notation");
+}
+
+fn get_velocity_unit_id() -> Id {
+    return Id::new("velocity_unit");
+}
+
+/// Lets DetailsUI implementations know which NumberNotation the user picked, as they don't have access to the state
+pub fn set_number_notation(ctx: &Context, notation: NumberNotation) {
+    ctx.data().insert_temp(get_number_notation_id(), notation);
+}
+
+/// The NumberNotation last set with set_number_notation, to use with format_number
+pub fn get_number_notation(ui: &Ui) -> NumberNotation {
+    return ui
+        .ctx()
+        .data()
+        .get_temp(get_number_notation_id())
+        .unwrap_or_default();
+}
+
+/// Lets DetailsUI implementations know which VelocityUnit to show velocities in, like set_number_notation
+pub fn set_velocity_unit(ctx: &Context, unit: VelocityUnit) {
+    ctx.data().insert_temp(get_velocity_unit_id(), unit);
+}
+
+/// The VelocityUnit last set with set_velocity_unit, to use with format_velocity
+pub fn get_velocity_unit(ui: &Ui) -> VelocityUnit {
+    return ui
+        .ctx()
+        .data()
+        .get_temp(get_velocity_unit_id())
+        .unwrap_or_default();
+}
+
+/// Fetches the component of one particular type of an entity, if it has one, as something we can draw the details of
+pub type DetailsUIAccessor = Box<dyn Fn(&mut Scene, Entity) -> Option<&mut dyn DetailsUI>>;
+
+fn get_component_details_ui<T>(scene: &mut Scene, entity: Entity) -> Option<&mut dyn DetailsUI>
+where
+    T: Component<ComponentType = T> + 'static,
+{
+    return scene
+        .get_component_mut::<T>(entity)
+        .map(|comp| comp as &mut dyn DetailsUI);
+}
+
+struct DetailsUIEntry {
+    component_type: u64, // From Component::get_component_type
+    name: String,        // Header of the collapsing section the details are drawn in
+    accessor: DetailsUIAccessor,
+}
+
+/// Component types whose details get drawn for the selected entity, in the order they were registered
+pub struct DetailsUIRegistry {
+    entries: Vec<DetailsUIEntry>,
+}
+impl DetailsUIRegistry {
+    pub fn new() -> Self {
+        return Self {
+            entries: Vec::new(),
+        };
+    }
+
+    /// Registry with the component types the debug window shows by default
+    pub fn with_default_components() -> Self {
+        let mut result = Self::new();
+        result.register::<TransformComponent>("Transform component");
+        result.register::<MeshComponent>("Mesh component");
+        result.register::<RigidBodyComponent>("RigidBody component");
+        result.register::<LightComponent>("Light component");
+        return result;
+    }
+
+    /// Draws the details of components of type T under a section called `name`. Registering the same type again
+    /// just renames its section
+    pub fn register<T>(&mut self, name: &str)
+    where
+        T: Component<ComponentType = T> + 'static,
+    {
+        let component_type = T::get_component_type();
+        if let Some(entry) = self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.component_type == component_type)
+        {
+            entry.name = name.to_owned();
+            return;
+        }
+
+        self.entries.push(DetailsUIEntry {
+            component_type,
+            name: name.to_owned(),
+            accessor: Box::new(get_component_details_ui::<T>),
+        });
+    }
+
+    /// Draws a collapsing section with the details of each registered component that `entity` has. Returns the names
+    /// of the sections we drew
+    pub fn draw_details(&self, scene: &mut Scene, entity: Entity, ui: &mut Ui) -> Vec<String> {
+        let mut drawn = Vec::new();
+
+        for entry in &self.entries {
+            if let Some(comp) = (entry.accessor)(scene, entity) {
+                ui.collapsing(&entry.name, |ui| comp.draw_details_ui(ui));
+                drawn.push(entry.name.clone());
+            }
+        }
+
+        return drawn;
+    }
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+    use crate::components::KinematicComponent;
+
+    #[wasm_bindgen_test]
+    pub fn registered_components_show_up_in_details() {
+        let mut scene = Scene::new("details");
+
+        let builder = scene.start_new_entity("Body");
+        let entity = scene.finish_new_entity(builder);
+        scene.add_component::<TransformComponent>(entity);
+        scene.add_component::<KinematicComponent>(entity);
+
+        let mut registry = DetailsUIRegistry::with_default_components();
+        let ctx = Context::default();
+        let draw = |registry: &DetailsUIRegistry, scene: &mut Scene| -> Vec<String> {
+            let mut drawn = Vec::new();
+            let _ = ctx.run(egui::RawInput::default(), |ctx| {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    drawn = registry.draw_details(scene, entity, ui);
+                });
+            });
+            return drawn;
+        };
+
+        // Only the components the entity has
+        assert_eq!(draw(&registry, &mut scene), ["Transform component"]);
+
+        // Nothing else needs to know about a newly registered type for it to show up
+        registry.register::<KinematicComponent>("Kinematic component");
+        assert_eq!(
+            draw(&registry, &mut scene),
+            ["Transform component", "Kinematic component"]
+        );
+
+        registry.register::<KinematicComponent>("Kinematics");
+        assert_eq!(
+            draw(&registry, &mut scene),
+            ["Transform component", "Kinematics"]
+        );
+    }
+}
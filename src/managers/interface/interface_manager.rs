@@ -1,34 +1,114 @@
-use crate::app_state::{AppState, ButtonState, ReferenceChange};
-use crate::components::{MeshComponent, MetadataComponent, RigidBodyComponent, TransformComponent};
-use crate::managers::details_ui::DetailsUI;
+use crate::app_state::{
+    deserialize_egui_memory, AppState, ButtonState, CameraDragMode, CameraRollMode,
+    CoordinateFrame, DistanceUnit, FocusCycleOrder, FramePacing, MoveBoostModifier, NumberNotation,
+    PickRay, PointColorMode, ReferenceChange, TimeFormat, ToastSeverity, Tonemap, VelocityUnit,
+    WheelMode,
+};
+use crate::components::{MetadataComponent, RigidBodyComponent, TransformComponent};
+use crate::managers::command_palette::{
+    get_palette_commands, rank_palette_commands, CommandContext,
+};
+use crate::managers::details_ui::{
+    set_number_notation, set_velocity_unit, DetailsUI, DetailsUIRegistry,
+};
+use crate::managers::orbit::OrbitalElements;
+use crate::managers::resource::gltf::build_line_strip_glb;
+use crate::managers::resource::shaders::{has_shader_override, SHADER_SOURCES};
 use crate::managers::scene::component_storage::ComponentStorage;
-use crate::managers::scene::{Entity, Scene, SceneManager};
+use crate::managers::scene::orbits::{
+    find_orbit_central_body, get_body_orbit_readouts, get_body_orbital_elements,
+    predict_body_position, reset_body_orbital_elements, set_body_orbital_elements,
+    MAX_EDITED_ECCENTRICITY,
+};
+use crate::managers::scene::{
+    get_body_radius, set_body_color_override, Entity, Scene, SceneManager,
+};
 use crate::managers::{OrbitManager, ResourceManager};
+use crate::utils::camera::{
+    get_fov_along_axis, get_vertical_fov, FovAxis, AXIS_GIZMO_MARGIN, AXIS_GIZMO_SIZE,
+};
+use crate::utils::grid::{get_scale_bar_length, GRID_ALPHA, GRID_CELLS};
+use crate::utils::labels::{declutter_labels, LabelRect, LABEL_PADDING};
 use crate::utils::log::*;
-use crate::utils::raycasting::{raycast, Ray};
-use crate::utils::units::{julian_date_number_to_date, Jdn, J2000_JDN};
+use crate::utils::memory::MemoryReport;
+use crate::utils::orbits::{
+    adaptive_eccentric_anomalies, bake_times_for_eccentric_anomalies, eccentric_anomaly_to_xyz,
+    elements_to_ellipse_rotation_transform, get_baked_orbit_points, get_orbit_event_countdowns,
+};
+use crate::utils::point_colors::{
+    get_body_type_color, get_gradient_color, BODY_TYPES, SEMI_MAJOR_AXIS_RANGE_MM, UNKNOWN_COLOR,
+};
+use crate::utils::raycasting::{pick_point_marker, raycast, Ray};
+use crate::utils::string::{decode_hex, fuzzy_match_score};
+use crate::utils::transits::{find_transits, get_transit_scan_step, TransitEvent, TransitKind};
+use crate::utils::units::{
+    format_distance_in, format_duration, format_sim_time, get_distance_unit_name,
+    get_distance_unit_size, get_time_step_to_jdn, julian_date_number_to_date, sim_time_to_jdn,
+    unix_time_ms_to_julian_date_number, Au, Deg, Jdn, Mm, Rad, J2000_JDN,
+};
 use crate::utils::web::{
-    get_document, is_local_storage_enabled, local_storage_clear, local_storage_enable,
-    local_storage_get,
+    download_bytes, get_document, is_local_storage_enabled, local_storage_clear,
+    local_storage_enable, local_storage_get, pick_local_text_file, simulate_gl_context_loss,
 };
-use crate::{GLCTX, UICTX};
+use crate::{ENGINE, GLCTX, STATE, UICTX};
 use egui::Widget;
 use lazy_static::__Deref;
 use na::*;
 use std::collections::VecDeque;
 
 const DEBUG: bool = true;
+const MAX_BODY_LIST_ROWS: usize = 200; // Drawing thousands of buttons every frame gets slow
+const DISTANCE_PLOT_SAMPLES: u32 = 360;
+const FRAME_RATE_AVERAGE_FRAMES: usize = 15; // How many of the most recent frames the displayed frame rate averages
+const DEFAULT_FRAME_TIME_HISTORY: usize = 240;
+const DEFAULT_TRANSIT_SCAN_DAYS: f64 = 365.25;
+
+/// Distance samples for the distance plot window, only recomputed when the selection or reference changes
+struct DistancePlotCache {
+    selection: Entity,
+    reference: Option<Entity>,
+    reference_name: String,
+    period_days: f64,
+    start: Jdn,
+    samples: Vec<[f64; 2]>, // Days since start, distance in Mm
+}
+
+/// Inputs and results of the transit finder window
+struct TransitFinder {
+    first: Option<Entity>,
+    second: Option<Entity>,
+    observer: Option<Entity>, // Looks from where the camera currently is if None
+    scan_days: f64,           // How far ahead of the current simulation time we search
+    results: Option<Vec<TransitEvent>>, // None until we search
+}
 
 pub struct InterfaceManager {
     painter: egui_glow::Painter,
+    reupload_font_atlas: bool, // Whether the painter was recreated and needs egui to send it the font atlas again
 
     selected_scene_desc_name: String,
     body_list_filter: String,
-
-    frame_times: VecDeque<f64>,
+    body_list_type_filter: Vec<(&'static str, bool)>, // As written into the "body_type" metadata
+    body_list_hide_children: bool, // Whether the visibility checkboxes also apply to the children of each body
+    body_list_visibility_changes: Vec<(Entity, bool)>, // Applied once we're done drawing, as we only have &Scene then
+    distance_plot_cache: Option<DistancePlotCache>,
+    transit_finder: TransitFinder,
+
+    shader_editor_name: String, // Shader open in the shader editor, e.g. "phong.frag"
+    shader_editor_source: String, // What's in the shader editor's text box, with includes unresolved
+    shader_editor_status: Option<Result<String, String>>, // Outcome of the last time we applied or reverted
+
+    frame_times: VecDeque<f64>, // Most recent first, in seconds
+    frame_time_history: usize,  // How many frame times we keep for the graph in the debug window
     time_of_last_update: f64,
     last_frame_rate: f64,
 
+    command_palette_open: bool,
+    command_palette_query: String,
+    command_palette_selected: usize, // Index into the ranked matches, that Enter runs
+
+    details_registry: DetailsUIRegistry, // Components whose details the debug window shows for the selection
+
     local_storage_ok: bool,
 }
 impl InterfaceManager {
@@ -37,11 +117,31 @@ impl InterfaceManager {
             return UICTX.with(|uictx| {
                 let new_man = Self {
                     painter: egui_glow::Painter::new(glctx.clone(), None, "").unwrap(),
+                    reupload_font_atlas: false,
                     selected_scene_desc_name: String::from(""),
                     body_list_filter: String::from(""),
-                    frame_times: vec![16.66; 15].into_iter().collect(),
+                    body_list_type_filter: BODY_TYPES.iter().map(|t| (*t, true)).collect(),
+                    body_list_hide_children: true,
+                    body_list_visibility_changes: Vec::new(),
+                    distance_plot_cache: None,
+                    transit_finder: TransitFinder {
+                        first: None,
+                        second: None,
+                        observer: None,
+                        scan_days: DEFAULT_TRANSIT_SCAN_DAYS,
+                        results: None,
+                    },
+                    shader_editor_name: String::new(),
+                    shader_editor_source: String::new(),
+                    shader_editor_status: None,
+                    frame_times: VecDeque::new(),
+                    frame_time_history: DEFAULT_FRAME_TIME_HISTORY,
                     time_of_last_update: -2.0,
                     last_frame_rate: 60.0,
+                    command_palette_open: false,
+                    command_palette_query: String::new(),
+                    command_palette_selected: 0,
+                    details_registry: DetailsUIRegistry::with_default_components(),
                     local_storage_ok: is_local_storage_enabled(),
                 };
 
@@ -72,13 +172,14 @@ impl InterfaceManager {
                 info!(LogCat::Io, "Loading egui state...");
                 if new_man.local_storage_ok {
                     if let Some(memory_string) = local_storage_get("egui_memory_json") {
-                        if let Ok(memory) = serde_json::from_str(&memory_string) {
-                            *uictx.memory() = memory;
-                        } else {
-                            error!(
+                        match deserialize_egui_memory(&memory_string) {
+                            Ok(memory) => *uictx.memory() = memory,
+                            Err(err) => error!(
                                 LogCat::Io,
-                                "Failed to load egui state from memory string {}", memory_string
-                            );
+                                "Failed to load egui state from memory string {}: {}",
+                                memory_string,
+                                err
+                            ),
                         }
                     }
                 }
@@ -88,6 +189,17 @@ impl InterfaceManager {
         });
     }
 
+    /// Recreates our painter once the WebGL context is restored after being lost
+    pub fn restore_gl_resources(&mut self) {
+        // Its GL objects are gone already, but this stops it from complaining about leaks when dropped
+        self.painter.destroy();
+        self.painter = GLCTX.with(|glctx| {
+            return egui_glow::Painter::new(glctx.clone(), None, "").unwrap();
+        });
+
+        self.reupload_font_atlas = true;
+    }
+
     /// This runs before all systems, and starts collecting all the UI elements we'll draw, as
     /// well as draws the main UI
     pub fn begin_frame(&mut self, state: &mut AppState) {
@@ -100,32 +212,49 @@ impl InterfaceManager {
             max: egui::Pos2::new(state.canvas_width as f32, state.canvas_height as f32),
         });
 
-        // If we have pointer lock then we don't really want to use the UI (we're rotating/orbiting/etc.)
-        // so don't give the updated mouse position to egui
+        // If we have pointer lock or are dragging the scene then we don't really want to use the UI (we're
+        // rotating/orbiting/etc.) so don't give the updated mouse position to egui
         let doc = get_document();
-        if let None = doc.pointer_lock_element() {
+        if doc.pointer_lock_element().is_none() && !state.input.dragging_scene {
             new_input.events.append(&mut state.input.egui_events);
+        } else {
+            state.input.egui_events.clear();
         }
         new_input.modifiers = state.input.modifiers;
 
+        // egui only sends the font atlas to the painter when it rebuilds it, which it does whenever
+        // max_texture_side changes. Changing it for a single frame gets a new painter its own copy
+        let max_texture_side = self.painter.max_texture_side();
+        new_input.max_texture_side = Some(if self.reupload_font_atlas {
+            max_texture_side - 1
+        } else {
+            max_texture_side
+        });
+        self.reupload_font_atlas = false;
+
         UICTX.with(|uictx| {
             uictx.begin_frame(new_input);
+            set_number_notation(uictx, state.number_notation);
+            set_velocity_unit(uictx, state.velocity_unit);
 
             // Always record our new frame times
-            self.frame_times.pop_back();
+            self.frame_times
+                .truncate(self.frame_time_history.max(1) - 1);
             self.frame_times.push_front(state.real_delta_time_s);
 
             // Update framerate display only once a second or else it's too hard to read
             if state.real_time_s - self.time_of_last_update > 1.0 {
                 self.time_of_last_update = state.real_time_s;
 
-                let new_frame_rate: f64 =
-                    1.0 / (self.frame_times.iter().sum::<f64>() / (self.frame_times.len() as f64));
+                let num_frames = self.frame_times.len().min(FRAME_RATE_AVERAGE_FRAMES);
+                let new_frame_rate: f64 = 1.0
+                    / (self.frame_times.iter().take(num_frames).sum::<f64>() / (num_frames as f64));
                 self.last_frame_rate = new_frame_rate;
             }
 
             let has_kb: bool = uictx.wants_keyboard_input();
             let egui_consuming_pointer: bool = uictx.wants_pointer_input();
+            state.input.over_ui = egui_consuming_pointer;
 
             // Suppress our inputs if egui wants it instead
             {
@@ -160,6 +289,49 @@ impl InterfaceManager {
                     if state.input.down == ButtonState::Pressed {
                         state.input.down = ButtonState::Handled;
                     }
+
+                    // Tab moves between text fields instead
+                    if state.input.cycle_focus == ButtonState::Pressed {
+                        state.input.cycle_focus = ButtonState::Handled;
+                    }
+
+                    if state.input.look_at_star == ButtonState::Pressed {
+                        state.input.look_at_star = ButtonState::Handled;
+                    }
+
+                    if state.input.roll_left == ButtonState::Pressed {
+                        state.input.roll_left = ButtonState::Handled;
+                    }
+
+                    if state.input.roll_right == ButtonState::Pressed {
+                        state.input.roll_right = ButtonState::Handled;
+                    }
+
+                    if state.input.spectator == ButtonState::Pressed {
+                        state.input.spectator = ButtonState::Handled;
+                    }
+                }
+
+                // The command palette takes every key while it's open, so that e.g. typing "f" into it
+                // doesn't also focus the selected body
+                if self.command_palette_open {
+                    for button in [
+                        &mut state.input.spacebar,
+                        &mut state.input.f,
+                        &mut state.input.g,
+                        &mut state.input.esc,
+                        &mut state.input.step_back,
+                        &mut state.input.step_forward,
+                        &mut state.input.cycle_focus,
+                        &mut state.input.look_at_star,
+                        &mut state.input.spectator,
+                    ]
+                    .iter_mut()
+                    {
+                        if **button == ButtonState::Pressed {
+                            **button = ButtonState::Handled;
+                        }
+                    }
                 }
             }
         });
@@ -192,10 +364,11 @@ impl InterfaceManager {
             // starts covering it (which can also include the label tooltip itself)
             state.hovered = None;
             if let Some(scene) = scene_man.get_main_scene_mut() {
-                if !uictx.wants_pointer_input() {
+                if !uictx.wants_pointer_input() && !state.spectator {
                     handle_pointer_on_scene(state, scene);
                 }
             }
+            state.input.tapped = false;
         });
     }
 
@@ -206,11 +379,462 @@ impl InterfaceManager {
         res_man: &mut ResourceManager,
         orbit_man: &OrbitManager,
     ) {
+        // Only the command palette, so that there's still a way back from it other than the key. Everything is painted
+        // onto the canvas, so screenshots of it don't show the interface either
+        if state.spectator {
+            self.draw_command_palette(state, scene_man, res_man, orbit_man);
+            return;
+        }
+
+        if let Some(scene) = scene_man.get_main_scene() {
+            self.draw_grid_labels(state, scene);
+            self.draw_axes_label(state, scene);
+            self.draw_scale_bar(state);
+            self.draw_body_labels(state, scene);
+            self.draw_event_countdowns(state, scene);
+            self.draw_pick_ray_label(state, scene);
+        }
+        self.draw_axis_gizmo_labels(state);
+
         self.draw_main_toolbar(state, scene_man, res_man, orbit_man);
 
         self.draw_open_windows(state, scene_man, res_man, orbit_man);
 
         self.draw_pop_ups(state, scene_man);
+
+        self.draw_command_palette(state, scene_man, res_man, orbit_man);
+
+        self.draw_loading_overlay(state);
+
+        self.draw_toasts(state);
+    }
+
+    /// Labels the major lines of the reference grid with their distance from the origin along its X and Y axes, and
+    /// shows the current spacing and coordinate frame on the bottom left corner
+    fn draw_grid_labels(&mut self, state: &AppState, scene: &Scene) {
+        let grid = match &scene.grid {
+            Some(grid) if state.show_grid => grid,
+            _ => return,
+        };
+
+        let major_spacing = grid.spacing * GRID_CELLS;
+        let half_cells = (GRID_CELLS / 2.0) as i32;
+        let local_center = grid.rotation.inverse_transform_vector(&grid.center);
+        let color = egui::Color32::from_white_alpha((255.0 * GRID_ALPHA) as u8);
+        let font = egui::FontId::proportional(12.0);
+
+        UICTX.with(|uictx| {
+            let painter = uictx.layer_painter(egui::LayerId::background());
+
+            for index in -half_cells..=half_cells {
+                let offset = index as f64 * major_spacing;
+
+                for (pt, value) in &[
+                    (
+                        grid.center + grid.rotation * Vector3::new(offset, 0.0, 0.0),
+                        local_center.x + offset,
+                    ),
+                    (
+                        grid.center + grid.rotation * Vector3::new(0.0, offset, 0.0),
+                        local_center.y + offset,
+                    ),
+                ] {
+                    let (x, y, visible) = state.camera.world_to_canvas(
+                        &Point3::from(*pt),
+                        state.canvas_width,
+                        state.canvas_height,
+                    );
+                    if !visible {
+                        continue;
+                    }
+
+                    painter.text(
+                        egui::pos2(
+                            x as f32 / state.pixels_per_point,
+                            y as f32 / state.pixels_per_point,
+                        ),
+                        egui::Align2::LEFT_TOP,
+                        format_distance_in(Mm(*value), state.distance_unit, state.number_notation),
+                        font.clone(),
+                        color,
+                    );
+                }
+            }
+
+            let screen = uictx.input().screen_rect();
+            painter.text(
+                screen.left_bottom() + egui::vec2(10.0, -10.0),
+                egui::Align2::LEFT_BOTTOM,
+                format!(
+                    "{:?} grid: {} ({} major)",
+                    state.coordinate_frame,
+                    format_distance_in(
+                        Mm(grid.spacing),
+                        state.distance_unit,
+                        state.number_notation
+                    ),
+                    format_distance_in(
+                        Mm(major_spacing),
+                        state.distance_unit,
+                        state.number_notation
+                    )
+                ),
+                font,
+                color,
+            );
+        });
+    }
+
+    /// Writes which coordinate frame the coordinate axes are in next to the tip of their Z axis
+    fn draw_axes_label(&mut self, state: &AppState, scene: &Scene) {
+        if !state.show_axes {
+            return;
+        }
+
+        let axes_trans = match scene
+            .axes
+            .and_then(|axes| scene.get_component::<TransformComponent>(axes))
+        {
+            Some(trans_comp) => trans_comp.get_world_transform(),
+            None => return,
+        };
+        let tip = axes_trans.trans + axes_trans.rot * Vector3::new(0.0, 0.0, axes_trans.scale.z);
+
+        let (x, y, visible) = state.camera.world_to_canvas(
+            &Point3::from(tip),
+            state.canvas_width,
+            state.canvas_height,
+        );
+        if !visible {
+            return;
+        }
+
+        UICTX.with(|uictx| {
+            uictx.layer_painter(egui::LayerId::background()).text(
+                egui::pos2(
+                    x as f32 / state.pixels_per_point,
+                    y as f32 / state.pixels_per_point,
+                ),
+                egui::Align2::LEFT_BOTTOM,
+                format!("{:?} J2000", state.coordinate_frame),
+                egui::FontId::proportional(14.0),
+                egui::Color32::from_white_alpha(200),
+            );
+        });
+    }
+
+    /// Marks where state.last_pick_ray hit, with what it hit, how far along the ray, and what that ended up selecting.
+    /// If it only picked a point marker the label goes on the picked body instead
+    fn draw_pick_ray_label(&mut self, state: &AppState, scene: &Scene) {
+        let ray = match &state.last_pick_ray {
+            Some(ray) if state.show_pick_ray => ray,
+            _ => return,
+        };
+
+        let describe = |entity: Option<Entity>| -> String {
+            return match entity {
+                Some(entity) => format!(
+                    "{:?}: {}",
+                    entity,
+                    scene.get_entity_name(entity).unwrap_or_default()
+                ),
+                None => String::from("Nothing"),
+            };
+        };
+
+        let (pos, text) = match ray.hit {
+            Some((entity, pos)) => (
+                pos,
+                format!(
+                    "Hit {} at {}\nPicked {}",
+                    describe(Some(entity)),
+                    format_distance_in(
+                        Mm((pos - ray.start).magnitude()),
+                        state.distance_unit,
+                        state.number_notation
+                    ),
+                    describe(ray.picked)
+                ),
+            ),
+            None => match ray
+                .picked
+                .and_then(|picked| scene.get_physical_position(picked))
+            {
+                Some(pos) => (
+                    Point3::from(pos),
+                    format!(
+                        "Missed colliders\nPicked {} by its point",
+                        describe(ray.picked)
+                    ),
+                ),
+                None => return,
+            },
+        };
+
+        let (x, y, visible) =
+            state
+                .camera
+                .world_to_canvas(&pos, state.canvas_width, state.canvas_height);
+        if !visible {
+            return;
+        }
+
+        UICTX.with(|uictx| {
+            let painter = uictx.layer_painter(egui::LayerId::background());
+            let center = egui::pos2(
+                x as f32 / state.pixels_per_point,
+                y as f32 / state.pixels_per_point,
+            );
+            let color = egui::Color32::from_rgb(80, 255, 80);
+
+            painter.circle_stroke(center, 6.0, egui::Stroke::new(1.5, color));
+            painter.text(
+                center + egui::vec2(10.0, -10.0),
+                egui::Align2::LEFT_BOTTOM,
+                text,
+                egui::FontId::monospace(12.0),
+                color,
+            );
+        });
+    }
+
+    /// Writes X, Y and Z just past the tips of the axis gizmo that the rendering system draws in the bottom left corner
+    fn draw_axis_gizmo_labels(&mut self, state: &AppState) {
+        if !state.show_axis_gizmo || !state.label_axis_gizmo {
+            return;
+        }
+
+        let transform = state.camera.get_axis_gizmo_transform();
+        let font = egui::FontId::proportional(12.0);
+
+        UICTX.with(|uictx| {
+            let painter = uictx.layer_painter(egui::LayerId::background());
+            let screen = uictx.input().screen_rect();
+            let center = screen.left_bottom()
+                + egui::vec2(
+                    AXIS_GIZMO_MARGIN + AXIS_GIZMO_SIZE / 2.0,
+                    -AXIS_GIZMO_MARGIN - AXIS_GIZMO_SIZE / 2.0,
+                );
+
+            for (axis, name, color) in &[
+                (Vector3::x(), "X", egui::Color32::from_rgb(255, 80, 80)),
+                (Vector3::y(), "Y", egui::Color32::from_rgb(80, 255, 80)),
+                (Vector3::z(), "Z", egui::Color32::from_rgb(80, 140, 255)),
+            ] {
+                // A bit past the tip, in gizmo NDC
+                let ndc = transform * (axis * 1.15).push(1.0);
+                let offset = egui::vec2(ndc.x as f32, -ndc.y as f32) * AXIS_GIZMO_SIZE / 2.0;
+
+                painter.text(
+                    center + offset,
+                    egui::Align2::CENTER_CENTER,
+                    name,
+                    font.clone(),
+                    *color,
+                );
+            }
+        });
+    }
+
+    /// Draws a bar on the bottom of the screen with a round length in the current distance unit, as long as that
+    /// length looks at the depth of the focused body
+    fn draw_scale_bar(&mut self, state: &AppState) {
+        if !state.show_scale_bar || state.reference_translation.is_none() {
+            return;
+        }
+
+        // The camera's position is relative to the focused body
+        let forward = (state.camera.target - state.camera.pos).normalize();
+        let depth = -state.camera.pos.coords.dot(&forward);
+        if depth <= state.camera.near {
+            return;
+        }
+
+        // Lengths in points, which is what egui draws with
+        let max_length = 150.0;
+        let mm_per_point = state
+            .camera
+            .get_world_units_per_pixel(depth, state.canvas_height)
+            * state.pixels_per_point as f64;
+        let max_distance = Mm(mm_per_point * max_length);
+        let unit = get_distance_unit_size(max_distance, state.distance_unit);
+        let distance = Mm(get_scale_bar_length(max_distance.0 / unit.0) * unit.0);
+        let length = (distance.0 / mm_per_point) as f32;
+        if !length.is_finite() {
+            return;
+        }
+
+        let color = egui::Color32::from_white_alpha(200);
+        let stroke = egui::Stroke::new(1.5, color);
+        let tick = egui::vec2(0.0, 5.0);
+
+        UICTX.with(|uictx| {
+            let painter = uictx.layer_painter(egui::LayerId::background());
+            let screen = uictx.input().screen_rect();
+
+            let left = screen.center_bottom() + egui::vec2(-length / 2.0, -15.0);
+            let right = left + egui::vec2(length, 0.0);
+            painter.line_segment([left, right], stroke);
+            painter.line_segment([left - tick, left], stroke);
+            painter.line_segment([right - tick, right], stroke);
+
+            painter.text(
+                screen.center_bottom() + egui::vec2(0.0, -22.0),
+                egui::Align2::CENTER_BOTTOM,
+                format_distance_in(distance, state.distance_unit, state.number_notation),
+                egui::FontId::proportional(12.0),
+                color,
+            );
+        });
+    }
+
+    /// Counts down to the next periapsis, apoapsis and node crossings of the focused body, if it has an orbit line
+    fn draw_event_countdowns(&mut self, state: &AppState, scene: &Scene) {
+        let reference = match state.reference_entity {
+            Some(reference) => reference,
+            None => return,
+        };
+        let orbit = match scene.orbits.iter().find(|orbit| orbit.body == reference) {
+            Some(orbit) => orbit,
+            None => return,
+        };
+
+        let now = Jdn(scene.epoch.0 + state.sim_time_s / 86400.0);
+        let countdowns = match get_orbit_event_countdowns(
+            now,
+            orbit.period_days,
+            orbit.eccentricity,
+            &orbit.baked_times,
+            &orbit.baked_anomalies,
+            &orbit.rotation,
+        ) {
+            Some(countdowns) => countdowns,
+            None => return,
+        };
+
+        // How long each event is going to take to happen on screen, if we're moving forward in time
+        let real_time_factor = if !state.simulation_paused && state.simulation_speed > 0.0 {
+            Some(1.0 / state.simulation_speed)
+        } else {
+            None
+        };
+
+        UICTX.with(|uictx| {
+            egui::Area::new("event countdowns")
+                .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-10.0, -10.0))
+                .interactable(false)
+                .show(&uictx, |ui| {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.label(format!(
+                            "Next events for {}",
+                            scene.get_entity_name(reference).unwrap_or_default()
+                        ));
+
+                        egui::Grid::new("event countdowns grid").show(ui, |ui| {
+                            for (name, days) in &[
+                                ("Periapsis", Some(countdowns.periapsis)),
+                                ("Apoapsis", Some(countdowns.apoapsis)),
+                                ("Ascending node", countdowns.ascending_node),
+                                ("Descending node", countdowns.descending_node),
+                            ] {
+                                let days = match days {
+                                    Some(days) => *days,
+                                    None => continue,
+                                };
+
+                                ui.label(*name);
+                                ui.label(egui::RichText::new(format_duration(days)).monospace());
+                                if let Some(factor) = real_time_factor {
+                                    ui.label(
+                                        egui::RichText::new(format!(
+                                            "({} real time)",
+                                            format_duration(days * factor)
+                                        ))
+                                        .monospace(),
+                                    );
+                                }
+                                ui.end_row();
+                            }
+                        });
+                    });
+                });
+        });
+    }
+
+    /// Stacks the toasts on the bottom right corner, with the newest one at the bottom
+    fn draw_toasts(&mut self, state: &mut AppState) {
+        state.remove_expired_toasts();
+        if state.toasts.is_empty() {
+            return;
+        }
+
+        UICTX.with(|uictx| {
+            egui::Area::new("toasts")
+                .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-10.0, -10.0))
+                .show(&uictx, |ui| {
+                    let mut dismissed: Option<usize> = None;
+
+                    for (index, toast) in state.toasts.iter().enumerate() {
+                        egui::Frame::popup(ui.style()).show(ui, |ui| {
+                            ui.horizontal(|ui| {
+                                let color = match toast.severity {
+                                    ToastSeverity::Info => egui::Color32::LIGHT_GRAY,
+                                    ToastSeverity::Warning => egui::Color32::YELLOW,
+                                    ToastSeverity::Error => egui::Color32::RED,
+                                };
+                                ui.label(egui::RichText::new(&toast.text).color(color));
+
+                                if ui.small_button("✖").on_hover_text("Dismiss").clicked() {
+                                    dismissed = Some(index);
+                                }
+                            });
+                        });
+                    }
+
+                    if let Some(index) = dismissed {
+                        state.toasts.remove(index);
+                    }
+                });
+        });
+    }
+
+    fn draw_loading_overlay(&mut self, state: &mut AppState) {
+        if state.loading.is_done() && state.loading.errors.is_empty() {
+            return;
+        }
+
+        UICTX.with(|uictx| {
+            let mut open_window = true;
+
+            egui::Window::new("Loading")
+                .open(&mut open_window)
+                .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+                .resizable(false)
+                .collapsible(false)
+                .show(&uictx, |ui| {
+                    let total = state.loading.total.max(1);
+                    let finished = state.loading.loaded + state.loading.failed;
+
+                    ui.add(
+                        egui::ProgressBar::new(finished as f32 / total as f32)
+                            .desired_width(300.0)
+                            .text(format!("{} / {} files", finished, state.loading.total)),
+                    );
+
+                    if !state.loading.errors.is_empty() {
+                        ui.separator();
+                        ui.label(format!("Failed to load {} file(s):", state.loading.failed));
+                        for error in &state.loading.errors {
+                            ui.label(egui::RichText::new(error).color(egui::Color32::RED));
+                        }
+                    }
+                });
+
+            // Closing the window just means we acknowledged the errors
+            if !open_window {
+                state.loading.errors.clear();
+            }
+        });
     }
 
     fn draw_main_toolbar(
@@ -235,16 +859,11 @@ impl InterfaceManager {
             uictx.set_style(style.clone());
 
             egui::TopBottomPanel::top(egui::Id::new("top panel")).show(&uictx, |ui| {
-                let num_bodies = scene_man
-                    .get_main_scene()
-                    .unwrap()
-                    .rigidbody
-                    .get_num_components();
-
-                let sim_date_str = format!(
-                    "{}",
-                    julian_date_number_to_date(Jdn(state.sim_time_s / 86400.0 + J2000_JDN.0))
-                );
+                let main_scene = scene_man.get_main_scene().unwrap();
+                let num_bodies = main_scene.rigidbody.get_num_components();
+
+                let sim_date_str =
+                    format_sim_time(state.sim_time_s, main_scene.epoch, state.time_format);
 
                 ui.with_layout(egui::Layout::left_to_right(), |ui| {
                     ui.menu_button("⚙", |ui| {
@@ -261,6 +880,16 @@ impl InterfaceManager {
                                     );
                                 }
 
+                                if ui
+                                    .button("Reset view")
+                                    .on_hover_text(
+                                        "Move the camera back to the scene's default framing, without reloading it",
+                                    )
+                                    .clicked()
+                                {
+                                    scene_man.reset_view_to_scene_default(state);
+                                }
+
                                 if ui.button("Close scene").clicked() {
                                     scene_man.set_scene("empty", res_man, orbit_man, state);
                                 }
@@ -286,6 +915,18 @@ impl InterfaceManager {
                                     state.open_windows.about = !state.open_windows.about;
                                 }
 
+                                ui.separator();
+
+                                if ui.button("Distance plot").clicked() {
+                                    state.open_windows.distance_plot =
+                                        !state.open_windows.distance_plot;
+                                }
+
+                                if ui.button("Transit finder").clicked() {
+                                    state.open_windows.transit_finder =
+                                        !state.open_windows.transit_finder;
+                                }
+
                                 if DEBUG {
                                     ui.separator();
 
@@ -293,12 +934,18 @@ impl InterfaceManager {
                                         state.open_windows.debug = !state.open_windows.debug;
                                     }
 
+                                    if ui.button("Shader editor").clicked() {
+                                        state.open_windows.shader_editor =
+                                            !state.open_windows.shader_editor;
+                                    }
+
                                     if ui.button("Organize windows").clicked() {
                                         uictx.memory().reset_areas();
                                     }
 
                                     if ui.button("Close all windows").clicked() {
                                         state.open_windows.debug = false;
+                                        state.open_windows.shader_editor = false;
                                         state.open_windows.body_list = false;
                                         state.open_windows.about = false;
                                         state.open_windows.scene_browser = false;
@@ -339,8 +986,27 @@ impl InterfaceManager {
                         .add(egui::Button::new(
                             egui::RichText::new(sim_date_str).monospace(),
                         ))
+                        .on_hover_text("Click to cycle between UTC, TT, Julian Date, Modified Julian Date and seconds since the scene epoch")
                         .clicked()
-                    {}
+                    {
+                        state.time_format = state.time_format.next();
+                    }
+
+                    if ui
+                        .button("Now")
+                        .on_hover_text("Move the simulation to the current real-world UTC time")
+                        .clicked()
+                    {
+                        let now = unix_time_ms_to_julian_date_number(js_sys::Date::now());
+                        state.pending_time_step_s = get_time_step_to_jdn(state.sim_time_s, main_scene.epoch, now);
+                    }
+
+                    // Long time steps take a few frames to get through
+                    if state.pending_time_step_s != 0.0 && state.time_step_total_s > 0.0 {
+                        let progress = 1.0 - state.pending_time_step_s.abs() / state.time_step_total_s;
+                        ui.add(egui::ProgressBar::new(progress as f32).desired_width(80.0).show_percentage())
+                            .on_hover_text("Stepping the simulation through time");
+                    }
 
                     ui.horizontal(|ui| {
                         ui.add(
@@ -456,6 +1122,103 @@ impl InterfaceManager {
         });
     }
 
+    /// Searchable list of quick actions (see get_palette_commands), toggled with Ctrl + state.command_palette_key
+    fn draw_command_palette(
+        &mut self,
+        state: &mut AppState,
+        scene_man: &mut SceneManager,
+        res_man: &mut ResourceManager,
+        orbit_man: &OrbitManager,
+    ) {
+        if state.input.command_palette == ButtonState::Pressed {
+            state.input.command_palette = ButtonState::Handled;
+
+            self.command_palette_open = !self.command_palette_open;
+            self.command_palette_query.clear();
+            self.command_palette_selected = 0;
+        }
+
+        if !self.command_palette_open {
+            return;
+        }
+
+        let commands = get_palette_commands(scene_man.get_main_scene(), DEBUG);
+        let matches = rank_palette_commands(&commands, &self.command_palette_query);
+
+        let mut command_to_run: Option<usize> = None;
+        UICTX.with(|uictx| {
+            egui::Window::new("Command palette")
+                .title_bar(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 60.0))
+                .fixed_size(egui::vec2(400.0, 0.0))
+                .show(&uictx, |ui| {
+                    let search = egui::TextEdit::singleline(&mut self.command_palette_query)
+                        .hint_text("Type a command or body name")
+                        .desired_width(f32::INFINITY)
+                        .ui(ui);
+                    search.request_focus();
+                    if search.changed() {
+                        self.command_palette_selected = 0;
+                    }
+
+                    let input = ui.input();
+                    if input.key_pressed(egui::Key::ArrowDown) {
+                        self.command_palette_selected += 1;
+                    }
+                    if input.key_pressed(egui::Key::ArrowUp) {
+                        self.command_palette_selected =
+                            self.command_palette_selected.saturating_sub(1);
+                    }
+                    self.command_palette_selected = self
+                        .command_palette_selected
+                        .min(matches.len().saturating_sub(1));
+
+                    let enter = input.key_pressed(egui::Key::Enter);
+                    let escape = input.key_pressed(egui::Key::Escape);
+                    drop(input);
+
+                    if enter && !matches.is_empty() {
+                        command_to_run = Some(matches[self.command_palette_selected]);
+                    }
+                    if escape {
+                        self.command_palette_open = false;
+                    }
+
+                    ui.separator();
+
+                    for (match_index, command_index) in matches.iter().enumerate() {
+                        if ui
+                            .selectable_label(
+                                match_index == self.command_palette_selected,
+                                &commands[*command_index].name,
+                            )
+                            .clicked()
+                        {
+                            command_to_run = Some(*command_index);
+                        }
+                    }
+
+                    if matches.is_empty() {
+                        ui.label("No matching commands");
+                    }
+                });
+        });
+
+        if let Some(command_index) = command_to_run {
+            let scene_desc_name = self.selected_scene_desc_name.clone();
+            (commands[command_index].run)(&mut CommandContext {
+                state,
+                scene_man,
+                res_man,
+                orbit_man,
+                scene_desc_name: &scene_desc_name,
+            });
+
+            self.command_palette_open = false;
+        }
+    }
+
     fn draw_open_windows(
         &mut self,
         state: &mut AppState,
@@ -463,19 +1226,73 @@ impl InterfaceManager {
         res_man: &mut ResourceManager,
         orbit_man: &OrbitManager,
     ) {
-        self.draw_debug_window(state, scene_man);
+        self.draw_debug_window(state, scene_man, res_man);
+        if DEBUG {
+            self.draw_shader_editor_window(state, res_man);
+        }
 
         if let Some(main_scene) = scene_man.get_main_scene() {
             self.draw_body_list_window(state, main_scene);
+            self.draw_distance_plot_window(state, main_scene, orbit_man);
+            self.draw_transit_finder_window(state, main_scene, orbit_man);
+        }
+
+        if let Some(main_scene) = scene_man.get_main_scene_mut() {
+            for (entity, visible) in self.body_list_visibility_changes.drain(..) {
+                main_scene.set_entity_visible(entity, visible, self.body_list_hide_children);
+            }
         }
 
         self.draw_about_window(state);
         self.draw_controls_window(state);
-        self.draw_settings_window(state);
+        self.draw_settings_window(state, scene_man);
         self.draw_scene_browser(state, scene_man, res_man, orbit_man);
+        self.draw_scene_load_report_window(scene_man);
+    }
+
+    /// Lists the bodies that the last loaded scene references but that we couldn't find, until the window is closed
+    fn draw_scene_load_report_window(&mut self, scene_man: &mut SceneManager) {
+        let report = match &scene_man.last_load_report {
+            Some(report) if !report.is_ok() => report,
+            _ => return,
+        };
+
+        let open_window = UICTX.with(|uictx| {
+            let mut open_window = true;
+
+            egui::Window::new("Scene load warnings")
+                .open(&mut open_window)
+                .resizable(false)
+                .show(&uictx, |ui| {
+                    ui.label(format!(
+                        "Some bodies of scene '{}' weren't found in the loaded body databases, and were skipped:",
+                        report.scene
+                    ));
+
+                    egui::ScrollArea::vertical()
+                        .max_height(200.0)
+                        .show(ui, |ui| {
+                            for db_name in report.missing_databases.iter() {
+                                ui.label(format!("Missing database '{}'", db_name));
+                            }
+                            for source in report.missing_bodies.iter() {
+                                ui.label(format!("Missing body '{}'", source));
+                            }
+                            for source in report.malformed_sources.iter() {
+                                ui.label(format!("Malformed body source '{}'", source));
+                            }
+                        });
+                });
+
+            return open_window;
+        });
+
+        if !open_window {
+            scene_man.last_load_report = None;
+        }
     }
 
-    fn draw_settings_window(&mut self, state: &mut AppState) {
+    fn draw_settings_window(&mut self, state: &mut AppState, scene_man: &mut SceneManager) {
         UICTX.with(|uictx| {
             let mut open_window = state.open_windows.settings;
 
@@ -484,25 +1301,197 @@ impl InterfaceManager {
                 .resizable(false)
                 .show(&uictx, |ui| {
                     egui::Grid::new("settings").show(ui, |ui| {
-                        ui.label("Vertical FOV:");
-                        ui.add(
-                            egui::Slider::new(&mut state.camera.fov_v, 0.0..=120.0)
-                                .text("degrees")
-                                .integer(),
-                        );
+                        // The slider shows the field of view along the axis that resizing keeps
+                        let aspect_ratio = state.canvas_width as f64 / state.canvas_height.max(1) as f64;
+                        let fov_axis = state.camera.fov_axis;
+                        let mut fov = get_fov_along_axis(state.camera.fov_v, aspect_ratio, fov_axis);
+                        ui.label("Field of view:");
+                        ui.horizontal(|ui| {
+                            egui::ComboBox::from_id_source("fov_axis")
+                                .selected_text(format!("{:?}", fov_axis))
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut state.camera.fov_axis, FovAxis::Vertical, "Vertical");
+                                    ui.selectable_value(&mut state.camera.fov_axis, FovAxis::Horizontal, "Horizontal");
+                                    ui.selectable_value(&mut state.camera.fov_axis, FovAxis::Diagonal, "Diagonal");
+                                })
+                                .response
+                                .on_hover_text("Which field of view stays the same when the window is resized. The other ones follow from the window's aspect ratio");
+                            if ui
+                                .add(egui::Slider::new(&mut fov, 0.0..=120.0).text("degrees").integer())
+                                .changed()
+                            {
+                                state.camera.fov_v = get_vertical_fov(fov, aspect_ratio, fov_axis);
+                            }
+                        });
                         ui.end_row();
 
                         ui.label("Rotation sensitivity:");
                         ui.add(egui::Slider::new(&mut state.rotate_speed, 0.0..=10.0).text(""));
                         ui.end_row();
 
-                        ui.label("Framerate limit:");
+                        ui.label("Boost modifier:");
+                        egui::ComboBox::from_id_source("move_boost_modifier")
+                            .selected_text(format!("{:?}", state.move_boost_modifier))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut state.move_boost_modifier, MoveBoostModifier::Shift, "Shift");
+                                ui.selectable_value(&mut state.move_boost_modifier, MoveBoostModifier::Alt, "Alt");
+                            })
+                            .response
+                            .on_hover_text("Key to hold along with WASDQE to move the camera faster");
+                        ui.end_row();
+
+                        ui.label("Boost factor:");
                         ui.add(
-                            egui::Slider::new(&mut state.frames_per_second_limit, 0.5..=120.0)
+                            egui::DragValue::new(&mut state.move_boost_factor)
+                                .clamp_range(1.0..=1000.0)
+                                .speed(0.1)
+                                .suffix("x"),
+                        )
+                        .on_hover_text("How many times faster the camera moves while the boost modifier is held");
+                        ui.end_row();
+
+                        ui.label("Acceleration time:");
+                        ui.add(
+                            egui::DragValue::new(&mut state.move_ramp_time_s)
+                                .clamp_range(0.0..=10.0)
+                                .speed(0.01)
+                                .suffix(" s"),
+                        )
+                        .on_hover_text("How long WASDQE need to be held to reach full speed, so that quick taps make small adjustments. Zero to always move at full speed");
+                        ui.end_row();
+
+                        ui.label("Camera roll:");
+                        ui.horizontal(|ui| {
+                            let old_mode = state.camera_roll_mode;
+                            egui::ComboBox::from_id_source("camera_roll_mode")
+                                .selected_text(format!("{:?}", state.camera_roll_mode))
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut state.camera_roll_mode, CameraRollMode::Locked, "Locked");
+                                    ui.selectable_value(&mut state.camera_roll_mode, CameraRollMode::Free, "Free");
+                                })
+                                .response
+                                .on_hover_text("Locked keeps the camera's up towards the ecliptic's north, and Free lets it turn every way and roll with Z and C");
+
+                            if ui.button("Level").on_hover_text("Roll the camera back so that its up points to the ecliptic's north").clicked()
+                                || (old_mode != state.camera_roll_mode && state.camera_roll_mode == CameraRollMode::Locked)
+                            {
+                                state.level_horizon_request = true;
+                            }
+                        });
+                        ui.end_row();
+
+                        ui.label("Camera drag:");
+                        egui::ComboBox::from_id_source("camera_drag_mode")
+                            .selected_text(format!("{:?}", state.camera_drag_mode))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut state.camera_drag_mode, CameraDragMode::PointerLock, "PointerLock");
+                                ui.selectable_value(&mut state.camera_drag_mode, CameraDragMode::Drag, "Drag");
+                            })
+                            .response
+                            .on_hover_text("PointerLock hides the cursor while orbiting or looking around, and Drag keeps it visible and only moves the camera while it's over the canvas, which works better on trackpads");
+                        ui.end_row();
+
+                        ui.label("Mouse wheel:");
+                        egui::ComboBox::from_id_source("wheel_mode")
+                            .selected_text(format!("{:?}", state.wheel_mode))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut state.wheel_mode, WheelMode::AdjustSpeed, "AdjustSpeed");
+                                ui.selectable_value(&mut state.wheel_mode, WheelMode::Dolly, "Dolly");
+                            })
+                            .response
+                            .on_hover_text("AdjustSpeed changes how fast the camera moves with WASD, and Dolly moves the camera towards or away from whatever is under the cursor");
+                        ui.end_row();
+
+                        ui.label("Tab cycles through:");
+                        egui::ComboBox::from_id_source("focus_cycle_order")
+                            .selected_text(format!("{:?}", state.focus_cycle_order))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut state.focus_cycle_order, FocusCycleOrder::BodyList, "BodyList");
+                                ui.selectable_value(&mut state.focus_cycle_order, FocusCycleOrder::Distance, "Distance");
+                            })
+                            .response
+                            .on_hover_text("Order that Tab and Shift-Tab select bodies in. BodyList follows the body list and its search filters while it's open, and Distance goes from the nearest body to the camera outwards");
+                        ui.end_row();
+
+                        ui.label("Tab goes to bodies:");
+                        ui.checkbox(&mut state.focus_cycle_go_to, "")
+                            .on_hover_text("Whether Tab and Shift-Tab also focus and fly to each body, instead of just selecting it");
+                        ui.end_row();
+
+                        ui.label("Framerate limit:");
+                        ui.add(
+                            egui::Slider::new(&mut state.frames_per_second_limit, 0.5..=120.0)
                                 .text("fps"),
                         );
                         ui.end_row();
 
+                        ui.label("Frame pacing:");
+                        egui::ComboBox::from_id_source("frame_pacing")
+                            .selected_text(format!("{:?}", state.frame_pacing))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut state.frame_pacing, FramePacing::Accumulate, "Accumulate");
+                                ui.selectable_value(&mut state.frame_pacing, FramePacing::DropFrames, "DropFrames");
+                                ui.selectable_value(&mut state.frame_pacing, FramePacing::Unlimited, "Unlimited");
+                            })
+                            .response
+                            .on_hover_text("Accumulate keeps an even pace close to the framerate limit, DropFrames only draws once a whole frame interval elapsed, and Unlimited ignores the limit");
+                        ui.end_row();
+
+                        let old_units = (state.number_notation, state.distance_unit, state.velocity_unit);
+
+                        ui.label("Number notation:");
+                        egui::ComboBox::from_id_source("number_notation")
+                            .selected_text(format!("{:?}", state.number_notation))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut state.number_notation, NumberNotation::Fixed, "Fixed");
+                                ui.selectable_value(&mut state.number_notation, NumberNotation::Scientific, "Scientific");
+                            })
+                            .response
+                            .on_hover_text("How distances, masses and velocities are shown. Fixed uses SI prefixes like 149.6k, and Scientific uses exponents like 1.496e5");
+                        ui.end_row();
+
+                        ui.label("Distance unit:");
+                        egui::ComboBox::from_id_source("distance_unit")
+                            .selected_text(format!("{:?}", state.distance_unit))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut state.distance_unit, DistanceUnit::Auto, "Auto");
+                                ui.selectable_value(&mut state.distance_unit, DistanceUnit::Km, "Km");
+                                ui.selectable_value(&mut state.distance_unit, DistanceUnit::Mm, "Mm");
+                                ui.selectable_value(&mut state.distance_unit, DistanceUnit::Au, "Au");
+                            })
+                            .response
+                            .on_hover_text("Unit for distances on the grid and to the selected body. Auto picks whichever of km, Mm or AU reads best");
+                        ui.end_row();
+
+                        ui.label("Velocity unit:");
+                        egui::ComboBox::from_id_source("velocity_unit")
+                            .selected_text(format!("{:?}", state.velocity_unit))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut state.velocity_unit, VelocityUnit::KmPerS, "KmPerS");
+                                ui.selectable_value(&mut state.velocity_unit, VelocityUnit::MmPerS, "MmPerS");
+                                ui.selectable_value(&mut state.velocity_unit, VelocityUnit::AuPerDay, "AuPerDay");
+                            })
+                            .response
+                            .on_hover_text("Unit for the speeds on the details panel");
+                        ui.end_row();
+
+                        ui.label("Time format:");
+                        egui::ComboBox::from_id_source("time_format")
+                            .selected_text(state.time_format.get_name())
+                            .show_ui(ui, |ui| {
+                                for format in [TimeFormat::Utc, TimeFormat::Tt, TimeFormat::Jd, TimeFormat::Mjd, TimeFormat::SecondsSinceEpoch] {
+                                    ui.selectable_value(&mut state.time_format, format, format.get_name());
+                                }
+                            })
+                            .response
+                            .on_hover_text("How the toolbar shows the simulation time: As a UTC or Terrestrial Time (TT) calendar date, a Julian Date or Modified Julian Date (both in UTC), or simulation seconds since the epoch of the scene. Clicking the date on the toolbar also cycles through these");
+                        ui.end_row();
+
+                        // Scenes pick their preferred units when loaded, unless we picked our own
+                        if (state.number_notation, state.distance_unit, state.velocity_unit) != old_units {
+                            state.display_units_overridden = true;
+                        }
+
                         ui.label("Pixels per point:");
                         ui.add(
                             egui::Slider::new(&mut state.pixels_per_point, 0.1..=10.0)
@@ -513,6 +1502,49 @@ impl InterfaceManager {
                         ui.checkbox(&mut state.use_skyboxes, "");
                         ui.end_row();
 
+                        ui.label("Max bodies:");
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::DragValue::new(&mut state.max_body_count)
+                                    .clamp_range(0..=1000000)
+                                    .speed(10.0),
+                            )
+                            .on_hover_text("Most database bodies a scene spawns, keeping the largest ones and then the brightest. Zero is no limit. Applies when a scene is loaded");
+
+                            let num_culled = scene_man.get_current_scene().map(|scene| scene.num_culled_bodies).unwrap_or(0);
+                            if num_culled > 0 {
+                                ui.label(format!("{} hidden", num_culled));
+                            }
+                        });
+                        ui.end_row();
+
+                        ui.label("Starfield stars:");
+                        ui.add(
+                            egui::DragValue::new(&mut state.starfield_num_stars)
+                                .clamp_range(0..=50000)
+                                .speed(10.0),
+                        )
+                        .on_hover_text("Procedural stars drawn when there's no skybox or star catalog. Zero disables them");
+                        ui.end_row();
+
+                        ui.label("Starfield seed:");
+                        ui.add(egui::DragValue::new(&mut state.starfield_seed));
+                        ui.end_row();
+
+                        ui.label("Use star catalog:");
+                        ui.checkbox(&mut state.use_star_catalog, "")
+                            .on_hover_text("Draws the real stars of the star catalog when there's no skybox, instead of procedural ones");
+                        ui.end_row();
+
+                        ui.label("Star catalog magnitude limit:");
+                        ui.add(
+                            egui::DragValue::new(&mut state.star_catalog_mag_limit)
+                                .clamp_range(-1.5..=15.0)
+                                .speed(0.05),
+                        )
+                        .on_hover_text("Faintest catalog stars to draw. The naked eye sees up to about 6.5 on a dark night");
+                        ui.end_row();
+
                         ui.label("Show ecliptic grid:");
                         ui.checkbox(&mut state.show_grid, "");
                         ui.end_row();
@@ -521,12 +1553,264 @@ impl InterfaceManager {
                         ui.checkbox(&mut state.show_axes, "");
                         ui.end_row();
 
+                        ui.label("Face the camera:");
+                        ui.checkbox(&mut state.billboard_axes, "")
+                            .on_hover_text("Turn the coordinate axes towards the camera every frame, with Z pointing at it and Y up on screen, instead of along the coordinate frame");
+                        ui.end_row();
+
+                        ui.label("Show axis gizmo:");
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut state.show_axis_gizmo, "")
+                                .on_hover_text("Small X, Y and Z axes in the bottom left corner, that turn along with the camera to show which way the world is oriented");
+                            ui.add_enabled(state.show_axis_gizmo, egui::Checkbox::new(&mut state.label_axis_gizmo, "Labels"));
+                        });
+                        ui.end_row();
+
+                        ui.label("Highlight bodies:");
+                        ui.checkbox(&mut state.show_outlines, "")
+                            .on_hover_text("Outline the body under the cursor, and the selected body in a different color");
+                        ui.end_row();
+
+                        ui.label("Coordinate frame:");
+                        egui::ComboBox::from_id_source("coordinate_frame")
+                            .selected_text(format!("{:?}", state.coordinate_frame))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut state.coordinate_frame, CoordinateFrame::Ecliptic, "Ecliptic");
+                                ui.selectable_value(&mut state.coordinate_frame, CoordinateFrame::Equatorial, "Equatorial");
+                            })
+                            .response
+                            .on_hover_text("Frame that the grid and coordinate axes are drawn in. Equatorial tilts them by the obliquity of the ecliptic, so that Z points at the north celestial pole");
+                        ui.end_row();
+
+                        ui.label("Center of mass frame:");
+                        ui.checkbox(&mut state.center_of_mass_frame, "")
+                            .on_hover_text("Draw everything relative to the center of mass of the simulated bodies, so that the system doesn't drift away. Physics is unaffected");
+                        ui.end_row();
+
+                        ui.label("Show scale bar:");
+                        ui.checkbox(&mut state.show_scale_bar, "")
+                            .on_hover_text("While focusing a body, show how long a length on screen is at the body's distance");
+                        ui.end_row();
+
                         ui.label("Show points:");
                         ui.checkbox(&mut state.show_points, "");
                         ui.end_row();
 
+                        ui.label("Point colors:");
+                        egui::ComboBox::from_id_source("point_color_mode")
+                            .selected_text(format!("{:?}", state.point_color_mode))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut state.point_color_mode, PointColorMode::Default, "Default");
+                                ui.selectable_value(&mut state.point_color_mode, PointColorMode::BodyType, "BodyType");
+                                ui.selectable_value(&mut state.point_color_mode, PointColorMode::Eccentricity, "Eccentricity");
+                                ui.selectable_value(&mut state.point_color_mode, PointColorMode::SemiMajorAxis, "SemiMajorAxis");
+                            })
+                            .response
+                            .on_hover_text("What the color of each point marker represents");
+                        ui.end_row();
+
+                        ui.label("");
+                        ui.vertical(|ui| draw_point_color_legend(ui, state.point_color_mode));
+                        ui.end_row();
+
+                        ui.label("Label density:");
+                        ui.add(egui::Slider::new(&mut state.max_body_labels, 0..=200).text("labels"))
+                            .on_hover_text("Most body names shown at once. Overlapping names keep the nearest body's, and names of bodies hidden behind others are skipped");
+                        ui.end_row();
+
+                        ui.label("Min point size:");
+                        ui.add(egui::Slider::new(&mut state.point_min_size_px, 1.0..=20.0).text("px"))
+                            .on_hover_text("Size of the point markers of the smallest bodies. Bodies are also picked by their marker");
+                        ui.end_row();
+
+                        ui.label("Max point size:");
+                        ui.add(egui::Slider::new(&mut state.point_max_size_px, 1.0..=40.0).text("px"))
+                            .on_hover_text("Size of the point markers of the largest bodies, like the Sun");
+                        ui.end_row();
+                        state.point_max_size_px = state.point_max_size_px.max(state.point_min_size_px);
+
+                        ui.label("Glowing points:");
+                        ui.checkbox(&mut state.point_glow, "")
+                            .on_hover_text("Fade the point markers out from their center like stars, instead of drawing solid dots. They're still picked by their whole disc");
+                        ui.end_row();
+
+                        ui.label("Show orbits:");
+                        ui.checkbox(&mut state.show_orbits, "");
+                        ui.end_row();
+
+                        ui.label("Fade orbits:");
+                        ui.checkbox(&mut state.fade_orbits, "")
+                            .on_hover_text("Fade orbit lines behind each body to show its direction of motion");
+                        ui.end_row();
+
+                        ui.label("Orbits relative to focus:");
+                        ui.checkbox(&mut state.orbits_relative_to_reference, "")
+                            .on_hover_text("While focusing a body, draw the orbit of the body it goes around as seen from it (e.g. the Sun around the Earth) instead of its own orbit");
+                        ui.end_row();
+
+                        ui.label("Show orbit trails:");
+                        ui.checkbox(&mut state.show_orbit_trails, "")
+                            .on_hover_text("Draw the arc of its orbit that each body went through recently. Requires orbits to be shown");
+                        ui.end_row();
+
+                        ui.label("Orbit trail length:");
+                        ui.add(egui::Slider::new(&mut state.orbit_trail_days, 0.1..=3650.0).logarithmic(true).text("days"))
+                            .on_hover_text("How far back in time orbit trails go");
+                        ui.end_row();
+
+                        ui.label("Show body trails:");
+                        ui.checkbox(&mut state.show_body_trails, "")
+                            .on_hover_text("Draw where each body that isn't on a Keplerian orbit has been recently, fading out towards the oldest points. Unlike orbit trails these follow the simulated motion");
+                        ui.end_row();
+
+                        ui.label("Body trail length:");
+                        ui.add(egui::Slider::new(&mut state.body_trail_days, 0.1..=3650.0).logarithmic(true).text("days"))
+                            .on_hover_text("How far back in time body trails go");
+                        ui.end_row();
+
+                        ui.label("Body trail points:");
+                        ui.add(egui::DragValue::new(&mut state.body_trail_points).clamp_range(2..=100000).speed(10.0))
+                            .on_hover_text("Most points each body trail keeps, one per simulation step. The oldest ones are dropped past it");
+                        ui.end_row();
+
+                        ui.label("Ground track length:");
+                        ui.add(egui::Slider::new(&mut state.ground_track_hours, 0.1..=240.0).logarithmic(true).text("hours"))
+                            .on_hover_text("How far back in time the ground track goes, when shown from a body's pop-up");
+                        ui.end_row();
+
+                        ui.label("Trajectory prediction:");
+                        ui.horizontal(|ui| {
+                            ui.add(egui::Slider::new(&mut state.trajectory_prediction_days, 0.1..=3650.0).logarithmic(true).text("days"))
+                                .on_hover_text("How far ahead to predict the trajectory of free bodies, when asked to from their pop-up");
+                            ui.add(egui::DragValue::new(&mut state.trajectory_prediction_steps).clamp_range(10..=10000).suffix(" steps"))
+                                .on_hover_text("Points along predicted trajectories. More steps follow close encounters better, but take longer to predict");
+                        });
+                        ui.end_row();
+
+                        if let Some(scene) = scene_man.get_main_scene_mut() {
+                            ui.label("Moon orbit spread:");
+                            ui.horizontal(|ui| {
+                                ui.add(egui::Slider::new(&mut scene.visual_orbit_scale, 1.0..=100.0).logarithmic(true).text("x"))
+                                    .on_hover_text("Draws moons this many times as far from the bodies they orbit, to tell them apart. Only affects drawing: Physics and the distances shown are unchanged");
+                                if ui.button("Reset").clicked() {
+                                    scene.visual_orbit_scale = 1.0;
+                                }
+                            });
+                            ui.end_row();
+
+                            ui.label("Body size exaggeration:");
+                            ui.horizontal(|ui| {
+                                ui.add(egui::Slider::new(&mut scene.render_scale, 1.0..=1000.0).logarithmic(true).text("x"))
+                                    .on_hover_text("Draws bodies this many times larger than they are, so that small moons are visible from far away. Only affects drawing: Picking and the distances shown use their actual radii");
+                                if ui.button("Reset").clicked() {
+                                    scene.render_scale = 1.0;
+                                }
+                            });
+                            ui.end_row();
+                        }
+
+                        ui.label("Time step:");
+                        ui.add(egui::Slider::new(&mut state.time_step_days, -365.0..=365.0).text("days"))
+                            .on_hover_text("How far the ',' and '.' keys step back and forward in time while paused. Negative values swap them around");
+                        ui.end_row();
+
+                        ui.label("Max physics substep:");
+                        ui.add(
+                            egui::DragValue::new(&mut state.max_physics_substep_s)
+                                .clamp_range(0.0..=86400.0)
+                                .speed(10.0)
+                                .suffix(" s"),
+                        )
+                        .on_hover_text("Longest step the orbits get integrated with. Frames at high time scales get split into several steps so that orbits don't fly apart, at the cost of performance. Zero takes a single step per frame");
+                        ui.end_row();
+
+                        ui.label("Follow keeps velocity horizontal:");
+                        ui.checkbox(&mut state.follow_keeps_velocity_horizontal, "")
+                            .on_hover_text("When following a body, turn the camera with it so that its velocity stays level with the horizon");
+                        ui.end_row();
+
+                        ui.label("Command palette key:");
+                        if egui::TextEdit::singleline(&mut state.command_palette_key)
+                            .desired_width(20.0)
+                            .ui(ui)
+                            .on_hover_text("Opens the command palette along with Ctrl (or Cmd)")
+                            .changed()
+                        {
+                            // Only keep the last character typed, so that typing a new key replaces the old one
+                            if let Some(last) = state.command_palette_key.chars().last() {
+                                state.command_palette_key = last.to_string();
+                            }
+                        }
+                        ui.end_row();
+
+                        ui.label("Look at star key:");
+                        if egui::TextEdit::singleline(&mut state.look_at_star_key)
+                            .desired_width(20.0)
+                            .ui(ui)
+                            .on_hover_text("Turns the camera to face the nearest star, without moving it")
+                            .changed()
+                        {
+                            if let Some(last) = state.look_at_star_key.chars().last() {
+                                state.look_at_star_key = last.to_string();
+                            }
+                        }
+                        ui.end_row();
+
+                        ui.label("Spectator mode key:");
+                        if egui::TextEdit::singleline(&mut state.spectator_key)
+                            .desired_width(20.0)
+                            .ui(ui)
+                            .on_hover_text("Hides the interface and stops clicks and Tab from changing the selection, until pressed again")
+                            .changed()
+                        {
+                            if let Some(last) = state.spectator_key.chars().last() {
+                                state.spectator_key = last.to_string();
+                            }
+                        }
+                        ui.end_row();
+
+                        ui.label("Smooth look at star:");
+                        ui.checkbox(&mut state.look_at_star_smooth, "")
+                            .on_hover_text("Whether the camera turns towards the star over a moment, instead of snapping to it");
+                        ui.end_row();
+
+                        ui.label("Line width:");
+                        ui.add(egui::Slider::new(&mut state.line_width, 0.5..=10.0).text("px"))
+                            .on_hover_text("Width of orbit, grid and axis lines");
+                        ui.end_row();
+
+                        // Scenes pick their preferred lighting when loaded, unless we changed it ourselves
                         ui.label("EV100:");
-                        ui.add(egui::Slider::new(&mut state.ev100, -20.0..=20.0).text(""));
+                        if ui.add(egui::Slider::new(&mut state.ev100, -20.0..=20.0).text(""))
+                            .on_hover_text("Exposure of the camera. Bigger is darker")
+                            .changed() {
+                            state.lighting_overridden = true;
+                        }
+                        ui.end_row();
+
+                        ui.label("Light intensity:");
+                        if ui.add(egui::Slider::new(&mut state.light_intensity_scale, 0.01..=100.0).logarithmic(true).text("x"))
+                            .on_hover_text("Multiplies the intensity of every light in the scene")
+                            .changed() {
+                            state.lighting_overridden = true;
+                        }
+                        ui.end_row();
+
+                        ui.label("Background color:");
+                        ui.color_edit_button_srgb(&mut state.background_color)
+                            .on_hover_text("Color of empty space, when not using skyboxes");
+                        ui.end_row();
+
+                        ui.label("Tonemapping:");
+                        egui::ComboBox::from_id_source("tonemap")
+                            .selected_text(format!("{:?}", state.tonemap))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut state.tonemap, Tonemap::None, "None");
+                                ui.selectable_value(&mut state.tonemap, Tonemap::Reinhard, "Reinhard");
+                                ui.selectable_value(&mut state.tonemap, Tonemap::ACESFilmic, "ACESFilmic");
+                            })
+                            .response
+                            .on_hover_text("Operator mapping the exposed scene color to the display, applied after EV100");
                         ui.end_row();
 
                         ui.label("Allow Local Storage:");
@@ -538,7 +1822,30 @@ impl InterfaceManager {
                             } else {
                                 info!(LogCat::Ui, "Stopping usage and clearing local storage");
                                 local_storage_clear();
+                                state.saved_hashes.clear();
+                            }
+                        }
+                        ui.end_row();
+
+                        ui.label("Auto-save every:");
+                        ui.horizontal(|ui| {
+                            ui.add_enabled(self.local_storage_ok, egui::Slider::new(&mut state.autosave_interval_s, 0.0..=600.0).logarithmic(true).text("s"))
+                                .on_hover_text("How often to save the app state to local storage. 0 to only save with 'Save now'. Saving is skipped if nothing changed");
+                            if ui.add_enabled(self.local_storage_ok, egui::Button::new("Save now")).clicked() {
+                                state.save_requested = true;
                             }
+                        });
+                        ui.end_row();
+
+                        ui.label("Body database:");
+                        if ui.button("Load from file...").on_hover_text("Load a JSON body database like the ones in public/database. Scenes can then use its bodies with sources like '<file name>/<body id>'").clicked() {
+                            pick_local_text_file(".json", receive_user_database);
+                        }
+                        ui.end_row();
+
+                        ui.label("MPC elements:");
+                        if ui.button("Load from file...").on_hover_text("Load asteroid orbital elements in the Minor Planet Center format, like MPCORB.DAT. Scenes can then use its bodies with sources like '<file name>/a0000001'").clicked() {
+                            pick_local_text_file(".dat,.txt", receive_user_mpc_database);
                         }
                         ui.end_row();
                     });
@@ -548,6 +1855,78 @@ impl InterfaceManager {
         });
     }
 
+    /// Writes the names of the bodies next to them, skipping the ones hidden behind other bodies. Labels that would
+    /// overlap are decluttered, keeping the label of the nearest body
+    fn draw_body_labels(&mut self, state: &AppState, scene: &Scene) {
+        if state.max_body_labels == 0 {
+            return;
+        }
+
+        let color = egui::Color32::from_white_alpha(200);
+        let font = egui::FontId::proportional(12.0);
+
+        UICTX.with(|uictx| {
+            let painter = uictx.layer_painter(egui::LayerId::background());
+
+            let mut bodies: Vec<(Entity, Point3<f64>)> = Vec::new();
+            let mut galleys = Vec::new();
+            let mut rects: Vec<LabelRect> = Vec::new();
+            let mut distances: Vec<f64> = Vec::new();
+            for (_, entity) in scene.get_body_id_entities().iter() {
+                // The selection already has its pop-up
+                if state.selection == Some(*entity) || !is_body_visible(scene, *entity) {
+                    continue;
+                }
+
+                let name = match scene.get_entity_name(*entity) {
+                    Some(name) if !name.is_empty() => name,
+                    _ => continue,
+                };
+
+                let pos = match scene.get_component::<TransformComponent>(*entity) {
+                    Some(trans) => Point3::from(trans.get_world_transform().trans),
+                    None => continue,
+                };
+
+                let (x, y, visible) =
+                    state
+                        .camera
+                        .world_to_canvas(&pos, state.canvas_width, state.canvas_height);
+                if !visible {
+                    continue;
+                }
+
+                let galley = painter.layout_no_wrap(name.to_owned(), font.clone(), color);
+                let min = egui::pos2(
+                    x as f32 / state.pixels_per_point + 6.0,
+                    y as f32 / state.pixels_per_point - galley.size().y / 2.0,
+                );
+
+                rects.push(LabelRect {
+                    min_x: min.x - LABEL_PADDING,
+                    min_y: min.y - LABEL_PADDING,
+                    max_x: min.x + galley.size().x + LABEL_PADDING,
+                    max_y: min.y + galley.size().y + LABEL_PADDING,
+                });
+                distances.push((pos.coords - state.camera.world_pos).magnitude());
+                bodies.push((*entity, pos));
+                galleys.push((min, galley));
+            }
+
+            let kept = declutter_labels(
+                &rects,
+                &distances,
+                state.max_body_labels as usize,
+                |index| !is_body_occluded(state, scene, bodies[index].0, &bodies[index].1),
+            );
+
+            for index in kept {
+                let (min, galley) = &galleys[index];
+                painter.galley(*min, galley.clone());
+            }
+        });
+    }
+
     fn draw_pop_ups(&mut self, state: &mut AppState, scene_man: &mut SceneManager) {
         let scene = scene_man.get_main_scene();
         if scene.is_none() {
@@ -555,6 +1934,12 @@ impl InterfaceManager {
         }
         let scene = scene.unwrap();
 
+        // Exported after we're done with the pop-ups, as it may need to add toasts
+        let mut entity_to_export: Option<Entity> = None;
+
+        // Applied once we're done with the pop-ups, as it needs the scene to be mutable
+        let mut color_to_set: Option<(Entity, Option<[f32; 4]>)> = None;
+
         UICTX.with(|uictx| {
             let mut cam_pos = state.camera.pos;
             let mut cam_target = state.camera.target;
@@ -584,6 +1969,10 @@ impl InterfaceManager {
                 }
                 let trans = trans.unwrap();
 
+                if is_body_occluded(state, scene, *selected_entity, &Point3::from(trans.trans)) {
+                    continue;
+                }
+
                 // I think there should be a much simpler way of finding the label position using trig,
                 // but I couldn't do it without trashing precision
                 // TODO: Use last_ndc_position or whatever it is used to draw points to draw this
@@ -592,6 +1981,11 @@ impl InterfaceManager {
                 let distance = obj_to_cam.magnitude();
                 obj_to_cam = obj_to_cam.normalize();
 
+                let physical_distance = scene
+                    .get_physical_position(*selected_entity)
+                    .map(|pos| (cam_pos - Point3::from(pos)).magnitude())
+                    .unwrap_or(distance);
+
                 // Have to enforce all models are within a radius 1 sphere to use this...
                 let ang_dir_to_tangent = (scale / distance).acos();
 
@@ -637,6 +2031,27 @@ impl InterfaceManager {
 
                 let mut entity_to_focus: Option<ReferenceChange> = None;
                 let mut entity_to_go_to: Option<Entity> = None;
+                let mut entity_to_predict: Option<Entity> = None;
+                let mut entity_to_track: Option<Entity> = None;
+
+                // Keplerian bodies already have their orbit line to show where they're going
+                let is_integrated = scene
+                    .get_component::<RigidBodyComponent>(*selected_entity)
+                    .is_some();
+
+                // Something to draw the ground track on
+                let has_central_body = scene
+                    .get_component::<MetadataComponent>(*selected_entity)
+                    .and_then(|meta| meta.get_metadata("body_central_id"))
+                    .and_then(|id| scene.get_entity_from_body_id(id))
+                    .is_some();
+
+                let has_orbit = scene.orbits.iter().any(|orbit| orbit.body == *selected_entity);
+
+                // Only database bodies can be recolored, as the colors are remembered by body id
+                let color_override = scene
+                    .get_body_id_from_entity(*selected_entity)
+                    .map(|id| state.body_colors.get(id).cloned());
 
                 egui::Window::new(name)
                     .fixed_pos(egui::Pos2 {
@@ -645,7 +2060,17 @@ impl InterfaceManager {
                     })
                     .resizable(false)
                     .show(&uictx, |ui| {
-                        ui.label(format!("Distance: {:.3} Mm", distance));
+                        ui.label(format!(
+                            "Distance: {}",
+                            format_distance_in(Mm(physical_distance), state.distance_unit, state.number_notation)
+                        ));
+                        if physical_distance != distance {
+                            ui.label(format!(
+                                "Drawn at: {}",
+                                format_distance_in(Mm(distance), state.distance_unit, state.number_notation)
+                            ))
+                            .on_hover_text("Distance to where the body is drawn, due to the moon orbit spread setting");
+                        }
 
                         ui.horizontal(|ui| {
                             if state.reference_entity == Some(*selected_entity) {
@@ -670,10 +2095,72 @@ impl InterfaceManager {
                                 }
                             }
 
+                            if state.follow.as_ref().map(|f| f.entity) != Some(*selected_entity) {
+                                let but_res = ui
+                                    .button("🛰")
+                                    .on_hover_text("Follow, turning with its motion");
+                                if but_res.clicked() {
+                                    entity_to_focus =
+                                        Some(ReferenceChange::Follow(*selected_entity));
+                                }
+                            }
+
                             let but_res = ui.button("🔍").on_hover_text("Go to");
                             if but_res.clicked() {
                                 entity_to_go_to = Some(*selected_entity);
                             }
+
+                            if is_integrated {
+                                let but_res = ui
+                                    .button("📈")
+                                    .on_hover_text("Predict its trajectory from where it is now");
+                                if but_res.clicked() {
+                                    entity_to_predict = Some(*selected_entity);
+                                }
+                            }
+
+                            if has_central_body {
+                                let tracking = scene.ground_track.as_ref().map(|t| t.body)
+                                    == Some(*selected_entity);
+                                let but_res = ui
+                                    .selectable_label(tracking, "🌐")
+                                    .on_hover_text("Draw its ground track on the body it orbits");
+                                if but_res.clicked() {
+                                    entity_to_track = Some(*selected_entity);
+                                }
+                            }
+
+                            if let Some(color_override) = color_override {
+                                let mut rgb = match color_override {
+                                    Some(color) => [color[0], color[1], color[2]],
+                                    None => get_metadata_base_color(scene, *selected_entity),
+                                };
+                                if ui
+                                    .color_edit_button_rgb(&mut rgb)
+                                    .on_hover_text("Recolor it. Remembered across reloads")
+                                    .changed()
+                                {
+                                    color_to_set =
+                                        Some((*selected_entity, Some([rgb[0], rgb[1], rgb[2], 1.0])));
+                                }
+
+                                if color_override.is_some() {
+                                    let but_res =
+                                        ui.button("🔄").on_hover_text("Clear its custom color");
+                                    if but_res.clicked() {
+                                        color_to_set = Some((*selected_entity, None));
+                                    }
+                                }
+                            }
+
+                            if has_orbit {
+                                let but_res = ui
+                                    .button("💾")
+                                    .on_hover_text("Export its orbit as a glTF line, in the current distance unit");
+                                if but_res.clicked() {
+                                    entity_to_export = Some(*selected_entity);
+                                }
+                            }
                         });
                     })
                     .unwrap();
@@ -685,6 +2172,14 @@ impl InterfaceManager {
                 if let Some(ent) = entity_to_go_to {
                     state.entity_going_to = Some(ent);
                 }
+
+                if let Some(ent) = entity_to_predict {
+                    state.trajectory_prediction_request = Some(ent);
+                }
+
+                if let Some(ent) = entity_to_track {
+                    state.ground_track_request = Some(ent);
+                }
             }
 
             if let Some(hovered) = state.hovered {
@@ -709,9 +2204,32 @@ impl InterfaceManager {
                 }
             }
         });
+
+        if let Some(body) = entity_to_export {
+            match export_orbit(scene, body, state.distance_unit) {
+                Ok(file_name) => state.add_info_toast(format!("Exported orbit to '{}'", file_name)),
+                Err(err) => state.add_error_toast(format!("Failed to export orbit:\n{}", err)),
+            }
+        }
+
+        if let Some((body, color)) = color_to_set {
+            let scene = scene_man.get_current_scene_mut().unwrap();
+            if let Some(body_id) = scene.get_body_id_from_entity(body).map(|id| id.to_owned()) {
+                match color {
+                    Some(color) => state.body_colors.insert(body_id, color),
+                    None => state.body_colors.remove(&body_id),
+                };
+                set_body_color_override(scene, body, color);
+            }
+        }
     }
 
-    fn draw_debug_window(&mut self, state: &mut AppState, scene_man: &mut SceneManager) {
+    fn draw_debug_window(
+        &mut self,
+        state: &mut AppState,
+        scene_man: &mut SceneManager,
+        res_man: &mut ResourceManager,
+    ) {
         UICTX.with(|uictx| {
             let frame_rate = self.last_frame_rate;
             let mut open_window = state.open_windows.debug;
@@ -727,11 +2245,15 @@ impl InterfaceManager {
                                 ui.label(format!("{:.2} seconds", state.sim_time_s));
                                 ui.end_row();
 
+                                let epoch = scene_man
+                                    .get_main_scene()
+                                    .map(|s| s.epoch)
+                                    .unwrap_or(J2000_JDN);
                                 ui.label("Simulation date:");
                                 ui.label(format!(
                                     "{}",
                                     julian_date_number_to_date(Jdn(
-                                        state.sim_time_s / 86400.0 + J2000_JDN.0
+                                        state.sim_time_s / 86400.0 + epoch.0
                                     ))
                                 ));
                                 ui.end_row();
@@ -744,22 +2266,49 @@ impl InterfaceManager {
                                 ui.label(format!("{:.2}", frame_rate));
                                 ui.end_row();
 
+                                ui.label("Primitives drawn / culled:");
+                                ui.label(format!(
+                                    "{} / {}",
+                                    state.render_stats.drawn_primitives,
+                                    state.render_stats.culled_primitives
+                                ));
+                                ui.end_row();
+
+                                ui.label("Show pick ray:");
+                                ui.checkbox(&mut state.show_pick_ray, "")
+                                    .on_hover_text("Draw the ray of the last click on the scene, where it hit a collider and what that selected");
+                                ui.end_row();
+
                                 ui.label("Simulation scale:");
                                 ui.add(
                                     egui::DragValue::new(&mut state.simulation_speed).speed(0.01),
                                 );
                                 ui.end_row();
 
+                                ui.label("Gravity softening [Mm]:")
+                                    .on_hover_text("Keeps gravity bounded when bodies get very close, but weakens it at distances near this value");
+                                ui.add(
+                                    egui::DragValue::new(&mut state.gravity_softening)
+                                        .clamp_range(0.0..=1000.0)
+                                        .speed(0.001),
+                                );
+                                ui.end_row();
+
                                 ui.separator();
                                 ui.separator();
                                 ui.end_row();
 
                                 ui.label("EV100:");
-                                ui.add(
-                                    egui::DragValue::new(&mut state.ev100)
-                                        .clamp_range(-20.0..=20.0)
-                                        .speed(0.01),
-                                );
+                                if ui
+                                    .add(
+                                        egui::DragValue::new(&mut state.ev100)
+                                            .clamp_range(-20.0..=20.0)
+                                            .speed(0.01),
+                                    )
+                                    .changed()
+                                {
+                                    state.lighting_overridden = true;
+                                }
                                 ui.end_row();
 
                                 ui.separator();
@@ -774,12 +2323,23 @@ impl InterfaceManager {
                                 );
                                 ui.end_row();
 
+                                ui.label("Auto depth range:");
+                                ui.checkbox(&mut state.auto_depth_range, "")
+                                    .on_hover_text("Fit near and far to the closest and farthest things we draw every frame. Disable to set them manually");
+                                ui.end_row();
+
                                 ui.label("Near [Mm]:");
-                                ui.add(egui::DragValue::new(&mut state.camera.near).speed(0.01));
+                                ui.add_enabled(
+                                    !state.auto_depth_range,
+                                    egui::DragValue::new(&mut state.camera.near).speed(0.01),
+                                );
                                 ui.end_row();
 
                                 ui.label("Far [Mm]:");
-                                ui.add(egui::DragValue::new(&mut state.camera.far));
+                                ui.add_enabled(
+                                    !state.auto_depth_range,
+                                    egui::DragValue::new(&mut state.camera.far),
+                                );
                                 ui.end_row();
 
                                 // Guarantee valid values even if we manually typed garbage in
@@ -844,8 +2404,43 @@ impl InterfaceManager {
                                         .speed(0.1),
                                 );
                                 ui.end_row();
+
+                                ui.separator();
+                                ui.separator();
+                                ui.end_row();
+
+                                ui.label("WebGL context:");
+                                if ui
+                                    .button("Simulate context loss")
+                                    .on_hover_text("Loses the WebGL context and restores it a second later, to test recovering from it")
+                                    .clicked()
+                                {
+                                    simulate_gl_context_loss();
+                                }
+                                ui.end_row();
+                            });
+
+                            ui.collapsing("Frame times", |ui| {
+                                ui.horizontal(|ui| {
+                                    ui.label("History [frames]:");
+                                    ui.add(
+                                        egui::DragValue::new(&mut self.frame_time_history)
+                                            .clamp_range(10..=2000),
+                                    );
+                                });
+                                draw_frame_time_graph(
+                                    ui,
+                                    &self.frame_times,
+                                    1000.0 / state.frames_per_second_limit,
+                                );
                             });
 
+                            if let Some(scene) = scene_man.get_current_scene() {
+                                ui.collapsing("Memory", |ui| {
+                                    draw_memory_report(ui, &scene.get_memory_report());
+                                });
+                            }
+
                             ui.separator();
 
                             if let Some(selection) = state.selection.iter().next().cloned() {
@@ -887,44 +2482,172 @@ impl InterfaceManager {
                                     }
                                     ui.end_row();
 
-                                    // TODO: Make this more generic
-                                    if let Some(comp) =
-                                        scene.get_component_mut::<TransformComponent>(selection)
-                                    {
-                                        ui.collapsing("Transform component", |ui| {
-                                            comp.draw_details_ui(ui)
-                                        });
-                                    }
+                                    self.details_registry.draw_details(scene, selection, ui);
+
+                                    // Recomputed every frame so that it follows the simulation
+                                    if let Some(mut readouts) =
+                                        get_body_orbit_readouts(scene, selection)
+                                    {
+                                        ui.collapsing("Orbit", |ui| readouts.draw_details_ui(ui));
+                                    }
+
+                                    if let Some(elements) =
+                                        get_body_orbital_elements(scene, selection).cloned()
+                                    {
+                                        ui.collapsing("Orbital elements", |ui| {
+                                            let now =
+                                                sim_time_to_jdn(state.sim_time_s, scene.epoch);
+                                            let mut edited = elements.clone();
+                                            if draw_orbital_elements_editor(ui, &mut edited) {
+                                                set_body_orbital_elements(
+                                                    scene, selection, &edited, now, res_man,
+                                                );
+                                            }
+
+                                            let edited_before =
+                                                scene.catalog_elements.contains_key(&selection);
+                                            let but_res = ui
+                                                .add_enabled(
+                                                    edited_before,
+                                                    egui::Button::new("Reset to catalog"),
+                                                )
+                                                .on_hover_text(
+                                                    "Go back to the orbit from the database",
+                                                );
+                                            if but_res.clicked() {
+                                                reset_body_orbital_elements(
+                                                    scene, selection, now, res_man,
+                                                );
+                                            }
+                                        });
+                                    }
+
+                                    if let Some(comp) =
+                                        scene.get_component_mut::<MetadataComponent>(selection)
+                                    {
+                                        ui.collapsing("Metadata component", |ui| {
+                                            comp.draw_details_ui(ui)
+                                        });
+                                    }
+                                }
+                            }
+                        });
+                });
+
+            state.open_windows.debug = open_window;
+        });
+    }
+
+    fn draw_shader_editor_window(&mut self, state: &mut AppState, res_man: &mut ResourceManager) {
+        UICTX.with(|uictx| {
+            let mut open_window = state.open_windows.shader_editor;
+
+            egui::Window::new("Shader editor")
+                .open(&mut open_window)
+                .resizable(true)
+                .default_size(egui::vec2(600.0, 500.0))
+                .show(&uictx, |ui| {
+                    let mut shader_names: Vec<&String> = SHADER_SOURCES.keys().collect();
+                    shader_names.sort();
+
+                    ui.horizontal(|ui| {
+                        ui.label("Shader:");
+
+                        let mut picked_name = self.shader_editor_name.clone();
+                        egui::ComboBox::from_id_source("shader_editor_name")
+                            .selected_text(&picked_name)
+                            .show_ui(ui, |ui| {
+                                for name in shader_names.iter() {
+                                    ui.selectable_value(&mut picked_name, (*name).clone(), *name);
+                                }
+                            });
+
+                        // Start over from the original source whenever we pick another shader
+                        if picked_name != self.shader_editor_name {
+                            self.shader_editor_source = SHADER_SOURCES
+                                .get(&picked_name)
+                                .map(|s| (*s).to_owned())
+                                .unwrap_or_default();
+                            self.shader_editor_name = picked_name;
+                            self.shader_editor_status = None;
+                        }
+
+                        if has_shader_override(&self.shader_editor_name) {
+                            ui.label(egui::RichText::new("(edited)").color(egui::Color32::YELLOW));
+                        }
+                    });
+
+                    if self.shader_editor_name.is_empty() {
+                        return;
+                    }
+
+                    ui.horizontal(|ui| {
+                        if ui
+                            .button("Apply")
+                            .on_hover_text(
+                                "Recompile every material using this shader with the source below",
+                            )
+                            .clicked()
+                        {
+                            self.shader_editor_status = Some(
+                                res_man
+                                    .recompile_shader(
+                                        &self.shader_editor_name,
+                                        &self.shader_editor_source,
+                                    )
+                                    .map(|num_materials| {
+                                        format!("Recompiling {} material(s)", num_materials)
+                                    }),
+                            );
+                        }
+
+                        if ui
+                            .button("Revert")
+                            .on_hover_text("Go back to the original source of this shader")
+                            .clicked()
+                        {
+                            res_man.revert_shader(&self.shader_editor_name);
+                            self.shader_editor_source = SHADER_SOURCES
+                                .get(&self.shader_editor_name)
+                                .map(|s| (*s).to_owned())
+                                .unwrap_or_default();
+                            self.shader_editor_status =
+                                Some(Ok(String::from("Reverted to the original source")));
+                        }
+                    });
 
-                                    if let Some(comp) =
-                                        scene.get_component_mut::<MeshComponent>(selection)
-                                    {
-                                        ui.collapsing("Mesh component", |ui| {
-                                            comp.draw_details_ui(ui)
-                                        });
-                                    }
+                    match &self.shader_editor_status {
+                        Some(Ok(message)) => {
+                            ui.label(
+                                egui::RichText::new(message).color(egui::Color32::LIGHT_GREEN),
+                            );
+                        }
+                        Some(Err(err)) => {
+                            egui::ScrollArea::vertical()
+                                .id_source("shader_editor_errors")
+                                .max_height(150.0)
+                                .show(ui, |ui| {
+                                    ui.label(egui::RichText::new(err).color(egui::Color32::RED));
+                                });
+                        }
+                        None => {}
+                    };
 
-                                    if let Some(comp) =
-                                        scene.get_component_mut::<RigidBodyComponent>(selection)
-                                    {
-                                        ui.collapsing("RigidBody component", |ui| {
-                                            comp.draw_details_ui(ui)
-                                        });
-                                    }
+                    ui.separator();
 
-                                    if let Some(comp) =
-                                        scene.get_component_mut::<MetadataComponent>(selection)
-                                    {
-                                        ui.collapsing("Metadata component", |ui| {
-                                            comp.draw_details_ui(ui)
-                                        });
-                                    }
-                                }
-                            }
+                    egui::ScrollArea::vertical()
+                        .id_source("shader_editor_source")
+                        .show(ui, |ui| {
+                            ui.add(
+                                egui::TextEdit::multiline(&mut self.shader_editor_source)
+                                    .code_editor()
+                                    .desired_width(f32::INFINITY)
+                                    .desired_rows(30),
+                            );
                         });
                 });
 
-            state.open_windows.debug = open_window;
+            state.open_windows.shader_editor = open_window;
         });
     }
 
@@ -951,6 +2674,14 @@ impl InterfaceManager {
     }
 
     fn draw_controls_window(&mut self, state: &mut AppState) {
+        let palette_shortcut = format!("Ctrl + {}", state.command_palette_key.to_uppercase());
+        let look_at_star_shortcut = state.look_at_star_key.to_uppercase();
+        let spectator_shortcut = state.spectator_key.to_uppercase();
+        let wheel_action = match state.wheel_mode {
+            WheelMode::AdjustSpeed => "Speed up or down",
+            WheelMode::Dolly => "Move towards cursor",
+        };
+
         UICTX.with(|uictx| {
             egui::Window::new("Controls")
                 .open(&mut state.open_windows.controls)
@@ -969,7 +2700,7 @@ impl InterfaceManager {
                         ui.label("Right-click and drag");
                         ui.end_row();
 
-                        ui.label("Speed up or down");
+                        ui.label(wheel_action);
                         ui.label("Mouse-wheel");
                         ui.end_row();
 
@@ -977,6 +2708,10 @@ impl InterfaceManager {
                         ui.label("Spacebar");
                         ui.end_row();
 
+                        ui.label("Step back/forward in time while paused");
+                        ui.label(", and .");
+                        ui.end_row();
+
                         ui.label("Focus selected object");
                         ui.label("F");
                         ui.end_row();
@@ -989,6 +2724,30 @@ impl InterfaceManager {
                         ui.label("G");
                         ui.end_row();
 
+                        ui.label("Focus and go to listed body");
+                        ui.label("Double-click in body list");
+                        ui.end_row();
+
+                        ui.label("Look at nearest star");
+                        ui.label(&look_at_star_shortcut);
+                        ui.end_row();
+
+                        ui.label("Toggle spectator mode (no UI)");
+                        ui.label(&spectator_shortcut);
+                        ui.end_row();
+
+                        ui.label("Roll camera (free roll only)");
+                        ui.label("Z and C");
+                        ui.end_row();
+
+                        ui.label("Cycle through bodies");
+                        ui.label("Tab / Shift + Tab");
+                        ui.end_row();
+
+                        ui.label("Command palette");
+                        ui.label(&palette_shortcut);
+                        ui.end_row();
+
                         ui.label("Orbit focused object");
                         ui.label("Alt + Left-click drag");
                         ui.end_row();
@@ -1155,7 +2914,226 @@ impl InterfaceManager {
         });
     }
 
+    fn draw_distance_plot_window(
+        &mut self,
+        state: &mut AppState,
+        scene: &Scene,
+        orbit_man: &OrbitManager,
+    ) {
+        if !state.open_windows.distance_plot {
+            return;
+        }
+
+        if let Some(selection) = state.selection {
+            let stale = match &self.distance_plot_cache {
+                Some(cache) => {
+                    cache.selection != selection || cache.reference != state.reference_entity
+                }
+                None => true,
+            };
+
+            if stale {
+                self.distance_plot_cache =
+                    sample_distances(selection, state.reference_entity, scene, orbit_man);
+            }
+        } else {
+            self.distance_plot_cache = None;
+        }
+
+        UICTX.with(|uictx| {
+            let mut open_window = state.open_windows.distance_plot;
+
+            egui::Window::new("Distance plot")
+                .open(&mut open_window)
+                .resizable(true)
+                .default_size(egui::vec2(400.0, 250.0))
+                .show(&uictx, |ui| {
+                    if state.selection.is_none() {
+                        ui.label("Select a body to plot its distance over one orbit");
+                        return;
+                    }
+
+                    let cache = self.distance_plot_cache.as_ref();
+                    if cache.is_none() {
+                        ui.label("No orbital elements available for the selected body");
+                        return;
+                    }
+                    let cache = cache.unwrap();
+
+                    ui.label(format!(
+                        "Distance to {} over one orbit ({:.2} days)",
+                        cache.reference_name, cache.period_days
+                    ));
+
+                    let now = Jdn(state.sim_time_s / 86400.0 + scene.epoch.0);
+                    let now_x = (now.0 - cache.start.0).rem_euclid(cache.period_days);
+
+                    egui::plot::Plot::new("distance_plot")
+                        .allow_drag(false)
+                        .show(ui, |plot_ui| {
+                            plot_ui.line(
+                                egui::plot::Line::new(egui::plot::Values::from_values_iter(
+                                    cache
+                                        .samples
+                                        .iter()
+                                        .map(|s| egui::plot::Value::new(s[0], s[1])),
+                                ))
+                                .name("Distance [Mm]"),
+                            );
+
+                            plot_ui.vline(egui::plot::VLine::new(now_x).name("Now"));
+                        });
+                });
+
+            state.open_windows.distance_plot = open_window;
+        });
+    }
+
+    fn draw_transit_finder_window(
+        &mut self,
+        state: &mut AppState,
+        scene: &Scene,
+        orbit_man: &OrbitManager,
+    ) {
+        if !state.open_windows.transit_finder {
+            return;
+        }
+
+        let mut bodies: Vec<(Entity, &str)> = scene
+            .get_entity_entries()
+            .iter()
+            .filter(|entry| {
+                entry.live
+                    && scene
+                        .get_component::<RigidBodyComponent>(entry.current)
+                        .is_some()
+            })
+            .filter_map(|entry| Some((entry.current, entry.name.as_ref()?.as_str())))
+            .collect();
+        bodies.sort_by(|a, b| a.1.cmp(b.1));
+
+        UICTX.with(|uictx| {
+            let mut open_window = state.open_windows.transit_finder;
+            let finder = &mut self.transit_finder;
+
+            egui::Window::new("Transit finder")
+                .open(&mut open_window)
+                .resizable(true)
+                .default_size(egui::vec2(350.0, 300.0))
+                .show(&uictx, |ui| {
+                    egui::Grid::new("transit_finder").show(ui, |ui| {
+                        ui.label("First body:");
+                        draw_body_combo_box(
+                            ui,
+                            "transit_first",
+                            &mut finder.first,
+                            &bodies,
+                            "None",
+                        );
+                        ui.end_row();
+
+                        ui.label("Second body:");
+                        draw_body_combo_box(
+                            ui,
+                            "transit_second",
+                            &mut finder.second,
+                            &bodies,
+                            "None",
+                        );
+                        ui.end_row();
+
+                        ui.label("Observer:");
+                        draw_body_combo_box(
+                            ui,
+                            "transit_observer",
+                            &mut finder.observer,
+                            &bodies,
+                            "Camera",
+                        );
+                        ui.end_row();
+
+                        ui.label("Days to scan:");
+                        ui.add(
+                            egui::DragValue::new(&mut finder.scan_days)
+                                .clamp_range(1.0..=36525.0)
+                                .speed(1.0),
+                        )
+                        .on_hover_text("How far ahead of the current simulation date to search");
+                        ui.end_row();
+                    });
+
+                    let can_search = finder.first.is_some()
+                        && finder.second.is_some()
+                        && finder.first != finder.second;
+                    if ui
+                        .add_enabled(can_search, egui::Button::new("Search"))
+                        .clicked()
+                    {
+                        finder.results = Some(search_transits(finder, state, scene, orbit_man));
+                    }
+
+                    let results = match &finder.results {
+                        Some(results) => results,
+                        None => return,
+                    };
+
+                    ui.separator();
+                    if results.is_empty() {
+                        ui.label("No transits or occultations found");
+                        return;
+                    }
+
+                    let get_name = |entity: Option<Entity>| {
+                        return entity
+                            .and_then(|e| scene.get_entity_name(e))
+                            .unwrap_or_default()
+                            .to_owned();
+                    };
+
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        for event in results.iter() {
+                            let (nearer, farther) = if event.first_is_nearer {
+                                (finder.first, finder.second)
+                            } else {
+                                (finder.second, finder.first)
+                            };
+                            let verb = match event.kind {
+                                TransitKind::Transit => "transits",
+                                TransitKind::Occultation => "occults",
+                            };
+
+                            let text = format!(
+                                "{}: {} {} {}",
+                                julian_date_number_to_date(event.time).format("%Y-%m-%d %H:%M"),
+                                get_name(nearer),
+                                verb,
+                                get_name(farther)
+                            );
+
+                            if ui
+                                .button(text)
+                                .on_hover_text(format!(
+                                    "Centers {:.4} degrees apart. Click to jump to this time",
+                                    event.separation_rad.to_degrees()
+                                ))
+                                .clicked()
+                            {
+                                state.pending_time_step_s =
+                                    get_time_step_to_jdn(state.sim_time_s, scene.epoch, event.time);
+                                state.simulation_paused = true;
+                            }
+                        }
+                    });
+                });
+
+            state.open_windows.transit_finder = open_window;
+        });
+    }
+
     fn draw_body_list_window(&mut self, state: &mut AppState, scene: &Scene) {
+        // Refilled below while the window is open
+        state.body_list_order.clear();
+
         UICTX.with(|uictx| {
             let mut open_window = state.open_windows.body_list;
 
@@ -1173,7 +3151,94 @@ impl InterfaceManager {
                             .ui(ui);
                     });
 
-                    let filter_lower = self.body_list_filter.to_lowercase();
+                    ui.horizontal_wrapped(|ui| {
+                        for (body_type, enabled) in self.body_list_type_filter.iter_mut() {
+                            ui.toggle_value(enabled, *body_type);
+                        }
+                    });
+
+                    ui.checkbox(&mut self.body_list_hide_children, "Show/hide children too")
+                        .on_hover_text("Whether the checkbox on each row also applies to the body's rings, moons, etc.");
+
+                    if scene.num_culled_bodies > 0 {
+                        ui.label(format!("{} smaller bodies left out by the body limit", scene.num_culled_bodies))
+                            .on_hover_text("Raise or clear 'Max bodies' in the settings and reload the scene to see them");
+                    }
+
+                    ui.collapsing("Generate asteroid belt", |ui| {
+                        let dist = &mut state.belt_distribution;
+                        egui::Grid::new("belt_generator").num_columns(2).show(ui, |ui| {
+                            ui.label("Orbiting:");
+                            ui.text_edit_singleline(&mut dist.central_id)
+                                .on_hover_text("Body id of what the asteroids orbit, e.g. 10 for the Sun");
+                            ui.end_row();
+
+                            ui.label("Count:");
+                            ui.add(egui::DragValue::new(&mut dist.count).clamp_range(1..=10000));
+                            ui.end_row();
+
+                            ui.label("Seed:");
+                            ui.add(egui::DragValue::new(&mut dist.seed))
+                                .on_hover_text("The same seed and ranges always generate the same asteroids");
+                            ui.end_row();
+
+                            for (label, range, suffix, max) in [
+                                ("Semi-major axis:", &mut dist.semi_major_axis, " AU", 100.0),
+                                ("Eccentricity:", &mut dist.eccentricity, "", 0.99),
+                                ("Inclination:", &mut dist.inclination, " deg", 180.0),
+                            ] {
+                                ui.label(label);
+                                ui.horizontal(|ui| {
+                                    ui.add(egui::DragValue::new(&mut range[0]).clamp_range(0.0..=max).speed(0.01).suffix(suffix));
+                                    ui.label("to");
+                                    ui.add(egui::DragValue::new(&mut range[1]).clamp_range(0.0..=max).speed(0.01).suffix(suffix));
+                                });
+                                ui.end_row();
+                            }
+                        });
+
+                        if ui.button("Generate").on_hover_text("Adds the asteroids to the current scene, on Keplerian orbits").clicked() {
+                            state.belt_request = Some(state.belt_distribution.clone());
+                        }
+                    });
+
+                    // Collect and score the matches first so that we can sort them
+                    let mut results: Vec<(i32, Entity, &str)> = Vec::new();
+                    for entity in scene.get_entity_entries() {
+                        if !entity.live {
+                            continue;
+                        }
+
+                        if scene
+                            .get_component::<RigidBodyComponent>(entity.current)
+                            .is_none()
+                        {
+                            continue;
+                        }
+
+                        let body_type = scene
+                            .get_component::<MetadataComponent>(entity.current)
+                            .and_then(|c| c.get_metadata("body_type"))
+                            .map(|t| t.as_str())
+                            .unwrap_or("Other");
+                        let type_enabled = self
+                            .body_list_type_filter
+                            .iter()
+                            .find(|(t, _)| *t == body_type)
+                            .map(|(_, enabled)| *enabled)
+                            .unwrap_or(true);
+                        if !type_enabled {
+                            continue;
+                        }
+
+                        if let Some(name) = &entity.name {
+                            if let Some(score) = fuzzy_match_score(&self.body_list_filter, name) {
+                                results.push((score, entity.current, name));
+                            }
+                        }
+                    }
+                    results.sort_by(|a, b| b.0.cmp(&a.0).then(a.2.cmp(b.2)));
+                    state.body_list_order = results.iter().map(|(_, entity, _)| *entity).collect();
 
                     egui::Frame::dark_canvas(ui.style()).show(ui, |ui| {
                         ui.set_min_height(ui.available_size().y);
@@ -1185,26 +3250,54 @@ impl InterfaceManager {
                                 egui::Frame::none()
                                     .inner_margin(egui::style::Margin::same(5.0))
                                     .show(ui, |ui| {
-                                        for entity in scene.get_entity_entries() {
-                                            if !entity.live {
-                                                continue;
-                                            }
+                                        for (_, entity, name) in
+                                            results.iter().take(MAX_BODY_LIST_ROWS)
+                                        {
+                                            let mut go_to = false;
+
+                                            ui.horizontal(|ui| {
+                                                let mut visible = scene.is_entity_visible(*entity);
+                                                if ui
+                                                    .checkbox(&mut visible, "")
+                                                    .on_hover_text("Show or hide without deleting it")
+                                                    .changed()
+                                                {
+                                                    self.body_list_visibility_changes
+                                                        .push((*entity, visible));
+                                                }
 
-                                            if scene
-                                                .get_component::<RigidBodyComponent>(entity.current)
-                                                .is_none()
-                                            {
-                                                continue;
-                                            }
+                                                if ui
+                                                    .button("🎥")
+                                                    .on_hover_text("Focus and go to")
+                                                    .clicked()
+                                                {
+                                                    go_to = true;
+                                                }
 
-                                            if let Some(name) = &entity.name {
-                                                if name.to_lowercase().contains(&filter_lower) {
-                                                    if ui.button(name).clicked() {
-                                                        state.selection = Some(entity.current);
-                                                    }
+                                                let name_res = ui.button(*name);
+                                                if name_res.clicked() {
+                                                    state.selection = Some(*entity);
                                                 }
+                                                if name_res.double_clicked() {
+                                                    go_to = true;
+                                                }
+                                            });
+
+                                            if go_to {
+                                                state.selection = Some(*entity);
+                                                state.next_reference_entity = Some(
+                                                    ReferenceChange::FocusKeepLocation(*entity),
+                                                );
+                                                state.entity_going_to = Some(*entity);
                                             }
                                         }
+
+                                        if results.len() > MAX_BODY_LIST_ROWS {
+                                            ui.label(format!(
+                                                "... and {} more, refine the search to see them",
+                                                results.len() - MAX_BODY_LIST_ROWS
+                                            ));
+                                        }
                                     });
                             });
                     });
@@ -1215,6 +3308,443 @@ impl InterfaceManager {
     }
 }
 
+/// Linear RGB of the "base_color" metadata of `body`, or white
+fn get_metadata_base_color(scene: &Scene, body: Entity) -> [f32; 3] {
+    let mut color: [f32; 3] = [1.0, 1.0, 1.0];
+    if let Some(bytes) = scene
+        .get_component::<MetadataComponent>(body)
+        .and_then(|meta| meta.get_metadata("base_color"))
+        .and_then(|hex| decode_hex(hex).ok())
+    {
+        // The metadata is sRGB. Same approximation to sRGB as functions.glsl
+        for (channel, byte) in color.iter_mut().zip(bytes.iter()) {
+            *channel = (*byte as f32 / 255.0).powf(2.2);
+        }
+    }
+
+    return color;
+}
+
+/// Downloads the orbit line of `body` as a GLB file with its points in `unit`, returning the file name
+fn export_orbit(scene: &Scene, body: Entity, unit: DistanceUnit) -> Result<String, String> {
+    let orbit = scene
+        .orbits
+        .iter()
+        .find(|orbit| orbit.body == body)
+        .ok_or(String::from("Body has no orbit line"))?;
+    let name = format!("{} orbit", scene.get_entity_name(body).unwrap_or("Body"));
+
+    let points = get_baked_orbit_points(&orbit.elements, &orbit.baked_anomalies, &orbit.rotation);
+    let a = orbit.elements.semi_major_axis;
+    let glb = build_line_strip_glb(
+        &name,
+        &points,
+        get_distance_unit_size(a, unit),
+        get_distance_unit_name(a, unit),
+    )?;
+
+    let file_name = format!("{}.glb", name);
+    download_bytes(&file_name, &glb, "model/gltf-binary")?;
+    return Ok(file_name);
+}
+
+/// Called once the user picks a body database file from the settings window
+fn receive_user_database(file_name: String, text: Result<String, String>) {
+    ENGINE.with(|e| {
+        STATE.with(|s| {
+            let mut ref_mut_e = e.borrow_mut();
+            let e = ref_mut_e.as_mut().unwrap();
+            let mut ref_mut_s = s.borrow_mut();
+            let s = ref_mut_s.as_mut().unwrap();
+
+            e.receive_user_database_text(&file_name, "body_database", text, s);
+        });
+    });
+}
+
+/// Called once the user picks a file of MPC orbital elements from the settings window
+fn receive_user_mpc_database(file_name: String, text: Result<String, String>) {
+    ENGINE.with(|e| {
+        STATE.with(|s| {
+            let mut ref_mut_e = e.borrow_mut();
+            let e = ref_mut_e.as_mut().unwrap();
+            let mut ref_mut_s = s.borrow_mut();
+            let s = ref_mut_s.as_mut().unwrap();
+
+            e.receive_user_database_text(&file_name, "mpc_database", text, s);
+        });
+    });
+}
+
+/// Draws a row per storage in `report` with its memory use and occupancy, and their total at the bottom
+fn draw_memory_report(ui: &mut egui::Ui, report: &[(&'static str, MemoryReport)]) {
+    egui::Grid::new("memory_report")
+        .striped(true)
+        .num_columns(4)
+        .show(ui, |ui| {
+            ui.label("Storage");
+            ui.label("Allocated [KiB]");
+            ui.label("Elements");
+            ui.label("Occupancy");
+            ui.end_row();
+
+            let mut total = MemoryReport::default();
+            for (name, storage_report) in report.iter() {
+                ui.label(*name);
+                ui.label(format!(
+                    "{:.1}",
+                    storage_report.allocated_bytes as f64 / 1024.0
+                ));
+                ui.label(format!(
+                    "{} / {}",
+                    storage_report.num_elements, storage_report.capacity
+                ));
+                ui.label(format!("{:.0}%", storage_report.get_occupancy() * 100.0));
+                ui.end_row();
+
+                total = total + *storage_report;
+            }
+
+            ui.label("Total");
+            ui.label(format!("{:.1}", total.allocated_bytes as f64 / 1024.0));
+            ui.label(format!("{} / {}", total.num_elements, total.capacity));
+            ui.label(format!("{:.0}%", total.get_occupancy() * 100.0));
+            ui.end_row();
+        });
+}
+
+/// Drag values for each of `elements`, with the angles in degrees. The period isn't editable, as it follows from the
+/// semi-major axis. Returns whether anything changed
+fn draw_orbital_elements_editor(ui: &mut egui::Ui, elements: &mut OrbitalElements) -> bool {
+    let mut changed = false;
+
+    egui::Grid::new("orbital_elements_editor")
+        .num_columns(2)
+        .show(ui, |ui| {
+            ui.label("Semi-major axis [Mm]:");
+            let speed = (elements.semi_major_axis.0 * 0.001).max(0.001);
+            changed |= ui
+                .add(
+                    egui::DragValue::new(&mut elements.semi_major_axis.0)
+                        .speed(speed)
+                        .clamp_range(0.001..=f64::MAX),
+                )
+                .changed();
+            ui.end_row();
+
+            ui.label("Eccentricity:");
+            changed |= ui
+                .add(
+                    egui::DragValue::new(&mut elements.eccentricity)
+                        .speed(0.001)
+                        .clamp_range(0.0..=MAX_EDITED_ECCENTRICITY),
+                )
+                .changed();
+            ui.end_row();
+
+            let mut angle = |ui: &mut egui::Ui, label: &str, value: &mut Rad| {
+                ui.label(label);
+                let mut degrees = value.to_deg().0;
+                let res = ui.add(egui::DragValue::new(&mut degrees).speed(0.1).suffix("°"));
+                if res.changed() {
+                    *value = Deg(degrees).to_rad();
+                    changed = true;
+                }
+                ui.end_row();
+            };
+            angle(ui, "Inclination:", &mut elements.inclination);
+            angle(
+                ui,
+                "Longitude of ascending node:",
+                &mut elements.long_asc_node,
+            );
+            angle(ui, "Argument of periapsis:", &mut elements.arg_periapsis);
+            angle(ui, "Mean anomaly at epoch:", &mut elements.mean_anomaly_0);
+
+            ui.label("Period [days]:");
+            ui.label(format!("{:.3}", elements.sidereal_orbit_period_days));
+            ui.end_row();
+        });
+
+    return changed;
+}
+
+/// Draws one bar per frame in `frame_times` (most recent first, in seconds) with the most recent on the right,
+/// and a line at `budget_ms`. Frames that took longer than the budget are drawn in red
+fn draw_frame_time_graph(ui: &mut egui::Ui, frame_times: &VecDeque<f64>, budget_ms: f64) {
+    let num_frames = frame_times.len();
+    let bars: Vec<egui::plot::Bar> = frame_times
+        .iter()
+        .enumerate()
+        .map(|(index, frame_time)| {
+            let ms = frame_time * 1000.0;
+            let color = if ms > budget_ms {
+                egui::Color32::RED
+            } else {
+                egui::Color32::LIGHT_GREEN
+            };
+
+            return egui::plot::Bar::new((num_frames - index) as f64, ms)
+                .width(1.0)
+                .fill(color)
+                .stroke(egui::Stroke::none());
+        })
+        .collect();
+
+    egui::plot::Plot::new("frame_time_graph")
+        .height(120.0)
+        .allow_drag(false)
+        .allow_zoom(false)
+        .allow_scroll(false)
+        .allow_boxed_zoom(false)
+        .show_x(false)
+        .include_x(0.0)
+        .include_x(num_frames as f64 + 1.0)
+        .include_y(0.0)
+        .include_y(budget_ms * 2.0)
+        .show(ui, |plot_ui| {
+            plot_ui.bar_chart(egui::plot::BarChart::new(bars).name("Frame time [ms]"));
+            plot_ui.hline(
+                egui::plot::HLine::new(budget_ms)
+                    .color(egui::Color32::YELLOW)
+                    .name("Budget [ms]"),
+            );
+        });
+}
+
+/// Shows which values each point marker color stands for, with `mode`
+fn draw_point_color_legend(ui: &mut egui::Ui, mode: PointColorMode) {
+    let to_color32 = |color: [f32; 3]| -> egui::Color32 {
+        return egui::Color32::from_rgb(
+            (color[0] * 255.0) as u8,
+            (color[1] * 255.0) as u8,
+            (color[2] * 255.0) as u8,
+        );
+    };
+
+    const NUM_GRADIENT_LABELS: u32 = 5;
+
+    ui.horizontal_wrapped(|ui| match mode {
+        PointColorMode::Default => {
+            ui.label("Fixed color per body type, or the body's own color");
+        }
+        PointColorMode::BodyType => {
+            for body_type in BODY_TYPES.iter() {
+                ui.colored_label(to_color32(get_body_type_color(body_type)), *body_type);
+            }
+        }
+        PointColorMode::Eccentricity => {
+            for i in 0..NUM_GRADIENT_LABELS {
+                let t = i as f32 / (NUM_GRADIENT_LABELS - 1) as f32;
+                ui.colored_label(to_color32(get_gradient_color(t)), format!("{:.2}", t));
+            }
+            ui.colored_label(to_color32(UNKNOWN_COLOR), "Unknown");
+        }
+        PointColorMode::SemiMajorAxis => {
+            let (min, max) = SEMI_MAJOR_AXIS_RANGE_MM;
+            for i in 0..NUM_GRADIENT_LABELS {
+                let t = i as f64 / (NUM_GRADIENT_LABELS - 1) as f64;
+                let au = min * (max / min).powf(t) / Au(1.0).to_Mm().0;
+                ui.colored_label(
+                    to_color32(get_gradient_color(t as f32)),
+                    format!("{:.1} AU", au),
+                );
+            }
+            ui.colored_label(to_color32(UNKNOWN_COLOR), "Unknown");
+        }
+    });
+}
+
+fn get_entity_translation(scene: &Scene, entity: Entity) -> Vector3<f64> {
+    return scene
+        .get_physical_position(entity)
+        .unwrap_or(Vector3::zeros());
+}
+
+/// Samples the distance from `selection` to `reference` (or the scene origin) across one orbital
+/// period, using the baked eccentric anomaly times so that we don't have to solve Kepler's equation
+/// for every sample. The central body and reference are assumed to stay where they currently are
+fn sample_distances(
+    selection: Entity,
+    reference: Option<Entity>,
+    scene: &Scene,
+    orbit_man: &OrbitManager,
+) -> Option<DistancePlotCache> {
+    let get_body_id = |entity: Entity| -> Option<&String> {
+        return scene
+            .get_component::<MetadataComponent>(entity)
+            .and_then(|c| c.get_metadata("body_id"));
+    };
+
+    let body_id = get_body_id(selection)?;
+    let all_elements = orbit_man.get_osc_elements().get(body_id)?;
+
+    // Prefer elements that are given wrt. our reference body, if we have any
+    let reference_body_id = reference.and_then(|r| get_body_id(r));
+    let elements = all_elements
+        .iter()
+        .find(|e| Some(&e.ref_id) == reference_body_id)
+        .or(all_elements.first())?;
+
+    let central_pos = scene
+        .get_entity_from_body_id(&elements.ref_id)
+        .map(|e| get_entity_translation(scene, e))
+        .unwrap_or(Vector3::zeros());
+
+    let (reference_pos, reference_name) = match reference {
+        Some(reference) => (
+            get_entity_translation(scene, reference),
+            scene
+                .get_entity_name(reference)
+                .unwrap_or_default()
+                .to_owned(),
+        ),
+        None => (Vector3::zeros(), String::from("scene origin")),
+    };
+    let offset = central_pos - reference_pos;
+
+    let rotation = elements_to_ellipse_rotation_transform(elements);
+    let anomalies = adaptive_eccentric_anomalies(elements.eccentricity, DISTANCE_PLOT_SAMPLES);
+    let times = bake_times_for_eccentric_anomalies(elements, &anomalies);
+
+    let start = times[0];
+    let samples = times
+        .iter()
+        .zip(anomalies.iter())
+        .map(|(t, anomaly)| {
+            let (pos, _) = eccentric_anomaly_to_xyz(elements, *anomaly, &rotation);
+            return [t.0 - start.0, (pos.coords + offset).magnitude()];
+        })
+        .collect();
+
+    return Some(DistancePlotCache {
+        selection,
+        reference,
+        reference_name,
+        period_days: elements.sidereal_orbit_period_days,
+        start,
+        samples,
+    });
+}
+
+/// Lets the user pick one of `bodies`, or nothing, which is shown as `none_text`
+fn draw_body_combo_box(
+    ui: &mut egui::Ui,
+    id: &str,
+    selected: &mut Option<Entity>,
+    bodies: &[(Entity, &str)],
+    none_text: &str,
+) {
+    let selected_text = selected
+        .and_then(|s| bodies.iter().find(|(e, _)| *e == s))
+        .map(|(_, name)| *name)
+        .unwrap_or(none_text);
+
+    egui::ComboBox::from_id_source(id)
+        .selected_text(selected_text)
+        .show_ui(ui, |ui| {
+            ui.selectable_value(selected, None, none_text);
+            for (entity, name) in bodies.iter() {
+                ui.selectable_value(selected, Some(*entity), *name);
+            }
+        });
+}
+
+/// Searches for transits between the two bodies picked in `finder`, using the orbital elements of the bodies (and of
+/// the observer, if it's a body) to predict where they'll be over the next `finder.scan_days`
+fn search_transits(
+    finder: &TransitFinder,
+    state: &AppState,
+    scene: &Scene,
+    orbit_man: &OrbitManager,
+) -> Vec<TransitEvent> {
+    let (first, second) = match (finder.first, finder.second) {
+        (Some(first), Some(second)) => (first, second),
+        _ => return Vec::new(),
+    };
+    let osc_elements = orbit_man.get_osc_elements();
+
+    // The separation changes the fastest with whichever of the bodies orbits the quickest
+    let periods: Vec<f64> = [Some(first), Some(second), finder.observer]
+        .iter()
+        .flatten()
+        .filter_map(|entity| {
+            let body_id = scene
+                .get_component::<MetadataComponent>(*entity)?
+                .get_metadata("body_id")?;
+            let (_, elements) = find_orbit_central_body(scene, body_id, osc_elements)?;
+            return Some(elements.sidereal_orbit_period_days);
+        })
+        .collect();
+
+    let camera_pos = Point3::from(state.camera.world_pos);
+    let start = Jdn(state.sim_time_s / 86400.0 + scene.epoch.0);
+    let end = Jdn(start.0 + finder.scan_days);
+
+    let events = find_transits(
+        start,
+        end,
+        get_transit_scan_step(&periods),
+        (
+            get_body_radius(scene, first),
+            get_body_radius(scene, second),
+        ),
+        |t| {
+            return [
+                finder
+                    .observer
+                    .map(|o| predict_body_position(scene, o, osc_elements, t))
+                    .unwrap_or(camera_pos),
+                predict_body_position(scene, first, osc_elements, t),
+                predict_body_position(scene, second, osc_elements, t),
+            ];
+        },
+    );
+
+    info!(
+        LogCat::Orbit,
+        "Found {} transits over {} days",
+        events.len(),
+        finder.scan_days
+    );
+    return events;
+}
+
+/// Whether the meshes of `body` and its children are drawn
+fn is_body_visible(scene: &Scene, body: Entity) -> bool {
+    return scene.is_entity_visible(body)
+        && scene
+            .get_entity_children(body)
+            .map(|children| children.iter().all(|child| scene.is_entity_visible(*child)))
+            .unwrap_or(true);
+}
+
+/// Whether a raycast from the camera towards `body` (at world position `body_pos`) hits some other body first
+fn is_body_occluded(state: &AppState, scene: &Scene, body: Entity, body_pos: &Point3<f64>) -> bool {
+    let start = Point3::from(state.camera.world_pos);
+    let to_body = body_pos - start;
+    let distance = to_body.magnitude();
+    if distance <= 0.0 {
+        return false;
+    }
+
+    let ray = Ray {
+        start,
+        direction: to_body / distance,
+    };
+
+    return match raycast(&ray, scene) {
+        Some(hit) => {
+            let hit_body = scene
+                .get_entity_from_index(hit.entity_index)
+                .map(|ent| scene.get_entity_ancestor(ent));
+
+            hit_body != Some(body) && (hit.hit_position_world - start).magnitude() < distance
+        }
+        None => false,
+    };
+}
+
 fn handle_pointer_on_scene(state: &mut AppState, scene: &mut Scene) {
     let end_world = state.camera.canvas_to_world(
         state.input.mouse_x,
@@ -1233,8 +3763,29 @@ fn handle_pointer_on_scene(state: &mut AppState, scene: &mut Scene) {
         direction: (end_world - start_world).normalize(),
     };
 
-    let mut entity =
-        raycast(&ray, &scene).and_then(|hit| scene.get_entity_from_index(hit.entity_index));
+    let hit = raycast(&ray, &scene).and_then(|hit| {
+        scene
+            .get_entity_from_index(hit.entity_index)
+            .map(|entity| (entity, hit.hit_position_world))
+    });
+    let mut entity = hit.map(|(entity, _)| entity);
+
+    // Bodies are usually much smaller than a pixel, so fall back to the point markers as they were last drawn
+    if entity.is_none() && state.show_points {
+        if let Some(points_mesh) = &scene.points_mesh {
+            if let Some(prim) = &points_mesh.borrow().dynamic_primitive {
+                entity = pick_point_marker(
+                    prim.get_pos_buffer(),
+                    prim.get_color_buffer(),
+                    state.input.mouse_x,
+                    state.input.mouse_y,
+                    state.canvas_width,
+                    state.canvas_height,
+                )
+                .and_then(|index| scene.get_entity_from_index(index));
+            }
+        }
+    }
 
     // If we hit e.g. Saturn's rings we want to select Saturn itself, as that will contain all the useful stuff
     if let Some(valid_entity) = entity {
@@ -1243,9 +3794,17 @@ fn handle_pointer_on_scene(state: &mut AppState, scene: &mut Scene) {
 
     let window = web_sys::window().unwrap();
     let doc = window.document().unwrap();
-    if let None = doc.pointer_lock_element() {
-        if state.input.m0 == ButtonState::Pressed {
+    if doc.pointer_lock_element().is_none() && !state.input.dragging_scene {
+        if state.input.m0 == ButtonState::Pressed || state.input.tapped {
             state.selection = entity;
+
+            // Only for clicks, as the hover ray is always just a dot under the cursor
+            state.last_pick_ray = Some(PickRay {
+                start: ray.start,
+                direction: ray.direction,
+                hit,
+                picked: entity,
+            });
         } else {
             state.hovered = entity;
         }
@@ -1,3 +1,4 @@
+pub mod command_palette;
 pub mod details_ui;
 mod interface_manager;
 
@@ -0,0 +1,163 @@
+use crate::managers::orbit::{BodyDescription, BodyType, OrbitalElements};
+use crate::utils::orbits::GRAVITATION_CONSTANT;
+use crate::utils::units::{Au, Deg, Jdn, Rad};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+/// Range of radii of generated asteroids, in Mm. Sampled log-uniformly so that most of them are small
+const BELT_RADIUS_RANGE: (f64, f64) = (0.0005, 0.05);
+
+/// Ranges that the orbital elements of the asteroids of a generated belt are uniformly sampled from. The other angles
+/// (longitude of the ascending node, argument of periapsis and mean anomaly) are sampled over the whole circle
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BeltDistribution {
+    pub seed: u64,
+    pub count: u32,
+    pub central_id: String,        // Body id of what the asteroids orbit
+    pub semi_major_axis: [f64; 2], // AU
+    pub eccentricity: [f64; 2],
+    pub inclination: [f64; 2], // Degrees
+}
+impl Default for BeltDistribution {
+    /// Roughly the main asteroid belt
+    fn default() -> Self {
+        return Self {
+            seed: 1,
+            count: 500,
+            central_id: String::from("10"),
+            semi_major_axis: [2.1, 3.3],
+            eccentricity: [0.0, 0.3],
+            inclination: [0.0, 20.0],
+        };
+    }
+}
+
+/// Samples `dist.count` asteroids orbiting a body of `central_mass` (kg), with orbital elements at `epoch`. Their
+/// periods follow from Kepler's third law. The same distribution (including its seed) always generates the same
+/// asteroids, with ids and names numbered after the seed
+pub fn generate_belt(
+    dist: &BeltDistribution,
+    central_mass: f64,
+    epoch: Jdn,
+) -> Vec<(BodyDescription, OrbitalElements)> {
+    let mut rng = StdRng::seed_from_u64(dist.seed);
+
+    // Swapped or empty ranges just produce their lower end
+    let mut sample = |range: &[f64; 2]| -> f64 {
+        if range[1] > range[0] {
+            return rng.gen_range(range[0]..range[1]);
+        }
+        return range[0];
+    };
+
+    let mut result = Vec::with_capacity(dist.count as usize);
+    for index in 0..dist.count {
+        let semi_major_axis = Au(sample(&dist.semi_major_axis)).to_Mm();
+        let eccentricity = sample(&dist.eccentricity).max(0.0).min(0.99);
+        let inclination = Deg(sample(&dist.inclination)).to_rad();
+        let long_asc_node = Rad(sample(&[0.0, std::f64::consts::TAU]));
+        let arg_periapsis = Rad(sample(&[0.0, std::f64::consts::TAU]));
+        let mean_anomaly_0 = Rad(sample(&[0.0, std::f64::consts::TAU]));
+        let radius = sample(&[BELT_RADIUS_RANGE.0.ln(), BELT_RADIUS_RANGE.1.ln()]).exp();
+
+        let period_s = 2.0
+            * std::f64::consts::PI
+            * (semi_major_axis.0.powi(3) / (GRAVITATION_CONSTANT * central_mass)).sqrt();
+
+        let body = BodyDescription {
+            id: Some(format!("belt_{}_{}", dist.seed, index)),
+            name: format!("Belt asteroid {}-{}", dist.seed, index),
+            body_type: BodyType::Asteroid,
+            radius: Some(radius as f32),
+            ..BodyDescription::default()
+        };
+        let elements = OrbitalElements {
+            ref_id: dist.central_id.clone(),
+            epoch,
+            semi_major_axis,
+            eccentricity,
+            inclination,
+            long_asc_node,
+            arg_periapsis,
+            mean_anomaly_0,
+            sidereal_orbit_period_days: period_s / 86400.0,
+        };
+        result.push((body, elements));
+    }
+
+    return result;
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+    use crate::utils::units::J2000_JDN;
+
+    #[wasm_bindgen_test]
+    pub fn belt_is_reproducible() {
+        let sun_mass = 1.989E30; // kg
+        let dist = BeltDistribution::default();
+        let first = generate_belt(&dist, sun_mass, J2000_JDN);
+        let second = generate_belt(&dist, sun_mass, J2000_JDN);
+        let other = generate_belt(
+            &BeltDistribution {
+                seed: 2,
+                ..dist.clone()
+            },
+            sun_mass,
+            J2000_JDN,
+        );
+
+        let get_elements = |e: &OrbitalElements| -> [f64; 7] {
+            return [
+                e.semi_major_axis.0,
+                e.eccentricity,
+                e.inclination.0,
+                e.long_asc_node.0,
+                e.arg_periapsis.0,
+                e.mean_anomaly_0.0,
+                e.sidereal_orbit_period_days,
+            ];
+        };
+
+        assert_eq!(first.len(), dist.count as usize);
+        for ((a_body, a), (b_body, b)) in first.iter().zip(second.iter()) {
+            assert_eq!(a_body.id, b_body.id);
+            assert_eq!(a_body.radius, b_body.radius);
+            assert_eq!(get_elements(a), get_elements(b));
+        }
+        assert!(first
+            .iter()
+            .zip(other.iter())
+            .any(|(a, b)| get_elements(&a.1) != get_elements(&b.1)));
+        assert_ne!(first[0].0.id, other[0].0.id);
+
+        // Everything within the ranges, and around the Sun
+        let au = Au(1.0).to_Mm().0;
+        for (body, elements) in &first {
+            assert_eq!(body.body_type, BodyType::Asteroid);
+            assert_eq!(elements.ref_id, "10");
+            assert!(elements.semi_major_axis.0 >= 2.1 * au - 1E-6);
+            assert!(elements.semi_major_axis.0 <= 3.3 * au + 1E-6);
+            assert!(elements.eccentricity >= 0.0 && elements.eccentricity <= 0.3);
+            assert!(elements.inclination.0 >= 0.0 && elements.inclination.0 <= 20_f64.to_radians());
+        }
+
+        // Kepler's third law: At 1 AU we'd have a year
+        let one_au = generate_belt(
+            &BeltDistribution {
+                count: 1,
+                semi_major_axis: [1.0, 1.0],
+                ..dist.clone()
+            },
+            sun_mass,
+            J2000_JDN,
+        );
+        let days = one_au[0].1.sidereal_orbit_period_days;
+        assert!((days / 365.25 - 1.0).abs() < 0.01, "{}", days);
+    }
+}
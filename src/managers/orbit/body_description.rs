@@ -20,6 +20,18 @@ impl Default for BodyType {
     }
 }
 
+/// How a scene moves a body over time
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Copy)]
+pub enum BodyMotion {
+    Dynamical, // Starts from its state vector at the scene epoch, then gets integrated by the physics system
+    Keplerian, // Follows the fixed ellipse of its orbital elements around its central body
+}
+impl Default for BodyMotion {
+    fn default() -> Self {
+        BodyMotion::Dynamical
+    }
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct BodyDescription {
     pub id: Option<String>,
@@ -50,9 +62,16 @@ pub struct BodyDescription {
     pub material: Option<String>,
     #[serde(default)]
     pub material_params: Option<HashMap<String, String>>,
+
+    #[serde(default)]
+    pub atmosphere_thickness: Option<f32>, // Fraction of the radius that the atmosphere glow extends past the surface
+    #[serde(default)]
+    pub atmosphere_color: Option<String>, // sRGB hex color of the atmosphere glow, with optional alpha for intensity
+    #[serde(default)]
+    pub light_color: Option<String>, // sRGB hex color of the light of light sources. White if missing
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct BodyInstanceDescription {
     // IDs
     pub name: Option<String>,
@@ -67,13 +86,30 @@ pub struct BodyInstanceDescription {
     // Physics
     pub linvel: Option<Vector3<f64>>,
     pub angvel: Option<Vector3<f64>>,
+    pub motion: Option<BodyMotion>,
 
     // BodyDescription overrides
     pub mass: Option<f32>,
     pub radius: Option<f32>,
     pub brightness: Option<f32>,
+    pub light_color: Option<String>,
     pub mesh: Option<String>,
     pub mesh_params: Option<HashMap<String, String>>,
     pub material: Option<String>,
     pub material_params: Option<HashMap<String, String>>,
 }
+
+// Test interface
+impl BodyDescription {
+    /// Body `id` called `name`, of radius `radius` (Mm). Of type Other so that it doesn't get a generated
+    /// sphere mesh, as tests don't have GL
+    pub fn new_test_body(id: &str, name: &str, radius: f32) -> Self {
+        return BodyDescription {
+            id: Some(id.to_owned()),
+            name: name.to_owned(),
+            body_type: BodyType::Other,
+            radius: Some(radius),
+            ..BodyDescription::default()
+        };
+    }
+}
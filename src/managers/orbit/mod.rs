@@ -1,9 +1,13 @@
+mod belt_generator;
 mod body_description;
 mod orbit_manager;
+mod orbit_parse_error;
 mod orbital_elements;
 mod state_vector;
 
+pub use belt_generator::*;
 pub use body_description::*;
 pub use orbit_manager::OrbitManager;
+pub use orbit_parse_error::OrbitParseError;
 pub use orbital_elements::*;
 pub use state_vector::*;
@@ -1,4 +1,6 @@
-use crate::managers::orbit::{BodyDescription, OrbitalElements, StateVector};
+use crate::managers::orbit::{
+    parse_mpc_elements, BodyDescription, OrbitParseError, OrbitalElements, StateVector,
+};
 use crate::utils::log::*;
 use std::collections::HashMap;
 
@@ -18,28 +20,35 @@ impl OrbitManager {
         return new_man;
     }
 
-    pub fn load_database_file(&mut self, url: &str, content_type: &str, text: &str) {
+    /// Parses a database file of `content_type` ("body_database", "vectors_database", "elements_database" or
+    /// "mpc_database"). Body databases are named after the file stem of `url`, and are merged into any existing
+    /// database of the same name so that user catalogs can add to (or override bodies of) the default ones.
+    ///
+    /// MPC databases are files of orbital elements in the Minor Planet Center format, like MPCORB.DAT. Their bodies are
+    /// merged into a body database like the above, and their elements replace the ones we have for those bodies
+    pub fn load_database_file(
+        &mut self,
+        url: &str,
+        content_type: &str,
+        text: &str,
+    ) -> Result<(), OrbitParseError> {
         match content_type {
             "body_database" => {
                 let mut parsed_data: HashMap<String, BodyDescription> =
-                    serde_json::de::from_str(text)
-                        .map_err(|e| format!("Database deserialization error:\n{}", e).to_owned())
-                        .unwrap();
+                    serde_json::de::from_str(text).map_err(|e| OrbitParseError::from_json(&e))?;
 
                 // TODO: Do I need the ids in the bodies as well?
                 for (key, val) in parsed_data.iter_mut() {
                     val.id = Some(key.clone());
                 }
 
-                let database_name: String = std::path::Path::new(url)
-                    .file_stem()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .to_owned();
+                let database_name = get_database_name(url)?;
 
                 let num_parsed = parsed_data.len();
-                self.bodies.insert(database_name, parsed_data);
+                self.bodies
+                    .entry(database_name)
+                    .or_insert_with(HashMap::new)
+                    .extend(parsed_data);
 
                 info!(
                     LogCat::Orbit,
@@ -47,9 +56,8 @@ impl OrbitManager {
                 );
             }
             "vectors_database" => {
-                let parsed_data: HashMap<String, Vec<StateVector>> = serde_json::de::from_str(text)
-                    .map_err(|e| format!("Database deserialization error:\n{}", e).to_owned())
-                    .unwrap();
+                let parsed_data: HashMap<String, Vec<StateVector>> =
+                    serde_json::de::from_str(text).map_err(|e| OrbitParseError::from_json(&e))?;
 
                 let num_parsed = parsed_data.len();
                 self.state_vectors = parsed_data;
@@ -61,9 +69,7 @@ impl OrbitManager {
             }
             "elements_database" => {
                 let parsed_data: HashMap<String, Vec<OrbitalElements>> =
-                    serde_json::de::from_str(text)
-                        .map_err(|e| format!("Database deserialization error:\n{}", e).to_owned())
-                        .unwrap();
+                    serde_json::de::from_str(text).map_err(|e| OrbitParseError::from_json(&e))?;
 
                 let num_parsed = parsed_data.len();
                 self.osc_elements = parsed_data;
@@ -73,14 +79,52 @@ impl OrbitManager {
                     "Parsed {} orbital elements from database '{}'", num_parsed, url
                 );
             }
-            _ => {
-                error!(
+            "mpc_database" => {
+                let database_name = get_database_name(url)?;
+
+                // MPCORB.DAT starts with a text header, that ends with a line of dashes
+                let mut lines: Vec<(usize, &str)> = text.lines().enumerate().collect();
+                if let Some(dashes) = lines.iter().position(|(_, line)| line.starts_with("-----")) {
+                    lines.drain(..=dashes);
+                }
+
+                let mut bodies = HashMap::new();
+                let mut elements = HashMap::new();
+                for (index, line) in lines {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+
+                    let (body, body_elements) = parse_mpc_elements(line).map_err(|e| match e {
+                        OrbitParseError::BadMpcLine { field, .. } => OrbitParseError::BadMpcLine {
+                            line: index + 1,
+                            field,
+                        },
+                        other => other,
+                    })?;
+                    let id = body.id.clone().unwrap();
+                    elements.insert(id.clone(), vec![body_elements]);
+                    bodies.insert(id, body);
+                }
+
+                let num_parsed = bodies.len();
+                self.bodies
+                    .entry(database_name)
+                    .or_insert_with(HashMap::new)
+                    .extend(bodies);
+                self.osc_elements.extend(elements);
+
+                info!(
                     LogCat::Orbit,
-                    "Unexpected database content type '{}' with url '{}'", content_type, url
+                    "Parsed {} MPC orbital elements from database '{}'", num_parsed, url
                 );
-                return;
+            }
+            _ => {
+                return Err(OrbitParseError::UnknownContentType(content_type.to_owned()));
             }
         }
+
+        return Ok(());
     }
 
     pub fn get_state_vectors(&self) -> &HashMap<String, Vec<StateVector>> {
@@ -105,6 +149,10 @@ impl OrbitManager {
         return Ok(body);
     }
 
+    pub fn has_database(&self, db_name: &str) -> bool {
+        return self.bodies.contains_key(db_name);
+    }
+
     pub fn get_n_bodies(&self, db_name: &str, limit: Option<usize>) -> Vec<&BodyDescription> {
         let limit_num = limit.unwrap_or(std::usize::MAX);
 
@@ -121,3 +169,140 @@ impl OrbitManager {
         return result;
     }
 }
+
+/// Name of the body database loaded from `url`, which is its file stem
+fn get_database_name(url: &str) -> Result<String, OrbitParseError> {
+    return std::path::Path::new(url)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .map(|s| s.to_owned())
+        .ok_or(OrbitParseError::BadDatabaseName(url.to_owned()));
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    pub fn user_body_database_merges_and_rejects_malformed() {
+        let mut orbit_man = OrbitManager::new();
+        orbit_man
+            .load_database_file(
+                "public/database/major_bodies.json",
+                "body_database",
+                r#"{"10": {"name": "Sun", "type": "star"}}"#,
+            )
+            .unwrap();
+
+        // Picked files are named after their file name, so this one gets merged into the database above
+        orbit_man
+            .load_database_file(
+                "major_bodies.json",
+                "body_database",
+                r#"{"9001": {"name": "Vulcan", "type": "planet", "radius": 1.0}}"#,
+            )
+            .unwrap();
+        assert_eq!(
+            orbit_man.get_body("major_bodies", "10").unwrap().name,
+            "Sun"
+        );
+        let vulcan = orbit_man.get_body("major_bodies", "9001").unwrap();
+        assert_eq!(vulcan.name, "Vulcan");
+        assert_eq!(vulcan.id.as_deref(), Some("9001"));
+
+        // Malformed files are reported instead of panicking, and don't touch what we already have
+        assert!(orbit_man
+            .load_database_file("catalog.json", "body_database", "{\"1\": ")
+            .is_err());
+        assert!(orbit_man
+            .load_database_file("catalog.json", "body_database", "[1, 2, 3]")
+            .is_err());
+        assert!(orbit_man.get_body("catalog", "1").is_err());
+        assert_eq!(orbit_man.get_n_bodies("major_bodies", None).len(), 2);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn malformed_databases_report_what_went_wrong() {
+        let mut orbit_man = OrbitManager::new();
+        let mut load = |content_type: &str, text: &str| {
+            return orbit_man
+                .load_database_file("public/database/test.json", content_type, text)
+                .unwrap_err();
+        };
+
+        assert_eq!(
+            load("body_database", "{\n\"10\": {\"name\": \"Sun\",, }}"),
+            OrbitParseError::Syntax {
+                line: 2,
+                column: 22
+            }
+        );
+        assert_eq!(
+            load("body_database", r#"{"10": {"name": "Sun""#),
+            OrbitParseError::UnexpectedEnd
+        );
+        assert_eq!(
+            load("body_database", r#"{"10": {"type": "star"}}"#),
+            OrbitParseError::MissingField(String::from("name"))
+        );
+        match load(
+            "vectors_database",
+            r#"{"399": [[2451545.0, "far", 0, 0, 0, 0, 0]]}"#,
+        ) {
+            OrbitParseError::BadValue { line, message, .. } => {
+                assert_eq!(line, 1);
+                assert!(message.starts_with("invalid type: string"), "{}", message);
+            }
+            other => panic!("Unexpected error {:?}", other),
+        }
+        assert_eq!(
+            load("tle_database", "{}"),
+            OrbitParseError::UnknownContentType(String::from("tle_database"))
+        );
+
+        // Messages say where things went wrong, without repeating the file
+        let message = load(
+            "elements_database",
+            &format!("{{\"1\": [{}]}}", "1, ".repeat(1000)),
+        )
+        .to_string();
+        assert!(message.len() < 100, "{}", message);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn load_mpc_database() {
+        use crate::managers::orbit::orbital_elements::tests::{CERES_MPC, PALLAS_MPC};
+
+        let mut orbit_man = OrbitManager::new();
+        let text = format!(
+            "MINOR PLANET CENTER ORBIT DATABASE (MPCORB)\n\nDes'n     H     G   Epoch     M\n{}\n{}\n\n{}\n",
+            "-".repeat(160),
+            CERES_MPC,
+            PALLAS_MPC
+        );
+        orbit_man
+            .load_database_file("MPCORB.DAT", "mpc_database", &text)
+            .unwrap();
+
+        assert_eq!(
+            orbit_man.get_body("MPCORB", "a0000002").unwrap().name,
+            "Pallas"
+        );
+        assert_eq!(orbit_man.get_osc_elements()["a0000001"].len(), 1);
+
+        // Errors point at the line in the file
+        let broken = format!("{}\n{}", CERES_MPC, &PALLAS_MPC[..47]);
+        assert_eq!(
+            orbit_man
+                .load_database_file("broken.dat", "mpc_database", &broken)
+                .unwrap_err(),
+            OrbitParseError::BadMpcLine {
+                line: 2,
+                field: String::from("longitude of the ascending node")
+            }
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use std::fmt;
+
+/// Why OrbitManager couldn't load a database file. Positions are 1-based, like in text editors
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrbitParseError {
+    Syntax {
+        line: usize,
+        column: usize,
+    }, // Not valid JSON
+    UnexpectedEnd, // Valid JSON so far, but the file ends early (e.g. a truncated download)
+    MissingField(String), // A body, state vector or set of elements lacks a required field
+    BadValue {
+        line: usize,
+        column: usize,
+        message: String, // From serde, e.g. "invalid type: string \"abc\", expected f64"
+    },
+    BadDatabaseName(String), // Url we couldn't name a body database after
+    UnknownContentType(String),
+    BadMpcLine {
+        line: usize,
+        field: String, // Which MPC column we couldn't parse, e.g. "epoch"
+    },
+}
+impl OrbitParseError {
+    /// Classifies a serde_json error, keeping just what went wrong and where instead of any of the text around it
+    pub fn from_json(err: &serde_json::Error) -> Self {
+        let (line, column) = (err.line(), err.column());
+
+        return match err.classify() {
+            serde_json::error::Category::Syntax => OrbitParseError::Syntax { line, column },
+            serde_json::error::Category::Eof | serde_json::error::Category::Io => {
+                OrbitParseError::UnexpectedEnd
+            }
+            serde_json::error::Category::Data => {
+                // serde_json only appends the position to the message of the underlying serde error
+                let full = err.to_string();
+                let message = full
+                    .strip_suffix(&format!(" at line {} column {}", line, column))
+                    .unwrap_or(&full);
+
+                match message
+                    .strip_prefix("missing field `")
+                    .and_then(|rest| rest.strip_suffix('`'))
+                {
+                    Some(field) => OrbitParseError::MissingField(field.to_owned()),
+                    None => OrbitParseError::BadValue {
+                        line,
+                        column,
+                        message: message.to_owned(),
+                    },
+                }
+            }
+        };
+    }
+}
+impl fmt::Display for OrbitParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            OrbitParseError::Syntax { line, column } => {
+                write!(f, "Invalid JSON at line {} column {}", line, column)
+            }
+            OrbitParseError::UnexpectedEnd => write!(f, "File ended unexpectedly"),
+            OrbitParseError::MissingField(field) => write!(f, "Missing field '{}'", field),
+            OrbitParseError::BadValue {
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "Bad value at line {} column {}: {}",
+                line, column, message
+            ),
+            OrbitParseError::BadDatabaseName(url) => {
+                write!(f, "Failed to get a database name from url '{}'", url)
+            }
+            OrbitParseError::UnknownContentType(content_type) => {
+                write!(f, "Unexpected database content type '{}'", content_type)
+            }
+            OrbitParseError::BadMpcLine { line, field } => {
+                write!(f, "Bad {} in MPC elements at line {}", field, line)
+            }
+        };
+    }
+}
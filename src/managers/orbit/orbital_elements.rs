@@ -1,5 +1,7 @@
+use crate::managers::orbit::{BodyDescription, BodyType, OrbitParseError};
+use crate::utils::units::{date_to_julian_date_number, Au, Deg, Jdn, Mm, Rad};
+use chrono::{TimeZone, Utc};
 use serde::{Deserialize, Serialize};
-use crate::utils::units::{Jdn, Mm, Rad};
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct OrbitalElements {
@@ -27,3 +29,239 @@ pub struct OrbitalElements {
     #[serde(rename = "p")]
     pub sidereal_orbit_period_days: f64,
 }
+
+/// Mean motion in degrees per day of a body in a circular orbit of 1 AU around the Sun (the Gaussian constant)
+const GAUSSIAN_MEAN_MOTION_DEG: f64 = 0.9856076686;
+
+/// Albedo we assume for MPC bodies when estimating their size from their absolute magnitude, typical of main belt
+/// asteroids
+const MPC_DEFAULT_ALBEDO: f64 = 0.14;
+
+/// Unpacks the number of a numbered minor planet in the MPC packed format (e.g. "00001" or "A0345" for 100345)
+fn unpack_mpc_number(packed: &str) -> Option<u32> {
+    let mut chars = packed.chars();
+    let first = chars.next()?;
+    let rest = chars.as_str();
+    if packed.len() != 5 || !rest.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let high = match first {
+        '0'..='9' => first as u32 - '0' as u32,
+        'A'..='Z' => first as u32 - 'A' as u32 + 10,
+        'a'..='z' => first as u32 - 'a' as u32 + 36,
+        _ => return None,
+    };
+    return Some(high * 10000 + rest.parse::<u32>().ok()?);
+}
+
+/// Unpacks an epoch in the MPC packed date format (e.g. "K205V" for 2020-05-31) into the JDN of 0h of that day
+fn unpack_mpc_epoch(packed: &str) -> Option<Jdn> {
+    let chars: Vec<char> = packed.chars().collect();
+    if chars.len() != 5 {
+        return None;
+    }
+
+    let century = match chars[0] {
+        'I' => 1800,
+        'J' => 1900,
+        'K' => 2000,
+        _ => return None,
+    };
+    let year = century + chars[1].to_digit(10)? * 10 + chars[2].to_digit(10)?;
+
+    // Months go 1-9 then A-C, and days 1-9 then A-V
+    let month = chars[3].to_digit(13)?;
+    let day = chars[4].to_digit(32)?;
+
+    let date = Utc
+        .with_ymd_and_hms(year as i32, month, day, 0, 0, 0)
+        .single()?;
+    return Some(date_to_julian_date_number(&date));
+}
+
+/// Parses one line of an MPC orbital element file (like MPCORB.DAT), into a body and its heliocentric J2000 ecliptic
+/// elements. See https://minorplanetcenter.net/iau/info/MPOrbitFormat.html for the column layout.
+///
+/// Returns the elements separately, as BodyDescription doesn't hold any. Numbered bodies get ids like the ones in
+/// asteroids.json (e.g. "a0000001"), so that they can replace the default ones, and others keep their packed
+/// provisional designation. MPC lines have no size, so the radius is estimated from the absolute magnitude
+pub fn parse_mpc_elements(
+    line: &str,
+) -> Result<(BodyDescription, OrbitalElements), OrbitParseError> {
+    // 1-based, inclusive column ranges like in the MPC documentation
+    let column = |name: &str, start: usize, end: usize| -> Result<&str, OrbitParseError> {
+        return line
+            .get(start - 1..end.min(line.len()))
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .ok_or(OrbitParseError::BadMpcLine {
+                line: 1,
+                field: name.to_owned(),
+            });
+    };
+    let number = |name: &str, start: usize, end: usize| -> Result<f64, OrbitParseError> {
+        return column(name, start, end)?
+            .parse::<f64>()
+            .map_err(|_| OrbitParseError::BadMpcLine {
+                line: 1,
+                field: name.to_owned(),
+            });
+    };
+
+    let designation = column("designation", 1, 7)?;
+    let epoch = unpack_mpc_epoch(column("epoch", 21, 25)?).ok_or(OrbitParseError::BadMpcLine {
+        line: 1,
+        field: String::from("epoch"),
+    })?;
+    let mean_anomaly = number("mean anomaly", 27, 35)?;
+    let arg_periapsis = number("argument of perihelion", 38, 46)?;
+    let long_asc_node = number("longitude of the ascending node", 49, 57)?;
+    let inclination = number("inclination", 60, 68)?;
+    let eccentricity = number("eccentricity", 71, 79)?;
+    let mean_motion = number("mean motion", 81, 91)?;
+    if mean_motion <= 0.0 {
+        return Err(OrbitParseError::BadMpcLine {
+            line: 1,
+            field: String::from("mean motion"),
+        });
+    }
+
+    // Some exports leave out the semi-major axis, but it follows from the mean motion anyway
+    let semi_major_axis = number("semi-major axis", 93, 103)
+        .unwrap_or_else(|_| (GAUSSIAN_MEAN_MOTION_DEG / mean_motion).powf(2.0 / 3.0));
+
+    let id = match unpack_mpc_number(designation) {
+        Some(number) => format!("a{:07}", number),
+        None => designation.to_owned(),
+    };
+
+    // Readable designations of numbered bodies are like "(1) Ceres"
+    let name = match column("readable designation", 167, 194) {
+        Ok(readable) => match readable.strip_prefix('(').and_then(|s| s.split_once(") ")) {
+            Some((_, name)) => name.to_owned(),
+            None => readable.to_owned(),
+        },
+        Err(_) => designation.to_owned(),
+    };
+
+    // Diameter in km from the absolute magnitude, see https://en.wikipedia.org/wiki/Absolute_magnitude#Solar_System_bodies_(H)
+    let magnitude = number("absolute magnitude", 9, 13).ok();
+    let radius =
+        magnitude.map(|h| 1329.0 / MPC_DEFAULT_ALBEDO.sqrt() * 10.0_f64.powf(-h / 5.0) / 2000.0);
+
+    let body = BodyDescription {
+        id: Some(id),
+        name,
+        body_type: BodyType::Asteroid,
+        magnitude: magnitude.map(|h| h as f32),
+        radius: radius.map(|r| r as f32),
+        ..BodyDescription::default()
+    };
+    let elements = OrbitalElements {
+        ref_id: String::from("10"),
+        epoch,
+        semi_major_axis: Au(semi_major_axis).to_Mm(),
+        eccentricity,
+        inclination: Deg(inclination).to_rad(),
+        long_asc_node: Deg(long_asc_node).to_rad(),
+        arg_periapsis: Deg(arg_periapsis).to_rad(),
+        mean_anomaly_0: Deg(mean_anomaly).to_rad(),
+        sidereal_orbit_period_days: 360.0 / mean_motion,
+    };
+
+    return Ok((body, elements));
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+
+    pub const CERES_MPC: &str = "00001    3.34  0.12 K205V 162.68631   73.73161   80.28698   10.58862  0.0775571  0.21406009   2.7676569  0 MPO492748  6751 115 1801-2019 0.60 M-v 30h Williams   0000 (1) Ceres                   20190915";
+    pub const PALLAS_MPC: &str = "00002    4.13  0.11 K205V 144.97567  310.20237  173.02474   34.83293  0.2299723  0.21334458   2.7738415  0 MPO492748  8027 109 1804-2019 0.58 M-c 28k Goffin     0000 (2) Pallas                  20190812";
+
+    #[wasm_bindgen_test]
+    pub fn parse_mpc_lines() {
+        let (ceres, elements) = parse_mpc_elements(CERES_MPC).unwrap();
+        assert_eq!(ceres.id.as_deref(), Some("a0000001"));
+        assert_eq!(ceres.name, "Ceres");
+        assert_eq!(ceres.body_type, BodyType::Asteroid);
+        assert_eq!(elements.ref_id, "10");
+        assert_eq!(elements.epoch, Jdn(2459000.5));
+        assert!((elements.semi_major_axis.to_AU().0 - 2.7676569).abs() < 1E-9);
+        assert!((elements.eccentricity - 0.0775571).abs() < 1E-9);
+        assert!((elements.inclination.to_deg().0 - 10.58862).abs() < 1E-9);
+        assert!((elements.sidereal_orbit_period_days - 1681.8).abs() < 0.1);
+
+        // The semi-major axis we'd get from the mean motion matches what the MPC lists
+        let (pallas, elements) = parse_mpc_elements(PALLAS_MPC).unwrap();
+        let without_a = format!(
+            "{}{}{}",
+            &PALLAS_MPC[..92],
+            " ".repeat(11),
+            &PALLAS_MPC[103..]
+        );
+        let (_, derived) = parse_mpc_elements(&without_a).unwrap();
+        assert_eq!(pallas.name, "Pallas");
+        assert!((elements.eccentricity - 0.2299723).abs() < 1E-9);
+        assert!((elements.semi_major_axis.to_AU().0 - 2.7738415).abs() < 1E-9);
+        assert!((derived.semi_major_axis.to_AU().0 - 2.7738415).abs() < 1E-4);
+
+        // Sized from the absolute magnitude, roughly like the real ones (939 and 512 km)
+        let ceres_km = ceres.radius.unwrap() * 2000.0;
+        assert!(ceres_km > 600.0 && ceres_km < 1100.0, "{}", ceres_km);
+        assert!(pallas.radius.unwrap() < ceres.radius.unwrap());
+
+        assert_eq!(unpack_mpc_number("A0345"), Some(100345));
+        assert_eq!(unpack_mpc_epoch("J9611"), Some(Jdn(2450083.5)));
+        assert!(unpack_mpc_epoch("K20D1").is_none());
+
+        match parse_mpc_elements(&CERES_MPC.replace("0.0775571", "0.07x5571")) {
+            Err(OrbitParseError::BadMpcLine { field, .. }) => assert_eq!(field, "eccentricity"),
+            other => panic!("Unexpected result {:?}", other),
+        }
+        assert!(parse_mpc_elements("00001    3.34").is_err());
+    }
+
+    #[wasm_bindgen_test]
+    pub fn mpc_body_is_placed_from_its_own_epoch() {
+        use crate::components::TransformComponent;
+        use crate::managers::scene::SceneBuilder;
+        use crate::managers::ResourceManager;
+        use crate::utils::units::J2000_JDN;
+        use na::Vector3;
+
+        // Heliocentric ecliptic position of Ceres at J2000 from JPL Horizons (as in state_vectors.json), in Mm
+        let horizons = Vector3::new(-362236.9184863835, 106697.198028462, 69992.18235189133);
+
+        let (ceres, elements) = parse_mpc_elements(CERES_MPC).unwrap();
+        let sun = BodyDescription {
+            mass: Some(1.989E30),
+            ..BodyDescription::new_test_body("10", "Sun", 695.7)
+        };
+        let ceres = BodyDescription {
+            body_type: BodyType::Other,
+            ..ceres
+        };
+
+        let mut res_man = ResourceManager::new();
+        let mut builder = SceneBuilder::new("ceres", &mut res_man).epoch(J2000_JDN);
+        let sun = builder.add_body(sun).unwrap();
+        let ceres = builder.add_body(ceres).unwrap();
+        builder.set_orbit(ceres, sun, &elements);
+        let scene = builder.build();
+        let pos = scene
+            .get_component::<TransformComponent>(ceres)
+            .unwrap()
+            .get_local_transform()
+            .trans;
+
+        // Going back 20 years from the 2020 epoch of the elements on an unperturbed orbit leaves it a couple degrees
+        // off, while placing it as if the elements were at J2000 instead would put it over 100 degrees away
+        let error = (pos - horizons).magnitude();
+        assert!(error < 0.05 * horizons.magnitude(), "{:?}", pos);
+    }
+}
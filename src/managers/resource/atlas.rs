@@ -0,0 +1,574 @@
+use crate::managers::resource::intermediate_mesh::create_primitive_vao;
+use crate::managers::resource::load_texture_from_bytes;
+use crate::managers::resource::material::Material;
+use crate::managers::resource::texture::{Texture, TextureSource, TextureUnit};
+use crate::managers::ResourceManager;
+use crate::utils::gl::GL;
+use crate::utils::log::*;
+use crate::GLCTX;
+use glow::*;
+use na::Vector2;
+use std::{cell::RefCell, rc::Rc};
+
+/// Width and height of each atlas texture, in pixels
+pub const ATLAS_SIZE: u32 = 1024;
+
+/// Textures larger than this on either side are left alone, as they'd fill an atlas on their own anyway
+pub const ATLAS_MAX_TEXTURE_SIZE: u32 = 256;
+
+/// Pixels around each texture in an atlas that repeat its edges, so that linear filtering doesn't bleed in the
+/// neighboring textures
+pub const ATLAS_PADDING: u32 = 2;
+
+/// Where a texture ended up in the atlases built by pack_atlas
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtlasPlacement {
+    pub atlas: usize, // Which of the atlases
+    pub x: u32,       // Pixel of the atlas with the texture's first pixel, past the padding
+    pub y: u32,
+}
+
+/// Packs textures of `sizes` (width, height) into as few `atlas_size` atlases as possible, in rows (shelves) of
+/// textures sorted by height. Returns the placement for each size in the same order, or None for textures that are
+/// too large to fit an atlas even on their own
+pub fn pack_atlas(
+    sizes: &[(u32, u32)],
+    atlas_size: u32,
+    padding: u32,
+) -> Vec<Option<AtlasPlacement>> {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by(|a, b| sizes[*b].1.cmp(&sizes[*a].1).then(a.cmp(b)));
+
+    let mut result: Vec<Option<AtlasPlacement>> = vec![None; sizes.len()];
+
+    let mut atlas: usize = 0;
+    let mut shelf_x: u32 = 0;
+    let mut shelf_y: u32 = 0;
+    let mut shelf_height: u32 = 0;
+    for index in order {
+        let width = sizes[index].0 + 2 * padding;
+        let height = sizes[index].1 + 2 * padding;
+        if width > atlas_size || height > atlas_size {
+            continue;
+        }
+
+        // Next shelf
+        if shelf_x + width > atlas_size {
+            shelf_x = 0;
+            shelf_y += shelf_height;
+            shelf_height = 0;
+        }
+
+        // Next atlas
+        if shelf_y + height > atlas_size {
+            atlas += 1;
+            shelf_x = 0;
+            shelf_y = 0;
+            shelf_height = 0;
+        }
+
+        result[index] = Some(AtlasPlacement {
+            atlas,
+            x: shelf_x + padding,
+            y: shelf_y + padding,
+        });
+
+        shelf_x += width;
+        shelf_height = shelf_height.max(height);
+    }
+
+    return result;
+}
+
+/// Like pack_atlas, but never puts textures of different `srgb` flags in the same atlas, as each atlas is sampled
+/// either as sRGB or as linear. Also returns whether each atlas holds sRGB textures
+pub fn pack_atlas_by_srgb(
+    sizes: &[(u32, u32)],
+    srgb: &[bool],
+    atlas_size: u32,
+    padding: u32,
+) -> (Vec<Option<AtlasPlacement>>, Vec<bool>) {
+    let mut placements: Vec<Option<AtlasPlacement>> = vec![None; sizes.len()];
+    let mut atlas_srgb: Vec<bool> = Vec::new();
+
+    for group_srgb in &[false, true] {
+        let group: Vec<usize> = (0..sizes.len())
+            .filter(|index| srgb[*index] == *group_srgb)
+            .collect();
+        let group_sizes: Vec<(u32, u32)> = group.iter().map(|index| sizes[*index]).collect();
+
+        let first_atlas = atlas_srgb.len();
+        for (index, placement) in group
+            .iter()
+            .zip(pack_atlas(&group_sizes, atlas_size, padding))
+        {
+            if let Some(placement) = placement {
+                let atlas = first_atlas + placement.atlas;
+                placements[*index] = Some(AtlasPlacement { atlas, ..placement });
+                if atlas >= atlas_srgb.len() {
+                    atlas_srgb.resize(atlas + 1, *group_srgb);
+                }
+            }
+        }
+    }
+
+    return (placements, atlas_srgb);
+}
+
+/// Returns the (offset, scale) that takes UVs of a `width` x `height` texture into its `placement` within an
+/// `atlas_size` atlas, as in new_uv = offset + uv * scale
+pub fn get_atlas_uv_transform(
+    placement: &AtlasPlacement,
+    width: u32,
+    height: u32,
+    atlas_size: u32,
+) -> (Vector2<f32>, Vector2<f32>) {
+    let size = atlas_size as f32;
+    return (
+        Vector2::new(placement.x as f32 / size, placement.y as f32 / size),
+        Vector2::new(width as f32 / size, height as f32 / size),
+    );
+}
+
+/// Copies the RGBA8 `pixels` of a `width` x `height` texture into `atlas` at `placement`, repeating its edge pixels
+/// over `padding` pixels around it
+pub fn blit_into_atlas(
+    atlas: &mut [u8],
+    atlas_size: u32,
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    placement: &AtlasPlacement,
+    padding: u32,
+) {
+    let padding = padding as i64;
+    for atlas_row in -padding..(height as i64 + padding) {
+        let src_row = atlas_row.max(0).min(height as i64 - 1) as u32;
+
+        for atlas_col in -padding..(width as i64 + padding) {
+            let src_col = atlas_col.max(0).min(width as i64 - 1) as u32;
+
+            let dst_x = (placement.x as i64 + atlas_col) as u32;
+            let dst_y = (placement.y as i64 + atlas_row) as u32;
+
+            let src = ((src_row * width + src_col) * 4) as usize;
+            let dst = ((dst_y * atlas_size + dst_x) * 4) as usize;
+            atlas[dst..dst + 4].copy_from_slice(&pixels[src..src + 4]);
+        }
+    }
+}
+
+/// Whether `uvs` stay within [0, 1], as wrapping can't work within an atlas
+pub fn uvs_fit_atlas(uvs: &[Vector2<f32>]) -> bool {
+    return uvs
+        .iter()
+        .all(|uv| uv.x >= 0.0 && uv.x <= 1.0 && uv.y >= 0.0 && uv.y <= 1.0);
+}
+
+/// Moves `uvs` into the spot of their texture in an atlas, given the (offset, scale) from get_atlas_uv_transform
+pub fn remap_uvs_into_atlas(uvs: &mut [Vector2<f32>], offset: Vector2<f32>, scale: Vector2<f32>) {
+    for uv in uvs.iter_mut() {
+        *uv = offset + uv.component_mul(&scale);
+    }
+}
+
+impl ResourceManager {
+    /// Packs small textures into shared atlas textures, and moves the UVs of the mesh primitives that use them into
+    /// their spot in the atlas, so that we don't have to bind a different texture for each of those primitives.
+    ///
+    /// Only materials with a single texture are considered, as all texture units share the same UVs. The texture must
+    /// be RGBA8 pixels we still have around, at most ATLAS_MAX_TEXTURE_SIZE on each side, and every primitive that
+    /// uses the material by default must keep its UVs within [0, 1], as wrapping can't work within an atlas. Materials
+    /// that no primitive uses by default (e.g. the ones only used as overrides) are left alone too, as we can't move
+    /// the UVs of whatever they end up on. Everything else keeps its own texture. sRGB textures (e.g. base colors) and
+    /// linear ones (e.g. normal maps) go into separate atlases. Atlases use linear filtering without mipmaps.
+    /// Safe to call again after loading more resources: Textures that are already in an atlas are skipped
+    pub fn build_atlas(&mut self) {
+        let mut candidates: Vec<Rc<RefCell<Texture>>> = Vec::new();
+        let mut candidate_materials: Vec<(Rc<RefCell<Material>>, TextureUnit, usize)> = Vec::new();
+
+        let mut material_names: Vec<&String> = self.materials.keys().collect();
+        material_names.sort(); // So that we build the same atlases every time
+        for name in material_names {
+            let mat = &self.materials[name];
+            let (unit, tex) = match mat.borrow().get_textures().iter().next() {
+                Some((unit, tex)) if mat.borrow().get_textures().len() == 1 => (*unit, tex.clone()),
+                _ => continue,
+            };
+
+            if !is_texture_atlasable(&tex.borrow()) || !self.material_uvs_fit_atlas(mat) {
+                continue;
+            }
+
+            let tex_index = match candidates.iter().position(|other| Rc::ptr_eq(other, &tex)) {
+                Some(index) => index,
+                None => {
+                    candidates.push(tex);
+                    candidates.len() - 1
+                }
+            };
+            candidate_materials.push((mat.clone(), unit, tex_index));
+        }
+
+        let sizes: Vec<(u32, u32)> = candidates
+            .iter()
+            .map(|tex| (tex.borrow().width, tex.borrow().height))
+            .collect();
+        let srgb: Vec<bool> = candidates.iter().map(|tex| tex.borrow().srgb).collect();
+        let (placements, atlas_srgb) = pack_atlas_by_srgb(&sizes, &srgb, ATLAS_SIZE, ATLAS_PADDING);
+
+        // Not worth it for an atlas to hold a single texture
+        let mut atlas_textures: Vec<Option<Rc<RefCell<Texture>>>> = Vec::new();
+        for atlas in 0..atlas_srgb.len() {
+            let members: Vec<usize> = (0..candidates.len())
+                .filter(|index| placements[*index].map(|p| p.atlas) == Some(atlas))
+                .collect();
+            if members.len() < 2 {
+                atlas_textures.push(None);
+                continue;
+            }
+
+            let mut pixels: Vec<u8> = vec![0; (ATLAS_SIZE * ATLAS_SIZE * 4) as usize];
+            for index in members.iter() {
+                let tex = candidates[*index].borrow();
+                if let Some(TextureSource::Pixels { data, .. }) = tex.source.as_deref() {
+                    blit_into_atlas(
+                        &mut pixels,
+                        ATLAS_SIZE,
+                        data,
+                        tex.width,
+                        tex.height,
+                        placements[*index].as_ref().unwrap(),
+                        ATLAS_PADDING,
+                    );
+                }
+            }
+
+            let name = format!("atlas_{}", self.num_atlases_created);
+            self.num_atlases_created += 1;
+            let atlas_tex = load_texture_from_bytes(
+                &name,
+                ATLAS_SIZE,
+                ATLAS_SIZE,
+                4,
+                GL::RGBA,
+                &pixels,
+                None,
+                None,
+                None,
+                None,
+                atlas_srgb[atlas],
+            );
+            match atlas_tex {
+                Ok(atlas_tex) => {
+                    info!(
+                        LogCat::Resources,
+                        "Packed {} textures into atlas '{}'",
+                        members.len(),
+                        name
+                    );
+                    self.textures.insert(name, atlas_tex.clone());
+                    atlas_textures.push(Some(atlas_tex));
+                }
+                Err(err) => {
+                    error!(LogCat::Resources, "Failed to create atlas texture: {}", err);
+                    atlas_textures.push(None);
+                }
+            }
+        }
+
+        for (mat, unit, tex_index) in candidate_materials.iter() {
+            let placement = match placements[*tex_index] {
+                Some(placement) => placement,
+                None => continue,
+            };
+            let atlas_tex = match &atlas_textures[placement.atlas] {
+                Some(atlas_tex) => atlas_tex.clone(),
+                None => continue,
+            };
+
+            let (width, height) = sizes[*tex_index];
+            let (offset, scale) = get_atlas_uv_transform(&placement, width, height, ATLAS_SIZE);
+            self.remap_material_uvs(mat, offset, scale);
+
+            mat.borrow_mut().set_texture(*unit, Some(atlas_tex));
+        }
+    }
+
+    /// Whether some primitive uses `mat` by default, and all the primitives that do have UVs that we can move into
+    /// an atlas
+    fn material_uvs_fit_atlas(&self, mat: &Rc<RefCell<Material>>) -> bool {
+        let mut num_users = 0;
+        for mesh in self.meshes.values() {
+            for prim in mesh.borrow().primitives.iter() {
+                if !prim
+                    .default_material
+                    .as_ref()
+                    .map_or(false, |m| Rc::ptr_eq(m, mat))
+                {
+                    continue;
+                }
+                num_users += 1;
+
+                let fits = prim
+                    .source_data
+                    .as_ref()
+                    .map_or(false, |data| uvs_fit_atlas(&data.uv0));
+                if !fits {
+                    return false;
+                }
+            }
+        }
+
+        return num_users > 0;
+    }
+
+    fn remap_material_uvs(
+        &self,
+        mat: &Rc<RefCell<Material>>,
+        offset: Vector2<f32>,
+        scale: Vector2<f32>,
+    ) {
+        GLCTX.with(|ctx| {
+            for mesh in self.meshes.values() {
+                for prim in mesh.borrow_mut().primitives.iter_mut() {
+                    if !prim
+                        .default_material
+                        .as_ref()
+                        .map_or(false, |m| Rc::ptr_eq(m, mat))
+                    {
+                        continue;
+                    }
+
+                    if let Some(data) = &mut prim.source_data {
+                        remap_uvs_into_atlas(&mut data.uv0, offset, scale);
+
+                        unsafe {
+                            ctx.delete_vertex_array(prim.vao);
+                            for buffer in prim.buffers.drain(..) {
+                                ctx.delete_buffer(buffer);
+                            }
+                        }
+                        let (vao, buffers) = create_primitive_vao(ctx, data);
+                        prim.vao = vao;
+                        prim.buffers = buffers;
+                    }
+                }
+            }
+        });
+    }
+}
+
+fn is_texture_atlasable(tex: &Texture) -> bool {
+    let has_pixels = match tex.source.as_deref() {
+        Some(TextureSource::Pixels { data, .. }) => {
+            data.len() == (tex.width * tex.height * 4) as usize
+        }
+        _ => false,
+    };
+
+    return has_pixels
+        && !tex.is_cubemap
+        && tex.num_channels == 4
+        && tex.gl_format == GL::RGBA
+        && tex.width <= ATLAS_MAX_TEXTURE_SIZE
+        && tex.height <= ATLAS_MAX_TEXTURE_SIZE;
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+    use crate::managers::resource::intermediate_mesh::{intermediate_to_mesh, IntermediateMesh};
+    use crate::managers::resource::mesh::Mesh;
+    use crate::managers::resource::procedural_meshes::generate_ring_primitive;
+    use crate::utils::web::add_test_canvas;
+
+    #[wasm_bindgen_test]
+    pub fn small_textures_share_an_atlas() {
+        // Two small textures and one that's too large to atlas
+        let sizes = [(4, 2), (2, 2), (2000, 8)];
+        let atlas_size = 16;
+        let placements = pack_atlas(&sizes, atlas_size, 1);
+
+        let first = placements[0].unwrap();
+        let second = placements[1].unwrap();
+        assert_eq!(first.atlas, second.atlas);
+        assert_eq!(placements[2], None);
+
+        // They're side by side, each past its own padding
+        assert_eq!((first.x, first.y), (1, 1));
+        assert_eq!((second.x, second.y), (1 + 4 + 1 + 1, 1));
+
+        let (offset, scale) = get_atlas_uv_transform(&second, 2, 2, atlas_size);
+        assert_eq!(offset, Vector2::new(7.0 / 16.0, 1.0 / 16.0));
+        assert_eq!(scale, Vector2::new(2.0 / 16.0, 2.0 / 16.0));
+        let far_corner = offset + Vector2::new(1.0, 1.0).component_mul(&scale);
+        assert_eq!(far_corner, Vector2::new(9.0 / 16.0, 3.0 / 16.0));
+
+        // Pixels land where the UVs point to, with the edges repeated into the padding
+        let mut atlas = vec![0; (atlas_size * atlas_size * 4) as usize];
+        let red_green: Vec<u8> = vec![
+            255, 0, 0, 255, 0, 255, 0, 255, 255, 0, 0, 255, 0, 255, 0, 255,
+        ];
+        blit_into_atlas(&mut atlas, atlas_size, &red_green, 2, 2, &second, 1);
+        let pixel = |x: u32, y: u32| {
+            let start = ((y * atlas_size + x) * 4) as usize;
+            return atlas[start..start + 4].to_vec();
+        };
+        assert_eq!(pixel(7, 1), vec![255, 0, 0, 255]);
+        assert_eq!(pixel(8, 2), vec![0, 255, 0, 255]);
+        assert_eq!(pixel(6, 0), vec![255, 0, 0, 255]);
+        assert_eq!(pixel(9, 3), vec![0, 255, 0, 255]);
+        assert_eq!(pixel(10, 3), vec![0, 0, 0, 0]);
+
+        // Things that don't fit together go into separate atlases
+        let placements = pack_atlas(&[(14, 14), (14, 14)], atlas_size, 1);
+        assert_eq!(placements[0].unwrap().atlas, 0);
+        assert_eq!(placements[1].unwrap().atlas, 1);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn srgb_textures_get_their_own_atlases() {
+        // Would all fit in a single atlas, but base colors are sRGB while the normal maps aren't
+        let sizes = [(2, 2), (2, 2), (2, 2), (2, 2)];
+        let srgb = [true, false, true, false];
+        let (placements, atlas_srgb) = pack_atlas_by_srgb(&sizes, &srgb, 16, 1);
+        assert_eq!(atlas_srgb.len(), 2);
+
+        for (placement, srgb) in placements.iter().zip(srgb.iter()) {
+            assert_eq!(atlas_srgb[placement.unwrap().atlas], *srgb);
+        }
+        assert_eq!(placements[0].unwrap().atlas, placements[2].unwrap().atlas);
+        assert_eq!(placements[1].unwrap().atlas, placements[3].unwrap().atlas);
+        assert_ne!(placements[0].unwrap().atlas, placements[1].unwrap().atlas);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn remapped_uvs_sample_their_spot_in_the_atlas() {
+        let atlas_size = 16;
+        let placement = AtlasPlacement {
+            atlas: 0,
+            x: 7,
+            y: 1,
+        };
+        let (offset, scale) = get_atlas_uv_transform(&placement, 2, 4, atlas_size);
+
+        let mut uvs = vec![
+            Vector2::new(0.0, 0.0),
+            Vector2::new(1.0, 1.0),
+            Vector2::new(0.5, 0.25),
+        ];
+        assert!(uvs_fit_atlas(&uvs));
+        remap_uvs_into_atlas(&mut uvs, offset, scale);
+
+        // Corners of the texture land on the corners of its pixels in the atlas, past the padding
+        let to_pixels = |uv: &Vector2<f32>| uv * atlas_size as f32;
+        assert_eq!(to_pixels(&uvs[0]), Vector2::new(7.0, 1.0));
+        assert_eq!(to_pixels(&uvs[1]), Vector2::new(9.0, 5.0));
+        assert_eq!(to_pixels(&uvs[2]), Vector2::new(8.0, 2.0));
+
+        assert!(!uvs_fit_atlas(&[Vector2::new(0.5, 1.5)]));
+        assert!(!uvs_fit_atlas(&[Vector2::new(-0.1, 0.5)]));
+    }
+
+    #[wasm_bindgen_test]
+    pub fn unused_materials_are_not_atlased() {
+        let mut res_man = ResourceManager::new();
+
+        // No primitive uses this one by default, so it would only ever be an override
+        let mat = res_man.get_or_create_material("basecolor").unwrap();
+        assert!(!res_man.material_uvs_fit_atlas(&mat));
+    }
+
+    /// Adds a 2x2 texture for each of `names`, each on its own material used by a ring (which keeps its UVs within
+    /// [0, 1]), all in the same mesh
+    fn add_textured_rings(
+        res_man: &mut ResourceManager,
+        names: &[&str],
+    ) -> (Vec<Rc<RefCell<Material>>>, Rc<RefCell<Mesh>>) {
+        let mut materials = Vec::new();
+        let mut prims = Vec::new();
+        for name in names {
+            let pixels: Vec<u8> = [255, 0, 0, 255].repeat(4);
+            let tex = load_texture_from_bytes(
+                name,
+                2,
+                2,
+                4,
+                GL::RGBA,
+                &pixels,
+                None,
+                None,
+                None,
+                None,
+                true,
+            )
+            .unwrap();
+            res_man.textures.insert(name.to_string(), tex.clone());
+
+            let mat = res_man.instantiate_material("basecolor", name).unwrap();
+            mat.borrow_mut()
+                .set_texture(TextureUnit::BaseColor, Some(tex));
+            prims.push(generate_ring_primitive(0.5, 1.0, 8, Some(mat.clone())));
+            materials.push(mat);
+        }
+
+        let mesh_name = format!("rings_{}", names[0]);
+        let mesh = intermediate_to_mesh(&IntermediateMesh {
+            name: mesh_name.clone(),
+            primitives: prims,
+        });
+        res_man.meshes.insert(mesh_name, mesh.clone());
+        return (materials, mesh);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn build_atlas_packs_small_textures_into_one_gl_texture() {
+        add_test_canvas();
+        let mut res_man = ResourceManager::new();
+        let (materials, mesh) = add_textured_rings(&mut res_man, &["a", "b"]);
+
+        res_man.build_atlas();
+
+        let get_atlas = |mat: &Rc<RefCell<Material>>| {
+            return mat.borrow().get_textures()[&TextureUnit::BaseColor].clone();
+        };
+        let atlas = get_atlas(&materials[0]);
+        assert!(Rc::ptr_eq(&atlas, &get_atlas(&materials[1])));
+        assert_eq!(atlas.borrow().width, ATLAS_SIZE);
+        assert!(atlas.borrow().gl_handle.is_some());
+
+        // Side by side in the order of their material names, each past its own padding
+        let size = ATLAS_SIZE as f32;
+        let texture_scale = Vector2::new(2.0 / size, 2.0 / size);
+        let expected_offsets = [
+            Vector2::new(2.0 / size, 2.0 / size),
+            Vector2::new(8.0 / size, 2.0 / size),
+        ];
+        for (prim, offset) in mesh.borrow().primitives.iter().zip(expected_offsets.iter()) {
+            let uvs = &prim.source_data.as_ref().unwrap().uv0;
+            let min = uvs
+                .iter()
+                .fold(Vector2::new(1.0, 1.0), |acc, uv| acc.inf(uv));
+            let max = uvs
+                .iter()
+                .fold(Vector2::new(0.0, 0.0), |acc, uv| acc.sup(uv));
+            assert_eq!(min, *offset);
+            assert_eq!(max, offset + texture_scale);
+        }
+
+        // Once the original textures are gone, the next atlas still gets a name of its own
+        res_man.textures.remove("a");
+        res_man.textures.remove("b");
+        let (materials, _) = add_textured_rings(&mut res_man, &["c", "d"]);
+        res_man.build_atlas();
+        assert!(Rc::ptr_eq(&res_man.textures["atlas_0"], &atlas));
+        assert!(Rc::ptr_eq(
+            &res_man.textures["atlas_1"],
+            &get_atlas(&materials[0])
+        ));
+    }
+}
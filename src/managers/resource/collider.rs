@@ -1,4 +1,6 @@
+use crate::managers::resource::intermediate_mesh::IntermediatePrimitive;
 use crate::managers::resource::mesh::Mesh;
+use crate::utils::gl::GL;
 use crate::utils::log::*;
 use crate::utils::raycasting::{
     aabb_ray_intersection, sphere_ray_intersection, triangle_ray_intersection, Ray,
@@ -29,6 +31,11 @@ pub trait Collider: ColliderClone + fmt::Debug {
     fn intersects(&self, ray: &Ray<f32>) -> f32; // Distance along ray to the intersection point
     fn contains(&self, point: &Point3<f32>) -> bool;
 
+    /// Center and radius of a sphere enclosing the collider, if it can tell
+    fn bounding_sphere(&self) -> Option<(Point3<f32>, f32)> {
+        None
+    }
+
     fn as_mesh(&self) -> Option<MeshCollider> {
         None
     }
@@ -65,6 +72,30 @@ impl Collider for CompoundCollider {
             .any(|collider| collider.contains(point));
     }
 
+    fn bounding_sphere(&self) -> Option<(Point3<f32>, f32)> {
+        let mut spheres = Vec::new();
+        for collider in &self.colliders {
+            spheres.push(collider.bounding_sphere()?);
+        }
+        if spheres.is_empty() {
+            return None;
+        }
+
+        // Not the tightest sphere, but cheap and always encloses all of them
+        let mut center = Vector3::zeros();
+        for (sphere_center, _) in &spheres {
+            center += sphere_center.coords;
+        }
+        let center = Point3::from(center / spheres.len() as f32);
+
+        let radius = spheres
+            .iter()
+            .map(|(c, r)| (c - center).magnitude() + r)
+            .fold(0.0, f32::max);
+
+        return Some((center, radius));
+    }
+
     fn as_compound(&self) -> CompoundCollider {
         return self.clone();
     }
@@ -88,6 +119,13 @@ impl Collider for AxisAlignedBoxCollider {
             && point.z <= self.maxes.z
             && point.z >= self.mins.z;
     }
+
+    fn bounding_sphere(&self) -> Option<(Point3<f32>, f32)> {
+        return Some((
+            na::center(&self.mins, &self.maxes),
+            (self.maxes - self.mins).magnitude() / 2.0,
+        ));
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -103,6 +141,10 @@ impl Collider for SphereCollider {
     fn contains(&self, point: &Point3<f32>) -> bool {
         return (self.center - point).magnitude_squared() <= self.radius2;
     }
+
+    fn bounding_sphere(&self) -> Option<(Point3<f32>, f32)> {
+        return Some((self.center, self.radius2.sqrt()));
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -141,30 +183,18 @@ impl Collider for MeshCollider {
 
             if other_as_mesh.is_none() || other_as_mesh.unwrap().mesh.upgrade() != mesh {
                 if collider.intersects(&ray) == min_t {
-                    warning!(
-                        LogCat::Physics,
-                        "Earlying out due to recursive MeshCollider!"
-                    );
                     return min_t;
                 }
             }
         }
 
-        for primitive in &mesh.unwrap().borrow().primitives {
-            if let Some(ref source_data) = primitive.source_data {
-                for i in 0..source_data.indices.len() / 3 {
-                    let p0 = source_data.positions[source_data.indices[i * 3 + 0] as usize];
-                    let p1 = source_data.positions[source_data.indices[i * 3 + 1] as usize];
-                    let p2 = source_data.positions[source_data.indices[i * 3 + 2] as usize];
-
-                    if let Some(inter_t) = triangle_ray_intersection(&p0, &p1, &p2, ray) {
-                        min_t = min_t.min(inter_t);
-                    }
-                }
-            }
-        }
-
-        return min_t;
+        let mesh = mesh.unwrap();
+        let mesh = mesh.borrow();
+        let source_data = mesh
+            .primitives
+            .iter()
+            .filter_map(|p| p.source_data.as_ref());
+        return triangles_ray_intersection(source_data, ray);
     }
 
     fn contains(&self, point: &Point3<f32>) -> bool {
@@ -195,30 +225,182 @@ impl Collider for MeshCollider {
             }
         }
 
-        let test_ray = Ray {
-            start: *point,
-            direction: Vector3::new(1.0, 0.0, 0.0),
-        };
+        let mesh = mesh.unwrap();
+        let mesh = mesh.borrow();
+        let source_data = mesh
+            .primitives
+            .iter()
+            .filter_map(|p| p.source_data.as_ref());
+        return triangles_contain(source_data, point);
+    }
 
-        let mut num_intersections = 0;
-        for primitive in &mesh.unwrap().borrow().primitives {
-            if let Some(ref source_data) = primitive.source_data {
-                for i in 0..source_data.indices.len() / 3 {
-                    let p0 = source_data.positions[source_data.indices[i * 3 + 0] as usize];
-                    let p1 = source_data.positions[source_data.indices[i * 3 + 1] as usize];
-                    let p2 = source_data.positions[source_data.indices[i * 3 + 2] as usize];
-
-                    if let Some(_) = triangle_ray_intersection(&p0, &p1, &p2, &test_ray) {
-                        num_intersections += 1;
-                    }
-                }
+    fn bounding_sphere(&self) -> Option<(Point3<f32>, f32)> {
+        return self
+            .additional_outer_collider
+            .as_ref()
+            .and_then(|c| c.bounding_sphere());
+    }
+
+    fn as_mesh(&self) -> Option<MeshCollider> {
+        return Some(self.clone());
+    }
+}
+
+/// Distance along `ray` to its closest intersection with the triangles of `primitives`. Other primitive modes
+/// (e.g. lines) are skipped
+fn triangles_ray_intersection<'a>(
+    primitives: impl Iterator<Item = &'a IntermediatePrimitive>,
+    ray: &Ray<f32>,
+) -> f32 {
+    let mut min_t: f32 = std::f32::INFINITY;
+
+    for prim in primitives.filter(|prim| prim.mode == GL::TRIANGLES) {
+        for tri in prim.indices.chunks_exact(3) {
+            let p0 = &prim.positions[tri[0] as usize];
+            let p1 = &prim.positions[tri[1] as usize];
+            let p2 = &prim.positions[tri[2] as usize];
+
+            if let Some(inter_t) = triangle_ray_intersection(p0, p1, p2, ray) {
+                min_t = min_t.min(inter_t);
             }
         }
+    }
+
+    return min_t;
+}
+
+/// Whether `point` is inside the closed surface formed by the triangles of `primitives`
+fn triangles_contain<'a>(
+    primitives: impl Iterator<Item = &'a IntermediatePrimitive>,
+    point: &Point3<f32>,
+) -> bool {
+    let test_ray = Ray {
+        start: *point,
+        direction: Vector3::new(1.0, 0.0, 0.0),
+    };
 
-        return num_intersections % 2 != 0;
+    let mut num_intersections = 0;
+    for prim in primitives.filter(|prim| prim.mode == GL::TRIANGLES) {
+        for tri in prim.indices.chunks_exact(3) {
+            let p0 = &prim.positions[tri[0] as usize];
+            let p1 = &prim.positions[tri[1] as usize];
+            let p2 = &prim.positions[tri[2] as usize];
+
+            if let Some(_) = triangle_ray_intersection(p0, p1, p2, &test_ray) {
+                num_intersections += 1;
+            }
+        }
     }
 
-    fn as_mesh(&self) -> Option<MeshCollider> {
-        return Some(self.clone());
+    return num_intersections % 2 != 0;
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+
+    /// Square torus-like ring on the XY plane: Outer walls at +-2, inner hole walls at +-1, from z -0.5 to 0.5
+    fn generate_square_ring() -> IntermediatePrimitive {
+        let mut positions: Vec<Vector3<f32>> = Vec::new();
+        let mut indices: Vec<u16> = Vec::new();
+
+        let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+        for (i, (x, y)) in corners.iter().enumerate() {
+            let (next_x, next_y) = corners[(i + 1) % corners.len()];
+
+            for radius in &[1.0f32, 2.0f32] {
+                let base = positions.len() as u16;
+                positions.push(Vector3::new(x * radius, y * radius, -0.5));
+                positions.push(Vector3::new(next_x * radius, next_y * radius, -0.5));
+                positions.push(Vector3::new(next_x * radius, next_y * radius, 0.5));
+                positions.push(Vector3::new(x * radius, y * radius, 0.5));
+                indices.extend(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+            }
+
+            // Top and bottom caps between the inner and outer walls
+            for z in &[-0.5f32, 0.5f32] {
+                let base = positions.len() as u16;
+                positions.push(Vector3::new(*x, *y, *z));
+                positions.push(Vector3::new(next_x, next_y, *z));
+                positions.push(Vector3::new(next_x * 2.0, next_y * 2.0, *z));
+                positions.push(Vector3::new(x * 2.0, y * 2.0, *z));
+                indices.extend(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+            }
+        }
+
+        return IntermediatePrimitive {
+            name: String::from("square_ring"),
+            indices,
+            positions,
+            normals: Vec::new(),
+            tangents: Vec::new(),
+            colors: Vec::new(),
+            uv0: Vec::new(),
+            uv1: Vec::new(),
+            mode: GL::TRIANGLES,
+            mat: None,
+            collider: None,
+        };
+    }
+
+    #[wasm_bindgen_test]
+    pub fn ray_through_hole_misses() {
+        let ring = generate_square_ring();
+        let bounds = AxisAlignedBoxCollider {
+            mins: Point3::new(-2.0, -2.0, -0.5),
+            maxes: Point3::new(2.0, 2.0, 0.5),
+        };
+
+        // Straight down through the hole: The AABB is hit but no triangle should be
+        let ray = Ray {
+            start: Point3::new(0.0, 0.0, 10.0),
+            direction: Vector3::new(0.0, 0.0, -1.0),
+        };
+        assert!(bounds.intersects(&ray) < std::f32::INFINITY);
+        assert_eq!(
+            triangles_ray_intersection(std::iter::once(&ring), &ray),
+            std::f32::INFINITY
+        );
+    }
+
+    #[wasm_bindgen_test]
+    pub fn ray_onto_ring_hits() {
+        let ring = generate_square_ring();
+
+        let ray = Ray {
+            start: Point3::new(1.5, 0.0, 10.0),
+            direction: Vector3::new(0.0, 0.0, -1.0),
+        };
+        let t = triangles_ray_intersection(std::iter::once(&ring), &ray);
+        assert!((t - 9.5).abs() < 0.0001);
+
+        assert!(triangles_contain(
+            std::iter::once(&ring),
+            &Point3::new(1.5, 0.1, 0.0)
+        ));
+        assert!(!triangles_contain(
+            std::iter::once(&ring),
+            &Point3::new(0.0, 0.1, 0.0)
+        ));
+    }
+
+    #[wasm_bindgen_test]
+    pub fn line_primitives_are_not_hit() {
+        let ring = IntermediatePrimitive {
+            mode: GL::LINES,
+            ..generate_square_ring()
+        };
+
+        let ray = Ray {
+            start: Point3::new(1.5, 0.0, 10.0),
+            direction: Vector3::new(0.0, 0.0, -1.0),
+        };
+        assert_eq!(
+            triangles_ray_intersection(std::iter::once(&ring), &ray),
+            std::f32::INFINITY
+        );
     }
 }
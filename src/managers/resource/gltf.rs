@@ -10,11 +10,165 @@ use crate::managers::ResourceManager;
 use crate::utils::gl::GL;
 use crate::utils::log::*;
 use crate::utils::transform::Transform;
+use crate::utils::units::Mm;
 use gltf::image::Format;
 use gltf::mesh::util::{ReadColors, ReadIndices, ReadTexCoords};
 use na::*;
+use std::collections::HashSet;
+use std::io::Read;
 use std::{cell::RefCell, f32::INFINITY, rc::Rc};
 
+/// Decompresses .gltf or .glb file `data` if it's gzip or zlib compressed (what "deflate" HTTP encodings use), which
+/// we can tell from its first bytes. Returns None if it isn't compressed, so that it can be used as is
+pub fn decompress_gltf_bytes(data: &[u8]) -> Result<Option<Vec<u8>>, String> {
+    let is_gzip = data.len() >= 2 && data[0] == 0x1F && data[1] == 0x8B;
+
+    // Deflate compression method, and a header checksum. Neither "glTF" nor JSON can start like this
+    let is_zlib =
+        data.len() >= 2 && data[0] & 0x0F == 8 && (data[0] as u16 * 256 + data[1] as u16) % 31 == 0;
+
+    let mut result = Vec::new();
+    if is_gzip {
+        flate2::read::GzDecoder::new(data)
+            .read_to_end(&mut result)
+            .map_err(|e| format!("Failed to decompress gzip data: {}", e))?;
+    } else if is_zlib {
+        flate2::read::ZlibDecoder::new(data)
+            .read_to_end(&mut result)
+            .map_err(|e| format!("Failed to decompress zlib data: {}", e))?;
+    } else {
+        return Ok(None);
+    }
+
+    return Ok(Some(result));
+}
+
+/// Wraps `json` and `bin` in a GLB container, with the chunks padded to 4 bytes like the spec wants
+pub fn build_glb(json: &str, bin: &[u8]) -> Vec<u8> {
+    let mut json_chunk = json.as_bytes().to_vec();
+    while json_chunk.len() % 4 != 0 {
+        json_chunk.push(b' ');
+    }
+    let mut bin_chunk = bin.to_vec();
+    while bin_chunk.len() % 4 != 0 {
+        bin_chunk.push(0);
+    }
+
+    let mut total_length = 20 + json_chunk.len();
+    if !bin_chunk.is_empty() {
+        total_length += 8 + bin_chunk.len();
+    }
+
+    let mut glb = Vec::new();
+    glb.extend_from_slice(b"glTF");
+    glb.extend_from_slice(&2u32.to_le_bytes());
+    glb.extend_from_slice(&(total_length as u32).to_le_bytes());
+    glb.extend_from_slice(&(json_chunk.len() as u32).to_le_bytes());
+    glb.extend_from_slice(b"JSON");
+    glb.extend_from_slice(&json_chunk);
+    if !bin_chunk.is_empty() {
+        glb.extend_from_slice(&(bin_chunk.len() as u32).to_le_bytes());
+        glb.extend_from_slice(b"BIN\0");
+        glb.extend_from_slice(&bin_chunk);
+    }
+    return glb;
+}
+
+/// Builds a GLB file with a single mesh named `name`, with a LINE_STRIP primitive through `points`. Points are divided
+/// by `unit` (which is written to the asset extras as `unit_name`), and converted back to Y-up like the files we import
+pub fn build_line_strip_glb(
+    name: &str,
+    points: &[Point3<f64>],
+    unit: Mm,
+    unit_name: &str,
+) -> Result<Vec<u8>, String> {
+    if points.is_empty() {
+        return Err(format!("Can't export line '{}' without any points", name));
+    }
+
+    let mut bin: Vec<u8> = Vec::new();
+    let mut mins = [INFINITY; 3];
+    let mut maxes = [-INFINITY; 3];
+    for point in points {
+        // Z-up right-handed to Y-up right-handed
+        let pos = [
+            (point.x / unit.0) as f32,
+            (point.z / unit.0) as f32,
+            (-point.y / unit.0) as f32,
+        ];
+        for axis in 0..3 {
+            mins[axis] = mins[axis].min(pos[axis]);
+            maxes[axis] = maxes[axis].max(pos[axis]);
+            bin.extend_from_slice(&pos[axis].to_le_bytes());
+        }
+    }
+
+    let json = serde_json::json!({
+        "asset": {
+            "version": "2.0",
+            "generator": "system-viewer",
+            "extras": { "unit": unit_name },
+        },
+        "buffers": [{ "byteLength": bin.len() }],
+        "bufferViews": [{ "buffer": 0, "byteOffset": 0, "byteLength": bin.len(), "target": GL::ARRAY_BUFFER }],
+        "accessors": [{
+            "bufferView": 0,
+            "componentType": GL::FLOAT,
+            "count": points.len(),
+            "type": "VEC3",
+            "min": mins,
+            "max": maxes,
+        }],
+        "meshes": [{
+            "name": name,
+            "primitives": [{ "attributes": { "POSITION": 0 }, "mode": GL::LINE_STRIP }],
+        }],
+        "nodes": [{ "name": name, "mesh": 0 }],
+        "scenes": [{ "nodes": [0] }],
+        "scene": 0,
+    });
+
+    return Ok(build_glb(&json.to_string(), &bin));
+}
+
+/// Returns the emissiveStrength of the KHR_materials_emissive_strength extension for each material in the .gltf or
+/// .glb file `data`, or 1.0 for materials without it. Our version of the gltf crate drops extensions it doesn't
+/// know about, so we have to go through the JSON ourselves
+pub fn parse_emissive_strengths(data: &[u8]) -> Vec<f32> {
+    // GLB files have a 12 byte header, followed by the JSON chunk's length, type and contents
+    let mut json = data;
+    if data.len() >= 20 && &data[0..4] == b"glTF" && &data[16..20] == b"JSON" {
+        let chunk_length = u32::from_le_bytes([data[12], data[13], data[14], data[15]]) as usize;
+        json = &data[20..(20 + chunk_length).min(data.len())];
+    }
+
+    let root = match serde_json::from_slice::<serde_json::Value>(json) {
+        Ok(root) => root,
+        Err(error) => {
+            warning!(
+                LogCat::Gltf,
+                "Failed to parse gltf JSON for emissive strengths: {}",
+                error
+            );
+            return Vec::new();
+        }
+    };
+
+    return root
+        .get("materials")
+        .and_then(|mats| mats.as_array())
+        .map(|mats| {
+            mats.iter()
+                .map(|mat| {
+                    mat.pointer("/extensions/KHR_materials_emissive_strength/emissiveStrength")
+                        .and_then(|strength| strength.as_f64())
+                        .unwrap_or(1.0) as f32
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+}
+
 pub trait GltfResource {
     fn get_identifier(&self, identifier: &str) -> String;
 }
@@ -80,6 +234,7 @@ impl ResourceManager {
         &mut self,
         file_identifier: &str,
         material: &gltf::Material,
+        emissive_strength: f32,
     ) -> Result<Rc<RefCell<Material>>, String> {
         let identifier = material.get_identifier(file_identifier);
         debug!(LogCat::Gltf, "\tLoading gltf material '{}'", identifier);
@@ -94,7 +249,9 @@ impl ResourceManager {
         // Base color texture
         if let Some(gltf_tex) = pbr.base_color_texture() {
             let tex_identifier = gltf_tex.texture().get_identifier(file_identifier);
-            if let Some(tex) = self.get_or_request_texture(&tex_identifier, false) {
+            if let Some(tex) =
+                self.get_or_request_texture(&tex_identifier, TextureUnit::BaseColor, false)
+            {
                 debug!(LogCat::Gltf, "\t\tBaseColor texture: '{}'", tex_identifier);
                 mat_mut.set_texture(TextureUnit::BaseColor, Some(tex));
             } else {
@@ -115,7 +272,9 @@ impl ResourceManager {
         // Metallic-roughness texture
         if let Some(gltf_tex) = pbr.metallic_roughness_texture() {
             let tex_identifier = gltf_tex.texture().get_identifier(file_identifier);
-            if let Some(tex) = self.get_or_request_texture(&tex_identifier, false) {
+            if let Some(tex) =
+                self.get_or_request_texture(&tex_identifier, TextureUnit::MetallicRoughness, false)
+            {
                 debug!(
                     LogCat::Gltf,
                     "\t\tMetallicRoughness texture: '{}'", tex_identifier
@@ -144,7 +303,9 @@ impl ResourceManager {
         // Normal texture
         if let Some(gltf_tex) = material.normal_texture() {
             let tex_identifier = gltf_tex.texture().get_identifier(file_identifier);
-            if let Some(tex) = self.get_or_request_texture(&tex_identifier, false) {
+            if let Some(tex) =
+                self.get_or_request_texture(&tex_identifier, TextureUnit::Normal, false)
+            {
                 debug!(LogCat::Gltf, "\t\tNormal texture: '{}'", tex_identifier);
                 mat_mut.set_texture(TextureUnit::Normal, Some(tex));
             } else {
@@ -160,7 +321,9 @@ impl ResourceManager {
         // Occlusion texture
         if let Some(gltf_tex) = material.occlusion_texture() {
             let tex_identifier = gltf_tex.texture().get_identifier(file_identifier);
-            if let Some(tex) = self.get_or_request_texture(&tex_identifier, false) {
+            if let Some(tex) =
+                self.get_or_request_texture(&tex_identifier, TextureUnit::Occlusion, false)
+            {
                 debug!(LogCat::Gltf, "\t\tOcclusion texture: '{}'", tex_identifier);
                 mat_mut.set_texture(TextureUnit::Occlusion, Some(tex));
             } else {
@@ -176,7 +339,9 @@ impl ResourceManager {
         // Emissive texture
         if let Some(gltf_tex) = material.emissive_texture() {
             let tex_identifier = gltf_tex.texture().get_identifier(file_identifier);
-            if let Some(tex) = self.get_or_request_texture(&tex_identifier, false) {
+            if let Some(tex) =
+                self.get_or_request_texture(&tex_identifier, TextureUnit::Emissive, false)
+            {
                 debug!(LogCat::Gltf, "\t\tEmissive texture: '{}'", tex_identifier);
                 mat_mut.set_texture(TextureUnit::Emissive, Some(tex));
             } else {
@@ -194,6 +359,16 @@ impl ResourceManager {
         mat_mut.set_uniform_value(UniformName::EmissiveFactor, UniformValue::Vec3(factor));
         debug!(LogCat::Gltf, "\t\tEmissive factor: '{:?}'", factor);
 
+        // Emissive strength
+        mat_mut.set_uniform_value(
+            UniformName::EmissiveStrength,
+            UniformValue::Float(emissive_strength),
+        );
+        debug!(
+            LogCat::Gltf,
+            "\t\tEmissive strength: '{:?}'", emissive_strength
+        );
+
         return Ok(mat.clone());
     }
 
@@ -204,6 +379,7 @@ impl ResourceManager {
         &mut self,
         file_identifier: &str,
         materials: gltf::iter::Materials,
+        emissive_strengths: &[f32],
     ) -> Vec<Option<Rc<RefCell<Material>>>> {
         info!(
             LogCat::Gltf,
@@ -216,7 +392,8 @@ impl ResourceManager {
         result.resize(materials.len(), None);
 
         for (index, material) in materials.enumerate() {
-            match self.load_material_from_gltf(file_identifier, &material) {
+            let emissive_strength = emissive_strengths.get(index).cloned().unwrap_or(1.0);
+            match self.load_material_from_gltf(file_identifier, &material, emissive_strength) {
                 Ok(new_mat) => {
                     result[index] = Some(new_mat);
                 }
@@ -522,6 +699,7 @@ impl ResourceManager {
         file_identifier: &str,
         texture: &gltf::Texture,
         image_data: &gltf::image::Data,
+        srgb: bool,
     ) -> Result<Rc<RefCell<Texture>>, String> {
         let identifier = texture.get_identifier(file_identifier);
         let width = image_data.width;
@@ -589,6 +767,7 @@ impl ResourceManager {
             Some(min_filter),
             Some(wrap_s),
             Some(wrap_t),
+            srgb,
         );
     }
 
@@ -597,6 +776,7 @@ impl ResourceManager {
         file_identifier: &str,
         textures: gltf::iter::Textures,
         images: &Vec<gltf::image::Data>,
+        srgb_textures: &HashSet<usize>,
     ) {
         info!(
             LogCat::Gltf,
@@ -610,6 +790,7 @@ impl ResourceManager {
                 file_identifier,
                 &texture,
                 &images[texture.source().index()],
+                srgb_textures.contains(&texture.index()),
             ) {
                 Ok(new_tex) => {
                     let name = &new_tex.borrow().name;
@@ -780,16 +961,39 @@ impl ResourceManager {
 
     /// Will parse and bake the entire gltf file into a single mesh with the same name as the file identifier.
     /// WIll also load all materials and textures contained within the file.
+    /// `emissive_strengths` has the KHR_materials_emissive_strength of each material, from parse_emissive_strengths
     pub fn load_gltf_data(
         &mut self,
         file_identifier: &str,
         gltf_doc: &gltf::Document,
         gltf_buffers: &Vec<gltf::buffer::Data>,
         gltf_images: &Vec<gltf::image::Data>,
+        emissive_strengths: &[f32],
     ) {
-        self.load_textures_from_gltf(file_identifier, gltf_doc.textures(), gltf_images);
+        // Color textures get uploaded as sRGB right away, instead of once their materials ask for them
+        let srgb_textures: HashSet<usize> = gltf_doc
+            .materials()
+            .flat_map(|mat| {
+                let base_color = mat
+                    .pbr_metallic_roughness()
+                    .base_color_texture()
+                    .map(|info| info.texture().index());
+                let emissive = mat.emissive_texture().map(|info| info.texture().index());
+                base_color.into_iter().chain(emissive)
+            })
+            .collect();
+        self.load_textures_from_gltf(
+            file_identifier,
+            gltf_doc.textures(),
+            gltf_images,
+            &srgb_textures,
+        );
 
-        let parsed_mats = self.load_materials_from_gltf(file_identifier, gltf_doc.materials());
+        let parsed_mats = self.load_materials_from_gltf(
+            file_identifier,
+            gltf_doc.materials(),
+            emissive_strengths,
+        );
 
         let parsed_meshes = self.load_meshes_from_gltf(
             file_identifier,
@@ -819,3 +1023,143 @@ impl ResourceManager {
         }
     }
 }
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+    use std::io::Write;
+
+    #[wasm_bindgen_test]
+    pub fn emissive_strength_defaults_to_one() {
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "materials": [
+                { "name": "plain" },
+                {
+                    "name": "star",
+                    "extensions": { "KHR_materials_emissive_strength": { "emissiveStrength": 50.0 } }
+                }
+            ]
+        }"#;
+        assert_eq!(parse_emissive_strengths(json.as_bytes()), vec![1.0, 50.0]);
+
+        // Same thing wrapped in a GLB container
+        let glb = build_glb(json, &[]);
+        assert_eq!(parse_emissive_strengths(&glb), vec![1.0, 50.0]);
+
+        assert!(parse_emissive_strengths(b"not json").is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    pub fn compressed_glb_parses_like_the_raw_one() {
+        let positions: [f32; 9] = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+        let bin: Vec<u8> = positions
+            .iter()
+            .flat_map(|f| f.to_le_bytes().to_vec())
+            .collect();
+        let glb = build_glb(
+            r#"{
+                "asset": { "version": "2.0" },
+                "buffers": [{ "byteLength": 36 }],
+                "bufferViews": [{ "buffer": 0, "byteLength": 36 }],
+                "accessors": [{
+                    "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                    "min": [0, 0, 0], "max": [1, 1, 0]
+                }],
+                "meshes": [{ "name": "triangle", "primitives": [{ "attributes": { "POSITION": 0 } }] }],
+                "nodes": [{ "mesh": 0 }],
+                "scenes": [{ "nodes": [0] }]
+            }"#,
+            &bin,
+        );
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(&glb).unwrap();
+        let mut zlib = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+        zlib.write_all(&glb).unwrap();
+
+        let read_meshes = |data: &[u8]| -> Vec<(Option<String>, Vec<[f32; 3]>)> {
+            let (doc, buffers, _) = gltf::import_slice(data).unwrap();
+            return doc
+                .meshes()
+                .map(|mesh| {
+                    let prim = mesh.primitives().next().unwrap();
+                    let reader = prim.reader(|buffer| Some(&buffers[buffer.index()]));
+                    let positions = reader.read_positions().unwrap().collect();
+                    return (mesh.name().map(|name| name.to_owned()), positions);
+                })
+                .collect();
+        };
+        let raw_meshes = read_meshes(&glb);
+        assert_eq!(raw_meshes.len(), 1);
+        assert_eq!(raw_meshes[0].0.as_deref(), Some("triangle"));
+
+        // Uncompressed files are left alone
+        assert!(decompress_gltf_bytes(&glb).unwrap().is_none());
+        assert!(decompress_gltf_bytes(b"{}").unwrap().is_none());
+
+        for compressed in &[gzip.finish().unwrap(), zlib.finish().unwrap()] {
+            let decompressed = decompress_gltf_bytes(compressed).unwrap().unwrap();
+            assert_eq!(decompressed, glb);
+            assert_eq!(read_meshes(&decompressed), raw_meshes);
+        }
+
+        // Truncated downloads are reported instead of handed to the gltf parser
+        let mut truncated = vec![0x1F, 0x8B];
+        truncated.extend_from_slice(b"garbage");
+        assert!(decompress_gltf_bytes(&truncated).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    pub fn exported_orbit_reads_back() {
+        use crate::managers::orbit::OrbitalElements;
+        use crate::utils::orbits::{
+            adaptive_eccentric_anomalies, elements_to_ellipse_rotation_transform,
+            get_baked_orbit_points,
+        };
+        use crate::utils::units::{Au, Deg, J2000_JDN};
+
+        // Mercury
+        let elements = OrbitalElements {
+            ref_id: String::from("10"),
+            epoch: J2000_JDN,
+            semi_major_axis: Au(0.3870982121840369).to_Mm(),
+            eccentricity: 0.2056302929816634,
+            inclination: Deg(7.00501414069919).to_rad(),
+            long_asc_node: Deg(48.3305373398104).to_rad(),
+            arg_periapsis: Deg(29.12428280936123).to_rad(),
+            mean_anomaly_0: Deg(174.7958829506606).to_rad(),
+            sidereal_orbit_period_days: 87.96909804182887,
+        };
+        let anomalies = adaptive_eccentric_anomalies(elements.eccentricity, 90);
+        let rotation = elements_to_ellipse_rotation_transform(&elements);
+        let points = get_baked_orbit_points(&elements, &anomalies, &rotation);
+        assert_eq!(points.len(), anomalies.len());
+        assert!((points[0] - points[points.len() - 1]).magnitude() < 1E-3);
+
+        let glb = build_line_strip_glb("Mercury orbit", &points, Au(1.0).to_Mm(), "AU").unwrap();
+        let (doc, buffers, _) = gltf::import_slice(&glb).unwrap();
+        let mesh = doc.meshes().next().unwrap();
+        assert_eq!(mesh.name(), Some("Mercury orbit"));
+
+        let primitive = mesh.primitives().next().unwrap();
+        assert_eq!(primitive.mode(), gltf::mesh::Mode::LineStrip);
+        let positions: Vec<[f32; 3]> = primitive
+            .reader(|buffer| Some(&buffers[buffer.index()]))
+            .read_positions()
+            .unwrap()
+            .collect();
+        assert_eq!(positions.len(), points.len());
+
+        // In AU, and back to Z-up the same way we import meshes
+        let first = Vector3::new(positions[0][0], -positions[0][2], positions[0][1]);
+        let expected = points[0].coords.map(|c| (c / Au(1.0).to_Mm().0) as f32);
+        assert!((first - expected).magnitude() < 1E-6, "{:?}", first);
+        assert!(first.magnitude() > 0.3 && first.magnitude() < 0.47);
+
+        assert!(build_line_strip_glb("Empty", &[], Mm(1.0), "Mm").is_err());
+    }
+}
@@ -6,20 +6,28 @@ use crate::utils::gl::GL;
 use crate::utils::log::*;
 use egui::Ui;
 use glow::*;
-use na::Matrix4;
+use na::{Matrix4, Vector3};
 use std::collections::HashSet;
 use std::{cell::RefCell, collections::HashMap, rc::Rc};
 use web_sys::*;
 
 pub struct FrameUniformValues {
-    pub v: Matrix4<f64>,  // World space to camera space
-    pub pv: Matrix4<f64>, // World space to NDC
+    pub v: Matrix4<f64>,          // World space to camera space
+    pub p: Matrix4<f64>,          // Camera space to NDC
+    pub v_rel: Matrix4<f64>,      // Camera-relative world space to camera space (no translation)
+    pub camera_pos: Vector3<f64>, // World space
     pub light_types: Vec<i32>,
     pub light_pos_or_dir_c: Vec<f32>, // For point lights, position; For directional lights, direction; Always in camera space
     pub light_colors: Vec<f32>,
     pub light_intensities: Vec<f32>,
     pub exposure_factor: f32,
-    pub f_coef: f32, // Logarithmic depth buffer constant
+    pub tonemap: i32,            // app_state::Tonemap the blit applies
+    pub f_coef: f32,             // Logarithmic depth buffer constant
+    pub viewport_size: [f32; 2], // Pixels
+    pub line_width: f32,         // Pixels
+    pub environment: Option<Rc<RefCell<Texture>>>, // Skybox cubemap that metallic materials reflect
+    pub environment_trans: [f32; 16], // Camera space directions to the directions to sample `environment` with
+    pub environment_max_lod: f32,     // Mip level that roughness 1.0 samples `environment` at
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -40,9 +48,23 @@ pub enum UniformName {
     Normal,
     Emissive,
     EmissiveFactor,
+    EmissiveStrength, // Multiplies the emissive factor, so that e.g. stars can be much brighter than 1.0
     Occlusion,
     ExposureFactor,
-    Fcoef, // Constant used for logarithmic depth buffer
+    Fcoef,         // Constant used for logarithmic depth buffer
+    OrbitFraction, // Current eccentric anomaly of the orbiting body, divided by 2pi
+    OrbitEccentricity,
+    OrbitFade, // 0.0 for uniform orbit lines, 1.0 to fade them behind the body
+    LineWidth, // Screen-space width of expanded lines, in pixels
+    ViewportSize,
+    Tonemap,           // Index of the app_state::Tonemap operator to apply, or CANVAS_TONEMAP
+    OccluderPosRadius, // View space center and radius of the sphere that may shadow the mesh from point lights
+    LightRadius,
+    AtmosphereThickness, // How far an atmosphere shell goes past the surface, as a fraction of the planet's radius
+    Environment,
+    EnvironmentTrans,
+    EnvironmentMaxLod,
+    PointGlow, // 0.0 for solid round point markers, 1.0 for a soft falloff from their center
 }
 impl UniformName {
     pub fn default_value(&self) -> UniformValue {
@@ -72,9 +94,28 @@ impl UniformName {
             UniformName::Normal => UniformValue::Int(TextureUnit::Normal as i32),
             UniformName::Emissive => UniformValue::Int(TextureUnit::Emissive as i32),
             UniformName::EmissiveFactor => UniformValue::Vec3([0.0, 0.0, 0.0]),
+            UniformName::EmissiveStrength => UniformValue::Float(1.0),
             UniformName::Occlusion => UniformValue::Int(TextureUnit::Occlusion as i32),
             UniformName::ExposureFactor => UniformValue::Float(1.0),
             UniformName::Fcoef => UniformValue::Float(1.0),
+            UniformName::OrbitFraction => UniformValue::Float(0.0),
+            UniformName::OrbitEccentricity => UniformValue::Float(0.0),
+            UniformName::OrbitFade => UniformValue::Float(0.0),
+            UniformName::LineWidth => UniformValue::Float(1.0),
+            UniformName::ViewportSize => UniformValue::Vec2([1.0, 1.0]),
+            UniformName::Tonemap => UniformValue::Int(1),
+            UniformName::OccluderPosRadius => UniformValue::Vec4([0.0, 0.0, 0.0, 0.0]),
+            UniformName::LightRadius => UniformValue::Float(0.0),
+            UniformName::AtmosphereThickness => UniformValue::Float(0.0),
+            UniformName::Environment => UniformValue::Int(TextureUnit::Environment as i32),
+            UniformName::EnvironmentTrans => UniformValue::Matrix([
+                1.0, 0.0, 0.0, 0.0, //
+                0.0, 1.0, 0.0, 0.0, //
+                0.0, 0.0, 1.0, 0.0, //
+                0.0, 0.0, 0.0, 1.0, //
+            ]),
+            UniformName::EnvironmentMaxLod => UniformValue::Float(0.0),
+            UniformName::PointGlow => UniformValue::Float(0.0),
         }
     }
 
@@ -96,9 +137,23 @@ impl UniformName {
             UniformName::Normal => "us_normal",
             UniformName::Emissive => "us_emissive",
             UniformName::EmissiveFactor => "u_emissive_factor",
+            UniformName::EmissiveStrength => "u_emissive_strength",
             UniformName::Occlusion => "us_occlusion",
             UniformName::ExposureFactor => "u_exposure_factor",
             UniformName::Fcoef => "u_f_coef",
+            UniformName::OrbitFraction => "u_orbit_fraction",
+            UniformName::OrbitEccentricity => "u_orbit_eccentricity",
+            UniformName::OrbitFade => "u_orbit_fade",
+            UniformName::LineWidth => "u_line_width",
+            UniformName::ViewportSize => "u_viewport_size",
+            UniformName::Tonemap => "u_tonemap",
+            UniformName::OccluderPosRadius => "u_occluder_pos_radius_c",
+            UniformName::LightRadius => "u_light_radius",
+            UniformName::AtmosphereThickness => "u_atmosphere_thickness",
+            UniformName::Environment => "us_environment",
+            UniformName::EnvironmentTrans => "u_environment_trans",
+            UniformName::EnvironmentMaxLod => "u_environment_max_lod",
+            UniformName::PointGlow => "u_point_glow",
         }
     }
 }
@@ -136,6 +191,7 @@ pub enum ShaderDefine {
     HasNormalTexture,
     HasEmissiveTexture,
     HasOcclusionTexture,
+    HasEnvironmentTexture,
 }
 impl ShaderDefine {
     pub fn as_str(&self) -> &str {
@@ -150,10 +206,12 @@ impl ShaderDefine {
             ShaderDefine::HasNormalTexture => "HAS_NORMAL_TEXTURE",
             ShaderDefine::HasEmissiveTexture => "HAS_EMISSIVE_TEXTURE",
             ShaderDefine::HasOcclusionTexture => "HAS_OCCLUSION_TEXTURE",
+            ShaderDefine::HasEnvironmentTexture => "HAS_ENVIRONMENT_TEXTURE",
         }
     }
 }
 
+/// Links a program from the resolved sources of its shaders (i.e. with their includes already replaced)
 fn link_program(
     gl: &glow::Context,
     prefix_lines: &str,
@@ -163,18 +221,8 @@ fn link_program(
     unsafe {
         let program = gl.create_program()?;
 
-        let vert_shader = compile_shader(
-            &gl,
-            GL::VERTEX_SHADER,
-            prefix_lines,
-            &SHADER_STORAGE[vert_source],
-        )?;
-        let frag_shader = compile_shader(
-            &gl,
-            GL::FRAGMENT_SHADER,
-            prefix_lines,
-            &SHADER_STORAGE[frag_source],
-        )?;
+        let vert_shader = compile_shader(&gl, GL::VERTEX_SHADER, prefix_lines, vert_source)?;
+        let frag_shader = compile_shader(&gl, GL::FRAGMENT_SHADER, prefix_lines, frag_source)?;
 
         gl.attach_shader(program, vert_shader);
         gl.attach_shader(program, frag_shader);
@@ -222,6 +270,15 @@ fn compile_shader(
     }
 }
 
+/// How the fragments of a material are combined with what was already drawn. Meshes with any blended material are
+/// drawn after all the opaque ones, from back to front
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlendMode {
+    Opaque,
+    Alpha,    // Mixed in by their alpha, e.g. lines with antialiased edges
+    Additive, // Added on top without writing depth, e.g. glows that shouldn't hide each other
+}
+
 #[derive(Clone, Debug)]
 pub struct Material {
     pub(super) name: String,
@@ -237,6 +294,8 @@ pub struct Material {
     uniforms: HashMap<UniformName, Uniform>,
     defines: HashSet<ShaderDefine>,
     pub double_sided: bool,
+    pub cull_front: bool, // Draws only the back faces instead, e.g. for outlines. Ignored if double_sided
+    pub blend_mode: BlendMode,
 
     failed_to_compile: bool,
 }
@@ -268,6 +327,8 @@ impl Material {
             uniforms,
             defines: HashSet::new(),
             double_sided: false,
+            cull_front: false,
+            blend_mode: BlendMode::Opaque,
             failed_to_compile: false,
         }
     }
@@ -280,6 +341,11 @@ impl Material {
         return self.compatible_prim_hash;
     }
 
+    /// Our compiled program, if any. Instances start out sharing the program of the master they're cloned from
+    pub fn get_program(&self) -> Option<glow::Program> {
+        return self.program;
+    }
+
     pub fn recompile_program(&mut self, gl: &glow::Context) {
         if self.failed_to_compile {
             return;
@@ -292,14 +358,7 @@ impl Material {
             self.compatible_prim_hash
         );
 
-        let prefix_defines = self
-            .defines
-            .iter()
-            .map(|d| "#define ".to_owned() + d.as_str())
-            .collect::<Vec<String>>()
-            .join("\n");
-
-        let program = link_program(gl, &prefix_defines, &self.vert, &self.frag);
+        let program = self.link_program_with(gl, None);
         if program.is_err() {
             error!(
                 LogCat::Resources,
@@ -331,6 +390,64 @@ impl Material {
         self.program = Some(program);
     }
 
+    /// Links a program with our defines and shaders. If `replacement` is Some, its resolved source is used for
+    /// the shader with that name instead of the usual one
+    fn link_program_with(
+        &self,
+        gl: &glow::Context,
+        replacement: Option<(&str, &str)>,
+    ) -> Result<glow::Program, String> {
+        let prefix_defines = self
+            .defines
+            .iter()
+            .map(|d| "#define ".to_owned() + d.as_str())
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let get_source = |name: &str| -> Result<String, String> {
+            if let Some((replaced_name, source)) = replacement {
+                if replaced_name == name {
+                    return Ok(source.to_owned());
+                }
+            }
+
+            return get_shader_source(name).ok_or(format!("Failed to find shader '{}'", name));
+        };
+
+        return link_program(
+            gl,
+            &prefix_defines,
+            &get_source(&self.vert)?,
+            &get_source(&self.frag)?,
+        );
+    }
+
+    pub fn uses_shader(&self, shader_name: &str) -> bool {
+        return self.vert == shader_name || self.frag == shader_name;
+    }
+
+    /// Checks whether our program would compile and link if shader `shader_name` had `resolved_source` instead,
+    /// without changing anything about us
+    pub fn test_shader_source(
+        &self,
+        gl: &glow::Context,
+        shader_name: &str,
+        resolved_source: &str,
+    ) -> Result<(), String> {
+        let program = self.link_program_with(gl, Some((shader_name, resolved_source)))?;
+        unsafe {
+            gl.delete_program(program);
+        }
+        return Ok(());
+    }
+
+    /// Drops our program so that it is compiled again the next time we're drawn (e.g. after the WebGL context
+    /// was lost)
+    pub fn invalidate_program(&mut self) {
+        self.program = None;
+        self.failed_to_compile = false;
+    }
+
     pub fn set_define(&mut self, define: ShaderDefine) {
         if self.defines.insert(define) {
             self.program = None;
@@ -372,10 +489,34 @@ impl Material {
         }
     }
 
+    /// Sets the cubemap that we reflect, if our shader supports environment reflections. This is called for every
+    /// draw, so it only touches our textures and defines when the cubemap actually changes
+    pub fn set_environment_texture(&mut self, tex: Option<&Rc<RefCell<Texture>>>) {
+        if !self.uniforms.contains_key(&UniformName::Environment) {
+            return;
+        }
+
+        let unchanged = match (self.textures.get(&TextureUnit::Environment), tex) {
+            (Some(current), Some(new)) => Rc::ptr_eq(current, new),
+            (None, None) => true,
+            _ => false,
+        };
+        if !unchanged {
+            self.set_texture(TextureUnit::Environment, tex.cloned());
+        }
+    }
+
     pub fn get_textures(&self) -> &HashMap<TextureUnit, Rc<RefCell<Texture>>> {
         return &self.textures;
     }
 
+    pub fn get_uniform_value(&self, name: UniformName) -> Option<UniformValue> {
+        return self
+            .uniforms
+            .get(&name)
+            .map(|uniform| uniform.value.clone());
+    }
+
     pub fn set_uniform_value(&mut self, name: UniformName, value: UniformValue) {
         if let Some(uniform) = self.uniforms.get_mut(&name) {
             if std::mem::discriminant(&uniform.value) != std::mem::discriminant(&value) {
@@ -498,12 +639,39 @@ impl Material {
                 gl.disable(GL::CULL_FACE);
             } else {
                 gl.enable(GL::CULL_FACE);
+                gl.cull_face(if self.cull_front { GL::FRONT } else { GL::BACK });
+            }
+
+            match self.blend_mode {
+                BlendMode::Opaque => {}
+                BlendMode::Alpha => {
+                    gl.enable(GL::BLEND);
+                    gl.blend_func(GL::SRC_ALPHA, GL::ONE_MINUS_SRC_ALPHA);
+                }
+                BlendMode::Additive => {
+                    gl.enable(GL::BLEND);
+                    gl.blend_func(GL::ONE, GL::ONE);
+                    gl.depth_mask(false);
+                }
             }
         }
     }
 
     pub fn unbind_from_drawing(&self, gl: &glow::Context) {
         unsafe {
+            if self.cull_front {
+                gl.cull_face(GL::BACK);
+            }
+
+            match self.blend_mode {
+                BlendMode::Opaque => {}
+                BlendMode::Alpha => gl.disable(GL::BLEND),
+                BlendMode::Additive => {
+                    gl.disable(GL::BLEND);
+                    gl.depth_mask(true);
+                }
+            }
+
             for (unit, tex) in &self.textures {
                 gl.active_texture(GL::TEXTURE0 + (*unit as u32));
 
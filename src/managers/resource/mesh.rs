@@ -1,7 +1,8 @@
 use crate::managers::resource::collider::Collider;
-use crate::managers::resource::intermediate_mesh::IntermediatePrimitive;
+use crate::managers::resource::intermediate_mesh::{create_primitive_vao, IntermediatePrimitive};
 use crate::managers::resource::material::Material;
 use crate::utils::gl::GL;
+use crate::utils::log::*;
 use crate::utils::memory::any_slice_to_u8_slice;
 use glow::*;
 use std::collections::hash_map::DefaultHasher;
@@ -24,6 +25,45 @@ pub struct Mesh {
     pub dynamic_primitive: Option<DynamicPrimitive>,
     pub collider: Option<Box<dyn Collider>>,
 }
+impl Mesh {
+    /// Recreates our GL objects from the data we kept around, once the WebGL context is restored after being lost
+    pub fn restore_gl_objects(&mut self, ctx: &glow::Context) {
+        for primitive in &mut self.primitives {
+            if let Some(source_data) = &primitive.source_data {
+                let (vao, buffers) = create_primitive_vao(ctx, source_data);
+                primitive.vao = vao;
+                primitive.buffers = buffers;
+            } else {
+                warning!(
+                    LogCat::Resources,
+                    "Can't restore primitive '{}' of mesh '{}' as it has no source data",
+                    primitive.name,
+                    self.name
+                );
+            }
+        }
+
+        if let Some(dynamic_primitive) = &mut self.dynamic_primitive {
+            dynamic_primitive.restore_gl_objects(ctx);
+        }
+    }
+
+    /// Frees the GPU memory of our vertex data. We can't be drawn afterwards
+    pub fn delete_gl_objects(&mut self, ctx: &glow::Context) {
+        for primitive in &mut self.primitives {
+            primitive.delete_gl_objects(ctx);
+        }
+
+        if let Some(dynamic_primitive) = &self.dynamic_primitive {
+            dynamic_primitive.delete_gl_objects(ctx);
+        }
+    }
+
+    /// Whether delete_gl_objects has anything to delete
+    pub fn has_gl_objects(&self) -> bool {
+        return !self.primitives.is_empty() || self.dynamic_primitive.is_some();
+    }
+}
 impl PartialEq for Mesh {
     fn eq(&self, other: &Self) -> bool {
         return self.name == other.name;
@@ -37,6 +77,7 @@ pub struct Primitive {
     pub index_count: i32,
     pub mode: u32,
     pub vao: glow::VertexArray,
+    pub buffers: Vec<glow::Buffer>, // Vertex data that vao points at, which isn't deleted along with it
 
     pub has_tangents: bool,
     pub has_normals: bool,
@@ -45,7 +86,7 @@ pub struct Primitive {
     pub has_uv1: bool,
     pub compatible_hash: u64,
 
-    // We keep these around sometimes in case this mesh is used as a collider
+    // We keep these around in case this mesh is used as a collider, or to rebuild our VAO if the WebGL context is lost
     pub source_data: Option<IntermediatePrimitive>,
 
     pub default_material: Option<Rc<RefCell<Material>>>,
@@ -59,6 +100,15 @@ impl Primitive {
         }
     }
 
+    pub fn delete_gl_objects(&mut self, ctx: &glow::Context) {
+        unsafe {
+            ctx.delete_vertex_array(self.vao);
+            for buffer in self.buffers.drain(..) {
+                ctx.delete_buffer(buffer);
+            }
+        }
+    }
+
     pub fn update_hash(&mut self) {
         let mut hasher = DefaultHasher::new();
         self.hash(&mut hasher);
@@ -87,6 +137,7 @@ pub struct DynamicPrimitive {
 
     pub color_buffer: glow::Buffer,
     color_buffer_data: Vec<f32>,
+    color_buffer_dirty: bool, // Colors rarely change, so we only upload them when needed
 
     last_uploaded_point_count: usize,
 }
@@ -131,11 +182,32 @@ impl DynamicPrimitive {
                 color_buffer,
                 pos_buffer_data: Vec::new(),
                 color_buffer_data: Vec::new(),
+                color_buffer_dirty: false,
                 last_uploaded_point_count: 0,
             }
         }
     }
 
+    pub fn delete_gl_objects(&self, ctx: &glow::Context) {
+        unsafe {
+            ctx.delete_vertex_array(self.vao);
+            ctx.delete_buffer(self.pos_buffer);
+            ctx.delete_buffer(self.color_buffer);
+        }
+    }
+
+    /// Creates new GL objects for our data, once the WebGL context is restored after being lost
+    pub fn restore_gl_objects(&mut self, ctx: &glow::Context) {
+        let restored = DynamicPrimitive::new(ctx);
+        self.vao = restored.vao;
+        self.pos_buffer = restored.pos_buffer;
+        self.color_buffer = restored.color_buffer;
+
+        // Make sure the next upload_buffers allocates storage for both buffers again
+        self.last_uploaded_point_count = 0;
+        self.color_buffer_dirty = true;
+    }
+
     pub fn draw(&self, ctx: &glow::Context) {
         unsafe {
             ctx.bind_vertex_array(Some(self.vao));
@@ -150,7 +222,7 @@ impl DynamicPrimitive {
     }
 
     pub fn get_num_elements(&self) -> usize {
-        return self.pos_buffer_data.len() / 3;
+        return self.pos_buffer_data.len() / 4;
     }
 
     pub fn get_pos_buffer(&self) -> &[f32] {
@@ -166,6 +238,7 @@ impl DynamicPrimitive {
     }
 
     pub fn get_color_buffer_mut(&mut self) -> &mut [f32] {
+        self.color_buffer_dirty = true;
         return self.color_buffer_data.as_mut_slice();
     }
 
@@ -173,21 +246,168 @@ impl DynamicPrimitive {
         unsafe {
             let pos_slice = any_slice_to_u8_slice(&self.pos_buffer_data);
             let color_slice = any_slice_to_u8_slice(&self.color_buffer_data);
+            let num_points = self.get_num_elements();
 
             ctx.bind_buffer(GL::ARRAY_BUFFER, Some(self.pos_buffer));
 
-            if self.pos_buffer_data.len() != self.last_uploaded_point_count {
+            if num_points != self.last_uploaded_point_count {
                 ctx.buffer_data_u8_slice(GL::ARRAY_BUFFER, pos_slice, GL::DYNAMIC_DRAW);
 
                 // If we're resizing also upload our color buffer
                 ctx.bind_buffer(GL::ARRAY_BUFFER, Some(self.color_buffer));
                 ctx.buffer_data_u8_slice(GL::ARRAY_BUFFER, color_slice, GL::DYNAMIC_DRAW);
 
-                self.last_uploaded_point_count = self.pos_buffer_data.len() / 4;
+                self.last_uploaded_point_count = num_points;
             } else {
-                // TODO: What does this even do?
+                // Same size, so we can just overwrite the existing storage
                 ctx.buffer_sub_data_u8_slice(GL::ARRAY_BUFFER, 0, &pos_slice);
+
+                if self.color_buffer_dirty {
+                    ctx.bind_buffer(GL::ARRAY_BUFFER, Some(self.color_buffer));
+                    ctx.buffer_sub_data_u8_slice(GL::ARRAY_BUFFER, 0, &color_slice);
+                }
             }
+
+            self.color_buffer_dirty = false;
+        }
+    }
+}
+
+/// Polyline that is rebuilt every frame (e.g. orbit trails). Each segment is expanded into a quad the same way
+/// expand_lines does it, so that it can be drawn with relay_lines.vert and keep a constant width on screen
+#[derive(Debug)]
+pub struct DynamicLinePrimitive {
+    pub vao: glow::VertexArray,
+
+    pub pos_buffer: glow::Buffer,
+    pos_buffer_data: Vec<f32>,
+
+    pub other_buffer: glow::Buffer,
+    other_buffer_data: Vec<f32>,
+
+    pub color_buffer: glow::Buffer,
+    color_buffer_data: Vec<f32>,
+
+    pub uv1_buffer: glow::Buffer,
+    uv1_buffer_data: Vec<f32>,
+
+    last_uploaded_vertex_count: usize,
+}
+impl DynamicLinePrimitive {
+    pub fn new(ctx: &glow::Context) -> Self {
+        unsafe {
+            let vao = ctx.create_vertex_array().unwrap();
+            ctx.bind_vertex_array(Some(vao));
+
+            let create_attribute_buffer = |attribute: PrimitiveAttribute, size: i32| {
+                let buffer = ctx.create_buffer().unwrap();
+                ctx.bind_buffer(GL::ARRAY_BUFFER, Some(buffer));
+                let location = attribute as u32;
+                ctx.enable_vertex_attrib_array(location);
+                ctx.vertex_attrib_pointer_f32(location, size, GL::FLOAT, false, 0, 0);
+                return buffer;
+            };
+
+            // Position of this end of the segment, position of the other end (like expand_lines), color and uv1
+            let pos_buffer = create_attribute_buffer(PrimitiveAttribute::Position, 3);
+            let other_buffer = create_attribute_buffer(PrimitiveAttribute::Normal, 3);
+            let color_buffer = create_attribute_buffer(PrimitiveAttribute::Color, 4);
+            let uv1_buffer = create_attribute_buffer(PrimitiveAttribute::UV1, 2);
+
+            ctx.bind_vertex_array(None);
+
+            Self {
+                vao,
+                pos_buffer,
+                pos_buffer_data: Vec::new(),
+                other_buffer,
+                other_buffer_data: Vec::new(),
+                color_buffer,
+                color_buffer_data: Vec::new(),
+                uv1_buffer,
+                uv1_buffer_data: Vec::new(),
+                last_uploaded_vertex_count: 0,
+            }
+        }
+    }
+
+    /// Creates new GL objects for our data, once the WebGL context is restored after being lost
+    pub fn restore_gl_objects(&mut self, ctx: &glow::Context) {
+        let restored = DynamicLinePrimitive::new(ctx);
+        self.vao = restored.vao;
+        self.pos_buffer = restored.pos_buffer;
+        self.other_buffer = restored.other_buffer;
+        self.color_buffer = restored.color_buffer;
+        self.uv1_buffer = restored.uv1_buffer;
+
+        // Nothing to draw until we're uploaded again
+        self.last_uploaded_vertex_count = 0;
+    }
+
+    /// Replaces our contents with a line strip going through `points`, each with the matching color in `colors`
+    pub fn set_strip(&mut self, points: &[[f32; 3]], colors: &[[f32; 4]]) {
+        assert!(points.len() == colors.len());
+
+        self.pos_buffer_data.clear();
+        self.other_buffer_data.clear();
+        self.color_buffer_data.clear();
+        self.uv1_buffer_data.clear();
+
+        for segment in 0..points.len().saturating_sub(1) {
+            let start = segment;
+            let end = segment + 1;
+
+            // Start right, start left, end right, end left, with the same triangles as expand_lines
+            for corner in &[0, 2, 1, 1, 2, 3] {
+                let (this, other, direction) = if *corner < 2 {
+                    (start, end, 1.0)
+                } else {
+                    (end, start, -1.0)
+                };
+                let side = if *corner % 2 == 0 { -1.0 } else { 1.0 };
+
+                self.pos_buffer_data.extend_from_slice(&points[this]);
+                self.other_buffer_data.extend_from_slice(&points[other]);
+                self.color_buffer_data.extend_from_slice(&colors[this]);
+                self.uv1_buffer_data.push(side);
+                self.uv1_buffer_data.push(direction);
+            }
+        }
+    }
+
+    pub fn get_num_vertices(&self) -> usize {
+        return self.pos_buffer_data.len() / 3;
+    }
+
+    pub fn upload_buffers(&mut self, ctx: &glow::Context) {
+        unsafe {
+            for (buffer, data) in &[
+                (self.pos_buffer, &self.pos_buffer_data),
+                (self.other_buffer, &self.other_buffer_data),
+                (self.color_buffer, &self.color_buffer_data),
+                (self.uv1_buffer, &self.uv1_buffer_data),
+            ] {
+                ctx.bind_buffer(GL::ARRAY_BUFFER, Some(*buffer));
+                ctx.buffer_data_u8_slice(
+                    GL::ARRAY_BUFFER,
+                    any_slice_to_u8_slice(data.as_slice()),
+                    GL::DYNAMIC_DRAW,
+                );
+            }
+        }
+
+        self.last_uploaded_vertex_count = self.get_num_vertices();
+    }
+
+    pub fn draw(&self, ctx: &glow::Context) {
+        if self.last_uploaded_vertex_count == 0 {
+            return;
+        }
+
+        unsafe {
+            ctx.bind_vertex_array(Some(self.vao));
+            ctx.draw_arrays(GL::TRIANGLES, 0, self.last_uploaded_vertex_count as i32);
+            ctx.bind_vertex_array(None);
         }
     }
 }
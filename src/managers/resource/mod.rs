@@ -1,3 +1,4 @@
+pub mod atlas;
 pub mod collider;
 pub mod gltf;
 pub mod intermediate_mesh;
@@ -1,4 +1,4 @@
-use crate::managers::resource::collider::{AxisAlignedBoxCollider, SphereCollider};
+use crate::managers::resource::collider::{AxisAlignedBoxCollider, MeshCollider, SphereCollider};
 use crate::managers::resource::intermediate_mesh::{
     generate_dynamic_mesh, generate_screen_space_quad,
 };
@@ -274,6 +274,110 @@ pub fn generate_disk(
     });
 }
 
+/// Flat annulus on the XY plane, facing +Z. uv0.x goes from 0 at the inner edge to 1 at the outer edge and uv0.y goes
+/// once around the ring, so that radial strip textures (like the usual planetary ring ones) show up as concentric bands.
+/// It collides with its own triangles (behind its box as an early out), so picking goes through the hole in the middle
+pub fn generate_ring(
+    inner_radius: f32,
+    outer_radius: f32,
+    num_segments: u32,
+    default_material: Option<Rc<RefCell<Material>>>,
+) -> Rc<RefCell<Mesh>> {
+    let prim = generate_ring_primitive(inner_radius, outer_radius, num_segments, default_material);
+
+    let mesh = intermediate_to_mesh(&IntermediateMesh {
+        name: String::from("ring"),
+        primitives: vec![prim],
+    });
+
+    let box_collider = mesh.borrow_mut().collider.take();
+    mesh.borrow_mut().collider = Some(Box::new(MeshCollider {
+        mesh: Rc::downgrade(&mesh),
+        additional_outer_collider: box_collider,
+    }));
+
+    return mesh;
+}
+
+pub fn generate_ring_primitive(
+    mut inner_radius: f32,
+    mut outer_radius: f32,
+    mut num_segments: u32,
+    default_material: Option<Rc<RefCell<Material>>>,
+) -> IntermediatePrimitive {
+    // Two vertices per segment edge, and we need to fit in u16 indices
+    num_segments = num_segments.max(3).min(32766);
+    inner_radius = inner_radius.max(0.0);
+    outer_radius = inner_radius.max(outer_radius);
+
+    let num_verts = ((num_segments + 1) * 2) as usize;
+    let mut positions: Vec<Vector3<f32>> = Vec::new();
+    let mut normals: Vec<Vector3<f32>> = Vec::new();
+    let mut tangents: Vec<Vector3<f32>> = Vec::new();
+    let mut uv0: Vec<Vector2<f32>> = Vec::new();
+    let mut indices: Vec<u16> = Vec::new();
+    positions.reserve(num_verts);
+    normals.resize(num_verts, Vector3::new(0.0, 0.0, 1.0));
+    tangents.reserve(num_verts);
+    uv0.reserve(num_verts);
+    indices.reserve((num_segments * 6) as usize);
+
+    // Extra column of vertices at the seam, so that uv0.y can go all the way to 1.0
+    let step = 2.0 * PI / (num_segments as f32);
+    for seg_index in 0..=num_segments {
+        let angle = (seg_index as f32) * step;
+        let dir = Vector3::new(angle.cos(), angle.sin(), 0.0);
+        let v = (seg_index as f32) / (num_segments as f32);
+
+        positions.push(dir * inner_radius);
+        positions.push(dir * outer_radius);
+        uv0.push(Vector2::new(0.0, v));
+        uv0.push(Vector2::new(1.0, v));
+
+        // Tangents point towards increasing uv0.x, which is radially outwards
+        tangents.push(dir);
+        tangents.push(dir);
+
+        if seg_index < num_segments {
+            let k = (seg_index * 2) as u16;
+            indices.push(k);
+            indices.push(k + 1);
+            indices.push(k + 2);
+
+            indices.push(k + 2);
+            indices.push(k + 1);
+            indices.push(k + 3);
+        }
+    }
+
+    debug!(
+        LogCat::Resources,
+        "Generating ring: Inner radius: {}, Outer radius: {}, Num segments: {}, Vertices: {}, Indices: {}",
+        inner_radius,
+        outer_radius,
+        num_segments,
+        positions.len(),
+        indices.len(),
+    );
+
+    return IntermediatePrimitive {
+        name: String::from("0"),
+        indices,
+        positions,
+        normals,
+        tangents,
+        colors: vec![],
+        uv0,
+        uv1: vec![],
+        mat: default_material,
+        mode: GL::TRIANGLES,
+        collider: Some(Box::new(AxisAlignedBoxCollider {
+            mins: Point3::new(-outer_radius, -outer_radius, -0.001),
+            maxes: Point3::new(outer_radius, outer_radius, 0.001),
+        })),
+    };
+}
+
 // Praise songho: http://www.songho.ca/opengl/gl_sphere.html
 pub fn generate_lat_long_sphere(
     mut num_lat_segs: u32,
@@ -499,6 +603,22 @@ pub fn generate_lat_long_sphere(
     });
 }
 
+/// Chain of `num_lods` lat-long spheres to use as the levels of detail of a mesh, from most to least detailed.
+/// The first one has `max_segs` latitudinal and longitudinal segments, and each next one has half as many
+pub fn generate_lat_long_sphere_lods(
+    max_segs: u32,
+    num_lods: u32,
+    radius: f32,
+    default_material: Option<Rc<RefCell<Material>>>,
+) -> Vec<Rc<RefCell<Mesh>>> {
+    return (0..num_lods)
+        .map(|lod| {
+            let segs = (max_segs >> lod).max(3);
+            generate_lat_long_sphere(segs, segs, radius, true, true, default_material.clone())
+        })
+        .collect();
+}
+
 // Praise songho: http://www.songho.ca/opengl/gl_sphere.html
 // https://schneide.blog/2016/07/15/generating-an-icosphere-in-c/
 // TODO: Fix weirdness when radius < 0
@@ -1101,7 +1221,7 @@ pub fn generate_grid(
 
     return intermediate_to_mesh(&IntermediateMesh {
         name: String::from("grid"),
-        primitives: vec![IntermediatePrimitive {
+        primitives: vec![expand_lines(&IntermediatePrimitive {
             name: String::from("0"),
             indices,
             positions,
@@ -1113,7 +1233,7 @@ pub fn generate_grid(
             mat: default_material,
             mode: GL::LINES,
             collider: None,
-        }],
+        })],
     });
 }
 
@@ -1123,37 +1243,53 @@ pub fn generate_circle(
 ) -> Rc<RefCell<Mesh>> {
     assert!(num_pts > 2);
 
+    let incr: f32 = 1.0 / num_pts as f32;
+    let angles: Vec<f32> = (0..=num_pts)
+        .map(|i| (2.0 * std::f32::consts::PI) * incr * i as f32)
+        .collect();
+
+    return generate_circle_from_angles("circle", &angles, default_material);
+}
+
+/// Unit circle line through each of `angles` (radians). The first and last angles should be 0 and 2pi,
+/// so that the line closes
+pub fn generate_circle_from_angles(
+    name: &str,
+    angles: &[f32],
+    default_material: Option<Rc<RefCell<Material>>>,
+) -> Rc<RefCell<Mesh>> {
+    assert!(angles.len() > 3);
+    let num_verts = angles.len();
+
+    // We repeat the first vertex at the end with uv0.x = 1.0 so that the last segment
+    // doesn't interpolate its uv0 from ~1.0 back to 0.0
     let mut positions: Vec<Vector3<f32>> = Vec::new();
-    positions.reserve((num_pts) as usize);
+    positions.reserve(num_verts);
 
     // TODO: Remove this and use a different shader
     let mut colors: Vec<Vector4<f32>> = Vec::new();
-    colors.resize((num_pts) as usize, Vector4::new(1.0, 1.0, 1.0, 1.0));
+    colors.resize(num_verts, Vector4::new(1.0, 1.0, 1.0, 1.0));
 
-    // uv0.x gives angle to true anomaly 0 degrees
+    // uv0.x gives the fraction of the full turn from angle 0 (eccentric anomaly, when shaped into an orbit)
     let mut uv0: Vec<Vector2<f32>> = Vec::new();
-    uv0.reserve((num_pts) as usize);
+    uv0.reserve(num_verts);
 
     let mut indices: Vec<u16> = Vec::new();
-    indices.reserve((num_pts * 2) as usize);
+    indices.reserve((num_verts - 1) * 2);
 
-    let incr: f32 = 1.0 / num_pts as f32;
-    for i in 0..num_pts {
-        let sum_angle = (2.0 * std::f32::consts::PI) * incr * i as f32;
+    for (i, angle) in angles.iter().enumerate() {
+        positions.push(Vector3::new(angle.cos(), angle.sin(), 0.0));
+        uv0.push(Vector2::new(angle / (2.0 * std::f32::consts::PI), 0.0));
 
-        positions.push(Vector3::new(sum_angle.cos(), sum_angle.sin(), 0.0));
-        uv0.push(Vector2::new(incr, 0.0));
-
-        indices.push(i as u16);
-        indices.push((i + 1) as u16);
+        if i < num_verts - 1 {
+            indices.push(i as u16);
+            indices.push((i + 1) as u16);
+        }
     }
 
-    let last_index = indices.len() - 1;
-    indices[last_index] = 0;
-
     return intermediate_to_mesh(&IntermediateMesh {
-        name: String::from("circle"),
-        primitives: vec![IntermediatePrimitive {
+        name: String::from(name),
+        primitives: vec![expand_lines(&IntermediatePrimitive {
             name: String::from("0"),
             indices,
             positions,
@@ -1165,14 +1301,14 @@ pub fn generate_circle(
             mat: default_material,
             mode: GL::LINES,
             collider: None,
-        }],
+        })],
     });
 }
 
 pub fn generate_axes(default_material: Option<Rc<RefCell<Material>>>) -> Rc<RefCell<Mesh>> {
     intermediate_to_mesh(&IntermediateMesh {
         name: String::from("axes"),
-        primitives: vec![IntermediatePrimitive {
+        primitives: vec![expand_lines(&IntermediatePrimitive {
             name: String::from("0"),
             indices: vec![0, 1, 0, 2, 0, 3],
             positions: vec![
@@ -1194,10 +1330,107 @@ pub fn generate_axes(default_material: Option<Rc<RefCell<Material>>>) -> Rc<RefC
             mode: GL::LINES,
             mat: default_material,
             collider: None,
-        }],
+        })],
     })
 }
 
+/// Turns a GL::LINES primitive into a GL::TRIANGLES primitive with one quad per segment, meant to be drawn
+/// with relay_lines.vert, which extrudes each quad on screen so that lines have a constant width in pixels.
+/// Every vertex gets the other end of its segment as its normal, and uv1 = (side, 1 or -1 for start or end)
+pub fn expand_lines(prim: &IntermediatePrimitive) -> IntermediatePrimitive {
+    assert!(prim.mode == GL::LINES);
+
+    let num_verts = prim.indices.len() * 2;
+    assert!(num_verts <= std::u16::MAX as usize);
+
+    let mut positions: Vec<Vector3<f32>> = Vec::new();
+    positions.reserve(num_verts);
+
+    let mut normals: Vec<Vector3<f32>> = Vec::new();
+    normals.reserve(num_verts);
+
+    let mut colors: Vec<Vector4<f32>> = Vec::new();
+    let mut uv0: Vec<Vector2<f32>> = Vec::new();
+
+    let mut uv1: Vec<Vector2<f32>> = Vec::new();
+    uv1.reserve(num_verts);
+
+    let mut indices: Vec<u16> = Vec::new();
+    indices.reserve(prim.indices.len() * 3);
+
+    for segment in prim.indices.chunks_exact(2) {
+        let start = segment[0] as usize;
+        let end = segment[1] as usize;
+        let first_index = positions.len() as u16;
+
+        // Start right, start left, end right, end left
+        for (this, other, direction) in &[(start, end, 1.0), (end, start, -1.0)] {
+            for side in &[-1.0, 1.0] {
+                positions.push(prim.positions[*this]);
+                normals.push(prim.positions[*other]);
+                uv1.push(Vector2::new(*side, *direction));
+
+                if !prim.colors.is_empty() {
+                    colors.push(prim.colors[*this]);
+                }
+                if !prim.uv0.is_empty() {
+                    uv0.push(prim.uv0[*this]);
+                }
+            }
+        }
+
+        indices.push(first_index + 0);
+        indices.push(first_index + 2);
+        indices.push(first_index + 1);
+        indices.push(first_index + 1);
+        indices.push(first_index + 2);
+        indices.push(first_index + 3);
+    }
+
+    return IntermediatePrimitive {
+        name: prim.name.clone(),
+        indices,
+        positions,
+        normals,
+        tangents: vec![],
+        colors,
+        uv0,
+        uv1,
+        mat: prim.mat.clone(),
+        mode: GL::TRIANGLES,
+        collider: prim.collider.clone(),
+    };
+}
+
 pub fn generate_points() -> Rc<RefCell<Mesh>> {
     return generate_dynamic_mesh();
 }
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    pub fn ring_uvs_are_radial() {
+        let inner = 0.5;
+        let outer = 1.2;
+        let prim = generate_ring_primitive(inner, outer, 64, None);
+
+        // uv0.x depends only on the distance to the center, so a strip texture sampled along it gives concentric bands
+        for (pos, uv) in prim.positions.iter().zip(prim.uv0.iter()) {
+            let expected_u = (pos.magnitude() - inner) / (outer - inner);
+            assert!((uv.x - expected_u).abs() < 1E-5);
+        }
+
+        // Every triangle should face +Z, matching the normals
+        for tri in prim.indices.chunks(3) {
+            let a = prim.positions[tri[0] as usize];
+            let b = prim.positions[tri[1] as usize];
+            let c = prim.positions[tri[2] as usize];
+            assert!((b - a).cross(&(c - a)).z > 0.0);
+        }
+    }
+}
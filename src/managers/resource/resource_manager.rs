@@ -1,11 +1,16 @@
-use crate::managers::resource::material::Material;
+use crate::app_state::{add_global_toast, ToastSeverity};
 use crate::managers::resource::material::UniformName;
+use crate::managers::resource::material::{BlendMode, Material};
 use crate::managers::resource::mesh::Mesh;
 use crate::managers::resource::procedural_meshes::*;
-use crate::managers::resource::texture::Texture;
+use crate::managers::resource::shaders::{resolve_shader_includes, set_shader_override};
+use crate::managers::resource::texture::{
+    get_texture_internal_format, Texture, TextureSource, TextureUnit,
+};
 use crate::utils::gl::GL;
 use crate::utils::hashmap::InsertOrGet;
 use crate::utils::log::*;
+use crate::utils::orbits::adaptive_eccentric_anomalies;
 use crate::utils::string::{get_unique_name, remove_numbered_suffix};
 use crate::utils::web::request_bytes;
 use crate::{ENGINE, GLCTX};
@@ -14,10 +19,42 @@ use glow::*;
 use image::{io::Reader, DynamicImage};
 use std::path::PathBuf;
 use std::rc::Weak;
-use std::{cell::RefCell, collections::HashMap, io::Cursor, rc::Rc};
+use std::{cell::RefCell, collections::HashMap, collections::HashSet, io::Cursor, rc::Rc};
 use wasm_bindgen::JsValue;
 use wasm_bindgen_futures::spawn_local;
 
+const ORBIT_LINE_SAMPLES: u32 = 100;
+
+/// Removes and returns the resources of `resources` that nothing else holds a reference to
+fn remove_unused<T>(resources: &mut HashMap<String, Rc<RefCell<T>>>) -> Vec<Rc<RefCell<T>>> {
+    let unused: Vec<String> = resources
+        .iter()
+        .filter(|(_, resource)| Rc::strong_count(resource) == 1)
+        .map(|(identifier, _)| identifier.clone())
+        .collect();
+
+    return unused
+        .iter()
+        .filter_map(|identifier| resources.remove(identifier))
+        .collect();
+}
+
+/// Programs of the `dropped` materials that none of the `kept` ones share, each only once. Material instances
+/// are clones of their masters, so several materials can hold the same program
+fn get_orphaned_programs<P: Copy + PartialEq>(dropped: &[Option<P>], kept: &[Option<P>]) -> Vec<P> {
+    let mut orphaned: Vec<P> = Vec::new();
+    for program in dropped.iter().flatten() {
+        if !kept.contains(&Some(*program)) && !orphaned.contains(program) {
+            orphaned.push(*program);
+        }
+    }
+    return orphaned;
+}
+
+/// Segments of the most detailed body sphere, and how many levels of detail it has in total
+const BODY_SPHERE_SEGMENTS: u32 = 64;
+const NUM_BODY_SPHERE_LODS: u32 = 4;
+
 pub(super) fn load_texture_from_bytes(
     identifier: &str,
     width: u32,
@@ -29,10 +66,57 @@ pub(super) fn load_texture_from_bytes(
     min_filter: Option<i32>,
     wrap_s: Option<i32>,
     wrap_t: Option<i32>,
+    srgb: bool,
+) -> Result<Rc<RefCell<Texture>>, String> {
+    let source = Rc::new(TextureSource::Pixels {
+        data: data.to_vec(),
+        mag_filter,
+        min_filter,
+        wrap_s,
+        wrap_t,
+    });
+
+    return create_texture_2d(
+        identifier,
+        width,
+        height,
+        num_channels,
+        format,
+        data,
+        mag_filter,
+        min_filter,
+        wrap_s,
+        wrap_t,
+        srgb,
+        source,
+    );
+}
+
+/// Logs and shows that fetching the asset at `url` failed. Whatever was waiting for it keeps its placeholder
+fn report_fetch_failure(url: &str, err: &JsValue) {
+    error!(LogCat::Io, "Failed to fetch asset '{}': {:?}", url, err);
+    add_global_toast(format!("Failed to fetch '{}'", url), ToastSeverity::Error);
+}
+
+fn create_texture_2d(
+    identifier: &str,
+    width: u32,
+    height: u32,
+    num_channels: u8,
+    format: u32,
+    data: &[u8],
+    mag_filter: Option<i32>,
+    min_filter: Option<i32>,
+    wrap_s: Option<i32>,
+    wrap_t: Option<i32>,
+    srgb: bool,
+    source: Rc<TextureSource>,
 ) -> Result<Rc<RefCell<Texture>>, String> {
+    let internal_format = get_texture_internal_format(format, srgb);
+
     return GLCTX.with(|ctx| {
         unsafe {
-            let gl_tex = ctx.create_texture().unwrap();
+            let gl_tex = ctx.create_texture()?;
             ctx.active_texture(GL::TEXTURE0);
             ctx.bind_texture(GL::TEXTURE_2D, Some(gl_tex));
 
@@ -60,7 +144,7 @@ pub(super) fn load_texture_from_bytes(
             ctx.tex_image_2d(
                 GL::TEXTURE_2D,
                 0,
-                format as i32,
+                internal_format as i32,
                 width as i32,
                 height as i32,
                 0,
@@ -76,9 +160,11 @@ pub(super) fn load_texture_from_bytes(
                 width,
                 height,
                 gl_format: format,
+                srgb: internal_format != format,
                 num_channels,
                 gl_handle: Some(gl_tex),
                 is_cubemap: false,
+                source: Some(source),
             })));
         }
     });
@@ -87,6 +173,7 @@ pub(super) fn load_texture_from_bytes(
 fn load_texture_from_image_bytes(
     identifier: &str,
     bytes: &[u8],
+    srgb: bool,
 ) -> Result<Rc<RefCell<Texture>>, String> {
     let reader = Reader::new(Cursor::new(bytes))
         .with_guessed_format()
@@ -159,7 +246,7 @@ fn load_texture_from_image_bytes(
         identifier
     ))?;
 
-    return load_texture_from_bytes(
+    return create_texture_2d(
         identifier,
         width,
         height,
@@ -170,6 +257,8 @@ fn load_texture_from_image_bytes(
         None,
         None,
         None,
+        srgb,
+        Rc::new(TextureSource::Image(bytes.to_vec())),
     );
 }
 
@@ -272,7 +361,7 @@ fn load_cubemap_texture_from_image_bytes(
 ) -> Result<Rc<RefCell<Texture>>, String> {
     let gl_tex: Option<glow::Texture>;
     unsafe {
-        gl_tex = Some(ctx.create_texture().unwrap());
+        gl_tex = Some(ctx.create_texture()?);
         ctx.active_texture(GL::TEXTURE0);
         ctx.bind_texture(GL::TEXTURE_CUBE_MAP, gl_tex);
     }
@@ -302,10 +391,8 @@ fn load_cubemap_texture_from_image_bytes(
         }
     }
 
-    // Dump our input bytes now that the texture is created/failed
-    for face in cubemap.faces.iter_mut() {
-        face.clear();
-    }
+    // Move our input bytes into the texture now that it is created/failed, in case we need to upload it again
+    let faces = std::mem::take(&mut cubemap.faces);
     cubemap.completed = false;
 
     unsafe {
@@ -328,10 +415,56 @@ fn load_cubemap_texture_from_image_bytes(
         gl_format: format,
         num_channels,
         gl_handle: gl_tex,
+        srgb: false,
         is_cubemap: true,
+        source: Some(Rc::new(TextureSource::CubemapFaces(faces))),
     })));
 }
 
+/// Creates a new texture with the same contents as `tex`, from the source it was created with. `srgb` picks
+/// whether to store it as sRGB, like for load_texture_from_bytes
+fn reupload_texture(
+    tex: &Texture,
+    srgb: bool,
+    ctx: &glow::Context,
+) -> Result<Rc<RefCell<Texture>>, String> {
+    let source = tex
+        .source
+        .as_ref()
+        .ok_or(format!("Texture '{}' has no source to upload", tex.name))?;
+
+    return match source.as_ref() {
+        TextureSource::Pixels {
+            data,
+            mag_filter,
+            min_filter,
+            wrap_s,
+            wrap_t,
+        } => create_texture_2d(
+            &tex.name,
+            tex.width,
+            tex.height,
+            tex.num_channels,
+            tex.gl_format,
+            data,
+            *mag_filter,
+            *min_filter,
+            *wrap_s,
+            *wrap_t,
+            srgb,
+            source.clone(),
+        ),
+        TextureSource::Image(bytes) => load_texture_from_image_bytes(&tex.name, bytes, srgb),
+        TextureSource::CubemapFaces(faces) => {
+            let mut cubemap = TempCubemap {
+                faces: faces.clone(),
+                completed: true,
+            };
+            load_cubemap_texture_from_image_bytes(&tex.name, &mut cubemap, ctx)
+        }
+    };
+}
+
 #[derive(Default)]
 pub struct TempCubemap {
     faces: [Vec<u8>; 6],
@@ -389,6 +522,12 @@ pub struct ResourceManager {
     pub(super) temp_cubemaps: HashMap<String, TempCubemap>,
 
     pub(super) default_texture: Option<Weak<RefCell<Texture>>>,
+
+    // Identifiers of the textures that were requested for a color TextureUnit, and so get loaded as sRGB
+    pub(super) srgb_textures: HashSet<String>,
+
+    // Only ever goes up, so that new atlases never take the name of one that is still alive
+    pub(super) num_atlases_created: u32,
 }
 impl ResourceManager {
     pub fn new() -> Self {
@@ -398,11 +537,178 @@ impl ResourceManager {
             materials: HashMap::new(),
             temp_cubemaps: HashMap::new(),
             default_texture: None,
+            srgb_textures: HashSet::new(),
+            num_atlases_created: 0,
         };
 
         return new_res_man;
     }
 
+    /// Recreates all of our GL objects from the data we kept for them, once the WebGL context is restored
+    /// after being lost. Every GL handle we had before that is invalid
+    pub fn restore_gl_resources(&mut self) {
+        GLCTX.with(|ctx| {
+            for tex in self.textures.values() {
+                if tex.borrow().source.is_none() {
+                    continue;
+                }
+
+                let srgb = tex.borrow().srgb;
+                let restored = reupload_texture(&tex.borrow(), srgb, ctx);
+                match restored {
+                    Ok(restored) => tex.swap(&restored),
+                    Err(err) => error!(
+                        LogCat::Resources,
+                        "Failed to restore texture '{}': {}",
+                        tex.borrow().name,
+                        err
+                    ),
+                }
+            }
+
+            // These will recompile from their shader sources the next time they're drawn
+            for mat in self.materials.values() {
+                mat.borrow_mut().invalidate_program();
+            }
+
+            for mesh in self.meshes.values() {
+                mesh.borrow_mut().restore_gl_objects(ctx);
+            }
+        });
+
+        info!(
+            LogCat::Resources,
+            "Restored {} textures, {} materials and {} meshes",
+            self.textures.len(),
+            self.materials.len(),
+            self.meshes.len()
+        );
+    }
+
+    /// Drops the meshes, materials and textures that nothing but us holds on to anymore (e.g. the ones only the last
+    /// scene used), deleting their GL objects to free GPU memory. Anything requested again later gets generated or
+    /// fetched again, like the first time. Returns how many resources were dropped
+    pub fn collect_unused(&mut self) -> usize {
+        // Meshes hold on to their default materials, and materials to their textures, so we go in that order
+        let meshes = remove_unused(&mut self.meshes);
+        if meshes.iter().any(|mesh| mesh.borrow().has_gl_objects()) {
+            GLCTX.with(|ctx| {
+                for mesh in &meshes {
+                    mesh.borrow_mut().delete_gl_objects(ctx);
+                }
+            });
+        }
+        let num_meshes = meshes.len();
+        drop(meshes);
+
+        // The last material holding on to a program takes it with it
+        let materials = remove_unused(&mut self.materials);
+        let dropped_programs: Vec<Option<glow::Program>> = materials
+            .iter()
+            .map(|mat| mat.borrow().get_program())
+            .collect();
+        let kept_programs: Vec<Option<glow::Program>> = self
+            .materials
+            .values()
+            .map(|mat| mat.borrow().get_program())
+            .collect();
+        let programs = get_orphaned_programs(&dropped_programs, &kept_programs);
+        if !programs.is_empty() {
+            GLCTX.with(|ctx| unsafe {
+                for program in programs {
+                    ctx.delete_program(program);
+                }
+            });
+        }
+        let num_materials = materials.len();
+        drop(materials);
+
+        // Only a weak reference keeps the default texture around, and the placeholders share its GL texture
+        let default_texture = self.default_texture.as_ref().and_then(|tex| tex.upgrade());
+        let default_handle = default_texture
+            .as_ref()
+            .and_then(|tex| tex.borrow().gl_handle);
+        let textures = remove_unused(&mut self.textures);
+        let handles: Vec<glow::Texture> = textures
+            .iter()
+            .filter_map(|tex| tex.borrow().gl_handle)
+            .filter(|handle| Some(*handle) != default_handle)
+            .collect();
+        if !handles.is_empty() {
+            GLCTX.with(|ctx| unsafe {
+                for handle in handles {
+                    ctx.delete_texture(handle);
+                }
+            });
+        }
+
+        let num_collected = num_meshes + num_materials + textures.len();
+        if num_collected > 0 {
+            info!(
+                LogCat::Resources,
+                "Dropped {} unused meshes, {} materials and {} textures",
+                num_meshes,
+                num_materials,
+                textures.len()
+            );
+        }
+        return num_collected;
+    }
+
+    /// How many meshes, materials and textures we're keeping track of
+    pub fn get_num_resources(&self) -> usize {
+        return self.meshes.len() + self.materials.len() + self.textures.len();
+    }
+
+    /// Makes every material using shader `shader_name` (e.g. "phong.frag") compile `source` for it instead.
+    /// `source` is first checked against each of those materials and nothing changes if any of them fails to
+    /// compile or link with it. Returns how many materials will recompile
+    pub fn recompile_shader(&mut self, shader_name: &str, source: &str) -> Result<usize, String> {
+        let resolved_source = resolve_shader_includes(source)?;
+
+        let users: Vec<&Rc<RefCell<Material>>> = self
+            .materials
+            .values()
+            .filter(|mat| mat.borrow().uses_shader(shader_name))
+            .collect();
+        if users.is_empty() {
+            return Err(format!("No material uses shader '{}'", shader_name));
+        }
+
+        GLCTX.with(|ctx| -> Result<(), String> {
+            for mat in users.iter() {
+                let mat = mat.borrow();
+                mat.test_shader_source(ctx, shader_name, &resolved_source)
+                    .map_err(|err| format!("Material '{}': {}", mat.get_name(), err))?;
+            }
+            return Ok(());
+        })?;
+
+        set_shader_override(shader_name, Some(resolved_source));
+        for mat in users.iter() {
+            mat.borrow_mut().invalidate_program();
+        }
+
+        info!(
+            LogCat::Resources,
+            "Recompiling {} materials with new source for shader '{}'",
+            users.len(),
+            shader_name
+        );
+        return Ok(users.len());
+    }
+
+    /// Goes back to the baked source of shader `shader_name` after recompile_shader
+    pub fn revert_shader(&mut self, shader_name: &str) {
+        set_shader_override(shader_name, None);
+
+        for mat in self.materials.values() {
+            if mat.borrow().uses_shader(shader_name) {
+                mat.borrow_mut().invalidate_program();
+            }
+        }
+    }
+
     pub fn get_mesh(&self, identifier: &str) -> Option<Rc<RefCell<Mesh>>> {
         if let Some(mesh) = self.meshes.get(identifier) {
             return Some(mesh.clone());
@@ -424,20 +730,42 @@ impl ResourceManager {
             "quad" => Some(generate_canvas_quad(default_mat_quad)),
             "cube" => Some(generate_cube(default_mat)),
             "plane" => Some(generate_plane(default_mat)),
-            "grid" => Some(generate_grid(11, default_mat)),
-            "axes" => Some(generate_axes(self.get_or_create_material("vertex_color"))),
-            "circle" => Some(generate_circle(100, default_mat)),
+            "grid" => Some(generate_grid(11, self.instantiate_material("line", "line"))),
+            "axes" => Some(generate_axes(self.instantiate_material("line", "line"))),
+            "circle" => Some(generate_circle(
+                100,
+                self.instantiate_material("line", "line"),
+            )),
             "disk" => Some(generate_disk(4, 128, 0.5, 1.0, true, default_mat)),
-            "lat_long_sphere" => Some(generate_lat_long_sphere(
-                32,
-                32,
+            // Inner radius is the C ring's inner edge, with the F ring as the outer edge
+            "ring" => Some(generate_ring(0.532, 1.0, 256, default_mat)),
+            // The coarser levels of detail get stored as "lat_long_sphere_lod1" and so on
+            "lat_long_sphere" => {
+                let mut lods = generate_lat_long_sphere_lods(
+                    BODY_SPHERE_SEGMENTS,
+                    NUM_BODY_SPHERE_LODS,
+                    1.0,
+                    default_mat,
+                );
+                for (lod, lod_mesh) in lods.drain(1..).enumerate() {
+                    let lod_name = format!("{}_lod{}", identifier, lod + 1);
+                    lod_mesh.borrow_mut().name = lod_name.clone();
+                    self.meshes.insert(lod_name, lod_mesh);
+                }
+                lods.pop()
+            }
+            // Denser than the body spheres, as the glow changes quickly along the limb
+            "atmosphere_shell" => Some(generate_lat_long_sphere(
+                64,
+                64,
                 1.0,
                 true,
                 true,
-                default_mat,
+                self.instantiate_material("atmosphere", "atmosphere"),
             )),
             "ico_sphere" => Some(generate_ico_sphere(1.0, 2, false, default_mat)),
             "points" => Some(generate_points()),
+            "starfield" => Some(generate_points()),
             _ => None,
         };
 
@@ -454,7 +782,10 @@ impl ResourceManager {
 
         let internal_full_path = full_path.clone();
         spawn_local(async move {
-            let mut vec = request_bytes(&internal_full_path).await.unwrap();
+            let mut vec = match request_bytes(&internal_full_path).await {
+                Ok(vec) => vec,
+                Err(err) => return report_fetch_failure(&internal_full_path, &err),
+            };
 
             ENGINE.with(|e| {
                 let mut ref_mut = e.borrow_mut();
@@ -470,6 +801,38 @@ impl ResourceManager {
         return temp_mesh;
     }
 
+    /// Coarser versions of the mesh `identifier` to draw when it's small on screen, if we generated those along with
+    /// it. See MeshComponent::set_lod_meshes
+    pub fn get_lod_meshes(&self, identifier: &str) -> Vec<Rc<RefCell<Mesh>>> {
+        let mut result = Vec::new();
+        while let Some(lod_mesh) = self.get_mesh(&format!("{}_lod{}", identifier, result.len() + 1))
+        {
+            result.push(lod_mesh);
+        }
+        return result;
+    }
+
+    /// Returns a unit circle line mesh for orbit lines, with its vertices concentrated where an orbit with
+    /// `eccentricity` curves the most. Eccentricities are bucketed so that similar orbits share a mesh
+    pub fn get_or_create_orbit_mesh(&mut self, eccentricity: f64) -> Option<Rc<RefCell<Mesh>>> {
+        let bucket = (eccentricity.max(0.0).min(0.99) * 100.0).round() / 100.0;
+        let identifier = format!("orbit_circle_{:.2}", bucket);
+        if let Some(mesh) = self.get_mesh(&identifier) {
+            return Some(mesh);
+        }
+
+        let angles: Vec<f32> = adaptive_eccentric_anomalies(bucket, ORBIT_LINE_SAMPLES)
+            .iter()
+            .map(|angle| angle.0 as f32)
+            .collect();
+        let line_mat = self.instantiate_material("line", "line");
+        let mesh = generate_circle_from_angles(&identifier, &angles, line_mat);
+
+        debug!(LogCat::Resources, "Generated mesh '{}'", identifier);
+        self.meshes.insert(identifier, mesh.clone());
+        return Some(mesh);
+    }
+
     pub fn get_material(&self, identifier: &str) -> Option<Rc<RefCell<Material>>> {
         if let Some(mat) = self.materials.get(identifier) {
             return Some(mat.clone());
@@ -524,20 +887,32 @@ impl ResourceManager {
                 identifier,
                 "relay_color.vert",
                 "white.frag",
-                &[UniformName::WVPTrans, UniformName::Fcoef],
+                &[
+                    UniformName::WVPTrans,
+                    UniformName::Fcoef,
+                    UniformName::Tonemap,
+                ],
             )),
             "default_screenspace" => Some(Material::new(
                 identifier,
                 "screenspace.vert",
                 "screenspace.frag",
-                &[UniformName::BaseColor],
-            )),
-            "default_points" => Some(Material::new(
-                identifier,
-                "relay_points.vert",
-                "color.frag",
-                &[UniformName::Fcoef],
+                &[UniformName::BaseColor, UniformName::Tonemap],
             )),
+            "default_points" => {
+                let mut mat = Material::new(
+                    identifier,
+                    "relay_points.vert",
+                    "points.frag",
+                    &[
+                        UniformName::Fcoef,
+                        UniformName::Tonemap,
+                        UniformName::PointGlow,
+                    ],
+                );
+                mat.blend_mode = BlendMode::Alpha;
+                Some(mat)
+            }
             "skybox" => Some(Material::new(
                 identifier,
                 "screenspace.vert",
@@ -548,8 +923,68 @@ impl ResourceManager {
                 identifier,
                 "relay_color.vert",
                 "color.frag",
-                &[UniformName::WVPTrans, UniformName::Fcoef],
+                &[
+                    UniformName::WVPTrans,
+                    UniformName::Fcoef,
+                    UniformName::Tonemap,
+                ],
             )),
+            "orbit" => {
+                let mut mat = Material::new(
+                    identifier,
+                    "relay_lines.vert",
+                    "orbit.frag",
+                    &[
+                        UniformName::WVPTrans,
+                        UniformName::BaseColorFactor,
+                        UniformName::OrbitFraction,
+                        UniformName::OrbitEccentricity,
+                        UniformName::OrbitFade,
+                        UniformName::Fcoef,
+                        UniformName::Tonemap,
+                        UniformName::LineWidth,
+                        UniformName::ViewportSize,
+                    ],
+                );
+                mat.double_sided = true;
+                mat.blend_mode = BlendMode::Alpha;
+                Some(mat)
+            }
+            "line" => {
+                let mut mat = Material::new(
+                    identifier,
+                    "relay_lines.vert",
+                    "line.frag",
+                    &[
+                        UniformName::WVPTrans,
+                        UniformName::Fcoef,
+                        UniformName::Tonemap,
+                        UniformName::LineWidth,
+                        UniformName::ViewportSize,
+                        UniformName::BaseColorFactor,
+                    ],
+                );
+                mat.double_sided = true;
+                mat.blend_mode = BlendMode::Alpha;
+                Some(mat)
+            }
+            "outline" => {
+                let mut mat = Material::new(
+                    identifier,
+                    "relay_outline.vert",
+                    "outline.frag",
+                    &[
+                        UniformName::WVPTrans,
+                        UniformName::Fcoef,
+                        UniformName::Tonemap,
+                        UniformName::LineWidth,
+                        UniformName::ViewportSize,
+                        UniformName::BaseColorFactor,
+                    ],
+                );
+                mat.cull_front = true;
+                Some(mat)
+            }
             "local_normals" => Some(Material::new(
                 identifier,
                 "relay_locals.vert",
@@ -559,6 +994,7 @@ impl ResourceManager {
                     UniformName::WVInvTranspTrans,
                     UniformName::WVPTrans,
                     UniformName::Fcoef,
+                    UniformName::Tonemap,
                 ],
             )),
             "world_normals" => Some(Material::new(
@@ -570,6 +1006,7 @@ impl ResourceManager {
                     UniformName::WVInvTranspTrans,
                     UniformName::WVPTrans,
                     UniformName::Fcoef,
+                    UniformName::Tonemap,
                 ],
             )),
             "local_tangents" => Some(Material::new(
@@ -581,6 +1018,7 @@ impl ResourceManager {
                     UniformName::WVInvTranspTrans,
                     UniformName::WVPTrans,
                     UniformName::Fcoef,
+                    UniformName::Tonemap,
                 ],
             )),
             "world_tangents" => Some(Material::new(
@@ -592,6 +1030,7 @@ impl ResourceManager {
                     UniformName::WVInvTranspTrans,
                     UniformName::WVPTrans,
                     UniformName::Fcoef,
+                    UniformName::Tonemap,
                 ],
             )),
             "uv0" => Some(Material::new(
@@ -603,6 +1042,7 @@ impl ResourceManager {
                     UniformName::WVInvTranspTrans,
                     UniformName::WVPTrans,
                     UniformName::Fcoef,
+                    UniformName::Tonemap,
                 ],
             )),
             "uv1" => Some(Material::new(
@@ -614,6 +1054,7 @@ impl ResourceManager {
                     UniformName::WVInvTranspTrans,
                     UniformName::WVPTrans,
                     UniformName::Fcoef,
+                    UniformName::Tonemap,
                 ],
             )),
             "basecolor" => Some(Material::new(
@@ -626,8 +1067,28 @@ impl ResourceManager {
                     UniformName::WVPTrans,
                     UniformName::BaseColor,
                     UniformName::Fcoef,
+                    UniformName::Tonemap,
                 ],
             )),
+            "atmosphere" => {
+                let mut mat = Material::new(
+                    identifier,
+                    "relay_all.vert",
+                    "atmosphere.frag",
+                    &[
+                        UniformName::WVTrans,
+                        UniformName::WVInvTranspTrans,
+                        UniformName::WVPTrans,
+                        UniformName::LightTypes,
+                        UniformName::LightPosDir,
+                        UniformName::BaseColorFactor,
+                        UniformName::AtmosphereThickness,
+                        UniformName::Fcoef,
+                    ],
+                );
+                mat.blend_mode = BlendMode::Additive;
+                Some(mat)
+            }
             "phong" => Some(Material::new(
                 identifier,
                 "relay_all.vert",
@@ -640,7 +1101,10 @@ impl ResourceManager {
                     UniformName::LightPosDir,
                     UniformName::LightColors,
                     UniformName::LightIntensities,
+                    UniformName::OccluderPosRadius,
+                    UniformName::LightRadius,
                     UniformName::Fcoef,
+                    UniformName::Tonemap,
                 ],
             )),
             "gltf_metal_rough" => Some(Material::new(
@@ -655,6 +1119,8 @@ impl ResourceManager {
                     UniformName::LightPosDir,
                     UniformName::LightColors,
                     UniformName::LightIntensities,
+                    UniformName::OccluderPosRadius,
+                    UniformName::LightRadius,
                     UniformName::BaseColor,
                     UniformName::BaseColorFactor,
                     UniformName::MetallicRoughness,
@@ -663,7 +1129,11 @@ impl ResourceManager {
                     UniformName::Normal,
                     UniformName::Emissive,
                     UniformName::EmissiveFactor,
+                    UniformName::EmissiveStrength,
                     UniformName::Occlusion,
+                    UniformName::Environment,
+                    UniformName::EnvironmentTrans,
+                    UniformName::EnvironmentMaxLod,
                     UniformName::ExposureFactor,
                     UniformName::Fcoef,
                 ],
@@ -767,12 +1237,17 @@ impl ResourceManager {
     }
 
     pub fn receive_texture_file_bytes(&mut self, identifier: &str, bytes: &[u8]) {
-        let tex = load_texture_from_image_bytes(identifier, bytes);
+        let srgb = self.srgb_textures.contains(identifier);
+        let tex = load_texture_from_image_bytes(identifier, bytes, srgb);
         if let Err(err) = tex {
             error!(
                 LogCat::Resources,
                 "Error when trying to load texture '{}': {}", identifier, err
             );
+            add_global_toast(
+                format!("Failed to load texture '{}':\n{}", identifier, err),
+                ToastSeverity::Error,
+            );
             return;
         }
         let tex = tex.unwrap();
@@ -790,12 +1265,33 @@ impl ResourceManager {
         }
     }
 
+    /// Texture `identifier`, to be used for `unit`. Textures we don't have yet are requested, with a copy of the
+    /// default texture standing in until they arrive. Textures for color units are stored as sRGB (see
+    /// TextureUnit::is_srgb), which re-uploads the ones that were first loaded as linear
     pub fn get_or_request_texture(
         &mut self,
         identifier: &str,
+        unit: TextureUnit,
         is_cubemap: bool,
     ) -> Option<Rc<RefCell<Texture>>> {
+        // Cubemaps are converted to linear by the shaders that sample them
+        let srgb = unit.is_srgb() && !is_cubemap;
+        if srgb {
+            self.srgb_textures.insert(identifier.to_owned());
+        }
+
         if let Some(tex) = self.textures.get(identifier) {
+            if srgb && !tex.borrow().srgb && tex.borrow().source.is_some() {
+                let reuploaded = GLCTX.with(|ctx| reupload_texture(&tex.borrow(), true, ctx));
+                match reuploaded {
+                    Ok(reuploaded) => tex.swap(&reuploaded),
+                    Err(err) => error!(
+                        LogCat::Resources,
+                        "Failed to upload texture '{}' as sRGB: {}", identifier, err
+                    ),
+                }
+            }
+
             return Some(tex.clone());
         }
 
@@ -819,11 +1315,15 @@ impl ResourceManager {
                     &(internal_full_path.to_owned() + "/Back.jpg"),
                 ];
 
-                let mut vecs: Vec<Vec<u8>> = join_all(urls.iter().map(|url| request_bytes(url)))
-                    .await
-                    .into_iter()
-                    .collect::<Result<Vec<Vec<u8>>, JsValue>>()
-                    .unwrap();
+                let mut vecs: Vec<Vec<u8>> =
+                    match join_all(urls.iter().map(|url| request_bytes(url)))
+                        .await
+                        .into_iter()
+                        .collect::<Result<Vec<Vec<u8>>, JsValue>>()
+                    {
+                        Ok(vecs) => vecs,
+                        Err(err) => return report_fetch_failure(&internal_full_path, &err),
+                    };
 
                 ENGINE.with(|e| {
                     let mut ref_mut = e.borrow_mut();
@@ -837,7 +1337,10 @@ impl ResourceManager {
             });
         } else {
             spawn_local(async move {
-                let mut vec = request_bytes(&internal_full_path).await.unwrap();
+                let mut vec = match request_bytes(&internal_full_path).await {
+                    Ok(vec) => vec,
+                    Err(err) => return report_fetch_failure(&internal_full_path, &err),
+                };
 
                 ENGINE.with(|e| {
                     let mut ref_mut = e.borrow_mut();
@@ -867,6 +1370,7 @@ impl ResourceManager {
                 None,
                 None,
                 None,
+                false,
             )
             .unwrap();
 
@@ -893,3 +1397,106 @@ impl ResourceManager {
         return Some(tex);
     }
 }
+
+// Test interface
+impl ResourceManager {
+    /// Adds a texture called `identifier` as if it had already been fetched, but without a GL handle
+    pub fn add_test_texture(&mut self, identifier: &str) -> Rc<RefCell<Texture>> {
+        let tex = Rc::new(RefCell::new(Texture {
+            name: identifier.to_owned(),
+            width: 1,
+            height: 1,
+            num_channels: 4,
+            gl_format: GL::RGBA,
+            srgb: true,
+            is_cubemap: false,
+            gl_handle: None,
+            source: None,
+        }));
+        self.textures.insert(identifier.to_owned(), tex.clone());
+        return tex;
+    }
+
+    /// Adds an empty mesh called `identifier`, e.g. so that we don't try generating one that would need GL
+    pub fn add_test_mesh(&mut self, identifier: &str) -> Rc<RefCell<Mesh>> {
+        let mesh = Rc::new(RefCell::new(Mesh {
+            name: identifier.to_owned(),
+            ..Mesh::default()
+        }));
+        self.meshes.insert(identifier.to_owned(), mesh.clone());
+        return mesh;
+    }
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+    use crate::components::MeshComponent;
+    use crate::managers::scene::SceneBuilder;
+    use crate::utils::transform::Transform;
+
+    #[wasm_bindgen_test]
+    pub fn closing_a_scene_frees_its_resources() {
+        let mut res_man = ResourceManager::new();
+
+        let new_mesh = |name: &str| {
+            Rc::new(RefCell::new(Mesh {
+                name: name.to_owned(),
+                ..Mesh::default()
+            }))
+        };
+
+        let texture = res_man.add_test_texture("earth_color");
+        let mut material = Material::new("earth", "relay_color.vert", "white.frag", &[]);
+        material.set_texture(TextureUnit::BaseColor, Some(texture.clone()));
+        let material = Rc::new(RefCell::new(material));
+        let mesh = new_mesh("earth");
+        res_man
+            .materials
+            .insert(String::from("earth"), material.clone());
+        res_man.meshes.insert(String::from("earth"), mesh.clone());
+
+        // Something that outlives the scene
+        let kept = new_mesh("kept");
+        res_man.meshes.insert(String::from("kept"), kept.clone());
+
+        let scene = {
+            let mut builder = SceneBuilder::new("textured", &mut res_man);
+            let earth = builder.add_mesh_entity("Earth", Some(mesh), Transform::identity());
+            let mut scene = builder.build();
+            scene
+                .get_component_mut::<MeshComponent>(earth)
+                .unwrap()
+                .set_material_override(Some(material), 0);
+            scene
+        };
+
+        // Everything is still in use
+        assert_eq!(res_man.collect_unused(), 0);
+        assert_eq!(res_man.get_num_resources(), 4);
+
+        // The texture is only freed through the material, and the material through the scene
+        drop(scene);
+        assert_eq!(res_man.collect_unused(), 3);
+        assert_eq!(res_man.get_num_resources(), 1);
+        assert!(res_man.get_mesh("kept").is_some());
+
+        drop(kept);
+        assert_eq!(res_man.collect_unused(), 1);
+        assert_eq!(res_man.get_num_resources(), 0);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn programs_go_with_the_last_material_using_them() {
+        // A master and its instance sharing program 1, a lone master with 2, and one not compiled yet
+        let dropped = [Some(1), Some(1), Some(2), None];
+        assert_eq!(get_orphaned_programs(&dropped, &[]), vec![1, 2]);
+
+        // Another instance of the first master is still around
+        assert_eq!(get_orphaned_programs(&dropped, &[Some(1), None]), vec![2]);
+        assert_eq!(get_orphaned_programs(&[None], &[Some(1)]).len(), 0);
+    }
+}
@@ -0,0 +1,54 @@
+precision highp float;
+
+#include <functions.glsl>
+
+uniform int u_light_types[MAX_LIGHTS];
+uniform vec3 u_light_pos_or_dir_c[MAX_LIGHTS];
+uniform vec4 u_basecolor_factor;
+uniform float u_atmosphere_thickness;
+
+in vec3 v_pos;
+in vec3 v_normal;
+
+out vec4 out_frag_color;
+
+// Mirrors utils::atmosphere::atmosphere_glow: Peaks at the limb, fading out towards the edge of the shell
+// and the center of the planet's disk
+float atmosphere_glow(float n_dot_v, float thickness)
+{
+    if (thickness <= 0.0)
+    {
+        return 0.0;
+    }
+
+    float inner_radius = 1.0 / (1.0 + thickness);
+    float ray_dist = sqrt(max(1.0 - n_dot_v * n_dot_v, 0.0));
+
+    if (ray_dist >= inner_radius)
+    {
+        float height = (ray_dist - inner_radius) / (1.0 - inner_radius);
+        return sq(1.0 - min(height, 1.0));
+    }
+
+    return pow(ray_dist / inner_radius, 8.0);
+}
+
+void main() 
+{
+    vec3 normal = normalize(v_normal);
+    float n_dot_v = dot(normal, normalize(-v_pos));
+
+    // Only the lit side glows, with some of it wrapping around past the terminator
+    float lit = 0.0;
+    for(int i = 0; i < MAX_LIGHTS; ++i)
+    {
+        vec3 to_light = u_light_types[i] == POINT_LIGHT 
+            ? normalize(u_light_pos_or_dir_c[i] - v_pos) 
+            : -normalize(u_light_pos_or_dir_c[i]);
+        lit = max(lit, clamp(dot(normal, to_light) + 0.3, 0.0, 1.0));
+    }
+
+    // Blended additively, so alpha is unused
+    float glow = atmosphere_glow(n_dot_v, u_atmosphere_thickness) * lit * u_basecolor_factor.a;
+    out_frag_color = vec4(u_basecolor_factor.rgb * glow, 1.0);
+}
@@ -1,6 +1,9 @@
 precision highp float;
 
+#include <functions.glsl>
+
 uniform sampler2D us_basecolor;
+uniform int u_tonemap;
 
 in vec3 v_normal;
 in vec4 v_color;
@@ -11,5 +14,6 @@ out vec4 out_frag_color;
 
 void main() 
 {
-    out_frag_color = texture(us_basecolor, v_uv0); 
+    vec4 color = texture(us_basecolor, v_uv0);
+    out_frag_color = vec4(display_to_hdr(color.rgb, u_tonemap), color.a);
 }
\ No newline at end of file
@@ -1,7 +1,11 @@
 precision highp float;
 
+#include <functions.glsl>
+
+uniform int u_tonemap;
+
 in vec4 v_color;
 
 out vec4 out_frag_color;
 
-void main() { out_frag_color = v_color; }
\ No newline at end of file
+void main() { out_frag_color = vec4(display_to_hdr(v_color.rgb, u_tonemap), v_color.a); }
\ No newline at end of file
@@ -4,4 +4,14 @@ const int DIR_LIGHT = 1;
 
 const float GAMMA = 2.2;
 const float INV_GAMMA = 1.0 / GAMMA;
-const float M_PI = 3.141592653589793;
\ No newline at end of file
+const float M_PI = 3.141592653589793;
+
+// Matches app_state::Tonemap
+const int TONEMAP_NONE = 0;
+const int TONEMAP_REINHARD = 1;
+const int TONEMAP_ACES_FILMIC = 2;
+const int CANVAS_TONEMAP = -1; // Matches rendering_system::CANVAS_TONEMAP
+
+// HACK because the current skybox is way too faint. There is no info on light units
+// so I'll have to calibrate some factor at some point
+const float SKYBOX_INTENSITY = 10000.0;
\ No newline at end of file
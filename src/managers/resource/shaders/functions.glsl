@@ -39,3 +39,87 @@ vec4 sRGB_to_linear(vec4 srgbIn)
 {
     return vec4(sRGB_to_linear(srgbIn.xyz), srgbIn.w);
 }
+
+// Antialiasing coverage for lines expanded by relay_lines.vert, given the interpolated side coordinate
+// (-1 or 1 at the edges of the quad, which extends one pixel past the actual line width)
+float line_coverage(float side, float line_width)
+{
+    float dist_px = abs(side) * (line_width * 0.5 + 1.0);
+    return clamp(line_width * 0.5 + 0.5 - dist_px, 0.0, 1.0);
+}
+
+vec3 tonemap_reinhard(vec3 color)
+{
+    return color / (color + vec3(1.0));
+}
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve
+vec3 tonemap_aces_filmic(vec3 color)
+{
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), 0.0, 1.0);
+}
+
+// Returns the HDR color that displays as `display` once it goes through `tonemap` and the sRGB conversion in
+// screenspace.frag. Mirrors get_background_clear_color in rendering_system.rs, and is meant for the passes that
+// output final colors (lines, markers, phong, debug views) instead of radiance
+vec3 display_to_hdr(vec3 display, int tonemap)
+{
+    if (tonemap == CANVAS_TONEMAP)
+    {
+        return display;
+    }
+
+    vec3 linear = min(sRGB_to_linear(clamp(display, 0.0, 1.0)), vec3(0.999));
+    if (tonemap == TONEMAP_REINHARD)
+    {
+        return linear / (1.0 - linear);
+    }
+    else if (tonemap == TONEMAP_ACES_FILMIC)
+    {
+        // Solve for x in y = x(ax + b) / (x(cx + d) + e), taking the positive root
+        const float a = 2.51;
+        const float b = 0.03;
+        const float c = 2.43;
+        const float d = 0.59;
+        const float e = 0.14;
+        vec3 qa = linear * c - a;
+        vec3 qb = linear * d - b;
+        vec3 qc = linear * e;
+        return (-qb - sqrt(qb * qb - 4.0 * qa * qc)) / (2.0 * qa);
+    }
+    return linear;
+}
+
+// Fraction of a spherical light that reaches pos around a spherical occluder (xyz center, w radius, 0 for none).
+// Mirrors utils::shadows::sphere_shadow: The overlap of both disks is interpolated linearly in angle
+float sphere_shadow(vec3 pos, vec3 light_pos, float light_radius, vec4 occluder)
+{
+    if (occluder.w <= 0.0)
+    {
+        return 1.0;
+    }
+
+    vec3 to_light = light_pos - pos;
+    vec3 to_occluder = occluder.xyz - pos;
+    float light_dist = length(to_light);
+    float occluder_dist = length(to_occluder);
+    if (dot(to_light, to_occluder) <= 0.0 || occluder_dist >= light_dist)
+    {
+        return 1.0;
+    }
+
+    float light_ang = asin(min(light_radius / light_dist, 1.0));
+    float occluder_ang = asin(min(occluder.w / occluder_dist, 1.0));
+
+    // atan instead of acos as the angles involved are tiny
+    float separation = atan(length(cross(to_light, to_occluder)), dot(to_light, to_occluder));
+
+    float max_cover = min(sq(occluder_ang / max(light_ang, 1E-9)), 1.0);
+    float visible = smoothstep(occluder_ang - light_ang, occluder_ang + light_ang, separation);
+    return 1.0 - (1.0 - visible) * max_cover;
+}
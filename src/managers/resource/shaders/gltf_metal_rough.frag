@@ -13,6 +13,8 @@ uniform int u_light_types[MAX_LIGHTS];
 uniform vec3 u_light_pos_or_dir_c[MAX_LIGHTS];
 uniform vec3 u_light_colors[MAX_LIGHTS];
 uniform float u_light_intensities[MAX_LIGHTS];
+uniform vec4 u_occluder_pos_radius_c;
+uniform float u_light_radius;
 
 const vec3 u_camera_pos_c = vec3(0, 0, 0);
 
@@ -20,12 +22,17 @@ uniform vec4 u_basecolor_factor;
 uniform float u_metallic_factor;
 uniform float u_roughness_factor;
 uniform vec3 u_emissive_factor;
+uniform float u_emissive_strength;
 
 uniform sampler2D us_basecolor;
 uniform sampler2D us_metal_rough;
 uniform sampler2D us_normal;
 uniform sampler2D us_emissive;
 uniform sampler2D us_occlusion;
+uniform samplerCube us_environment;
+
+uniform mat4 u_environment_trans;
+uniform float u_environment_max_lod;
 
 in vec3 v_pos;
 in vec3 v_normal;
@@ -41,7 +48,8 @@ vec4 get_base_color()
     vec4 base_color = u_basecolor_factor;
 
     #ifdef HAS_BASECOLOR_TEXTURE
-        base_color *= sRGB_to_linear(texture(us_basecolor, v_uv0));
+        // Color textures are uploaded as sRGB, so this is already linear
+        base_color *= texture(us_basecolor, v_uv0);
     #endif
 
     return base_color;// TODO: Vertexcolor, but only when set: * v_color;
@@ -120,6 +128,9 @@ void main()
             
             // Dot here would be in units of Mm^2, hence the 1E12 to m^2
             attenuation = 1.0 / (1E12 * dot(pos_to_light, pos_to_light));
+
+            // Eclipses
+            attenuation *= sphere_shadow(v_pos, u_light_pos_or_dir_c[i], u_light_radius, u_occluder_pos_radius_c);
         }        
 
         vec3 l = normalize(pos_to_light);
@@ -138,12 +149,27 @@ void main()
         }
     }
 
+    // Reflections of the skybox. We don't have prefiltered environment maps or a BRDF LUT, so the blur for rough
+    // surfaces comes from the cubemap's mips and the split-sum term from the roughness-aware Fresnel approximation
+    // that the reference viewer uses too
+    #ifdef HAS_ENVIRONMENT_TEXTURE
+        vec3 r = reflect(-v, n);
+        vec3 env_dir = normalize((u_environment_trans * vec4(r, 0.0)).xyz);
+        float lod = perceptual_roughness * u_environment_max_lod;
+        vec3 env_color = sRGB_to_linear(textureLod(us_environment, env_dir, lod).rgb) * SKYBOX_INTENSITY;
+
+        float env_n_dot_v = clamped_dot(n, v);
+        vec3 fr = max(vec3(1.0 - perceptual_roughness), f0) - f0;
+        vec3 k_s = f0 + fr * pow(1.0 - env_n_dot_v, 5.0);
+        specular_luminance += env_color * k_s;
+    #endif
+
     // Emissive luminance
     // HACK: Need to expose an additional emissive exposure compensation factor, but for now this 100k factor
     // gets us similar to what the GLTF reference viewer looks, for a reasonable exposure level
-    vec3 emissive_color = 100000.0 * u_emissive_factor;
+    vec3 emissive_color = 100000.0 * u_emissive_strength * u_emissive_factor;
     #ifdef HAS_EMISSIVE_TEXTURE
-        emissive_color *= sRGB_to_linear(texture(us_emissive, v_uv0)).rgb;
+        emissive_color *= texture(us_emissive, v_uv0).rgb;
     #endif 
     
     vec3 color = emissive_color + diffuse_luminance + specular_luminance;
@@ -158,10 +184,6 @@ void main()
     // Exposure
     color *= u_exposure_factor;
 
-    // Reinhard tonemapping
-    color = color / (color + vec3(1.0));    
-
-    // Convert to sRGB
-    color = linear_to_sRGB(color);
+    // Tonemapping and sRGB conversion happen when blitting the framebuffer to the canvas
     out_frag_color = vec4(color * base_color.a, base_color.a);
 }
\ No newline at end of file
@@ -0,0 +1,18 @@
+precision highp float;
+
+#include <functions.glsl>
+
+uniform float u_line_width;
+uniform vec4 u_basecolor_factor;
+uniform int u_tonemap;
+
+in vec4 v_color;
+in vec2 v_uv1;
+
+out vec4 out_frag_color;
+
+void main() 
+{
+    vec4 color = v_color * u_basecolor_factor;
+    out_frag_color = vec4(display_to_hdr(color.rgb, u_tonemap), color.a * line_coverage(v_uv1.x, u_line_width));
+}
@@ -1,4 +1,5 @@
 use regex::Regex;
+use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 
 macro_rules! include_str_map {
@@ -14,9 +15,10 @@ macro_rules! include_str_map {
 }
 
 lazy_static! {
-    pub static ref SHADER_STORAGE: HashMap<String, String> = {
+    /// Shader sources exactly as they are on disk, with their #include directives
+    pub static ref SHADER_SOURCES: HashMap<String, &'static str> = {
         // TODO: Find how to scan all files in a directory at compile time
-        let shader_paths: HashMap<String, &str> = include_str_map![
+        include_str_map![
             "basecolor.frag",
             "color.frag",
             "white.frag",
@@ -27,7 +29,12 @@ lazy_static! {
             "uv0.frag",
             "uv1.frag",
             "normals.frag",
+            "orbit.frag",
+            "line.frag",
             "tangents.frag",
+            "atmosphere.frag",
+            "outline.frag",
+            "points.frag",
 
             "brdf.glsl",
             "constants.glsl",
@@ -37,43 +44,88 @@ lazy_static! {
             "relay_locals.vert",
             "relay_color.vert",
             "relay_points.vert",
+            "relay_lines.vert",
+            "relay_outline.vert",
             "screenspace.vert"
-        ];
-
-        let re = Regex::new("#include [\"<](.*)[\">]").unwrap();
+        ]
+    };
 
-        // Resolve includes (also handles nested includes)
+    /// Shader sources with their includes resolved, ready to compile
+    pub static ref SHADER_STORAGE: HashMap<String, String> = {
         let mut storage = HashMap::new();
-        for (path, code) in shader_paths.iter() {
-            let mut modified_code: String = (*code).to_owned();
-            let mut included_files: HashSet<String> = HashSet::new();
-
-            loop {
-                // We intentionally go one at a time, because an include should always
-                // show up as early as possible
-                let cap = re.captures(&modified_code);
-                if cap.is_none() {
-                    break;
-                }
-                let cap = cap.unwrap();
-
-                let outer_match = cap.get(0).unwrap();
-                let start = outer_match.start();
-                let end = outer_match.end();
-
-                let file = cap.get(1).unwrap().as_str().to_owned();
-
-                if included_files.contains(&file) {
-                    modified_code.replace_range(start..end, "");
-                } else {
-                    modified_code.replace_range(start..end, shader_paths.get(&file).expect(&format!("Failed to find shader '{}'!", file)));
-                    included_files.insert(file.clone());
-                }
-            }
-
-            storage.insert(path.clone(), modified_code);
+        for (path, code) in SHADER_SOURCES.iter() {
+            storage.insert(path.clone(), resolve_shader_includes(code).unwrap());
         }
 
         storage
     };
 }
+
+thread_local! {
+    // Resolved sources that replace the ones in SHADER_STORAGE, from editing shaders at runtime
+    static SHADER_OVERRIDES: RefCell<HashMap<String, String>> = RefCell::new(HashMap::new());
+}
+
+/// Replaces each #include directive in `code` with the contents of that file from SHADER_SOURCES
+/// (also handles nested includes). Files are only included the first time they show up
+pub fn resolve_shader_includes(code: &str) -> Result<String, String> {
+    let re = Regex::new("#include [\"<](.*)[\">]").unwrap();
+
+    let mut modified_code: String = code.to_owned();
+    let mut included_files: HashSet<String> = HashSet::new();
+
+    loop {
+        // We intentionally go one at a time, because an include should always
+        // show up as early as possible
+        let cap = re.captures(&modified_code);
+        if cap.is_none() {
+            break;
+        }
+        let cap = cap.unwrap();
+
+        let outer_match = cap.get(0).unwrap();
+        let start = outer_match.start();
+        let end = outer_match.end();
+
+        let file = cap.get(1).unwrap().as_str().to_owned();
+
+        if included_files.contains(&file) {
+            modified_code.replace_range(start..end, "");
+        } else {
+            let included = SHADER_SOURCES
+                .get(&file)
+                .ok_or(format!("Failed to find shader '{}'!", file))?;
+            modified_code.replace_range(start..end, included);
+            included_files.insert(file.clone());
+        }
+    }
+
+    return Ok(modified_code);
+}
+
+/// Returns the resolved source we should compile for shader `name`, taking runtime edits into account
+pub fn get_shader_source(name: &str) -> Option<String> {
+    return SHADER_OVERRIDES.with(|overrides| {
+        return overrides
+            .borrow()
+            .get(name)
+            .cloned()
+            .or_else(|| SHADER_STORAGE.get(name).cloned());
+    });
+}
+
+/// Makes materials compile `resolved_source` for shader `name` instead of its baked source, or go back to it
+/// if `resolved_source` is None
+pub fn set_shader_override(name: &str, resolved_source: Option<String>) {
+    SHADER_OVERRIDES.with(|overrides| {
+        let mut overrides = overrides.borrow_mut();
+        match resolved_source {
+            Some(source) => overrides.insert(name.to_owned(), source),
+            None => overrides.remove(name),
+        };
+    });
+}
+
+pub fn has_shader_override(name: &str) -> bool {
+    return SHADER_OVERRIDES.with(|overrides| overrides.borrow().contains_key(name));
+}
@@ -0,0 +1,33 @@
+precision highp float;
+
+#include <functions.glsl>
+
+uniform vec4 u_basecolor_factor;
+uniform float u_line_width;
+uniform float u_orbit_fraction;
+uniform float u_orbit_eccentricity;
+uniform float u_orbit_fade;
+uniform int u_tonemap;
+
+in vec2 v_uv0;
+in vec2 v_uv1;
+
+out vec4 out_frag_color;
+
+// Converts a fraction of a turn in eccentric anomaly into a fraction of a turn in mean anomaly,
+// which is proportional to time. This keeps the fade uniform in time even for very eccentric
+// orbits, where equal steps in eccentric anomaly are covered at very different speeds
+float to_mean_fraction(float eccentric_fraction)
+{
+    float eccentric_anomaly = eccentric_fraction * 2.0 * M_PI;
+    return (eccentric_anomaly - u_orbit_eccentricity * sin(eccentric_anomaly)) / (2.0 * M_PI);
+}
+
+void main() 
+{
+    // 0.0 right at the body, approaching 1.0 a full revolution behind it
+    float behind = fract(to_mean_fraction(u_orbit_fraction) - to_mean_fraction(v_uv0.x));
+    float brightness = mix(1.0, max(1.0 - behind, 0.05), u_orbit_fade);
+
+    out_frag_color = vec4(display_to_hdr(u_basecolor_factor.rgb * brightness, u_tonemap), line_coverage(v_uv1.x, u_line_width));
+}
@@ -0,0 +1,13 @@
+precision highp float;
+
+#include <functions.glsl>
+
+uniform vec4 u_basecolor_factor;
+uniform int u_tonemap;
+
+out vec4 out_frag_color;
+
+void main() 
+{
+    out_frag_color = vec4(display_to_hdr(u_basecolor_factor.rgb, u_tonemap), u_basecolor_factor.a);
+}
@@ -1,14 +1,14 @@
 precision highp float;
 
-const int MAX_LIGHTS = 8;
-
-const int POINT_LIGHT = 0;
-const int DIR_LIGHT = 1;
+#include <functions.glsl>
 
 uniform int u_light_types[MAX_LIGHTS];
 uniform vec3 u_light_pos_or_dir_c[MAX_LIGHTS];
 uniform vec3 u_light_colors[MAX_LIGHTS];
 uniform float u_light_intensities[MAX_LIGHTS];
+uniform vec4 u_occluder_pos_radius_c;
+uniform float u_light_radius;
+uniform int u_tonemap;
 
 in vec3 v_pos;
 in vec3 v_normal;
@@ -23,6 +23,7 @@ vec3 calc_point_light(vec3 light_pos, vec3 light_color, float light_intensity)
     vec3 frag_to_light = light_pos - v_pos;
     float dist_m2 = 1E12 * dot(frag_to_light, frag_to_light);
     float intensity = light_intensity / dist_m2;
+    intensity *= sphere_shadow(v_pos, light_pos, u_light_radius, u_occluder_pos_radius_c);
 
     intensity *= dot(normalize(frag_to_light), v_normal);
     intensity = clamp(intensity, 0.0, 1.0);
@@ -60,5 +61,5 @@ void main()
         }
     }
 
-    out_frag_color = vec4(total_color, 1.0);
+    out_frag_color = vec4(display_to_hdr(total_color, u_tonemap), 1.0);
 }
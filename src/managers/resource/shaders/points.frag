@@ -0,0 +1,29 @@
+precision highp float;
+
+#include <functions.glsl>
+
+uniform float u_point_glow;
+uniform int u_tonemap;
+
+in vec4 v_color;
+in float v_point_size;
+
+out vec4 out_frag_color;
+
+void main() 
+{
+    // Round markers inscribed in the gl_PointSize squares, with their last pixel smoothed out
+    float radius_px = v_point_size * 0.5;
+    float dist_px = length(gl_PointCoord * 2.0 - 1.0) * radius_px;
+    float coverage = 1.0 - smoothstep(radius_px - 1.0, radius_px, dist_px);
+    if (coverage <= 0.0) 
+    {
+        discard;
+    }
+
+    // Star-like markers fade out from their center instead
+    float dist = dist_px / max(radius_px, 1e-3);
+    float glow = exp(-4.0 * dist * dist);
+
+    out_frag_color = vec4(display_to_hdr(v_color.rgb, u_tonemap), v_color.a * coverage * mix(1.0, glow, u_point_glow));
+}
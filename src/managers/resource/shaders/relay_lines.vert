@@ -0,0 +1,58 @@
+in vec3 a_position; // This end of the segment
+in vec3 a_normal;   // Other end of the segment
+in vec4 a_color;
+in vec2 a_uv0;
+in vec2 a_uv1;      // x: Side of the line (-1 or 1), y: 1 at the start of the segment, -1 at its end
+
+uniform mat4 u_wvp_trans;
+uniform float u_f_coef;
+uniform float u_line_width;   // Pixels
+uniform vec2 u_viewport_size; // Pixels
+
+out vec4 v_color;
+out vec2 v_uv0;
+out vec2 v_uv1;
+
+// Clip space w of the point where we cut segments that go behind the camera
+const float MIN_W = 0.001;
+
+void main() {
+    v_color = a_color;
+    v_uv0 = a_uv0;
+    v_uv1 = a_uv1;
+
+    vec4 this_clip = u_wvp_trans * vec4(a_position, 1.0);
+    vec4 other_clip = u_wvp_trans * vec4(a_normal, 1.0);
+
+    // Segment is entirely behind the camera: Collapse all of its vertices outside the clip volume
+    if (this_clip.w < MIN_W && other_clip.w < MIN_W) {
+        gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+        return;
+    }
+
+    // Pull ends that are behind the camera back along the segment, or the perspective divide flips them around
+    if (this_clip.w < MIN_W) {
+        this_clip = mix(this_clip, other_clip, (MIN_W - this_clip.w) / (other_clip.w - this_clip.w));
+    }
+    if (other_clip.w < MIN_W) {
+        other_clip = mix(other_clip, this_clip, (MIN_W - other_clip.w) / (this_clip.w - other_clip.w));
+    }
+
+    vec2 half_viewport = u_viewport_size * 0.5;
+    vec2 this_screen = this_clip.xy / this_clip.w * half_viewport;
+    vec2 other_screen = other_clip.xy / other_clip.w * half_viewport;
+
+    vec2 dir = (other_screen - this_screen) * a_uv1.y;
+    dir = length(dir) > 1e-6 ? normalize(dir) : vec2(1.0, 0.0);
+    vec2 perp = vec2(-dir.y, dir.x);
+
+    // Extra pixel on each side for the antialiasing falloff (see line_coverage)
+    vec2 offset_px = perp * a_uv1.x * (u_line_width * 0.5 + 1.0);
+
+    // Offset in NDC scaled by w, so that the line keeps its width on screen no matter how far it is
+    gl_Position = this_clip;
+    gl_Position.xy += offset_px / half_viewport * this_clip.w;
+
+    // Logarithmic depth buffer    
+    gl_Position.z = log2(max(1e-6, 1.0 + gl_Position.w)) * u_f_coef - 1.0;
+}
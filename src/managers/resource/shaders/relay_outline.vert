@@ -0,0 +1,26 @@
+in vec3 a_position;
+in vec3 a_normal;
+
+uniform mat4 u_wvp_trans;
+uniform float u_f_coef;
+uniform float u_line_width;   // Pixels
+uniform vec2 u_viewport_size; // Pixels
+
+void main() {
+    gl_Position = u_wvp_trans * vec4(a_position, 1.0);
+
+    // Push the vertex out along its normal on screen, so that the back faces stick out past the silhouette of the
+    // mesh by the same amount of pixels at any distance. Meshes without normals just push away from their origin
+    #ifdef HAS_NORMALS
+    vec3 dir = a_normal;
+    #else
+    vec3 dir = a_position;
+    #endif
+    vec2 dir_screen = (u_wvp_trans * vec4(dir, 0.0)).xy;
+    if (length(dir_screen) > 1e-9) {
+        gl_Position.xy += normalize(dir_screen) * u_line_width / (u_viewport_size * 0.5) * gl_Position.w;
+    }
+
+    // Logarithmic depth buffer    
+    gl_Position.z = log2(max(1e-6, 1.0 + gl_Position.w)) * u_f_coef - 1.0;
+}
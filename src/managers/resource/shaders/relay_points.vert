@@ -4,10 +4,12 @@ in vec4 a_color;
 uniform float u_f_coef;
 
 out vec4 v_color;
+out float v_point_size;
 
 void main() {
     v_color = vec4(a_color.xyz, 1.0);
     gl_PointSize = a_color.w;
+    v_point_size = a_color.w;
 
     gl_Position = a_position;
 
@@ -1,6 +1,9 @@
 precision highp float;
 
+#include <functions.glsl>
+
 uniform sampler2D us_basecolor;
+uniform int u_tonemap;
 
 in vec2 v_position;
 in vec2 v_uv0;
@@ -10,9 +13,23 @@ out vec4 out_frag_color;
 void main() 
 {
     #ifdef HAS_BASECOLOR_TEXTURE
-        out_frag_color = texture(us_basecolor, v_uv0);
+        // Linear HDR color, with exposure already applied by each material
+        vec4 hdr = texture(us_basecolor, v_uv0);
+
+        vec3 color = hdr.rgb;
+        if (u_tonemap == TONEMAP_REINHARD)
+        {
+            color = tonemap_reinhard(color);
+        }
+        else if (u_tonemap == TONEMAP_ACES_FILMIC)
+        {
+            color = tonemap_aces_filmic(color);
+        }
+        color = clamp(color, 0.0, 1.0);
+
+        out_frag_color = vec4(linear_to_sRGB(color), hdr.a);
     #else
         //out_frag_color = vec4((v_position.x + 1.0) * 0.5, (v_position.y + 1.0) * 0.5, 0, 1); 
         out_frag_color = vec4(v_uv0.xy, 0, 1); 
     #endif
-}
\ No newline at end of file
+}
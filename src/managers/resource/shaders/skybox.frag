@@ -23,14 +23,8 @@ void main()
     // Exposure
     color *= u_exposure_factor;
 
-    // HACK because the current skybox is way too faint. There is no info on light units
-    // so I'll have to calibrate some factor at some point
-    color *= 10000.0;
+    color *= SKYBOX_INTENSITY;
 
-    // Reinhard tonemapping
-    color = color / (color + vec3(1.0));    
-
-    // Convert to sRGB
-    color = linear_to_sRGB(color);
+    // Tonemapping and sRGB conversion happen when blitting the framebuffer to the canvas
     out_frag_color = vec4(color, 1.0);    
 }
\ No newline at end of file
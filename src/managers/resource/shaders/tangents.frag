@@ -1,5 +1,9 @@
 precision highp float;
 
+#include <functions.glsl>
+
+uniform int u_tonemap;
+
 in vec3 v_pos;
 in vec3 v_normal;
 in vec3 v_tangent;
@@ -11,5 +15,5 @@ out vec4 out_frag_color;
 
 void main() 
 {
-    out_frag_color = vec4(normalize(v_tangent), 1.0); 
+    out_frag_color = vec4(display_to_hdr(normalize(v_tangent), u_tonemap), 1.0);
 }
\ No newline at end of file
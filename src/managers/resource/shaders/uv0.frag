@@ -1,10 +1,14 @@
 precision highp float;
 
+#include <functions.glsl>
+
+uniform int u_tonemap;
+
 in vec2 v_uv0;
 
 out vec4 out_frag_color;
 
 void main()
 {
-    out_frag_color = vec4(v_uv0, 0.0, 1.0); 
+    out_frag_color = vec4(display_to_hdr(vec3(v_uv0, 0.0), u_tonemap), 1.0);
 }
\ No newline at end of file
@@ -1,8 +1,12 @@
 precision highp float;
 
+#include <functions.glsl>
+
+uniform int u_tonemap;
+
 out vec4 out_frag_color;
 
 void main() 
 {
-    out_frag_color = vec4(1, 1, 1, 1); 
+    out_frag_color = vec4(display_to_hdr(vec3(1.0), u_tonemap), 1.0);
 }
\ No newline at end of file
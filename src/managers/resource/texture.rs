@@ -1,4 +1,6 @@
 use crate::managers::resource::material::ShaderDefine;
+use crate::utils::gl::GL;
+use std::rc::Rc;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum TextureUnit {
@@ -7,6 +9,7 @@ pub enum TextureUnit {
     Normal = 2,
     Emissive = 3,
     Occlusion = 4,
+    Environment = 5, // Skybox cubemap, for reflections
 }
 impl TextureUnit {
     pub fn get_define(&self) -> ShaderDefine {
@@ -16,8 +19,32 @@ impl TextureUnit {
             TextureUnit::Normal => ShaderDefine::HasNormalTexture,
             TextureUnit::Emissive => ShaderDefine::HasEmissiveTexture,
             TextureUnit::Occlusion => ShaderDefine::HasOcclusionTexture,
+            TextureUnit::Environment => ShaderDefine::HasEnvironmentTexture,
         }
     }
+
+    /// Whether textures for this unit hold colors, which are stored in sRGB so that sampling them decodes to linear.
+    /// Data textures like normals and metallic-roughness stay linear
+    pub fn is_srgb(&self) -> bool {
+        match *self {
+            TextureUnit::BaseColor | TextureUnit::Emissive => true,
+            _ => false,
+        }
+    }
+}
+
+/// Internal format to upload pixels of `format` with. Only RGB and RGBA have sRGB variants, so everything else stays
+/// linear even if `srgb` is set
+pub fn get_texture_internal_format(format: u32, srgb: bool) -> u32 {
+    if !srgb {
+        return format;
+    }
+
+    return match format {
+        GL::RGBA => GL::SRGB8_ALPHA8,
+        GL::RGB => GL::SRGB8,
+        other => other,
+    };
 }
 
 #[derive(Debug, Clone)]
@@ -27,6 +54,59 @@ pub struct Texture {
     pub height: u32,
     pub num_channels: u8,
     pub gl_format: u32,
+    pub srgb: bool, // Stored as sRGB (see get_texture_internal_format), so shaders sample it as linear
     pub is_cubemap: bool,
     pub gl_handle: Option<glow::Texture>,
+
+    // Kept so that we can upload the texture again if the WebGL context is lost. None for textures
+    // whose owners recreate them on their own (e.g. framebuffer attachments)
+    pub source: Option<Rc<TextureSource>>,
+}
+
+/// What a Texture was created from
+#[derive(Debug)]
+pub enum TextureSource {
+    // Decoded pixels, along with the sampler parameters they were uploaded with
+    Pixels {
+        data: Vec<u8>,
+        mag_filter: Option<i32>,
+        min_filter: Option<i32>,
+        wrap_s: Option<i32>,
+        wrap_t: Option<i32>,
+    },
+
+    // Still encoded image files, as they're much smaller than the decoded pixels
+    Image(Vec<u8>),
+    CubemapFaces([Vec<u8>; 6]), // In the same order as TempCubemap
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    pub fn only_color_textures_are_srgb() {
+        assert!(TextureUnit::BaseColor.is_srgb());
+        assert!(TextureUnit::Emissive.is_srgb());
+        assert!(!TextureUnit::Normal.is_srgb());
+        assert!(!TextureUnit::MetallicRoughness.is_srgb());
+        assert!(!TextureUnit::Occlusion.is_srgb());
+
+        assert_eq!(
+            get_texture_internal_format(GL::RGBA, true),
+            GL::SRGB8_ALPHA8
+        );
+        assert_eq!(get_texture_internal_format(GL::RGB, true), GL::SRGB8);
+        assert_eq!(get_texture_internal_format(GL::RGBA, false), GL::RGBA);
+
+        // No sRGB variants for these
+        assert_eq!(get_texture_internal_format(GL::ALPHA, true), GL::ALPHA);
+        assert_eq!(
+            get_texture_internal_format(GL::LUMINANCE_ALPHA, true),
+            GL::LUMINANCE_ALPHA
+        );
+    }
 }
@@ -0,0 +1,241 @@
+use crate::components::{MeshComponent, MetadataComponent, RigidBodyComponent, TransformComponent};
+use crate::managers::orbit::{
+    generate_belt, BeltDistribution, BodyDescription, BodyInstanceDescription, BodyMotion,
+    OrbitalElements,
+};
+use crate::managers::resource::mesh::Mesh;
+use crate::managers::scene::orbits::{
+    add_body_instance_entities, add_keplerian_body, place_body_on_orbit,
+};
+use crate::managers::scene::{Entity, Scene};
+use crate::managers::ResourceManager;
+use crate::utils::transform::Transform;
+use crate::utils::units::Jdn;
+use na::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Builds a Scene from code instead of from a scene description, without any file IO. Bodies are spawned the same
+/// way SceneManager spawns the bodies of scene descriptions, so they get the same components and metadata
+pub struct SceneBuilder<'a> {
+    scene: Scene,
+    res_man: &'a mut ResourceManager,
+}
+impl<'a> SceneBuilder<'a> {
+    pub fn new(identifier: &str, res_man: &'a mut ResourceManager) -> Self {
+        return Self {
+            scene: Scene::new(identifier),
+            res_man,
+        };
+    }
+
+    /// Continues building `scene`, e.g. to add generated bodies to the scene that's already loaded. Leaves an empty
+    /// scene in its place until `build` hands it back
+    pub fn from_scene(scene: &mut Scene, res_man: &'a mut ResourceManager) -> Self {
+        let identifier = scene.identifier.clone();
+        return Self {
+            scene: std::mem::replace(scene, Scene::new(&identifier)),
+            res_man,
+        };
+    }
+
+    /// Date corresponding to a sim_time_s of zero, that orbits passed to `set_orbit` are evaluated at
+    pub fn epoch(mut self, epoch: Jdn) -> Self {
+        self.scene.epoch = epoch;
+        return self;
+    }
+
+    /// Adds `body` like a scene description with just its source would. Returns None if the body gets skipped,
+    /// e.g. a planet without a radius
+    pub fn add_body(&mut self, body: BodyDescription) -> Option<Entity> {
+        return self.add_body_instance(Some(&body), &BodyInstanceDescription::default());
+    }
+
+    /// Adds a body with the overrides of `instance` (position, velocity, mass, mesh, etc.), optionally on top of
+    /// `body`. Parents are resolved by instance name, like in scene descriptions
+    pub fn add_body_instance(
+        &mut self,
+        body: Option<&BodyDescription>,
+        instance: &BodyInstanceDescription,
+    ) -> Option<Entity> {
+        let epoch = self.scene.epoch;
+        let (_, entity) =
+            add_body_instance_entities(&mut self.scene, epoch, body, instance, None, self.res_man)?;
+
+        if let Some(parent_name) = &instance.parent {
+            if let Some(parent) = self.scene.get_entity_from_name(parent_name) {
+                self.scene.set_entity_parent(parent, entity);
+            }
+        }
+
+        return Some(entity);
+    }
+
+    /// Adds a plain entity that draws `mesh` with `transform`, without any of the body components
+    pub fn add_mesh_entity(
+        &mut self,
+        name: &str,
+        mesh: Option<Rc<RefCell<Mesh>>>,
+        transform: Transform<f64>,
+    ) -> Entity {
+        let builder = self.scene.start_new_entity(name);
+        let entity = self.scene.finish_new_entity(builder);
+
+        let trans_comp = self.scene.add_component::<TransformComponent>(entity);
+        *trans_comp.get_local_transform_mut() = transform;
+
+        let mesh_comp = self.scene.add_component::<MeshComponent>(entity);
+        mesh_comp.set_mesh(mesh);
+
+        return entity;
+    }
+
+    /// Places `body` on the orbit described by `elements` around `central_body`, at the scene epoch. Bodies with a
+    /// RigidBodyComponent also get the matching velocity and get integrated from there (like BodyMotion::Dynamical),
+    /// while the others keep following the orbit (like BodyMotion::Keplerian)
+    pub fn set_orbit(&mut self, body: Entity, central_body: Entity, elements: &OrbitalElements) {
+        let epoch = self.scene.epoch;
+        place_body_on_orbit(&mut self.scene, body, central_body, elements, epoch);
+        if self
+            .scene
+            .get_component::<RigidBodyComponent>(body)
+            .is_none()
+        {
+            add_keplerian_body(&mut self.scene, body, central_body, elements);
+        }
+
+        // So that we show orbital readouts for it too
+        if let Some(central_id) = self
+            .scene
+            .get_body_id_from_entity(central_body)
+            .map(|id| id.to_owned())
+        {
+            if let Some(meta_comp) = self.scene.get_component_mut::<MetadataComponent>(body) {
+                meta_comp.set_metadata("body_central_id", &central_id);
+            }
+        }
+    }
+
+    /// Adds the asteroids that generate_belt samples from `dist`, on Keplerian orbits around the body with id
+    /// dist.central_id. Fails if there's no such body, or if we don't know its mass
+    pub fn add_belt(&mut self, dist: &BeltDistribution) -> Result<Vec<Entity>, String> {
+        let central_body = self
+            .scene
+            .get_entity_from_body_id(&dist.central_id)
+            .ok_or_else(|| format!("There's no body with id '{}'", dist.central_id))?;
+
+        let central_mass = self
+            .scene
+            .get_component::<RigidBodyComponent>(central_body)
+            .map(|rb| rb.mass)
+            .or_else(|| {
+                self.scene
+                    .get_component::<MetadataComponent>(central_body)
+                    .and_then(|meta| meta.get_metadata("body_mass"))
+                    .and_then(|s| s.parse::<f64>().ok())
+            })
+            .filter(|mass| *mass > 0.0)
+            .ok_or_else(|| format!("Body '{}' has no mass to orbit", dist.central_id))?;
+
+        let instance = BodyInstanceDescription {
+            motion: Some(BodyMotion::Keplerian),
+            ..BodyInstanceDescription::default()
+        };
+
+        let mut result = Vec::new();
+        for (body, elements) in generate_belt(dist, central_mass, self.scene.epoch) {
+            if let Some(entity) = self.add_body_instance(Some(&body), &instance) {
+                self.set_orbit(entity, central_body, &elements);
+                result.push(entity);
+            }
+        }
+
+        return Ok(result);
+    }
+
+    pub fn build(self) -> Scene {
+        return self.scene;
+    }
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+    use crate::app_state::AppState;
+    use crate::engine::Engine;
+    use crate::utils::orbits::GRAVITATION_CONSTANT;
+    use crate::utils::units::{Mm, J2000_JDN};
+
+    #[wasm_bindgen_test]
+    pub fn build_and_step_three_body_scene() {
+        let mut engine = Engine::new_headless();
+        let mut state = AppState::new();
+
+        let sun_mass = 1.989E30; // kg
+        let inner_radius = 149597.870; // Mm
+        let outer_radius = 2.0 * inner_radius;
+        let get_period_s = |radius: f64| {
+            2.0 * std::f64::consts::PI * (radius.powi(3) / (GRAVITATION_CONSTANT * sun_mass)).sqrt()
+        };
+        let circular_orbit = |radius: f64| OrbitalElements {
+            ref_id: String::from("10"),
+            epoch: J2000_JDN,
+            semi_major_axis: Mm(radius),
+            sidereal_orbit_period_days: get_period_s(radius) / 86400.0,
+            ..OrbitalElements::default()
+        };
+        let new_body = |id: &str, name: &str, mass: f32| BodyDescription {
+            mass: Some(mass),
+            ..BodyDescription::new_test_body(id, name, 1.0)
+        };
+
+        let scene = {
+            let mut builder =
+                SceneBuilder::new("three bodies", &mut engine.res_man).epoch(J2000_JDN);
+            let sun = builder
+                .add_body(new_body("10", "Sun", sun_mass as f32))
+                .unwrap();
+            let inner = builder.add_body(new_body("1", "Inner", 5.972E24)).unwrap();
+            let outer = builder.add_body(new_body("2", "Outer", 5.972E24)).unwrap();
+            builder.set_orbit(inner, sun, &circular_orbit(inner_radius));
+            builder.set_orbit(outer, sun, &circular_orbit(outer_radius));
+            builder.add_mesh_entity("Marker", None, Transform::identity());
+            builder.build()
+        };
+        assert_eq!(scene.identifier, "three bodies");
+        for id in &["10", "1", "2"] {
+            let ent = scene.get_entity_from_body_id(id).unwrap();
+            assert!(scene.get_component::<RigidBodyComponent>(ent).is_some());
+        }
+        engine.scene_man.set_current_scene(scene, &mut state);
+
+        let scene = engine.scene_man.get_current_scene().unwrap();
+        let inner = scene.get_entity_from_body_id("1").unwrap();
+        let outer = scene.get_entity_from_body_id("2").unwrap();
+        assert!(scene.get_entity_from_name("Marker").is_some());
+
+        // One period of the inner body, which takes the outer one around a bit more than a third of its orbit
+        let inner_period_s = get_period_s(inner_radius);
+        let num_steps = 10000;
+        for _ in 0..num_steps {
+            engine.step(&mut state, inner_period_s / num_steps as f64);
+        }
+
+        let inner_end = engine.body_position(inner).unwrap();
+        let dist = (inner_end - Point3::new(inner_radius, 0.0, 0.0)).magnitude();
+        assert!(
+            dist < inner_radius * 0.01,
+            "Inner body ended {} Mm away from the start",
+            dist
+        );
+
+        let outer_end = engine.body_position(outer).unwrap();
+        let expected_angle =
+            2.0 * std::f64::consts::PI * inner_period_s / get_period_s(outer_radius);
+        let angle = outer_end.y.atan2(outer_end.x);
+        assert!((angle - expected_angle).abs() < expected_angle * 0.01);
+    }
+}
@@ -1,7 +1,24 @@
 use na::*;
 use serde::{Deserialize, Serialize};
 
+use crate::app_state::{
+    AppState, DistanceUnit, NumberNotation, OpenWindows, VelocityUnit, DEFAULT_EV100,
+    DEFAULT_LIGHT_INTENSITY_SCALE,
+};
 use crate::managers::orbit::BodyInstanceDescription;
+use crate::managers::OrbitManager;
+use crate::utils::units::J2000_JDN;
+
+fn default_epoch_jdn() -> f64 {
+    return J2000_JDN.0;
+}
+
+fn default_render_scale() -> f64 {
+    return 1.0;
+}
+
+/// Skybox texture of scenes that don't specify their own, under public/textures/
+pub const DEFAULT_SKYBOX: &str = "starmap_16k";
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct SceneDescription {
@@ -10,6 +27,14 @@ pub struct SceneDescription {
     pub time: String,
     pub simulation_scale: f64,
 
+    // How many times larger than their actual radii bodies are drawn, so that e.g. tiny moons are visible from far away
+    #[serde(default = "default_render_scale")]
+    pub render_scale: f64,
+
+    // JDN that the scene's simulation time counts from, and that the mean anomalies of its orbits are given at
+    #[serde(default = "default_epoch_jdn")]
+    pub epoch_jdn: f64,
+
     #[serde(default)]
     pub focus: Option<String>,
 
@@ -21,5 +46,355 @@ pub struct SceneDescription {
 
     #[serde(default)]
     pub camera_target: Option<Point3<f64>>,
+
+    // Name of the entity to select once the scene is loaded
+    #[serde(default)]
+    pub initial_selection: Option<String>,
+
+    #[serde(default)]
+    pub open_windows: Option<OpenWindowsDescription>,
+
+    // Units and notation the scene reads best in, e.g. km around Earth and AU for the whole solar system
+    #[serde(default)]
+    pub distance_unit: Option<DistanceUnit>,
+
+    #[serde(default)]
+    pub velocity_unit: Option<VelocityUnit>,
+
+    #[serde(default)]
+    pub number_notation: Option<NumberNotation>,
+
+    // Texture under public/textures/ to use as the skybox (when skyboxes are enabled), instead of DEFAULT_SKYBOX
+    #[serde(default)]
+    pub skybox: Option<String>,
+
+    // Exposure and light intensity scale the scene is lit best with, e.g. brighter for deep space than near Earth
+    #[serde(default)]
+    pub ev100: Option<f32>,
+
+    #[serde(default)]
+    pub light_intensity: Option<f32>,
     pub bodies: Vec<BodyInstanceDescription>,
 }
+
+impl SceneDescription {
+    /// Switches `state` to the units and notation of the scene, with the defaults for the ones it doesn't specify.
+    /// Does nothing if the user picked their own on the settings window
+    pub fn apply_display_preferences(&self, state: &mut AppState) {
+        if state.display_units_overridden {
+            return;
+        }
+
+        state.distance_unit = self.distance_unit.unwrap_or_default();
+        state.velocity_unit = self.velocity_unit.unwrap_or_default();
+        state.number_notation = self.number_notation.unwrap_or_default();
+    }
+
+    /// Switches `state` to the exposure and light intensity scale of the scene, with the defaults for the ones it
+    /// doesn't specify. Does nothing if the user changed them on the settings or debug windows
+    pub fn apply_lighting_preferences(&self, state: &mut AppState) {
+        if state.lighting_overridden {
+            return;
+        }
+
+        state.ev100 = self.ev100.unwrap_or(DEFAULT_EV100);
+        state.light_intensity_scale = self
+            .light_intensity
+            .unwrap_or(DEFAULT_LIGHT_INTENSITY_SCALE);
+    }
+
+    /// Path of the texture the skybox of the scene uses
+    pub fn get_skybox_texture(&self) -> String {
+        let name = self.skybox.as_deref().unwrap_or(DEFAULT_SKYBOX);
+        return "public/textures/".to_owned() + name;
+    }
+
+    /// Checks every body source of the description against the body databases we have loaded, so that we can tell
+    /// the user about bodies that would otherwise just silently not show up. Results follow the order of `bodies`
+    pub fn validate(&self, orbit_man: &OrbitManager) -> SceneLoadReport {
+        let mut report = SceneLoadReport {
+            scene: self.name.clone(),
+            ..SceneLoadReport::default()
+        };
+
+        for source in self.bodies.iter().filter_map(|body| body.source.as_ref()) {
+            let slash_pos = match source.rfind("/") {
+                Some(pos) => pos,
+                None => {
+                    report.malformed_sources.push(source.clone());
+                    continue;
+                }
+            };
+            let db_name = &source[..slash_pos]; // e.g. "major_bodies"
+            let body_id = &source[slash_pos + 1..]; // e.g. "399"
+
+            if !orbit_man.has_database(db_name) {
+                if !report.missing_databases.iter().any(|db| db == db_name) {
+                    report.missing_databases.push(db_name.to_owned());
+                }
+            }
+
+            if !body_id.starts_with("*") && orbit_man.get_body(db_name, body_id).is_err() {
+                report.missing_bodies.push(source.clone());
+            }
+        }
+
+        return report;
+    }
+}
+
+/// Problems found when loading a scene description, so that they can be shown to the user
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct SceneLoadReport {
+    pub scene: String,
+    pub missing_databases: Vec<String>, // Databases referenced by body sources that aren't loaded, e.g. "asteroids"
+    pub missing_bodies: Vec<String>, // Full body sources that weren't found, e.g. "major_bodies/399"
+    pub malformed_sources: Vec<String>, // Body sources that aren't in the "database/id" form
+}
+impl SceneLoadReport {
+    pub fn is_ok(&self) -> bool {
+        return self.missing_databases.is_empty()
+            && self.missing_bodies.is_empty()
+            && self.malformed_sources.is_empty();
+    }
+}
+
+/// Which UI windows a scene opens (true) or closes (false) when loaded. Windows left out keep their current state
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct OpenWindowsDescription {
+    #[serde(default)]
+    pub debug: Option<bool>,
+
+    #[serde(default)]
+    pub body_list: Option<bool>,
+
+    #[serde(default)]
+    pub scene_browser: Option<bool>,
+
+    #[serde(default)]
+    pub settings: Option<bool>,
+
+    #[serde(default)]
+    pub controls: Option<bool>,
+
+    #[serde(default)]
+    pub about: Option<bool>,
+
+    #[serde(default)]
+    pub distance_plot: Option<bool>,
+}
+impl OpenWindowsDescription {
+    pub fn apply(&self, open_windows: &mut OpenWindows) {
+        let pairs = [
+            (self.debug, &mut open_windows.debug),
+            (self.body_list, &mut open_windows.body_list),
+            (self.scene_browser, &mut open_windows.scene_browser),
+            (self.settings, &mut open_windows.settings),
+            (self.controls, &mut open_windows.controls),
+            (self.about, &mut open_windows.about),
+            (self.distance_plot, &mut open_windows.distance_plot),
+        ];
+
+        for (preset, window) in pairs {
+            if let Some(open) = preset {
+                *window = open;
+            }
+        }
+    }
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    pub fn deserialize_initial_selection_and_open_windows() {
+        let old_desc: SceneDescription = ron::de::from_str(
+            r#"(
+                name: "Old scene",
+                description: "",
+                time: "J2000",
+                simulation_scale: 1.0,
+                bodies: [],
+            )"#,
+        )
+        .unwrap();
+        assert_eq!(old_desc.initial_selection, None);
+        assert_eq!(old_desc.open_windows, None);
+
+        let new_desc: SceneDescription = ron::de::from_str(
+            r#"(
+                name: "Crowded scene",
+                description: "",
+                time: "J2000",
+                simulation_scale: 1.0,
+                initial_selection: Some("Jupiter"),
+                open_windows: Some((
+                    body_list: Some(true),
+                    settings: Some(false),
+                )),
+                bodies: [],
+            )"#,
+        )
+        .unwrap();
+        assert_eq!(new_desc.initial_selection, Some(String::from("Jupiter")));
+
+        let mut open_windows = OpenWindows::default();
+        open_windows.body_list = false;
+        open_windows.about = true;
+        new_desc.open_windows.unwrap().apply(&mut open_windows);
+        assert!(open_windows.body_list);
+        assert!(!open_windows.settings);
+        assert!(open_windows.about); // Not in the preset, so left alone
+    }
+
+    #[wasm_bindgen_test]
+    pub fn validation_reports_missing_bodies() {
+        let mut orbit_man = OrbitManager::new();
+        orbit_man
+            .load_database_file(
+                "public/database/major_bodies.json",
+                "body_database",
+                r#"{"10": {"name": "Sun", "type": "star"}}"#,
+            )
+            .unwrap();
+
+        let desc: SceneDescription = ron::de::from_str(
+            r#"#![enable(implicit_some)]
+            (
+                name: "Broken scene",
+                description: "",
+                time: "J2000",
+                simulation_scale: 1.0,
+                bodies: [
+                    (source: "major_bodies/10"),
+                    (source: "major_bodies/9999"),
+                    (source: "asteroids/*100"),
+                    (source: "asteroids/1"),
+                    (source: "vulcan"),
+                    (name: "Custom body"),
+                ],
+            )"#,
+        )
+        .unwrap();
+
+        let report = desc.validate(&orbit_man);
+        assert!(!report.is_ok());
+        assert_eq!(report.scene, "Broken scene");
+        assert_eq!(report.missing_databases, vec![String::from("asteroids")]);
+        assert_eq!(
+            report.missing_bodies,
+            vec![
+                String::from("major_bodies/9999"),
+                String::from("asteroids/1")
+            ]
+        );
+        assert_eq!(report.malformed_sources, vec![String::from("vulcan")]);
+
+        // Validating again gives the exact same report
+        assert_eq!(desc.validate(&orbit_man), report);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn scenes_apply_their_display_preferences() {
+        let earth_desc: SceneDescription = ron::de::from_str(
+            r#"(
+                name: "Earth orbit",
+                description: "",
+                time: "J2000",
+                simulation_scale: 1.0,
+                distance_unit: Some(Km),
+                velocity_unit: Some(KmPerS),
+                number_notation: Some(Scientific),
+                bodies: [],
+            )"#,
+        )
+        .unwrap();
+        let solar_desc: SceneDescription = ron::de::from_str(
+            r#"(
+                name: "Solar system",
+                description: "",
+                time: "J2000",
+                simulation_scale: 1.0,
+                distance_unit: Some(Au),
+                bodies: [],
+            )"#,
+        )
+        .unwrap();
+
+        let mut state = AppState::new();
+        earth_desc.apply_display_preferences(&mut state);
+        assert_eq!(state.distance_unit, DistanceUnit::Km);
+        assert_eq!(state.velocity_unit, VelocityUnit::KmPerS);
+        assert_eq!(state.number_notation, NumberNotation::Scientific);
+
+        // Whatever the solar system scene doesn't specify goes back to the defaults
+        solar_desc.apply_display_preferences(&mut state);
+        assert_eq!(state.distance_unit, DistanceUnit::Au);
+        assert_eq!(state.velocity_unit, VelocityUnit::default());
+        assert_eq!(state.number_notation, NumberNotation::default());
+
+        // Scenes leave the units alone once the user picked their own
+        state.distance_unit = DistanceUnit::Mm;
+        state.display_units_overridden = true;
+        earth_desc.apply_display_preferences(&mut state);
+        assert_eq!(state.distance_unit, DistanceUnit::Mm);
+        assert_eq!(state.velocity_unit, VelocityUnit::default());
+    }
+
+    #[wasm_bindgen_test]
+    pub fn scenes_apply_their_lighting_presets() {
+        let deep_space_desc: SceneDescription = ron::de::from_str(
+            r#"(
+                name: "Deep space",
+                description: "",
+                time: "J2000",
+                simulation_scale: 1.0,
+                skybox: Some("milky_way"),
+                ev100: Some(6.5),
+                light_intensity: Some(20.0),
+                bodies: [],
+            )"#,
+        )
+        .unwrap();
+        let earth_desc: SceneDescription = ron::de::from_str(
+            r#"(
+                name: "Earth orbit",
+                description: "",
+                time: "J2000",
+                simulation_scale: 1.0,
+                ev100: Some(13.0),
+                bodies: [],
+            )"#,
+        )
+        .unwrap();
+
+        let mut state = AppState::new();
+        deep_space_desc.apply_lighting_preferences(&mut state);
+        assert_eq!(
+            deep_space_desc.get_skybox_texture(),
+            "public/textures/milky_way"
+        );
+        assert_eq!(state.ev100, 6.5);
+        assert_eq!(state.light_intensity_scale, 20.0);
+
+        // Whatever the Earth scene doesn't specify goes back to the defaults
+        earth_desc.apply_lighting_preferences(&mut state);
+        assert_eq!(
+            earth_desc.get_skybox_texture(),
+            "public/textures/".to_owned() + DEFAULT_SKYBOX
+        );
+        assert_eq!(state.ev100, 13.0);
+        assert_eq!(state.light_intensity_scale, DEFAULT_LIGHT_INTENSITY_SCALE);
+
+        // Scenes leave the lighting alone once the user changed it
+        state.ev100 = 8.0;
+        state.lighting_overridden = true;
+        deep_space_desc.apply_lighting_preferences(&mut state);
+        assert_eq!(state.ev100, 8.0);
+        assert_eq!(state.light_intensity_scale, DEFAULT_LIGHT_INTENSITY_SCALE);
+    }
+}
@@ -1,8 +1,10 @@
+mod builder;
 pub mod component_storage;
 pub mod description;
 pub mod orbits;
 mod scene;
 mod scene_manager;
 
+pub use builder::*;
 pub use scene::*;
 pub use scene_manager::*;
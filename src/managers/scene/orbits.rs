@@ -3,21 +3,345 @@ use crate::components::{
     KinematicComponent, LightComponent, MeshComponent, MetadataComponent, RigidBodyComponent,
     TransformComponent,
 };
-use crate::managers::orbit::{BodyDescription, BodyInstanceDescription, BodyType, StateVector};
+use crate::managers::orbit::{
+    BodyDescription, BodyInstanceDescription, BodyMotion, BodyType, OrbitalElements, StateVector,
+};
 use crate::managers::resource::material::{Material, UniformName, UniformValue};
-use crate::managers::resource::mesh::Mesh;
+use crate::managers::resource::mesh::{DynamicLinePrimitive, Mesh};
 use crate::managers::resource::texture::TextureUnit;
-use crate::managers::scene::{Entity, Scene};
+use crate::managers::scene::{Entity, KeplerianBody, OrbitLine, Scene};
 use crate::managers::{OrbitManager, ResourceManager};
+use crate::utils::lighting::decode_light_color;
 use crate::utils::log::*;
+use crate::utils::orbits::{
+    adaptive_eccentric_anomalies, bake_times_for_eccentric_anomalies, elements_to_circle_transform,
+    elements_to_ellipse_rotation_transform, get_orbit_readouts, orbital_elements_to_xyz,
+    scale_orbital_period, OrbitReadouts,
+};
 use crate::utils::string::decode_hex;
 use crate::utils::units::Jdn;
+use crate::GLCTX;
 use na::*;
 use nalgebra::Vector3;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 
+/// How many eccentric anomalies we bake the times at which each body crosses them for, for drawing orbit trails
+const ORBIT_TRAIL_BAKED_ANGLES: u32 = 360;
+
+/// How many central bodies predict_body_position goes through, e.g. moon -> planet -> star
+const MAX_ORBIT_CHAIN_DEPTH: u32 = 8;
+
+/// Most eccentric orbit that set_body_orbital_elements allows, as our orbit meshes only go up to it
+pub const MAX_EDITED_ECCENTRICITY: f64 = 0.99;
+
+/// Finds the entity `body_id` orbits around in `scene`, and the orbital elements it follows around it.
+/// Elements with a reference body that wasn't spawned in the scene are skipped
+pub fn find_orbit_central_body<'a>(
+    scene: &Scene,
+    body_id: &str,
+    osc_elements: &'a HashMap<String, Vec<OrbitalElements>>,
+) -> Option<(Entity, &'a OrbitalElements)> {
+    for elements in osc_elements.get(body_id)? {
+        if elements.semi_major_axis.0 <= 0.0 || elements.ref_id == body_id {
+            continue;
+        }
+
+        if let Some(central_ent) = scene.get_entity_from_body_id(&elements.ref_id) {
+            return Some((central_ent, elements));
+        }
+    }
+
+    return None;
+}
+
+/// Returns the speed, energy, etc. of `body` around the central body recorded in its "body_central_id" metadata,
+/// using their current positions and velocities. None if it doesn't have a central body in the scene
+pub fn get_body_orbit_readouts(scene: &Scene, body: Entity) -> Option<OrbitReadouts> {
+    let central_id = scene
+        .get_component::<MetadataComponent>(body)?
+        .get_metadata("body_central_id")?;
+    let central_ent = scene.get_entity_from_body_id(central_id)?;
+
+    let body_pos = scene.get_physical_position(body)?;
+    let central_pos = scene.get_physical_position(central_ent)?;
+
+    // Prefer the simulated mass, but fall back to the database one for bodies that aren't rigid bodies
+    let central_mass = scene
+        .get_component::<RigidBodyComponent>(central_ent)
+        .map(|rb| rb.mass)
+        .or_else(|| {
+            scene
+                .get_component::<MetadataComponent>(central_ent)?
+                .get_metadata("body_mass")?
+                .parse::<f64>()
+                .ok()
+        });
+
+    return Some(get_orbit_readouts(
+        &(body_pos - central_pos),
+        &(get_body_velocity(scene, body) - get_body_velocity(scene, central_ent)),
+        central_mass,
+    ));
+}
+
+/// Predicts the world space position (Mm) of `body` at `t` from its orbital elements, adding up the predicted
+/// positions of the bodies it orbits around. Anything without elements is assumed to stay where it currently is
+pub fn predict_body_position(
+    scene: &Scene,
+    body: Entity,
+    osc_elements: &HashMap<String, Vec<OrbitalElements>>,
+    t: Jdn,
+) -> Point3<f64> {
+    let mut result = Vector3::zeros();
+    let mut current = body;
+    for _ in 0..MAX_ORBIT_CHAIN_DEPTH {
+        let central = scene
+            .get_component::<MetadataComponent>(current)
+            .and_then(|meta| meta.get_metadata("body_id"))
+            .and_then(|body_id| find_orbit_central_body(scene, body_id, osc_elements));
+
+        match central {
+            Some((central, elements)) => {
+                let rotation = elements_to_ellipse_rotation_transform(elements);
+                let (pos, _) = orbital_elements_to_xyz(elements, t, &rotation);
+                result += pos.coords;
+                current = central;
+            }
+            None => break,
+        }
+    }
+
+    if let Some(pos) = scene.get_physical_position(current) {
+        result += pos;
+    }
+
+    return Point3::from(result);
+}
+
+/// Mm/s. Zero for bodies without a rigid body or kinematic component
+fn get_body_velocity(scene: &Scene, body: Entity) -> Vector3<f64> {
+    if let Some(rb) = scene.get_component::<RigidBodyComponent>(body) {
+        if rb.mass > 0.0 {
+            return rb.lin_mom / rb.mass;
+        }
+    }
+
+    if let Some(kc) = scene.get_component::<KinematicComponent>(body) {
+        return kc.lin_vel;
+    }
+
+    return Vector3::zeros();
+}
+
+/// Makes `body` follow `elements` around `central_body` from now on, instead of being integrated by the physics system
+pub fn add_keplerian_body(
+    scene: &mut Scene,
+    body: Entity,
+    central_body: Entity,
+    elements: &OrbitalElements,
+) {
+    scene.keplerian_bodies.push(KeplerianBody {
+        body,
+        central_body,
+        elements: elements.clone(),
+        rotation: elements_to_ellipse_rotation_transform(elements),
+    });
+}
+
+/// Moves `body` to where `elements` put it around `central_body` at `t`. Bodies with a RigidBodyComponent also get the
+/// matching velocity, so that physics integrates them along that orbit from there
+pub fn place_body_on_orbit(
+    scene: &mut Scene,
+    body: Entity,
+    central_body: Entity,
+    elements: &OrbitalElements,
+    t: Jdn,
+) {
+    let rotation = elements_to_ellipse_rotation_transform(elements);
+    let (pos, vel) = orbital_elements_to_xyz(elements, t, &rotation);
+
+    let central_pos = scene
+        .get_component::<TransformComponent>(central_body)
+        .map(|trans| trans.get_local_transform().trans)
+        .unwrap_or_default();
+    let central_vel = scene
+        .get_component::<RigidBodyComponent>(central_body)
+        .filter(|rb| rb.mass > 0.0)
+        .map(|rb| rb.lin_mom / rb.mass)
+        .unwrap_or_default();
+
+    if let Some(trans) = scene.get_component_mut::<TransformComponent>(body) {
+        trans.get_local_transform_mut().trans = central_pos + pos.coords;
+    }
+
+    if let Some(rb) = scene.get_component_mut::<RigidBodyComponent>(body) {
+        rb.lin_mom = (central_vel + vel / 86400.0) * rb.mass;
+    }
+}
+
+/// Adds an entity with an orbit line for `body` following `elements`, parented to `central_body`
+pub fn add_orbit_line_entity(
+    scene: &mut Scene,
+    body: Entity,
+    central_body: Entity,
+    elements: &OrbitalElements,
+    res_man: &mut ResourceManager,
+) -> Entity {
+    let body_name = scene.get_entity_name(body).unwrap_or_default().to_owned();
+    let line_ent = scene.new_entity(Some(&(body_name + "'s orbit")));
+    scene.set_entity_parent(central_body, line_ent);
+
+    let trans_comp = scene.add_component::<TransformComponent>(line_ent);
+    *trans_comp.get_local_transform_mut() = elements_to_circle_transform(elements);
+
+    let mesh_comp = scene.add_component::<MeshComponent>(line_ent);
+    mesh_comp.raycasting_visible = false;
+    mesh_comp.set_mesh(res_man.get_or_create_orbit_mesh(elements.eccentricity));
+
+    let mat = res_man.instantiate_material("orbit", "orbit");
+    if let Some(mat) = &mat {
+        mat.borrow_mut().set_uniform_value(
+            UniformName::OrbitEccentricity,
+            UniformValue::Float(elements.eccentricity as f32),
+        );
+    }
+    mesh_comp.set_material_override(mat, 0);
+
+    let trail = GLCTX.with(|ctx| Rc::new(RefCell::new(DynamicLinePrimitive::new(ctx))));
+    let anomalies = adaptive_eccentric_anomalies(elements.eccentricity, ORBIT_TRAIL_BAKED_ANGLES);
+    let baked_times = bake_times_for_eccentric_anomalies(elements, &anomalies);
+
+    scene.orbits.push(OrbitLine {
+        line: line_ent,
+        body,
+        eccentricity: elements.eccentricity,
+        period_days: elements.sidereal_orbit_period_days,
+        baked_anomalies: Rc::new(anomalies),
+        baked_times: Rc::new(baked_times),
+        rotation: elements_to_ellipse_rotation_transform(elements),
+        trail: Some(trail),
+        elements: elements.clone(),
+    });
+
+    return line_ent;
+}
+
+/// Orbital elements that `body` currently follows, or that its orbit line is drawn with
+pub fn get_body_orbital_elements(scene: &Scene, body: Entity) -> Option<&OrbitalElements> {
+    if let Some(kep) = scene.keplerian_bodies.iter().find(|kep| kep.body == body) {
+        return Some(&kep.elements);
+    }
+
+    return scene
+        .orbits
+        .iter()
+        .find(|orbit| orbit.body == body)
+        .map(|orbit| &orbit.elements);
+}
+
+/// Moves `body` onto the orbit of `elements`, and rebuilds its orbit line to match. The eccentricity is clamped and
+/// the period recomputed from the semi-major axis, keeping the central mass implied by the catalog elements (the ones
+/// the body had before it was first edited, which reset_body_orbital_elements goes back to). Simulated bodies are
+/// placed where the new orbit has them at `t`, with the matching velocity
+pub fn set_body_orbital_elements(
+    scene: &mut Scene,
+    body: Entity,
+    elements: &OrbitalElements,
+    t: Jdn,
+    res_man: &mut ResourceManager,
+) {
+    if elements.semi_major_axis.0 <= 0.0 {
+        return;
+    }
+
+    let catalog = match get_body_orbital_elements(scene, body) {
+        Some(current) => current.clone(),
+        None => return,
+    };
+    let catalog = scene
+        .catalog_elements
+        .entry(body)
+        .or_insert(catalog)
+        .clone();
+
+    let mut elements = elements.clone();
+    elements.eccentricity = elements.eccentricity.max(0.0).min(MAX_EDITED_ECCENTRICITY);
+    elements.sidereal_orbit_period_days = scale_orbital_period(
+        catalog.sidereal_orbit_period_days,
+        catalog.semi_major_axis,
+        elements.semi_major_axis,
+    );
+    let rotation = elements_to_ellipse_rotation_transform(&elements);
+    let anomalies = Rc::new(adaptive_eccentric_anomalies(
+        elements.eccentricity,
+        ORBIT_TRAIL_BAKED_ANGLES,
+    ));
+    let baked_times = Rc::new(bake_times_for_eccentric_anomalies(&elements, &anomalies));
+
+    for kep in scene
+        .keplerian_bodies
+        .iter_mut()
+        .filter(|kep| kep.body == body)
+    {
+        kep.elements = elements.clone();
+        kep.rotation = rotation.clone();
+    }
+
+    let mut lines = Vec::new();
+    for orbit in scene.orbits.iter_mut().filter(|orbit| orbit.body == body) {
+        orbit.eccentricity = elements.eccentricity;
+        orbit.period_days = elements.sidereal_orbit_period_days;
+        orbit.baked_anomalies = anomalies.clone();
+        orbit.baked_times = baked_times.clone();
+        orbit.rotation = rotation.clone();
+        orbit.elements = elements.clone();
+        lines.push(orbit.line);
+    }
+
+    for line in lines {
+        if let Some(trans_comp) = scene.get_component_mut::<TransformComponent>(line) {
+            *trans_comp.get_local_transform_mut() = elements_to_circle_transform(&elements);
+        }
+
+        if let Some(mesh_comp) = scene.get_component_mut::<MeshComponent>(line) {
+            mesh_comp.set_mesh(res_man.get_or_create_orbit_mesh(elements.eccentricity));
+            if let Some(mat) = mesh_comp.get_material_override(0) {
+                mat.borrow_mut().set_uniform_value(
+                    UniformName::OrbitEccentricity,
+                    UniformValue::Float(elements.eccentricity as f32),
+                );
+            }
+        }
+    }
+
+    // Keplerian bodies pick up their new elements on their own, but simulated ones would keep going with their old
+    // position and velocity
+    if scene.get_component::<RigidBodyComponent>(body).is_some() {
+        let central_body = scene
+            .get_component::<MetadataComponent>(body)
+            .and_then(|meta| meta.get_metadata("body_central_id"))
+            .and_then(|central_id| scene.get_entity_from_body_id(central_id));
+        if let Some(central_body) = central_body {
+            place_body_on_orbit(scene, body, central_body, &elements, t);
+        }
+    }
+}
+
+/// Puts `body` back on the orbit it had before set_body_orbital_elements first changed it
+pub fn reset_body_orbital_elements(
+    scene: &mut Scene,
+    body: Entity,
+    t: Jdn,
+    res_man: &mut ResourceManager,
+) {
+    if let Some(catalog) = scene.catalog_elements.get(&body).cloned() {
+        set_body_orbital_elements(scene, body, &catalog, t, res_man);
+        scene.catalog_elements.remove(&body);
+    }
+}
+
 pub fn add_body_instance_entities(
     scene: &mut Scene,
     _epoch: Jdn,
@@ -31,6 +355,7 @@ pub fn add_body_instance_entities(
     let mut mass = None;
     let mut radius = None;
     let mut brightness = None;
+    let mut light_color = None;
     let mut mesh_params = None;
     let mut material_params = None;
     let mut pos = default_state_vector.as_ref().and_then(|v| Some(v.pos));
@@ -49,8 +374,8 @@ pub fn add_body_instance_entities(
                 }
             }
             BodyType::Artificial => {}
-            // Skip barycenters for now as they also end up generating points and just waste time in general
-            BodyType::Barycenter => return None,
+            // No mesh, and placed by the barycenter system instead of simulated
+            BodyType::Barycenter => {}
             BodyType::Other => {}
         }
 
@@ -58,6 +383,7 @@ pub fn add_body_instance_entities(
         mass = body.mass;
         radius = body.radius;
         brightness = body.brightness;
+        light_color = body.light_color.as_ref();
         mesh_params = body.mesh_params.as_ref();
         material_params = body.material_params.as_ref();
     }
@@ -75,6 +401,9 @@ pub fn add_body_instance_entities(
     if let Some(instance_brightness) = body_instance.brightness {
         brightness = Some(instance_brightness);
     }
+    if let Some(instance_color) = body_instance.light_color.as_ref() {
+        light_color = Some(instance_color);
+    }
     if let Some(instance_params) = body_instance.mesh_params.as_ref() {
         mesh_params = Some(instance_params);
     }
@@ -105,6 +434,17 @@ pub fn add_body_instance_entities(
     if (body.is_some() && body.unwrap().body_type == BodyType::Star) || brightness.is_some() {
         let light_comp = scene.add_component::<LightComponent>(body_ent);
         light_comp.color = Vector3::new(1.0, 1.0, 1.0);
+        if let Some(color) = light_color {
+            match decode_light_color(color) {
+                Some(color) => light_comp.color = color,
+                None => warning!(
+                    LogCat::Orbit,
+                    "Invalid light_color '{}' for body '{}'",
+                    color,
+                    name.cloned().unwrap_or_default()
+                ),
+            }
+        }
         light_comp.intensity = brightness.unwrap();
         light_comp.light_type = LightType::Point;
     }
@@ -164,6 +504,9 @@ pub fn add_body_instance_entities(
     if let Some(brightness) = brightness {
         meta_comp.set_metadata("body_brightness", &brightness.to_string());
     }
+    if let Some(body_id) = body.and_then(|b| b.id.as_ref()) {
+        scene.set_entity_body_id(body_ent, body_id);
+    }
 
     // Child mesh holder entity
     // Separate entity so that our main entity can have children, and yet a separate child mesh
@@ -172,6 +515,7 @@ pub fn add_body_instance_entities(
     let sphere_ent = scene.new_entity(sphere_ent_name.as_ref().and_then(|s| Some(s.as_str())));
     scene.set_entity_parent(body_ent, sphere_ent);
     let sphere_trans_comp = scene.add_component::<TransformComponent>(sphere_ent);
+    sphere_trans_comp.set_render_scaled(true);
     let sphere_trans = sphere_trans_comp.get_local_transform_mut();
     if let Some(rot) = body_instance.rot {
         sphere_trans.rot = UnitQuaternion::from_euler_angles(
@@ -191,7 +535,18 @@ pub fn add_body_instance_entities(
 
     // Child mesh
     let mesh_comp = scene.add_component::<MeshComponent>(sphere_ent);
-    mesh_comp.set_mesh(get_body_mesh(body, body_instance, res_man));
+    let mesh = get_body_mesh(body, body_instance, res_man);
+    let mesh_name = mesh.as_ref().map(|mesh| mesh.borrow().name.clone());
+    mesh_comp.set_mesh(mesh);
+    if let Some(mesh_name) = mesh_name {
+        mesh_comp.set_lod_meshes(res_man.get_lod_meshes(&mesh_name));
+    }
+    if let Some(params) = &mesh_params {
+        mesh_comp.precise_raycasting = params
+            .get("precise_picking")
+            .and_then(|s| s.parse::<bool>().ok())
+            .unwrap_or(false);
+    }
     if let Some(mat_over) = get_body_material(body, body_instance, res_man) {
         debug!(
             LogCat::Orbit,
@@ -201,8 +556,27 @@ pub fn add_body_instance_entities(
         mesh_comp.set_material_override(Some(mat_over.clone()), 0);
     }
 
+    // Atmosphere shell. Also a child of the main entity, as it doesn't need to rotate
+    if let (Some(body), Some(radius)) = (body, radius) {
+        add_atmosphere_entity(scene, body_ent, body, radius, res_man);
+    }
+
+    // Keplerian bodies get hooked up to their central body once the whole scene is spawned
+    let motion = body_instance.motion.unwrap_or_default();
+    if motion == BodyMotion::Keplerian {
+        if let Some(meta_comp) = scene.get_component_mut::<MetadataComponent>(body_ent) {
+            meta_comp.set_metadata("body_motion", &format!("{:?}", motion));
+        }
+    }
+
     // Parent rigidbody
-    if body_instance.parent.is_none() && mass.is_some() && mass.unwrap() > 0.0 {
+    let is_barycenter = body.map(|b| b.body_type) == Some(BodyType::Barycenter);
+    if body_instance.parent.is_none()
+        && !is_barycenter
+        && motion == BodyMotion::Dynamical
+        && mass.is_some()
+        && mass.unwrap() > 0.0
+    {
         let phys_comp = scene.add_component::<RigidBodyComponent>(body_ent);
         phys_comp.mass = mass.unwrap() as f64;
 
@@ -226,6 +600,68 @@ pub fn add_body_instance_entities(
     return Some((name.cloned().unwrap_or_default(), body_ent));
 }
 
+/// Adds a child entity to `body_ent` with a shell around the body that draws the glow of its atmosphere, if
+/// `body` has one. The shell is see-through to raycasts, so that we still pick and occlude against the body itself
+fn add_atmosphere_entity(
+    scene: &mut Scene,
+    body_ent: Entity,
+    body: &BodyDescription,
+    radius: f32,
+    res_man: &mut ResourceManager,
+) {
+    let thickness = match body.atmosphere_thickness {
+        Some(thickness) if thickness > 0.0 && radius > 0.0 => thickness,
+        _ => return,
+    };
+
+    let mat = res_man.instantiate_material("atmosphere", "atmosphere");
+    if mat.is_none() {
+        return;
+    }
+    let mat = mat.unwrap();
+    {
+        let mut mat_mut = mat.borrow_mut();
+        mat_mut.set_uniform_value(
+            UniformName::AtmosphereThickness,
+            UniformValue::Float(thickness),
+        );
+
+        if let Some(color) = &body.atmosphere_color {
+            match decode_hex(color) {
+                Ok(bytes) if color.len() % 2 == 0 => {
+                    let mut color: Vec<f32> = bytes.iter().map(|u| *u as f32 / 255.0).collect();
+                    color.resize(4, 1.0);
+                    mat_mut.set_uniform_value(
+                        UniformName::BaseColorFactor,
+                        UniformValue::Vec4([color[0], color[1], color[2], color[3]]),
+                    );
+                }
+                _ => warning!(
+                    LogCat::Orbit,
+                    "Invalid atmosphere_color '{}' for body '{}'",
+                    color,
+                    body.name
+                ),
+            }
+        }
+    }
+
+    let builder = scene.start_new_entity(&(body.name.clone() + "'s atmosphere"));
+    let atmo_ent = scene.finish_new_entity(builder);
+    scene.set_entity_parent(body_ent, atmo_ent);
+
+    let trans_comp = scene.add_component::<TransformComponent>(atmo_ent);
+    trans_comp.set_render_scaled(true);
+    let shell_radius = radius as f64 * (1.0 + thickness as f64);
+    trans_comp.get_local_transform_mut().scale =
+        Vector3::new(shell_radius, shell_radius, shell_radius);
+
+    let mesh_comp = scene.add_component::<MeshComponent>(atmo_ent);
+    mesh_comp.set_mesh(res_man.get_or_create_mesh("atmosphere_shell"));
+    mesh_comp.set_material_override(Some(mat), 0);
+    mesh_comp.raycasting_visible = false;
+}
+
 pub fn get_body_mesh(
     body: Option<&BodyDescription>,
     body_instance: &BodyInstanceDescription,
@@ -326,7 +762,11 @@ pub fn get_body_material(
 
             mat_mut.set_texture(
                 TextureUnit::BaseColor,
-                res_man.get_or_request_texture(&("public/textures/".to_owned() + path), false),
+                res_man.get_or_request_texture(
+                    &("public/textures/".to_owned() + path),
+                    TextureUnit::BaseColor,
+                    false,
+                ),
             );
         }
 
@@ -338,7 +778,11 @@ pub fn get_body_material(
 
             mat_mut.set_texture(
                 TextureUnit::Normal,
-                res_man.get_or_request_texture(&("public/textures/".to_owned() + path), false),
+                res_man.get_or_request_texture(
+                    &("public/textures/".to_owned() + path),
+                    TextureUnit::Normal,
+                    false,
+                ),
             );
         }
 
@@ -350,7 +794,11 @@ pub fn get_body_material(
 
             mat_mut.set_texture(
                 TextureUnit::MetallicRoughness,
-                res_man.get_or_request_texture(&("public/textures/".to_owned() + path), false),
+                res_man.get_or_request_texture(
+                    &("public/textures/".to_owned() + path),
+                    TextureUnit::MetallicRoughness,
+                    false,
+                ),
             );
         }
 
@@ -362,7 +810,11 @@ pub fn get_body_material(
 
             mat_mut.set_texture(
                 TextureUnit::Emissive,
-                res_man.get_or_request_texture(&("public/textures/".to_owned() + path), false),
+                res_man.get_or_request_texture(
+                    &("public/textures/".to_owned() + path),
+                    TextureUnit::Emissive,
+                    false,
+                ),
             );
         }
 
@@ -387,6 +839,26 @@ pub fn get_body_material(
             );
         }
 
+        if let Some(strength_str) = params.get("emissive_strength") {
+            debug!(
+                LogCat::Orbit,
+                "Parsed emissive_strength {:?} for body {:?}", strength_str, body_name
+            );
+
+            match strength_str.trim().parse::<f32>() {
+                Ok(strength) => mat_mut.set_uniform_value(
+                    UniformName::EmissiveStrength,
+                    UniformValue::Float(strength),
+                ),
+                Err(_) => warning!(
+                    LogCat::Orbit,
+                    "Invalid emissive_strength '{}' for body {:?}",
+                    strength_str,
+                    body_name
+                ),
+            };
+        }
+
         if let Some(path) = params.get("double_sided") {
             let double_sided = path == "true";
 
@@ -443,3 +915,341 @@ pub fn fetch_default_motion_if_needed(
 
     return Some(vectors[lowest_index].clone());
 }
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+    use crate::managers::scene::description::SceneDescription;
+    use crate::managers::scene::SceneBuilder;
+    use crate::utils::orbits::{
+        bake_eccentric_anomaly_times, elements_to_ellipse_rotation_transform,
+        orbital_elements_to_xyz,
+    };
+    use crate::utils::units::{Au, Deg, Mm, J2000_JDN};
+
+    #[wasm_bindgen_test]
+    pub fn bodies_are_placed_from_their_own_elements_epoch() {
+        let desc: SceneDescription = ron::de::from_str(
+            r#"(
+                name: "Venus at a later epoch",
+                description: "",
+                time: "J2000",
+                simulation_scale: 1.0,
+                epoch_jdn: 2451645.0,
+                bodies: [],
+            )"#,
+        )
+        .unwrap();
+        let epoch = Jdn(desc.epoch_jdn);
+        assert!((epoch.0 - J2000_JDN.0 - 100.0).abs() < 1E-9);
+
+        // Venus heliocentric, with its mean anomaly given at J2000 instead of at the scene epoch
+        let elements = OrbitalElements {
+            ref_id: String::from("10"),
+            epoch: J2000_JDN,
+            semi_major_axis: Au(7.233269274790103E-01).to_Mm(),
+            eccentricity: 6.755786250503024E-03,
+            inclination: Deg(3.394589648659516E+00).to_rad(),
+            long_asc_node: Deg(7.667837463924961E+01).to_rad(),
+            arg_periapsis: Deg(5.518596653686583E+01).to_rad(),
+            mean_anomaly_0: Deg(5.011477187351476E+01).to_rad(),
+            sidereal_orbit_period_days: 2.246983300739057E+02,
+        };
+
+        // Where Venus is 100 days (160.2 degrees of mean anomaly) after J2000
+        let expected = Point3::new(
+            1.032136312614512E+05,
+            -3.394029820163210E+04,
+            -6.421379225205462E+03,
+        );
+
+        // Matching state vector at the scene epoch, and an unrelated one at J2000 that shouldn't be picked
+        let mut orbit_man = OrbitManager::new();
+        orbit_man.load_database_file(
+            "public/database/vectors.json",
+            "vectors_database",
+            r#"{"299": [
+                [2451545.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+                [2451645.0, 1.032136312614512E+05, -3.394029820163210E+04, -6.421379225205462E+03, 0.0, 0.0, 0.0]
+            ]}"#,
+        ).unwrap();
+        let vector = fetch_default_motion_if_needed("299", &orbit_man, epoch).unwrap();
+        assert_eq!(vector.jdn_date, epoch);
+        assert!((vector.pos - expected).magnitude() < 1E-6);
+
+        // Placing it in a scene at the later epoch propagates the elements from their own epoch
+        let mut res_man = ResourceManager::new();
+        let mut builder = SceneBuilder::new("venus", &mut res_man).epoch(epoch);
+        let sun = builder
+            .add_body(BodyDescription::new_test_body("10", "Sun", 1.0))
+            .unwrap();
+        let venus = builder
+            .add_body(BodyDescription::new_test_body("299", "Venus", 1.0))
+            .unwrap();
+        builder.set_orbit(venus, sun, &elements);
+        let scene = builder.build();
+        let pos = scene
+            .get_component::<TransformComponent>(venus)
+            .unwrap()
+            .get_local_transform()
+            .trans;
+        assert!((pos - expected.coords).magnitude() < 1E-3, "{:?}", pos);
+
+        // Same orbit as the one with the elements given at the scene epoch instead, so it passes periapsis at the
+        // exact same times
+        let at_epoch = OrbitalElements {
+            epoch,
+            mean_anomaly_0: Deg(5.011477187351476E+01 + 100.0 * 360.0 / 2.246983300739057E+02)
+                .to_rad(),
+            ..elements.clone()
+        };
+        let trans = elements_to_ellipse_rotation_transform(&at_epoch);
+        let (pos, _) = orbital_elements_to_xyz(&at_epoch, epoch, &trans);
+        assert!((pos - expected).magnitude() < 1E-3);
+
+        let from_j2000 = bake_eccentric_anomaly_times(&elements, 4);
+        let from_epoch = bake_eccentric_anomaly_times(&at_epoch, 4);
+        for (a, b) in from_j2000.iter().zip(from_epoch.iter()) {
+            assert!((a.0 - b.0).abs() < 1E-6);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    pub fn satellites_link_to_central_body_by_id() {
+        let mut scene = Scene::new("test");
+        let mut spawn = |name: &str, body_id: &str| {
+            let builder = scene.start_new_entity(name);
+            let ent = scene.finish_new_entity(builder);
+            scene.set_entity_body_id(ent, body_id);
+            return ent;
+        };
+        let sun = spawn("Sun", "10");
+        let earth = spawn("Earth", "399");
+        let moon = spawn("Moon", "301");
+        let jupiter = spawn("Jupiter", "599");
+        let io = spawn("Io", "501");
+
+        // The Moon's first elements are around the Earth-Moon barycenter, which isn't in the scene
+        let mut orbit_man = OrbitManager::new();
+        orbit_man.load_database_file(
+            "public/database/osc_elements.json",
+            "elements_database",
+            r#"{
+                "10": [{"epoch": 2451545.0, "ref_id": "10", "e": 1, "i": 0, "O": 0, "w": 0, "M": 0, "a": 0, "p": 1}],
+                "399": [{"epoch": 2451545.0, "ref_id": "10", "e": 0.0167, "i": 0, "O": 0, "w": 0, "M": 0, "a": 149598.0, "p": 365.25}],
+                "301": [
+                    {"epoch": 2451545.0, "ref_id": "3", "e": 0.0549, "i": 0, "O": 0, "w": 0, "M": 0, "a": 379.7, "p": 27.3},
+                    {"epoch": 2451545.0, "ref_id": "399", "e": 0.0549, "i": 0, "O": 0, "w": 0, "M": 0, "a": 384.4, "p": 27.3}
+                ],
+                "501": [{"epoch": 2451545.0, "ref_id": "599", "e": 0.0041, "i": 0, "O": 0, "w": 0, "M": 0, "a": 421.7, "p": 1.77}]
+            }"#,
+        ).unwrap();
+        let osc_elements = orbit_man.get_osc_elements();
+
+        let central = |body_id: &str| {
+            return find_orbit_central_body(&scene, body_id, osc_elements)
+                .map(|(ent, e)| (ent, e.ref_id.clone()));
+        };
+        assert_eq!(central("399"), Some((sun, String::from("10"))));
+        assert_eq!(central("301"), Some((earth, String::from("399"))));
+        assert_eq!(central("501"), Some((jupiter, String::from("599"))));
+
+        // The Sun doesn't orbit anything, and Jupiter has no elements at all
+        assert_eq!(central("10"), None);
+        assert_eq!(central("599"), None);
+
+        assert_eq!(scene.get_body_id_from_entity(moon), Some("301"));
+        assert_eq!(scene.get_body_id_from_entity(io), Some("501"));
+    }
+
+    #[wasm_bindgen_test]
+    pub fn edited_orbits_follow_keplers_third_law_and_reset() {
+        let mut res_man = ResourceManager::new();
+        let mut scene = Scene::new("test");
+        let mut spawn = |name: &str| {
+            let builder = scene.start_new_entity(name);
+            return scene.finish_new_entity(builder);
+        };
+        let sun = spawn("Sun");
+        let earth = spawn("Earth");
+        let comet = spawn("Comet");
+        let epoch = scene.epoch;
+
+        let catalog = OrbitalElements {
+            ref_id: String::from("10"),
+            semi_major_axis: Mm(149598.0),
+            eccentricity: 0.0167,
+            sidereal_orbit_period_days: 365.25,
+            ..OrbitalElements::default()
+        };
+        add_keplerian_body(&mut scene, earth, sun, &catalog);
+
+        // Four times further out takes eight times longer, and the eccentricity can't open up the orbit
+        let mut edited = catalog.clone();
+        edited.semi_major_axis = Mm(4.0 * 149598.0);
+        edited.eccentricity = 1.5;
+        edited.inclination = Deg(30.0).to_rad();
+        edited.sidereal_orbit_period_days = 1.0;
+        set_body_orbital_elements(&mut scene, earth, &edited, epoch, &mut res_man);
+
+        let current = get_body_orbital_elements(&scene, earth).unwrap().clone();
+        assert!((current.sidereal_orbit_period_days - 8.0 * 365.25).abs() < 1E-9);
+        assert_eq!(current.eccentricity, MAX_EDITED_ECCENTRICITY);
+        assert!((current.inclination.0 - Deg(30.0).to_rad().0).abs() < 1E-12);
+
+        // The keplerian system places the body with the new orbit
+        let kep = &scene.keplerian_bodies[0];
+        let (pos, _) = orbital_elements_to_xyz(&kep.elements, scene.epoch, &kep.rotation);
+        assert!((pos.coords.magnitude() - 4.0 * 149598.0 * (1.0 - 0.99)).abs() < 1E-3);
+
+        // Further edits still scale from the catalog period
+        edited.semi_major_axis = Mm(149598.0);
+        set_body_orbital_elements(&mut scene, earth, &edited, epoch, &mut res_man);
+        let current = get_body_orbital_elements(&scene, earth).unwrap();
+        assert!((current.sidereal_orbit_period_days - 365.25).abs() < 1E-9);
+
+        reset_body_orbital_elements(&mut scene, earth, epoch, &mut res_man);
+        let current = get_body_orbital_elements(&scene, earth).unwrap();
+        assert_eq!(current.eccentricity, 0.0167);
+        assert_eq!(current.inclination.0, 0.0);
+        assert!(scene.catalog_elements.is_empty());
+
+        // Nothing to edit on bodies without an orbit
+        set_body_orbital_elements(&mut scene, comet, &edited, epoch, &mut res_man);
+        assert!(get_body_orbital_elements(&scene, comet).is_none());
+        assert!(scene.catalog_elements.is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    pub fn edited_orbits_move_simulated_bodies() {
+        let mut res_man = ResourceManager::new();
+        let mut scene = Scene::new("test");
+        let mut spawn = |name: &str, mass: f64| {
+            let builder = scene.start_new_entity(name);
+            let entity = scene.finish_new_entity(builder);
+            scene.add_component::<TransformComponent>(entity);
+            scene.add_component::<MetadataComponent>(entity);
+            scene.add_component::<RigidBodyComponent>(entity).mass = mass;
+            return entity;
+        };
+        let sun = spawn("Sun", 1.989E30);
+        let earth = spawn("Earth", 5.972E24);
+        let line = {
+            let builder = scene.start_new_entity("Earth's orbit");
+            scene.finish_new_entity(builder)
+        };
+        scene.set_entity_body_id(sun, "10");
+        scene
+            .get_component_mut::<MetadataComponent>(earth)
+            .unwrap()
+            .set_metadata("body_central_id", "10");
+
+        // The Sun is somewhere else and moving, so that we can tell the Earth is placed relative to it
+        let sun_pos = Vector3::new(1000.0, 2000.0, 0.0);
+        let sun_vel = Vector3::new(0.0, 0.0, 0.01);
+        scene
+            .get_component_mut::<TransformComponent>(sun)
+            .unwrap()
+            .get_local_transform_mut()
+            .trans = sun_pos;
+        scene
+            .get_component_mut::<RigidBodyComponent>(sun)
+            .unwrap()
+            .lin_mom = sun_vel * 1.989E30;
+
+        let catalog = OrbitalElements {
+            ref_id: String::from("10"),
+            semi_major_axis: Mm(149598.0),
+            sidereal_orbit_period_days: 365.25,
+            ..OrbitalElements::default()
+        };
+        scene.orbits.push(OrbitLine {
+            line,
+            body: earth,
+            eccentricity: catalog.eccentricity,
+            period_days: catalog.sidereal_orbit_period_days,
+            baked_anomalies: Rc::new(Vec::new()),
+            baked_times: Rc::new(Vec::new()),
+            rotation: elements_to_ellipse_rotation_transform(&catalog),
+            trail: None,
+            elements: catalog.clone(),
+        });
+
+        // Circular orbit four times further out, which takes eight times longer
+        let mut edited = catalog.clone();
+        edited.semi_major_axis = Mm(4.0 * 149598.0);
+        let t = Jdn(scene.epoch.0 + 100.0);
+        set_body_orbital_elements(&mut scene, earth, &edited, t, &mut res_man);
+
+        let earth_pos = scene
+            .get_component::<TransformComponent>(earth)
+            .unwrap()
+            .get_local_transform()
+            .trans;
+        let rb = scene.get_component::<RigidBodyComponent>(earth).unwrap();
+        let earth_vel = rb.lin_mom / rb.mass;
+
+        let current = get_body_orbital_elements(&scene, earth).unwrap();
+        let (expected_pos, expected_vel) =
+            orbital_elements_to_xyz(current, t, &elements_to_ellipse_rotation_transform(current));
+        assert!((earth_pos - sun_pos - expected_pos.coords).magnitude() < 1E-6);
+        assert!((earth_vel - sun_vel - expected_vel / 86400.0).magnitude() < 1E-12);
+
+        let radius = 4.0 * 149598.0;
+        let speed = 2.0 * std::f64::consts::PI * radius / (8.0 * 365.25 * 86400.0);
+        assert!(((earth_pos - sun_pos).magnitude() - radius).abs() < 1E-3);
+        assert!(((earth_vel - sun_vel).magnitude() - speed).abs() < speed * 1E-6);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn binary_star_lights_planet_with_both_colors() {
+        let desc: SceneDescription =
+            ron::de::from_str(include_str!("../../../public/scenes/binary_star.ron")).unwrap();
+        let mut res_man = ResourceManager::new();
+        let mut scene = Scene::new("binary star");
+
+        // Just the light sources, without their meshes and textures
+        let mut lights = Vec::new();
+        for instance in desc.bodies.iter().filter(|b| b.light_color.is_some()) {
+            let mut instance = instance.clone();
+            instance.mesh = None;
+            instance.material = None;
+            instance.material_params = None;
+
+            let (_, ent) = add_body_instance_entities(
+                &mut scene,
+                J2000_JDN,
+                None,
+                &instance,
+                None,
+                &mut res_man,
+            )
+            .unwrap();
+            let light = scene.get_component::<LightComponent>(ent).unwrap().clone();
+            let pos = scene
+                .get_component::<TransformComponent>(ent)
+                .unwrap()
+                .get_local_transform()
+                .trans;
+            lights.push((Point3::from(pos), light));
+        }
+        assert_eq!(lights.len(), 2);
+
+        // A red and a blue point light, equally bright, on either side of the planet
+        let (red_pos, red) = &lights[0];
+        let (blue_pos, blue) = &lights[1];
+        assert_eq!(red.light_type, LightType::Point);
+        assert_eq!(blue.light_type, LightType::Point);
+        assert_eq!(Some(red.color), decode_light_color("FF6A3D"));
+        assert_eq!(Some(blue.color), decode_light_color("7FA6FF"));
+        assert!(red.color.x > red.color.z * 5.0);
+        assert!(blue.color.z > blue.color.x * 3.0);
+        assert_eq!(red.intensity, 1E27);
+        assert_eq!(blue.intensity, 1E27);
+        assert_eq!(*red_pos, Point3::new(-80000.0, 0.0, 0.0));
+        assert_eq!(*blue_pos, Point3::new(80000.0, 0.0, 0.0));
+    }
+}
@@ -1,19 +1,30 @@
+use crate::app_state::PointColorMode;
 use crate::components::{
     Component, KinematicComponent, LightComponent, MeshComponent, MetadataComponent,
     RigidBodyComponent, TransformComponent,
 };
+use crate::managers::orbit::OrbitalElements;
 use crate::managers::resource::material::Material;
-use crate::managers::resource::mesh::Mesh;
+use crate::managers::resource::mesh::{DynamicLinePrimitive, Mesh};
 
 use crate::utils::log::*;
+use crate::utils::memory::MemoryReport;
+use crate::utils::star_catalog::CatalogStar;
+use crate::utils::starfield::{Star, StarfieldSource};
+use crate::utils::transform::Transform;
+use crate::utils::units::{Jdn, Rad, J2000_JDN};
 use na::*;
 use serde::{Deserialize, Serialize};
-use std::cell::RefCell;
+use std::cell::{Cell, Ref, RefCell};
 use std::cmp::Reverse;
-use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
 use std::hash::Hash;
+use std::mem::size_of;
 use std::rc::Rc;
 
+/// Handle to an entity of a Scene. These are never reused: Every new entity gets a fresh value even if it ends up
+/// occupying the storage index of a deleted entity, and deleted handles are dropped from entity_to_index.
+/// Stale handles then just fail to resolve to an index, which makes every lookup through them return None
 #[derive(Debug, Copy, Clone, Eq, Hash, Serialize, Deserialize)]
 pub struct Entity(u32);
 impl PartialEq for Entity {
@@ -64,15 +75,84 @@ pub struct EntityEntry {
     pub components: u64, // These are flags
 }
 
+/// Where a dynamically integrated body is predicted to go, from integrating a copy of the scene's rigid bodies forward
+#[derive(Clone)]
+pub struct TrajectoryPrediction {
+    pub body: Entity,
+    pub points: Vec<Vector3<f64>>, // World space positions, starting from where the body was when predicted
+    pub trail: Option<Rc<RefCell<DynamicLinePrimitive>>>, // Created by the rendering system when first drawn
+}
+
+/// Path that the point right below `body` traced on the surface of `central_body`, which the ground track system
+/// extends as they move
+#[derive(Clone)]
+pub struct GroundTrack {
+    pub body: Entity,
+    pub central_body: Entity,
+    pub points: VecDeque<(f64, Vector3<f64>)>, // Sim time and unit vector in the frame of the surface, oldest first
+    pub trail: Option<Rc<RefCell<DynamicLinePrimitive>>>, // Created by the rendering system when first drawn
+}
+
+/// Where a freely integrated body has been recently, which the body trail system extends as it moves
+#[derive(Clone)]
+pub struct BodyTrail {
+    pub points: VecDeque<(f64, Vector3<f64>)>, // Sim time and world space position, oldest first
+    pub trail: Option<Rc<RefCell<DynamicLinePrimitive>>>, // Created by the rendering system when first drawn
+}
+
+/// Entities of the reference grid on the plane of state.coordinate_frame, which the transform update system moves,
+/// rescales and rotates along with the camera
+#[derive(Clone)]
+pub struct ReferenceGrid {
+    pub minor: Entity,
+    pub major: Entity,
+    pub spacing: f64, // Mm between minor lines. Major lines are 10 times as far apart
+    pub minor_fade: f32, // 0 when minor lines are fully visible, 1 when they're faded out
+    pub center: Vector3<f64>, // World space, on the plane of the grid
+    pub rotation: UnitQuaternion<f64>, // From the grid's axes to world space
+}
+
+/// An entity drawing the orbit of `body` (usually parented to the body it orbits around)
+#[derive(Clone)]
+pub struct OrbitLine {
+    pub line: Entity,
+    pub body: Entity,
+
+    // Used to draw the trail of the orbit the body has traversed recently
+    pub eccentricity: f64,
+    pub period_days: f64,
+    pub baked_anomalies: Rc<Vec<Rad>>, // From adaptive_eccentric_anomalies
+    pub baked_times: Rc<Vec<Jdn>>, // From bake_times_for_eccentric_anomalies, at baked_anomalies
+    pub rotation: Transform<f64>,  // From elements_to_ellipse_rotation_transform
+    pub trail: Option<Rc<RefCell<DynamicLinePrimitive>>>,
+
+    pub elements: OrbitalElements, // What the line was last built from
+}
+
+/// A body with BodyMotion::Keplerian, which the keplerian system places on the ellipse of `elements` around
+/// `central_body` every frame instead of the physics system integrating it
+#[derive(Clone)]
+pub struct KeplerianBody {
+    pub body: Entity,
+    pub central_body: Entity,
+    pub elements: OrbitalElements,
+    pub rotation: Transform<f64>, // From elements_to_ellipse_rotation_transform
+}
+
 #[derive(Clone)]
 pub struct Scene {
     pub identifier: String,
+    pub epoch: Jdn, // Date corresponding to a sim_time_s of zero
 
     entity_storage: Vec<EntityEntry>,
     free_indices: BinaryHeap<Reverse<u32>>,
     entity_to_index: Rc<RefCell<HashMap<Entity, u32>>>,
     last_used_entity: Entity,
 
+    // Index of the entities spawned for database bodies, keyed by the body id (e.g. "399", "a0000001")
+    body_id_to_entity: HashMap<String, Entity>,
+    entity_to_body_id: HashMap<Entity, String>,
+
     // Not sure where these should be. I think once I expand the rendering capabilities
     // to add shadows, multiple passes and stuff it will become more clear
     // TODO: Need to put these on the resource manager maybe? Or just have some type of skybox/points components
@@ -81,27 +161,93 @@ pub struct Scene {
     pub skybox_mat: Option<Rc<RefCell<Material>>>,
     pub points_mesh: Option<Rc<RefCell<Mesh>>>,
     pub points_mat: Option<Rc<RefCell<Material>>>,
+    pub points_color_mode: Option<PointColorMode>, // What the points mesh colors were last computed with
+    pub points_size_range: Option<(f32, f32)>, // Min and max marker sizes the points mesh was last computed with
+    pub starfield_mesh: Option<Rc<RefCell<Mesh>>>,
+    pub starfield_mat: Option<Rc<RefCell<Material>>>,
+    pub starfield_stars: Vec<Star>,
+    pub starfield_source: Option<StarfieldSource>, // What starfield_stars was last generated from
+    pub star_catalog: Option<Rc<Vec<CatalogStar>>>, // Real stars to draw instead of procedural ones, if we have them
+    pub trail_mat: Option<Rc<RefCell<Material>>>,
+    pub outline_mat: Option<Rc<RefCell<Material>>>, // Draws the outlines of the hovered and selected bodies
+    pub orbits: Vec<OrbitLine>,
+    pub apparent_orbit_line: Option<Entity>, // Orbit line of the reference body, drawn around it instead this frame
+    pub trajectory_prediction: Option<TrajectoryPrediction>,
+    pub ground_track: Option<GroundTrack>,
+    pub body_trails: HashMap<Entity, BodyTrail>, // Only while state.show_body_trails
+    pub grid: Option<ReferenceGrid>,
+    pub axes: Option<Entity>, // Coordinate axes, which turn to match state.coordinate_frame
+    pub keplerian_bodies: Vec<KeplerianBody>,
+    pub catalog_elements: HashMap<Entity, OrbitalElements>, // Orbits from before they were edited in the UI
+
+    // Multiplies how far moons are drawn from the bodies they orbit, without affecting physics
+    pub visual_orbit_scale: f64,
+    pub visual_orbit_offsets: HashMap<Entity, Vector3<f64>>, // Added to world transforms for visual_orbit_scale
+
+    // Multiplies the drawn size of the render scaled transforms (the meshes of bodies), without affecting their
+    // radii for physics, picking or the distances shown
+    pub render_scale: f64,
+
+    // Center of mass of the rigid bodies, subtracted from the world transforms of root entities while we draw in the
+    // center of mass frame. None when we don't
+    pub frame_offset: Option<Vector3<f64>>,
+
+    pub num_culled_bodies: usize, // Database bodies left out because of state.max_body_count
+
+    world_spheres: RefCell<Option<Vec<(Point3<f64>, f64)>>>, // Cache of get_world_bounding_spheres, None until computed
+    world_bounds: Cell<Option<Option<(Point3<f64>, Point3<f64>)>>>, // Cache of get_world_bounds, None until computed
 
     component_groups: HashMap<u64, ComponentGroup>,
 }
 
 // Main interface
 impl Scene {
-    pub(super) fn new(identifier: &str) -> Self {
+    pub fn new(identifier: &str) -> Self {
         let entity_to_index = Rc::new(RefCell::new(HashMap::new()));
 
         Self {
             identifier: identifier.to_string(),
+            epoch: J2000_JDN,
             entity_storage: Vec::new(),
             free_indices: BinaryHeap::new(),
             entity_to_index: entity_to_index.clone(),
             last_used_entity: Entity(0),
 
+            body_id_to_entity: HashMap::new(),
+            entity_to_body_id: HashMap::new(),
+
             skybox_trans: None,
             skybox_mesh: None,
             skybox_mat: None,
             points_mesh: None,
             points_mat: None,
+            points_color_mode: None,
+            points_size_range: None,
+            starfield_mesh: None,
+            starfield_mat: None,
+            starfield_stars: Vec::new(),
+            starfield_source: None,
+            star_catalog: None,
+            trail_mat: None,
+            outline_mat: None,
+            orbits: Vec::new(),
+            apparent_orbit_line: None,
+            trajectory_prediction: None,
+            ground_track: None,
+            body_trails: HashMap::new(),
+            grid: None,
+            axes: None,
+            keplerian_bodies: Vec::new(),
+            catalog_elements: HashMap::new(),
+
+            visual_orbit_scale: 1.0,
+            render_scale: 1.0,
+            visual_orbit_offsets: HashMap::new(),
+            frame_offset: None,
+            num_culled_bodies: 0,
+
+            world_spheres: RefCell::new(None),
+            world_bounds: Cell::new(None),
 
             component_groups: HashMap::new(),
         }
@@ -110,6 +256,95 @@ impl Scene {
     pub fn get_entity_entries(&self) -> &Vec<EntityEntry> {
         return &self.entity_storage;
     }
+
+    /// World space position of `entity` as physics sees it, without the offset it's drawn with due to
+    /// visual_orbit_scale. Distances and velocities shown to the user should be computed from this. It's still moved by
+    /// frame_offset like everything else we draw, so that it can be compared with the camera position
+    pub fn get_physical_position(&self, entity: Entity) -> Option<Vector3<f64>> {
+        let trans = self
+            .get_component::<TransformComponent>(entity)?
+            .get_world_transform()
+            .trans;
+
+        return Some(match self.visual_orbit_offsets.get(&entity) {
+            Some(offset) => trans - offset,
+            None => trans,
+        });
+    }
+
+    /// Bounding spheres (center, radius) of the colliders of every live entity with a visible mesh, as they're drawn in
+    /// world space. Meshes without colliders are left out.
+    ///
+    /// Cached until something could have moved them, i.e. the transform update system changing a world transform, or
+    /// any component being added or fetched mutably
+    pub fn get_world_bounding_spheres(&self) -> Ref<Vec<(Point3<f64>, f64)>> {
+        if self.world_spheres.borrow().is_none() {
+            *self.world_spheres.borrow_mut() = Some(self.compute_world_bounding_spheres());
+        }
+
+        return Ref::map(self.world_spheres.borrow(), |spheres| {
+            spheres.as_ref().unwrap()
+        });
+    }
+
+    fn compute_world_bounding_spheres(&self) -> Vec<(Point3<f64>, f64)> {
+        let mut spheres = Vec::new();
+        for entry in self.entity_storage.iter().filter(|entry| entry.live) {
+            let mesh = match self.get_component::<MeshComponent>(entry.current) {
+                Some(mesh_comp) if mesh_comp.visible => mesh_comp.get_mesh(),
+                _ => None,
+            };
+            let trans = self.get_component::<TransformComponent>(entry.current);
+            let sphere = mesh.and_then(|mesh| {
+                mesh.borrow()
+                    .collider
+                    .as_ref()
+                    .and_then(|collider| collider.bounding_sphere())
+            });
+            if trans.is_none() || sphere.is_none() {
+                continue;
+            }
+
+            let trans = trans.unwrap().get_world_transform();
+            let (center, radius) = sphere.unwrap();
+            spheres.push((
+                trans.transform_point(&na::convert::<Point3<f32>, Point3<f64>>(center)),
+                radius as f64 * trans.scale.abs().max(),
+            ));
+        }
+
+        return spheres;
+    }
+
+    /// Axis aligned box (mins, maxes) around get_world_bounding_spheres, or None if there aren't any. Cached along with
+    /// them
+    pub fn get_world_bounds(&self) -> Option<(Point3<f64>, Point3<f64>)> {
+        if let Some(bounds) = self.world_bounds.get() {
+            return bounds;
+        }
+
+        let mut bounds: Option<(Point3<f64>, Point3<f64>)> = None;
+        for &(center, radius) in self.get_world_bounding_spheres().iter() {
+            let mins = center.coords.add_scalar(-radius);
+            let maxes = center.coords.add_scalar(radius);
+            bounds = Some(match bounds {
+                Some((old_mins, old_maxes)) => (
+                    Point3::from(old_mins.coords.inf(&mins)),
+                    Point3::from(old_maxes.coords.sup(&maxes)),
+                ),
+                None => (Point3::from(mins), Point3::from(maxes)),
+            });
+        }
+
+        self.world_bounds.set(Some(bounds));
+        return bounds;
+    }
+
+    /// Makes the next get_world_bounding_spheres and get_world_bounds compute them again
+    pub fn invalidate_world_bounds(&self) {
+        self.world_spheres.replace(None);
+        self.world_bounds.set(None);
+    }
 }
 
 // Entity interface
@@ -256,6 +491,11 @@ impl Scene {
                 self.free_indices.push(Reverse(index));
                 self.entity_to_index.borrow_mut().remove(&e);
 
+                if let Some(body_id) = self.entity_to_body_id.remove(&e) {
+                    self.body_id_to_entity.remove(&body_id);
+                }
+                self.invalidate_world_bounds();
+
                 return true;
             }
             None => return false,
@@ -333,6 +573,45 @@ impl Scene {
         return None;
     }
 
+    /// Registers `entity` as the main entity of the database body `body_id`, so that it can be found by id later.
+    /// If another entity was already registered for that body id, it is replaced
+    pub fn set_entity_body_id(&mut self, entity: Entity, body_id: &str) {
+        if let Some(old_body_id) = self.entity_to_body_id.remove(&entity) {
+            self.body_id_to_entity.remove(&old_body_id);
+        }
+
+        if let Some(old_entity) = self.body_id_to_entity.insert(body_id.to_owned(), entity) {
+            warning!(
+                LogCat::Scene,
+                "Body id '{}' was already used by entity '{:?}'. Entity '{:?}' will be used from now on",
+                body_id,
+                old_entity,
+                entity
+            );
+            self.entity_to_body_id.remove(&old_entity);
+        }
+
+        self.entity_to_body_id.insert(entity, body_id.to_owned());
+    }
+
+    /// Finds the entity spawned for the database body `body_id`, if any
+    pub fn get_entity_from_body_id(&self, body_id: &str) -> Option<Entity> {
+        return self.body_id_to_entity.get(body_id).cloned();
+    }
+
+    /// Returns the id of the database body `entity` was spawned for, if any
+    pub fn get_body_id_from_entity(&self, entity: Entity) -> Option<&str> {
+        return self
+            .entity_to_body_id
+            .get(&entity)
+            .and_then(|id| Some(id.as_str()));
+    }
+
+    /// All the body ids registered with set_entity_body_id and their entities
+    pub fn get_body_id_entities(&self) -> &HashMap<String, Entity> {
+        return &self.body_id_to_entity;
+    }
+
     // Weird hacky function to quickly get the parent index for TransformUpdateSystem
     pub fn get_parent_index_from_index(&self, entity_index: u32) -> Option<u32> {
         match self.entity_storage.get(entity_index as usize) {
@@ -511,6 +790,7 @@ impl Scene {
     where
         T: Component<ComponentType = T>,
     {
+        self.invalidate_world_bounds();
         return T::get_storage_mut(self).get_component_mut(entity);
     }
 
@@ -525,9 +805,39 @@ impl Scene {
             entity
         );
 
+        self.invalidate_world_bounds();
         return T::get_storage_mut(self).add_component(entity);
     }
 
+    /// Shows or hides the mesh of `entity` without deleting it, and the meshes of all of its descendants if
+    /// `include_children` is set. Entities without a MeshComponent have nothing to hide, but we still go through
+    /// their children
+    pub fn set_entity_visible(&mut self, entity: Entity, visible: bool, include_children: bool) {
+        if let Some(mesh_comp) = self.get_component_mut::<MeshComponent>(entity) {
+            mesh_comp.visible = visible;
+        }
+
+        if !include_children {
+            return;
+        }
+
+        let children = self
+            .get_entity_children(entity)
+            .cloned()
+            .unwrap_or_default();
+        for child in children {
+            self.set_entity_visible(child, visible, true);
+        }
+    }
+
+    /// Whether the mesh of `entity` gets drawn. Entities without a MeshComponent count as visible
+    pub fn is_entity_visible(&self, entity: Entity) -> bool {
+        return self
+            .get_component::<MeshComponent>(entity)
+            .map(|mesh_comp| mesh_comp.visible)
+            .unwrap_or(true);
+    }
+
     fn swap_components(&mut self, index_a: u32, index_b: u32) {
         if index_a == index_b {
             return;
@@ -547,6 +857,41 @@ impl Scene {
         self.light.swap_components(ent_a, ent_b);
     }
 
+    /// Memory held by each of our storages, with a name for displaying alongside it
+    pub fn get_memory_report(&self) -> Vec<(&'static str, MemoryReport)> {
+        let children_bytes: usize = self
+            .entity_storage
+            .iter()
+            .map(|entry| {
+                entry.children.capacity() * size_of::<Entity>()
+                    + entry.name.as_ref().map(|n| n.capacity()).unwrap_or(0)
+            })
+            .sum();
+
+        // Deleted entities leave their slots allocated until something reuses them
+        let entities = MemoryReport::from_vec(
+            &self.entity_storage,
+            self.entity_storage.len() - self.free_indices.len(),
+            children_bytes,
+        );
+
+        let body_id_bytes: usize = self
+            .body_id_to_entity
+            .keys()
+            .map(|id| id.capacity() * 2) // Each id is stored in both maps
+            .sum();
+        let body_ids = MemoryReport {
+            allocated_bytes: (self.body_id_to_entity.capacity()
+                + self.entity_to_body_id.capacity())
+                * size_of::<(String, Entity)>()
+                + body_id_bytes,
+            capacity: self.body_id_to_entity.capacity(),
+            num_elements: self.body_id_to_entity.len(),
+        };
+
+        return vec![("Entities", entities), ("Body id index", body_ids)];
+    }
+
     /// Resizes sparse storage components to match a target min_length
     fn resize_components(&mut self, min_length: u32) {
         if min_length <= self.transform.get_num_components() {
@@ -557,3 +902,204 @@ impl Scene {
         self.mesh.resize(min_length);
     }
 }
+
+// Test interface
+impl Scene {
+    /// Adds a free body called `name` at `pos` (Mm) with a rigid body of `mass` (kg), at rest.
+    /// Its world transform starts there too, so it can be used before the transform update runs
+    pub fn add_test_body(&mut self, name: &str, pos: Vector3<f64>, mass: f64) -> Entity {
+        let builder = self.start_new_entity(name);
+        let entity = self.finish_new_entity(builder);
+        let trans = self.add_component::<TransformComponent>(entity);
+        trans.get_local_transform_mut().trans = pos;
+        trans.get_world_transform_mut().trans = pos;
+
+        let rb = self.add_component::<RigidBodyComponent>(entity);
+        rb.mass = mass;
+        rb.trans.trans = pos;
+        return entity;
+    }
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    pub fn reused_index_invalidates_stale_entity() {
+        let mut scene = Scene::new("test");
+
+        let builder = scene.start_new_entity("old");
+        let old = scene.finish_new_entity(builder);
+        let old_index = scene.get_entity_index(old).unwrap();
+        assert!(scene.delete_entity(old));
+
+        // The freed index gets reused by the next entity
+        let builder = scene.start_new_entity("new");
+        let new = scene.finish_new_entity(builder);
+        assert_eq!(scene.get_entity_index(new), Some(old_index));
+        assert_ne!(old, new);
+
+        // But the old handle doesn't resolve to it
+        assert!(!scene.is_live(old));
+        assert_eq!(scene.get_entity_index(old), None);
+        assert_eq!(scene.get_entity_name(old), None);
+        assert_eq!(scene.get_entity_name(new), Some("new"));
+    }
+
+    #[wasm_bindgen_test]
+    pub fn body_id_index_follows_entities() {
+        let mut scene = Scene::new("test");
+
+        let builder = scene.start_new_entity("Earth");
+        let earth = scene.finish_new_entity(builder);
+        let builder = scene.start_new_entity("Moon");
+        let moon = scene.finish_new_entity(builder);
+        scene.set_entity_body_id(earth, "399");
+        scene.set_entity_body_id(moon, "301");
+
+        assert_eq!(scene.get_entity_from_body_id("399"), Some(earth));
+        assert_eq!(scene.get_entity_from_body_id("301"), Some(moon));
+        assert_eq!(scene.get_body_id_from_entity(moon), Some("301"));
+
+        // Deleting an entity drops it from the index
+        assert!(scene.delete_entity(moon));
+        assert_eq!(scene.get_entity_from_body_id("301"), None);
+        assert_eq!(scene.get_body_id_from_entity(moon), None);
+        assert_eq!(scene.get_body_id_entities().len(), 1);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn memory_report_counts_freed_slots() {
+        let mut scene = Scene::new("test");
+
+        let mut entities = Vec::new();
+        for index in 0..10 {
+            let builder = scene.start_new_entity(&format!("entity {}", index));
+            entities.push(scene.finish_new_entity(builder));
+        }
+
+        // Delete every other entity, which leaves holes in the entity storage
+        for entity in entities.iter().step_by(2) {
+            assert!(scene.delete_entity(*entity));
+        }
+
+        let report = scene
+            .get_memory_report()
+            .iter()
+            .find(|(name, _)| *name == "Entities")
+            .unwrap()
+            .1;
+        assert_eq!(report.num_elements, 5);
+        assert!(report.capacity > report.num_elements);
+        assert!(report.get_occupancy() < 1.0);
+        assert!(report.allocated_bytes >= report.capacity * size_of::<EntityEntry>());
+    }
+
+    #[wasm_bindgen_test]
+    pub fn hiding_entities_with_children() {
+        let mut scene = Scene::new("test");
+
+        let mut entities = Vec::new();
+        for name in &["Saturn", "Saturn's rings", "Titan"] {
+            let builder = scene.start_new_entity(name);
+            let entity = scene.finish_new_entity(builder);
+            scene.add_component::<MeshComponent>(entity);
+            entities.push(entity);
+        }
+        let (saturn, rings, titan) = (entities[0], entities[1], entities[2]);
+        scene.set_entity_parent(saturn, rings);
+        scene.set_entity_parent(saturn, titan);
+
+        scene.set_entity_visible(rings, false, false);
+        assert!(scene.is_entity_visible(saturn));
+        assert!(!scene.is_entity_visible(rings));
+
+        scene.set_entity_visible(saturn, false, true);
+        assert!(entities.iter().all(|e| !scene.is_entity_visible(*e)));
+
+        // Showing Saturn alone leaves its children hidden
+        scene.set_entity_visible(saturn, true, false);
+        assert!(scene.is_entity_visible(saturn));
+        assert!(!scene.is_entity_visible(titan));
+    }
+
+    #[wasm_bindgen_test]
+    pub fn world_bounds_fit_scaled_colliders() {
+        use crate::managers::resource::collider::SphereCollider;
+
+        let mut scene = Scene::new("test");
+        assert!(scene.get_world_bounds().is_none());
+
+        // Unit sphere colliders, moved and scaled by their world transforms
+        let mesh = Rc::new(RefCell::new(Mesh {
+            collider: Some(Box::new(SphereCollider {
+                center: Point3::origin(),
+                radius2: 1.0,
+            })),
+            ..Mesh::default()
+        }));
+        let add_sphere = |scene: &mut Scene, pos: Vector3<f64>, scale: f64| -> Entity {
+            let builder = scene.start_new_entity("sphere");
+            let entity = scene.finish_new_entity(builder);
+            let trans = scene
+                .add_component::<TransformComponent>(entity)
+                .get_world_transform_mut();
+            trans.trans = pos;
+            trans.scale = Vector3::new(scale, scale, scale);
+            scene
+                .add_component::<MeshComponent>(entity)
+                .set_mesh(Some(mesh.clone()));
+            return entity;
+        };
+        add_sphere(&mut scene, Vector3::new(10.0, 0.0, 0.0), 1.0);
+        let big = add_sphere(&mut scene, Vector3::new(-5.0, 3.0, 0.0), 4.0);
+
+        // Entities without colliders don't count
+        let builder = scene.start_new_entity("no collider");
+        let empty = scene.finish_new_entity(builder);
+        scene
+            .add_component::<TransformComponent>(empty)
+            .get_world_transform_mut()
+            .trans = Vector3::new(0.0, 0.0, 1000.0);
+        scene.add_component::<MeshComponent>(empty);
+
+        let (mins, maxes) = scene.get_world_bounds().unwrap();
+        assert!(
+            (mins - Point3::new(-9.0, -1.0, -4.0)).magnitude() < 1E-9,
+            "{:?}",
+            mins
+        );
+        assert!(
+            (maxes - Point3::new(11.0, 7.0, 4.0)).magnitude() < 1E-9,
+            "{:?}",
+            maxes
+        );
+
+        // Hiding or deleting them updates the bounds
+        scene.set_entity_visible(big, false, false);
+        let (mins, maxes) = scene.get_world_bounds().unwrap();
+        assert!(
+            (mins - Point3::new(9.0, -1.0, -1.0)).magnitude() < 1E-9,
+            "{:?}",
+            mins
+        );
+        assert!(
+            (maxes - Point3::new(11.0, 1.0, 1.0)).magnitude() < 1E-9,
+            "{:?}",
+            maxes
+        );
+
+        scene.set_entity_visible(big, true, false);
+        scene.delete_entity(big);
+        let (mins, _) = scene.get_world_bounds().unwrap();
+        assert!(
+            (mins - Point3::new(9.0, -1.0, -1.0)).magnitude() < 1E-9,
+            "{:?}",
+            mins
+        );
+    }
+}
@@ -1,45 +1,201 @@
 use crate::app_state::{AppState, ReferenceChange};
-use crate::components::{MeshComponent, TransformComponent};
+use crate::components::{MeshComponent, MetadataComponent, TransformComponent};
 use crate::managers::orbit::{BodyDescription, BodyInstanceDescription};
+use crate::managers::resource::material::Material;
 use crate::managers::resource::texture::TextureUnit;
-use crate::managers::scene::description::SceneDescription;
-use crate::managers::scene::orbits::{add_body_instance_entities, fetch_default_motion_if_needed};
-use crate::managers::scene::{Entity, Scene};
+use crate::managers::scene::description::{SceneDescription, SceneLoadReport};
+use crate::managers::scene::orbits::{
+    add_body_instance_entities, add_keplerian_body, add_orbit_line_entity,
+    fetch_default_motion_if_needed, find_orbit_central_body,
+};
+use crate::managers::scene::{Entity, ReferenceGrid, Scene};
 use crate::managers::OrbitManager;
 use crate::managers::ResourceManager;
+use crate::utils::camera::{get_bounding_sphere, get_framing_distance, DEFAULT_FOV_V};
 use crate::utils::log::*;
-use crate::utils::orbits::OBLIQUITY_OF_ECLIPTIC;
-use crate::utils::units::J2000_JDN;
+use crate::utils::star_catalog::CatalogStar;
+use crate::utils::units::{Jdn, OBLIQUITY_OF_ECLIPTIC};
 use na::*;
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 
 pub struct SceneManager {
     current_scene: Option<Scene>,
     pub descriptions: HashMap<String, SceneDescription>,
+    pub last_load_report: Option<SceneLoadReport>, // Problems with the last loaded scene, until the user dismisses them
+    star_catalog: Option<Rc<Vec<CatalogStar>>>,    // Shared with every scene we load
 }
+/// Mm. Falls back to the scale of the body for things we don't have a radius for
+pub fn get_body_radius(scene: &Scene, body: Entity) -> f64 {
+    if let Some(radius) = scene
+        .get_component::<MetadataComponent>(body)
+        .and_then(|meta| meta.get_metadata("body_radius"))
+        .and_then(|s| s.parse::<f64>().ok())
+    {
+        if radius > 0.0 {
+            return radius;
+        }
+    }
+
+    return scene
+        .get_component::<TransformComponent>(body)
+        .map(|trans| trans.get_world_transform().scale.mean())
+        .unwrap_or(1.0);
+}
+
+/// Draws the meshes of `body` with `color` instead of their materials' base color, or with their own color again if
+/// None. Only recolors the mesh holders of the body itself, and not e.g. its atmosphere shell or its moons
+pub fn set_body_color_override(scene: &mut Scene, body: Entity, color: Option<[f32; 4]>) {
+    let mut entities = vec![body];
+    if let Some(children) = scene.get_entity_children(body) {
+        entities.extend(
+            children
+                .iter()
+                .filter(|child| scene.get_body_id_from_entity(**child).is_none()),
+        );
+    }
+
+    for entity in entities {
+        if let Some(mesh_comp) = scene.get_component_mut::<MeshComponent>(entity) {
+            if mesh_comp.raycasting_visible {
+                mesh_comp.color_override = color;
+            }
+        }
+    }
+
+    // So that the point markers pick it up too
+    scene.points_color_mode = None;
+}
+
+/// Applies state.body_colors to whichever of those bodies are in `scene`
+pub fn apply_body_colors(state: &AppState, scene: &mut Scene) {
+    for (body_id, color) in state.body_colors.iter() {
+        if let Some(body) = scene.get_entity_from_body_id(body_id) {
+            set_body_color_override(scene, body, Some(*color));
+        }
+    }
+}
+
+/// Drops all but the `max_count` most significant database bodies of `bodies`: The largest ones, and then the brightest
+/// ones among those we don't know the size of. Bodies that aren't from a database are always kept, and zero keeps
+/// everything. Returns how many were dropped.
+///
+/// Children of dropped bodies won't find their parents, so they won't be spawned either
+fn cull_least_significant_bodies(
+    bodies: &mut Vec<(Option<&BodyDescription>, &BodyInstanceDescription)>,
+    max_count: usize,
+) -> usize {
+    let mut ranked: Vec<(usize, &BodyDescription)> = bodies
+        .iter()
+        .enumerate()
+        .filter_map(|(index, (body, _))| body.map(|body| (index, body)))
+        .collect();
+    if max_count == 0 || ranked.len() <= max_count {
+        return 0;
+    }
+
+    // Stable, so that equally significant bodies keep their order
+    ranked.sort_by(|(_, a), (_, b)| {
+        let radius = |body: &BodyDescription| body.radius.unwrap_or(0.0);
+        let magnitude = |body: &BodyDescription| body.magnitude.unwrap_or(std::f32::INFINITY);
+        return radius(b)
+            .partial_cmp(&radius(a))
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(
+                magnitude(a)
+                    .partial_cmp(&magnitude(b))
+                    .unwrap_or(std::cmp::Ordering::Equal),
+            );
+    });
+
+    let culled: HashSet<usize> = ranked[max_count..]
+        .iter()
+        .map(|(index, _)| *index)
+        .collect();
+    let mut index = 0;
+    bodies.retain(|_| {
+        index += 1;
+        return !culled.contains(&(index - 1));
+    });
+
+    return culled.len();
+}
+
+/// New skybox material instance showing the skybox texture of `desc`, which gets requested if we don't have it yet
+fn create_skybox_material(
+    desc: &SceneDescription,
+    res_man: &mut ResourceManager,
+) -> Option<Rc<RefCell<Material>>> {
+    let mat = res_man.instantiate_material("skybox", "test_skybox")?;
+    mat.borrow_mut().set_texture(
+        TextureUnit::BaseColor,
+        res_man.get_or_request_texture(&desc.get_skybox_texture(), TextureUnit::BaseColor, true),
+    );
+    return Some(mat);
+}
+
 impl SceneManager {
     pub fn new() -> Self {
         Self {
             current_scene: Some(Scene::new("empty")),
             descriptions: HashMap::new(),
+            last_load_report: None,
+            star_catalog: None,
         }
     }
 
+    /// Makes the current scene and every scene loaded after it draw the stars of `catalog`
+    pub fn set_star_catalog(&mut self, catalog: Vec<CatalogStar>) {
+        let catalog = Rc::new(catalog);
+        if let Some(scene) = &mut self.current_scene {
+            scene.star_catalog = Some(catalog.clone());
+            scene.starfield_source = None;
+        }
+
+        self.star_catalog = Some(catalog);
+    }
+
+    /// Returns the bodies of the scene description that couldn't be found, which are also kept in `last_load_report`
     pub fn load_scene(
         &mut self,
         identifier: &str,
         res_man: &mut ResourceManager,
         orbit_man: &OrbitManager,
         state: &mut AppState,
-    ) {
+    ) -> SceneLoadReport {
         state.selection = None;
         state.hovered = None;
+        state.last_pick_ray = None;
+
+        // Whatever time step was still under way was for the previous scene
+        state.pending_time_step_s = 0.0;
+        state.time_step_total_s = 0.0;
 
         self.current_scene = Some(Scene::new(&identifier));
 
+        let report = match self.descriptions.get(identifier) {
+            Some(desc) => desc.validate(orbit_man),
+            None => SceneLoadReport {
+                scene: identifier.to_owned(),
+                ..SceneLoadReport::default()
+            },
+        };
+        if !report.is_ok() {
+            warning!(
+                LogCat::Scene,
+                "Scene '{}' references bodies that aren't in the loaded databases, they will be skipped: {:#?}",
+                identifier,
+                report
+            );
+        }
+        self.last_load_report = Some(report.clone());
+
         // Check if we have a description for that scene (they should have same name)
         if let Some(desc) = self.descriptions.get(identifier) {
             state.simulation_speed = desc.simulation_scale;
+            desc.apply_display_preferences(state);
+            desc.apply_lighting_preferences(state);
 
             let mut need_go_to: bool = false;
 
@@ -80,7 +236,7 @@ impl SceneManager {
             }
 
             // TODO: Proper handling of time (would involve rolling simulation/orbits forward/back
-            // to match target time, for now let's just all sit at J2000)
+            // to match target time, for now let's just all sit at the scene's epoch)
             assert!(desc.time == String::from("J2000"));
             state.sim_time_s = 0.0;
         }
@@ -90,6 +246,41 @@ impl SceneManager {
         state.last_scene_identifier = identifier.to_string();
 
         self.load_scene_from_desc(identifier, res_man, orbit_man, state);
+        if let Some(scene) = &mut self.current_scene {
+            apply_body_colors(state, scene);
+        }
+
+        // Whatever only the previous scene was using
+        res_man.collect_unused();
+
+        // Do these after the entities are created, so that we can find the selection by name
+        if let Some(desc) = self.descriptions.get(identifier) {
+            if let Some(open_windows) = &desc.open_windows {
+                open_windows.apply(&mut state.open_windows);
+            }
+
+            if let Some(name) = &desc.initial_selection {
+                state.selection = self
+                    .current_scene
+                    .as_ref()
+                    .and_then(|scene| scene.get_entity_from_name(name));
+
+                if state.selection.is_none() {
+                    warning!(
+                        LogCat::Scene,
+                        "Failed to find initial selection '{}' for scene '{}'",
+                        name,
+                        identifier
+                    );
+                    state.add_warning_toast(format!(
+                        "Couldn't find '{}' to select in scene '{}'",
+                        name, identifier
+                    ));
+                }
+            }
+        }
+
+        return report;
     }
 
     pub fn load_last_scene(
@@ -115,6 +306,88 @@ impl SceneManager {
         }
     }
 
+    /// Moves the camera back to the framing that the current scene's description wants, without reloading anything.
+    /// If the description has no camera we frame its focused body, or the whole system if it has no focus either
+    pub fn reset_view_to_scene_default(&self, state: &mut AppState) {
+        let scene = match &self.current_scene {
+            Some(scene) => scene,
+            None => return,
+        };
+        let desc = self.descriptions.get(&scene.identifier);
+
+        let focus = desc
+            .and_then(|desc| desc.focus.as_ref())
+            .and_then(|focus| scene.get_entity_from_body_id(focus));
+
+        state.follow = None;
+        state.entity_going_to = None;
+        state.reference_entity = None;
+        state.reference_translation = None;
+        state.camera.fov_v = DEFAULT_FOV_V;
+        state.camera.up = Unit::new_unchecked(Vector3::z());
+
+        // All the camera positions below are wrt. the focused entity, or in world space if we have none
+        state.next_reference_entity = Some(match focus {
+            Some(focus) => ReferenceChange::FocusKeepCoords(focus),
+            None => ReferenceChange::Clear,
+        });
+
+        if let Some(desc) = desc {
+            if desc.camera_pos.is_some() && desc.camera_target.is_some() && desc.camera_up.is_some()
+            {
+                state.camera.pos = desc.camera_pos.unwrap();
+                state.camera.up = desc.camera_up.unwrap();
+                state.camera.target = desc.camera_target.unwrap();
+                return;
+            }
+        }
+
+        let (target, radius) = match focus {
+            Some(focus) => (Point3::origin(), get_body_radius(scene, focus)),
+            // Everything we draw, or just the bodies if none of them have colliders yet
+            None => match scene.get_world_bounds() {
+                Some((mins, maxes)) => {
+                    (na::center(&mins, &maxes), (maxes - mins).magnitude() / 2.0)
+                }
+                None => {
+                    let spheres: Vec<(Point3<f64>, f64)> = scene
+                        .get_body_id_entities()
+                        .values()
+                        .filter_map(|ent| {
+                            let trans = scene.get_component::<TransformComponent>(*ent)?;
+                            return Some((
+                                Point3::from(trans.get_world_transform().trans),
+                                get_body_radius(scene, *ent),
+                            ));
+                        })
+                        .collect();
+
+                    match get_bounding_sphere(&spheres) {
+                        Some(sphere) => sphere,
+                        None => return,
+                    }
+                }
+            },
+        };
+
+        // Look from above the ecliptic at an angle, like when going to a body
+        let distance = get_framing_distance(radius, state.camera.fov_v);
+        state.camera.target = target;
+        state.camera.pos = target + Vector3::new(1.0, 1.0, 1.0).normalize() * distance;
+    }
+
+    /// Replaces the current scene with one that was built in code, e.g. with a SceneBuilder
+    pub fn set_current_scene(&mut self, scene: Scene, state: &mut AppState) {
+        state.selection = None;
+        state.hovered = None;
+        state.last_scene_identifier = scene.identifier.clone();
+
+        self.last_load_report = None;
+        self.current_scene = Some(scene);
+        self.current_scene.as_mut().unwrap().star_catalog = self.star_catalog.clone();
+        apply_body_colors(state, self.current_scene.as_mut().unwrap());
+    }
+
     pub fn get_current_scene(&self) -> Option<&Scene> {
         self.current_scene.as_ref()
     }
@@ -123,14 +396,9 @@ impl SceneManager {
         self.current_scene.as_mut()
     }
 
-    pub fn receive_serialized_scene(&mut self, serialized: &str) {
-        let new_desc: Result<SceneDescription, String> = ron::de::from_str(serialized)
-            .map_err(|e| format!("RON deserialization error:\n{}", e).to_owned());
-        if let Err(e) = new_desc {
-            error!(LogCat::Io, "{}", e);
-            return;
-        }
-        let new_desc = new_desc.unwrap();
+    pub fn receive_serialized_scene(&mut self, serialized: &str) -> Result<(), String> {
+        let new_desc: SceneDescription = ron::de::from_str(serialized)
+            .map_err(|e| format!("RON deserialization error:\n{}", e).to_owned())?;
 
         info!(
             LogCat::Scene,
@@ -139,6 +407,45 @@ impl SceneManager {
 
         let name = new_desc.name.clone();
         self.descriptions.insert(name, new_desc);
+        return Ok(());
+    }
+
+    /// Reloads the current scene if any of its bodies come from the body database `db_name`, so that it picks up
+    /// bodies that were just added to it. Returns whether it reloaded
+    pub fn reload_current_scene_if_using_database(
+        &mut self,
+        db_name: &str,
+        res_man: &mut ResourceManager,
+        orbit_man: &OrbitManager,
+        state: &mut AppState,
+    ) -> bool {
+        let identifier = match &self.current_scene {
+            Some(scene) => scene.identifier.clone(),
+            None => return false,
+        };
+
+        let uses_database = self
+            .descriptions
+            .get(&identifier)
+            .map(|desc| {
+                desc.bodies.iter().any(|body| {
+                    body.source
+                        .as_ref()
+                        .and_then(|source| source.rfind("/").map(|pos| &source[..pos] == db_name))
+                        .unwrap_or(false)
+                })
+            })
+            .unwrap_or(false);
+        if !uses_database {
+            return false;
+        }
+
+        info!(
+            LogCat::Scene,
+            "Reloading scene '{}' as its body database '{}' changed", identifier, db_name
+        );
+        self.load_scene(&identifier, res_man, orbit_man, state);
+        return true;
     }
 
     fn load_scene_from_desc(
@@ -154,7 +461,9 @@ impl SceneManager {
         // TODO: Remove this cloning
         let name = desc.name.clone();
         let mut instance_descs: Vec<BodyInstanceDescription> = desc.bodies.clone();
-        let time = J2000_JDN;
+        let time = Jdn(desc.epoch_jdn);
+        self.current_scene.as_mut().unwrap().epoch = time;
+        self.current_scene.as_mut().unwrap().render_scale = desc.render_scale;
 
         let mut parsed_body_name_to_main_ent: HashMap<String, Entity> = HashMap::new();
         let mut bodies_to_parse: Vec<(Option<&BodyDescription>, &BodyInstanceDescription)> =
@@ -164,7 +473,11 @@ impl SceneManager {
         // TODO: This type of stuff shouldn't be here...
         for instance_desc in instance_descs.iter_mut() {
             if let Some(source) = instance_desc.source.clone() {
-                let slash_pos = source.rfind("/").unwrap();
+                // Already reported by SceneDescription::validate
+                let slash_pos = match source.rfind("/") {
+                    Some(pos) => pos,
+                    None => continue,
+                };
                 let db_name = &source[..slash_pos]; // e.g. "major_bodies"
                 let body_id = &source[slash_pos + 1..]; // e.g. "399"
 
@@ -185,6 +498,9 @@ impl SceneManager {
                         limit = Some(parsed.unwrap());
                     }
 
+                    if !orbit_man.has_database(db_name) {
+                        continue;
+                    }
                     let bodies = orbit_man.get_n_bodies(db_name, limit);
 
                     info!(
@@ -206,6 +522,18 @@ impl SceneManager {
             }
         }
 
+        let num_culled = cull_least_significant_bodies(&mut bodies_to_parse, state.max_body_count);
+        if num_culled > 0 {
+            info!(
+                LogCat::Scene,
+                "Leaving out {} bodies from scene '{}' to stay under the limit of {}",
+                num_culled,
+                identifier,
+                state.max_body_count
+            );
+        }
+        self.current_scene.as_mut().unwrap().num_culled_bodies = num_culled;
+
         // Parse bodies
         let mut last_num_left = 0;
         let num_left = loop {
@@ -272,14 +600,117 @@ impl SceneManager {
 
         let mut_scene = self.current_scene.as_mut().unwrap();
 
+        let body_id_to_ent: Vec<(String, Entity)> = mut_scene
+            .get_body_id_entities()
+            .iter()
+            .map(|(id, ent)| (id.clone(), *ent))
+            .collect();
+
+        // Orbital element metadata, used to color points by them
+        for (body_id, ent) in body_id_to_ent.iter() {
+            let elements = orbit_man.get_osc_elements().get(body_id).and_then(|all| {
+                all.iter()
+                    .find(|e| e.semi_major_axis.0 > 0.0 && &e.ref_id != body_id)
+            });
+            if elements.is_none() {
+                continue;
+            }
+            let elements = elements.unwrap();
+
+            if let Some(meta_comp) = mut_scene.get_component_mut::<MetadataComponent>(*ent) {
+                meta_comp.set_metadata("body_eccentricity", &elements.eccentricity.to_string());
+                meta_comp.set_metadata(
+                    "body_semi_major_axis",
+                    &elements.semi_major_axis.0.to_string(),
+                );
+            }
+        }
+
+        // Central bodies, used to show orbital readouts for each body
+        for (body_id, ent) in body_id_to_ent.iter() {
+            if let Some((_, elements)) =
+                find_orbit_central_body(mut_scene, body_id, orbit_man.get_osc_elements())
+            {
+                let central_id = elements.ref_id.clone();
+                if let Some(meta_comp) = mut_scene.get_component_mut::<MetadataComponent>(*ent) {
+                    meta_comp.set_metadata("body_central_id", &central_id);
+                }
+            }
+        }
+
+        // Keplerian bodies, which the keplerian system moves along their orbits instead of simulating them
+        for (body_id, ent) in body_id_to_ent.iter() {
+            let is_keplerian = mut_scene
+                .get_component::<MetadataComponent>(*ent)
+                .and_then(|meta| meta.get_metadata("body_motion"))
+                .map(|motion| motion == "Keplerian")
+                .unwrap_or(false);
+            if !is_keplerian {
+                continue;
+            }
+
+            if mut_scene.get_entity_parent(*ent).is_some() {
+                warning!(
+                    LogCat::Scene,
+                    "Ignoring Keplerian motion for body '{}' as it has a parent",
+                    body_id
+                );
+                continue;
+            }
+
+            match find_orbit_central_body(mut_scene, body_id, orbit_man.get_osc_elements()) {
+                Some((central_ent, elements)) => {
+                    add_keplerian_body(mut_scene, *ent, central_ent, elements);
+                }
+                None => warning!(
+                    LogCat::Scene,
+                    "Keplerian body '{}' has no orbital elements around a body in the scene, so it won't move",
+                    body_id
+                ),
+            }
+        }
+
+        // Orbit trails and predicted trajectories
+        mut_scene.trail_mat = res_man.get_or_create_material("line");
+        mut_scene.outline_mat = res_man.get_or_create_material("outline");
+
+        // Orbit lines
+        if state.show_orbits {
+            for (body_id, ent) in body_id_to_ent.iter() {
+                // Only draw orbits around bodies that are also in the scene
+                if let Some((central_ent, elements)) =
+                    find_orbit_central_body(mut_scene, body_id, orbit_man.get_osc_elements())
+                {
+                    add_orbit_line_entity(mut_scene, *ent, central_ent, elements, res_man);
+                }
+            }
+        }
+
         // Grid
+        // Its transforms get updated every frame to follow the camera
         if state.show_grid {
-            let grid = mut_scene.new_entity(Some("grid"));
-            let trans_comp = mut_scene.add_component::<TransformComponent>(grid);
-            trans_comp.get_local_transform_mut().scale =
-                Vector3::new(1000000.0, 1000000.0, 1000000.0);
-            let mesh_comp = mut_scene.add_component::<MeshComponent>(grid);
-            mesh_comp.set_mesh(res_man.get_or_create_mesh("grid"));
+            let mut new_grid_entity = |name: &str| -> Entity {
+                let grid = mut_scene.new_entity(Some(name));
+                mut_scene.add_component::<TransformComponent>(grid);
+                let mesh_comp = mut_scene.add_component::<MeshComponent>(grid);
+                mesh_comp.set_mesh(res_man.get_or_create_mesh("grid"));
+                mesh_comp.raycasting_visible = false;
+
+                // Separate material instances so that we can fade the minor lines on their own
+                mesh_comp.set_material_override(res_man.instantiate_material("line", name), 0);
+                return grid;
+            };
+
+            let major = new_grid_entity("grid");
+            let minor = new_grid_entity("grid minor lines");
+            mut_scene.grid = Some(ReferenceGrid {
+                minor,
+                major,
+                spacing: 1.0,
+                minor_fade: 0.0,
+                center: Vector3::zeros(),
+                rotation: UnitQuaternion::identity(),
+            });
         }
 
         // Axes
@@ -289,6 +720,7 @@ impl SceneManager {
             trans_comp.get_local_transform_mut().scale = Vector3::new(10000.0, 10000.0, 10000.0);
             let mesh_comp = mut_scene.add_component::<MeshComponent>(axes);
             mesh_comp.set_mesh(res_man.get_or_create_mesh("axes"));
+            mut_scene.axes = Some(axes);
         }
 
         // Skybox
@@ -301,19 +733,7 @@ impl SceneManager {
                 )
                 .to_homogeneous(),
             );
-            mut_scene.skybox_mat = res_man.instantiate_material("skybox", "test_skybox");
-            mut_scene
-                .skybox_mat
-                .as_ref()
-                .unwrap()
-                .borrow_mut()
-                .set_texture(
-                    TextureUnit::BaseColor,
-                    res_man.get_or_request_texture(
-                        &("public/textures/".to_owned() + "starmap_16k"),
-                        true,
-                    ),
-                );
+            mut_scene.skybox_mat = create_skybox_material(&desc, res_man);
         }
 
         // Points
@@ -321,5 +741,158 @@ impl SceneManager {
             mut_scene.points_mesh = res_man.get_or_create_mesh("points");
             mut_scene.points_mat = res_man.get_or_create_material("default_points");
         }
+
+        // Starfield. The stars themselves are generated when drawing, as the settings may change at any time
+        mut_scene.starfield_mesh = res_man.get_or_create_mesh("starfield");
+        mut_scene.starfield_mat = res_man.get_or_create_material("default_points");
+        mut_scene.star_catalog = self.star_catalog.clone();
+    }
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    pub fn body_limit_keeps_the_largest_bodies() {
+        let mut orbit_man = OrbitManager::new();
+        orbit_man
+            .load_database_file(
+                "public/database/asteroids.json",
+                "body_database",
+                r#"{
+                    "1": {"name": "Ceres", "type": "asteroid", "radius": 0.47},
+                    "2": {"name": "Pallas", "type": "asteroid", "radius": 0.26},
+                    "3": {"name": "Juno", "type": "asteroid", "radius": 0.12},
+                    "4": {"name": "Vesta", "type": "asteroid", "radius": 0.26, "magnitude": 3.2},
+                    "5": {"name": "Astraea", "type": "asteroid", "radius": 0.06},
+                    "6": {"name": "Hebe", "type": "asteroid", "magnitude": 5.7}
+                }"#,
+            )
+            .unwrap();
+
+        let instance = BodyInstanceDescription::default();
+        let custom = BodyInstanceDescription {
+            name: Some(String::from("Custom body")),
+            ..BodyInstanceDescription::default()
+        };
+        let get_bodies = || {
+            let mut bodies: Vec<(Option<&BodyDescription>, &BodyInstanceDescription)> = orbit_man
+                .get_n_bodies("asteroids", None)
+                .into_iter()
+                .map(|body| (Some(body), &instance))
+                .collect();
+            bodies.push((None, &custom));
+            return bodies;
+        };
+        let names = |bodies: &Vec<(Option<&BodyDescription>, &BodyInstanceDescription)>| {
+            let mut names: Vec<String> = bodies
+                .iter()
+                .filter_map(|(body, _)| body.map(|b| b.name.clone()))
+                .collect();
+            names.sort();
+            return names;
+        };
+
+        // Vesta and Pallas are the same size, but Vesta is brighter. The custom body doesn't count
+        let mut bodies = get_bodies();
+        assert_eq!(cull_least_significant_bodies(&mut bodies, 2), 4);
+        assert_eq!(names(&bodies), vec!["Ceres", "Vesta"]);
+        assert!(bodies.iter().any(|(body, _)| body.is_none()));
+
+        // Bodies without a size go last
+        let mut bodies = get_bodies();
+        assert_eq!(cull_least_significant_bodies(&mut bodies, 5), 1);
+        assert_eq!(
+            names(&bodies),
+            vec!["Astraea", "Ceres", "Juno", "Pallas", "Vesta"]
+        );
+
+        // No limit, or one we're already under
+        let mut bodies = get_bodies();
+        assert_eq!(cull_least_significant_bodies(&mut bodies, 0), 0);
+        assert_eq!(cull_least_significant_bodies(&mut bodies, 6), 0);
+        assert_eq!(bodies.len(), 7);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn skyboxes_show_the_scene_texture() {
+        use crate::managers::scene::description::DEFAULT_SKYBOX;
+
+        let new_desc = |skybox: &str| -> SceneDescription {
+            return ron::de::from_str(&format!(
+                r#"(
+                    name: "Skybox",
+                    description: "",
+                    time: "J2000",
+                    simulation_scale: 1.0,
+                    skybox: {},
+                    bodies: [],
+                )"#,
+                skybox
+            ))
+            .unwrap();
+        };
+
+        let mut res_man = ResourceManager::new();
+        let milky_way = res_man.add_test_texture("public/textures/milky_way");
+        let default = res_man.add_test_texture(&("public/textures/".to_owned() + DEFAULT_SKYBOX));
+        let get_skybox = |res_man: &mut ResourceManager, desc: &SceneDescription| {
+            let mat = create_skybox_material(desc, res_man).unwrap();
+            return mat.borrow().get_textures()[&TextureUnit::BaseColor].clone();
+        };
+
+        let tex = get_skybox(&mut res_man, &new_desc(r#"Some("milky_way")"#));
+        assert!(Rc::ptr_eq(&tex, &milky_way));
+
+        let tex = get_skybox(&mut res_man, &new_desc("None"));
+        assert!(Rc::ptr_eq(&tex, &default));
+    }
+
+    #[wasm_bindgen_test]
+    pub fn picked_units_only_last_for_the_session() {
+        use crate::app_state::DistanceUnit;
+
+        let desc: SceneDescription = ron::de::from_str(
+            r#"(
+                name: "Earth orbit",
+                description: "",
+                time: "J2000",
+                simulation_scale: 1.0,
+                distance_unit: Some(Km),
+                bodies: [],
+            )"#,
+        )
+        .unwrap();
+        let mut scene_man = SceneManager::new();
+        scene_man
+            .descriptions
+            .insert(String::from("Earth orbit"), desc);
+
+        // The other generated meshes aren't used with these settings
+        let mut res_man = ResourceManager::new();
+        res_man.add_test_mesh("starfield");
+        let orbit_man = OrbitManager::new();
+        let mut state = AppState::new();
+        state.show_points = false;
+        let mut load = |state: &mut AppState| {
+            scene_man.load_scene("Earth orbit", &mut res_man, &orbit_man, state);
+            return state.distance_unit;
+        };
+        assert_eq!(load(&mut state), DistanceUnit::Km);
+
+        // Picked on the settings window
+        state.distance_unit = DistanceUnit::Mm;
+        state.display_units_overridden = true;
+        assert_eq!(load(&mut state), DistanceUnit::Mm);
+
+        // Scenes get to pick again after a restart
+        let serialized = serde_json::to_string(&state).unwrap();
+        let mut state = AppState::deserialize_and_migrate(&serialized).unwrap();
+        assert_eq!(state.distance_unit, DistanceUnit::Mm);
+        assert_eq!(load(&mut state), DistanceUnit::Km);
     }
 }
@@ -1,32 +1,79 @@
 use crate::app_state::AppState;
 use crate::managers::scene::Scene;
 use crate::managers::ResourceManager;
-use crate::systems::{PhysicsSystem, RenderingSystem, TransformUpdateSystem};
+use crate::systems::{
+    BarycenterSystem, BodyTrailSystem, GroundTrackSystem, KeplerianSystem, PhysicsSystem,
+    RenderingSystem, TransformUpdateSystem,
+};
 use crate::GLCTX;
 
 pub struct SystemManager {
-    render: RenderingSystem,
+    render: Option<RenderingSystem>, // None when running headless
     physics: PhysicsSystem,
+    keplerian: KeplerianSystem,
+    barycenter: BarycenterSystem,
     trans: TransformUpdateSystem,
+    ground_track: GroundTrackSystem,
+    body_trail: BodyTrailSystem,
 }
 impl SystemManager {
     pub fn new(res_man: &mut ResourceManager) -> Self {
         return Self {
-            render: RenderingSystem::new(res_man),
+            render: Some(RenderingSystem::new(res_man)),
             physics: PhysicsSystem {},
+            keplerian: KeplerianSystem {},
+            barycenter: BarycenterSystem {},
             trans: TransformUpdateSystem {},
+            ground_track: GroundTrackSystem {},
+            body_trail: BodyTrailSystem {},
+        };
+    }
+
+    /// Creates a SystemManager without a rendering system, so that it never touches GL or the DOM
+    pub fn new_headless() -> Self {
+        return Self {
+            render: None,
+            physics: PhysicsSystem {},
+            keplerian: KeplerianSystem {},
+            barycenter: BarycenterSystem {},
+            trans: TransformUpdateSystem {},
+            ground_track: GroundTrackSystem {},
+            body_trail: BodyTrailSystem {},
         };
     }
 
     pub fn run(&mut self, state: &mut AppState, mut scene: &mut Scene) {
+        self.step(state, &mut scene);
+
+        if let Some(render) = &mut self.render {
+            render.run(state, &mut scene);
+        }
+    }
+
+    /// Runs every system except rendering, advancing the scene by state.sim_delta_time_s
+    pub fn step(&mut self, state: &mut AppState, mut scene: &mut Scene) {
         self.physics.run(state, &mut scene);
+        self.keplerian.run(state, &mut scene);
+        self.barycenter.run(state, &mut scene);
         self.trans.run(state, &mut scene);
-        self.render.run(state, &mut scene);
+        self.ground_track.run(state, &mut scene);
+        self.body_trail.run(state, &mut scene);
+    }
+
+    /// Recreates the GL resources owned by our systems, once the WebGL context is restored after being lost
+    pub fn restore_gl_resources(&mut self, state: &AppState) {
+        if let Some(render) = &mut self.render {
+            GLCTX.with(|ctx| {
+                render.restore_gl_resources(state.canvas_width, state.canvas_height, ctx);
+            });
+        }
     }
 
     pub fn resize(&mut self, width: u32, height: u32) {
-        GLCTX.with(|ctx| {
-            self.render.resize(width, height, ctx);
-        });
+        if let Some(render) = &mut self.render {
+            GLCTX.with(|ctx| {
+                render.resize(width, height, ctx);
+            });
+        }
     }
 }
@@ -0,0 +1,202 @@
+use crate::app_state::AppState;
+use crate::components::{MetadataComponent, RigidBodyComponent, TransformComponent};
+use crate::managers::scene::{Entity, Scene};
+use na::*;
+
+/// Places each barycenter entity at the mass-weighted center of the bodies orbiting it.
+///
+/// Members are the simulated bodies whose orbital elements are around the barycenter, as recorded in their
+/// "body_central_id" metadata (e.g. Pluto and Charon around the Pluto Barycenter). Keplerian bodies are placed
+/// relative to the barycenter instead, so they don't count. Members are expected to not be parented to other
+/// entities, as we use their local transforms directly, but barycenters themselves can be parented.
+pub struct BarycenterSystem {}
+impl BarycenterSystem {
+    pub fn run(&self, _state: &AppState, scene: &mut Scene) {
+        let mut barycenters: Vec<(Entity, String)> = Vec::new();
+        for (entity, meta) in scene.metadata.ent_iter() {
+            if meta.get_metadata("body_type").map(|t| t.as_str()) != Some("Barycenter") {
+                continue;
+            }
+
+            if let Some(id) = meta.get_metadata("body_id") {
+                barycenters.push((*entity, id.clone()));
+            }
+        }
+
+        for (barycenter, barycenter_id) in barycenters.iter() {
+            let mut members: Vec<(Vector3<f64>, f64)> = Vec::new();
+            for (entity, meta) in scene.metadata.ent_iter() {
+                if entity == barycenter || scene.get_entity_parent(*entity).is_some() {
+                    continue;
+                }
+
+                if meta.get_metadata("body_central_id") != Some(barycenter_id) {
+                    continue;
+                }
+
+                let mass = scene
+                    .get_component::<RigidBodyComponent>(*entity)
+                    .map(|rb| rb.mass);
+                let trans = scene
+                    .get_component::<TransformComponent>(*entity)
+                    .map(|t| t.get_local_transform().trans);
+                if let (Some(mass), Some(trans)) = (mass, trans) {
+                    members.push((trans, mass));
+                }
+            }
+
+            let center = compute_barycenter(&members);
+            if center.is_none() {
+                continue;
+            }
+            let mut center = center.unwrap();
+
+            // Our transform is relative to our parent, if any. Parent world transforms are from the
+            // previous frame at this point, which should be close enough
+            if let Some(parent) = scene.get_entity_parent(*barycenter) {
+                if let Some(parent_trans) = scene.get_component::<TransformComponent>(parent) {
+                    center = parent_trans
+                        .get_world_transform()
+                        .inverse_transform_point(&Point3::from(center))
+                        .coords;
+                }
+            }
+
+            if let Some(trans) = scene.get_component_mut::<TransformComponent>(*barycenter) {
+                trans.get_local_transform_mut().trans = center;
+            }
+        }
+    }
+}
+
+/// Returns the mass-weighted average position of `members`, given as (position, mass in kg) pairs
+pub fn compute_barycenter(members: &[(Vector3<f64>, f64)]) -> Option<Vector3<f64>> {
+    let total_mass: f64 = members.iter().map(|(_, mass)| mass).sum();
+    if total_mass <= 0.0 {
+        return None;
+    }
+
+    let weighted_sum = members
+        .iter()
+        .fold(Vector3::zeros(), |acc, (pos, mass)| acc + pos * *mass);
+
+    return Some(weighted_sum / total_mass);
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+
+    const ACCEPTABLE_DELTA: f64 = 0.0000001;
+
+    #[wasm_bindgen_test]
+    pub fn two_body_barycenter() {
+        // Pluto and Charon, about 19.6 Mm apart
+        let pluto_mass = 1.303E22;
+        let charon_mass = 1.586E21;
+        let pluto_pos = Vector3::new(0.0, 0.0, 0.0);
+        let charon_pos = Vector3::new(19.596, 0.0, 0.0);
+
+        let center =
+            compute_barycenter(&[(pluto_pos, pluto_mass), (charon_pos, charon_mass)]).unwrap();
+
+        // Should be between the two, and the distance to each should be inversely proportional to its mass
+        assert!(center.x > pluto_pos.x && center.x < charon_pos.x);
+        assert!(center.y.abs() < ACCEPTABLE_DELTA && center.z.abs() < ACCEPTABLE_DELTA);
+
+        let dist_pluto = (center - pluto_pos).magnitude();
+        let dist_charon = (center - charon_pos).magnitude();
+        assert!(
+            (dist_pluto * pluto_mass - dist_charon * charon_mass).abs() / pluto_mass
+                < ACCEPTABLE_DELTA
+        );
+    }
+
+    #[wasm_bindgen_test]
+    pub fn massless_barycenter() {
+        assert!(compute_barycenter(&[]).is_none());
+        assert!(compute_barycenter(&[(Vector3::new(1.0, 2.0, 3.0), 0.0)]).is_none());
+    }
+
+    #[wasm_bindgen_test]
+    pub fn barycenter_follows_its_members() {
+        use crate::managers::orbit::{BodyDescription, BodyType};
+        use crate::managers::scene::SceneBuilder;
+        use crate::managers::ResourceManager;
+
+        let mut res_man = ResourceManager::new();
+        let state = AppState::new();
+
+        // Rigid bodies get their mass from the f32 in the body description
+        let pluto_mass = 1.303E22_f32 as f64;
+        let charon_mass = 1.586E21_f32 as f64;
+        let new_body = |id: &str, name: &str, body_type: BodyType, mass: f64| BodyDescription {
+            body_type,
+            mass: Some(mass as f32),
+            ..BodyDescription::new_test_body(id, name, 1.0)
+        };
+
+        let mut builder = SceneBuilder::new("pluto system", &mut res_man);
+        let barycenter = builder
+            .add_body(new_body(
+                "9",
+                "Pluto Barycenter",
+                BodyType::Barycenter,
+                pluto_mass + charon_mass,
+            ))
+            .unwrap();
+        let pluto = builder
+            .add_body(new_body("999", "Pluto", BodyType::Other, pluto_mass))
+            .unwrap();
+        let charon = builder
+            .add_body(new_body("901", "Charon", BodyType::Other, charon_mass))
+            .unwrap();
+        let sun = builder
+            .add_body(new_body("10", "Sun", BodyType::Other, 1.989E30))
+            .unwrap();
+        let mut scene = builder.build();
+
+        // Barycenters are placed by us, so they're not simulated themselves
+        assert!(scene
+            .get_component::<RigidBodyComponent>(barycenter)
+            .is_none());
+
+        let pluto_pos = Vector3::new(100.0, 0.0, 0.0);
+        let charon_pos = Vector3::new(119.596, 0.0, 0.0);
+        for (entity, pos, central_id) in &[
+            (pluto, pluto_pos, "9"),
+            (charon, charon_pos, "9"),
+            (sun, Vector3::new(-1000.0, 0.0, 0.0), "0"),
+        ] {
+            scene
+                .get_component_mut::<TransformComponent>(*entity)
+                .unwrap()
+                .get_local_transform_mut()
+                .trans = *pos;
+            scene
+                .get_component_mut::<MetadataComponent>(*entity)
+                .unwrap()
+                .set_metadata("body_central_id", central_id);
+        }
+
+        BarycenterSystem {}.run(&state, &mut scene);
+
+        // The Sun is simulated too, but doesn't orbit the barycenter
+        let expected =
+            compute_barycenter(&[(pluto_pos, pluto_mass), (charon_pos, charon_mass)]).unwrap();
+        let center = scene
+            .get_component::<TransformComponent>(barycenter)
+            .unwrap()
+            .get_local_transform()
+            .trans;
+        assert!(
+            (center - expected).magnitude() < ACCEPTABLE_DELTA,
+            "Barycenter placed at {:?} instead of {:?}",
+            center,
+            expected
+        );
+    }
+}
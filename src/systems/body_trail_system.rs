@@ -0,0 +1,182 @@
+use crate::app_state::AppState;
+use crate::components::{RigidBodyComponent, TransformComponent};
+use crate::managers::scene::{BodyTrail, Entity, Scene};
+use na::*;
+use std::collections::{HashSet, VecDeque};
+
+/// Extends scene.body_trails with where each freely integrated body is now, while state.show_body_trails.
+///
+/// Runs after the transform update system, as the points are world space positions. Bodies on Keplerian orbits
+/// already have their orbit lines (and orbit trails), so they don't get one
+pub struct BodyTrailSystem {}
+impl BodyTrailSystem {
+    pub fn run(&self, state: &mut AppState, scene: &mut Scene) {
+        if !state.show_body_trails {
+            scene.body_trails.clear();
+            return;
+        }
+
+        let keplerian: HashSet<Entity> = scene.keplerian_bodies.iter().map(|k| k.body).collect();
+        let bodies: Vec<(Entity, Vector3<f64>)> = scene
+            .get_entity_entries()
+            .iter()
+            .filter(|entry| entry.live && !keplerian.contains(&entry.current))
+            .filter(|entry| {
+                scene
+                    .get_component::<RigidBodyComponent>(entry.current)
+                    .is_some()
+            })
+            .filter_map(|entry| {
+                scene
+                    .get_component::<TransformComponent>(entry.current)
+                    .map(|trans| (entry.current, trans.get_world_transform().trans))
+            })
+            .collect();
+
+        // Bodies that were deleted or became Keplerian
+        let current: HashSet<Entity> = bodies.iter().map(|(entity, _)| *entity).collect();
+        scene
+            .body_trails
+            .retain(|entity, _| current.contains(entity));
+
+        let now = state.sim_time_s;
+        let max_age_s = state.body_trail_days * 86400.0;
+        let max_points = state.body_trail_points.max(2);
+        for (entity, pos) in bodies {
+            let points = &mut scene
+                .body_trails
+                .entry(entity)
+                .or_insert_with(|| BodyTrail {
+                    points: VecDeque::new(),
+                    trail: None,
+                })
+                .points;
+
+            extend_timed_points(points, now, Some(pos), max_age_s, max_points);
+        }
+    }
+}
+
+/// Appends `point` to `points` at `now` (sim_time_s), then drops the oldest ones until there are at most `max_points`
+/// and none older than `max_age_s`. Points from after `now` are dropped first, so that stepping back in time undoes
+/// them instead of drawing them again on top. With no `point` we just trim
+pub fn extend_timed_points<T>(
+    points: &mut VecDeque<(f64, T)>,
+    now: f64,
+    point: Option<T>,
+    max_age_s: f64,
+    max_points: usize,
+) {
+    while points.back().map(|(t, _)| *t > now).unwrap_or(false) {
+        points.pop_back();
+    }
+
+    if let Some(point) = point {
+        if points.back().map(|(t, _)| *t < now).unwrap_or(true) {
+            points.push_back((now, point));
+        }
+    }
+
+    while points.len() > max_points
+        || points
+            .front()
+            .map(|(t, _)| now - *t > max_age_s)
+            .unwrap_or(false)
+    {
+        points.pop_front();
+    }
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+    use crate::engine::Engine;
+
+    #[wasm_bindgen_test]
+    pub fn trails_follow_free_bodies() {
+        let mut engine = Engine::new_headless();
+        let mut state = AppState::new();
+
+        // Alone, so it just drifts along +X at 1 Mm/s
+        let mut scene = Scene::new("trails");
+        let rock = scene.add_test_body("Rock", Vector3::zeros(), 1.0);
+        scene
+            .get_component_mut::<RigidBodyComponent>(rock)
+            .unwrap()
+            .lin_mom = Vector3::new(1.0, 0.0, 0.0);
+        engine.scene_man.set_current_scene(scene, &mut state);
+
+        let get_points = |engine: &Engine| -> Vec<(f64, Vector3<f64>)> {
+            let scene = engine.scene_man.get_current_scene().unwrap();
+            return scene
+                .body_trails
+                .get(&rock)
+                .map(|trail| trail.points.iter().cloned().collect())
+                .unwrap_or_default();
+        };
+
+        // Nothing unless asked for
+        engine.step(&mut state, 1.0);
+        assert!(get_points(&engine).is_empty());
+
+        state.show_body_trails = true;
+        state.body_trail_days = 1.0;
+        state.body_trail_points = 5;
+        for _ in 0..3 {
+            engine.step(&mut state, 3600.0);
+        }
+        let points = get_points(&engine);
+        assert_eq!(points.len(), 3);
+        assert!((points[2].1.x - points[0].1.x - 7200.0).abs() < 1E-6);
+        assert_eq!(points[2].0, state.sim_time_s);
+
+        // Capped to the most recent points
+        for _ in 0..10 {
+            engine.step(&mut state, 3600.0);
+        }
+        let points = get_points(&engine);
+        assert_eq!(points.len(), 5);
+        assert_eq!(points[4].0, state.sim_time_s);
+
+        // And to the most recent day
+        state.body_trail_points = 1000;
+        engine.step(&mut state, 86400.0 - 2.5 * 3600.0);
+        let points = get_points(&engine);
+        assert!(points.iter().all(|(t, _)| state.sim_time_s - t <= 86400.0));
+        assert_eq!(points.len(), 4);
+
+        // Stepping back undoes it
+        let newest = points[3].0;
+        engine.step(&mut state, -1.0);
+        let points = get_points(&engine);
+        assert!(points.iter().all(|(t, _)| *t < newest));
+        assert_eq!(points.last().unwrap().0, state.sim_time_s);
+
+        // Turning them off clears them
+        state.show_body_trails = false;
+        engine.step(&mut state, 1.0);
+        assert!(engine
+            .scene_man
+            .get_current_scene()
+            .unwrap()
+            .body_trails
+            .is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    pub fn missing_points_still_trim() {
+        let mut points: VecDeque<(f64, u32)> = (0..5).map(|i| (i as f64 * 10.0, i)).collect();
+
+        // Stepping back drops what came after, even with nothing to add
+        extend_timed_points(&mut points, 25.0, None, 1000.0, 100);
+        assert_eq!(points.back(), Some(&(20.0, 2)));
+
+        // And too old points go too
+        extend_timed_points(&mut points, 35.0, None, 20.0, 100);
+        assert_eq!(points.front(), Some(&(20.0, 2)));
+        assert_eq!(points.len(), 1);
+    }
+}
@@ -0,0 +1,197 @@
+use crate::app_state::AppState;
+use crate::components::{KinematicComponent, MetadataComponent, TransformComponent};
+use crate::managers::scene::{get_body_radius, Entity, GroundTrack, Scene};
+use crate::systems::extend_timed_points;
+use na::*;
+use std::collections::VecDeque;
+
+/// Most points a ground track keeps, dropping the oldest ones past this regardless of state.ground_track_hours
+pub const MAX_GROUND_TRACK_POINTS: usize = 5000;
+
+/// Extends scene.ground_track with the point right below its body on the surface of the body it orbits.
+///
+/// Runs after the transform update system, as it needs the current world rotation of the central body. The points are
+/// kept in the rotating frame of the central body's surface, so that the track turns along with it
+pub struct GroundTrackSystem {}
+impl GroundTrackSystem {
+    pub fn run(&self, state: &mut AppState, scene: &mut Scene) {
+        if let Some(body) = state.ground_track_request.take() {
+            toggle_ground_track(scene, body);
+        }
+
+        if scene.ground_track.is_none() {
+            return;
+        }
+        let (body, central_body) = {
+            let track = scene.ground_track.as_ref().unwrap();
+            (track.body, track.central_body)
+        };
+
+        let body_pos = scene.get_physical_position(body);
+        let central_pos = scene.get_physical_position(central_body);
+        if body_pos.is_none() || central_pos.is_none() {
+            scene.ground_track = None;
+            return;
+        }
+
+        let radius = get_body_radius(scene, central_body);
+        let rotation = get_body_fixed_rotation(scene, central_body);
+        let sub_point = get_sub_point(&(body_pos.unwrap() - central_pos.unwrap()), radius);
+
+        let now = state.sim_time_s;
+        let max_age_s = state.ground_track_hours * 3600.0;
+        extend_timed_points(
+            &mut scene.ground_track.as_mut().unwrap().points,
+            now,
+            sub_point.map(|sub_point| rotation.inverse_transform_vector(&sub_point)),
+            max_age_s,
+            MAX_GROUND_TRACK_POINTS,
+        );
+    }
+}
+
+/// Starts a ground track of `body` on the body it orbits (as per its "body_central_id" metadata), or stops it if it
+/// already had one. Only one body has a ground track at a time
+fn toggle_ground_track(scene: &mut Scene, body: Entity) {
+    if scene.ground_track.as_ref().map(|track| track.body) == Some(body) {
+        scene.ground_track = None;
+        return;
+    }
+
+    let central_body = scene
+        .get_component::<MetadataComponent>(body)
+        .and_then(|meta| meta.get_metadata("body_central_id"))
+        .and_then(|id| scene.get_entity_from_body_id(id));
+
+    scene.ground_track = central_body.map(|central_body| GroundTrack {
+        body,
+        central_body,
+        points: VecDeque::new(),
+        trail: None,
+    });
+}
+
+/// World space rotation of the surface of `body`. Bodies that spin do so with a KinematicComponent on the child
+/// entity holding their mesh, so that their own children aren't spun along
+pub fn get_body_fixed_rotation(scene: &Scene, body: Entity) -> UnitQuaternion<f64> {
+    let spinning_child = scene.get_entity_children(body).and_then(|children| {
+        children
+            .iter()
+            .find(|child| scene.get_component::<KinematicComponent>(**child).is_some())
+            .cloned()
+    });
+
+    return scene
+        .get_component::<TransformComponent>(spinning_child.unwrap_or(body))
+        .map(|trans| trans.get_world_transform().rot)
+        .unwrap_or_else(UnitQuaternion::identity);
+}
+
+/// Where the line from the center of a sphere of `radius` to something at `offset` from it crosses its surface, as a
+/// unit vector from the center. None if it's inside the sphere
+pub fn get_sub_point(offset: &Vector3<f64>, radius: f64) -> Option<Vector3<f64>> {
+    if offset.magnitude() <= radius {
+        return None;
+    }
+
+    return Some(offset.normalize());
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+    use crate::engine::Engine;
+    use crate::managers::orbit::{BodyDescription, BodyInstanceDescription};
+    use crate::managers::scene::SceneBuilder;
+
+    #[wasm_bindgen_test]
+    pub fn ground_track_turns_with_the_planet() {
+        let mut engine = Engine::new_headless();
+        let mut state = AppState::new();
+
+        // A planet spinning once a day around +Z, and a static satellite above its equator
+        let day_s = 86400.0;
+        let new_body = |id: &str, name: &str| BodyDescription::new_test_body(id, name, 6.0);
+        let scene = {
+            let mut builder = SceneBuilder::new("ground track", &mut engine.res_man);
+            builder.add_body_instance(
+                Some(&new_body("399", "Planet")),
+                &BodyInstanceDescription {
+                    angvel: Some(Vector3::new(0.0, 0.0, 2.0 * std::f64::consts::PI / day_s)),
+                    ..BodyInstanceDescription::default()
+                },
+            );
+            builder.add_body_instance(
+                Some(&new_body("1", "Satellite")),
+                &BodyInstanceDescription {
+                    pos: Some(Point3::new(40.0, 0.0, 0.0)),
+                    ..BodyInstanceDescription::default()
+                },
+            );
+            builder.build()
+        };
+        engine.scene_man.set_current_scene(scene, &mut state);
+
+        let scene = engine.scene_man.get_current_scene_mut().unwrap();
+        let planet = scene.get_entity_from_body_id("399").unwrap();
+        let satellite = scene.get_entity_from_body_id("1").unwrap();
+        scene
+            .get_component_mut::<MetadataComponent>(satellite)
+            .unwrap()
+            .set_metadata("body_central_id", "399");
+
+        // Only bodies orbiting something get one
+        state.ground_track_request = Some(planet);
+        engine.step(&mut state, 1.0);
+        assert!(engine
+            .scene_man
+            .get_current_scene()
+            .unwrap()
+            .ground_track
+            .is_none());
+
+        state.ground_track_hours = 24.0;
+        state.ground_track_request = Some(satellite);
+        let num_steps = 60;
+        for _ in 0..num_steps {
+            engine.step(&mut state, day_s / 4.0 / num_steps as f64);
+        }
+        let scene = engine.scene_man.get_current_scene().unwrap();
+        let track = scene.ground_track.as_ref().unwrap();
+        assert_eq!(track.points.len(), num_steps);
+
+        // The newest point is still right below the satellite, but the oldest one turned a quarter of the way around
+        // along with the surface
+        let rotation = get_body_fixed_rotation(scene, planet);
+        let newest = rotation * track.points.back().unwrap().1;
+        let oldest = rotation * track.points.front().unwrap().1;
+        assert!((newest - Vector3::x()).magnitude() < 1E-5, "{:?}", newest);
+        assert!((oldest - Vector3::y()).magnitude() < 0.05, "{:?}", oldest);
+
+        // In the planet's frame the track heads west
+        let local_newest = track.points.back().unwrap().1;
+        assert!((local_newest - Vector3::new(0.0, -1.0, 0.0)).magnitude() < 1E-3);
+
+        // Old points fall off the end of the track
+        state.ground_track_hours = 3.0;
+        engine.step(&mut state, 1.0);
+        let scene = engine.scene_man.get_current_scene().unwrap();
+        let track = scene.ground_track.as_ref().unwrap();
+        let age = state.sim_time_s - track.points.front().unwrap().0;
+        assert!(age <= 3.0 * 3600.0);
+        assert!(track.points.len() < num_steps);
+
+        // Asking again turns it off
+        state.ground_track_request = Some(satellite);
+        engine.step(&mut state, 1.0);
+        assert!(engine
+            .scene_man
+            .get_current_scene()
+            .unwrap()
+            .ground_track
+            .is_none());
+    }
+}
@@ -0,0 +1,56 @@
+use crate::app_state::AppState;
+use crate::components::TransformComponent;
+use crate::managers::scene::{Entity, Scene};
+use crate::utils::orbits::orbital_elements_to_xyz;
+use crate::utils::units::Jdn;
+use na::*;
+use std::collections::HashMap;
+
+/// How many Keplerian central bodies we go through when placing a Keplerian body, e.g. moon -> planet -> star
+const MAX_KEPLERIAN_CHAIN_DEPTH: u32 = 8;
+
+/// Places every body in scene.keplerian_bodies on its orbital ellipse for the current simulation time.
+///
+/// Runs after the physics system, so that bodies orbiting a dynamical body follow where it was just integrated to.
+/// Keplerian bodies don't attract anything, and like barycenter members they're expected to not be parented to other
+/// entities, as we write their local transforms directly
+pub struct KeplerianSystem {}
+impl KeplerianSystem {
+    pub fn run(&self, state: &AppState, scene: &mut Scene) {
+        if scene.keplerian_bodies.is_empty() {
+            return;
+        }
+
+        let t = Jdn(scene.epoch.0 + state.sim_time_s / 86400.0);
+
+        // Offset of each body from its central body at t
+        let mut offsets: HashMap<Entity, (Entity, Vector3<f64>)> = HashMap::new();
+        for kep in scene.keplerian_bodies.iter() {
+            let (pos, _) = orbital_elements_to_xyz(&kep.elements, t, &kep.rotation);
+            offsets.insert(kep.body, (kep.central_body, pos.coords));
+        }
+
+        for (body, (central, offset)) in offsets.iter() {
+            // Central bodies may be Keplerian themselves, and we don't know in which order we'd update them
+            let mut pos = *offset;
+            let mut current = *central;
+            for _ in 0..MAX_KEPLERIAN_CHAIN_DEPTH {
+                match offsets.get(&current) {
+                    Some((next_central, next_offset)) => {
+                        pos += next_offset;
+                        current = *next_central;
+                    }
+                    None => break,
+                }
+            }
+
+            if let Some(trans) = scene.get_component::<TransformComponent>(current) {
+                pos += trans.get_local_transform().trans;
+            }
+
+            if let Some(trans) = scene.get_component_mut::<TransformComponent>(*body) {
+                trans.get_local_transform_mut().trans = pos;
+            }
+        }
+    }
+}
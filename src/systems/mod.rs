@@ -1,7 +1,15 @@
+mod barycenter_system;
+mod body_trail_system;
+mod ground_track_system;
+mod keplerian_system;
 mod physics_system;
 mod rendering;
 mod transform_update_system;
 
+pub use barycenter_system::*;
+pub use body_trail_system::*;
+pub use ground_track_system::*;
+pub use keplerian_system::*;
 pub use physics_system::*;
 pub use rendering::*;
 pub use transform_update_system::*;
@@ -2,15 +2,22 @@ use crate::app_state::AppState;
 use crate::components::RigidBodyComponent;
 use crate::components::{Component, KinematicComponent, TransformComponent};
 use crate::managers::scene::component_storage::ComponentStorage;
-use crate::managers::scene::Scene;
+use crate::managers::scene::{Entity, Scene, TrajectoryPrediction};
 use crate::managers::EventReceiver;
 use crate::utils::orbits::GRAVITATION_CONSTANT;
 use na::*;
 
+/// Most substeps we split a single physics update into. Past this the substeps just get longer than
+/// state.max_physics_substep_s, so that huge time steps can't stall the frame
+pub const MAX_PHYSICS_SUBSTEPS: u32 = 1000;
+
 pub struct PhysicsSystem {}
 impl PhysicsSystem {
-    pub fn run(&self, state: &AppState, scene: &mut Scene) {
-        if state.simulation_paused || state.simulation_speed == 0.0 {
+    pub fn run(&self, state: &mut AppState, scene: &mut Scene) {
+        update_trajectory_prediction(state, scene);
+
+        // The render loop already zeroes this when paused, and headless stepping sets it directly
+        if state.sim_delta_time_s == 0.0 {
             return;
         }
 
@@ -21,13 +28,13 @@ impl PhysicsSystem {
             phys.trans = trans.get_local_transform().clone();
         }
 
-        // Collect forces
-        collect_gravity(scene);
-
-        // Update state vector
-        for phys in scene.rigidbody.iter_mut() {
-            update_rigidbody(state, phys);
-        }
+        // Collect forces and update state vectors
+        integrate_rigidbodies(
+            scene.rigidbody.get_storage_mut(),
+            state.sim_delta_time_s,
+            state.max_physics_substep_s,
+            state.gravity_softening,
+        );
 
         // Unload new transforms
         for (ent, phys) in scene.rigidbody.ent_iter() {
@@ -50,12 +57,119 @@ impl EventReceiver for PhysicsSystem {
     }
 }
 
-fn collect_gravity(scene: &mut Scene) {
-    let phys_comps = scene.rigidbody.get_storage_mut();
+/// Predicts the trajectory of the body in state.trajectory_prediction_request, if any, and drops the prediction once its
+/// body isn't selected anymore
+fn update_trajectory_prediction(state: &mut AppState, scene: &mut Scene) {
+    if let Some(body) = state.trajectory_prediction_request.take() {
+        scene.trajectory_prediction = predict_trajectory(
+            scene,
+            body,
+            state.trajectory_prediction_days * 86400.0,
+            state.trajectory_prediction_steps,
+            state.max_physics_substep_s,
+            state.gravity_softening,
+        )
+        .map(|points| TrajectoryPrediction {
+            body,
+            points,
+            trail: None,
+        });
+    }
+
+    if let Some(prediction) = &scene.trajectory_prediction {
+        if state.selection != Some(prediction.body) {
+            scene.trajectory_prediction = None;
+        }
+    }
+}
+
+/// Positions of `body` over the next `horizon_s`, in `num_steps` equal steps (plus where it is now). A copy of all the
+/// rigid bodies of `scene` gets integrated with the same forces and substeps as `run` would use, while `scene` itself
+/// is left untouched. None if `body` isn't a rigid body
+pub fn predict_trajectory(
+    scene: &Scene,
+    body: Entity,
+    horizon_s: f64,
+    num_steps: u32,
+    max_substep_s: f64,
+    softening_mm: f64,
+) -> Option<Vec<Vector3<f64>>> {
+    let mut comps: Vec<RigidBodyComponent> = Vec::new();
+    let mut body_index = None;
+    for (ent, phys) in scene.rigidbody.ent_iter() {
+        if *ent == body {
+            body_index = Some(comps.len());
+        }
+
+        let mut phys = phys.clone();
+        if let Some(trans) = scene.transform.get_component(*ent) {
+            phys.trans = trans.get_local_transform().clone();
+        }
+        comps.push(phys);
+    }
+    let body_index = body_index?;
+
+    let num_steps = num_steps.max(1);
+    let step_s = horizon_s / num_steps as f64;
+
+    let mut result = Vec::with_capacity(num_steps as usize + 1);
+    result.push(comps[body_index].trans.trans);
+    for _ in 0..num_steps {
+        integrate_rigidbodies(&mut comps, step_s, max_substep_s, softening_mm);
+        result.push(comps[body_index].trans.trans);
+    }
+
+    return Some(result);
+}
+
+/// How many substeps a physics update of `dt_s` gets split into so that none of them are longer than
+/// `max_substep_s`, up to MAX_PHYSICS_SUBSTEPS. A `max_substep_s` of zero disables substepping
+pub fn get_num_physics_substeps(dt_s: f64, max_substep_s: f64) -> u32 {
+    if !(max_substep_s > 0.0) || !dt_s.is_finite() {
+        return 1;
+    }
+
+    return ((dt_s.abs() / max_substep_s).ceil() as u32)
+        .max(1)
+        .min(MAX_PHYSICS_SUBSTEPS);
+}
+
+/// Advances `phys_comps` by `dt_s` under their mutual gravity, in equal substeps no longer than `max_substep_s`.
+///
+/// A single semi-implicit Euler step is only stable while it's short compared to the orbits involved, so at high
+/// simulation speeds the time step of one frame could otherwise fling orbiting bodies apart
+fn integrate_rigidbodies(
+    phys_comps: &mut [RigidBodyComponent],
+    dt_s: f64,
+    max_substep_s: f64,
+    softening_mm: f64,
+) {
+    let num_substeps = get_num_physics_substeps(dt_s, max_substep_s);
+    let substep_s = dt_s / num_substeps as f64;
+
+    for _ in 0..num_substeps {
+        collect_gravity(phys_comps, softening_mm);
+
+        for phys in phys_comps.iter_mut() {
+            update_rigidbody(substep_s, phys);
+        }
+    }
+}
+
+/// Accumulates the pairwise gravitational forces into each component's force_sum.
+///
+/// Uses Plummer softening, so that the acceleration is G*M*r/(r^2+epsilon^2)^{3/2} instead of G*M*r/r^3.
+/// This keeps the force bounded when two bodies get closer than `softening_mm` (otherwise a single Euler step
+/// at a tiny distance can fling them apart), at the cost of weakening gravity at short range: Orbits much wider
+/// than epsilon are barely affected, but close encounters lose/gain energy relative to true Newtonian gravity,
+/// so keep epsilon well below the radii of the bodies involved
+fn collect_gravity(phys_comps: &mut [RigidBodyComponent], softening_mm: f64) {
     if phys_comps.len() < 2 {
         return;
     }
 
+    let softening2 = softening_mm * softening_mm;
+
     for i in 0..phys_comps.len() {
         let this_comp = &phys_comps[i];
 
@@ -66,16 +180,15 @@ fn collect_gravity(scene: &mut Scene) {
             let other_comp = &phys_comps[j];
 
             let delta = other_comp.trans.trans - pos;
-            let dist = delta.magnitude();
+            let dist2 = delta.magnitude_squared() + softening2;
 
-            // TEMP Safety in case something causes two bodies to be right on top of eachother
-            // Should go away once I properly implement collision
+            // Safety in case softening is disabled and two bodies end up right on top of eachother
             let force: Vector3<f64>;
-            if dist < 1E-10 {
+            if dist2 < 1E-20 {
                 force = Vector3::zeros()
             } else {
-                force = delta.normalize() * GRAVITATION_CONSTANT * mass * other_comp.mass
-                    / (dist * dist);
+                force =
+                    delta * GRAVITATION_CONSTANT * mass * other_comp.mass / (dist2 * dist2.sqrt());
             }
 
             phys_comps[i].force_sum += force;
@@ -84,14 +197,118 @@ fn collect_gravity(scene: &mut Scene) {
     }
 }
 
-// Applies semi-implicit Euler integration to update `physics` to time t
-fn update_rigidbody(state: &AppState, phys_comp: &mut RigidBodyComponent) {
+/// Values per body in the flat buffers of compute_gravity_chunk: x, y, z (Mm) and mass (kg)
+pub const GRAVITY_BODY_STRIDE: usize = 4;
+
+/// Packs the positions and masses of `phys_comps` into a flat buffer for compute_gravity_chunk
+pub fn pack_gravity_bodies(phys_comps: &[RigidBodyComponent]) -> Vec<f64> {
+    let mut result = Vec::with_capacity(phys_comps.len() * GRAVITY_BODY_STRIDE);
+    for comp in phys_comps {
+        let pos = comp.trans.trans;
+        result.extend_from_slice(&[pos.x, pos.y, pos.z, comp.mass]);
+    }
+
+    return result;
+}
+
+/// Writes the net gravitational force (kg Mm/s²) on each body in `chunk` from all of the `bodies` into `forces`, as
+/// x, y, z for each body of the chunk. `bodies` is laid out like pack_gravity_bodies, and the softening is the same as
+/// collect_gravity's.
+///
+/// This only reads plain f64 buffers, so separate chunks can be computed independently (e.g. by Web Workers sharing
+/// `bodies`) and then merged into the force sums before integrating. It does visit each pair twice though, so on a
+/// single thread collect_gravity is about twice as fast
+pub fn compute_gravity_chunk(
+    bodies: &[f64],
+    chunk: std::ops::Range<usize>,
+    softening_mm: f64,
+    forces: &mut [f64],
+) {
+    let softening2 = softening_mm * softening_mm;
+    let num_bodies = bodies.len() / GRAVITY_BODY_STRIDE;
+    let get_pos = |index: usize| {
+        let offset = index * GRAVITY_BODY_STRIDE;
+        return Vector3::new(bodies[offset], bodies[offset + 1], bodies[offset + 2]);
+    };
+
+    for (chunk_index, i) in chunk.enumerate() {
+        let pos = get_pos(i);
+        let mass = bodies[i * GRAVITY_BODY_STRIDE + 3];
+
+        let mut force_sum: Vector3<f64> = Vector3::zeros();
+        for j in 0..num_bodies {
+            if j == i {
+                continue;
+            }
+
+            let delta = get_pos(j) - pos;
+            let dist2 = delta.magnitude_squared() + softening2;
+            if dist2 >= 1E-20 {
+                let other_mass = bodies[j * GRAVITY_BODY_STRIDE + 3];
+                force_sum +=
+                    delta * GRAVITATION_CONSTANT * mass * other_mass / (dist2 * dist2.sqrt());
+            }
+        }
+
+        forces[chunk_index * 3] = force_sum.x;
+        forces[chunk_index * 3 + 1] = force_sum.y;
+        forces[chunk_index * 3 + 2] = force_sum.z;
+    }
+}
+
+/// Same as collect_gravity, but going through compute_gravity_chunk for `chunk_size` bodies at a time and merging the
+/// results into each force_sum, like a pool of workers would
+pub fn collect_gravity_chunked(
+    phys_comps: &mut [RigidBodyComponent],
+    softening_mm: f64,
+    chunk_size: usize,
+) {
+    let bodies = pack_gravity_bodies(phys_comps);
+    let chunk_size = chunk_size.max(1);
+
+    let mut forces = vec![0.0; chunk_size * 3];
+    let mut start = 0;
+    while start < phys_comps.len() {
+        let end = (start + chunk_size).min(phys_comps.len());
+        compute_gravity_chunk(&bodies, start..end, softening_mm, &mut forces);
+
+        for (chunk_index, comp) in phys_comps[start..end].iter_mut().enumerate() {
+            comp.force_sum += Vector3::new(
+                forces[chunk_index * 3],
+                forces[chunk_index * 3 + 1],
+                forces[chunk_index * 3 + 2],
+            );
+        }
+        start = end;
+    }
+}
+
+/// Kinetic plus gravitational potential energy of `phys_comps`, in kg Mm²/s². Uses the same softened potential
+/// -G*m1*m2/sqrt(r^2+epsilon^2) that collect_gravity's forces come from, so that it's conserved by the simulation
+pub fn get_total_energy(phys_comps: &[RigidBodyComponent], softening_mm: f64) -> f64 {
+    let softening2 = softening_mm * softening_mm;
+
+    let mut result = 0.0;
+    for (i, comp) in phys_comps.iter().enumerate() {
+        result += comp.lin_mom.magnitude_squared() / (2.0 * comp.mass);
+
+        for other in phys_comps[i + 1..].iter() {
+            let dist2 = (other.trans.trans - comp.trans.trans).magnitude_squared() + softening2;
+            if dist2 > 1E-20 {
+                result -= GRAVITATION_CONSTANT * comp.mass * other.mass / dist2.sqrt();
+            }
+        }
+    }
+
+    return result;
+}
+
+// Applies semi-implicit Euler integration to advance `physics` by `dt_s`
+fn update_rigidbody(dt_s: f64, phys_comp: &mut RigidBodyComponent) {
     if !phys_comp.get_enabled() {
         return;
     }
 
-    let dt_s = state.sim_delta_time_s;
-
     // TODO: What if the object is scaled? Should that affect its linear/rotational motion?
 
     // TODO: Gyroscopic effects here
@@ -131,3 +348,392 @@ fn update_kinematic(state: &AppState, kin: &KinematicComponent, trans: &mut Tran
     transform.trans += kin.lin_vel * state.sim_delta_time_s;
     transform.rot *= UnitQuaternion::from_scaled_axis(kin.ang_vel * state.sim_delta_time_s);
 }
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+    use crate::managers::orbit::OrbitalElements;
+    use crate::utils::log::*;
+    use crate::utils::orbits::{elements_to_ellipse_rotation_transform, orbital_elements_to_xyz};
+    use crate::utils::units::{Au, Deg, Jdn, Mm, J2000_JDN};
+    use crate::utils::web::now_sec;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    pub struct PhysicsBenchmark {
+        pub steps_per_s: f64,
+        pub energy_drift: f64, // Relative change of the total energy between the first and last step
+    }
+
+    /// Builds a scene with a Sun-like star and `num_bodies` free bodies on roughly circular orbits around it, placed
+    /// according to `seed`, and then times `num_steps` runs of the PhysicsSystem with a time step of `dt_s`
+    pub fn run_physics_benchmark(
+        num_bodies: u32,
+        num_steps: u32,
+        dt_s: f64,
+        seed: u64,
+    ) -> PhysicsBenchmark {
+        let star_mass = 2E30; // kg
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let mut state = AppState::new();
+        state.sim_delta_time_s = dt_s;
+
+        let mut scene = Scene::new("physics benchmark");
+        let mut entities = Vec::new();
+        for index in 0..=num_bodies {
+            let (pos, mass, vel) = if index == 0 {
+                (Vector3::zeros(), star_mass, Vector3::zeros())
+            } else {
+                let radius = rng.gen_range(0.3..2.0) * Au(1.0).to_Mm().0;
+                let angle: f64 = rng.gen_range(0.0..std::f64::consts::TAU);
+                let height = rng.gen_range(-0.01..0.01) * radius;
+                let speed = (GRAVITATION_CONSTANT * star_mass / radius).sqrt();
+                (
+                    Vector3::new(radius * angle.cos(), radius * angle.sin(), height),
+                    10.0_f64.powf(rng.gen_range(23.0..26.0)),
+                    Vector3::new(-speed * angle.sin(), speed * angle.cos(), 0.0),
+                )
+            };
+
+            let entity = scene.add_test_body(&format!("Body {}", index), pos, mass);
+            scene
+                .get_component_mut::<RigidBodyComponent>(entity)
+                .unwrap()
+                .lin_mom = vel * mass;
+
+            entities.push(entity);
+        }
+
+        let softening = state.gravity_softening;
+        let get_energy = |scene: &Scene| {
+            let comps: Vec<RigidBodyComponent> = entities
+                .iter()
+                .filter_map(|ent| scene.get_component::<RigidBodyComponent>(*ent).cloned())
+                .collect();
+            return get_total_energy(&comps, softening);
+        };
+        let start_energy = get_energy(&scene);
+
+        let system = PhysicsSystem {};
+        let start_s = now_sec();
+        for _ in 0..num_steps {
+            system.run(&mut state, &mut scene);
+        }
+        let elapsed_s = now_sec() - start_s;
+
+        return PhysicsBenchmark {
+            steps_per_s: num_steps as f64 / elapsed_s.max(1E-6),
+            energy_drift: ((get_energy(&scene) - start_energy) / start_energy).abs(),
+        };
+    }
+
+    #[wasm_bindgen_test]
+    pub fn softened_near_collision_stays_bounded() {
+        let mass = 1E24; // kg
+        let start_dist = 2.0; // Mm
+        let softening = 0.01; // Mm
+        let dt_s = 0.005;
+
+        let mut state = AppState::new();
+        state.sim_delta_time_s = dt_s;
+        state.gravity_softening = softening;
+
+        // Almost head-on: Only a tiny sideways offset, so without softening they'd pass within 1 m of eachother
+        let mut comps = vec![RigidBodyComponent::default(), RigidBodyComponent::default()];
+        comps[0].mass = mass;
+        comps[0].trans.trans = Vector3::new(-start_dist / 2.0, -0.0000005, 0.0);
+        comps[1].mass = mass;
+        comps[1].trans.trans = Vector3::new(start_dist / 2.0, 0.0000005, 0.0);
+
+        // Energy conservation with the softened potential -G*m1*m2/sqrt(r^2+epsilon^2) limits the relative speed
+        let total_mass = 2.0 * mass;
+        let max_rel_speed = (2.0
+            * GRAVITATION_CONSTANT
+            * total_mass
+            * (1.0 / softening - 1.0 / (start_dist * start_dist + softening * softening).sqrt()))
+        .sqrt();
+
+        // Long enough for a few passes through eachother
+        for _ in 0..120000 {
+            collect_gravity(&mut comps, state.gravity_softening);
+            for comp in comps.iter_mut() {
+                update_rigidbody(state.sim_delta_time_s, comp);
+            }
+
+            let rel_speed = (comps[1].lin_mom - comps[0].lin_mom).magnitude() / mass;
+            let dist = (comps[1].trans.trans - comps[0].trans.trans).magnitude();
+            assert!(
+                rel_speed < max_rel_speed * 1.5,
+                "Relative speed {} blew up",
+                rel_speed
+            );
+            assert!(dist < start_dist * 1.5, "Bodies flung apart to {} Mm", dist);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    pub fn seeded_two_body_system_follows_keplerian_orbit() {
+        let central_mass = 2E30; // kg
+        let body_mass = 6E24; // kg
+        let semi_major_axis = 1.5E5; // Mm
+        let dt_s = 600.0;
+
+        let mut state = AppState::new();
+        state.sim_delta_time_s = dt_s;
+        state.gravity_softening = 0.0;
+
+        // Period that matches our masses, so that the elements describe the orbit gravity will actually produce
+        let period_s = 2.0
+            * std::f64::consts::PI
+            * (semi_major_axis.powi(3) / (GRAVITATION_CONSTANT * (central_mass + body_mass)))
+                .sqrt();
+        let elements = OrbitalElements {
+            ref_id: String::from("10"),
+            epoch: J2000_JDN,
+            semi_major_axis: Mm(semi_major_axis),
+            eccentricity: 0.2,
+            inclination: Deg(10.0).to_rad(),
+            long_asc_node: Deg(30.0).to_rad(),
+            arg_periapsis: Deg(60.0).to_rad(),
+            mean_anomaly_0: Deg(45.0).to_rad(),
+            sidereal_orbit_period_days: period_s / 86400.0,
+        };
+        let rotation = elements_to_ellipse_rotation_transform(&elements);
+
+        // Seed the orbiting body from its state vector at the epoch (velocity comes in Mm/day)
+        let (pos, vel) = orbital_elements_to_xyz(&elements, J2000_JDN, &rotation);
+        let mut comps = vec![RigidBodyComponent::default(), RigidBodyComponent::default()];
+        comps[0].mass = central_mass;
+        comps[1].mass = body_mass;
+        comps[1].trans.trans = pos.coords;
+        comps[1].lin_mom = vel * (body_mass / 86400.0);
+
+        let num_steps = (period_s / dt_s).round() as u32;
+        for step in 1..=num_steps {
+            collect_gravity(&mut comps, state.gravity_softening);
+            for comp in comps.iter_mut() {
+                update_rigidbody(state.sim_delta_time_s, comp);
+            }
+
+            if step % 100 != 0 && step != num_steps {
+                continue;
+            }
+
+            let t = Jdn(J2000_JDN.0 + step as f64 * dt_s / 86400.0);
+            let (expected, _) = orbital_elements_to_xyz(&elements, t, &rotation);
+            let simulated = comps[1].trans.trans - comps[0].trans.trans;
+            let error = (simulated - expected.coords).magnitude();
+            assert!(
+                error < semi_major_axis * 1E-3,
+                "Drifted {} Mm from the Keplerian orbit after {} steps",
+                error,
+                step
+            );
+        }
+    }
+
+    #[wasm_bindgen_test]
+    pub fn substepping_keeps_fast_orbits_bounded() {
+        let central_mass = 6E24; // kg
+        let body_mass = 7E22; // kg
+        let radius = 400.0; // Mm
+        let mu = GRAVITATION_CONSTANT * (central_mass + body_mass);
+        let period_s = 2.0 * std::f64::consts::PI * (radius.powi(3) / mu).sqrt();
+
+        // Half an orbit per frame, like at an extremely high simulation speed
+        let frame_s = period_s / 2.0;
+        let num_frames = 20;
+
+        let new_circular_orbit = || {
+            let speed = (mu / radius).sqrt();
+            let mut comps = vec![RigidBodyComponent::default(), RigidBodyComponent::default()];
+            comps[0].mass = central_mass;
+            comps[0].lin_mom = Vector3::new(0.0, -speed * body_mass, 0.0);
+            comps[1].mass = body_mass;
+            comps[1].trans.trans = Vector3::new(radius, 0.0, 0.0);
+            comps[1].lin_mom = Vector3::new(0.0, speed * body_mass, 0.0);
+            return comps;
+        };
+        let get_dist = |comps: &[RigidBodyComponent]| {
+            return (comps[1].trans.trans - comps[0].trans.trans).magnitude();
+        };
+
+        // A few hundred substeps per frame
+        let max_substep_s = period_s / 1000.0;
+        assert_eq!(get_num_physics_substeps(frame_s, max_substep_s), 500);
+        let mut comps = new_circular_orbit();
+        for frame in 0..num_frames {
+            integrate_rigidbodies(&mut comps, frame_s, max_substep_s, 0.0);
+
+            let dist = get_dist(&comps);
+            assert!(
+                dist > radius * 0.9 && dist < radius * 1.1,
+                "Orbit went to {} Mm after {} frames",
+                dist,
+                frame + 1
+            );
+        }
+
+        // One step per frame flings them apart
+        assert_eq!(get_num_physics_substeps(frame_s, 0.0), 1);
+        let mut comps = new_circular_orbit();
+        for _ in 0..num_frames {
+            integrate_rigidbodies(&mut comps, frame_s, 0.0, 0.0);
+        }
+        assert!(get_dist(&comps) > radius * 10.0);
+
+        // Never more than the cap, even for absurd time steps
+        assert_eq!(get_num_physics_substeps(1E12, 1.0), MAX_PHYSICS_SUBSTEPS);
+        assert_eq!(get_num_physics_substeps(-3.5, 1.0), 4);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn benchmark_free_bodies() {
+        // Hour long steps with the default substeps, the same seed every time so that runs are comparable
+        for num_bodies in &[10, 100] {
+            let num_steps = 200;
+            let result = run_physics_benchmark(*num_bodies, num_steps, 3600.0, 0);
+            info!(
+                LogCat::Physics,
+                "Physics benchmark: {} bodies, {} steps: {:.1} steps/s, relative energy drift {:e}",
+                num_bodies,
+                num_steps,
+                result.steps_per_s,
+                result.energy_drift
+            );
+
+            assert!(result.steps_per_s.is_finite() && result.steps_per_s > 0.0);
+            assert!(result.energy_drift < 1E-4, "{}", result.energy_drift);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    pub fn chunked_gravity_matches_pairwise() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let new_comps = |rng: &mut StdRng, num_bodies: usize| -> Vec<RigidBodyComponent> {
+            return (0..num_bodies)
+                .map(|_| {
+                    let mut comp = RigidBodyComponent::default();
+                    comp.mass = 10.0_f64.powf(rng.gen_range(22.0..30.0));
+                    comp.trans.trans = Vector3::new(
+                        rng.gen_range(-1E5..1E5),
+                        rng.gen_range(-1E5..1E5),
+                        rng.gen_range(-1E3..1E3),
+                    );
+                    comp
+                })
+                .collect();
+        };
+
+        // Same forces regardless of how the bodies are split, including chunks that don't divide them evenly
+        let comps = new_comps(&mut rng, 37);
+        let mut pairwise = comps.clone();
+        collect_gravity(&mut pairwise, 1.0);
+        for chunk_size in &[1, 5, 37, 100] {
+            let mut chunked = comps.clone();
+            collect_gravity_chunked(&mut chunked, 1.0, *chunk_size);
+            for (a, b) in pairwise.iter().zip(chunked.iter()) {
+                let error = (a.force_sum - b.force_sum).magnitude();
+                assert!(
+                    error <= a.force_sum.magnitude() * 1E-6,
+                    "{} with chunks of {}",
+                    error,
+                    chunk_size
+                );
+            }
+        }
+
+        // Single threaded comparison between the two, to see what a worker pool would need to make up for
+        let num_bodies = 500;
+        let num_steps = 10;
+        let mut comps = new_comps(&mut rng, num_bodies);
+        let start_s = now_sec();
+        for _ in 0..num_steps {
+            collect_gravity(&mut comps, 0.0);
+        }
+        let pairwise_s = now_sec() - start_s;
+
+        let start_s = now_sec();
+        for _ in 0..num_steps {
+            collect_gravity_chunked(&mut comps, 0.0, num_bodies / 4);
+        }
+        let chunked_s = now_sec() - start_s;
+
+        info!(
+            LogCat::Physics,
+            "Gravity benchmark: {} bodies, {} steps: pairwise {:.2} ms/step, in four chunks {:.2} ms/step",
+            num_bodies,
+            num_steps,
+            pairwise_s * 1000.0 / num_steps as f64,
+            chunked_s * 1000.0 / num_steps as f64
+        );
+    }
+
+    #[wasm_bindgen_test]
+    pub fn predicted_trajectory_matches_live_physics() {
+        let star_mass = 2E30; // kg
+        let radius = Au(1.0).to_Mm().0;
+        let speed = (GRAVITATION_CONSTANT * star_mass / radius).sqrt();
+
+        let mut scene = Scene::new("prediction");
+        scene.add_test_body("Star", Vector3::zeros(), star_mass);
+        let planet = scene.add_test_body("Planet", Vector3::new(radius, 0.0, 0.0), 6E24);
+        scene
+            .get_component_mut::<RigidBodyComponent>(planet)
+            .unwrap()
+            .lin_mom = Vector3::new(0.0, speed * 6E24, 0.0);
+
+        let mut state = AppState::new();
+        state.selection = Some(planet);
+        state.trajectory_prediction_days = 10.0;
+        state.trajectory_prediction_steps = 20;
+        state.trajectory_prediction_request = Some(planet);
+
+        // Predicting doesn't move anything by itself
+        state.sim_delta_time_s = 0.0;
+        let system = PhysicsSystem {};
+        system.run(&mut state, &mut scene);
+        assert!(state.trajectory_prediction_request.is_none());
+        let points = scene.trajectory_prediction.as_ref().unwrap().points.clone();
+        assert_eq!(points.len(), 21);
+        assert_eq!(points[0], Vector3::new(radius, 0.0, 0.0));
+        assert_eq!(
+            scene
+                .get_component::<TransformComponent>(planet)
+                .unwrap()
+                .get_local_transform()
+                .trans,
+            points[0]
+        );
+
+        // Same steps taken by the live simulation end up at the same points
+        state.sim_delta_time_s = 10.0 * 86400.0 / 20.0;
+        for step in 1..=20 {
+            system.run(&mut state, &mut scene);
+            let pos = scene
+                .get_component::<TransformComponent>(planet)
+                .unwrap()
+                .get_local_transform()
+                .trans;
+            assert!(
+                (pos - points[step]).magnitude() < 1E-6,
+                "{} Mm off after {} steps",
+                (pos - points[step]).magnitude(),
+                step
+            );
+        }
+        assert!(points[20].y > 0.1 * radius);
+
+        // Only rigid bodies can be predicted, and deselecting drops the prediction
+        let builder = scene.start_new_entity("Marker");
+        let marker = scene.finish_new_entity(builder);
+        assert!(predict_trajectory(&scene, marker, 86400.0, 10, 600.0, 0.0).is_none());
+        state.selection = None;
+        system.run(&mut state, &mut scene);
+        assert!(scene.trajectory_prediction.is_none());
+    }
+}
@@ -10,6 +10,7 @@ pub struct Framebuffer {
     width: u32,
     height: u32,
     color_tex: Rc<RefCell<Texture>>,
+    color_format: (u32, u32), // Internal format, pixel type
     depth_renderbuf: Option<glow::Renderbuffer>,
 }
 impl Framebuffer {
@@ -17,6 +18,8 @@ impl Framebuffer {
         width = width.max(1);
         height = height.max(1);
 
+        let color_format = get_color_format(gl);
+
         unsafe {
             let handle = gl.create_framebuffer().unwrap();
             gl.bind_framebuffer(GL::FRAMEBUFFER, Some(handle));
@@ -29,12 +32,12 @@ impl Framebuffer {
             gl.tex_image_2d(
                 GL::TEXTURE_2D,
                 0,
-                GL::RGBA as i32,
+                color_format.0 as i32,
                 width as i32,
                 height as i32,
                 0,
                 GL::RGBA as u32,
-                GL::UNSIGNED_BYTE,
+                color_format.1,
                 None,
             );
             gl.framebuffer_texture_2d(
@@ -51,9 +54,11 @@ impl Framebuffer {
                 width,
                 height,
                 num_channels: 4,
-                gl_format: GL::UNSIGNED_BYTE,
+                gl_format: color_format.1,
+                srgb: false,
                 is_cubemap: false,
                 gl_handle: Some(color_tex),
+                source: None,
             }));
 
             // Depth renderbuffer
@@ -93,6 +98,7 @@ impl Framebuffer {
                 width,
                 height,
                 color_tex: tex,
+                color_format,
                 depth_renderbuf: Some(depth_buf),
             };
         }
@@ -134,12 +140,12 @@ impl Framebuffer {
             gl.tex_image_2d(
                 GL::TEXTURE_2D,
                 0,
-                GL::RGBA as i32,
+                self.color_format.0 as i32,
                 self.width as i32,
                 self.height as i32,
                 0,
                 GL::RGBA as u32,
-                GL::UNSIGNED_BYTE,
+                self.color_format.1,
                 None,
             );
             gl.bind_texture(GL::TEXTURE_2D, None);
@@ -174,3 +180,18 @@ impl Framebuffer {
         }
     }
 }
+
+/// We want to store HDR color so that tonemapping can happen when blitting to the canvas, but rendering
+/// to float textures needs EXT_color_buffer_float on WebGL2. Without it we fall back to 8-bit color, which
+/// clamps everything above 1.0 before the tonemapper gets to it
+fn get_color_format(gl: &glow::Context) -> (u32, u32) {
+    if gl.supported_extensions().contains("EXT_color_buffer_float") {
+        return (GL::RGBA16F, GL::HALF_FLOAT);
+    }
+
+    warning!(
+        LogCat::Resources,
+        "EXT_color_buffer_float is not supported, main framebuffer will be low dynamic range"
+    );
+    return (GL::RGBA, GL::UNSIGNED_BYTE);
+}
@@ -1,27 +1,62 @@
-use crate::app_state::AppState;
+use crate::app_state::{AppState, PointColorMode, RenderStats, Tonemap};
 use crate::components::light_component::LightType;
-use crate::components::{Component, MeshComponent, TransformComponent};
+use crate::components::{Component, MeshComponent, MetadataComponent, TransformComponent};
 use crate::managers::resource::material::{
-    FrameUniformValues, Material, UniformName, UniformValue,
+    BlendMode, FrameUniformValues, Material, UniformName, UniformValue,
 };
-use crate::managers::resource::mesh::Mesh;
+use crate::managers::resource::mesh::{DynamicLinePrimitive, Mesh};
 use crate::managers::resource::texture::TextureUnit;
 use crate::managers::scene::component_storage::ComponentStorage;
-use crate::managers::scene::Scene;
+use crate::managers::scene::{get_body_radius, Entity, OrbitLine, Scene};
 use crate::managers::ResourceManager;
-use crate::systems::Framebuffer;
+use crate::systems::{get_body_fixed_rotation, Framebuffer};
+use crate::utils::camera::{AXIS_GIZMO_MARGIN, AXIS_GIZMO_SIZE};
+use crate::utils::frustum::Frustum;
 use crate::utils::gl::GL;
 use crate::utils::log::*;
+use crate::utils::orbits::get_sampled_eccentric_anomaly;
+use crate::utils::point_colors::{get_point_color, get_point_size};
+use crate::utils::shadows::{find_occluder, Occluder};
+use crate::utils::star_catalog::get_catalog_stars;
+use crate::utils::starfield::{generate_starfield, StarfieldSource, STARFIELD_RADIUS_FRACTION};
 use crate::utils::string::decode_hex;
+use crate::utils::transform::Transform;
+use crate::utils::units::{Jdn, J2000_JDN};
 use crate::{GLCTX, STATE};
 use glow::*;
 use na::*;
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::convert::TryInto;
 use std::rc::Rc;
 
 pub const NUM_LIGHTS: usize = 8;
 
+/// Number of points in an orbit trail that spans an entire orbit. Shorter trails use proportionally fewer
+const ORBIT_TRAIL_SAMPLES: usize = 256;
+
+/// Ground tracks are drawn this much above the surface (as a fraction of the radius), so they don't sink into it
+const GROUND_TRACK_LIFT: f64 = 1.003;
+
+/// Meshes at least this many pixels across on screen are drawn at full detail. Each LOD past that is meant for half
+/// the size of the previous one
+const LOD_0_MIN_DIAMETER_PX: f64 = 256.0;
+
+/// Linear RGBA of the outlines of the hovered and selected bodies. Selected wins if a body is both
+pub const HOVER_OUTLINE_COLOR: [f32; 4] = [0.8, 0.8, 0.8, 1.0];
+pub const SELECTION_OUTLINE_COLOR: [f32; 4] = [1.0, 0.45, 0.05, 1.0];
+
+/// Linear RGBA of the debug line of state.last_pick_ray, depending on whether it hit a collider
+const PICK_RAY_HIT_COLOR: [f32; 4] = [0.1, 1.0, 0.1, 1.0];
+const PICK_RAY_MISS_COLOR: [f32; 4] = [1.0, 0.1, 0.1, 1.0];
+
+/// How far outlines stick out past the silhouette of their meshes, in pixels
+const OUTLINE_WIDTH_PX: f32 = 2.5;
+
+/// Tonemap uniform value for materials drawn straight onto the canvas, after the blit. Their display colors are
+/// then written out unchanged instead of being converted to the HDR values the blit would map back to them
+const CANVAS_TONEMAP: i32 = -1;
+
 fn exposure_factor(ev100: f32) -> f32 {
     return 1.0 / (2.0.powf(ev100) * 1.2);
 }
@@ -30,6 +65,8 @@ pub struct RenderingSystem {
     framebuffer: Framebuffer,
     screenspace_quad: Option<Rc<RefCell<Mesh>>>,
     blit_framebuffer_mat: Option<Rc<RefCell<Material>>>,
+    axis_gizmo_mesh: Option<Rc<RefCell<Mesh>>>,
+    pick_ray_line: Option<DynamicLinePrimitive>, // Created when first drawn
 }
 impl RenderingSystem {
     pub fn new(res_man: &mut ResourceManager) -> Self {
@@ -49,6 +86,7 @@ impl RenderingSystem {
         let screenspace_quad = res_man.get_or_create_mesh("quad");
         let blit_framebuffer_mat =
             res_man.instantiate_material("default_screenspace", "default_screenspace");
+        let axis_gizmo_mesh = res_man.get_or_create_mesh("axes");
 
         // Create framebuffer
         let framebuffer = GLCTX.with(|gl| {
@@ -68,6 +106,8 @@ impl RenderingSystem {
             framebuffer,
             screenspace_quad,
             blit_framebuffer_mat,
+            axis_gizmo_mesh,
+            pick_ray_line: None,
         };
     }
 
@@ -75,28 +115,81 @@ impl RenderingSystem {
         self.framebuffer.resize(width, height, gl);
     }
 
-    pub fn run(&mut self, state: &AppState, scene: &mut Scene) {
+    /// Recreates our framebuffer once the WebGL context is restored after being lost
+    pub fn restore_gl_resources(&mut self, width: u32, height: u32, gl: &glow::Context) {
+        self.framebuffer = Framebuffer::new(width, height, gl);
+
+        if let Some(mat) = &self.blit_framebuffer_mat {
+            mat.borrow_mut().set_texture(
+                TextureUnit::BaseColor,
+                Some(self.framebuffer.get_color_tex().clone()),
+            );
+        }
+
+        if let Some(line) = &mut self.pick_ray_line {
+            line.restore_gl_objects(gl);
+        }
+    }
+
+    pub fn run(&mut self, state: &mut AppState, scene: &mut Scene) {
         GLCTX.with(|gl| {
             // Main pass
             self.framebuffer.bind(gl);
             let mut uniform_data = pre_draw(state, gl, scene);
-            draw(gl, &mut uniform_data, scene);
+            update_orbit_lines(state, scene);
+            draw_starfield(state, gl, &uniform_data, scene);
+            state.render_stats = draw(state, gl, &mut uniform_data, scene);
+            draw_outlines(state, gl, &uniform_data, scene);
+            draw_orbit_trails(state, gl, &uniform_data, scene);
+            draw_trajectory_prediction(gl, &uniform_data, scene);
+            draw_ground_track(state, gl, &uniform_data, scene);
+            draw_body_trails(state, gl, &uniform_data, scene);
+            draw_pick_ray(state, gl, &uniform_data, scene, &mut self.pick_ray_line);
             draw_points(state, gl, &mut uniform_data, scene);
             draw_skybox(state, gl, &mut uniform_data, scene);
             self.framebuffer.unbind(gl);
 
             // Blit to main render target
+            // The egui overlay is painted straight onto the canvas after this, so it never goes through the tonemapper
             post_draw(
                 state.canvas_width,
                 state.canvas_height,
+                state.tonemap,
                 gl,
                 self.blit_framebuffer_mat.as_ref(),
                 self.screenspace_quad.as_ref(),
             );
+            draw_axis_gizmo(state, gl, self.axis_gizmo_mesh.as_ref());
         });
     }
 }
 
+/// Returns the HDR color to clear our framebuffer with so that once it goes through `tonemap` and the sRGB
+/// conversion in screenspace.frag it displays as `srgb`. display_to_hdr in functions.glsl does the same for the
+/// materials that output display colors
+fn get_background_clear_color(srgb: [u8; 3], tonemap: Tonemap) -> [f32; 3] {
+    let mut result = [0.0; 3];
+    for (channel, value) in result.iter_mut().zip(srgb.iter()) {
+        // Same approximation to sRGB as functions.glsl
+        let linear = (*value as f32 / 255.0).powf(2.2).min(0.999);
+
+        *channel = match tonemap {
+            Tonemap::None => linear,
+            Tonemap::Reinhard => linear / (1.0 - linear),
+            Tonemap::ACESFilmic => {
+                // Solve for x in y = x(ax + b) / (x(cx + d) + e), taking the positive root
+                let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+                let qa = linear * c - a;
+                let qb = linear * d - b;
+                let qc = linear * e;
+                (-qb - (qb * qb - 4.0 * qa * qc).sqrt()) / (2.0 * qa)
+            }
+        };
+    }
+
+    return result;
+}
+
 fn pre_draw(state: &AppState, gl: &glow::Context, scene: &mut Scene) -> FrameUniformValues {
     unsafe {
         gl.enable(GL::CULL_FACE);
@@ -105,19 +198,64 @@ fn pre_draw(state: &AppState, gl: &glow::Context, scene: &mut Scene) -> FrameUni
         gl.enable(GL::DEPTH_TEST);
         gl.depth_func(GL::LESS);
 
-        gl.clear_color(0.1, 0.1, 0.2, 1.0);
+        let clear = get_background_clear_color(state.background_color, state.tonemap);
+        gl.clear_color(clear[0], clear[1], clear[2], 1.0);
         gl.clear(GL::COLOR_BUFFER_BIT | GL::DEPTH_BUFFER_BIT);
     }
 
+    return get_frame_uniform_values(state, scene);
+}
+
+/// Uniform values shared by everything we draw this frame, including the first NUM_LIGHTS lights of `scene`
+fn get_frame_uniform_values(state: &AppState, scene: &Scene) -> FrameUniformValues {
+    // Metallic materials reflect the skybox, once its cubemap has loaded
+    let environment = match (&scene.skybox_mat, &scene.skybox_trans) {
+        (Some(mat), Some(_)) => mat
+            .borrow()
+            .get_textures()
+            .get(&TextureUnit::BaseColor)
+            .filter(|tex| {
+                let tex = tex.borrow();
+                return tex.is_cubemap && tex.gl_handle.is_some();
+            })
+            .cloned(),
+        _ => None,
+    };
+    let environment_trans: [f32; 16] = match &scene.skybox_trans {
+        Some(skybox_trans) => na::convert::<Matrix4<f64>, Matrix4<f32>>(get_environment_transform(
+            skybox_trans,
+            &state.camera.v_inv,
+        ))
+        .as_slice()
+        .try_into()
+        .unwrap(),
+        None => Matrix4::<f32>::identity().as_slice().try_into().unwrap(),
+    };
+    let environment_max_lod = environment
+        .as_ref()
+        .map(|tex| {
+            let tex = tex.borrow();
+            return (tex.width.max(tex.height).max(1) as f32).log2().floor();
+        })
+        .unwrap_or(0.0);
+
     let mut result = FrameUniformValues {
         v: state.camera.v,
-        pv: state.camera.p * state.camera.v,
+        p: state.camera.p,
+        v_rel: state.camera.v_rel,
+        camera_pos: state.camera.world_pos,
         light_types: Vec::new(),
         light_colors: Vec::new(),
         light_pos_or_dir_c: Vec::new(),
         light_intensities: Vec::new(),
         exposure_factor: exposure_factor(state.ev100),
+        tonemap: state.tonemap as i32,
         f_coef: (2.0 / (state.camera.far + 1.0).log2()) as f32,
+        viewport_size: [state.canvas_width as f32, state.canvas_height as f32],
+        line_width: state.line_width,
+        environment,
+        environment_trans,
+        environment_max_lod,
     };
 
     result.light_types.reserve(NUM_LIGHTS);
@@ -136,7 +274,7 @@ fn pre_draw(state: &AppState, gl: &glow::Context, scene: &mut Scene) -> FrameUni
             .trans;
 
         let pos = match light.light_type {
-            LightType::Point => result.v.transform_point(&Point3::from(*pos)).coords,
+            LightType::Point => result.v_rel.transform_vector(&(pos - result.camera_pos)),
             LightType::Directional => result.v.transform_vector(pos),
         };
 
@@ -146,7 +284,9 @@ fn pre_draw(state: &AppState, gl: &glow::Context, scene: &mut Scene) -> FrameUni
         result.light_colors.push(light.color.y);
         result.light_colors.push(light.color.z);
 
-        result.light_intensities.push(light.intensity);
+        result
+            .light_intensities
+            .push(light.intensity * state.light_intensity_scale);
 
         result.light_pos_or_dir_c.push(pos.x as f32);
         result.light_pos_or_dir_c.push(pos.y as f32);
@@ -163,15 +303,730 @@ fn pre_draw(state: &AppState, gl: &glow::Context, scene: &mut Scene) -> FrameUni
     return result;
 }
 
-fn draw(gl: &glow::Context, uniform_data: &mut FrameUniformValues, scene: &mut Scene) {
-    for (t, m) in scene.transform.iter().zip(scene.mesh.iter_mut()) {
-        draw_one(gl, uniform_data, t, m);
+/// Body that moves along the drawn orbit line of `orbit`, which for the apparent orbit of the reference body is
+/// its central body instead
+fn get_orbit_line_body(scene: &Scene, orbit: &OrbitLine) -> Entity {
+    if scene.apparent_orbit_line == Some(orbit.line) {
+        return scene.get_entity_parent(orbit.line).unwrap_or(orbit.body);
+    }
+
+    return orbit.body;
+}
+
+/// Updates the orbit line materials with the current position of each body along its orbit
+fn update_orbit_lines(state: &AppState, scene: &mut Scene) {
+    for orbit in scene.orbits.iter() {
+        let body_trans = scene
+            .get_component::<TransformComponent>(get_orbit_line_body(scene, orbit))
+            .and_then(|c| Some(c.get_world_transform().trans));
+        let line_trans = scene
+            .get_component::<TransformComponent>(orbit.line)
+            .and_then(|c| Some(c.get_world_transform()));
+        let mat = scene
+            .get_component::<MeshComponent>(orbit.line)
+            .and_then(|c| c.get_material_override(0));
+        if body_trans.is_none() || line_trans.is_none() || mat.is_none() {
+            continue;
+        }
+
+        // Bring the body back into the unit circle space of the orbit line, which directly
+        // gives us its eccentric anomaly
+        let local_pos = line_trans
+            .unwrap()
+            .inverse_transform_point(&Point3::from(body_trans.unwrap()));
+        let angle = local_pos.y.atan2(local_pos.x);
+        let fraction = (angle / (2.0 * std::f64::consts::PI)).rem_euclid(1.0);
+
+        let mat = mat.unwrap();
+        let mut mat_mut = mat.borrow_mut();
+        mat_mut.set_uniform_value(
+            UniformName::OrbitFraction,
+            UniformValue::Float(fraction as f32),
+        );
+        mat_mut.set_uniform_value(
+            UniformName::OrbitFade,
+            UniformValue::Float(state.fade_orbits as i32 as f32),
+        );
+    }
+}
+
+/// Draws the arc of its orbit that each body went through in the last `state.orbit_trail_days`, fading out
+/// towards the tail. The trails are rebuilt every frame in the unit circle space of each orbit line
+fn draw_orbit_trails(
+    state: &AppState,
+    gl: &glow::Context,
+    uniform_data: &FrameUniformValues,
+    scene: &Scene,
+) {
+    if !state.show_orbit_trails || state.orbit_trail_days <= 0.0 || scene.trail_mat.is_none() {
+        return;
+    }
+
+    let mut mat_mut = scene.trail_mat.as_ref().unwrap().borrow_mut();
+    let mut points: Vec<[f32; 3]> = Vec::new();
+    let mut colors: Vec<[f32; 4]> = Vec::new();
+    for orbit in scene.orbits.iter() {
+        if orbit.trail.is_none() || orbit.baked_times.len() < 2 || orbit.period_days <= 0.0 {
+            continue;
+        }
+
+        let body_trans = scene
+            .get_component::<TransformComponent>(get_orbit_line_body(scene, orbit))
+            .and_then(|c| Some(c.get_world_transform().trans));
+        let line_trans = scene
+            .get_component::<TransformComponent>(orbit.line)
+            .and_then(|c| Some(c.get_world_transform()));
+        if body_trans.is_none() || line_trans.is_none() {
+            continue;
+        }
+        let line_trans = line_trans.unwrap();
+
+        // The apparent orbit is the body's own orbit line turned half a turn, see get_apparent_orbit_transform
+        let half_turn = if scene.apparent_orbit_line == Some(orbit.line) {
+            std::f64::consts::PI
+        } else {
+            0.0
+        };
+
+        // Bodies are moved by the physics system and not by their orbital elements, so instead of looking up
+        // sim_time_s directly we find where along the baked orbit the body currently is, and look back from there.
+        // The eccentric anomaly comes from the unit circle space just like for update_orbit_lines
+        let local_pos = line_trans.inverse_transform_point(&Point3::from(body_trans.unwrap()));
+        let eccentric_anomaly =
+            (local_pos.y.atan2(local_pos.x) + half_turn).rem_euclid(2.0 * std::f64::consts::PI);
+        let mean_anomaly = eccentric_anomaly - orbit.eccentricity * eccentric_anomaly.sin();
+        let now = Jdn(orbit.baked_times[0].0
+            + orbit.period_days * mean_anomaly / (2.0 * std::f64::consts::PI));
+
+        // Anything longer than one orbit would just overlap itself
+        let window_days = state.orbit_trail_days.min(orbit.period_days);
+        let num_segments =
+            ((ORBIT_TRAIL_SAMPLES as f64 * window_days / orbit.period_days).ceil() as usize).max(1);
+
+        let color = get_body_line_color(scene, orbit.body);
+
+        // From the tail to the body. get_sampled_eccentric_anomaly takes care of wrapping across periapsis
+        points.clear();
+        colors.clear();
+        for i in 0..=num_segments {
+            let fraction = i as f64 / num_segments as f64;
+            let date = Jdn(now.0 - window_days * (1.0 - fraction));
+            let angle = get_sampled_eccentric_anomaly(
+                date,
+                orbit.period_days,
+                &orbit.baked_times,
+                &orbit.baked_anomalies,
+            )
+            .0 - half_turn;
+
+            points.push([angle.cos() as f32, angle.sin() as f32, 0.0]);
+            colors.push([color[0], color[1], color[2], fraction as f32]);
+        }
+
+        let mut trail = orbit.trail.as_ref().unwrap().borrow_mut();
+        draw_line_strip(
+            gl,
+            uniform_data,
+            &mut mat_mut,
+            &mut trail,
+            line_trans,
+            &points,
+            &colors,
+        );
+    }
+}
+
+/// Draws `points` as a line strip with `mat` (usually scene.trail_mat), placed by `world_trans`. The buffers of `line`
+/// are refilled with them every time
+fn draw_line_strip(
+    gl: &glow::Context,
+    uniform_data: &FrameUniformValues,
+    mat: &mut Material,
+    line: &mut DynamicLinePrimitive,
+    world_trans: &Transform<f64>,
+    points: &[[f32; 3]],
+    colors: &[[f32; 4]],
+) {
+    let wvp_arr: [f32; 16] = na::convert::<Matrix4<f64>, Matrix4<f32>>(
+        uniform_data.p * get_wv_transform(uniform_data, world_trans),
+    )
+    .as_slice()
+    .try_into()
+    .unwrap();
+    mat.set_uniform_value(UniformName::WVPTrans, UniformValue::Matrix(wvp_arr));
+    mat.set_uniform_value(UniformName::Fcoef, UniformValue::Float(uniform_data.f_coef));
+    mat.set_uniform_value(
+        UniformName::Tonemap,
+        UniformValue::Int(uniform_data.tonemap),
+    );
+    mat.set_uniform_value(
+        UniformName::LineWidth,
+        UniformValue::Float(uniform_data.line_width),
+    );
+    mat.set_uniform_value(
+        UniformName::ViewportSize,
+        UniformValue::Vec2(uniform_data.viewport_size),
+    );
+
+    line.set_strip(points, colors);
+    line.upload_buffers(gl);
+
+    mat.bind_for_drawing(gl);
+    line.draw(gl);
+    mat.unbind_from_drawing(gl);
+}
+
+/// Color of the "base_color" metadata of `body`, or white
+fn get_body_line_color(scene: &Scene, body: Entity) -> [f32; 3] {
+    let mut color: [f32; 3] = [1.0, 1.0, 1.0];
+    if let Some(base_color) = scene
+        .get_component::<MetadataComponent>(body)
+        .and_then(|c| c.get_metadata("base_color"))
+    {
+        if let Ok(bytes) = decode_hex(base_color) {
+            for (channel, byte) in color.iter_mut().zip(bytes.iter()) {
+                *channel = *byte as f32 / 255.0;
+            }
+        }
+    }
+
+    return color;
+}
+
+/// Draws scene.trajectory_prediction as a dotted line. The points are drawn relative to the first one so that they
+/// keep their precision even when far from the origin
+fn draw_trajectory_prediction(
+    gl: &glow::Context,
+    uniform_data: &FrameUniformValues,
+    scene: &mut Scene,
+) {
+    if scene.trail_mat.is_none() {
+        return;
+    }
+
+    let color = match &scene.trajectory_prediction {
+        Some(prediction) if prediction.points.len() >= 2 => {
+            get_body_line_color(scene, prediction.body)
+        }
+        _ => return,
+    };
+    let prediction = scene.trajectory_prediction.as_mut().unwrap();
+
+    let origin = prediction.points[0];
+    let points: Vec<[f32; 3]> = prediction
+        .points
+        .iter()
+        .map(|point| {
+            let relative = point - origin;
+            [relative.x as f32, relative.y as f32, relative.z as f32]
+        })
+        .collect();
+
+    // Alternating points fade to transparent, which breaks the line up into dots
+    let colors: Vec<[f32; 4]> = (0..points.len())
+        .map(|i| [color[0], color[1], color[2], (i % 2 == 0) as i32 as f32])
+        .collect();
+
+    let world_trans = Transform {
+        trans: origin,
+        ..Transform::identity()
+    };
+    let trail = prediction
+        .trail
+        .get_or_insert_with(|| Rc::new(RefCell::new(DynamicLinePrimitive::new(gl))));
+    draw_line_strip(
+        gl,
+        uniform_data,
+        &mut scene.trail_mat.as_ref().unwrap().borrow_mut(),
+        &mut trail.borrow_mut(),
+        &world_trans,
+        &points,
+        &colors,
+    );
+}
+
+/// Draws scene.ground_track on the surface of its central body, fading out towards the oldest points. The points are
+/// unit vectors in the frame of the surface, so we just draw them with its current rotation
+fn draw_ground_track(
+    state: &AppState,
+    gl: &glow::Context,
+    uniform_data: &FrameUniformValues,
+    scene: &mut Scene,
+) {
+    if scene.trail_mat.is_none() {
+        return;
+    }
+
+    let (body, central_body) = match &scene.ground_track {
+        Some(track) if track.points.len() >= 2 => (track.body, track.central_body),
+        _ => return,
+    };
+    let central_trans = scene
+        .get_component::<TransformComponent>(central_body)
+        .map(|trans| trans.get_world_transform().trans);
+    if central_trans.is_none() {
+        return;
+    }
+
+    let color = get_body_line_color(scene, body);
+    let radius = get_body_radius(scene, central_body) * GROUND_TRACK_LIFT;
+    let world_trans = Transform {
+        trans: central_trans.unwrap(),
+        rot: get_body_fixed_rotation(scene, central_body),
+        scale: Vector3::new(radius, radius, radius),
+    };
+
+    let track = scene.ground_track.as_mut().unwrap();
+    let max_age_s = (state.ground_track_hours * 3600.0).max(1E-6);
+    let points: Vec<[f32; 3]> = track
+        .points
+        .iter()
+        .map(|(_, dir)| [dir.x as f32, dir.y as f32, dir.z as f32])
+        .collect();
+    let colors: Vec<[f32; 4]> = track
+        .points
+        .iter()
+        .map(|(t, _)| {
+            let fade = 1.0 - ((state.sim_time_s - t) / max_age_s).max(0.0).min(1.0);
+            [color[0], color[1], color[2], fade as f32]
+        })
+        .collect();
+
+    let trail = track
+        .trail
+        .get_or_insert_with(|| Rc::new(RefCell::new(DynamicLinePrimitive::new(gl))));
+    draw_line_strip(
+        gl,
+        uniform_data,
+        &mut scene.trail_mat.as_ref().unwrap().borrow_mut(),
+        &mut trail.borrow_mut(),
+        &world_trans,
+        &points,
+        &colors,
+    );
+}
+
+/// Draws state.last_pick_ray as a line from where the camera was to what it hit, green if it hit a collider and red
+/// out to the far plane if it didn't. The interface manager labels the hit point
+fn draw_pick_ray(
+    state: &AppState,
+    gl: &glow::Context,
+    uniform_data: &FrameUniformValues,
+    scene: &Scene,
+    line: &mut Option<DynamicLinePrimitive>,
+) {
+    let ray = match &state.last_pick_ray {
+        Some(ray) if state.show_pick_ray => ray,
+        _ => return,
+    };
+    let mat = match &scene.trail_mat {
+        Some(mat) => mat,
+        None => return,
+    };
+
+    let (end, color) = match ray.hit {
+        Some((_, pos)) => (pos, PICK_RAY_HIT_COLOR),
+        None => (
+            ray.start + ray.direction * state.camera.far,
+            PICK_RAY_MISS_COLOR,
+        ),
+    };
+    let relative_end = end - ray.start;
+
+    // Relative to the start, where the camera was
+    let world_trans = Transform {
+        trans: ray.start.coords,
+        ..Transform::identity()
+    };
+    draw_line_strip(
+        gl,
+        uniform_data,
+        &mut mat.borrow_mut(),
+        line.get_or_insert_with(|| DynamicLinePrimitive::new(gl)),
+        &world_trans,
+        &[
+            [0.0, 0.0, 0.0],
+            [
+                relative_end.x as f32,
+                relative_end.y as f32,
+                relative_end.z as f32,
+            ],
+        ],
+        &[color, color],
+    );
+}
+
+/// Entities with meshes that get outlined for state.hovered and state.selection, and their outline colors. Bodies
+/// get the meshes of their children (e.g. their sphere and rings) outlined too, but not those of their moons nor
+/// the ones that can't be picked, like atmosphere shells
+pub fn get_outlined_meshes(state: &AppState, scene: &Scene) -> Vec<(Entity, [f32; 4])> {
+    let mut result: Vec<(Entity, [f32; 4])> = Vec::new();
+    if !state.show_outlines {
+        return result;
+    }
+
+    // Selection last, so that its color replaces the hover one
+    for (entity, color) in &[
+        (state.hovered, HOVER_OUTLINE_COLOR),
+        (state.selection, SELECTION_OUTLINE_COLOR),
+    ] {
+        let entity = match entity {
+            Some(entity) => *entity,
+            None => continue,
+        };
+
+        let mut entities = vec![entity];
+        if let Some(children) = scene.get_entity_children(entity) {
+            entities.extend(
+                children
+                    .iter()
+                    .filter(|child| scene.get_body_id_from_entity(**child).is_none()),
+            );
+        }
+
+        for entity in entities {
+            let drawn = scene
+                .get_component::<MeshComponent>(entity)
+                .map(|mc| mc.visible && mc.raycasting_visible)
+                .unwrap_or(false);
+            if !drawn {
+                continue;
+            }
+
+            match result.iter_mut().find(|(e, _)| *e == entity) {
+                Some(existing) => existing.1 = *color,
+                None => result.push((entity, *color)),
+            }
+        }
+    }
+
+    return result;
+}
+
+/// Draws the back faces of the meshes from get_outlined_meshes pushed out a few pixels past their silhouettes, in a
+/// solid color. Goes after the main pass so that the depth test against the meshes themselves leaves only the rim
+fn draw_outlines(
+    state: &AppState,
+    gl: &glow::Context,
+    uniform_data: &FrameUniformValues,
+    scene: &Scene,
+) {
+    let mat = match &scene.outline_mat {
+        Some(mat) => mat,
+        None => return,
+    };
+
+    for (entity, color) in get_outlined_meshes(state, scene) {
+        let tc = scene.get_component::<TransformComponent>(entity);
+        let mesh = scene
+            .get_component::<MeshComponent>(entity)
+            .and_then(|mc| mc.get_mesh());
+        if tc.is_none() || mesh.is_none() {
+            continue;
+        }
+
+        let wvp_arr: [f32; 16] = na::convert::<Matrix4<f64>, Matrix4<f32>>(
+            uniform_data.p * get_wv_transform(uniform_data, tc.unwrap().get_world_transform()),
+        )
+        .as_slice()
+        .try_into()
+        .unwrap();
+
+        let mut mat_mut = mat.borrow_mut();
+        mat_mut.set_uniform_value(UniformName::WVPTrans, UniformValue::Matrix(wvp_arr));
+        mat_mut.set_uniform_value(UniformName::Fcoef, UniformValue::Float(uniform_data.f_coef));
+        mat_mut.set_uniform_value(
+            UniformName::Tonemap,
+            UniformValue::Int(uniform_data.tonemap),
+        );
+        mat_mut.set_uniform_value(
+            UniformName::LineWidth,
+            UniformValue::Float(OUTLINE_WIDTH_PX * state.pixels_per_point),
+        );
+        mat_mut.set_uniform_value(
+            UniformName::ViewportSize,
+            UniformValue::Vec2(uniform_data.viewport_size),
+        );
+        mat_mut.set_uniform_value(UniformName::BaseColorFactor, UniformValue::Vec4(color));
+
+        for primitive in mesh.unwrap().borrow().primitives.iter() {
+            // Outlining lines or points would just thicken them
+            if primitive.mode != GL::TRIANGLES {
+                continue;
+            }
+
+            if mat_mut.get_compatible_prim_hash() != primitive.compatible_hash {
+                mat_mut.set_prim_defines(primitive);
+            }
+
+            mat_mut.bind_for_drawing(gl);
+            primitive.draw(gl);
+            mat_mut.unbind_from_drawing(gl);
+        }
+    }
+}
+
+/// Draws scene.body_trails as lines fading out towards their oldest point
+fn draw_body_trails(
+    state: &AppState,
+    gl: &glow::Context,
+    uniform_data: &FrameUniformValues,
+    scene: &mut Scene,
+) {
+    if scene.trail_mat.is_none() || scene.body_trails.is_empty() {
+        return;
+    }
+
+    let colors: HashMap<Entity, [f32; 3]> = scene
+        .body_trails
+        .keys()
+        .map(|entity| (*entity, get_body_line_color(scene, *entity)))
+        .collect();
+    let max_age_s = (state.body_trail_days * 86400.0).max(1E-6);
+
+    let mut mat_mut = scene.trail_mat.as_ref().unwrap().borrow_mut();
+    for (entity, body_trail) in scene.body_trails.iter_mut() {
+        if body_trail.points.len() < 2 {
+            continue;
+        }
+
+        // Relative to the newest point, as the trail can span far more than f32 can hold precisely
+        let newest = body_trail.points.back().unwrap().1;
+        let world_trans = Transform {
+            trans: newest,
+            rot: UnitQuaternion::identity(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        };
+
+        let color = colors[entity];
+        let points: Vec<[f32; 3]> = body_trail
+            .points
+            .iter()
+            .map(|(_, pos)| {
+                let offset = pos - newest;
+                [offset.x as f32, offset.y as f32, offset.z as f32]
+            })
+            .collect();
+        let point_colors: Vec<[f32; 4]> = body_trail
+            .points
+            .iter()
+            .map(|(t, _)| {
+                let fade = 1.0 - ((state.sim_time_s - t) / max_age_s).max(0.0).min(1.0);
+                [color[0], color[1], color[2], fade as f32]
+            })
+            .collect();
+
+        let trail = body_trail
+            .trail
+            .get_or_insert_with(|| Rc::new(RefCell::new(DynamicLinePrimitive::new(gl))));
+        draw_line_strip(
+            gl,
+            uniform_data,
+            &mut mat_mut,
+            &mut trail.borrow_mut(),
+            &world_trans,
+            &points,
+            &point_colors,
+        );
+    }
+}
+
+fn draw(
+    state: &AppState,
+    gl: &glow::Context,
+    uniform_data: &mut FrameUniformValues,
+    scene: &mut Scene,
+) -> RenderStats {
+    // Planes are in view space (not world space) so that the culling tests happen with the same small,
+    // camera-relative coordinates we use for drawing
+    let frustum = Frustum::from_matrix(&state.camera.p);
+
+    let shadows = collect_shadow_casters(uniform_data, scene);
+
+    let mut stats = RenderStats::default();
+    let mut blended: Vec<(f64, usize, &TransformComponent, &mut MeshComponent)> = Vec::new();
+    for (index, (t, m)) in iter_drawn_meshes(scene.transform.iter(), scene.mesh.iter_mut()) {
+        if is_blended(&*m) {
+            let dist2 =
+                (t.get_world_transform().trans - uniform_data.camera_pos).magnitude_squared();
+            blended.push((dist2, index, t, m));
+            continue;
+        }
+
+        draw_one(
+            gl,
+            uniform_data,
+            &frustum,
+            &shadows,
+            &mut stats,
+            index,
+            t,
+            m,
+        );
+    }
+
+    // Blended meshes go after everything they may be in front of, from back to front so that they blend over
+    // eachother in the right order
+    blended.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    for (_, index, t, m) in blended {
+        draw_one(
+            gl,
+            uniform_data,
+            &frustum,
+            &shadows,
+            &mut stats,
+            index,
+            t,
+            m,
+        );
+    }
+    return stats;
+}
+
+/// Whether any of the materials that `mc` draws with blends with what was drawn behind it
+fn is_blended(mc: &MeshComponent) -> bool {
+    return (0..mc.get_num_materials()).any(|index| {
+        mc.get_resolved_material(index)
+            .map_or(false, |mat| mat.borrow().blend_mode != BlendMode::Opaque)
+    });
+}
+
+/// Pairs up the components that the main draw loop goes through, along with their entity index. Skips hidden meshes
+fn iter_drawn_meshes<'a>(
+    transforms: impl Iterator<Item = &'a TransformComponent> + 'a,
+    meshes: impl Iterator<Item = &'a mut MeshComponent> + 'a,
+) -> impl Iterator<Item = (usize, (&'a TransformComponent, &'a mut MeshComponent))> + 'a {
+    return transforms
+        .zip(meshes)
+        .enumerate()
+        .filter(|(_, (_, m))| m.visible);
+}
+
+/// The main point light and the bodies that may eclipse it, all in view space
+struct ShadowCasters {
+    light: Option<(Point3<f64>, f64)>, // Position and radius, in Mm
+    occluders: Vec<Occluder>,
+}
+
+fn collect_shadow_casters(uniform_data: &FrameUniformValues, scene: &Scene) -> ShadowCasters {
+    let mut result = ShadowCasters {
+        light: None,
+        occluders: Vec::new(),
+    };
+
+    // Only the first point light casts shadows, which is the star for every scene we have
+    let light_index = uniform_data
+        .light_types
+        .iter()
+        .position(|t| *t == LightType::Point as i32);
+    if light_index.is_none() {
+        return result;
+    }
+    let light_index = light_index.unwrap();
+    let light_pos = Point3::new(
+        uniform_data.light_pos_or_dir_c[light_index * 3] as f64,
+        uniform_data.light_pos_or_dir_c[light_index * 3 + 1] as f64,
+        uniform_data.light_pos_or_dir_c[light_index * 3 + 2] as f64,
+    );
+
+    // Only actual bodies: We don't want e.g. Saturn's rings to act as a huge sphere.
+    // Body meshes live on a child entity, so the body metadata is on their parent
+    for (index, (t, m)) in scene.transform.iter().zip(scene.mesh.iter()).enumerate() {
+        let is_body = scene
+            .get_entity_from_index(index as u32)
+            .and_then(|ent| scene.get_entity_parent(ent))
+            .and_then(|parent| scene.get_component::<MetadataComponent>(parent))
+            .and_then(|meta| meta.get_metadata("body_type"))
+            .map(|body_type| body_type != "Barycenter" && body_type != "Artificial")
+            .unwrap_or(false);
+        // Atmosphere shells are on the body entity too, but are see-through
+        if !is_body || is_blended(m) {
+            continue;
+        }
+
+        if let Some((center, radius)) = get_view_bounding_sphere(uniform_data, t, m) {
+            result.occluders.push(Occluder {
+                index,
+                center,
+                radius,
+            });
+        }
+    }
+
+    // The light is a point light, but for soft shadows we need to know how large the star is
+    let light_radius = result
+        .occluders
+        .iter()
+        .find(|o| (light_pos - o.center).magnitude_squared() <= o.radius * o.radius)
+        .map(|o| o.radius)
+        .unwrap_or(0.0);
+
+    result.light = Some((light_pos, light_radius));
+    return result;
+}
+
+/// Returns the matrix that takes `world_trans`'s local space into camera space.
+/// The camera position is subtracted from the translation in f64 before multiplying anything,
+/// so that objects near the camera stay precise even when both are very far from the origin
+fn get_wv_transform(
+    uniform_data: &FrameUniformValues,
+    world_trans: &Transform<f64>,
+) -> Matrix4<f64> {
+    let mut relative = world_trans.clone();
+    relative.trans -= uniform_data.camera_pos;
+    return uniform_data.v_rel * relative.to_matrix4();
+}
+
+/// Returns the view space bounding sphere of the mesh's collider, if it has one
+fn get_view_bounding_sphere(
+    uniform_data: &FrameUniformValues,
+    tc: &TransformComponent,
+    mc: &MeshComponent,
+) -> Option<(Point3<f64>, f64)> {
+    let mesh = mc.get_mesh()?;
+    let mesh = mesh.borrow();
+    let (center, radius) = mesh.collider.as_ref()?.bounding_sphere()?;
+
+    let wv = get_wv_transform(uniform_data, tc.get_world_transform());
+    let view_center = wv.transform_point(&na::convert::<Point3<f32>, Point3<f64>>(center));
+    let max_scale = tc.get_world_transform().scale.abs().max();
+
+    return Some((view_center, radius as f64 * max_scale));
+}
+
+/// Diameter in pixels of a sphere of `view_radius` at `view_center` (in camera space) when projected with `p` onto a
+/// viewport of `viewport_height` pixels. Infinite if the camera is inside it
+fn get_projected_diameter_px(
+    view_center: &Point3<f64>,
+    view_radius: f64,
+    p: &Matrix4<f64>,
+    viewport_height: f32,
+) -> f64 {
+    let dist = view_center.coords.magnitude();
+    if dist <= view_radius {
+        return std::f64::INFINITY;
+    }
+
+    // The NDC range of 2 maps to the full viewport height
+    return view_radius / dist * p[(1, 1)] * viewport_height as f64;
+}
+
+/// Level of detail to draw a mesh with `num_lods` levels with, given how many pixels across it is on screen
+fn select_lod(diameter_px: f64, num_lods: usize) -> usize {
+    if num_lods <= 1 || diameter_px >= LOD_0_MIN_DIAMETER_PX {
+        return 0;
     }
+
+    let lod = (LOD_0_MIN_DIAMETER_PX / diameter_px.max(1E-6))
+        .log2()
+        .ceil() as usize;
+    return lod.min(num_lods - 1);
 }
 
 fn post_draw(
     width: u32,
     height: u32,
+    tonemap: Tonemap,
     gl: &glow::Context,
     mat: Option<&Rc<RefCell<Material>>>,
     quad: Option<&Rc<RefCell<Mesh>>>,
@@ -189,6 +1044,7 @@ fn post_draw(
         let mut mat = RefCell::borrow_mut(mat.unwrap());
         let quad = quad.unwrap();
 
+        mat.set_uniform_value(UniformName::Tonemap, UniformValue::Int(tonemap as i32));
         mat.bind_for_drawing(gl);
 
         for prim in &RefCell::borrow(quad).primitives {
@@ -202,15 +1058,18 @@ fn post_draw(
 fn draw_one(
     gl: &glow::Context,
     uniform_data: &FrameUniformValues,
+    frustum: &Frustum,
+    shadows: &ShadowCasters,
+    stats: &mut RenderStats,
+    index: usize,
     tc: &TransformComponent,
     mc: &mut MeshComponent,
 ) {
     // We never do calculations in world space to evade precision problems:
     // If we're at 15557283 and our target is at 15557284 we'd get tons of triangle jittering,
     // but if we do it in camera space then we're at 0 and our target is at 1, which is fine
-    let w = tc.get_world_transform().to_matrix4();
-    let wv = uniform_data.v * w;
-    let wvp = uniform_data.pv * w;
+    let wv = get_wv_transform(uniform_data, tc.get_world_transform());
+    let wvp = uniform_data.p * wv;
 
     // TODO: This seems very expensive just to support non-uniform normal scaling...
     let mut wv_no_trans = wv.clone();
@@ -230,6 +1089,53 @@ fn draw_one(
     mc.last_ndc_position =
         na::convert::<Vector4<f64>, Vector4<f32>>(wvp * Vector4::new(0.0, 0.0, 0.0, 1.0));
 
+    // Frustum culling against the bounding sphere of the mesh's collider, if it has one.
+    // Meshes without one are always drawn
+    let view_sphere = get_view_bounding_sphere(uniform_data, tc, mc);
+    if let Some((view_center, view_radius)) = view_sphere {
+        if !frustum.intersects_sphere(&view_center, view_radius) {
+            stats.culled_primitives += mc.get_mesh().unwrap().borrow().primitives.len() as u32;
+            return;
+        }
+    }
+
+    // Coarser meshes when we're small on screen. Meshes without a collider always use their full detail
+    let lod = view_sphere
+        .map(|(view_center, view_radius)| {
+            let diameter_px = get_projected_diameter_px(
+                &view_center,
+                view_radius,
+                &uniform_data.p,
+                uniform_data.viewport_size[1],
+            );
+            select_lod(diameter_px, mc.get_num_lods())
+        })
+        .unwrap_or(0);
+
+    // Find the body that may be eclipsing this one, if any
+    let mut occluder_pos_radius: [f32; 4] = [0.0, 0.0, 0.0, 0.0];
+    let mut light_radius: f32 = 0.0;
+    if let (Some((light_pos, light_rad)), Some((view_center, view_radius))) =
+        (shadows.light, view_sphere)
+    {
+        light_radius = light_rad as f32;
+        if let Some(occluder) = find_occluder(
+            index,
+            &view_center,
+            view_radius,
+            &light_pos,
+            light_rad,
+            &shadows.occluders,
+        ) {
+            occluder_pos_radius = [
+                occluder.center.x as f32,
+                occluder.center.y as f32,
+                occluder.center.z as f32,
+                occluder.radius as f32,
+            ];
+        }
+    }
+
     let wv_inv_trans = wv_no_trans.try_inverse().unwrap().transpose(); // Note: This is correct, it's not meant to be v * w.inv().trans()
 
     let wv_arr: [f32; 16] = na::convert::<Matrix4<f64>, Matrix4<f32>>(wv)
@@ -245,7 +1151,7 @@ fn draw_one(
         .try_into()
         .unwrap();
 
-    if let Some(mesh) = mc.get_mesh() {
+    if let Some(mesh) = mc.get_lod_mesh(lod) {
         for (primitive_index, primitive) in mesh.borrow().primitives.iter().enumerate() {
             let resolved_mat = mc.get_resolved_material(primitive_index);
 
@@ -276,6 +1182,39 @@ fn draw_one(
                     UniformValue::Float(uniform_data.f_coef),
                 );
 
+                mat_mut.set_uniform_value(
+                    UniformName::Tonemap,
+                    UniformValue::Int(uniform_data.tonemap),
+                );
+
+                mat_mut.set_uniform_value(
+                    UniformName::LineWidth,
+                    UniformValue::Float(uniform_data.line_width),
+                );
+
+                mat_mut.set_uniform_value(
+                    UniformName::ViewportSize,
+                    UniformValue::Vec2(uniform_data.viewport_size),
+                );
+
+                mat_mut.set_uniform_value(
+                    UniformName::OccluderPosRadius,
+                    UniformValue::Vec4(occluder_pos_radius),
+                );
+
+                mat_mut
+                    .set_uniform_value(UniformName::LightRadius, UniformValue::Float(light_radius));
+
+                mat_mut.set_environment_texture(uniform_data.environment.as_ref());
+                mat_mut.set_uniform_value(
+                    UniformName::EnvironmentTrans,
+                    UniformValue::Matrix(uniform_data.environment_trans),
+                );
+                mat_mut.set_uniform_value(
+                    UniformName::EnvironmentMaxLod,
+                    UniformValue::Float(uniform_data.environment_max_lod),
+                );
+
                 if uniform_data.light_types.len() > 0 {
                     mat_mut.set_uniform_value(
                         UniformName::LightTypes,
@@ -298,11 +1237,25 @@ fn draw_one(
                     );
                 }
 
+                // The material is shared with the body's other primitives and possibly other bodies, so we put its
+                // own color back once it's bound
+                let base_color = mc.color_override.and_then(|color| {
+                    let base_color = mat_mut.get_uniform_value(UniformName::BaseColorFactor);
+                    mat_mut
+                        .set_uniform_value(UniformName::BaseColorFactor, UniformValue::Vec4(color));
+                    return base_color;
+                });
+
                 // info!("Drawing mesh {} with material {}", mesh.name, mat_mut.name);
                 mat_mut.bind_for_drawing(gl);
+
+                if let Some(base_color) = base_color {
+                    mat_mut.set_uniform_value(UniformName::BaseColorFactor, base_color);
+                }
             }
 
             primitive.draw(gl);
+            stats.drawn_primitives += 1;
 
             if let Some(mat) = &resolved_mat {
                 mat.borrow().unbind_from_drawing(gl);
@@ -312,7 +1265,7 @@ fn draw_one(
 }
 
 fn draw_points(
-    _state: &AppState,
+    state: &AppState,
     gl: &glow::Context,
     uniform_data: &mut FrameUniformValues,
     scene: &mut Scene,
@@ -325,9 +1278,15 @@ fn draw_points(
     if let Some(prim) = &mut pts.dynamic_primitive {
         let num_bodies = scene.mesh.get_num_components() as usize;
 
-        // Update color buffer only when entity number changes (expensive)
-        if prim.get_num_elements() != num_bodies {
+        // Update color buffer only when entity number, color mode or marker sizes change (expensive)
+        let size_range = (state.point_min_size_px, state.point_max_size_px);
+        if prim.get_num_elements() != num_bodies
+            || scene.points_color_mode != Some(state.point_color_mode)
+            || scene.points_size_range != Some(size_range)
+        {
             prim.set_num_elements(num_bodies);
+            scene.points_color_mode = Some(state.point_color_mode);
+            scene.points_size_range = Some(size_range);
 
             let buf = prim.get_color_buffer_mut();
             for (ent, metadata) in scene.metadata.iter() {
@@ -341,45 +1300,27 @@ fn draw_points(
                     continue;
                 }
 
-                let mut color: [f32; 4] = match metadata
-                    .get_metadata("body_type")
-                    .unwrap_or(&String::from(""))
-                    .as_str()
-                {
-                    "Satellite" => [0.8, 0.8, 0.8, 1.5],
-                    "Asteroid" => [1.0, 1.0, 1.0, 1.0],
-                    "Star" => [1.0, 0.5, 0.0, 2.5],
-                    "Planet" => [0.6, 0.8, 0.6, 2.0],
-                    "Comet" => [0.4, 0.6, 0.8, 1.0],
-                    "Artificial" => [1.0, 1.0, 1.0, 1.0],
-                    "Barycenter" => [0.5, 0.5, 0.5, 0.5],
-                    _ => [1.0, 1.0, 1.0, 1.0],
-                };
-
-                if let Some(base_color) = metadata.get_metadata("base_color") {
-                    let mut bytes: Vec<f32> = decode_hex(base_color)
-                        .unwrap()
-                        .iter()
-                        .map(|u| *u as f32 / 255.0)
-                        .collect();
-                    bytes.resize(4, 1.0);
-
-                    color[0] = bytes[0];
-                    color[1] = bytes[1];
-                    color[2] = bytes[2];
+                let mut color = get_point_color(state.point_color_mode, metadata);
+                if state.point_color_mode == PointColorMode::Default {
+                    if let Some(over) = scene
+                        .get_body_id_from_entity(*ent)
+                        .and_then(|id| state.body_colors.get(id))
+                    {
+                        color[..3].copy_from_slice(&over[..3]);
+                    }
                 }
 
                 buf[ent_index * 4 + 0] = color[0];
                 buf[ent_index * 4 + 1] = color[1];
                 buf[ent_index * 4 + 2] = color[2];
-                buf[ent_index * 4 + 3] = color[3];
+                buf[ent_index * 4 + 3] = get_point_size(metadata, color[3], size_range);
             }
         }
 
         // Update pos buffer every frame
         let buf = prim.get_pos_buffer_mut();
         for (ent_index, mesh_comp) in scene.mesh.iter().enumerate() {
-            if mesh_comp.get_enabled() {
+            if mesh_comp.get_enabled() && mesh_comp.visible {
                 buf[ent_index * 4 + 0] = mesh_comp.last_ndc_position.x;
                 buf[ent_index * 4 + 1] = mesh_comp.last_ndc_position.y;
                 buf[ent_index * 4 + 2] = mesh_comp.last_ndc_position.z;
@@ -398,6 +1339,14 @@ fn draw_points(
 
         scene_mat_mut
             .set_uniform_value(UniformName::Fcoef, UniformValue::Float(uniform_data.f_coef));
+        scene_mat_mut.set_uniform_value(
+            UniformName::Tonemap,
+            UniformValue::Int(uniform_data.tonemap),
+        );
+        scene_mat_mut.set_uniform_value(
+            UniformName::PointGlow,
+            UniformValue::Float(state.point_glow as i32 as f32),
+        );
 
         scene_mat_mut.bind_for_drawing(gl);
         prim.upload_buffers(gl);
@@ -406,22 +1355,179 @@ fn draw_points(
     }
 }
 
-fn draw_skybox(
+/// Draws the stars of the star catalog (or procedurally generated ones if we don't have it) on a sphere around the
+/// camera, behind everything else. Skipped when we're already drawing a skybox
+fn draw_starfield(
     state: &AppState,
     gl: &glow::Context,
-    uniform_data: &mut FrameUniformValues,
+    uniform_data: &FrameUniformValues,
     scene: &mut Scene,
 ) {
-    if scene.skybox_mesh.is_none() || scene.skybox_trans.is_none() {
+    if scene.starfield_mesh.is_none() || scene.starfield_mat.is_none() {
         return;
     }
 
+    if state.use_skyboxes && scene.skybox_mesh.is_some() {
+        return;
+    }
+
+    let source = match &scene.star_catalog {
+        Some(_) if state.use_star_catalog => {
+            let days_since_j2000 = scene.epoch.0 + state.sim_time_s / 86400.0 - J2000_JDN.0;
+            StarfieldSource::Catalog {
+                mag_limit: state.star_catalog_mag_limit,
+                years_since_j2000: (days_since_j2000 / 365.25).floor() as i32,
+            }
+        }
+        _ if state.starfield_num_stars > 0 => StarfieldSource::Procedural {
+            num_stars: state.starfield_num_stars,
+            seed: state.starfield_seed,
+        },
+        _ => return,
+    };
+
+    let mut mesh = RefCell::borrow_mut(scene.starfield_mesh.as_ref().unwrap());
+    if let Some(prim) = &mut mesh.dynamic_primitive {
+        // Only regenerate stars when the settings change
+        if scene.starfield_source != Some(source) {
+            scene.starfield_stars = match source {
+                StarfieldSource::Procedural { num_stars, seed } => {
+                    generate_starfield(num_stars, seed)
+                }
+                StarfieldSource::Catalog {
+                    mag_limit,
+                    years_since_j2000,
+                } => get_catalog_stars(
+                    scene.star_catalog.as_ref().unwrap(),
+                    mag_limit,
+                    years_since_j2000 as f64,
+                ),
+            };
+            scene.starfield_source = Some(source);
+
+            prim.set_num_elements(scene.starfield_stars.len());
+            let buf = prim.get_color_buffer_mut();
+            for (index, star) in scene.starfield_stars.iter().enumerate() {
+                buf[index * 4..index * 4 + 4].copy_from_slice(&star.color);
+            }
+        }
+
+        // Stars are infinitely far away, so they only depend on the camera orientation
+        let radius = state.camera.far * STARFIELD_RADIUS_FRACTION;
+        let pv_rel = uniform_data.p * uniform_data.v_rel;
+        let buf = prim.get_pos_buffer_mut();
+        for (index, star) in scene.starfield_stars.iter().enumerate() {
+            let pos = star.dir * radius;
+            let clip = na::convert::<Vector4<f64>, Vector4<f32>>(
+                pv_rel * Vector4::new(pos.x, pos.y, pos.z, 1.0),
+            );
+
+            buf[index * 4 + 0] = clip.x;
+            buf[index * 4 + 1] = clip.y;
+            buf[index * 4 + 2] = clip.z;
+            buf[index * 4 + 3] = clip.w;
+        }
+
+        let mut mat_mut = scene.starfield_mat.as_ref().unwrap().borrow_mut();
+        mat_mut.set_uniform_value(UniformName::Fcoef, UniformValue::Float(uniform_data.f_coef));
+        mat_mut.set_uniform_value(
+            UniformName::Tonemap,
+            UniformValue::Int(uniform_data.tonemap),
+        );
+
+        // Don't write depth so that everything else is drawn over the stars
+        unsafe {
+            gl.depth_mask(false);
+        }
+
+        mat_mut.bind_for_drawing(gl);
+        prim.upload_buffers(gl);
+        prim.draw(gl);
+        mat_mut.unbind_from_drawing(gl);
+
+        unsafe {
+            gl.depth_mask(true);
+        }
+    }
+}
+
+/// Transforms camera space directions into the directions to sample the skybox cubemap with, so that reflections show
+/// the same stars that the skybox draws behind them
+fn get_environment_transform(skybox_trans: &Matrix4<f64>, v_inv: &Matrix4<f64>) -> Matrix4<f64> {
     // Remove translation or else we can get precision issues on large coordinates
-    let mut v_no_trans = state.camera.v_inv.clone();
+    let mut v_no_trans = v_inv.clone();
     v_no_trans.set_column(3, &Vector4::new(0.0, 0.0, 0.0, 1.0));
 
+    return skybox_trans * v_no_trans;
+}
+
+/// Draws `mesh` (the axes) straight onto the canvas in a small viewport in its bottom left corner, turned like the world
+/// is seen from the camera. Goes after the blit, so that it's on top of everything without touching the depth buffer of
+/// the main pass
+fn draw_axis_gizmo(state: &AppState, gl: &glow::Context, mesh: Option<&Rc<RefCell<Mesh>>>) {
+    if !state.show_axis_gizmo || state.spectator || mesh.is_none() {
+        return;
+    }
+
+    let size_px = (AXIS_GIZMO_SIZE * state.pixels_per_point) as i32;
+    let margin_px = (AXIS_GIZMO_MARGIN * state.pixels_per_point) as i32;
+    let wvp_arr: [f32; 16] =
+        na::convert::<Matrix4<f64>, Matrix4<f32>>(state.camera.get_axis_gizmo_transform())
+            .as_slice()
+            .try_into()
+            .unwrap();
+
+    unsafe {
+        gl.viewport(margin_px, margin_px, size_px, size_px);
+        gl.disable(GL::DEPTH_TEST);
+    }
+
+    for primitive in mesh.unwrap().borrow().primitives.iter() {
+        let mat = match &primitive.default_material {
+            Some(mat) => mat,
+            None => continue,
+        };
+        let mut mat_mut = mat.borrow_mut();
+
+        if mat_mut.get_compatible_prim_hash() != primitive.compatible_hash {
+            mat_mut.set_prim_defines(primitive);
+        }
+        mat_mut.set_uniform_value(UniformName::WVPTrans, UniformValue::Matrix(wvp_arr));
+        // Puts w = 1 (as the projection is orthographic) at depth 0, though we're not depth testing anyway
+        mat_mut.set_uniform_value(UniformName::Fcoef, UniformValue::Float(1.0));
+        mat_mut.set_uniform_value(UniformName::Tonemap, UniformValue::Int(CANVAS_TONEMAP));
+        mat_mut.set_uniform_value(
+            UniformName::LineWidth,
+            UniformValue::Float(state.line_width.max(2.0)),
+        );
+        mat_mut.set_uniform_value(
+            UniformName::ViewportSize,
+            UniformValue::Vec2([size_px as f32, size_px as f32]),
+        );
+
+        mat_mut.bind_for_drawing(gl);
+        primitive.draw(gl);
+        mat_mut.unbind_from_drawing(gl);
+    }
+
+    unsafe {
+        gl.viewport(0, 0, state.canvas_width as i32, state.canvas_height as i32);
+    }
+}
+
+fn draw_skybox(
+    state: &AppState,
+    gl: &glow::Context,
+    uniform_data: &mut FrameUniformValues,
+    scene: &mut Scene,
+) {
+    if scene.skybox_mesh.is_none() || scene.skybox_trans.is_none() {
+        return;
+    }
+
     let vp_inv_arr: [f32; 16] = na::convert::<Matrix4<f64>, Matrix4<f32>>(
-        scene.skybox_trans.unwrap() * v_no_trans * state.camera.p_inv,
+        get_environment_transform(scene.skybox_trans.as_ref().unwrap(), &state.camera.v_inv)
+            * state.camera.p_inv,
     )
     .as_slice()
     .try_into()
@@ -465,3 +1571,211 @@ fn draw_skybox(
         gl.depth_func(old_depth_func);
     }
 }
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    pub fn hidden_entities_are_not_drawn() {
+        let transforms = vec![TransformComponent::default(); 4];
+        let mut meshes = vec![MeshComponent::default(); 4];
+        meshes[1].visible = false;
+        meshes[3].visible = false;
+
+        let drawn: Vec<usize> = iter_drawn_meshes(transforms.iter(), meshes.iter_mut())
+            .map(|(index, _)| index)
+            .collect();
+        assert_eq!(drawn, vec![0, 2]);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn reflections_sample_the_skybox_directions() {
+        let skybox_trans =
+            Rotation3::<f64>::from_axis_angle(&Vector3::x_axis(), 0.4).to_homogeneous();
+        let v = Isometry3::look_at_rh(
+            &Point3::new(1E6, -2E6, 3E5),
+            &Point3::new(1E6 + 1.0, -2E6 + 2.0, 3E5 - 0.5),
+            &Vector3::z(),
+        )
+        .to_homogeneous();
+        let v_inv = v.try_inverse().unwrap();
+        let p = Perspective3::new(1.5, 1.2, 0.01, 1E10).to_homogeneous();
+        let env_trans = get_environment_transform(&skybox_trans, &v_inv);
+
+        // Where the skybox samples at the center of the screen (see skybox.frag), and what a mirror facing the
+        // camera would reflect back from there
+        let skybox_dir = env_trans * p.try_inverse().unwrap() * Vector4::new(0.0, 0.0, 1.0, 1.0);
+        let skybox_dir = skybox_dir.xyz().normalize();
+        let forward_dir = (env_trans * Vector4::new(0.0, 0.0, -1.0, 0.0)).xyz();
+        assert!((skybox_dir - forward_dir).magnitude() < 1E-6);
+
+        // Same as rotating the world space view direction into the skybox's frame
+        let world_dir = Vector3::new(1.0, 2.0, -0.5).normalize();
+        assert!((forward_dir - skybox_trans.transform_vector(&world_dir)).magnitude() < 1E-6);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn frame_uniforms_carry_the_scene_lights() {
+        use crate::components::LightComponent;
+
+        let mut state = AppState::new();
+        state.light_intensity_scale = 2.0;
+        state.camera.world_pos = Vector3::new(0.0, 0.0, 10.0);
+        state.camera.v_rel =
+            Rotation3::from_axis_angle(&Vector3::z_axis(), std::f64::consts::FRAC_PI_2)
+                .to_homogeneous();
+
+        // A red and a blue star like in the binary star scene, and more lights than we have room for
+        let mut scene = Scene::new("lights");
+        let mut add_light = |x: f64, color: Vector3<f32>| {
+            let builder = scene.start_new_entity("Light");
+            let entity = scene.finish_new_entity(builder);
+            scene
+                .add_component::<TransformComponent>(entity)
+                .get_world_transform_mut()
+                .trans = Vector3::new(x, 0.0, 0.0);
+            let light = scene.add_component::<LightComponent>(entity);
+            light.color = color;
+            light.intensity = 1E27;
+        };
+        add_light(-80000.0, Vector3::new(1.0, 0.14, 0.05));
+        add_light(80000.0, Vector3::new(0.21, 0.38, 1.0));
+        for _ in 0..NUM_LIGHTS {
+            add_light(0.0, Vector3::zeros());
+        }
+
+        let values = get_frame_uniform_values(&state, &scene);
+        assert_eq!(
+            values.light_types,
+            vec![LightType::Point as i32; NUM_LIGHTS]
+        );
+        assert_eq!(values.light_colors.len(), NUM_LIGHTS * 3);
+        assert_eq!(values.light_pos_or_dir_c.len(), NUM_LIGHTS * 3);
+        assert_eq!(values.light_intensities, vec![2E27; NUM_LIGHTS]);
+
+        // Each with its own color, and relative to the camera in camera space
+        assert_eq!(values.light_colors[..6], [1.0, 0.14, 0.05, 0.21, 0.38, 1.0]);
+        let get_pos = |index: usize| {
+            Vector3::from_column_slice(&values.light_pos_or_dir_c[index * 3..index * 3 + 3])
+        };
+        assert!((get_pos(0) - Vector3::new(0.0, -80000.0, -10.0)).magnitude() < 1E-2);
+        assert!((get_pos(1) - Vector3::new(0.0, 80000.0, -10.0)).magnitude() < 1E-2);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn lod_gets_coarser_with_distance() {
+        let p = Perspective3::new(1.5, 1.0, 0.01, 1E10).to_homogeneous();
+        let num_lods = 4;
+
+        let mut last_lod = 0;
+        let mut lods = Vec::new();
+        for dist in &[1.5, 3.0, 10.0, 20.0, 30.0, 100.0, 300.0, 1000.0, 1E5] {
+            let diameter =
+                get_projected_diameter_px(&Point3::new(0.0, 0.0, -dist), 1.0, &p, 1000.0);
+            let lod = select_lod(diameter, num_lods);
+            assert!(
+                lod >= last_lod,
+                "LOD {} at {} after LOD {}",
+                lod,
+                dist,
+                last_lod
+            );
+            last_lod = lod;
+            lods.push(lod);
+        }
+        assert_eq!(lods[0], 0);
+        assert_eq!(*lods.last().unwrap(), num_lods - 1);
+        assert!(lods.contains(&1) && lods.contains(&2));
+
+        // Full detail from inside, and for meshes that only have the one
+        assert_eq!(
+            get_projected_diameter_px(&Point3::new(0.0, 0.0, -0.5), 1.0, &p, 1000.0),
+            std::f64::INFINITY
+        );
+        assert_eq!(select_lod(1.0, 1), 0);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn hovered_and_selected_bodies_are_outlined() {
+        use crate::engine::Engine;
+        use crate::managers::orbit::{BodyDescription, BodyInstanceDescription};
+        use crate::managers::scene::SceneBuilder;
+
+        let mut engine = Engine::new_headless();
+        let mut state = AppState::new();
+
+        let new_body = |id: &str| BodyDescription::new_test_body(id, id, 6.0);
+        let mut scene = {
+            let mut builder = SceneBuilder::new("outlines", &mut engine.res_man);
+            builder.add_body_instance(Some(&new_body("399")), &BodyInstanceDescription::default());
+            builder.add_body_instance(Some(&new_body("301")), &BodyInstanceDescription::default());
+            builder.build()
+        };
+        let planet = scene.get_entity_from_body_id("399").unwrap();
+        let moon = scene.get_entity_from_body_id("301").unwrap();
+        scene.set_entity_parent(planet, moon);
+
+        // Each body holds its mesh in a child entity
+        let get_mesh_holder = |scene: &Scene, body: Entity| -> Entity {
+            return *scene
+                .get_entity_children(body)
+                .unwrap()
+                .iter()
+                .find(|child| scene.get_component::<MeshComponent>(**child).is_some())
+                .unwrap();
+        };
+        let sphere = get_mesh_holder(&scene, planet);
+        let moon_sphere = get_mesh_holder(&scene, moon);
+
+        // And an atmosphere shell that can't be picked
+        let builder = scene.start_new_entity("Atmosphere");
+        let atmo = scene.finish_new_entity(builder);
+        scene.add_component::<TransformComponent>(atmo);
+        scene
+            .add_component::<MeshComponent>(atmo)
+            .raycasting_visible = false;
+        scene.set_entity_parent(planet, atmo);
+
+        // Just the sphere, not the atmosphere nor the moon
+        state.hovered = Some(planet);
+        assert_eq!(
+            get_outlined_meshes(&state, &scene),
+            vec![(sphere, HOVER_OUTLINE_COLOR)]
+        );
+
+        state.hovered = Some(moon);
+        state.selection = Some(planet);
+        assert_eq!(
+            get_outlined_meshes(&state, &scene),
+            vec![
+                (moon_sphere, HOVER_OUTLINE_COLOR),
+                (sphere, SELECTION_OUTLINE_COLOR)
+            ]
+        );
+
+        // Selection wins when it's both
+        state.hovered = Some(planet);
+        assert_eq!(
+            get_outlined_meshes(&state, &scene),
+            vec![(sphere, SELECTION_OUTLINE_COLOR)]
+        );
+
+        // Hidden meshes don't get outlines
+        scene
+            .get_component_mut::<MeshComponent>(sphere)
+            .unwrap()
+            .visible = false;
+        assert!(get_outlined_meshes(&state, &scene).is_empty());
+        scene
+            .get_component_mut::<MeshComponent>(sphere)
+            .unwrap()
+            .visible = true;
+
+        state.show_outlines = false;
+        assert!(get_outlined_meshes(&state, &scene).is_empty());
+    }
+}
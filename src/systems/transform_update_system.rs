@@ -1,49 +1,345 @@
-use crate::app_state::{AppState, ReferenceChange};
-use crate::components::{MetadataComponent, TransformComponent};
-use crate::managers::scene::Scene;
+use crate::app_state::{AppState, FollowState, ReferenceChange};
+use crate::components::{
+    KinematicComponent, MeshComponent, MetadataComponent, RigidBodyComponent, TransformComponent,
+};
+use crate::managers::resource::material::{UniformName, UniformValue};
+use crate::managers::scene::{Entity, Scene};
+use crate::systems::compute_barycenter;
+use crate::utils::camera::get_auto_depth_range;
+use crate::utils::grid::{get_grid_spacing, snap_to_spacing, GRID_ALPHA, GRID_CELLS};
 use crate::utils::log::*;
+use crate::utils::orbits::{get_apparent_orbit_transform, get_coordinate_frame_rotation};
 use na::*;
+use std::collections::HashMap;
+
+/// How many central bodies we go through when offsetting a satellite for visual_orbit_scale, e.g. moon -> planet
+const MAX_VISUAL_ORBIT_CHAIN_DEPTH: u32 = 8;
 
 pub struct TransformUpdateSystem {}
 impl TransformUpdateSystem {
     pub fn run(&self, state: &mut AppState, scene: &mut Scene) {
+        update_frame_offset(state, scene);
+
         concatenate_parent_transforms(scene);
 
         update_reference_translation(state, scene);
 
         handle_reference_changes(state, scene);
 
+        update_follow(state, scene);
+
         handle_go_to(state, scene);
 
+        update_apparent_orbit(state, scene);
+
+        update_auto_depth_range(state, scene);
+
         // The systems may have updated the reference body's position, so refresh our camera transforms
         state.camera.update_transforms(
             state.canvas_width as f64 / state.canvas_height as f64,
             state.reference_translation,
         );
+
+        update_grid(state, scene);
+
+        update_axes(state, scene);
+
+        update_billboards(state, scene);
     }
 }
 
 fn concatenate_parent_transforms(scene: &mut Scene) {
+    update_visual_orbit_offsets(scene);
+
+    let render_scale = scene.render_scale;
+    let mut moved_bounds = false;
     for entity_index in 0..scene.get_num_entities() {
+        let offset = scene
+            .get_entity_from_index(entity_index)
+            .and_then(|entity| scene.visual_orbit_offsets.get(&entity).cloned());
+
         let parent_trans = scene
             .get_parent_index_from_index(entity_index)
             .and_then(|parent_index| scene.transform.get_component_from_index(parent_index))
             .and_then(|parent_trans| Some(parent_trans.get_world_transform().clone()));
 
+        // Only meshes with colliders count towards the world bounds. The others (orbit lines, the grid, etc.) get
+        // their world transforms tweaked again after this every frame anyway
+        let has_bounds = has_world_bounds(scene.mesh.get_component_from_index(entity_index));
+
         if let Some(ent_trans) = scene.transform.get_component_from_index_mut(entity_index) {
+            let old_world = ent_trans.get_world_transform().clone();
+
             match parent_trans {
                 Some(mut trans) => {
                     trans.concat(ent_trans.get_local_transform());
                     *ent_trans.get_world_transform_mut() = trans;
                 }
                 None => {
-                    *ent_trans.get_world_transform_mut() = ent_trans.get_local_transform().clone()
+                    *ent_trans.get_world_transform_mut() = ent_trans.get_local_transform().clone();
+                    if let Some(frame_offset) = scene.frame_offset {
+                        ent_trans.get_world_transform_mut().trans -= frame_offset;
+                    }
                 }
             };
+
+            // Children just follow along through their parent's world transform
+            if let Some(offset) = offset {
+                ent_trans.get_world_transform_mut().trans += offset;
+            }
+
+            if ent_trans.is_render_scaled() {
+                ent_trans.get_world_transform_mut().scale *= render_scale;
+            }
+
+            moved_bounds |= has_bounds && *ent_trans.get_world_transform() != old_world;
+        }
+    }
+
+    scale_visual_orbit_lines(scene);
+
+    // Everything else only moves local transforms, so this is where the world bounds can go stale
+    if moved_bounds {
+        scene.invalidate_world_bounds();
+    }
+}
+
+/// Whether an entity with `mesh_comp` is part of Scene::get_world_bounds, meaning that moving it invalidates them
+fn has_world_bounds(mesh_comp: Option<&MeshComponent>) -> bool {
+    return mesh_comp
+        .and_then(|mesh_comp| mesh_comp.get_mesh())
+        .map_or(false, |mesh| mesh.borrow().collider.is_some());
+}
+
+/// Finds the mass-weighted center of every rigid body for state.center_of_mass_frame, which concatenate_parent_transforms
+/// then moves to the origin. Only world transforms are affected, so physics carries on in its own frame.
+///
+/// While we're not focusing anything the camera is in world space, so it's moved along when the frame gets toggled in
+/// order to keep looking at the same thing
+fn update_frame_offset(state: &mut AppState, scene: &mut Scene) {
+    let old_offset = scene.frame_offset;
+
+    scene.frame_offset = None;
+    if state.center_of_mass_frame {
+        let mut bodies: Vec<(Vector3<f64>, f64)> = Vec::new();
+        for (entity, rb) in scene.rigidbody.ent_iter() {
+            if scene.get_entity_parent(*entity).is_some() {
+                continue;
+            }
+
+            if let Some(trans) = scene.get_component::<TransformComponent>(*entity) {
+                bodies.push((trans.get_local_transform().trans, rb.mass));
+            }
+        }
+        scene.frame_offset = compute_barycenter(&bodies);
+    }
+
+    if state.reference_entity.is_none() && old_offset.is_some() != scene.frame_offset.is_some() {
+        let delta = old_offset.unwrap_or_default() - scene.frame_offset.unwrap_or_default();
+        state.camera.pos += delta;
+        state.camera.target += delta;
+    }
+}
+
+/// Computes how far to move the world transforms of satellites so that they're drawn scene.visual_orbit_scale times
+/// as far from the bodies they orbit. Only bodies orbiting something other than a star are moved, so that moons
+/// spread out while the planets stay where they are. Local transforms are left alone, so physics is unaffected.
+///
+/// Like for barycenter members, satellites and their central bodies are expected to not be parented to other entities
+fn update_visual_orbit_offsets(scene: &mut Scene) {
+    scene.visual_orbit_offsets.clear();
+    if scene.visual_orbit_scale == 1.0 {
+        return;
+    }
+
+    let mut satellites: HashMap<Entity, Entity> = HashMap::new();
+    for entity in scene.get_body_id_entities().values() {
+        let central = scene
+            .get_component::<MetadataComponent>(*entity)
+            .and_then(|meta| meta.get_metadata("body_central_id"))
+            .and_then(|id| scene.get_entity_from_body_id(id));
+        if central.is_none() {
+            continue;
+        }
+        let central = central.unwrap();
+
+        let central_is_star = scene
+            .get_component::<MetadataComponent>(central)
+            .and_then(|meta| meta.get_metadata("body_type"))
+            .map(|t| t.as_str())
+            == Some("Star");
+        if central_is_star
+            || scene.get_entity_parent(*entity).is_some()
+            || scene.get_entity_parent(central).is_some()
+        {
+            continue;
+        }
+
+        satellites.insert(*entity, central);
+    }
+
+    let get_pos = |entity: Entity| {
+        scene
+            .get_component::<TransformComponent>(entity)
+            .map(|trans| trans.get_local_transform().trans)
+    };
+
+    // Central bodies may be satellites themselves, so we add up the offsets of the whole chain
+    let mut offsets: HashMap<Entity, Vector3<f64>> = HashMap::new();
+    for body in satellites.keys() {
+        let mut offset = Vector3::zeros();
+        let mut current = *body;
+        for _ in 0..MAX_VISUAL_ORBIT_CHAIN_DEPTH {
+            let central = match satellites.get(&current) {
+                Some(central) => *central,
+                None => break,
+            };
+
+            if let (Some(pos), Some(central_pos)) = (get_pos(current), get_pos(central)) {
+                offset += (pos - central_pos) * (scene.visual_orbit_scale - 1.0);
+            }
+            current = central;
+        }
+
+        offsets.insert(*body, offset);
+    }
+
+    scene.visual_orbit_offsets = offsets;
+}
+
+/// Scales the orbit lines of the satellites moved by update_visual_orbit_offsets around their central bodies, so
+/// that they still go through where the satellites are drawn
+fn scale_visual_orbit_lines(scene: &mut Scene) {
+    if scene.visual_orbit_offsets.is_empty() {
+        return;
+    }
+
+    let scale = scene.visual_orbit_scale;
+    for index in 0..scene.orbits.len() {
+        let line = scene.orbits[index].line;
+        if !scene
+            .visual_orbit_offsets
+            .contains_key(&scene.orbits[index].body)
+        {
+            continue;
+        }
+
+        // Orbit lines are parented to the body they go around
+        let center = scene
+            .get_entity_parent(line)
+            .and_then(|central| scene.get_component::<TransformComponent>(central))
+            .map(|trans| trans.get_world_transform().trans);
+
+        // Straight through the storage, as orbit lines don't count towards the world bounds
+        if let (Some(center), Some(trans)) = (center, scene.transform.get_component_mut(line)) {
+            let world = trans.get_world_transform_mut();
+            world.trans = center + (world.trans - center) * scale;
+            world.scale *= scale;
         }
     }
 }
 
+/// When drawing orbits in the frame of the reference body, mirrors the orbit line of the reference body so that it
+/// shows the apparent orbit of its central body around it instead (e.g. the Sun around the Earth). Orbits around the
+/// reference body already go around it, as orbit lines are parented to the bodies they orbit
+fn update_apparent_orbit(state: &AppState, scene: &mut Scene) {
+    scene.apparent_orbit_line = None;
+    if !state.orbits_relative_to_reference || state.reference_entity.is_none() {
+        return;
+    }
+    let reference = state.reference_entity.unwrap();
+
+    let line = match scene.orbits.iter().find(|orbit| orbit.body == reference) {
+        Some(orbit) => orbit.line,
+        None => return,
+    };
+    let get_pos = |entity: Entity| {
+        scene
+            .get_component::<TransformComponent>(entity)
+            .map(|trans| trans.get_world_transform().trans)
+    };
+    let (body_pos, central_pos) = match (
+        get_pos(reference),
+        scene.get_entity_parent(line).and_then(get_pos),
+    ) {
+        (Some(body_pos), Some(central_pos)) => (body_pos, central_pos),
+        _ => return,
+    };
+
+    if let Some(trans) = scene.transform.get_component_mut(line) {
+        let world = trans.get_world_transform_mut();
+        *world = get_apparent_orbit_transform(world, &body_pos, &central_pos);
+        scene.apparent_orbit_line = Some(line);
+    }
+}
+
+/// Fits the camera's near and far planes to the world bounding spheres of the scene, if we want that.
+/// Meshes without colliders (orbit lines, the grid, etc.) aren't part of those. They're unit sized, so their world
+/// scale bounds them instead. Those boxes usually contain the camera though (e.g. the orbit of the body we're looking
+/// at), so they only push the far plane out
+fn update_auto_depth_range(state: &mut AppState, scene: &Scene) {
+    if !state.auto_depth_range {
+        return;
+    }
+
+    let camera_pos = state.camera.pos.coords + state.reference_translation.unwrap_or_default();
+
+    let spheres: Vec<(Vector3<f64>, f64)> = scene
+        .get_world_bounding_spheres()
+        .iter()
+        .map(|(center, radius)| (center.coords - camera_pos, *radius))
+        .collect();
+
+    let mut line_bounds: Option<(Vector3<f64>, Vector3<f64>)> = None;
+    for (trans_comp, mesh_comp) in scene.transform.iter().zip(scene.mesh.iter()) {
+        let mesh = match mesh_comp.get_mesh() {
+            Some(mesh) if mesh_comp.visible => mesh,
+            _ => continue,
+        };
+        let has_sphere = mesh
+            .borrow()
+            .collider
+            .as_ref()
+            .and_then(|c| c.bounding_sphere())
+            .is_some();
+        if has_sphere {
+            continue;
+        }
+
+        let trans = trans_comp.get_world_transform();
+        let max_scale = trans.scale.abs().max();
+        let center = trans.trans - camera_pos;
+        line_bounds = Some(merge_bounds(
+            line_bounds,
+            (center.add_scalar(-max_scale), center.add_scalar(max_scale)),
+        ));
+    }
+
+    // The far plane has to reach the farthest corner of the scene too
+    let mut bounds = scene
+        .get_world_bounds()
+        .map(|(mins, maxes)| (mins.coords - camera_pos, maxes.coords - camera_pos));
+    if let Some(line_bounds) = line_bounds {
+        bounds = Some(merge_bounds(bounds, line_bounds));
+    }
+
+    if let Some((near, far)) = get_auto_depth_range(&spheres, bounds) {
+        state.camera.near = near;
+        state.camera.far = far;
+    }
+}
+
+/// Smallest box (mins, maxes) containing both `bounds`, if any, and `other`
+fn merge_bounds(
+    bounds: Option<(Vector3<f64>, Vector3<f64>)>,
+    other: (Vector3<f64>, Vector3<f64>),
+) -> (Vector3<f64>, Vector3<f64>) {
+    return match bounds {
+        Some((mins, maxes)) => (mins.inf(&other.0), maxes.sup(&other.1)),
+        None => other,
+    };
+}
+
 /// Fetch the intended reference entity and store on the state its translation directly.
 /// This so that the camera and other consumers don't have to all poke around the scene to find it
 /// Plus this way it is always up to date and a single consistent value throughout all uses
@@ -90,10 +386,18 @@ fn handle_reference_changes(state: &mut AppState, scene: &mut Scene) {
             *new_entity = None;
         }
     }
+    if let Some(ReferenceChange::Follow(entity)) = new_entity {
+        if state.follow.as_ref().map(|f| f.entity) == Some(*entity) {
+            *new_entity = None;
+        }
+    }
     if new_entity.is_none() {
         return;
     }
 
+    // Any other change stops following, and Follow will start it again below
+    state.follow = None;
+
     let new_entity = new_entity.as_ref().unwrap();
     match new_entity {
         ReferenceChange::FocusKeepLocation(new_entity) => {
@@ -121,6 +425,34 @@ fn handle_reference_changes(state: &mut AppState, scene: &mut Scene) {
         ReferenceChange::FocusKeepCoords(new_entity) => {
             state.reference_entity = Some(*new_entity);
         }
+        ReferenceChange::Follow(new_entity) => {
+            let new_trans = scene
+                .get_component::<TransformComponent>(*new_entity)
+                .unwrap()
+                .get_world_transform()
+                .trans;
+
+            // If we're already focused on it we can keep our camera as is
+            if state.reference_entity != Some(*new_entity) {
+                let old_to_world = match state.reference_translation {
+                    Some(old_trans) => Translation3::from(old_trans).to_homogeneous(),
+                    None => Matrix4::identity(),
+                };
+                let world_to_new = Translation3::from(-new_trans).to_homogeneous();
+                let trans = world_to_new * old_to_world;
+
+                state.camera.pos = trans.transform_point(&state.camera.pos);
+                state.camera.up = Unit::new_normalize(trans.transform_vector(&state.camera.up));
+                state.camera.target = Point3::new(0.0, 0.0, 0.0);
+                state.reference_entity = Some(*new_entity);
+            }
+
+            state.follow = Some(FollowState {
+                entity: *new_entity,
+                last_translation: new_trans,
+                last_velocity: None,
+            });
+        }
         ReferenceChange::Clear => {
             let old_to_world = match state.reference_translation {
                 Some(old_trans) => Translation3::from(old_trans).to_homogeneous(),
@@ -140,6 +472,88 @@ fn handle_reference_changes(state: &mut AppState, scene: &mut Scene) {
     state.next_reference_entity = None;
 }
 
+/// If we're following our reference entity, turns the camera along with the change in direction
+/// of the entity's velocity since last frame, so that e.g. a fast moon keeps its parent planet at
+/// the same spot on screen instead of having it swing around the view.
+///
+/// This function expects that reference_translation is up-to-date for this frame
+fn update_follow(state: &mut AppState, scene: &mut Scene) {
+    if state.follow.is_none() {
+        return;
+    }
+
+    let follow = state.follow.as_mut().unwrap();
+    if state.reference_entity != Some(follow.entity) || state.reference_translation.is_none() {
+        state.follow = None;
+        return;
+    }
+
+    let translation = state.reference_translation.unwrap();
+    let velocity = get_world_velocity(
+        scene,
+        follow.entity,
+        translation - follow.last_translation,
+        state.sim_delta_time_s,
+    );
+    follow.last_translation = translation;
+
+    let velocity = match velocity {
+        Some(vel) if vel.norm_squared() > 0.0 => vel,
+        _ => return,
+    };
+
+    if !state.follow_keeps_velocity_horizontal {
+        follow.last_velocity = Some(velocity);
+        return;
+    }
+
+    // The camera is wrt. the followed entity, so rotating around the origin rotates around it
+    if let Some(last_velocity) = follow.last_velocity {
+        if let Some(rot) = UnitQuaternion::rotation_between(&last_velocity, &velocity) {
+            state.camera.pos = rot * state.camera.pos;
+            state.camera.target = rot * state.camera.target;
+            state.camera.up = rot * state.camera.up;
+        }
+    }
+    follow.last_velocity = Some(velocity);
+
+    // Level the horizon with the velocity, which also keeps error from accumulating over the rotations.
+    // Can't do it if we're moving straight up though
+    let vel_dir = velocity.normalize();
+    let leveled_up = state.camera.up.as_ref() - vel_dir * state.camera.up.dot(&vel_dir);
+    if leveled_up.norm_squared() > 1E-12 {
+        state.camera.up = Unit::new_normalize(leveled_up);
+    }
+}
+
+/// Returns the world space velocity of `entity` in Mm/s.
+///
+/// Root rigidbodies and kinematic bodies know their velocity at the end of the physics step, which is exactly this
+/// frame's sim time. For anything else we fall back to `displacement` over the last step, which is the average velocity
+/// across it
+fn get_world_velocity(
+    scene: &Scene,
+    entity: Entity,
+    displacement: Vector3<f64>,
+    dt_s: f64,
+) -> Option<Vector3<f64>> {
+    if scene.get_entity_parent(entity).is_none() {
+        if let Some(rb) = scene.get_component::<RigidBodyComponent>(entity) {
+            return Some(rb.lin_mom / rb.mass);
+        }
+
+        if let Some(kin) = scene.get_component::<KinematicComponent>(entity) {
+            return Some(kin.lin_vel);
+        }
+    }
+
+    if dt_s == 0.0 {
+        return None;
+    }
+
+    return Some(displacement / dt_s);
+}
+
 fn handle_go_to(state: &mut AppState, scene: &mut Scene) {
     if state.entity_going_to.is_none() {
         return;
@@ -153,7 +567,9 @@ fn handle_go_to(state: &mut AppState, scene: &mut Scene) {
         .get_component::<TransformComponent>(target_entity)
         .unwrap()
         .get_world_transform();
-    let mut offset = transform.scale.mean() * 2.0;
+
+    // Far enough to see the body whole even if it's drawn larger than it is
+    let mut offset = transform.scale.mean() * 2.0 * scene.render_scale.max(1.0);
 
     if let Some(meta) = scene.get_component::<MetadataComponent>(target_entity) {
         if let Some(val) = meta
@@ -176,3 +592,478 @@ fn handle_go_to(state: &mut AppState, scene: &mut Scene) {
     state.camera.target = Point3::from(target_pos);
     state.entity_going_to = None;
 }
+
+/// Rescales the reference grid so that its spacing is a power of ten suited to how far the camera is from its target,
+/// and moves it under the target along the plane of state.coordinate_frame, snapped to the major lines so that the
+/// lines don't slide around.
+///
+/// This function expects the camera transforms to be up-to-date
+fn update_grid(state: &mut AppState, scene: &mut Scene) {
+    if scene.grid.is_none() {
+        return;
+    }
+
+    let distance = (state.camera.pos - state.camera.target).magnitude();
+    let (spacing, minor_fade) = get_grid_spacing(distance);
+
+    let mut target = state.camera.target.coords;
+    if let Some(reference_trans) = state.reference_translation {
+        target += reference_trans;
+    }
+
+    // Snapped along the grid's own axes
+    let rotation = get_coordinate_frame_rotation(state.coordinate_frame);
+    let local_target = rotation.inverse_transform_vector(&target);
+    let major_spacing = spacing * GRID_CELLS;
+    let center = rotation
+        * Vector3::new(
+            snap_to_spacing(local_target.x, major_spacing),
+            snap_to_spacing(local_target.y, major_spacing),
+            0.0,
+        );
+
+    let grid = scene.grid.as_mut().unwrap();
+    grid.spacing = spacing;
+    grid.minor_fade = minor_fade;
+    grid.center = center;
+    grid.rotation = rotation;
+    let grid = grid.clone();
+
+    for (entity, scale, alpha) in &[
+        (grid.minor, major_spacing, GRID_ALPHA * (1.0 - minor_fade)),
+        (grid.major, major_spacing * GRID_CELLS, GRID_ALPHA),
+    ] {
+        // Like orbit lines, the grid doesn't count towards the world bounds
+        if let Some(trans_comp) = scene.transform.get_component_mut(*entity) {
+            let local = trans_comp.get_local_transform_mut();
+            local.trans = center;
+            local.rot = rotation;
+            local.scale = Vector3::new(*scale, *scale, *scale);
+
+            // We already ran concatenate_parent_transforms this frame, and the grid has no parent
+            *trans_comp.get_world_transform_mut() = trans_comp.get_local_transform().clone();
+        }
+
+        if let Some(mesh_comp) = scene.get_component::<MeshComponent>(*entity) {
+            if let Some(mat) = mesh_comp.get_material_override(0) {
+                mat.borrow_mut().set_uniform_value(
+                    UniformName::BaseColorFactor,
+                    UniformValue::Vec4([1.0, 1.0, 1.0, *alpha]),
+                );
+            }
+        }
+    }
+}
+
+/// Turns the coordinate axes to match state.coordinate_frame
+fn update_axes(state: &AppState, scene: &mut Scene) {
+    let axes = match scene.axes {
+        Some(axes) => axes,
+        None => return,
+    };
+
+    if let Some(trans_comp) = scene.transform.get_component_mut(axes) {
+        trans_comp.get_local_transform_mut().rot =
+            get_coordinate_frame_rotation(state.coordinate_frame);
+
+        // Like the grid, the axes have no parent
+        *trans_comp.get_world_transform_mut() = trans_comp.get_local_transform().clone();
+        trans_comp.set_billboard(state.billboard_axes);
+    }
+}
+
+/// Turns the world transforms of billboards to face the camera. Runs last, after everything else has placed them
+fn update_billboards(state: &AppState, scene: &mut Scene) {
+    let camera_pos = state.camera.world_pos;
+    let camera_up = state.camera.up.into_inner();
+
+    let entities: Vec<Entity> = scene
+        .get_entity_entries()
+        .iter()
+        .filter(|entry| entry.live)
+        .map(|entry| entry.current)
+        .collect();
+
+    let mut turned_bounds = false;
+    for entity in entities {
+        let has_bounds = has_world_bounds(scene.get_component::<MeshComponent>(entity));
+        let trans_comp = match scene.transform.get_component_mut(entity) {
+            Some(trans_comp) if trans_comp.is_billboard() => trans_comp,
+            _ => continue,
+        };
+
+        let world_trans = trans_comp.get_world_transform_mut();
+        let to_camera = camera_pos - world_trans.trans;
+        if to_camera.magnitude_squared() == 0.0 {
+            continue;
+        }
+
+        // Looking straight along our up there's no way to tell which way is up, so keep the old rotation
+        if to_camera.cross(&camera_up).magnitude_squared() > 0.0 {
+            world_trans.rot = UnitQuaternion::face_towards(&to_camera, &camera_up);
+            turned_bounds |= has_bounds;
+        }
+    }
+
+    // Colliders aren't necessarily centered on the billboard, so turning it can move its bounds
+    if turned_bounds {
+        scene.invalidate_world_bounds();
+    }
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+    use crate::engine::Engine;
+    use crate::managers::scene::SceneBuilder;
+    use crate::utils::orbits::GRAVITATION_CONSTANT;
+
+    #[wasm_bindgen_test]
+    pub fn sun_wobbles_around_fixed_barycenter() {
+        let sun_mass = 2E30; // kg
+        let planet_mass = 2E28; // kg, heavy so that the Sun visibly wobbles
+        let radius = 1E5; // Mm
+        let speed = (GRAVITATION_CONSTANT * sun_mass / radius).sqrt();
+        let drift = Vector3::new(0.0, 0.0, 0.02); // Mm/s, that the whole system moves with
+
+        let mut engine = Engine::new_headless();
+        let mut state = AppState::new();
+        let mut scene = Scene::new("binary");
+        let sun = scene.add_test_body("Sun", Vector3::zeros(), sun_mass);
+        let planet = scene.add_test_body("Planet", Vector3::new(radius, 0.0, 0.0), planet_mass);
+        for &(entity, vel, mass) in &[
+            (sun, Vector3::zeros(), sun_mass),
+            (planet, Vector3::new(0.0, speed, 0.0), planet_mass),
+        ] {
+            scene
+                .get_component_mut::<RigidBodyComponent>(entity)
+                .unwrap()
+                .lin_mom = (vel + drift) * mass;
+        }
+        engine.scene_man.set_current_scene(scene, &mut state);
+        state.center_of_mass_frame = true;
+
+        let get_world_pos = |engine: &Engine, entity: Entity| {
+            let scene = engine.scene_man.get_current_scene().unwrap();
+            return scene
+                .get_component::<TransformComponent>(entity)
+                .unwrap()
+                .get_world_transform()
+                .trans;
+        };
+        let get_local_pos = |engine: &Engine, entity: Entity| {
+            let scene = engine.scene_man.get_current_scene().unwrap();
+            return scene
+                .get_component::<TransformComponent>(entity)
+                .unwrap()
+                .get_local_transform()
+                .trans;
+        };
+
+        let period_s = 2.0 * std::f64::consts::PI * radius / speed;
+        let mut sun_positions = Vec::new();
+        for _ in 0..100 {
+            engine.step(&mut state, period_s / 100.0);
+
+            // The barycenter stays at the origin where it's drawn...
+            let sun_pos = get_world_pos(&engine, sun);
+            let planet_pos = get_world_pos(&engine, planet);
+            let center = (sun_pos * sun_mass + planet_pos * planet_mass) / (sun_mass + planet_mass);
+            assert!(
+                center.magnitude() < 1E-6,
+                "Barycenter drawn at {:?}",
+                center
+            );
+            sun_positions.push(sun_pos);
+        }
+
+        // ...while the Sun goes around it, and physics still drifts along
+        let wobble = radius * planet_mass / (sun_mass + planet_mass);
+        assert!(sun_positions
+            .iter()
+            .all(|pos| (pos.magnitude() - wobble).abs() < wobble * 0.1));
+        assert!((sun_positions[0] - sun_positions[49]).magnitude() > wobble);
+        assert!(get_local_pos(&engine, sun).z > 0.9 * drift.z * period_s);
+
+        // Back to drawing the frame physics uses
+        state.center_of_mass_frame = false;
+        engine.step(&mut state, 0.0);
+        assert_eq!(get_world_pos(&engine, sun), get_local_pos(&engine, sun));
+    }
+
+    #[wasm_bindgen_test]
+    pub fn axes_and_grid_tilt_with_the_coordinate_frame() {
+        use crate::app_state::CoordinateFrame;
+        use crate::managers::scene::ReferenceGrid;
+        use crate::utils::orbits::equatorial_to_ecliptic;
+        use crate::utils::transform::Transform;
+
+        let mut engine = Engine::new_headless();
+        let mut state = AppState::new();
+        let mut builder = SceneBuilder::new("frames", &mut engine.res_man);
+        let axes = builder.add_mesh_entity("axes", None, Transform::identity());
+        let major = builder.add_mesh_entity("grid", None, Transform::identity());
+        let minor = builder.add_mesh_entity("grid minor lines", None, Transform::identity());
+        let mut scene = builder.build();
+        scene.axes = Some(axes);
+        scene.grid = Some(ReferenceGrid {
+            minor,
+            major,
+            spacing: 1.0,
+            minor_fade: 0.0,
+            center: Vector3::zeros(),
+            rotation: UnitQuaternion::identity(),
+        });
+        engine.scene_man.set_current_scene(scene, &mut state);
+
+        // Looking at something well above the ecliptic
+        state.camera.target = Point3::new(0.0, 2000.0, 3000.0);
+        state.camera.pos = Point3::new(0.0, 2000.0, 3100.0);
+
+        let get_rotation = |engine: &Engine, entity: Entity| {
+            let scene = engine.scene_man.get_current_scene().unwrap();
+            return scene
+                .get_component::<TransformComponent>(entity)
+                .unwrap()
+                .get_world_transform()
+                .rot;
+        };
+        let get_grid_center = |engine: &Engine| {
+            let scene = engine.scene_man.get_current_scene().unwrap();
+            return scene.grid.as_ref().unwrap().center;
+        };
+
+        engine.step(&mut state, 0.0);
+        assert_eq!(get_rotation(&engine, axes), UnitQuaternion::identity());
+        assert_eq!(get_grid_center(&engine).z, 0.0);
+
+        // The Z axis points at the north celestial pole, and the grid lies on the celestial equator
+        state.coordinate_frame = CoordinateFrame::Equatorial;
+        engine.step(&mut state, 0.0);
+        let pole = equatorial_to_ecliptic(&Vector3::z());
+        let axes_z = get_rotation(&engine, axes) * Vector3::z();
+        assert!((axes_z - pole).magnitude() < 1E-9, "{:?}", axes_z);
+        assert!((axes_z.z.acos().to_degrees() - 23.4392794).abs() < 1E-6);
+        assert!((get_rotation(&engine, major) * Vector3::z() - pole).magnitude() < 1E-9);
+        assert!(get_grid_center(&engine).dot(&pole).abs() < 1E-6);
+
+        // The vernal equinox is along X in both
+        assert!((get_rotation(&engine, axes) * Vector3::x() - Vector3::x()).magnitude() < 1E-9);
+
+        state.coordinate_frame = CoordinateFrame::Ecliptic;
+        engine.step(&mut state, 0.0);
+        assert_eq!(get_rotation(&engine, minor), UnitQuaternion::identity());
+    }
+
+    #[wasm_bindgen_test]
+    pub fn billboards_face_the_camera() {
+        use crate::utils::transform::Transform;
+
+        let mut engine = Engine::new_headless();
+        let mut state = AppState::new();
+        state.canvas_width = 800;
+        state.canvas_height = 600;
+
+        let mut builder = SceneBuilder::new("billboards", &mut engine.res_man);
+        let mut trans = Transform::identity();
+        trans.trans = Vector3::new(10.0, 20.0, 0.0);
+        let label = builder.add_mesh_entity("label", None, trans.clone());
+        let other = builder.add_mesh_entity("other", None, trans);
+        let axes = builder.add_mesh_entity("axes", None, Transform::identity());
+        let mut scene = builder.build();
+        scene.axes = Some(axes);
+        scene
+            .get_component_mut::<TransformComponent>(label)
+            .unwrap()
+            .set_billboard(true);
+        engine.scene_man.set_current_scene(scene, &mut state);
+
+        state.camera.pos = Point3::new(-30.0, 5.0, 40.0);
+        state.camera.target = Point3::new(10.0, 20.0, 0.0);
+        state.camera.up = Vector3::z_axis();
+
+        let get_rotation = |engine: &Engine, entity: Entity| {
+            let scene = engine.scene_man.get_current_scene().unwrap();
+            return scene
+                .get_component::<TransformComponent>(entity)
+                .unwrap()
+                .get_world_transform()
+                .rot;
+        };
+
+        // Forward at the camera, and up as upright on screen as it can be
+        engine.step(&mut state, 0.0);
+        let rot = get_rotation(&engine, label);
+        let to_camera = (state.camera.pos.coords - Vector3::new(10.0, 20.0, 0.0)).normalize();
+        assert!((rot * Vector3::z() - to_camera).magnitude() < 1E-9);
+        assert!((rot * Vector3::x()).z.abs() < 1E-9);
+        assert!((rot * Vector3::y()).z > 0.0);
+        assert_eq!(get_rotation(&engine, other), UnitQuaternion::identity());
+
+        // The axes only face the camera when asked to
+        assert_eq!(get_rotation(&engine, axes), UnitQuaternion::identity());
+        state.billboard_axes = true;
+        engine.step(&mut state, 0.0);
+        let axes_z = get_rotation(&engine, axes) * Vector3::z();
+        assert!((axes_z - state.camera.pos.coords.normalize()).magnitude() < 1E-9);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn render_scale_only_affects_drawing() {
+        use crate::managers::orbit::{BodyDescription, BodyInstanceDescription};
+        use crate::managers::resource::collider::SphereCollider;
+        use crate::managers::resource::mesh::Mesh;
+        use crate::managers::scene::get_body_radius;
+        use crate::utils::raycasting::{raycast, Ray};
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let mut engine = Engine::new_headless();
+        let mut state = AppState::new();
+
+        let mut scene = {
+            let mut builder = SceneBuilder::new("render scale", &mut engine.res_man);
+            builder.add_body_instance(
+                Some(&BodyDescription::new_test_body("501", "Moon", 6.0)),
+                &BodyInstanceDescription::default(),
+            );
+            builder.build()
+        };
+
+        // Unit sphere collider, that the mesh holder scales up to the body radius
+        let moon = scene.get_entity_from_body_id("501").unwrap();
+        let mesh_holder = scene.get_entity_children(moon).unwrap()[0];
+        scene
+            .get_component_mut::<MeshComponent>(mesh_holder)
+            .unwrap()
+            .set_mesh(Some(Rc::new(RefCell::new(Mesh {
+                collider: Some(Box::new(SphereCollider {
+                    center: Point3::origin(),
+                    radius2: 1.0,
+                })),
+                ..Mesh::default()
+            }))));
+        scene.render_scale = 10.0;
+        engine.scene_man.set_current_scene(scene, &mut state);
+        engine.step(&mut state, 0.0);
+
+        // Drawn ten times larger
+        let scene = engine.scene_man.get_current_scene().unwrap();
+        let trans = scene
+            .get_component::<TransformComponent>(mesh_holder)
+            .unwrap();
+        assert!((trans.get_world_transform().scale.x - 60.0).abs() < 1E-9);
+        assert!((trans.get_physical_world_transform(10.0).scale.x - 6.0).abs() < 1E-9);
+        assert!((get_body_radius(scene, moon) - 6.0).abs() < 1E-9);
+
+        // Picked against its actual radius
+        let ray = |y: f64| Ray {
+            start: Point3::new(100.0, y, 0.0),
+            direction: -Vector3::x(),
+        };
+        let hit = raycast(&ray(0.0), scene).unwrap();
+        assert!((hit.hit_position_world.x - 6.0).abs() < 1E-4, "{:?}", hit);
+        assert!(raycast(&ray(20.0), scene).is_none());
+    }
+
+    #[wasm_bindgen_test]
+    pub fn orbit_lines_only_push_out_the_far_plane() {
+        use crate::managers::resource::collider::SphereCollider;
+        use crate::managers::resource::mesh::Mesh;
+        use crate::utils::starfield::STARFIELD_RADIUS_FRACTION;
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let mut state = AppState::new();
+        let mut scene = Scene::new("depth range");
+        let mut add_mesh = |name: &str, pos: Vector3<f64>, scale: f64, mesh: Mesh| {
+            let builder = scene.start_new_entity(name);
+            let entity = scene.finish_new_entity(builder);
+            let trans = scene
+                .add_component::<TransformComponent>(entity)
+                .get_local_transform_mut();
+            trans.trans = pos;
+            trans.scale = Vector3::new(scale, scale, scale);
+            scene
+                .add_component::<MeshComponent>(entity)
+                .set_mesh(Some(Rc::new(RefCell::new(mesh))));
+        };
+        add_mesh(
+            "Planet",
+            Vector3::new(1000.0, 0.0, 0.0),
+            1.0,
+            Mesh {
+                collider: Some(Box::new(SphereCollider {
+                    center: Point3::origin(),
+                    radius2: 1.0,
+                })),
+                ..Mesh::default()
+            },
+        );
+
+        // Unit circle scaled up to the planet's orbit, so its box contains the camera
+        add_mesh("Orbit", Vector3::zeros(), 1000.0, Mesh::default());
+
+        state.camera.pos = Point3::new(1000.0, 0.0, 10.0);
+        concatenate_parent_transforms(&mut scene);
+        update_auto_depth_range(&mut state, &scene);
+
+        // The near plane fits the planet, and the far plane still reaches the other side of the orbit
+        assert!(
+            (state.camera.near - 4.5).abs() < 1E-9,
+            "{}",
+            state.camera.near
+        );
+        assert!(state.camera.far * STARFIELD_RADIUS_FRACTION > 2000.0);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn world_bounds_only_update_when_something_moves() {
+        use crate::managers::resource::collider::SphereCollider;
+        use crate::managers::resource::mesh::Mesh;
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let mut scene = Scene::new("bounds");
+        let mesh = Rc::new(RefCell::new(Mesh {
+            collider: Some(Box::new(SphereCollider {
+                center: Point3::origin(),
+                radius2: 1.0,
+            })),
+            ..Mesh::default()
+        }));
+        let builder = scene.start_new_entity("Planet");
+        let planet = scene.finish_new_entity(builder);
+        scene.add_component::<TransformComponent>(planet);
+        scene
+            .add_component::<MeshComponent>(planet)
+            .set_mesh(Some(mesh.clone()));
+
+        concatenate_parent_transforms(&mut scene);
+        let (_, maxes) = scene.get_world_bounds().unwrap();
+        assert!((maxes.x - 1.0).abs() < 1E-9);
+
+        // Nothing moved, so we keep what we had even though the collider grew behind the scene's back
+        mesh.borrow_mut().collider = Some(Box::new(SphereCollider {
+            center: Point3::origin(),
+            radius2: 4.0,
+        }));
+        concatenate_parent_transforms(&mut scene);
+        assert_eq!(scene.get_world_bounds().unwrap().1, maxes);
+
+        // Moved like physics does it, straight through the storage
+        scene
+            .transform
+            .get_component_mut(planet)
+            .unwrap()
+            .get_local_transform_mut()
+            .trans = Vector3::new(10.0, 0.0, 0.0);
+        concatenate_parent_transforms(&mut scene);
+        let (_, maxes) = scene.get_world_bounds().unwrap();
+        assert!((maxes.x - 12.0).abs() < 1E-9, "{:?}", maxes);
+        assert_eq!(scene.get_world_bounding_spheres().len(), 1);
+    }
+}
@@ -0,0 +1,85 @@
+/// How quickly the glow fades in front of the planet's disk, away from the limb. Higher is a thinner haze
+pub const ATMOSPHERE_DISK_FALLOFF: i32 = 8;
+
+/// Brightness of the atmosphere glow (0 to 1) for a fragment of the atmosphere shell, given the dot product between
+/// the shell's normal and the direction from the fragment to the camera. `thickness` is how far past the surface the
+/// shell goes, as a fraction of the planet's radius.
+///
+/// This is the same function as atmosphere_glow in atmosphere.frag (which is what actually gets drawn). The distance
+/// from the center to the view ray through the fragment only depends on `n_dot_v` on a sphere, so the glow peaks at
+/// the limb and fades out towards the edge of the shell and the center of the disk, with the same profile relative
+/// to the planet's size from every distance
+pub fn atmosphere_glow(n_dot_v: f32, thickness: f32) -> f32 {
+    if thickness <= 0.0 {
+        return 0.0;
+    }
+
+    // In units of the shell radius
+    let inner_radius = 1.0 / (1.0 + thickness);
+    let ray_dist = (1.0 - n_dot_v * n_dot_v).max(0.0).sqrt();
+
+    if ray_dist >= inner_radius {
+        let height = (ray_dist - inner_radius) / (1.0 - inner_radius);
+        return (1.0 - height.min(1.0)).powi(2);
+    }
+
+    return (ray_dist / inner_radius).powi(ATMOSPHERE_DISK_FALLOFF);
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+    use na::{Point3, Vector3};
+
+    /// Glow seen along the ray from `camera_dist` away on +Z that passes `ray_dist` away from the center of a shell of
+    /// radius 1 around the origin, or None if the ray misses the shell
+    fn glow_along_ray(camera_dist: f64, ray_dist: f64, thickness: f32) -> Option<f32> {
+        let camera = Point3::new(0.0, 0.0, camera_dist);
+        let sin = ray_dist / camera_dist;
+        let dir = Vector3::new(0.0, sin, -(1.0 - sin * sin).sqrt());
+
+        // Nearest intersection with the shell
+        let b = camera.coords.dot(&dir);
+        let c = camera.coords.magnitude_squared() - 1.0;
+        let discriminant = b * b - c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let hit = camera + dir * (-b - discriminant.sqrt());
+
+        let normal: Vector3<f64> = hit.coords.normalize();
+        return Some(atmosphere_glow(normal.dot(&-dir) as f32, thickness));
+    }
+
+    #[wasm_bindgen_test]
+    pub fn glow_peaks_at_the_limb() {
+        let thickness: f32 = 0.1;
+        let limb = 1.0 / (1.0 + thickness);
+
+        // Right at the limb, fading out towards the edge of the shell and the center of the disk
+        assert!((atmosphere_glow((1.0 - limb * limb).sqrt(), thickness) - 1.0).abs() < 1E-4);
+        assert!(atmosphere_glow(0.0, thickness) < 1E-6);
+        assert!(atmosphere_glow(1.0, thickness) < 1E-6);
+        assert!(atmosphere_glow(0.9, thickness) < 0.05);
+
+        // No atmosphere, no glow
+        assert_eq!(atmosphere_glow(0.5, 0.0), 0.0);
+
+        // Same profile relative to the planet from near and far, so the glow scales along with the planet on screen
+        for ray_dist in &[0.2, 0.5, 0.85, limb as f64, 0.95, 0.99] {
+            let near = glow_along_ray(5.0, *ray_dist, thickness).unwrap();
+            let far = glow_along_ray(500.0, *ray_dist, thickness).unwrap();
+            assert!(
+                (near - far).abs() < 1E-3,
+                "{} vs {} at {}",
+                near,
+                far,
+                ray_dist
+            );
+        }
+        assert!(glow_along_ray(5.0, 1.01, thickness).is_none());
+    }
+}
@@ -1,15 +1,59 @@
 use na::*;
 use serde::{Deserialize, Serialize};
 
+/// Degrees
+pub const DEFAULT_FOV_V: f64 = 60.0;
+
+/// How much farther than strictly needed we stand when framing something, so that it doesn't fill the whole view
+const FRAMING_MARGIN: f64 = 2.0;
+
+/// Closest that get_auto_depth_range puts the near plane (1 meter, in Mm)
+pub const AUTO_NEAR_MIN: f64 = 1E-6;
+
+/// Side of the square in the bottom left corner of the canvas that the axis gizmo is drawn in, in points
+pub const AXIS_GIZMO_SIZE: f32 = 80.0;
+
+/// Distance from the axis gizmo to the left and bottom edges of the canvas, in points. Leaves room for the grid label
+pub const AXIS_GIZMO_MARGIN: f32 = 30.0;
+
+/// Largest far / near ratio that get_auto_depth_range uses, past which the camera matrices get imprecise
+const AUTO_DEPTH_MAX_RATIO: f64 = 1E13;
+
+/// Fraction of the distance to the closest bounding sphere that the near plane goes at. The near plane is a depth
+/// along the view direction and not a distance, so this also covers spheres off to the sides of wide fields of view
+const AUTO_NEAR_FRACTION: f64 = 0.5;
+
+/// How many times the distance to the farthest bounding sphere the far plane goes at. Leaves room for the starfield,
+/// which is drawn at a fraction of the far distance, to stay behind everything
+const AUTO_FAR_FACTOR: f64 = 4.0;
+
+/// Which field of view the camera keeps when the aspect ratio of the canvas changes. The others follow from it
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FovAxis {
+    Vertical,
+    Horizontal,
+    Diagonal,
+}
+impl Default for FovAxis {
+    fn default() -> Self {
+        FovAxis::Vertical
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Camera {
     pub pos: Point3<f64>,
     pub up: Unit<Vector3<f64>>,
     pub target: Point3<f64>,
-    pub fov_v: f64,
+    pub fov_v: f64, // Degrees
     pub near: f64,
     pub far: f64,
 
+    #[serde(default)]
+    pub fov_axis: FovAxis,
+    #[serde(skip)]
+    pub last_aspect_ratio: Option<f64>, // What update_transforms last got, to tell when the canvas is resized
+
     // Calculated once per frame after inputs are accounted for
     #[serde(skip)]
     pub v: Matrix4<f64>,
@@ -19,6 +63,13 @@ pub struct Camera {
     pub v_inv: Matrix4<f64>,
     #[serde(skip)]
     pub p_inv: Matrix4<f64>,
+
+    // Same orientation as `v`, but for positions that are already relative to the camera (i.e. with world_pos
+    // subtracted in f64). Keeps the large translations out of the matrices we send to the GPU
+    #[serde(skip)]
+    pub v_rel: Matrix4<f64>,
+    #[serde(skip)]
+    pub world_pos: Vector3<f64>, // Camera position in world space, including the reference translation
 }
 impl Camera {
     /// Converts from pixels (with 0,0 on top left of canvas) into world space coordinates
@@ -40,6 +91,38 @@ impl Camera {
         return ndc_to_world.transform_point(&ndc_near_pos);
     }
 
+    /// Moves the camera by `distance` along the ray through the pixel x, y (with 0,0 on top left of canvas).
+    /// Negative distances move away from whatever is under the pixel. Unless `keep_target` is set the target moves
+    /// along with it, so that the camera keeps looking the same way
+    pub fn dolly_towards_canvas_point(
+        &mut self,
+        x: i32,
+        y: i32,
+        canvas_width: u32,
+        canvas_height: u32,
+        distance: f64,
+        keep_target: bool,
+    ) {
+        let end_world = self.canvas_to_world(x, y, canvas_width, canvas_height);
+        let dir = (end_world.coords - self.world_pos).normalize();
+
+        self.pos += dir * distance;
+        if !keep_target {
+            self.target += dir * distance;
+        }
+    }
+
+    /// How many world space units (Mm) one canvas pixel spans `depth` in front of the camera, for either a
+    /// perspective or an orthographic `p`
+    pub fn get_world_units_per_pixel(&self, depth: f64, canvas_height: u32) -> f64 {
+        // Clip space w of a point `depth` in front of the camera, which is `depth` itself for perspective
+        // projections and 1 for orthographic ones
+        let w = -self.p[(3, 2)] * depth + self.p[(3, 3)];
+        let pixels_per_unit = self.p[(1, 1)] / w * canvas_height as f64 / 2.0;
+
+        return 1.0 / pixels_per_unit;
+    }
+
     /// Converts from Mm xyz to pixel xy (with 0,0 on top left of canvas). Also returns whether the point is in front of camera or not
     pub fn world_to_canvas(
         &self,
@@ -58,11 +141,31 @@ impl Camera {
         );
     }
 
+    /// Transform from world space directions to the NDC of the axis gizmo viewport. Has only the rotation of the camera
+    /// and an orthographic projection that fits the unit axes, so that the gizmo turns along with the camera but never
+    /// moves or changes size
+    pub fn get_axis_gizmo_transform(&self) -> Matrix4<f64> {
+        let extent = 1.25;
+        return Matrix4::new_orthographic(-extent, extent, -extent, extent, -extent, extent)
+            * self.v_rel;
+    }
+
     pub fn update_transforms(
         &mut self,
         aspect_ratio: f64,
         reference_translation: Option<Vector3<f64>>,
     ) {
+        // Resizing keeps the field of view along fov_axis, whatever the vertical one becomes
+        if aspect_ratio.is_finite() && aspect_ratio > 0.0 {
+            if let Some(last_aspect_ratio) = self.last_aspect_ratio {
+                if last_aspect_ratio != aspect_ratio {
+                    let fov = get_fov_along_axis(self.fov_v, last_aspect_ratio, self.fov_axis);
+                    self.fov_v = get_vertical_fov(fov, aspect_ratio, self.fov_axis);
+                }
+            }
+            self.last_aspect_ratio = Some(aspect_ratio);
+        }
+
         self.p = Matrix4::new_perspective(
             aspect_ratio,
             self.fov_v.to_radians() as f64,
@@ -76,5 +179,430 @@ impl Camera {
             self.v *= Translation3::from(-trans).to_homogeneous();
         }
         self.v_inv = self.v.try_inverse().unwrap();
+
+        self.world_pos = self.pos.coords + reference_translation.unwrap_or(Vector3::zeros());
+        self.v_rel = Matrix4::look_at_rh(
+            &Point3::origin(),
+            &Point3::from(self.target - self.pos),
+            &self.up,
+        );
+    }
+}
+
+/// Field of view along `axis` in degrees, for a vertical field of view of `fov_v` degrees at `aspect_ratio`
+pub fn get_fov_along_axis(fov_v: f64, aspect_ratio: f64, axis: FovAxis) -> f64 {
+    let tan_half_v = (fov_v.to_radians() / 2.0).tan();
+    let tan_half = match axis {
+        FovAxis::Vertical => return fov_v,
+        FovAxis::Horizontal => tan_half_v * aspect_ratio,
+        FovAxis::Diagonal => tan_half_v * (1.0 + aspect_ratio * aspect_ratio).sqrt(),
+    };
+    return (2.0 * tan_half.atan()).to_degrees();
+}
+
+/// Vertical field of view in degrees that has a field of view of `fov` degrees along `axis` at `aspect_ratio`
+pub fn get_vertical_fov(fov: f64, aspect_ratio: f64, axis: FovAxis) -> f64 {
+    let tan_half = (fov.to_radians() / 2.0).tan();
+    let tan_half_v = match axis {
+        FovAxis::Vertical => return fov,
+        FovAxis::Horizontal => tan_half / aspect_ratio,
+        FovAxis::Diagonal => tan_half / (1.0 + aspect_ratio * aspect_ratio).sqrt(),
+    };
+    return (2.0 * tan_half_v.atan()).to_degrees();
+}
+
+/// Distance from the center of a sphere of `radius` at which the camera sees all of it with a vertical field of view
+/// of `fov_v` degrees, with some margin
+pub fn get_framing_distance(radius: f64, fov_v: f64) -> f64 {
+    let half_fov = (fov_v.max(1.0).min(179.0) / 2.0).to_radians();
+    return FRAMING_MARGIN * radius / half_fov.sin();
+}
+
+/// Near and far distances that fit all of the `spheres` (center relative to the camera, radius), or None if there
+/// aren't any. The far plane also reaches every corner of `bounds` (mins and maxes relative to the camera), if given.
+/// If they're too far apart we pull in the far plane instead of pushing out the near one, as the farthest things are
+/// tiny on screen anyway
+pub fn get_auto_depth_range(
+    spheres: &[(Vector3<f64>, f64)],
+    bounds: Option<(Vector3<f64>, Vector3<f64>)>,
+) -> Option<(f64, f64)> {
+    if spheres.is_empty() {
+        return None;
+    }
+
+    let mut nearest = f64::INFINITY;
+    let mut farthest: f64 = 0.0;
+    for (center, radius) in spheres {
+        let dist = center.magnitude();
+        nearest = nearest.min(dist - radius);
+        farthest = farthest.max(dist + radius);
+    }
+
+    // The farthest corner is the one with the farthest coordinate along each axis
+    if let Some((mins, maxes)) = bounds {
+        let corner = mins.abs().sup(&maxes.abs());
+        farthest = farthest.max(corner.magnitude());
+    }
+
+    let near = (nearest * AUTO_NEAR_FRACTION).max(AUTO_NEAR_MIN);
+    let far = (farthest * AUTO_FAR_FACTOR)
+        .max(near * 10.0)
+        .min(near * AUTO_DEPTH_MAX_RATIO);
+    return Some((near, far));
+}
+
+/// Returns a sphere (center, radius) containing all of the `spheres`. Not the smallest possible one, but close enough
+/// for framing them with the camera
+pub fn get_bounding_sphere(spheres: &[(Point3<f64>, f64)]) -> Option<(Point3<f64>, f64)> {
+    if spheres.is_empty() {
+        return None;
+    }
+
+    let mut min = spheres[0].0.coords;
+    let mut max = spheres[0].0.coords;
+    for (center, radius) in spheres.iter() {
+        min = min.inf(&center.coords.add_scalar(-radius));
+        max = max.sup(&center.coords.add_scalar(*radius));
+    }
+
+    let center = Point3::from((min + max) / 2.0);
+    let radius = spheres
+        .iter()
+        .map(|(pos, radius)| (pos - center).magnitude() + radius)
+        .fold(0.0, f64::max);
+
+    return Some((center, radius));
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+    use crate::utils::starfield::STARFIELD_RADIUS_FRACTION;
+
+    #[wasm_bindgen_test]
+    pub fn focused_body_far_from_origin_stays_precise() {
+        // Roughly where the far_from_origin scene puts Charon, with the camera orbiting close to it
+        let moon_pos = Vector3::new(1.0E7 + 0.123456789, -19.59123456, 0.000987654);
+        let mut camera = Camera {
+            pos: Point3::new(2.0, 0.0, 0.0),
+            up: Unit::new_unchecked(Vector3::z()),
+            target: Point3::new(0.0, 0.0, 0.0),
+            fov_v: 60.0,
+            near: 0.01,
+            far: 100000000.0,
+            fov_axis: FovAxis::Vertical,
+            last_aspect_ratio: None,
+            v: Matrix4::identity(),
+            p: Matrix4::identity(),
+            v_inv: Matrix4::identity(),
+            p_inv: Matrix4::identity(),
+            v_rel: Matrix4::identity(),
+            world_pos: Vector3::zeros(),
+        };
+
+        for step in 0..100 {
+            let angle = step as f64 * 0.01;
+            camera.pos = Point3::new(2.0 * angle.cos(), 2.0 * angle.sin(), 0.5);
+            camera.update_transforms(1.0, Some(moon_pos));
+
+            // What the GPU gets: The translation of the moon's camera-relative world-view transform, in f32
+            let relative = moon_pos - camera.world_pos;
+            let wv = camera.v_rel * Translation3::from(relative).to_homogeneous();
+            let wv = na::convert::<Matrix4<f64>, Matrix4<f32>>(wv);
+            let view_pos = Vector3::new(wv[(0, 3)], wv[(1, 3)], wv[(2, 3)]);
+
+            // The moon is our target, so it should be straight ahead without any wobble
+            let distance = camera.pos.coords.magnitude() as f32;
+            assert!(view_pos.x.abs() < 1E-6);
+            assert!(view_pos.y.abs() < 1E-6);
+            assert!((view_pos.z + distance).abs() < 1E-6);
+
+            // Same result as going through the full world space view matrix in f64
+            let full = camera.v.transform_point(&Point3::from(moon_pos));
+            assert!((full.coords - relative.magnitude() * -Vector3::z()).magnitude() < 1E-6);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    pub fn framing_fits_bodies() {
+        // A sphere seen from its framing distance should span less than the field of view
+        let distance = get_framing_distance(1.0, 60.0);
+        assert!((1.0 / distance).asin().to_degrees() * 2.0 < 60.0);
+        assert!(get_framing_distance(2.0, 60.0) > distance);
+
+        assert!(get_bounding_sphere(&[]).is_none());
+
+        let spheres = [
+            (Point3::new(-10.0, 0.0, 0.0), 1.0),
+            (Point3::new(10.0, 0.0, 0.0), 3.0),
+            (Point3::new(0.0, 5.0, 0.0), 0.0),
+        ];
+        let (center, radius) = get_bounding_sphere(&spheres).unwrap();
+        for (pos, body_radius) in spheres.iter() {
+            assert!((pos - center).magnitude() + body_radius <= radius + 1E-9);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    pub fn dolly_moves_along_the_cursor_ray() {
+        let new_camera = || Camera {
+            pos: Point3::new(10.0, 0.0, 0.0),
+            up: Unit::new_unchecked(Vector3::z()),
+            target: Point3::new(0.0, 0.0, 0.0),
+            fov_v: 60.0,
+            near: 0.01,
+            far: 1000.0,
+            fov_axis: FovAxis::Vertical,
+            last_aspect_ratio: None,
+            v: Matrix4::identity(),
+            p: Matrix4::identity(),
+            v_inv: Matrix4::identity(),
+            p_inv: Matrix4::identity(),
+            v_rel: Matrix4::identity(),
+            world_pos: Vector3::zeros(),
+        };
+
+        // Straight ahead from the center of the canvas, keeping the camera's orientation
+        let mut camera = new_camera();
+        camera.update_transforms(1.0, None);
+        camera.dolly_towards_canvas_point(50, 50, 101, 101, 2.0, false);
+        assert!((camera.pos - Point3::new(8.0, 0.0, 0.0)).magnitude() < 1E-9);
+        assert!((camera.target - Point3::new(-2.0, 0.0, 0.0)).magnitude() < 1E-9);
+
+        // Towards a point off to the left and up, with the same result when focused on something far away
+        let reference = Vector3::new(1.0E7, 2.0E6, 0.0);
+        for reference_translation in &[None, Some(reference)] {
+            let mut camera = new_camera();
+            camera.update_transforms(1.0, *reference_translation);
+            camera.dolly_towards_canvas_point(0, 0, 101, 101, 1.0, false);
+
+            let moved = camera.pos - Point3::new(10.0, 0.0, 0.0);
+            assert!((moved.magnitude() - 1.0).abs() < 1E-6);
+            assert!(moved.x < 0.0 && moved.y < 0.0 && moved.z > 0.0, "{}", moved);
+            assert!((camera.target.coords - moved).magnitude() < 1E-6);
+        }
+
+        // Negative distances back away
+        let mut camera = new_camera();
+        camera.update_transforms(1.0, None);
+        camera.dolly_towards_canvas_point(50, 50, 101, 101, -2.0, false);
+        assert!((camera.pos - Point3::new(12.0, 0.0, 0.0)).magnitude() < 1E-9);
+
+        // Focused cameras keep looking at the focused body
+        let mut camera = new_camera();
+        camera.update_transforms(1.0, Some(reference));
+        camera.dolly_towards_canvas_point(0, 0, 101, 101, 1.0, true);
+        assert!((camera.pos - Point3::new(10.0, 0.0, 0.0)).magnitude() > 0.5);
+        assert_eq!(camera.target, Point3::new(0.0, 0.0, 0.0));
+    }
+
+    #[wasm_bindgen_test]
+    pub fn world_units_per_pixel_for_both_projections() {
+        let mut camera = Camera {
+            pos: Point3::new(10.0, 0.0, 0.0),
+            up: Unit::new_unchecked(Vector3::z()),
+            target: Point3::new(0.0, 0.0, 0.0),
+            fov_v: 90.0,
+            near: 0.01,
+            far: 1000.0,
+            fov_axis: FovAxis::Vertical,
+            last_aspect_ratio: None,
+            v: Matrix4::identity(),
+            p: Matrix4::identity(),
+            v_inv: Matrix4::identity(),
+            p_inv: Matrix4::identity(),
+            v_rel: Matrix4::identity(),
+            world_pos: Vector3::zeros(),
+        };
+        camera.update_transforms(1.0, None);
+
+        // With a 90 degree field of view the canvas is twice the depth tall
+        assert!((camera.get_world_units_per_pixel(10.0, 200) - 0.1).abs() < 1E-9);
+        assert!((camera.get_world_units_per_pixel(20.0, 200) - 0.2).abs() < 1E-9);
+
+        // Same as measuring between two projected points
+        let (_, top, _) = camera.world_to_canvas(&Point3::new(0.0, 0.0, 5.0), 1001, 1001);
+        let (_, bottom, _) = camera.world_to_canvas(&Point3::new(0.0, 0.0, -5.0), 1001, 1001);
+        let measured = 10.0 / (bottom - top) as f64;
+        assert!((camera.get_world_units_per_pixel(10.0, 1001) - measured).abs() < 1E-4);
+
+        // Orthographic projections don't depend on the depth at all
+        camera.p = Orthographic3::new(-50.0, 50.0, -25.0, 25.0, 0.01, 1000.0).to_homogeneous();
+        assert!((camera.get_world_units_per_pixel(10.0, 100) - 0.5).abs() < 1E-9);
+        assert!((camera.get_world_units_per_pixel(500.0, 100) - 0.5).abs() < 1E-9);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn auto_depth_range_fits_from_moon_surface_to_whole_system() {
+        let moon = (Vector3::new(149597.87, 384.4, 0.0), 1.7374);
+        let sun = (Vector3::new(0.0, 0.0, 0.0), 696.34);
+        let neptune = (Vector3::new(-4.4951E6, 0.0, 1E4), 24.764);
+        let bodies = [moon, sun, neptune];
+
+        // From a kilometer off the lunar surface out to way past Neptune, looking back at the Sun
+        let moon_dir = moon.0.normalize();
+        let mut camera_positions = vec![moon.0 + moon_dir * (moon.1 + 0.001)];
+        for exponent in 0..8 {
+            camera_positions.push(moon.0 + moon_dir * (moon.1 + 10.0_f64.powi(exponent)));
+        }
+        camera_positions.push(Vector3::new(0.0, 0.0, 1E8));
+
+        for camera_pos in &camera_positions {
+            let spheres: Vec<(Vector3<f64>, f64)> = bodies
+                .iter()
+                .map(|(center, radius)| (center - camera_pos, *radius))
+                .collect();
+            let (near, far) = get_auto_depth_range(&spheres, None).unwrap();
+
+            assert!(near >= AUTO_NEAR_MIN);
+            assert!(far / near <= AUTO_DEPTH_MAX_RATIO * 1.0001);
+            for (center, radius) in &spheres {
+                let closest = center.magnitude() - radius;
+                let farthest = center.magnitude() + radius;
+
+                // Even if it's 60 degrees off to the side
+                assert!(near < closest * 0.5_f64.max(60.0_f64.to_radians().cos()) + 1E-12);
+                assert!(
+                    farthest < far * STARFIELD_RADIUS_FRACTION,
+                    "{:?}",
+                    camera_pos
+                );
+            }
+        }
+
+        // Inside a sphere (e.g. an atmosphere shell) the near plane just goes as close as it can
+        let (near, _) = get_auto_depth_range(&[(Vector3::new(1.0, 0.0, 0.0), 2.0)], None).unwrap();
+        assert_eq!(near, AUTO_NEAR_MIN);
+        assert!(get_auto_depth_range(&[], None).is_none());
+
+        // Bounds only ever push the far plane out
+        let spheres = [(Vector3::new(10.0, 0.0, 0.0), 1.0)];
+        let (near, far) = get_auto_depth_range(&spheres, None).unwrap();
+        let bounds = (
+            Vector3::new(-1.0, -2.0, -3.0),
+            Vector3::new(1000.0, 5.0, 2.0),
+        );
+        let (bounded_near, bounded_far) = get_auto_depth_range(&spheres, Some(bounds)).unwrap();
+        assert_eq!(bounded_near, near);
+        assert!(bounded_far > far);
+        assert!(
+            bounded_far * STARFIELD_RADIUS_FRACTION > Vector3::new(1000.0, 5.0, 3.0).magnitude()
+        );
+        let small_bounds = (Vector3::new(9.0, -0.5, -0.5), Vector3::new(10.5, 0.5, 0.5));
+        assert_eq!(
+            get_auto_depth_range(&spheres, Some(small_bounds))
+                .unwrap()
+                .1,
+            far
+        );
+    }
+
+    #[wasm_bindgen_test]
+    pub fn axis_gizmo_only_turns_with_the_camera() {
+        let mut camera = Camera {
+            pos: Point3::new(0.0, 0.0, 10.0),
+            up: Vector3::y_axis(),
+            target: Point3::origin(),
+            fov_v: 60.0,
+            near: 0.01,
+            far: 1000.0,
+            fov_axis: FovAxis::Vertical,
+            last_aspect_ratio: None,
+            v: Matrix4::identity(),
+            p: Matrix4::identity(),
+            v_inv: Matrix4::identity(),
+            p_inv: Matrix4::identity(),
+            v_rel: Matrix4::identity(),
+            world_pos: Vector3::zeros(),
+        };
+        camera.update_transforms(16.0 / 9.0, None);
+        let tip = |camera: &Camera, axis: Vector3<f64>| {
+            let ndc = camera.get_axis_gizmo_transform() * axis.push(1.0);
+            return Vector2::new(ndc.x, ndc.y);
+        };
+
+        // Looking down on the XY plane, X is to the right and Y is up, and Z points right at us
+        assert!((tip(&camera, Vector3::x()) - Vector2::new(0.8, 0.0)).magnitude() < 1E-9);
+        assert!((tip(&camera, Vector3::y()) - Vector2::new(0.0, 0.8)).magnitude() < 1E-9);
+        assert!(tip(&camera, Vector3::z()).magnitude() < 1E-9);
+
+        // Moving the camera or focusing something far away doesn't move it, or change its size
+        camera.pos = Point3::new(1E9, 0.0, 10.0);
+        camera.target = Point3::new(1E9, 0.0, 0.0);
+        camera.update_transforms(1.0, Some(Vector3::new(5E8, 0.0, 0.0)));
+        assert!((tip(&camera, Vector3::x()) - Vector2::new(0.8, 0.0)).magnitude() < 1E-9);
+
+        // Looking along -Y instead flips X around
+        camera.pos = Point3::new(0.0, 10.0, 0.0);
+        camera.target = Point3::origin();
+        camera.up = Vector3::z_axis();
+        camera.update_transforms(1.0, None);
+        assert!((tip(&camera, Vector3::x()) - Vector2::new(-0.8, 0.0)).magnitude() < 1E-9);
+        assert!((tip(&camera, Vector3::z()) - Vector2::new(0.0, 0.8)).magnitude() < 1E-9);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn resizing_keeps_the_fov_along_the_chosen_axis() {
+        let new_camera = |fov_axis: FovAxis| Camera {
+            pos: Point3::new(10.0, 0.0, 0.0),
+            up: Unit::new_unchecked(Vector3::z()),
+            target: Point3::new(0.0, 0.0, 0.0),
+            fov_v: 60.0,
+            near: 0.01,
+            far: 1000.0,
+            fov_axis,
+            last_aspect_ratio: None,
+            v: Matrix4::identity(),
+            p: Matrix4::identity(),
+            v_inv: Matrix4::identity(),
+            p_inv: Matrix4::identity(),
+            v_rel: Matrix4::identity(),
+            world_pos: Vector3::zeros(),
+        };
+
+        // Conversions go both ways, and a square canvas has the same horizontal and vertical fields of view
+        let diagonal = get_fov_along_axis(60.0, 16.0 / 9.0, FovAxis::Diagonal);
+        assert!((get_vertical_fov(diagonal, 16.0 / 9.0, FovAxis::Diagonal) - 60.0).abs() < 1E-9);
+        assert!((get_fov_along_axis(60.0, 1.0, FovAxis::Horizontal) - 60.0).abs() < 1E-9);
+
+        // The projection matrix scales x by 1 / tan(horizontal FOV / 2) and y by 1 / tan(vertical FOV / 2)
+        let get_fovs = |camera: &Camera| -> (f64, f64) {
+            return (
+                (2.0 * (1.0 / camera.p[(0, 0)]).atan()).to_degrees(),
+                (2.0 * (1.0 / camera.p[(1, 1)]).atan()).to_degrees(),
+            );
+        };
+
+        let aspect_ratios = [16.0 / 9.0, 1.0, 9.0 / 16.0];
+        for axis in &[FovAxis::Vertical, FovAxis::Horizontal, FovAxis::Diagonal] {
+            let mut camera = new_camera(*axis);
+            camera.update_transforms(aspect_ratios[0], None);
+            let (h_0, v_0) = get_fovs(&camera);
+            let d_0 = get_fov_along_axis(v_0, aspect_ratios[0], FovAxis::Diagonal);
+            assert!((v_0 - 60.0).abs() < 1E-9);
+
+            for aspect_ratio in &aspect_ratios[1..] {
+                camera.update_transforms(*aspect_ratio, None);
+                let (h, v) = get_fovs(&camera);
+                let d = get_fov_along_axis(v, *aspect_ratio, FovAxis::Diagonal);
+
+                let kept = match axis {
+                    FovAxis::Vertical => (v, v_0),
+                    FovAxis::Horizontal => (h, h_0),
+                    FovAxis::Diagonal => (d, d_0),
+                };
+                assert!((kept.0 - kept.1).abs() < 1E-9, "{:?} {:?}", axis, kept);
+            }
+        }
+
+        // Only changes in the aspect ratio count, not the field of view we set in between
+        let mut camera = new_camera(FovAxis::Horizontal);
+        camera.update_transforms(2.0, None);
+        camera.fov_v = 45.0;
+        camera.update_transforms(2.0, None);
+        assert_eq!(camera.fov_v, 45.0);
     }
 }
@@ -0,0 +1,42 @@
+use na::{Matrix4, Point3, RowVector4};
+
+/// Six planes (left, right, bottom, top, near, far) extracted from a projection or view-projection matrix.
+/// Each plane is (a, b, c, d) with a normalized normal pointing into the frustum, so that a*x + b*y + c*z + d
+/// is the signed distance of a point to the plane
+pub struct Frustum {
+    pub planes: [RowVector4<f64>; 6],
+}
+impl Frustum {
+    /// Gribb/Hartmann plane extraction. The planes will be in whatever space `matrix` takes points from:
+    /// Pass in just the projection matrix to get the planes in view space
+    pub fn from_matrix(matrix: &Matrix4<f64>) -> Self {
+        let r0 = matrix.row(0);
+        let r1 = matrix.row(1);
+        let r2 = matrix.row(2);
+        let r3 = matrix.row(3);
+
+        let mut planes = [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r3 + r2, r3 - r2];
+
+        for plane in planes.iter_mut() {
+            let normal_length =
+                (plane[0] * plane[0] + plane[1] * plane[1] + plane[2] * plane[2]).sqrt();
+            if normal_length > 0.0 {
+                *plane /= normal_length;
+            }
+        }
+
+        return Self { planes };
+    }
+
+    /// Returns false only if the sphere is fully outside of at least one of the planes
+    pub fn intersects_sphere(&self, center: &Point3<f64>, radius: f64) -> bool {
+        for plane in &self.planes {
+            let dist = plane[0] * center.x + plane[1] * center.y + plane[2] * center.z + plane[3];
+            if dist < -radius {
+                return false;
+            }
+        }
+
+        return true;
+    }
+}
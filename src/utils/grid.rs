@@ -0,0 +1,88 @@
+/// Number of cells across each of the grid meshes, which should match the "grid" mesh of the resource manager
+pub const GRID_CELLS: f64 = 10.0;
+
+/// How much of the grid's color the major lines use. Minor lines fade from this down to zero
+pub const GRID_ALPHA: f32 = 0.6;
+
+/// Returns the spacing (Mm) between minor grid lines for a camera `distance` (Mm) away from what it's looking at, as a
+/// power of ten. Major lines are ten times farther apart. Also returns how far we are into that decade, in [0, 1),
+/// which we use to fade out the minor lines as they get too dense to be useful
+pub fn get_grid_spacing(distance: f64) -> (f64, f32) {
+    // Minor lines fill the whole view when the camera is about this far from the grid
+    let exponent = (distance.max(1E-9) / (GRID_CELLS / 2.0)).log10();
+    let decade = exponent.floor();
+
+    return (10.0_f64.powf(decade), (exponent - decade) as f32);
+}
+
+/// Snaps `pos` to the closest multiple of `spacing` on each axis, so that grid lines stay put as the camera moves
+pub fn snap_to_spacing(pos: f64, spacing: f64) -> f64 {
+    return (pos / spacing).round() * spacing;
+}
+
+/// Longest length that a scale bar can show without going over `max_length`, out of 1, 2 or 5 times a power of ten
+pub fn get_scale_bar_length(max_length: f64) -> f64 {
+    let decade = 10.0_f64.powi(max_length.max(1E-12).log10().floor() as i32);
+    for multiple in &[5.0, 2.0, 1.0] {
+        if decade * multiple <= max_length {
+            return decade * multiple;
+        }
+    }
+
+    return decade;
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+    use crate::app_state::DistanceUnit;
+    use crate::utils::units::{format_distance, get_distance_unit_size, Au, Mm};
+
+    #[wasm_bindgen_test]
+    pub fn grid_spacing_is_continuous_across_decades() {
+        let (spacing, fade) = get_grid_spacing(100.0);
+        assert_eq!(spacing, 10.0);
+        assert!(fade > 0.0 && fade < 1.0);
+
+        // Right before a decade the minor lines are nearly gone, and right after it the old major lines become the
+        // new minor lines, at full alpha
+        let (before, before_fade) = get_grid_spacing(499.999);
+        let (after, after_fade) = get_grid_spacing(500.001);
+        assert_eq!(before * 10.0, after);
+        assert!(before_fade > 0.99);
+        assert!(after_fade < 0.01);
+
+        assert_eq!(snap_to_spacing(123.0, 10.0), 120.0);
+        assert_eq!(snap_to_spacing(-126.0, 10.0), -130.0);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn scale_bars_use_round_lengths() {
+        assert_eq!(get_scale_bar_length(100.0), 100.0);
+        assert_eq!(get_scale_bar_length(199.0), 100.0);
+        assert_eq!(get_scale_bar_length(420.0), 200.0);
+        assert_eq!(get_scale_bar_length(9999.0), 5000.0);
+        assert!((get_scale_bar_length(0.3) - 0.2).abs() < 1E-12);
+        assert!((get_scale_bar_length(0.0007) - 0.0005).abs() < 1E-15);
+
+        // Round in whatever unit the bar is labeled with
+        let unit = get_distance_unit_size(Mm(20000.0), DistanceUnit::Auto);
+        assert_eq!(unit.0, Au(1.0).to_Mm().0);
+        assert_eq!(get_distance_unit_size(Mm(0.5), DistanceUnit::Auto).0, 0.001);
+        assert_eq!(get_distance_unit_size(Mm(0.5), DistanceUnit::Mm).0, 1.0);
+        let length = Mm(get_scale_bar_length(20000.0 / unit.0) * unit.0);
+        assert_eq!(format_distance(length), "0.1 AU");
+    }
+
+    #[wasm_bindgen_test]
+    pub fn distances_pick_readable_units() {
+        assert_eq!(format_distance(Mm(0.1)), "100 km");
+        assert_eq!(format_distance(Mm(10.0)), "10 Mm");
+        assert_eq!(format_distance(Mm(1234.4)), "1234 Mm");
+        assert_eq!(format_distance(Au(2.0).to_Mm()), "2 AU");
+        assert_eq!(format_distance(Mm(0.0)), "0 km");
+    }
+}
@@ -0,0 +1,96 @@
+/// Space kept around each body label when checking for overlaps, in egui points
+pub const LABEL_PADDING: f32 = 2.0;
+
+/// Screen space rectangle covered by a label
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LabelRect {
+    pub min_x: f32,
+    pub min_y: f32,
+    pub max_x: f32,
+    pub max_y: f32,
+}
+impl LabelRect {
+    pub fn overlaps(&self, other: &LabelRect) -> bool {
+        return self.min_x < other.max_x
+            && other.min_x < self.max_x
+            && self.min_y < other.max_y
+            && other.min_y < self.max_y;
+    }
+}
+
+/// Picks which labels to show so that none of them overlap, preferring the ones of bodies nearer to the camera.
+/// `distances` are from the camera to the body of each label in `rects`. `is_visible` is only called for labels that
+/// would be shown otherwise, so that it can do expensive things like raycasting for occlusion.
+/// Returns indices into `rects`, nearest first, and at most `max_labels` of them
+pub fn declutter_labels(
+    rects: &[LabelRect],
+    distances: &[f64],
+    max_labels: usize,
+    mut is_visible: impl FnMut(usize) -> bool,
+) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..rects.len().min(distances.len())).collect();
+    order.sort_by(|a, b| distances[*a].partial_cmp(&distances[*b]).unwrap());
+
+    let mut result: Vec<usize> = Vec::new();
+    for index in order {
+        if result.len() >= max_labels {
+            break;
+        }
+
+        if result
+            .iter()
+            .any(|kept| rects[*kept].overlaps(&rects[index]))
+        {
+            continue;
+        }
+
+        // Occluded labels don't hide the ones behind them
+        if !is_visible(index) {
+            continue;
+        }
+
+        result.push(index);
+    }
+
+    return result;
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    pub fn overlapping_labels_keep_the_nearest() {
+        let rect = |x: f32, y: f32| LabelRect {
+            min_x: x,
+            min_y: y,
+            max_x: x + 50.0,
+            max_y: y + 10.0,
+        };
+
+        // 0 and 1 overlap, 2 is off on its own, 3 overlaps 2 but is occluded
+        let rects = [
+            rect(0.0, 0.0),
+            rect(20.0, 5.0),
+            rect(100.0, 0.0),
+            rect(120.0, 0.0),
+        ];
+        let distances = [10.0, 5.0, 20.0, 1.0];
+
+        let mut tested = Vec::new();
+        let kept = declutter_labels(&rects, &distances, 10, |index| {
+            tested.push(index);
+            return index != 3;
+        });
+        assert_eq!(kept, vec![1, 2]);
+
+        // Label 0 was hidden by the nearer 1 before we got to test it
+        assert_eq!(tested, vec![3, 1, 2]);
+
+        assert_eq!(declutter_labels(&rects, &distances, 1, |_| true), vec![3]);
+        assert!(declutter_labels(&rects, &distances, 0, |_| true).is_empty());
+    }
+}
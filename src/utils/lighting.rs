@@ -0,0 +1,37 @@
+use crate::utils::string::decode_hex;
+use na::Vector3;
+
+/// Linear color to give a light from a sRGB hex color like "FFD2A1", or None if it isn't one
+pub fn decode_light_color(hex: &str) -> Option<Vector3<f32>> {
+    if hex.len() != 6 {
+        return None;
+    }
+
+    // Same approximation to sRGB as functions.glsl
+    let bytes = decode_hex(hex).ok()?;
+    let linear = |byte: u8| (byte as f32 / 255.0).powf(2.2);
+    return Some(Vector3::new(
+        linear(bytes[0]),
+        linear(bytes[1]),
+        linear(bytes[2]),
+    ));
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    pub fn light_colors_are_linear() {
+        assert_eq!(
+            decode_light_color("FF0000"),
+            Some(Vector3::new(1.0, 0.0, 0.0))
+        );
+        assert!((decode_light_color("808080").unwrap().y - 0.2158605).abs() < 1E-5);
+        assert_eq!(decode_light_color("FF00"), None);
+        assert_eq!(decode_light_color("GG0000"), None);
+    }
+}
@@ -4,3 +4,41 @@ pub unsafe fn any_slice_to_u8_slice<T>(input: &[T]) -> &[u8] {
         input.len() * core::mem::size_of::<T>(),
     )
 }
+
+/// How much memory a container holds on to, and how much of it is actually in use
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MemoryReport {
+    pub allocated_bytes: usize,
+    pub capacity: usize,     // Number of elements we have room for
+    pub num_elements: usize, // Number of those that hold something live
+}
+impl MemoryReport {
+    /// Reports a vec with `num_elements` live elements, plus `extra_bytes` of heap memory owned by them
+    pub fn from_vec<T>(vec: &Vec<T>, num_elements: usize, extra_bytes: usize) -> Self {
+        return Self {
+            allocated_bytes: vec.capacity() * core::mem::size_of::<T>() + extra_bytes,
+            capacity: vec.capacity(),
+            num_elements,
+        };
+    }
+
+    /// Fraction of the capacity that holds live elements, in [0, 1]
+    pub fn get_occupancy(&self) -> f64 {
+        if self.capacity == 0 {
+            return 1.0;
+        }
+
+        return self.num_elements as f64 / self.capacity as f64;
+    }
+}
+impl std::ops::Add for MemoryReport {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        return Self {
+            allocated_bytes: self.allocated_bytes + other.allocated_bytes,
+            capacity: self.capacity + other.capacity,
+            num_elements: self.num_elements + other.num_elements,
+        };
+    }
+}
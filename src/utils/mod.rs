@@ -1,12 +1,23 @@
+pub mod atmosphere;
 pub mod camera;
+pub mod frustum;
 pub mod gl;
+pub mod grid;
 pub mod hashmap;
+pub mod labels;
+pub mod lighting;
 pub mod log;
 pub mod memory;
 pub mod orbits;
+pub mod point_colors;
 pub mod raycasting;
+pub mod shadows;
+pub mod star_catalog;
+pub mod starfield;
 pub mod string;
 pub mod transform;
+pub mod transits;
 pub mod units;
 pub mod vec;
+pub mod view_link;
 pub mod web;
@@ -1,17 +1,52 @@
+use crate::app_state::CoordinateFrame;
+use crate::managers::details_ui::{get_number_notation, get_velocity_unit, DetailsUI};
 use crate::managers::orbit::OrbitalElements;
 use crate::utils::log::*;
+use crate::utils::string::format_number;
 use crate::utils::transform::Transform;
-use crate::utils::units::{Au, Deg, Jdn, Rad, J2000_JDN};
+use crate::utils::units::{format_velocity, Au, Deg, Jdn, Mm, Rad, OBLIQUITY_OF_ECLIPTIC};
 use na::{Point3, UnitQuaternion, Vector3};
 use std::f64::consts::PI;
 
 /// Mm3 / (kg s2)
 pub const GRAVITATION_CONSTANT: f64 = 6.743E-29;
-pub const OBLIQUITY_OF_ECLIPTIC: Deg = Deg(23.4392794);
 const NEWTON_RAPHSON_MAX_ITER: u32 = 30;
 const NEWTON_RAPHSON_DELTA: f64 = 0.00000001;
 
-#[allow(dead_code)]
+/// Orbits that never get further than this from the ecliptic (as a fraction of their semi-major axis) don't have nodes
+const ECLIPTIC_HEIGHT_EPSILON: f64 = 1E-9;
+
+/// Unit vector towards right ascension `ra` and declination `dec`, in the J2000 equatorial frame (X towards the vernal
+/// equinox and Z towards the north celestial pole)
+pub fn ra_dec_to_unit_vector(ra: Rad, dec: Rad) -> Vector3<f64> {
+    return Vector3::new(
+        dec.0.cos() * ra.0.cos(),
+        dec.0.cos() * ra.0.sin(),
+        dec.0.sin(),
+    );
+}
+
+/// Rotates a vector from the J2000 equatorial frame into the J2000 ecliptic frame that our scenes use
+pub fn equatorial_to_ecliptic(vec: &Vector3<f64>) -> Vector3<f64> {
+    let obliquity = OBLIQUITY_OF_ECLIPTIC.to_rad().0;
+    return Vector3::new(
+        vec.x,
+        vec.y * obliquity.cos() + vec.z * obliquity.sin(),
+        -vec.y * obliquity.sin() + vec.z * obliquity.cos(),
+    );
+}
+
+/// Rotation from the axes of `frame` into our J2000 ecliptic frame, e.g. taking the Z axis to the north celestial pole
+/// for CoordinateFrame::Equatorial
+pub fn get_coordinate_frame_rotation(frame: CoordinateFrame) -> UnitQuaternion<f64> {
+    return match frame {
+        CoordinateFrame::Ecliptic => UnitQuaternion::identity(),
+        CoordinateFrame::Equatorial => {
+            UnitQuaternion::from_axis_angle(&Vector3::x_axis(), -OBLIQUITY_OF_ECLIPTIC.to_rad().0)
+        }
+    };
+}
+
 pub fn elements_to_circle_transform(elements: &OrbitalElements) -> Transform<f64> {
     let mut result = Transform::identity();
 
@@ -37,6 +72,22 @@ pub fn elements_to_circle_transform(elements: &OrbitalElements) -> Transform<f64
     return result;
 }
 
+/// World transform to draw the orbit line with world transform `line` (of a body at `body_pos` around a central body
+/// at `central_pos`) as the apparent orbit of the central body around the body instead, e.g. the Sun around the Earth.
+/// That mirrors every point of the line through the midpoint of the two bodies, which for the flat unit circle space
+/// of orbit lines is just half a turn around its local Z axis
+pub fn get_apparent_orbit_transform(
+    line: &Transform<f64>,
+    body_pos: &Vector3<f64>,
+    central_pos: &Vector3<f64>,
+) -> Transform<f64> {
+    return Transform {
+        trans: body_pos + central_pos - line.trans,
+        rot: line.rot * UnitQuaternion::from_axis_angle(&Vector3::z_axis(), PI),
+        scale: line.scale,
+    };
+}
+
 /// Returns the transform to rotate an ellipse with correct semi-major/minor axes into the correct
 /// orientation to match it's orbital elements. Corresponds to applying, in order:
 /// - Rotation around +Z with -arg_periapsis
@@ -75,6 +126,9 @@ pub fn elements_to_ellipse_rotation_transform(elements: &OrbitalElements) -> Tra
 /// - https://space.stackexchange.com/questions/19322/converting-orbital-elements-to-cartesian-state-vectors
 /// - https://downloads.rene-schwarz.com/download/M001-Keplerian_Orbit_Elements_to_Cartesian_State_Vectors.pdf
 ///
+/// `elements.mean_anomaly_0` is the mean anomaly at `elements.epoch`, which needn't be the scene's epoch (e.g. MPC
+/// elements are given at their own, recent epochs).
+///
 /// Returns (position, velocity) in world space (cartesian coordinates), in Mm and Mm / day (86400 s)
 pub fn orbital_elements_to_xyz(
     elements: &OrbitalElements,
@@ -82,10 +136,9 @@ pub fn orbital_elements_to_xyz(
     ellipse_rotation_transform: &Transform<f64>,
 ) -> (Point3<f64>, Vector3<f64>) {
     let mean_motion = 2.0 * PI / elements.sidereal_orbit_period_days; // Rads/day
-    let gravitation_const = mean_motion * mean_motion * elements.semi_major_axis.0.powi(3);
 
     // Calculate mean anomaly at t
-    let mean_anomaly = elements.mean_anomaly_0.0 + mean_motion * (t.0 - J2000_JDN.0);
+    let mean_anomaly = elements.mean_anomaly_0.0 + mean_motion * (t.0 - elements.epoch.0);
 
     // Find eccentric anomaly by solving Kepler's equation using Newton-Raphson
     let mut eccentric_anomaly = mean_anomaly;
@@ -109,6 +162,22 @@ pub fn orbital_elements_to_xyz(
         );
     }
 
+    return eccentric_anomaly_to_xyz(elements, Rad(eccentric_anomaly), ellipse_rotation_transform);
+}
+
+/// Finishes the calculation of orbital_elements_to_xyz once we already know the eccentric anomaly,
+/// e.g. from get_eccentric_anomaly or the angles used by bake_eccentric_anomaly_times.
+///
+/// Returns (position, velocity) in world space (cartesian coordinates), in Mm and Mm / day (86400 s)
+pub fn eccentric_anomaly_to_xyz(
+    elements: &OrbitalElements,
+    eccentric_anomaly: Rad,
+    ellipse_rotation_transform: &Transform<f64>,
+) -> (Point3<f64>, Vector3<f64>) {
+    let mean_motion = 2.0 * PI / elements.sidereal_orbit_period_days; // Rads/day
+    let gravitation_const = mean_motion * mean_motion * elements.semi_major_axis.0.powi(3);
+    let eccentric_anomaly = eccentric_anomaly.0;
+
     // Find true anomaly from eccentric anomaly
     let true_anomaly = 2.0
         * ((1.0 + elements.eccentricity).sqrt() * (eccentric_anomaly * 0.5).sin())
@@ -163,17 +232,55 @@ pub fn orbital_elements_to_xyz(
 /// during execution interpolate each time to find the eccentric anomaly for it, then just
 /// do the end of the calculation.
 ///
+/// Like for orbital_elements_to_xyz, `elements.mean_anomaly_0` is the mean anomaly at `elements.epoch`.
+///
 /// Note: if num_angles is N, N+1 values will be returned, because we want the time for angle 0 and also for 2pi
 pub fn bake_eccentric_anomaly_times(elements: &OrbitalElements, num_angles: u32) -> Vec<Jdn> {
+    let incr = 360.0 / num_angles as f64;
+    let eccentric_anomalies: Vec<Rad> = (0..=num_angles)
+        .map(|i| Rad((i as f64 * incr).to_radians()))
+        .collect();
+
+    return bake_times_for_eccentric_anomalies(elements, &eccentric_anomalies);
+}
+
+/// Positions relative to the central body (in Mm) along the orbit of `elements`, one for each of the
+/// `eccentric_anomalies` (e.g. from adaptive_eccentric_anomalies). If they go from 0 to 2pi the last one is back at
+/// the first, closing the orbit
+pub fn get_baked_orbit_points(
+    elements: &OrbitalElements,
+    eccentric_anomalies: &[Rad],
+    ellipse_rotation_transform: &Transform<f64>,
+) -> Vec<Point3<f64>> {
+    return eccentric_anomalies
+        .iter()
+        .map(|eccentric_anomaly| {
+            return eccentric_anomaly_to_xyz(
+                elements,
+                *eccentric_anomaly,
+                ellipse_rotation_transform,
+            )
+            .0;
+        })
+        .collect();
+}
+
+/// Like bake_eccentric_anomaly_times, but for arbitrary eccentric anomalies (e.g. from adaptive_eccentric_anomalies).
+/// Note that get_eccentric_anomaly assumes the angles are evenly spaced, so use get_sampled_eccentric_anomaly with
+/// these results instead
+pub fn bake_times_for_eccentric_anomalies(
+    elements: &OrbitalElements,
+    eccentric_anomalies: &[Rad],
+) -> Vec<Jdn> {
     let mut result: Vec<Jdn> = Vec::new();
-    result.reserve((num_angles + 1) as usize);
+    result.reserve(eccentric_anomalies.len());
 
     let mean_motion = 2.0 * PI / elements.sidereal_orbit_period_days; // Rads/day
-    let time_of_periapsis: Jdn = time_of_prev_periapsis(elements.mean_anomaly_0, mean_motion);
+    let time_of_periapsis: Jdn =
+        time_of_prev_periapsis(elements.mean_anomaly_0, mean_motion, elements.epoch);
 
-    let incr = 360.0 / num_angles as f64;
-    for i in 0..=num_angles {
-        let eccentric_anomaly = (i as f64 * incr).to_radians();
+    for eccentric_anomaly in eccentric_anomalies {
+        let eccentric_anomaly = eccentric_anomaly.0;
 
         let mean_anomaly = eccentric_anomaly - elements.eccentricity * eccentric_anomaly.sin(); // Rad
         let t = (mean_anomaly + mean_motion * time_of_periapsis.0) / mean_motion; // day
@@ -185,30 +292,110 @@ pub fn bake_eccentric_anomaly_times(elements: &OrbitalElements, num_angles: u32)
     return result;
 }
 
-#[allow(dead_code)]
-pub fn get_eccentric_anomaly(mut date: Jdn, orbital_period: f64, baked_times: &Vec<Jdn>) -> Rad {
-    while date > baked_times[baked_times.len() - 1] {
-        date.0 -= orbital_period;
+/// Returns num_samples + 1 eccentric anomalies from 0 to 2pi, concentrated where the ellipse curves the most
+/// so that every segment of a polyline through them has roughly the same chordal error.
+///
+/// The chordal error of a segment of length h is about curvature * h^2 / 8, so we want the spacing in arc length
+/// to go with 1 / sqrt(curvature). Parameterized by the eccentric anomaly E that means a sample density proportional
+/// to (sin^2 E + (1 - e^2) cos^2 E)^(-1/4), which we integrate numerically and invert
+pub fn adaptive_eccentric_anomalies(eccentricity: f64, num_samples: u32) -> Vec<Rad> {
+    const NUM_STEPS: usize = 4096;
+
+    // Clamp so that we still get something sensible for (near) parabolic orbits
+    let one_minus_e2 = (1.0 - eccentricity.powi(2)).max(0.000001);
+    let density = |eccentric_anomaly: f64| -> f64 {
+        return (eccentric_anomaly.sin().powi(2) + one_minus_e2 * eccentric_anomaly.cos().powi(2))
+            .powf(-0.25);
+    };
+
+    let step = 2.0 * PI / NUM_STEPS as f64;
+    let mut cumulative: Vec<f64> = Vec::new();
+    cumulative.reserve(NUM_STEPS + 1);
+    cumulative.push(0.0);
+    for i in 0..NUM_STEPS {
+        let mid = (i as f64 + 0.5) * step;
+        cumulative.push(cumulative[i] + density(mid) * step);
     }
+    let total = cumulative[NUM_STEPS];
+
+    let mut result: Vec<Rad> = Vec::new();
+    result.reserve((num_samples + 1) as usize);
+
+    let mut step_index = 0;
+    for i in 0..=num_samples {
+        let target = total * i as f64 / num_samples as f64;
+        while step_index < NUM_STEPS - 1 && cumulative[step_index + 1] < target {
+            step_index += 1;
+        }
 
+        let fraction = (target - cumulative[step_index])
+            / (cumulative[step_index + 1] - cumulative[step_index]);
+        result.push(Rad((step_index as f64 + fraction.max(0.0).min(1.0)) * step));
+    }
+
+    // Make sure we start and end exactly on periapsis, like bake_eccentric_anomaly_times
+    result[0] = Rad(0.0);
+    result[num_samples as usize] = Rad(2.0 * PI);
+    return result;
+}
+
+/// Interpolates the eccentric anomaly at `date` from the results of bake_eccentric_anomaly_times.
+/// Dates outside of the baked orbit (either before or after it) are wrapped back into it by whole orbital periods,
+/// and the result is always in [0, 2pi]
+pub fn get_eccentric_anomaly(date: Jdn, orbital_period: f64, baked_times: &Vec<Jdn>) -> Rad {
     // We may have 5 angles: [0, 90, 180, 270, 360], so we want an incr of 90 deg
     // so that if our binary search gives index 3 we just return 270
     let angle_incr = 2.0 * PI / ((baked_times.len() - 1) as f64);
 
+    return interpolate_eccentric_anomaly(date, orbital_period, baked_times, |index| {
+        angle_incr * index as f64
+    });
+}
+
+/// Like get_eccentric_anomaly, but for `baked_times` from bake_times_for_eccentric_anomalies, baked at each of
+/// `eccentric_anomalies` (e.g. from adaptive_eccentric_anomalies)
+pub fn get_sampled_eccentric_anomaly(
+    date: Jdn,
+    orbital_period: f64,
+    baked_times: &[Jdn],
+    eccentric_anomalies: &[Rad],
+) -> Rad {
+    return interpolate_eccentric_anomaly(date, orbital_period, baked_times, |index| {
+        eccentric_anomalies[index].0
+    });
+}
+
+/// Shared by get_eccentric_anomaly and get_sampled_eccentric_anomaly, where `angle_at` gives the eccentric anomaly
+/// that each of the `baked_times` was baked at
+fn interpolate_eccentric_anomaly(
+    mut date: Jdn,
+    orbital_period: f64,
+    baked_times: &[Jdn],
+    angle_at: impl Fn(usize) -> f64,
+) -> Rad {
+    while date > baked_times[baked_times.len() - 1] {
+        date.0 -= orbital_period;
+    }
+    while date < baked_times[0] {
+        date.0 += orbital_period;
+    }
+
     // TODO: We can probably speed up this search if we keep track of the last index we used, as it's likely
     // the same one
     match baked_times.binary_search_by(|p| p.partial_cmp(&date).unwrap()) {
         Ok(exact_index) => {
-            return Rad(angle_incr * exact_index as f64);
+            return Rad(angle_at(exact_index));
         }
         #[allow(non_snake_case)]
-        Err(prev_index) => {
-            let next_index = (prev_index + 1) % baked_times.len();
+        Err(next_index) => {
+            // We wrapped date into [first, last] so this is never 0 or len. It's the index of the first
+            // baked time that comes after date
+            let prev_index = next_index - 1;
 
             let prev_date = baked_times[prev_index];
             let next_date = baked_times[next_index];
-            let prev_E = angle_incr * prev_index as f64;
-            let next_E = angle_incr * next_index as f64;
+            let prev_E = angle_at(prev_index);
+            let next_E = angle_at(next_index);
 
             return Rad(
                 prev_E + (next_E - prev_E) * ((date.0 - prev_date.0) / (next_date.0 - prev_date.0))
@@ -217,15 +404,222 @@ pub fn get_eccentric_anomaly(mut date: Jdn, orbital_period: f64, baked_times: &V
     }
 }
 
-/// Returns the JDN time of the next periapsis crossing
+/// Returns the JDN time of the next periapsis crossing after `epoch`
 #[allow(dead_code)]
-pub fn time_of_next_periapsis(mean_anomaly_at_epoch: Rad, mean_motion: f64) -> Jdn {
-    return Jdn((2.0 * PI - mean_anomaly_at_epoch.0) / mean_motion + J2000_JDN.0);
+pub fn time_of_next_periapsis(mean_anomaly_at_epoch: Rad, mean_motion: f64, epoch: Jdn) -> Jdn {
+    return Jdn((2.0 * PI - mean_anomaly_at_epoch.0) / mean_motion + epoch.0);
+}
+
+/// Returns the time of the periapsis crossing immediately before `epoch`
+pub fn time_of_prev_periapsis(mean_anomaly_at_epoch: Rad, mean_motion: f64, epoch: Jdn) -> Jdn {
+    return Jdn((0.0 - mean_anomaly_at_epoch.0) / mean_motion + epoch.0);
+}
+
+/// Days until a body next goes through each of the notable points of its orbit
+#[derive(Debug, Clone, Default)]
+pub struct OrbitEventCountdowns {
+    pub periapsis: f64,
+    pub apoapsis: f64,
+    pub ascending_node: Option<f64>, // None if the orbit doesn't cross the ecliptic
+    pub descending_node: Option<f64>,
+}
+
+/// Sidereal period (days) of an orbit with semi-major axis `new_a`, around the same central body as an orbit with
+/// semi-major axis `old_a` that takes `old_period_days`. Kepler's third law, so that we don't need the central mass
+pub fn scale_orbital_period(old_period_days: f64, old_a: Mm, new_a: Mm) -> f64 {
+    if old_a.0 <= 0.0 {
+        return old_period_days;
+    }
+
+    return old_period_days * (new_a.0 / old_a.0).powf(1.5);
 }
 
-/// Returns the time of the periapsis crossing immediately before JDN
-pub fn time_of_prev_periapsis(mean_anomaly_at_epoch: Rad, mean_motion: f64) -> Jdn {
-    return Jdn((0.0 - mean_anomaly_at_epoch.0) / mean_motion + J2000_JDN.0);
+/// Days from `now` until a body with an orbital period of `period_days` next gets back to where it was (or will be)
+/// at `event_time`. Always in [0, period_days)
+pub fn time_until_next_occurrence(now: Jdn, event_time: Jdn, period_days: f64) -> f64 {
+    return (event_time.0 - now.0).rem_euclid(period_days);
+}
+
+/// Finds how long from `now` until the next periapsis, apoapsis and node crossings, given the `baked_times` from
+/// bake_times_for_eccentric_anomalies for the orbit, at each of the `eccentric_anomalies`. The nodes are where the
+/// orbit's z coordinate changes sign once rotated by `ellipse_rotation_transform`: Going up through the ecliptic for
+/// the ascending node, and down for the descending one. Returns None if there aren't enough baked times or the period
+/// isn't valid
+pub fn get_orbit_event_countdowns(
+    now: Jdn,
+    period_days: f64,
+    eccentricity: f64,
+    baked_times: &[Jdn],
+    eccentric_anomalies: &[Rad],
+    ellipse_rotation_transform: &Transform<f64>,
+) -> Option<OrbitEventCountdowns> {
+    if baked_times.len() < 3
+        || eccentric_anomalies.len() != baked_times.len()
+        || !(period_days > 0.0)
+    {
+        return None;
+    }
+
+    // The baked times go from one periapsis to the next
+    let num_angles = baked_times.len() - 1;
+    let time_at = |index: usize, fraction: f64| -> Jdn {
+        return Jdn(
+            baked_times[index].0 + fraction * (baked_times[index + 1].0 - baked_times[index].0)
+        );
+    };
+
+    let apoapsis_index = eccentric_anomalies
+        .iter()
+        .rposition(|eccentric_anomaly| eccentric_anomaly.0 <= PI)
+        .unwrap_or(0)
+        .min(num_angles - 1);
+    let (prev_angle, next_angle) = (
+        eccentric_anomalies[apoapsis_index].0,
+        eccentric_anomalies[apoapsis_index + 1].0,
+    );
+    let apoapsis_time = time_at(
+        apoapsis_index,
+        (PI - prev_angle) / (next_angle - prev_angle),
+    );
+
+    // Heights above the ecliptic of each baked angle, on an orbit with a semi-major axis of 1
+    let semi_minor_factor = (1.0 - eccentricity * eccentricity).max(0.0).sqrt();
+    let heights: Vec<f64> = eccentric_anomalies
+        .iter()
+        .map(|eccentric_anomaly| {
+            let eccentric_anomaly = eccentric_anomaly.0;
+            let plane_pos = Vector3::new(
+                eccentric_anomaly.cos() - eccentricity,
+                semi_minor_factor * eccentric_anomaly.sin(),
+                0.0,
+            );
+            return ellipse_rotation_transform.transform_vector(&plane_pos).z;
+        })
+        .collect();
+
+    let mut ascending_time: Option<Jdn> = None;
+    let mut descending_time: Option<Jdn> = None;
+    if heights
+        .iter()
+        .any(|height| height.abs() > ECLIPTIC_HEIGHT_EPSILON)
+    {
+        for i in 0..num_angles {
+            let (z0, z1) = (heights[i], heights[i + 1]);
+            if z0 <= 0.0 && z1 > 0.0 && ascending_time.is_none() {
+                ascending_time = Some(time_at(i, -z0 / (z1 - z0)));
+            } else if z0 >= 0.0 && z1 < 0.0 && descending_time.is_none() {
+                descending_time = Some(time_at(i, z0 / (z0 - z1)));
+            }
+        }
+    }
+
+    let until = |time: Jdn| time_until_next_occurrence(now, time, period_days);
+    return Some(OrbitEventCountdowns {
+        periapsis: until(baked_times[0]),
+        apoapsis: until(apoapsis_time),
+        ascending_node: ascending_time.map(until),
+        descending_node: descending_time.map(until),
+    });
+}
+
+/// Physical quantities describing the current motion of a body around a central body, computed from state vectors
+#[derive(Debug, Clone, Default)]
+pub struct OrbitReadouts {
+    pub speed: f64,                           // Mm/s, relative to the central body
+    pub specific_orbital_energy: Option<f64>, // Mm2/s2
+    pub specific_angular_momentum: f64,       // Mm2/s
+    pub time_to_periapsis: Option<f64>, // s, None if we never get to it (e.g. escaping) or can't tell
+}
+
+/// Computes readouts for a body at `rel_pos` (Mm) moving at `rel_vel` (Mm/s) with respect to a central body of
+/// `central_mass` (kg). Quantities that need the gravitational parameter are None if `central_mass` is unknown
+pub fn get_orbit_readouts(
+    rel_pos: &Vector3<f64>,
+    rel_vel: &Vector3<f64>,
+    central_mass: Option<f64>,
+) -> OrbitReadouts {
+    let mut result = OrbitReadouts::default();
+    result.speed = rel_vel.magnitude();
+    result.specific_angular_momentum = rel_pos.cross(rel_vel).magnitude();
+
+    let r = rel_pos.magnitude();
+    let mu = central_mass.unwrap_or(0.0) * GRAVITATION_CONSTANT;
+    if r <= 0.0 || mu <= 0.0 || !mu.is_finite() {
+        return result;
+    }
+
+    let energy = result.speed * result.speed / 2.0 - mu / r;
+    result.specific_orbital_energy = Some(energy);
+    if energy == 0.0 {
+        return result;
+    }
+
+    let semi_major_axis = -mu / (2.0 * energy);
+    let radial = rel_pos.dot(rel_vel);
+    let eccentricity =
+        (((result.speed * result.speed - mu / r) * rel_pos - radial * rel_vel) / mu).magnitude();
+    if eccentricity <= 0.0 {
+        return result;
+    }
+
+    if energy < 0.0 {
+        // Bound orbit: Wrap the mean anomaly into [0, 2pi) to find how long until it gets back to zero
+        let ecc_anomaly = (radial / (mu * semi_major_axis).sqrt()).atan2(1.0 - r / semi_major_axis);
+        let mean_anomaly = ecc_anomaly - eccentricity * ecc_anomaly.sin();
+        let mean_motion = (mu / semi_major_axis.powi(3)).sqrt();
+        result.time_to_periapsis = Some((-mean_anomaly).rem_euclid(2.0 * PI) / mean_motion);
+    } else if radial < 0.0 {
+        // Escape trajectory, but we're still on our way into periapsis
+        let hyp_anomaly = (radial / (eccentricity * (-mu * semi_major_axis).sqrt())).asinh();
+        let mean_anomaly = eccentricity * hyp_anomaly.sinh() - hyp_anomaly;
+        let mean_motion = (mu / (-semi_major_axis).powi(3)).sqrt();
+        result.time_to_periapsis = Some(-mean_anomaly / mean_motion);
+    }
+
+    return result;
+}
+
+impl DetailsUI for OrbitReadouts {
+    fn draw_details_ui(&mut self, ui: &mut egui::Ui) {
+        let notation = get_number_notation(ui);
+        let velocity_unit = get_velocity_unit(ui);
+
+        ui.columns(2, |cols| {
+            cols[0]
+                .label("Speed:")
+                .on_hover_text("Magnitude of the velocity relative to the central body");
+            cols[1].label(format_velocity(self.speed, velocity_unit, notation));
+        });
+
+        ui.columns(2, |cols| {
+            cols[0]
+                .label("Specific orbital energy [Mm²/s²]:")
+                .on_hover_text(
+                    "v²/2 - μ/r, per unit mass. Negative for bound orbits, positive when escaping",
+                );
+            cols[1].label(match self.specific_orbital_energy {
+                Some(energy) => format_number(energy, notation),
+                None => String::from("Unknown central body mass"),
+            });
+        });
+
+        ui.columns(2, |cols| {
+            cols[0]
+                .label("Specific angular momentum [Mm²/s]:")
+                .on_hover_text("|r × v|, per unit mass. Constant for an undisturbed orbit");
+            cols[1].label(format_number(self.specific_angular_momentum, notation));
+        });
+
+        ui.columns(2, |cols| {
+            cols[0]
+                .label("Time to periapsis [days]:")
+                .on_hover_text("Time until the next closest approach to the central body, assuming a two-body orbit");
+            cols[1].label(match self.time_to_periapsis {
+                Some(time_s) => format!("{:.3}", time_s / 86400.0),
+                None => String::from("-"),
+            });
+        });
+    }
 }
 
 // pub use tests::*;
@@ -233,7 +627,7 @@ pub mod tests {
     extern crate wasm_bindgen_test;
     use wasm_bindgen_test::*;
     wasm_bindgen_test_configure!(run_in_browser);
-    use crate::utils::units::J2000_JDN;
+    use crate::utils::units::{Mm, J2000_JDN};
 
     use super::*;
 
@@ -304,6 +698,71 @@ pub mod tests {
         assert!((vel - expected_vel).magnitude() < ACCEPTABLE_DELTA);
     }
 
+    /// Largest distance between the ellipse and the polyline that goes through it at `eccentric_anomalies`
+    fn max_chordal_error(elements: &OrbitalElements, eccentric_anomalies: &[Rad]) -> f64 {
+        const SUBDIVISIONS: u32 = 16;
+
+        let a = elements.semi_major_axis.0;
+        let b = a * (1.0 - elements.eccentricity.powi(2)).sqrt();
+        let point = |e: f64| -> Vector3<f64> { Vector3::new(a * e.cos(), b * e.sin(), 0.0) };
+
+        let mut max_error: f64 = 0.0;
+        for pair in eccentric_anomalies.windows(2) {
+            let start = point(pair[0].0);
+            let chord = point(pair[1].0) - start;
+
+            for i in 1..SUBDIVISIONS {
+                let e = pair[0].0 + (pair[1].0 - pair[0].0) * i as f64 / SUBDIVISIONS as f64;
+                let error = (point(e) - start).cross(&chord).magnitude() / chord.magnitude();
+                max_error = max_error.max(error);
+            }
+        }
+
+        return max_error;
+    }
+
+    #[wasm_bindgen_test]
+    pub fn adaptive_sampling_reduces_chordal_error() {
+        // Mercury
+        let elements = OrbitalElements {
+            ref_id: String::from("10"),
+            epoch: J2000_JDN,
+            semi_major_axis: Au(0.3870982121840369).to_Mm(),
+            eccentricity: 0.2056302929816634,
+            inclination: Deg(7.00501414069919).to_rad(),
+            long_asc_node: Deg(48.3305373398104).to_rad(),
+            arg_periapsis: Deg(29.12428280936123).to_rad(),
+            mean_anomaly_0: Deg(174.7958829506606).to_rad(),
+            sidereal_orbit_period_days: 87.96909804182887,
+        };
+
+        for num_samples in &[16, 64, 100] {
+            let uniform: Vec<Rad> = (0..=*num_samples)
+                .map(|i| Rad(2.0 * PI * i as f64 / *num_samples as f64))
+                .collect();
+            let adaptive = adaptive_eccentric_anomalies(elements.eccentricity, *num_samples);
+
+            assert_eq!(adaptive.len(), uniform.len());
+            assert!(adaptive.windows(2).all(|pair| pair[0].0 < pair[1].0));
+
+            let uniform_error = max_chordal_error(&elements, &uniform);
+            let adaptive_error = max_chordal_error(&elements, &adaptive);
+            assert!(
+                adaptive_error < uniform_error,
+                "Adaptive error {} Mm was not below uniform error {} Mm with {} samples",
+                adaptive_error,
+                uniform_error,
+                num_samples
+            );
+        }
+
+        // Circular orbits should just get evenly spaced samples
+        let circular = adaptive_eccentric_anomalies(0.0, 4);
+        for (i, angle) in circular.iter().enumerate() {
+            assert!((angle.0 - i as f64 * PI / 2.0).abs() < 0.00001);
+        }
+    }
+
     #[wasm_bindgen_test]
     pub fn test_bake_eccentric_anomaly_times() {
         // Mercury
@@ -1094,4 +1553,271 @@ pub mod tests {
         let res = bake_eccentric_anomaly_times(&elements, 360);
         assert_eq!(res, expected);
     }
+
+    #[wasm_bindgen_test]
+    pub fn get_eccentric_anomaly_wraps_across_periapsis() {
+        // Mercury
+        let elements = OrbitalElements {
+            ref_id: String::from("10"),
+            epoch: J2000_JDN,
+            semi_major_axis: Au(0.3870982121840369).to_Mm(),
+            eccentricity: 0.2056302929816634,
+            inclination: Deg(7.00501414069919).to_rad(),
+            long_asc_node: Deg(48.3305373398104).to_rad(),
+            arg_periapsis: Deg(29.12428280936123).to_rad(),
+            mean_anomaly_0: Deg(174.7958829506606).to_rad(),
+            sidereal_orbit_period_days: 87.96909804182887,
+        };
+        let period = elements.sidereal_orbit_period_days;
+        let baked = bake_eccentric_anomaly_times(&elements, 360);
+
+        let quarter = baked[90].0;
+        for orbits in &[-3.0, -1.0, 0.0, 1.0, 3.0] {
+            let e = get_eccentric_anomaly(Jdn(quarter + orbits * period), period, &baked);
+            assert!((e.0 - PI / 2.0).abs() < 1E-6);
+        }
+
+        // Halfway between two baked times lands between their angles
+        let mid = get_eccentric_anomaly(Jdn((baked[10].0 + baked[11].0) * 0.5), period, &baked);
+        assert!(mid.0 > 10.0_f64.to_radians() && mid.0 < 11.0_f64.to_radians());
+
+        // Stepping backwards over periapsis goes from just after 0 to just before 2pi
+        let after = get_eccentric_anomaly(Jdn(baked[0].0 + 0.001), period, &baked);
+        let before = get_eccentric_anomaly(Jdn(baked[0].0 - 0.001), period, &baked);
+        assert!(after.0 > 0.0 && after.0 < 1.0_f64.to_radians());
+        assert!(before.0 < 2.0 * PI && before.0 > 359.0_f64.to_radians());
+    }
+
+    #[wasm_bindgen_test]
+    pub fn sampled_eccentric_anomaly_follows_uneven_samples() {
+        // Mercury
+        let elements = OrbitalElements {
+            ref_id: String::from("10"),
+            epoch: J2000_JDN,
+            semi_major_axis: Au(0.3870982121840369).to_Mm(),
+            eccentricity: 0.2056302929816634,
+            inclination: Deg(7.00501414069919).to_rad(),
+            long_asc_node: Deg(48.3305373398104).to_rad(),
+            arg_periapsis: Deg(29.12428280936123).to_rad(),
+            mean_anomaly_0: Deg(174.7958829506606).to_rad(),
+            sidereal_orbit_period_days: 87.96909804182887,
+        };
+        let period = elements.sidereal_orbit_period_days;
+        let rotation = elements_to_ellipse_rotation_transform(&elements);
+        let anomalies = adaptive_eccentric_anomalies(elements.eccentricity, 360);
+        let baked = bake_times_for_eccentric_anomalies(&elements, &anomalies);
+
+        // Lands on the samples themselves, even a few orbits away
+        for (time, anomaly) in baked.iter().zip(anomalies.iter()).step_by(37) {
+            let date = Jdn(time.0 + 2.0 * period);
+            let e = get_sampled_eccentric_anomaly(date, period, &baked, &anomalies);
+            assert!((e.0 - anomaly.0).abs() < 1E-6);
+        }
+
+        // In between them it puts the body about where solving Kepler's equation does
+        for i in 0..100 {
+            let date = Jdn(baked[0].0 + period * i as f64 / 100.0);
+            let e = get_sampled_eccentric_anomaly(date, period, &baked, &anomalies);
+            let sampled = eccentric_anomaly_to_xyz(&elements, e, &rotation).0;
+            let exact = orbital_elements_to_xyz(&elements, date, &rotation).0;
+            assert!((sampled - exact).magnitude() < 1E-4 * elements.semi_major_axis.0);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    pub fn orbit_readouts_match_two_body_orbits() {
+        let central_mass = 1.989E30;
+        let mu = central_mass * GRAVITATION_CONSTANT;
+
+        // Circular orbit
+        let radius = 149597.87;
+        let circular_speed = (mu / radius).sqrt();
+        let readouts = get_orbit_readouts(
+            &Vector3::new(radius, 0.0, 0.0),
+            &Vector3::new(0.0, circular_speed, 0.0),
+            Some(central_mass),
+        );
+        assert!((readouts.speed - circular_speed).abs() < 1E-12);
+        let energy = readouts.specific_orbital_energy.unwrap();
+        assert!((energy + mu / (2.0 * radius)).abs() / energy.abs() < 1E-9);
+        assert!((readouts.specific_angular_momentum - (mu * radius).sqrt()).abs() < 1E-3);
+
+        // At apoapsis of an ellipse we're half an orbit away from periapsis
+        let semi_major_axis = 100000.0;
+        let eccentricity = 0.5;
+        let apoapsis = semi_major_axis * (1.0 + eccentricity);
+        let apoapsis_speed = (mu * (2.0 / apoapsis - 1.0 / semi_major_axis)).sqrt();
+        let readouts = get_orbit_readouts(
+            &Vector3::new(-apoapsis, 0.0, 0.0),
+            &Vector3::new(0.0, -apoapsis_speed, 0.0),
+            Some(central_mass),
+        );
+        let half_period = PI * (semi_major_axis.powi(3) / mu).sqrt();
+        assert!((readouts.time_to_periapsis.unwrap() - half_period).abs() / half_period < 1E-6);
+
+        // Nothing that needs mu when we don't know the central mass
+        let readouts = get_orbit_readouts(
+            &Vector3::new(radius, 0.0, 0.0),
+            &Vector3::new(0.0, circular_speed, 0.0),
+            None,
+        );
+        assert!(readouts.specific_orbital_energy.is_none());
+        assert!(readouts.time_to_periapsis.is_none());
+        assert!(readouts.speed > 0.0);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn orbit_event_countdowns_find_the_next_events() {
+        // Wraps around whole periods, from either side
+        assert!((time_until_next_occurrence(Jdn(10.0), Jdn(13.0), 5.0) - 3.0).abs() < 1E-9);
+        assert!((time_until_next_occurrence(Jdn(10.0), Jdn(27.0), 5.0) - 2.0).abs() < 1E-9);
+        assert!((time_until_next_occurrence(Jdn(10.0), Jdn(-6.0), 5.0) - 4.0).abs() < 1E-9);
+        assert_eq!(time_until_next_occurrence(Jdn(10.0), Jdn(10.0), 5.0), 0.0);
+
+        let elements = OrbitalElements {
+            ref_id: String::from("10"),
+            epoch: J2000_JDN,
+            semi_major_axis: Mm(1000.0),
+            eccentricity: 0.3,
+            inclination: Deg(20.0).to_rad(),
+            long_asc_node: Deg(60.0).to_rad(),
+            arg_periapsis: Deg(110.0).to_rad(),
+            mean_anomaly_0: Deg(30.0).to_rad(),
+            sidereal_orbit_period_days: 100.0,
+        };
+        let period = elements.sidereal_orbit_period_days;
+        let rotation = elements_to_ellipse_rotation_transform(&elements);
+        let anomalies = adaptive_eccentric_anomalies(elements.eccentricity, 360);
+        let baked = bake_times_for_eccentric_anomalies(&elements, &anomalies);
+        let pos_at = |t: f64| orbital_elements_to_xyz(&elements, Jdn(t), &rotation).0;
+
+        for now in &[
+            J2000_JDN.0 - 250.0,
+            J2000_JDN.0,
+            J2000_JDN.0 + 37.3,
+            J2000_JDN.0 + 1000.0,
+        ] {
+            let countdowns = get_orbit_event_countdowns(
+                Jdn(*now),
+                period,
+                elements.eccentricity,
+                &baked,
+                &anomalies,
+                &rotation,
+            )
+            .unwrap();
+            let ascending = countdowns.ascending_node.unwrap();
+            let descending = countdowns.descending_node.unwrap();
+            for days in &[
+                countdowns.periapsis,
+                countdowns.apoapsis,
+                ascending,
+                descending,
+            ] {
+                assert!(*days >= 0.0 && *days < period);
+            }
+
+            // Closest and furthest from the central body
+            let periapsis = pos_at(now + countdowns.periapsis).coords.magnitude();
+            let apoapsis = pos_at(now + countdowns.apoapsis).coords.magnitude();
+            assert!((periapsis - 700.0).abs() < 1E-3, "{}", periapsis);
+            assert!((apoapsis - 1300.0).abs() < 1E-3, "{}", apoapsis);
+            assert!(
+                ((countdowns.apoapsis - countdowns.periapsis).abs() - period / 2.0).abs() < 1E-6
+            );
+
+            // Crossing the ecliptic going up and going down
+            assert!(pos_at(now + ascending).z.abs() < 0.5);
+            assert!(pos_at(now + ascending + 1.0).z > 0.0);
+            assert!(pos_at(now + descending).z.abs() < 0.5);
+            assert!(pos_at(now + descending + 1.0).z < 0.0);
+        }
+
+        // Orbits on the ecliptic have no nodes
+        let flat = OrbitalElements {
+            inclination: Rad(0.0),
+            ..elements.clone()
+        };
+        let flat_rotation = elements_to_ellipse_rotation_transform(&flat);
+        let flat_baked = bake_times_for_eccentric_anomalies(&flat, &anomalies);
+        let countdowns = get_orbit_event_countdowns(
+            J2000_JDN,
+            period,
+            flat.eccentricity,
+            &flat_baked,
+            &anomalies,
+            &flat_rotation,
+        )
+        .unwrap();
+        assert!(countdowns.ascending_node.is_none() && countdowns.descending_node.is_none());
+
+        assert!(
+            get_orbit_event_countdowns(J2000_JDN, 0.0, 0.3, &baked, &anomalies, &rotation)
+                .is_none()
+        );
+    }
+
+    #[wasm_bindgen_test]
+    pub fn ra_dec_to_unit_vector_for_vega() {
+        // Vega (J2000): RA 18h 36m 56.34s, Dec +38° 47' 01.3"
+        let ra = Deg((18.0 + 36.0 / 60.0 + 56.34 / 3600.0) * 15.0).to_rad();
+        let dec = Deg(38.0 + 47.0 / 60.0 + 1.3 / 3600.0).to_rad();
+
+        let equatorial = ra_dec_to_unit_vector(ra, dec);
+        let expected = Vector3::new(0.1250967, -0.7694130, 0.6263820);
+        assert!((equatorial - expected).magnitude() < 1E-6, "{}", equatorial);
+        assert!((equatorial.magnitude() - 1.0).abs() < ACCEPTABLE_DELTA);
+
+        // Ecliptic longitude 285.32° and latitude 61.73°
+        let ecliptic = equatorial_to_ecliptic(&equatorial);
+        let longitude = ecliptic.y.atan2(ecliptic.x).to_degrees().rem_euclid(360.0);
+        let latitude = ecliptic.z.asin().to_degrees();
+        assert!((longitude - 285.316).abs() < 1E-3, "{}", longitude);
+        assert!((latitude - 61.733).abs() < 1E-3, "{}", latitude);
+
+        // The north celestial pole is tilted towards +Y by the obliquity
+        let pole = equatorial_to_ecliptic(&Vector3::z());
+        assert!((pole.z.acos() - OBLIQUITY_OF_ECLIPTIC.to_rad().0).abs() < ACCEPTABLE_DELTA);
+        assert!(pole.y > 0.0);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn apparent_orbit_goes_around_the_body() {
+        let moon_elements = OrbitalElements {
+            semi_major_axis: Mm(384.4),
+            eccentricity: 0.0549,
+            inclination: Deg(5.145).to_rad(),
+            long_asc_node: Deg(125.08).to_rad(),
+            arg_periapsis: Deg(318.15).to_rad(),
+            ..OrbitalElements::default()
+        };
+        let earth_pos = Vector3::new(-26500.0, 144700.0, -3.0);
+        let line = Transform {
+            trans: earth_pos,
+            ..Transform::identity()
+        }
+        .concat_clone(&elements_to_circle_transform(&moon_elements));
+
+        let unit_circle = |angle: f64| Point3::new(angle.cos(), angle.sin(), 0.0);
+        let moon_pos = line.transform_point(&unit_circle(1.0)).coords;
+        let apparent = get_apparent_orbit_transform(&line, &moon_pos, &earth_pos);
+
+        // Seen from the Moon, the Earth goes through the same shape of orbit with the same distances
+        for i in 0..16 {
+            let u = unit_circle(i as f64 * 2.0 * PI / 16.0);
+            let orbit_point = line.transform_point(&u).coords;
+            let apparent_point = apparent.transform_point(&u).coords;
+
+            assert!((apparent_point - (moon_pos + earth_pos - orbit_point)).magnitude() < 1E-6);
+            assert!(
+                ((apparent_point - moon_pos).magnitude() - (orbit_point - earth_pos).magnitude())
+                    .abs()
+                    < 1E-6
+            );
+        }
+
+        // ...and is currently where the Moon is on its own orbit
+        let earth_now = apparent.transform_point(&unit_circle(1.0)).coords;
+        assert!((earth_now - earth_pos).magnitude() < 1E-6);
+    }
 }
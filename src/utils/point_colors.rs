@@ -0,0 +1,216 @@
+use crate::app_state::PointColorMode;
+use crate::components::MetadataComponent;
+use crate::utils::string::decode_hex;
+
+/// Semi-major axes at either end of the gradient used by PointColorMode::SemiMajorAxis, in Mm (about 0.1 and 100 AU).
+/// The gradient is logarithmic in between
+pub const SEMI_MAJOR_AXIS_RANGE_MM: (f64, f64) = (1.496E4, 1.496E7);
+
+/// Used when a body doesn't have the value we're coloring by (e.g. no orbital elements)
+pub const UNKNOWN_COLOR: [f32; 3] = [0.3, 0.3, 0.3];
+
+/// Body radii that get the smallest and largest point markers, in Mm (about 1 km and the Sun's radius).
+/// Marker sizes are logarithmic in between
+pub const POINT_SIZE_RADIUS_RANGE_MM: (f64, f64) = (1E-3, 7E2);
+
+/// Body types in the order they're shown in the legend, as they are written into the "body_type" metadata
+pub const BODY_TYPES: [&str; 8] = [
+    "Star",
+    "Planet",
+    "Satellite",
+    "Asteroid",
+    "Comet",
+    "Artificial",
+    "Barycenter",
+    "Other",
+];
+
+/// Returns the color of the point marker for a body, with the point size in pixels as the last component
+pub fn get_point_color(mode: PointColorMode, metadata: &MetadataComponent) -> [f32; 4] {
+    let body_type = metadata
+        .get_metadata("body_type")
+        .and_then(|s| Some(s.as_str()))
+        .unwrap_or("");
+
+    let mut color: [f32; 4] = match body_type {
+        "Satellite" => [0.8, 0.8, 0.8, 1.5],
+        "Asteroid" => [1.0, 1.0, 1.0, 1.0],
+        "Star" => [1.0, 0.5, 0.0, 2.5],
+        "Planet" => [0.6, 0.8, 0.6, 2.0],
+        "Comet" => [0.4, 0.6, 0.8, 1.0],
+        "Artificial" => [1.0, 1.0, 1.0, 1.0],
+        "Barycenter" => [0.5, 0.5, 0.5, 0.5],
+        _ => [1.0, 1.0, 1.0, 1.0],
+    };
+
+    let rgb = match mode {
+        PointColorMode::Default => {
+            let mut rgb = [color[0], color[1], color[2]];
+            if let Some(base_color) = metadata.get_metadata("base_color") {
+                if let Ok(bytes) = decode_hex(base_color) {
+                    for (channel, byte) in rgb.iter_mut().zip(bytes.iter()) {
+                        *channel = *byte as f32 / 255.0;
+                    }
+                }
+            }
+            rgb
+        }
+        PointColorMode::BodyType => get_body_type_color(body_type),
+        PointColorMode::Eccentricity => get_metadata_f64(metadata, "body_eccentricity")
+            .and_then(|e| Some(get_gradient_color(e as f32)))
+            .unwrap_or(UNKNOWN_COLOR),
+        PointColorMode::SemiMajorAxis => get_metadata_f64(metadata, "body_semi_major_axis")
+            .and_then(|a| Some(get_gradient_color(get_semi_major_axis_fraction(a))))
+            .unwrap_or(UNKNOWN_COLOR),
+    };
+
+    color[0] = rgb[0];
+    color[1] = rgb[1];
+    color[2] = rgb[2];
+    return color;
+}
+
+/// Returns the size in pixels of the point marker for a body, from its radius in log scale, clamped to `size_range_px`.
+/// Bodies without a radius keep the size of their body type (the last component of get_point_color), also clamped
+pub fn get_point_size(
+    metadata: &MetadataComponent,
+    type_size: f32,
+    size_range_px: (f32, f32),
+) -> f32 {
+    let (min_px, max_px) = size_range_px;
+
+    let size = match get_metadata_f64(metadata, "body_radius").filter(|r| *r > 0.0) {
+        Some(radius) => {
+            let (min, max) = POINT_SIZE_RADIUS_RANGE_MM;
+            let fraction = ((radius.ln() - min.ln()) / (max.ln() - min.ln())) as f32;
+            min_px + (max_px - min_px) * fraction
+        }
+        None => type_size,
+    };
+
+    return size.max(min_px).min(max_px.max(min_px));
+}
+
+/// Saturated colors that are easy to tell apart, used by PointColorMode::BodyType
+pub fn get_body_type_color(body_type: &str) -> [f32; 3] {
+    return match body_type {
+        "Star" => [1.0, 0.8, 0.1],
+        "Planet" => [0.2, 0.9, 0.3],
+        "Satellite" => [0.1, 0.8, 0.9],
+        "Asteroid" => [0.9, 0.5, 0.2],
+        "Comet" => [0.4, 0.5, 1.0],
+        "Artificial" => [1.0, 0.3, 0.8],
+        "Barycenter" => [0.6, 0.6, 0.6],
+        _ => [1.0, 1.0, 1.0],
+    };
+}
+
+/// Maps `t` in [0, 1] from blue through cyan, green and yellow to red. Values outside that range are clamped
+pub fn get_gradient_color(t: f32) -> [f32; 3] {
+    const STOPS: [[f32; 3]; 5] = [
+        [0.1, 0.2, 1.0],
+        [0.1, 0.8, 1.0],
+        [0.2, 1.0, 0.2],
+        [1.0, 0.9, 0.1],
+        [1.0, 0.1, 0.1],
+    ];
+
+    let scaled = t.max(0.0).min(1.0) * (STOPS.len() - 1) as f32;
+    let index = (scaled.floor() as usize).min(STOPS.len() - 2);
+    let fraction = scaled - index as f32;
+
+    let start = STOPS[index];
+    let end = STOPS[index + 1];
+    return [
+        start[0] + (end[0] - start[0]) * fraction,
+        start[1] + (end[1] - start[1]) * fraction,
+        start[2] + (end[2] - start[2]) * fraction,
+    ];
+}
+
+/// Where `semi_major_axis` (in Mm) falls within SEMI_MAJOR_AXIS_RANGE_MM, in log scale. Not clamped
+pub fn get_semi_major_axis_fraction(semi_major_axis: f64) -> f32 {
+    let (min, max) = SEMI_MAJOR_AXIS_RANGE_MM;
+    return ((semi_major_axis.max(1E-9).ln() - min.ln()) / (max.ln() - min.ln())) as f32;
+}
+
+fn get_metadata_f64(metadata: &MetadataComponent, key: &str) -> Option<f64> {
+    return metadata
+        .get_metadata(key)
+        .and_then(|s| s.parse::<f64>().ok());
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    pub fn point_colors_follow_mode() {
+        let mut metadata = MetadataComponent::default();
+        metadata.set_metadata("body_type", "Asteroid");
+        metadata.set_metadata("base_color", "ff0000");
+
+        // Default keeps the base color and the point size of the body type
+        assert_eq!(
+            get_point_color(PointColorMode::Default, &metadata),
+            [1.0, 0.0, 0.0, 1.0]
+        );
+
+        // We don't have orbital elements for it yet
+        let color = get_point_color(PointColorMode::Eccentricity, &metadata);
+        assert_eq!([color[0], color[1], color[2]], UNKNOWN_COLOR);
+
+        metadata.set_metadata("body_eccentricity", "1.0");
+        metadata.set_metadata("body_semi_major_axis", "1496");
+        let color = get_point_color(PointColorMode::Eccentricity, &metadata);
+        assert_eq!([color[0], color[1], color[2]], get_gradient_color(1.0));
+        let color = get_point_color(PointColorMode::SemiMajorAxis, &metadata);
+        assert_eq!([color[0], color[1], color[2]], get_gradient_color(0.0));
+
+        let color = get_point_color(PointColorMode::BodyType, &metadata);
+        assert_eq!(
+            [color[0], color[1], color[2]],
+            get_body_type_color("Asteroid")
+        );
+        assert_eq!(color[3], 1.0);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn semi_major_axis_gradient_is_logarithmic() {
+        let (min, max) = SEMI_MAJOR_AXIS_RANGE_MM;
+        assert!(get_semi_major_axis_fraction(min).abs() < 1E-6);
+        assert!((get_semi_major_axis_fraction(max) - 1.0).abs() < 1E-6);
+        assert!((get_semi_major_axis_fraction((min * max).sqrt()) - 0.5).abs() < 1E-6);
+
+        assert_eq!(get_gradient_color(-1.0), get_gradient_color(0.0));
+        assert_eq!(get_gradient_color(2.0), get_gradient_color(1.0));
+    }
+
+    #[wasm_bindgen_test]
+    pub fn point_sizes_follow_radius() {
+        let range = (2.0, 10.0);
+        let (min, max) = POINT_SIZE_RADIUS_RANGE_MM;
+
+        let mut metadata = MetadataComponent::default();
+        assert_eq!(get_point_size(&metadata, 1.0, range), 2.0);
+        assert_eq!(get_point_size(&metadata, 20.0, range), 10.0);
+
+        metadata.set_metadata("body_radius", &min.to_string());
+        assert!((get_point_size(&metadata, 1.0, range) - 2.0).abs() < 1E-4);
+
+        metadata.set_metadata("body_radius", &(min * max).sqrt().to_string());
+        assert!((get_point_size(&metadata, 1.0, range) - 6.0).abs() < 1E-4);
+
+        metadata.set_metadata("body_radius", &max.to_string());
+        assert!((get_point_size(&metadata, 1.0, range) - 10.0).abs() < 1E-4);
+
+        // Tiny and huge bodies are clamped so they remain clickable and don't cover everything
+        metadata.set_metadata("body_radius", "1E-9");
+        assert_eq!(get_point_size(&metadata, 1.0, range), 2.0);
+        metadata.set_metadata("body_radius", "1E9");
+        assert_eq!(get_point_size(&metadata, 1.0, range), 10.0);
+    }
+}
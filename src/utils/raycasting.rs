@@ -1,5 +1,7 @@
+use crate::managers::resource::collider::{Collider, MeshCollider};
 use crate::managers::scene::Scene;
 use na::{Matrix4, Point3, RealField, SimdRealField, Vector3};
+use std::rc::Rc;
 
 pub const INTERSECTION_EPSILON: f32 = 1e-6;
 
@@ -35,18 +37,32 @@ pub fn raycast(ray: &Ray<f64>, scene: &Scene) -> Option<RaycastHit<f64>> {
             continue;
         }
 
-        let mesh = mesh_comp.get_mesh();
-        if mesh.is_none() {
+        let mesh_rc = mesh_comp.get_mesh();
+        if mesh_rc.is_none() {
             continue;
         }
-        let mesh = mesh.as_ref().unwrap().borrow();
+        let mesh_rc = mesh_rc.unwrap();
+        let mesh = mesh_rc.borrow();
 
-        if mesh.collider.is_none() {
-            continue;
-        }
-        let collider = mesh.collider.as_ref().unwrap();
+        // Precise raycasts go against the triangles of the mesh itself, using its regular collider as an early out
+        let precise_collider;
+        let collider: &dyn Collider = if mesh_comp.precise_raycasting {
+            precise_collider = MeshCollider {
+                mesh: Rc::downgrade(&mesh_rc),
+                additional_outer_collider: None,
+            };
+            &precise_collider
+        } else {
+            match &mesh.collider {
+                Some(collider) => collider.as_ref(),
+                None => continue,
+            }
+        };
 
-        let world_trans: Matrix4<f64> = trans_comp.get_world_transform().to_matrix4();
+        // Against the actual size of the bodies, regardless of how large we draw them
+        let world_trans: Matrix4<f64> = trans_comp
+            .get_physical_world_transform(scene.render_scale)
+            .to_matrix4();
         let inv_world_trans = world_trans.try_inverse().unwrap();
 
         let bb_space_dir_64 = inv_world_trans.transform_vector(&ray.direction).normalize();
@@ -76,6 +92,52 @@ pub fn raycast(ray: &Ray<f64>, scene: &Scene) -> Option<RaycastHit<f64>> {
     return None;
 }
 
+/// Returns the index of the point marker drawn under the canvas position `x`, `y` (with 0,0 on top left), preferring
+/// the one nearest to the camera if several overlap. `pos_buffer` and `color_buffer` are the points mesh buffers,
+/// with the clip space position and the RGB + size in pixels of each point, so that we test against the footprint
+/// of the markers exactly as they are drawn: Discs as wide as their size, see points.frag
+pub fn pick_point_marker(
+    pos_buffer: &[f32],
+    color_buffer: &[f32],
+    x: i32,
+    y: i32,
+    canvas_width: u32,
+    canvas_height: u32,
+) -> Option<u32> {
+    let mut min_w = std::f32::INFINITY;
+    let mut result: Option<u32> = None;
+
+    for (index, (pos, color)) in pos_buffer
+        .chunks_exact(4)
+        .zip(color_buffer.chunks_exact(4))
+        .enumerate()
+    {
+        // Behind the camera (this also skips the hidden points parked off screen, as they have w of 1.0 but
+        // xy far outside the canvas)
+        let w = pos[3];
+        if w <= 0.0 {
+            continue;
+        }
+
+        let canvas_x = (pos[0] / w + 1.0) * 0.5 * (canvas_width - 1) as f32;
+        let canvas_y = (1.0 - pos[1] / w) * 0.5 * (canvas_height - 1) as f32;
+
+        // gl_PointSize is the width of a square centered on the point, and the marker is the disc inscribed in it
+        let radius = color[3] * 0.5;
+        let (dx, dy) = (canvas_x - x as f32, canvas_y - y as f32);
+        if dx * dx + dy * dy > radius * radius {
+            continue;
+        }
+
+        if w < min_w {
+            min_w = w;
+            result = Some(index as u32);
+        }
+    }
+
+    return result;
+}
+
 // Source: https://tavianator.com/2015/ray_box_nan.html
 pub fn aabb_ray_intersection(mins: &Point3<f32>, maxes: &Point3<f32>, ray: &Ray<f32>) -> f32 {
     let mut t1: f32 = (mins[0] - ray.start[0]) / ray.direction[0];
@@ -162,3 +224,63 @@ pub fn triangle_ray_intersection(
     }
     return None;
 }
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    pub fn point_markers_are_picked_by_footprint() {
+        // Canvas center, the same point further away, and a point behind the camera
+        let pos_buffer = [
+            0.0, 0.0, 0.0, 1.0, //
+            0.0, 0.0, 0.0, 2.0, //
+            0.0, 0.0, 0.0, -1.0, //
+        ];
+        let color_buffer = [
+            1.0, 1.0, 1.0, 4.0, //
+            1.0, 1.0, 1.0, 20.0, //
+            1.0, 1.0, 1.0, 100.0, //
+        ];
+
+        // Both overlap at the center and we pick the nearest one
+        assert_eq!(
+            pick_point_marker(&pos_buffer, &color_buffer, 50, 50, 101, 101),
+            Some(0)
+        );
+
+        // Only the large marker covers this
+        assert_eq!(
+            pick_point_marker(&pos_buffer, &color_buffer, 58, 50, 101, 101),
+            Some(1)
+        );
+
+        assert_eq!(
+            pick_point_marker(&pos_buffer, &color_buffer, 80, 50, 101, 101),
+            None
+        );
+
+        // Markers are round at every size: Inside the square of gl_PointSize but past the corner of the disc misses
+        for size in &[4.0, 9.0, 20.0, 40.0] {
+            let color_buffer = [1.0, 1.0, 1.0, *size];
+            let pos_buffer = [0.0, 0.0, 0.0, 1.0];
+            let pick = |dx: i32, dy: i32| {
+                pick_point_marker(&pos_buffer, &color_buffer, 50 + dx, 50 + dy, 101, 101)
+            };
+
+            // In whole pixels, rounded towards the inside for hits and the outside for misses
+            let radius = size / 2.0;
+            let inside = radius.floor() as i32;
+            let diagonal = (radius * 0.5_f32.sqrt()).floor() as i32;
+            let outside = radius.ceil() as i32;
+            assert_eq!(pick(inside, 0), Some(0), "{}", size);
+            assert_eq!(pick(0, -inside), Some(0), "{}", size);
+            assert_eq!(pick(diagonal, diagonal), Some(0), "{}", size);
+            assert_eq!(pick(outside, outside), None, "{}", size);
+            assert_eq!(pick(-outside, outside), None, "{}", size);
+        }
+    }
+}
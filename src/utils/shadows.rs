@@ -0,0 +1,184 @@
+use na::{Point3, Vector3};
+
+/// Sphere that may block the light from reaching other bodies. Coordinates are in whatever space the caller uses
+/// (the rendering system uses view space), in Mm
+pub struct Occluder {
+    pub index: usize, // Entity index, so that bodies don't shadow themselves
+    pub center: Point3<f64>,
+    pub radius: f64,
+}
+
+/// Returns the occluder closest to the receiver sphere that may cast any shadow (umbra or penumbra) on it from a
+/// spherical light at `light_pos`. Occluders that contain the light (i.e. the star itself) are ignored
+pub fn find_occluder<'a>(
+    receiver_index: usize,
+    receiver_center: &Point3<f64>,
+    receiver_radius: f64,
+    light_pos: &Point3<f64>,
+    light_radius: f64,
+    occluders: &'a [Occluder],
+) -> Option<&'a Occluder> {
+    let to_light = light_pos - receiver_center;
+    let light_dist = to_light.magnitude();
+    if light_dist <= 0.0 {
+        return None;
+    }
+    let light_dir = to_light / light_dist;
+
+    // Half-angle that the light's disk covers when seen from the receiver
+    let light_ang = (light_radius / light_dist).min(1.0).asin();
+
+    let mut nearest: Option<&'a Occluder> = None;
+    let mut nearest_dist = std::f64::INFINITY;
+    for occluder in occluders {
+        if occluder.index == receiver_index {
+            continue;
+        }
+
+        if (light_pos - occluder.center).magnitude_squared() <= occluder.radius * occluder.radius {
+            continue;
+        }
+
+        // Must be between the receiver and the light
+        let to_occluder = occluder.center - receiver_center;
+        let along = to_occluder.dot(&light_dir);
+        if along <= 0.0 || along >= light_dist {
+            continue;
+        }
+
+        // The penumbra widens with distance behind the occluder, roughly by the light's angular radius
+        let off_axis = (to_occluder - light_dir * along).magnitude();
+        if off_axis > occluder.radius + receiver_radius + along * light_ang {
+            continue;
+        }
+
+        if along < nearest_dist {
+            nearest_dist = along;
+            nearest = Some(occluder);
+        }
+    }
+
+    return nearest;
+}
+
+/// Fraction of the spherical light at `light_pos` that reaches `pos` around the spherical occluder.
+///
+/// This is the same cheap approximation done by sphere_shadow in functions.glsl (which is what actually gets drawn):
+/// The overlap between the light and occluder disks is interpolated linearly in angle, instead of computing the
+/// actual area of the lens between both circles
+pub fn sphere_shadow(
+    pos: &Point3<f64>,
+    light_pos: &Point3<f64>,
+    light_radius: f64,
+    occluder_center: &Point3<f64>,
+    occluder_radius: f64,
+) -> f64 {
+    if occluder_radius <= 0.0 {
+        return 1.0;
+    }
+
+    let to_light: Vector3<f64> = light_pos - pos;
+    let to_occluder: Vector3<f64> = occluder_center - pos;
+    let light_dist = to_light.magnitude();
+    let occluder_dist = to_occluder.magnitude();
+    if to_light.dot(&to_occluder) <= 0.0 || occluder_dist >= light_dist {
+        return 1.0;
+    }
+
+    let light_ang = (light_radius / light_dist).min(1.0).asin();
+    let occluder_ang = (occluder_radius / occluder_dist).min(1.0).asin();
+    let separation = to_light
+        .cross(&to_occluder)
+        .magnitude()
+        .atan2(to_light.dot(&to_occluder));
+
+    // If the occluder looks smaller than the light it can only ever cover part of it (annular eclipse)
+    let max_cover = (occluder_ang / light_ang.max(1E-9)).powi(2).min(1.0);
+    let visible = smoothstep(
+        occluder_ang - light_ang,
+        occluder_ang + light_ang,
+        separation,
+    );
+    return 1.0 - (1.0 - visible) * max_cover;
+}
+
+fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    let t = ((x - edge0) / (edge1 - edge0)).max(0.0).min(1.0);
+    return t * t * (3.0 - 2.0 * t);
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+
+    const SUN_RADIUS: f64 = 695.7;
+    const EARTH_RADIUS: f64 = 6.371;
+    const MOON_RADIUS: f64 = 1.737;
+    const AU: f64 = 149597.871;
+    const EARTH_MOON_DIST: f64 = 384.4;
+
+    /// Returns the light that reaches the center of the Moon, when the Moon is `moon_offset` Mm off of the
+    /// Sun-Earth line on the night side of the Earth
+    fn moon_light_fraction(moon_offset: f64) -> f64 {
+        let sun = Point3::new(0.0, 0.0, 0.0);
+        let occluders = vec![
+            Occluder {
+                index: 0,
+                center: sun,
+                radius: SUN_RADIUS,
+            },
+            Occluder {
+                index: 1,
+                center: Point3::new(AU, 0.0, 0.0),
+                radius: EARTH_RADIUS,
+            },
+        ];
+        let moon = Point3::new(AU + EARTH_MOON_DIST, moon_offset, 0.0);
+
+        let occluder = find_occluder(2, &moon, MOON_RADIUS, &sun, SUN_RADIUS, &occluders);
+        if occluder.is_none() {
+            return 1.0;
+        }
+        let occluder = occluder.unwrap();
+        assert_eq!(occluder.index, 1);
+
+        return sphere_shadow(&moon, &sun, SUN_RADIUS, &occluder.center, occluder.radius);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn lunar_eclipse_darkens_moon() {
+        // Total eclipse: The Moon sits in the middle of the Earth's umbra
+        assert_eq!(moon_light_fraction(0.0), 0.0);
+
+        // Partially inside the penumbra
+        let penumbral = moon_light_fraction(EARTH_RADIUS);
+        assert!(penumbral > 0.0 && penumbral < 1.0);
+
+        // A day later the Moon has moved about 13 degrees along its orbit and is out of the shadow again
+        let after = moon_light_fraction(EARTH_MOON_DIST * 13.0_f64.to_radians().sin());
+        assert_eq!(after, 1.0);
+    }
+
+    #[wasm_bindgen_test]
+    pub fn star_and_self_dont_occlude() {
+        let sun = Point3::new(0.0, 0.0, 0.0);
+        let occluders = vec![
+            Occluder {
+                index: 0,
+                center: sun,
+                radius: SUN_RADIUS,
+            },
+            Occluder {
+                index: 1,
+                center: Point3::new(AU, 0.0, 0.0),
+                radius: EARTH_RADIUS,
+            },
+        ];
+
+        let earth = Point3::new(AU, 0.0, 0.0);
+        assert!(find_occluder(1, &earth, EARTH_RADIUS, &sun, SUN_RADIUS, &occluders).is_none());
+    }
+}
@@ -0,0 +1,207 @@
+use crate::utils::orbits::{equatorial_to_ecliptic, ra_dec_to_unit_vector};
+use crate::utils::starfield::{Star, STAR_SIZE_RANGE};
+use crate::utils::units::{Deg, Rad};
+
+/// Magnitude of the brightest star in the night sky (Sirius), which gets the biggest and brightest point
+pub const CATALOG_BRIGHTEST_MAG: f32 = -1.5;
+
+/// Anything brighter than this can only be our own Sun, which HYG lists as its first star
+const CATALOG_SUN_MAG: f32 = -5.0;
+
+/// B-V color index of a Sun-like, white-ish star, for the stars that don't have one
+const DEFAULT_COLOR_INDEX: f32 = 0.65;
+
+/// Milliarcseconds per year to radians per year
+const MAS_TO_RAD: f64 = std::f64::consts::PI / (180.0 * 3600.0 * 1000.0);
+
+#[derive(Clone, Debug)]
+pub struct CatalogStar {
+    pub ra: Rad,                  // J2000 right ascension
+    pub dec: Rad,                 // J2000 declination
+    pub mag: f32,                 // Apparent visual magnitude
+    pub color_index: Option<f32>, // B-V
+    pub pm_ra: f64,               // Proper motion in right ascension (times cos(dec)), mas/year
+    pub pm_dec: f64,              // Proper motion in declination, mas/year
+}
+
+/// Parses a CSV star catalog with a header row, like the HYG database (https://github.com/astronexus/HYG-Database).
+/// Needs the "ra" (hours), "dec" (degrees) and "mag" columns, and uses "ci" (B-V color index) and "pmra"/"pmdec"
+/// (mas/year) if they're there. Other columns are ignored, as are rows we can't parse
+pub fn parse_star_catalog(text: &str) -> Result<Vec<CatalogStar>, String> {
+    let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+
+    let header: Vec<String> = match lines.next() {
+        Some(line) => line
+            .split(',')
+            .map(|col| col.trim().trim_matches('"').to_lowercase())
+            .collect(),
+        None => return Err(String::from("Star catalog is empty")),
+    };
+    let find_column = |name: &str| header.iter().position(|col| col == name);
+
+    let (ra_col, dec_col, mag_col) =
+        match (find_column("ra"), find_column("dec"), find_column("mag")) {
+            (Some(ra), Some(dec), Some(mag)) => (ra, dec, mag),
+            _ => {
+                return Err(format!(
+                    "Star catalog needs 'ra', 'dec' and 'mag' columns, but has {:?}",
+                    header
+                ))
+            }
+        };
+    let ci_col = find_column("ci");
+    let pm_ra_col = find_column("pmra");
+    let pm_dec_col = find_column("pmdec");
+
+    let mut result = Vec::new();
+    for line in lines {
+        let fields: Vec<&str> = line
+            .split(',')
+            .map(|f| f.trim().trim_matches('"'))
+            .collect();
+        let field = |col: Option<usize>| -> Option<f64> {
+            return col
+                .and_then(|col| fields.get(col))
+                .and_then(|f| f.parse::<f64>().ok());
+        };
+
+        let (ra_hours, dec_deg, mag) = match (
+            field(Some(ra_col)),
+            field(Some(dec_col)),
+            field(Some(mag_col)),
+        ) {
+            (Some(ra), Some(dec), Some(mag)) => (ra, dec, mag as f32),
+            _ => continue,
+        };
+        if mag < CATALOG_SUN_MAG {
+            continue;
+        }
+
+        result.push(CatalogStar {
+            ra: Deg(ra_hours * 15.0).to_rad(),
+            dec: Deg(dec_deg).to_rad(),
+            mag,
+            color_index: field(ci_col).map(|ci| ci as f32),
+            pm_ra: field(pm_ra_col).unwrap_or(0.0),
+            pm_dec: field(pm_dec_col).unwrap_or(0.0),
+        });
+    }
+
+    return Ok(result);
+}
+
+/// Approximate color of a star with B-V color index `bv`. Uses Ballesteros' formula for the temperature, and then
+/// a fit of the color of a blackbody at that temperature
+pub fn color_index_to_rgb(bv: f32) -> [f32; 3] {
+    let bv = bv.max(-0.4).min(2.0);
+    let temperature = 4600.0 * (1.0 / (0.92 * bv + 1.7) + 1.0 / (0.92 * bv + 0.62));
+    let t = temperature / 100.0;
+
+    let r = if t <= 66.0 {
+        255.0
+    } else {
+        329.698727446 * (t - 60.0).powf(-0.1332047592)
+    };
+    let g = if t <= 66.0 {
+        99.4708025861 * t.ln() - 161.1195681661
+    } else {
+        288.1221695283 * (t - 60.0).powf(-0.0755148492)
+    };
+    let b = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.5177312231 * (t - 10.0).ln() - 305.0447927307
+    };
+
+    let to_unit = |c: f32| (c / 255.0).max(0.0).min(1.0);
+    return [to_unit(r), to_unit(g), to_unit(b)];
+}
+
+/// Stars of `catalog` that are at least as bright as `mag_limit`, with directions in our J2000 ecliptic frame moved
+/// along their proper motions by `years_since_j2000`. Brighter stars get bigger and brighter points, like the ones from
+/// generate_starfield
+pub fn get_catalog_stars(
+    catalog: &[CatalogStar],
+    mag_limit: f32,
+    years_since_j2000: f64,
+) -> Vec<Star> {
+    let mag_range = (mag_limit - CATALOG_BRIGHTEST_MAG).max(0.1);
+
+    let mut result = Vec::new();
+    for star in catalog.iter().filter(|star| star.mag <= mag_limit) {
+        let dec = star.dec.0 + star.pm_dec * MAS_TO_RAD * years_since_j2000;
+        let ra =
+            star.ra.0 + star.pm_ra * MAS_TO_RAD * years_since_j2000 / star.dec.0.cos().max(1E-6);
+        let dir = equatorial_to_ecliptic(&ra_dec_to_unit_vector(Rad(ra), Rad(dec)));
+
+        let brightness = ((mag_limit - star.mag) / mag_range).max(0.0).min(1.0);
+        let intensity = 0.15 + 0.85 * brightness;
+        let size = STAR_SIZE_RANGE.0 + (STAR_SIZE_RANGE.1 - STAR_SIZE_RANGE.0) * brightness;
+        let rgb = color_index_to_rgb(star.color_index.unwrap_or(DEFAULT_COLOR_INDEX));
+
+        result.push(Star {
+            dir,
+            color: [
+                intensity * rgb[0],
+                intensity * rgb[1],
+                intensity * rgb[2],
+                size,
+            ],
+        });
+    }
+
+    return result;
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    pub fn parse_catalog_and_place_stars() {
+        let catalog = parse_star_catalog(
+            "id,proper,ra,dec,mag,ci,pmra,pmdec
+            0,Sol,0.000000,0.000000,-26.700,0.656,0.00,0.00
+            32263,Sirius,6.752481,-16.716116,-1.440,0.009,-546.01,-1223.08
+            91262,Vega,18.615649,38.783692,0.030,-0.001,201.02,287.46
+            80763,Antares,16.490128,-26.432002,1.060,1.865,-10.16,-23.21
+            99999,Faint,1.0,2.0,9.5,,,
+            12345,Broken,abc,2.0,1.0,0.5,0,0",
+        )
+        .unwrap();
+
+        // Sun and the unparseable row are skipped
+        assert_eq!(catalog.len(), 4);
+        assert!((catalog[0].mag - -1.44).abs() < 1E-6);
+        assert!((catalog[3].mag - 9.5).abs() < 1E-6);
+        assert_eq!(catalog[3].color_index, None);
+        assert_eq!(catalog[3].pm_ra, 0.0);
+
+        // Faint stars are culled, and brighter stars are bigger
+        let stars = get_catalog_stars(&catalog, 6.5, 0.0);
+        assert_eq!(stars.len(), 3);
+        assert!(stars[0].color[3] > stars[1].color[3]);
+        assert!(stars[1].color[3] > stars[2].color[3]);
+
+        // Vega is white-blue and Antares red
+        assert!(stars[1].color[2] >= stars[1].color[0]);
+        assert!(stars[2].color[0] > stars[2].color[2] * 1.5);
+
+        // Vega's ecliptic latitude is about 61.73 degrees
+        let latitude = stars[1].dir.z.asin().to_degrees();
+        assert!((latitude - 61.733).abs() < 0.01, "{}", latitude);
+
+        // A thousand years of proper motion moves Sirius by about 22 arcminutes
+        let later = get_catalog_stars(&catalog, 6.5, 1000.0);
+        let arcmin = stars[0].dir.angle(&later[0].dir).to_degrees() * 60.0;
+        assert!((arcmin - 22.3).abs() < 0.5, "{}", arcmin);
+
+        assert!(parse_star_catalog("").is_err());
+        assert!(parse_star_catalog("ra,dec\n1.0,2.0").is_err());
+    }
+}
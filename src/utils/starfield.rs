@@ -0,0 +1,93 @@
+use na::Vector3;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Fraction of the camera's far plane distance that we place the starfield sphere at, so that it never
+/// gets clipped
+pub const STARFIELD_RADIUS_FRACTION: f64 = 0.5;
+
+/// Range of point sizes for stars, in pixels
+pub const STAR_SIZE_RANGE: (f32, f32) = (1.0, 3.0);
+
+/// What the stars of a starfield were generated from, so that we know when to regenerate them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StarfieldSource {
+    Procedural {
+        num_stars: u32,
+        seed: u64,
+    },
+    Catalog {
+        mag_limit: f32,
+        years_since_j2000: i32,
+    }, // Whole years, as proper motions are very slow
+}
+
+#[derive(Clone)]
+pub struct Star {
+    pub dir: Vector3<f64>, // Unit vector from the camera towards the star
+    pub color: [f32; 4], // RGB with the point size in pixels as the last component, like get_point_color
+}
+
+/// Generates `num_stars` stars uniformly distributed over the unit sphere. The same `seed` always
+/// generates the same stars
+pub fn generate_starfield(num_stars: u32, seed: u64) -> Vec<Star> {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let mut result = Vec::with_capacity(num_stars as usize);
+    for _ in 0..num_stars {
+        // Uniform on the sphere: Uniform z and uniform angle around it
+        let z: f64 = rng.gen_range(-1.0..=1.0);
+        let angle: f64 = rng.gen_range(0.0..std::f64::consts::TAU);
+        let xy = (1.0 - z * z).max(0.0).sqrt();
+        let dir = Vector3::new(xy * angle.cos(), xy * angle.sin(), z);
+
+        // Most stars should be dim, with only a few bright ones
+        let brightness: f32 = rng.gen::<f32>().powf(4.0);
+        let intensity = 0.15 + 0.85 * brightness;
+        let size = STAR_SIZE_RANGE.0 + (STAR_SIZE_RANGE.1 - STAR_SIZE_RANGE.0) * brightness;
+
+        // Slight tint from reddish to bluish
+        let tint: f32 = rng.gen_range(-1.0..=1.0) * 0.15;
+
+        result.push(Star {
+            dir,
+            color: [
+                intensity * (1.0 + tint).min(1.0),
+                intensity * (1.0 - tint.abs() * 0.5),
+                intensity * (1.0 - tint).min(1.0),
+                size,
+            ],
+        });
+    }
+
+    return result;
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    pub fn starfield_is_reproducible() {
+        let first = generate_starfield(500, 42);
+        let second = generate_starfield(500, 42);
+        let other = generate_starfield(500, 43);
+
+        assert_eq!(first.len(), 500);
+        for (a, b) in first.iter().zip(second.iter()) {
+            assert_eq!(a.dir, b.dir);
+            assert_eq!(a.color, b.color);
+        }
+        assert!(first.iter().zip(other.iter()).any(|(a, b)| a.dir != b.dir));
+
+        for star in first.iter() {
+            assert!((star.dir.magnitude() - 1.0).abs() < 1E-9);
+            assert!(star.color[3] >= STAR_SIZE_RANGE.0 && star.color[3] <= STAR_SIZE_RANGE.1);
+        }
+
+        assert!(generate_starfield(0, 42).is_empty());
+    }
+}
@@ -1,6 +1,15 @@
+use crate::app_state::NumberNotation;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::num::ParseIntError;
 
+/// How many significant digits format_number keeps
+const SIGNIFICANT_DIGITS: i32 = 4;
+
+/// SI prefixes for each power of 1000, starting at 1E-12
+const SI_PREFIXES: [&str; 11] = ["p", "n", "µ", "m", "", "k", "M", "G", "T", "P", "E"];
+const SI_PREFIXES_UNIT_INDEX: i32 = 4;
+
 pub fn remove_numbered_suffix(s: &str) -> &str {
     let mut split_index: usize = s.len();
     for (i, c) in s.char_indices().rev() {
@@ -34,3 +43,129 @@ pub fn decode_hex(s: &str) -> Result<Vec<u8>, ParseIntError> {
         .map(|i| u8::from_str_radix(&s[i..i + 2], 16))
         .collect()
 }
+
+/// Scores how well `pattern` matches `candidate` as a case-insensitive subsequence.
+/// Returns None if not all characters of `pattern` appear in order in `candidate`.
+/// Consecutive matches and matches at the start of words score higher, and gaps are penalized
+pub fn fuzzy_match_score(pattern: &str, candidate: &str) -> Option<i32> {
+    if pattern.is_empty() {
+        return Some(0);
+    }
+
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+
+    let mut score: i32 = 0;
+    let mut pattern_index: usize = 0;
+    let mut last_match: Option<usize> = None;
+    let mut prev_char: Option<char> = None;
+
+    for (i, c) in candidate.to_lowercase().chars().enumerate() {
+        if pattern_index < pattern.len() && c == pattern[pattern_index] {
+            score += 1;
+
+            // Start of the candidate or of a word
+            if prev_char.is_none() || !prev_char.unwrap().is_alphanumeric() {
+                score += 8;
+            }
+
+            match last_match {
+                Some(last) if last + 1 == i => score += 5,
+                Some(last) => score -= (i - last - 1).min(5) as i32,
+                None => score -= i.min(5) as i32,
+            }
+
+            last_match = Some(i);
+            pattern_index += 1;
+        }
+
+        prev_char = Some(c);
+    }
+
+    if pattern_index < pattern.len() {
+        return None;
+    }
+
+    return Some(score);
+}
+
+/// Formats `value` with SIGNIFICANT_DIGITS significant digits and no trailing decimal zeroes. NumberNotation::Fixed
+/// scales it to an SI prefix (e.g. "149.6k"), and NumberNotation::Scientific uses an exponent instead (e.g. "1.496e5").
+/// Values outside of the range of the SI prefixes are always shown in scientific notation
+pub fn format_number(value: f64, notation: NumberNotation) -> String {
+    if value == 0.0 {
+        return String::from("0");
+    }
+    if !value.is_finite() {
+        return format!("{}", value);
+    }
+
+    // Round first, so that e.g. 999.96 becomes 1k instead of 1000
+    let exponent = value.abs().log10().floor() as i32;
+    let scale = 10.0_f64.powi(SIGNIFICANT_DIGITS - 1 - exponent);
+    let value = (value * scale).round() / scale;
+    let exponent = value.abs().log10().floor() as i32;
+
+    if notation == NumberNotation::Fixed {
+        let thousands = exponent.div_euclid(3);
+        let prefix = usize::try_from(thousands + SI_PREFIXES_UNIT_INDEX)
+            .ok()
+            .and_then(|index| SI_PREFIXES.get(index));
+
+        if let Some(prefix) = prefix {
+            let scaled = value / 10.0_f64.powi(thousands * 3);
+            let decimals = (SIGNIFICANT_DIGITS - 1 - (exponent - thousands * 3)).max(0) as usize;
+            return format!(
+                "{}{}",
+                trim_decimal_zeroes(format!("{:.*}", decimals, scaled)),
+                prefix
+            );
+        }
+    }
+
+    let mantissa = value / 10.0_f64.powi(exponent);
+    let decimals = (SIGNIFICANT_DIGITS - 1) as usize;
+    return format!(
+        "{}e{}",
+        trim_decimal_zeroes(format!("{:.*}", decimals, mantissa)),
+        exponent
+    );
+}
+
+fn trim_decimal_zeroes(text: String) -> String {
+    if !text.contains('.') {
+        return text;
+    }
+
+    return text.trim_end_matches('0').trim_end_matches('.').to_owned();
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    pub fn number_formatting_boundaries() {
+        let fixed = |value: f64| format_number(value, NumberNotation::Fixed);
+        let scientific = |value: f64| format_number(value, NumberNotation::Scientific);
+
+        assert_eq!(fixed(0.0), "0");
+        assert_eq!(scientific(0.0), "0");
+
+        assert_eq!(fixed(12.0), "12");
+        assert_eq!(fixed(149597.87), "149.6k");
+        assert_eq!(scientific(149597.87), "1.496e5");
+        assert_eq!(fixed(999.96), "1k");
+        assert_eq!(fixed(-0.00123), "-1.23m");
+
+        assert_eq!(fixed(1E12), "1T");
+        assert_eq!(scientific(1E12), "1e12");
+
+        // Past the smallest SI prefix
+        assert_eq!(fixed(1.5E-20), "1.5e-20");
+        assert_eq!(scientific(1.5E-20), "1.5e-20");
+        assert_eq!(fixed(1E-6), "1µ");
+    }
+}
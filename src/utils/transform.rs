@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 
 // Heavily based off of how Amethyst has a custom wrapper over nalgebra stuff: https://docs.amethyst.rs/stable/src/amethyst_core/transform/components/transform.rs.html#500-508
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Transform<T>
 where
     T: RealField + SimdRealField,
@@ -64,6 +64,15 @@ where
             .transform_vector(&self.scale.component_mul(&vector));
     }
 
+    /// Inverse of transform_point. Assumes scale has no zero components
+    pub fn inverse_transform_point(&self, point: &Point3<T>) -> Point3<T> {
+        return Point3::from(
+            self.rot
+                .inverse_transform_vector(&(point.coords - self.trans))
+                .component_div(&self.scale),
+        );
+    }
+
     pub fn identity() -> Self {
         Self {
             trans: Vector3::zeros(),
@@ -0,0 +1,196 @@
+use crate::utils::units::Jdn;
+use na::Point3;
+
+/// How many samples we take across the shortest orbital period involved in a transit search. Separation minima are
+/// at least around half a relative orbit apart, so this is plenty to never step over one
+pub const TRANSIT_SAMPLES_PER_ORBIT: f64 = 360.0;
+
+/// Upper bound on the samples taken by a single search, so that long date ranges don't freeze the UI
+pub const MAX_TRANSIT_SAMPLES: u32 = 200000;
+
+/// Golden section iterations when refining each minimum, enough to shrink a bracket by over 1E12
+const REFINE_ITERATIONS: u32 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransitKind {
+    Transit,     // The nearer body looks smaller, and crosses in front of the farther one
+    Occultation, // The nearer body looks larger, and hides the farther one (e.g. a solar eclipse)
+}
+
+#[derive(Debug, Clone)]
+pub struct TransitEvent {
+    pub time: Jdn, // When the two bodies look closest together
+    pub kind: TransitKind,
+    pub first_is_nearer: bool, // Whether the first body of the search is the one in front
+    pub separation_rad: f64,   // Angle between the two body centers at `time`
+}
+
+/// Picks a step for find_transits from the orbital periods (in days) of the bodies involved
+pub fn get_transit_scan_step(periods_days: &[f64]) -> f64 {
+    let shortest = periods_days
+        .iter()
+        .cloned()
+        .filter(|p| *p > 0.0)
+        .fold(f64::INFINITY, f64::min);
+
+    if !shortest.is_finite() {
+        return 1.0;
+    }
+    return shortest / TRANSIT_SAMPLES_PER_ORBIT;
+}
+
+/// Scans from `start` to `end` for times when the disks of two bodies overlap as seen by an observer.
+/// `get_positions` returns the positions of the observer and of the two bodies at a time, and `radii` has the radii
+/// of the two bodies, all in Mm.
+///
+/// We sample the angular separation every `step_days` and refine each of its local minima with a golden section
+/// search. Looking for minima instead of for samples that overlap is what finds transits much shorter than the step
+pub fn find_transits<F>(
+    start: Jdn,
+    end: Jdn,
+    step_days: f64,
+    radii: (f64, f64),
+    get_positions: F,
+) -> Vec<TransitEvent>
+where
+    F: Fn(Jdn) -> [Point3<f64>; 3],
+{
+    let mut result = Vec::new();
+    if end.0 <= start.0 || step_days <= 0.0 {
+        return result;
+    }
+
+    let num_steps =
+        (((end.0 - start.0) / step_days).ceil().max(2.0) as u32).min(MAX_TRANSIT_SAMPLES);
+    let step_days = (end.0 - start.0) / num_steps as f64;
+    let get_separation = |t: f64| get_angular_separation(&get_positions(Jdn(t)));
+
+    let separations: Vec<f64> = (0..=num_steps)
+        .map(|i| get_separation(start.0 + i as f64 * step_days))
+        .collect();
+
+    for i in 1..num_steps as usize {
+        if separations[i] > separations[i - 1] || separations[i] >= separations[i + 1] {
+            continue;
+        }
+
+        let time = refine_minimum(
+            &get_separation,
+            start.0 + (i - 1) as f64 * step_days,
+            start.0 + (i + 1) as f64 * step_days,
+        );
+
+        let [observer, first, second] = get_positions(Jdn(time));
+        let first_dist = (first - observer).magnitude();
+        let second_dist = (second - observer).magnitude();
+        let first_angular_radius = (radii.0 / first_dist).min(1.0).asin();
+        let second_angular_radius = (radii.1 / second_dist).min(1.0).asin();
+
+        let separation_rad = get_angular_separation(&[observer, first, second]);
+        if separation_rad >= first_angular_radius + second_angular_radius {
+            continue;
+        }
+
+        let first_is_nearer = first_dist < second_dist;
+        let nearer_looks_larger = if first_is_nearer {
+            first_angular_radius > second_angular_radius
+        } else {
+            second_angular_radius > first_angular_radius
+        };
+
+        result.push(TransitEvent {
+            time: Jdn(time),
+            kind: if nearer_looks_larger {
+                TransitKind::Occultation
+            } else {
+                TransitKind::Transit
+            },
+            first_is_nearer,
+            separation_rad,
+        });
+    }
+
+    return result;
+}
+
+/// Angle between the directions from `positions[0]` to `positions[1]` and to `positions[2]`, in radians
+fn get_angular_separation(positions: &[Point3<f64>; 3]) -> f64 {
+    let first_dir = positions[1] - positions[0];
+    let second_dir = positions[2] - positions[0];
+
+    // More precise than acos for the tiny angles we care about
+    return first_dir
+        .cross(&second_dir)
+        .magnitude()
+        .atan2(first_dir.dot(&second_dir));
+}
+
+/// Golden section search for the minimum of `f` between `a` and `b`
+fn refine_minimum<F>(f: &F, mut a: f64, mut b: f64) -> f64
+where
+    F: Fn(f64) -> f64,
+{
+    let ratio = (5.0_f64.sqrt() - 1.0) / 2.0;
+
+    let mut c = b - ratio * (b - a);
+    let mut d = a + ratio * (b - a);
+    let mut fc = f(c);
+    let mut fd = f(d);
+    for _ in 0..REFINE_ITERATIONS {
+        if fc < fd {
+            b = d;
+            d = c;
+            fd = fc;
+            c = b - ratio * (b - a);
+            fc = f(c);
+        } else {
+            a = c;
+            c = d;
+            fc = fd;
+            d = a + ratio * (b - a);
+            fd = f(d);
+        }
+    }
+
+    return (a + b) / 2.0;
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    pub fn transits_are_found_between_samples() {
+        // A moon of radius 1 going around the observer every day at distance 10, and a body of radius 5 sitting still
+        // at distance 100. The moon is right in front of it at every whole day, and looks twice as large
+        let get_positions = |t: Jdn| -> [Point3<f64>; 3] {
+            let angle = t.0 * std::f64::consts::TAU;
+            return [
+                Point3::origin(),
+                Point3::new(10.0 * angle.cos(), 10.0 * angle.sin(), 0.0),
+                Point3::new(100.0, 0.0, 0.0),
+            ];
+        };
+
+        // Overlaps only last about 0.024 days, much less than this step
+        let events = find_transits(Jdn(0.3), Jdn(3.3), 0.15, (1.0, 5.0), get_positions);
+        assert_eq!(events.len(), 3);
+        for (event, day) in events.iter().zip(1..=3) {
+            assert!((event.time.0 - day as f64).abs() < 1E-6);
+            assert!(event.separation_rad < 1E-6);
+            assert!(event.first_is_nearer);
+            assert_eq!(event.kind, TransitKind::Occultation);
+        }
+
+        // A moon smaller than its disk only transits it
+        let events = find_transits(Jdn(0.3), Jdn(1.3), 0.15, (0.1, 5.0), get_positions);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, TransitKind::Transit);
+
+        assert_eq!(get_transit_scan_step(&[]), 1.0);
+        assert_eq!(get_transit_scan_step(&[0.0, 720.0, 36.0]), 0.1);
+    }
+}
@@ -1,8 +1,52 @@
+use crate::app_state::{DistanceUnit, NumberNotation, TimeFormat, VelocityUnit};
+use crate::utils::string::format_number;
 use chrono::{TimeZone, Utc};
 use serde::{Deserialize, Serialize};
 
 pub const J2000_JDN: Jdn = Jdn(2451545.0);
 
+/// Offset between Julian Dates and Modified Julian Dates, which start at midnight of nov 17th, 1858
+pub const MJD_OFFSET_DAYS: f64 = 2400000.5;
+
+/// TT - TAI, in seconds
+const TT_MINUS_TAI_S: f64 = 32.184;
+
+/// Modified Julian Dates (UTC) at which each leap second so far took effect, and TAI - UTC in seconds from then on.
+/// See https://hpiers.obspm.fr/eop-pc/earthor/utc/UTC.html
+const LEAP_SECONDS: [(f64, f64); 28] = [
+    (41317.0, 10.0), // 1972-01-01
+    (41499.0, 11.0), // 1972-07-01
+    (41683.0, 12.0), // 1973-01-01
+    (42048.0, 13.0), // 1974-01-01
+    (42413.0, 14.0), // 1975-01-01
+    (42778.0, 15.0), // 1976-01-01
+    (43144.0, 16.0), // 1977-01-01
+    (43509.0, 17.0), // 1978-01-01
+    (43874.0, 18.0), // 1979-01-01
+    (44239.0, 19.0), // 1980-01-01
+    (44786.0, 20.0), // 1981-07-01
+    (45151.0, 21.0), // 1982-07-01
+    (45516.0, 22.0), // 1983-07-01
+    (46247.0, 23.0), // 1985-07-01
+    (47161.0, 24.0), // 1988-01-01
+    (47892.0, 25.0), // 1990-01-01
+    (48257.0, 26.0), // 1991-01-01
+    (48804.0, 27.0), // 1992-07-01
+    (49169.0, 28.0), // 1993-07-01
+    (49534.0, 29.0), // 1994-07-01
+    (50083.0, 30.0), // 1996-01-01
+    (50630.0, 31.0), // 1997-07-01
+    (51179.0, 32.0), // 1999-01-01
+    (53736.0, 33.0), // 2006-01-01
+    (54832.0, 34.0), // 2009-01-01
+    (56109.0, 35.0), // 2012-07-01
+    (57204.0, 36.0), // 2015-07-01
+    (57754.0, 37.0), // 2017-01-01
+];
+
+/// Angle between the J2000 ecliptic and the celestial equator
+pub const OBLIQUITY_OF_ECLIPTIC: Deg = Deg(23.4392794);
+
 #[derive(Clone, Copy, Default, Debug, Serialize, Deserialize)]
 pub struct Rad(pub f64);
 impl Rad {
@@ -51,13 +95,206 @@ impl Eq for Jdn {}
 
 /// Returns the corresponding Julian Day Number (JDN) for an instant in time.
 /// See https://en.wikipedia.org/wiki/Julian_day
-#[allow(dead_code)]
 pub fn date_to_julian_date_number(date: &chrono::DateTime<Utc>) -> Jdn {
     return Jdn((date.timestamp() as f64) / 86400.0 + 2440587.5);
 }
 
+/// Returns the Julian Day Number (JDN) for a number of milliseconds since the Unix epoch, like js_sys::Date::now()
+pub fn unix_time_ms_to_julian_date_number(time_ms: f64) -> Jdn {
+    return Jdn(time_ms / 86400000.0 + 2440587.5);
+}
+
+/// How many seconds the simulation has to step from `sim_time_s` to get to `target`, when a sim_time_s of zero is at
+/// `epoch`
+pub fn get_time_step_to_jdn(sim_time_s: f64, epoch: Jdn, target: Jdn) -> f64 {
+    return (target.0 - epoch.0) * 86400.0 - sim_time_s;
+}
+
+/// Julian Day Number that a `sim_time_s` is at, when a sim_time_s of zero is at `epoch`
+pub fn sim_time_to_jdn(sim_time_s: f64, epoch: Jdn) -> Jdn {
+    return Jdn(epoch.0 + sim_time_s / 86400.0);
+}
+
+/// Modified Julian Date for a Julian Day Number
+pub fn jdn_to_mjd(jdn: Jdn) -> f64 {
+    return jdn.0 - MJD_OFFSET_DAYS;
+}
+
+/// How many seconds Terrestrial Time is ahead of UTC at the UTC instant `jdn`. Before 1972 UTC didn't have whole leap
+/// seconds, so we just use the first offset: Those dates are off by up to a few seconds
+pub fn get_tt_minus_utc_s(jdn: Jdn) -> f64 {
+    let mjd = jdn_to_mjd(jdn);
+    let tai_minus_utc = LEAP_SECONDS
+        .iter()
+        .rev()
+        .find(|(start_mjd, _)| mjd >= *start_mjd)
+        .unwrap_or(&LEAP_SECONDS[0])
+        .1;
+    return tai_minus_utc + TT_MINUS_TAI_S;
+}
+
+/// Formats the instant at `sim_time_s` (with a sim_time_s of zero at `epoch`, in UTC) the way the toolbar shows it
+pub fn format_sim_time(sim_time_s: f64, epoch: Jdn, format: TimeFormat) -> String {
+    let utc = sim_time_to_jdn(sim_time_s, epoch);
+    let tt = Jdn(utc.0 + get_tt_minus_utc_s(utc) / 86400.0);
+
+    return match format {
+        TimeFormat::Utc => format!("{}", julian_date_number_to_date(utc)),
+        TimeFormat::Tt => format!(
+            "{} TT",
+            julian_date_number_to_date(tt).format("%Y-%m-%d %H:%M:%S")
+        ),
+        TimeFormat::Jd => format!("JD {:.5}", utc.0),
+        TimeFormat::Mjd => format!("MJD {:.5}", jdn_to_mjd(utc)),
+        TimeFormat::SecondsSinceEpoch => format!("{:.0} s since epoch", sim_time_s),
+    };
+}
+
 /// Returns the date object corresponding to a particular Julian Day Number (JDN)
 /// See https://en.wikipedia.org/wiki/Julian_day
 pub fn julian_date_number_to_date(jdn: Jdn) -> chrono::DateTime<Utc> {
     Utc.timestamp(((jdn.0 - 2440587.5) * 86400.0).round() as i64, 0)
 }
+
+/// Formats a distance in Mm with whichever of km, Mm or AU reads best for its magnitude
+pub fn format_distance(distance: Mm) -> String {
+    let abs = distance.0.abs();
+    if abs < 1.0 {
+        return format!("{} km", format_significant(distance.0 * 1000.0));
+    } else if abs < 0.1 * Au(1.0).to_Mm().0 {
+        return format!("{} Mm", format_significant(distance.0));
+    } else {
+        return format!("{} AU", format_significant(distance.to_AU().0));
+    }
+}
+
+/// Formats a distance in Mm in `unit`. Fixed units use `notation`, like the rest of the numbers on the UI
+pub fn format_distance_in(distance: Mm, unit: DistanceUnit, notation: NumberNotation) -> String {
+    return match unit {
+        DistanceUnit::Auto => format_distance(distance),
+        DistanceUnit::Km => format!("{} km", format_number(distance.0 * 1000.0, notation)),
+        DistanceUnit::Mm => format!("{} Mm", format_number(distance.0, notation)),
+        DistanceUnit::Au => format!("{} AU", format_number(distance.to_AU().0, notation)),
+    };
+}
+
+/// How long one of the unit that format_distance_in would show `distance` with is
+pub fn get_distance_unit_size(distance: Mm, unit: DistanceUnit) -> Mm {
+    return match unit {
+        DistanceUnit::Auto if distance.0.abs() < 1.0 => Mm(0.001),
+        DistanceUnit::Auto if distance.0.abs() < 0.1 * Au(1.0).to_Mm().0 => Mm(1.0),
+        DistanceUnit::Auto => Au(1.0).to_Mm(),
+        DistanceUnit::Km => Mm(0.001),
+        DistanceUnit::Mm => Mm(1.0),
+        DistanceUnit::Au => Au(1.0).to_Mm(),
+    };
+}
+
+/// Name of the unit that format_distance_in would show `distance` with
+pub fn get_distance_unit_name(distance: Mm, unit: DistanceUnit) -> &'static str {
+    let size = get_distance_unit_size(distance, unit).0;
+    return if size < 1.0 {
+        "km"
+    } else if size == 1.0 {
+        "Mm"
+    } else {
+        "AU"
+    };
+}
+
+/// Formats a velocity in Mm/s in `unit`
+pub fn format_velocity(velocity: f64, unit: VelocityUnit, notation: NumberNotation) -> String {
+    return match unit {
+        VelocityUnit::KmPerS => format!("{} km/s", format_number(velocity * 1000.0, notation)),
+        VelocityUnit::MmPerS => format!("{} Mm/s", format_number(velocity, notation)),
+        VelocityUnit::AuPerDay => format!(
+            "{} AU/day",
+            format_number(Mm(velocity * 86400.0).to_AU().0, notation)
+        ),
+    };
+}
+
+/// Formats a duration in days with whichever of seconds, minutes, hours or days reads best for its magnitude
+pub fn format_duration(days: f64) -> String {
+    let abs = days.abs();
+    if abs < 1.0 / 1440.0 {
+        return format!("{} s", format_significant(days * 86400.0));
+    } else if abs < 1.0 / 24.0 {
+        return format!("{} min", format_significant(days * 1440.0));
+    } else if abs < 1.0 {
+        return format!("{} h", format_significant(days * 24.0));
+    } else {
+        return format!("{} days", format_significant(days));
+    }
+}
+
+/// Keeps 3 significant digits, or all of the integer part if it has more, without trailing decimal zeroes
+fn format_significant(value: f64) -> String {
+    if value == 0.0 {
+        return String::from("0");
+    }
+
+    let decimals = (2 - value.abs().log10().floor() as i32).max(0) as usize;
+    let text = format!("{:.*}", decimals, value);
+    if text.contains('.') {
+        return text.trim_end_matches('0').trim_end_matches('.').to_owned();
+    }
+    return text;
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    pub fn julian_dates_match_reference_values() {
+        // J2000 and the Unix epoch
+        assert_eq!(jdn_to_mjd(J2000_JDN), 51544.5);
+        assert_eq!(jdn_to_mjd(Jdn(2440587.5)), 40587.0);
+        let unix_epoch = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(date_to_julian_date_number(&unix_epoch).0, 2440587.5);
+
+        // The MJD epoch, and a day and a half into the simulation
+        assert_eq!(jdn_to_mjd(Jdn(MJD_OFFSET_DAYS)), 0.0);
+        assert_eq!(sim_time_to_jdn(1.5 * 86400.0, J2000_JDN).0, 2451546.5);
+
+        assert_eq!(
+            format_sim_time(0.0, J2000_JDN, TimeFormat::Jd),
+            "JD 2451545.00000"
+        );
+        assert_eq!(
+            format_sim_time(3600.0, J2000_JDN, TimeFormat::Mjd),
+            "MJD 51544.54167"
+        );
+        assert_eq!(
+            format_sim_time(0.0, J2000_JDN, TimeFormat::Utc),
+            "2000-01-01 12:00:00 UTC"
+        );
+    }
+
+    #[wasm_bindgen_test]
+    pub fn terrestrial_time_follows_leap_seconds() {
+        // TAI - UTC was 32 s from 1999 to 2006, and 37 s since 2017
+        let is_close = |a: f64, b: f64| (a - b).abs() < 1E-9;
+        assert!(is_close(get_tt_minus_utc_s(J2000_JDN), 64.184));
+        let mjd_2020 = Jdn(58849.0 + MJD_OFFSET_DAYS);
+        assert!(is_close(get_tt_minus_utc_s(mjd_2020), 69.184));
+
+        // The leap second at the start of 2017
+        let mjd_2017 = Jdn(57754.0 + MJD_OFFSET_DAYS);
+        assert!(is_close(get_tt_minus_utc_s(Jdn(mjd_2017.0 - 1E-6)), 68.184));
+        assert!(is_close(get_tt_minus_utc_s(mjd_2017), 69.184));
+
+        assert_eq!(
+            format_sim_time(0.0, J2000_JDN, TimeFormat::Tt),
+            "2000-01-01 12:01:04 TT"
+        );
+        assert_eq!(
+            format_sim_time(1000.0, Jdn(2451546.0), TimeFormat::SecondsSinceEpoch),
+            "1000 s since epoch"
+        );
+    }
+}
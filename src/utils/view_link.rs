@@ -0,0 +1,158 @@
+use na::{Point3, Vector3};
+
+/// Compact snapshot of what we're looking at, so that it can be shared as an URL fragment like
+/// `#scene=full_solar_system&t=86400&ref=399&cam=px,py,pz,tx,ty,tz,ux,uy,uz`.
+/// A plain `#scene_name` fragment is also accepted, and just loads that scene
+#[derive(Debug, Clone, PartialEq)]
+pub struct ViewLink {
+    pub scene: String,
+    pub sim_time_s: Option<f64>,
+    pub reference: Option<String>, // body_id of the reference entity, or its name if it has no body_id
+    pub camera: Option<ViewLinkCamera>,
+}
+
+/// Camera pose, relative to the reference entity if there is one
+#[derive(Debug, Clone, PartialEq)]
+pub struct ViewLinkCamera {
+    pub pos: Point3<f64>,
+    pub target: Point3<f64>,
+    pub up: Vector3<f64>,
+}
+
+impl ViewLink {
+    pub fn to_hash(&self) -> String {
+        let mut result = format!("#scene={}", encode_component(&self.scene));
+
+        if let Some(sim_time_s) = self.sim_time_s {
+            result += &format!("&t={}", sim_time_s);
+        }
+
+        if let Some(reference) = &self.reference {
+            result += &format!("&ref={}", encode_component(reference));
+        }
+
+        if let Some(camera) = &self.camera {
+            let values = [
+                camera.pos.x,
+                camera.pos.y,
+                camera.pos.z,
+                camera.target.x,
+                camera.target.y,
+                camera.target.z,
+                camera.up.x,
+                camera.up.y,
+                camera.up.z,
+            ];
+            let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+            result += &format!("&cam={}", values.join(","));
+        }
+
+        return result;
+    }
+
+    /// Parses an URL fragment (with or without the leading '#'). Returns None if it doesn't contain a scene
+    pub fn from_hash(hash: &str) -> Option<Self> {
+        let hash = hash.trim_start_matches('#');
+        if hash.is_empty() {
+            return None;
+        }
+
+        // Backwards compatibility with plain `#scene_name` links
+        if !hash.contains('=') {
+            return Some(Self {
+                scene: decode_component(hash),
+                sim_time_s: None,
+                reference: None,
+                camera: None,
+            });
+        }
+
+        let mut scene: Option<String> = None;
+        let mut sim_time_s: Option<f64> = None;
+        let mut reference: Option<String> = None;
+        let mut camera: Option<ViewLinkCamera> = None;
+
+        for pair in hash.split('&') {
+            let mut key_and_value = pair.splitn(2, '=');
+            let key = key_and_value.next().unwrap_or_default();
+            let value = key_and_value.next().unwrap_or_default();
+
+            match key {
+                "scene" => scene = Some(decode_component(value)),
+                "t" => sim_time_s = value.parse::<f64>().ok(),
+                "ref" => reference = Some(decode_component(value)),
+                "cam" => {
+                    let values: Vec<f64> = value
+                        .split(',')
+                        .filter_map(|v| v.parse::<f64>().ok())
+                        .collect();
+
+                    if values.len() == 9 {
+                        camera = Some(ViewLinkCamera {
+                            pos: Point3::new(values[0], values[1], values[2]),
+                            target: Point3::new(values[3], values[4], values[5]),
+                            up: Vector3::new(values[6], values[7], values[8]),
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let scene = scene.filter(|s| !s.is_empty())?;
+        return Some(Self {
+            scene,
+            sim_time_s,
+            reference,
+            camera,
+        });
+    }
+}
+
+fn encode_component(value: &str) -> String {
+    return String::from(js_sys::encode_uri_component(value));
+}
+
+fn decode_component(value: &str) -> String {
+    return js_sys::decode_uri_component(value)
+        .map(String::from)
+        .unwrap_or(value.to_owned());
+}
+
+pub mod tests {
+    extern crate wasm_bindgen_test;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    pub fn view_link_round_trip() {
+        let link = ViewLink {
+            scene: String::from("planets and satellites"),
+            sim_time_s: Some(86400.5),
+            reference: Some(String::from("399")),
+            camera: Some(ViewLinkCamera {
+                pos: Point3::new(1.5, -2.25, 149597.870691),
+                target: Point3::new(0.0, 0.0, 0.0),
+                up: Vector3::new(0.0, 0.0, 1.0),
+            }),
+        };
+
+        let hash = link.to_hash();
+        assert!(!hash.contains(' '));
+        assert_eq!(ViewLink::from_hash(&hash), Some(link));
+    }
+
+    #[wasm_bindgen_test]
+    pub fn view_link_plain_scene_name() {
+        let link = ViewLink::from_hash("#earth_centric").unwrap();
+        assert_eq!(link.scene, "earth_centric");
+        assert_eq!(link.sim_time_s, None);
+        assert_eq!(link.reference, None);
+        assert_eq!(link.camera, None);
+
+        assert_eq!(ViewLink::from_hash("#"), None);
+        assert_eq!(ViewLink::from_hash("#t=10"), None);
+    }
+}
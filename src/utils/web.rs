@@ -1,16 +1,34 @@
 use crate::utils::log::*;
 use crate::{app_state::AppState, STATE};
-use crate::{app_state::ButtonState, wasm_bindgen::JsCast};
-use js_sys::{ArrayBuffer, Uint8Array};
+use crate::{app_state::ButtonState, app_state::CameraDragMode, wasm_bindgen::JsCast};
+use js_sys::{Array, ArrayBuffer, Uint8Array};
+use std::cell::RefCell;
+use std::rc::Rc;
 use wasm_bindgen::prelude::Closure;
 use wasm_bindgen::JsValue;
 use wasm_bindgen_futures::JsFuture;
 use web_sys::{
-    HtmlCanvasElement, Request, RequestInit, RequestMode, Response, WebGl2RenderingContext,
+    Blob, BlobPropertyBag, HtmlAnchorElement, HtmlCanvasElement, HtmlInputElement, Request,
+    RequestInit, RequestMode, Response, Url, WebGl2RenderingContext, WebGlContextEvent,
+    WebglLoseContext,
 };
 
 const OUR_CANVAS_ID: &str = "rustCanvas";
 
+/// How far a finger can move in client pixels before a tap turns into a drag
+const TAP_SLOP_PX: i32 = 10;
+
+/// How much the distance between two fingers has to change in client pixels for each step of pinch zoom, which
+/// works like one notch of the scroll wheel
+const PINCH_STEP_PX: f64 = 10.0;
+
+/// What the fingers on the canvas are doing, kept in between touch events
+#[derive(Default)]
+struct TouchGesture {
+    tap_start: Option<(i32, i32)>, // Client position of a single finger that hasn't moved enough to be a drag yet
+    last_pinch_distance: Option<f64>, // Client pixels between the two fingers at the last pinch step
+}
+
 pub fn get_canvas() -> HtmlCanvasElement {
     let window = web_sys::window().expect("no global `window` exists");
     let document = window.document().expect("should have a document on window");
@@ -33,6 +51,18 @@ pub fn request_animation_frame(f: &Closure<dyn FnMut()>) {
         .expect("failed to request animation frame");
 }
 
+/// Overrides the CSS cursor of our canvas (e.g. "grabbing"). An empty string restores the default
+pub fn set_canvas_cursor(cursor: &str) {
+    if let Err(err) = get_canvas().style().set_property("cursor", cursor) {
+        warning!(
+            LogCat::Io,
+            "Failed to set canvas cursor to '{}': {:?}",
+            cursor,
+            err
+        );
+    }
+}
+
 pub fn get_gl_context() -> glow::Context {
     let canvas = get_canvas();
 
@@ -46,6 +76,111 @@ pub fn get_gl_context() -> glow::Context {
     return glow::Context::from_webgl2_context(gl);
 }
 
+/// Loses our WebGL context on purpose and has the browser restore it a second later, to test that we recover
+pub fn simulate_gl_context_loss() {
+    let extension = get_canvas()
+        .get_context("webgl2")
+        .ok()
+        .flatten()
+        .and_then(|ctx| ctx.dyn_into::<WebGl2RenderingContext>().ok())
+        .and_then(|gl| gl.get_extension("WEBGL_lose_context").ok().flatten());
+    if extension.is_none() {
+        warning!(
+            LogCat::Engine,
+            "WEBGL_lose_context is not available, can't simulate WebGL context loss"
+        );
+        return;
+    }
+
+    // Extension objects aren't instances of anything we can check against with dyn_into
+    let extension: WebglLoseContext = extension.unwrap().unchecked_into();
+    extension.lose_context();
+
+    // The context can only be restored after the browser has finished losing it
+    let restore = Closure::once_into_js(move || {
+        extension.restore_context();
+    });
+    if let Err(err) = get_window()
+        .set_timeout_with_callback_and_timeout_and_arguments_0(restore.unchecked_ref(), 1000)
+    {
+        error!(
+            LogCat::Engine,
+            "Failed to schedule WebGL context restore: {:?}", err
+        );
+    }
+}
+
+/// Has the browser download `bytes` as a file named `file_name`, with MIME type `mime_type`
+pub fn download_bytes(file_name: &str, bytes: &[u8], mime_type: &str) -> Result<(), String> {
+    let parts = Array::of1(&Uint8Array::from(bytes));
+    let options = BlobPropertyBag::new();
+    options.set_type(mime_type);
+    let blob = Blob::new_with_u8_array_sequence_and_options(&parts, &options)
+        .map_err(|err| format!("Failed to create blob: {:?}", err))?;
+    let url = Url::create_object_url_with_blob(&blob)
+        .map_err(|err| format!("Failed to create blob url: {:?}", err))?;
+
+    let anchor: HtmlAnchorElement = get_document()
+        .create_element("a")
+        .and_then(|el| el.dyn_into::<HtmlAnchorElement>().map_err(|el| el.into()))
+        .map_err(|err| format!("Failed to create anchor element: {:?}", err))?;
+    anchor.set_href(&url);
+    anchor.set_download(file_name);
+    anchor.click();
+
+    // Some browsers only start reading the blob after the click event is done
+    let revoke = Closure::once_into_js(move || {
+        let _ = Url::revoke_object_url(&url);
+    });
+    if let Err(err) = get_window()
+        .set_timeout_with_callback_and_timeout_and_arguments_0(revoke.unchecked_ref(), 1000)
+    {
+        warning!(LogCat::Io, "Failed to schedule blob url cleanup: {:?}", err);
+    }
+
+    return Ok(());
+}
+
+/// Opens the browser's file picker for files matching `accept` (e.g. ".json") and calls `on_picked` with the
+/// file name and its contents as text, once the user picks one. Nothing happens if the picker is cancelled
+pub fn pick_local_text_file(
+    accept: &str,
+    on_picked: impl FnOnce(String, Result<String, String>) + 'static,
+) {
+    let input: HtmlInputElement = match get_document()
+        .create_element("input")
+        .and_then(|el| el.dyn_into::<HtmlInputElement>().map_err(|el| el.into()))
+    {
+        Ok(input) => input,
+        Err(err) => {
+            error!(LogCat::Io, "Failed to create file input element: {:?}", err);
+            return;
+        }
+    };
+    input.set_type("file");
+    input.set_accept(accept);
+
+    let picked_input = input.clone();
+    let on_change = Closure::once_into_js(move || {
+        let file = picked_input.files().and_then(|files| files.get(0));
+        if file.is_none() {
+            return;
+        }
+        let file = file.unwrap();
+
+        wasm_bindgen_futures::spawn_local(async move {
+            let text = JsFuture::from(file.text())
+                .await
+                .map_err(|err| format!("{:?}", err))
+                .and_then(|value| value.as_string().ok_or(String::from("File is not text")));
+
+            on_picked(file.name(), text);
+        });
+    });
+    input.set_onchange(Some(on_change.unchecked_ref()));
+    input.click();
+}
+
 pub async fn request_text(url: &str) -> Result<String, JsValue> {
     let mut opts = RequestInit::new();
     opts.method("GET");
@@ -116,6 +251,23 @@ pub fn pos_from_mouse_event(canvas: &HtmlCanvasElement, event: &web_sys::MouseEv
     }
 }
 
+pub fn pos_from_touch(canvas: &HtmlCanvasElement, touch: &web_sys::Touch) -> egui::Pos2 {
+    let rect = canvas.get_bounding_client_rect();
+    egui::Pos2 {
+        x: touch.client_x() as f32 - rect.left() as f32,
+        y: touch.client_y() as f32 - rect.top() as f32,
+    }
+}
+
+fn get_touch_distance(touches: &web_sys::TouchList) -> Option<f64> {
+    let first = touches.get(0)?;
+    let second = touches.get(1)?;
+
+    let dx = (first.client_x() - second.client_x()) as f64;
+    let dy = (first.client_y() - second.client_y()) as f64;
+    return Some((dx * dx + dy * dy).sqrt());
+}
+
 pub fn button_from_mouse_event(event: &web_sys::MouseEvent) -> Option<egui::PointerButton> {
     match event.button() {
         0 => Some(egui::PointerButton::Primary),
@@ -213,7 +365,16 @@ fn handle_key_press(key: &str, modifiers: &egui::Modifiers, s: &mut AppState, pr
         "Home" => egui_key = Some(egui::Key::Home),
         "PageDown" => egui_key = Some(egui::Key::PageDown),
         "PageUp" => egui_key = Some(egui::Key::PageUp),
-        "Tab" => egui_key = Some(egui::Key::Tab),
+        "Tab" => {
+            if button_state == ButtonState::Pressed && s.input.cycle_focus == ButtonState::Depressed
+            {
+                s.input.cycle_focus = ButtonState::Pressed;
+            } else if button_state == ButtonState::Depressed {
+                s.input.cycle_focus = ButtonState::Depressed;
+            }
+
+            egui_key = Some(egui::Key::Tab);
+        }
         "a" | "A" => {
             s.input.left = button_state;
             egui_key = Some(egui::Key::A);
@@ -237,17 +398,55 @@ fn handle_key_press(key: &str, modifiers: &egui::Modifiers, s: &mut AppState, pr
             egui_key = Some(egui::Key::U);
         }
         "z" | "Z" => {
+            s.input.roll_left = button_state;
             egui_key = Some(egui::Key::Z);
         }
+        "c" | "C" => {
+            s.input.roll_right = button_state;
+        }
         "f" | "F" => {
             s.input.f = button_state;
         }
         "g" | "G" => {
             s.input.g = button_state;
         }
+        // These get set to Pressed again by key repeat, so holding them keeps stepping
+        "," => {
+            s.input.step_back = button_state;
+        }
+        "." => {
+            s.input.step_forward = button_state;
+        }
         _ => {}
     };
 
+    if is_command_palette_shortcut(key, modifiers, s) {
+        if button_state == ButtonState::Pressed && s.input.command_palette == ButtonState::Depressed
+        {
+            s.input.command_palette = ButtonState::Pressed;
+        }
+    }
+
+    handle_bound_key_press(
+        key,
+        modifiers,
+        &s.look_at_star_key,
+        &mut s.input.look_at_star,
+        pressed,
+    );
+    handle_bound_key_press(
+        key,
+        modifiers,
+        &s.spectator_key,
+        &mut s.input.spectator,
+        pressed,
+    );
+
+    // We may get the key up without the modifier if it was released first
+    if !pressed && key.eq_ignore_ascii_case(&s.command_palette_key) {
+        s.input.command_palette = ButtonState::Depressed;
+    }
+
     if let Some(key) = egui_key {
         s.input.egui_events.push(egui::Event::Key {
             key,
@@ -257,6 +456,33 @@ fn handle_key_press(key: &str, modifiers: &egui::Modifiers, s: &mut AppState, pr
     }
 }
 
+/// Updates `button` for a key press of the user-configurable `bound_key`. Like Tab and Space, holding it down doesn't
+/// press it again with key repeat. Ignored along with Ctrl (or Cmd), as those are the browser's shortcuts
+fn handle_bound_key_press(
+    key: &str,
+    modifiers: &egui::Modifiers,
+    bound_key: &str,
+    button: &mut ButtonState,
+    pressed: bool,
+) {
+    if bound_key.is_empty() || !key.eq_ignore_ascii_case(bound_key) {
+        return;
+    }
+
+    if !pressed {
+        *button = ButtonState::Depressed;
+    } else if *button == ButtonState::Depressed && !modifiers.ctrl && !modifiers.command {
+        *button = ButtonState::Pressed;
+    }
+}
+
+/// Whether `key` toggles the command palette when pressed along with `modifiers`
+fn is_command_palette_shortcut(key: &str, modifiers: &egui::Modifiers, s: &AppState) -> bool {
+    return (modifiers.ctrl || modifiers.command)
+        && !s.command_palette_key.is_empty()
+        && key.eq_ignore_ascii_case(&s.command_palette_key);
+}
+
 /// Sets up the canvas event handlers to change the app_state blackboard
 pub fn setup_event_handlers() {
     let canvas = get_canvas();
@@ -278,26 +504,34 @@ pub fn setup_event_handlers() {
                 if let Some(button) = button_from_mouse_event(&event) {
                     let pos = pos_from_mouse_event(&canvas_clone, &event);
 
-                    match button {
+                    let starts_drag = match button {
                         egui::PointerButton::Primary => {
                             // Don't revert back to "pressed" if it's already handled
                             if s.input.m0 == ButtonState::Depressed {
                                 s.input.m0 = ButtonState::Pressed;
                             }
 
-                            if s.input.modifiers.alt {
-                                canvas_clone.request_pointer_lock();
-                                s.input.mouse_x = event.client_x();
-                                s.input.mouse_y = event.client_y();
-                            }
+                            s.input.modifiers.alt
                         }
                         egui::PointerButton::Secondary => {
                             s.input.m1 = ButtonState::Pressed;
-                            canvas_clone.request_pointer_lock();
-                            s.input.mouse_x = event.client_x();
-                            s.input.mouse_y = event.client_y();
+                            true
                         }
-                        egui::PointerButton::Middle => {}
+                        egui::PointerButton::Middle => false,
+                    };
+
+                    if starts_drag {
+                        match s.camera_drag_mode {
+                            CameraDragMode::PointerLock => {
+                                canvas_clone.request_pointer_lock();
+                                s.input.dragging_scene = true;
+                            }
+                            // Without the lock there's nothing stopping egui from also seeing the drag, so
+                            // leave it alone if it started over the UI
+                            CameraDragMode::Drag => s.input.dragging_scene = !s.input.over_ui,
+                        };
+                        s.input.mouse_x = event.client_x();
+                        s.input.mouse_y = event.client_y();
                     }
 
                     s.input.egui_events.push(egui::Event::PointerButton {
@@ -345,6 +579,14 @@ pub fn setup_event_handlers() {
                 } else {
                     s.input.mouse_x = event.client_x();
                     s.input.mouse_y = event.client_y();
+
+                    // Without pointer lock we don't get the mouseup if the button is released outside the canvas,
+                    // so stop the drag as soon as the cursor comes back with no buttons held
+                    if s.input.dragging_scene && event.buttons() == 0 {
+                        s.input.dragging_scene = false;
+                        s.input.m0 = ButtonState::Depressed;
+                        s.input.m1 = ButtonState::Depressed;
+                    }
                 }
 
                 let pos = pos_from_mouse_event(&canvas_clone, &event);
@@ -375,6 +617,7 @@ pub fn setup_event_handlers() {
                         egui::PointerButton::Secondary => s.input.m1 = ButtonState::Depressed,
                         egui::PointerButton::Middle => {}
                     };
+                    s.input.dragging_scene = false;
 
                     s.input.egui_events.push(egui::Event::PointerButton {
                         pos,
@@ -424,6 +667,159 @@ pub fn setup_event_handlers() {
         handler.forget();
     }
 
+    // Touch events translate into the same inputs as the mouse: One finger drags like the secondary button to look
+    // around, pinching works like the scroll wheel and a tap clicks
+    let gesture = Rc::new(RefCell::new(TouchGesture::default()));
+
+    // touchstart
+    {
+        let canvas_clone = canvas.clone();
+        let gesture = gesture.clone();
+        let handler = move |event: web_sys::TouchEvent| {
+            STATE.with(|s| {
+                let mut ref_mut = s.borrow_mut();
+                let s = ref_mut.as_mut().unwrap();
+                let mut gesture = gesture.borrow_mut();
+
+                let touches = event.touches();
+                if touches.length() == 1 {
+                    let touch = touches.get(0).unwrap();
+                    s.input.mouse_x = touch.client_x();
+                    s.input.mouse_y = touch.client_y();
+                    gesture.tap_start = Some((touch.client_x(), touch.client_y()));
+
+                    // Let egui know where the finger is, so that it can report if it's over a window
+                    let pos = pos_from_touch(&canvas_clone, &touch);
+                    s.input.egui_events.push(egui::Event::PointerMoved(pos));
+                    s.input.egui_events.push(egui::Event::PointerButton {
+                        pos,
+                        button: egui::PointerButton::Primary,
+                        pressed: true,
+                        modifiers: s.input.modifiers,
+                    });
+                } else {
+                    // A second finger cancels the tap or the drag and starts pinching instead
+                    gesture.tap_start = None;
+                    gesture.last_pinch_distance = get_touch_distance(&touches);
+                    s.input.m1 = ButtonState::Depressed;
+                    s.input.dragging_scene = false;
+                    s.input.pinching = !s.input.over_ui;
+                }
+
+                // Prevents the browser from also scrolling or zooming the page, and emulating mouse events for it
+                event.prevent_default();
+            });
+        };
+
+        let handler = Closure::wrap(Box::new(handler) as Box<dyn FnMut(_)>);
+        canvas
+            .add_event_listener_with_callback("touchstart", handler.as_ref().unchecked_ref())
+            .expect("Failed to set touchstart event handler");
+        handler.forget();
+    }
+
+    // touchmove
+    {
+        let canvas_clone = canvas.clone();
+        let gesture = gesture.clone();
+        let handler = move |event: web_sys::TouchEvent| {
+            STATE.with(|s| {
+                let mut ref_mut = s.borrow_mut();
+                let s = ref_mut.as_mut().unwrap();
+                let mut gesture = gesture.borrow_mut();
+
+                let touches = event.touches();
+                if touches.length() == 1 {
+                    let touch = touches.get(0).unwrap();
+
+                    // By now egui has had a frame to tell us whether the finger is on a window
+                    if let Some((start_x, start_y)) = gesture.tap_start {
+                        if (touch.client_x() - start_x).abs() > TAP_SLOP_PX
+                            || (touch.client_y() - start_y).abs() > TAP_SLOP_PX
+                        {
+                            gesture.tap_start = None;
+                            if !s.input.over_ui {
+                                s.input.m1 = ButtonState::Pressed;
+                                s.input.dragging_scene = true;
+                            }
+                        }
+                    }
+
+                    s.input.mouse_x = touch.client_x();
+                    s.input.mouse_y = touch.client_y();
+
+                    let pos = pos_from_touch(&canvas_clone, &touch);
+                    s.input.egui_events.push(egui::Event::PointerMoved(pos));
+                } else if s.input.pinching {
+                    if let (Some(distance), Some(last_distance)) =
+                        (get_touch_distance(&touches), gesture.last_pinch_distance)
+                    {
+                        // Spreading the fingers apart zooms in, like scrolling up
+                        if (distance - last_distance).abs() > PINCH_STEP_PX {
+                            s.input.scroll_delta_y += if distance > last_distance { -1 } else { 1 };
+                            gesture.last_pinch_distance = Some(distance);
+                        }
+                    }
+                }
+
+                event.prevent_default();
+            });
+        };
+
+        let handler = Closure::wrap(Box::new(handler) as Box<dyn FnMut(_)>);
+        canvas
+            .add_event_listener_with_callback("touchmove", handler.as_ref().unchecked_ref())
+            .expect("Failed to set touchmove event handler");
+        handler.forget();
+    }
+
+    // touchend and touchcancel
+    {
+        let canvas_clone = canvas.clone();
+        let gesture = gesture.clone();
+        let handler = move |event: web_sys::TouchEvent| {
+            STATE.with(|s| {
+                let mut ref_mut = s.borrow_mut();
+                let s = ref_mut.as_mut().unwrap();
+                let mut gesture = gesture.borrow_mut();
+
+                // Wait until all fingers are lifted, so that lifting one of them mid-pinch doesn't start a drag
+                if event.touches().length() > 0 {
+                    event.prevent_default();
+                    return;
+                }
+
+                if gesture.tap_start.take().is_some() && event.type_() == "touchend" {
+                    s.input.tapped = true;
+                }
+                gesture.last_pinch_distance = None;
+                s.input.pinching = false;
+                s.input.m1 = ButtonState::Depressed;
+                s.input.dragging_scene = false;
+
+                if let Some(touch) = event.changed_touches().get(0) {
+                    let pos = pos_from_touch(&canvas_clone, &touch);
+                    s.input.egui_events.push(egui::Event::PointerButton {
+                        pos,
+                        button: egui::PointerButton::Primary,
+                        pressed: false,
+                        modifiers: s.input.modifiers,
+                    });
+                }
+
+                event.prevent_default();
+            });
+        };
+
+        let handler = Closure::wrap(Box::new(handler) as Box<dyn FnMut(_)>);
+        for event_name in &["touchend", "touchcancel"] {
+            canvas
+                .add_event_listener_with_callback(event_name, handler.as_ref().unchecked_ref())
+                .expect("Failed to set touchend event handler");
+        }
+        handler.forget();
+    }
+
     // keydown (some of this is copied from egui's web demo: https://github.com/emilk/egui/blob/650450bc3a01f8fe44ba89781597c3c8f60c2777/egui_web/src/lib.rs )
     {
         let handler = move |event: web_sys::KeyboardEvent| {
@@ -446,12 +842,16 @@ pub fn setup_event_handlers() {
                     s.input.egui_events.push(egui::Event::Text(key.to_owned()));
                 }
 
+                // Otherwise e.g. Ctrl+K would focus the browser's search bar
+                let palette_shortcut = is_command_palette_shortcut(&key, &modifiers, s);
+
                 if modifiers.alt
                     || matches!(
                         event.key().as_str(),
                         "Backspace"  // so we don't go back to previous page when deleting text
                     | "Tab" // so that e.g. tab doesn't move focus to url bar
                     )
+                    || palette_shortcut
                 {
                     event.prevent_default();
                 }
@@ -490,6 +890,70 @@ pub fn setup_event_handlers() {
             .expect("Failed to set keyup event handler");
         handler.forget();
     }
+
+    // webglcontextlost
+    {
+        let handler = move |event: WebGlContextEvent| {
+            // Otherwise the browser won't ever try restoring the context
+            event.prevent_default();
+
+            STATE.with(|s| {
+                let mut ref_mut = s.borrow_mut();
+                let s = ref_mut.as_mut().unwrap();
+
+                s.gl_context_lost = true;
+            });
+
+            warning!(
+                LogCat::Engine,
+                "Lost the WebGL context! Rendering is paused until it is restored"
+            );
+        };
+
+        let handler = Closure::wrap(Box::new(handler) as Box<dyn FnMut(_)>);
+        canvas
+            .add_event_listener_with_callback("webglcontextlost", handler.as_ref().unchecked_ref())
+            .expect("Failed to set webglcontextlost event handler");
+        handler.forget();
+    }
+
+    // webglcontextrestored
+    {
+        let handler = move |_event: WebGlContextEvent| {
+            STATE.with(|s| {
+                let mut ref_mut = s.borrow_mut();
+                let s = ref_mut.as_mut().unwrap();
+
+                s.gl_context_lost = false;
+                s.gl_context_restored = true;
+            });
+
+            info!(
+                LogCat::Engine,
+                "WebGL context restored, rebuilding GL resources..."
+            );
+        };
+
+        let handler = Closure::wrap(Box::new(handler) as Box<dyn FnMut(_)>);
+        canvas
+            .add_event_listener_with_callback(
+                "webglcontextrestored",
+                handler.as_ref().unchecked_ref(),
+            )
+            .expect("Failed to set webglcontextrestored event handler");
+        handler.forget();
+    }
+}
+
+// From egui web backend
+/// Seconds since the page loaded, with sub-millisecond precision where the browser allows it
+pub fn now_sec() -> f64 {
+    return web_sys::window()
+        .expect("should have a Window")
+        .performance()
+        .expect("should have a Performance")
+        .now()
+        / 1000.0;
 }
 
 // From egui web backend
@@ -522,3 +986,30 @@ pub fn local_storage_remove(key: &str) {
 pub fn local_storage_clear() {
     local_storage().map(|storage| storage.clear());
 }
+
+// From egui web backend
+pub fn location_hash() -> Option<String> {
+    web_sys::window()?.location().hash().ok()
+}
+
+/// Replaces the URL fragment without adding a new entry to the browser history
+pub fn replace_location_hash(hash: &str) {
+    if let Some(history) = web_sys::window().and_then(|w| w.history().ok()) {
+        if let Err(err) = history.replace_state_with_url(&JsValue::NULL, "", Some(hash)) {
+            warning!(LogCat::Io, "Failed to set location hash: {:?}", err);
+        }
+    }
+}
+
+// Test interface
+/// Adds our canvas to the page if it isn't there yet, so that tests can create GL resources
+pub fn add_test_canvas() {
+    let document = get_document();
+    if document.get_element_by_id(OUR_CANVAS_ID).is_some() {
+        return;
+    }
+
+    let canvas = document.create_element("canvas").unwrap();
+    canvas.set_id(OUR_CANVAS_ID);
+    document.body().unwrap().append_child(&canvas).unwrap();
+}